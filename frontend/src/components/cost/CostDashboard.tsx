@@ -523,6 +523,8 @@ function CliTypeBadge(props: { cliType?: string }) {
     switch (props.cliType) {
       case "codex": return "#10a37f";
       case "gemini": return "#4285f4";
+      case "aider": return "#14b014";
+      case "opencode": return "#a1a1aa";
       default: return "#d4a373";
     }
   };
@@ -530,6 +532,8 @@ function CliTypeBadge(props: { cliType?: string }) {
     switch (props.cliType) {
       case "codex": return "CDX";
       case "gemini": return "GEM";
+      case "aider": return "AID";
+      case "opencode": return "OPC";
       default: return "CLD";
     }
   };
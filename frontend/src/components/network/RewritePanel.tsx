@@ -25,7 +25,7 @@ const GEMINI_MODEL_OPTIONS: RewriteModelOption[] = [
   { value: "gemini-2.5-flash-lite", label: "Gemini 2.5 Flash Lite" },
 ];
 
-function modelOptionsFor(cliType?: "claude" | "codex" | "gemini"): RewriteModelOption[] {
+function modelOptionsFor(cliType?: string): RewriteModelOption[] {
   switch (cliType) {
     case "codex":
       return CODEX_MODEL_OPTIONS;
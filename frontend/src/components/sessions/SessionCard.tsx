@@ -42,6 +42,10 @@ function cliColor(cliType?: string): string {
       return "#10a37f"; // OpenAI green
     case "gemini":
       return "#4285f4"; // Google blue
+    case "aider":
+      return "#14b014"; // Aider green
+    case "opencode":
+      return "#a1a1aa"; // OpenCode neutral
     default:
       return "#d4a373"; // Claude warm amber
   }
@@ -54,6 +58,10 @@ function cliLabel(cliType?: string): string {
       return "CDX";
     case "gemini":
       return "GEM";
+    case "aider":
+      return "AID";
+    case "opencode":
+      return "OPC";
     default:
       return "CLD";
   }
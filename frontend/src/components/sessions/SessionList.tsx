@@ -244,7 +244,7 @@ function orbStateForSession(session: Session): OrbState {
   }
 }
 
-/** Inline SVG logos for the three CLI tools. */
+/** Inline SVG logos for the supported CLI tools. */
 function ClaudeLogo() {
   return (
     <svg viewBox="0 0 24 24" width="20" height="20" fill="none">
@@ -284,12 +284,32 @@ function GeminiLogo() {
   );
 }
 
-type CliType = "claude" | "codex" | "gemini";
+function AiderLogo() {
+  return (
+    <svg viewBox="0 0 24 24" width="20" height="20" fill="none">
+      <rect x="2" y="4" width="20" height="16" rx="2" stroke="#14b014" stroke-width="1.5" />
+      <path d="M6 9l3 3-3 3M11 15h6" stroke="#14b014" stroke-width="1.5" stroke-linecap="round" />
+    </svg>
+  );
+}
+
+function OpenCodeLogo() {
+  return (
+    <svg viewBox="0 0 24 24" width="20" height="20" fill="none">
+      <rect x="4" y="3" width="16" height="18" stroke="#a1a1aa" stroke-width="1.5" />
+      <rect x="8" y="7" width="8" height="10" fill="#a1a1aa" opacity="0.6" />
+    </svg>
+  );
+}
+
+type CliType = "claude" | "codex" | "gemini" | "aider" | "opencode";
 
 const CLI_OPTIONS: { type: CliType; label: string; desc: string; color: string; Logo: () => ReturnType<typeof ClaudeLogo> }[] = [
   { type: "claude", label: "Claude", desc: "Anthropic", color: "#d4a373", Logo: ClaudeLogo },
   { type: "codex", label: "Codex", desc: "OpenAI", color: "#10a37f", Logo: CodexLogo },
   { type: "gemini", label: "Gemini", desc: "Google", color: "#4285f4", Logo: GeminiLogo },
+  { type: "aider", label: "Aider", desc: "Any model", color: "#14b014", Logo: AiderLogo },
+  { type: "opencode", label: "OpenCode", desc: "SST", color: "#a1a1aa", Logo: OpenCodeLogo },
 ];
 
 /** Format bytes to human readable. */
@@ -34,7 +34,7 @@ export interface Session {
   lastActivityAt: number;
   messageCount: number;
   cost?: number;
-  cliType?: "claude" | "codex" | "gemini";
+  cliType?: "claude" | "codex" | "gemini" | "aider" | "opencode";
 }
 
 export interface LoadingProgress {
@@ -0,0 +1,445 @@
+//! Cross-session analytics.
+//!
+//! Usage and tool statistics are folded into per-session, per-day
+//! aggregates (`analytics_usage`, `analytics_tools`) as transcripts grow, so
+//! fleet-wide reports are a scan over small tables instead of a re-parse of
+//! every JSONL. Each transcript keeps its own parser cursor in
+//! `analytics_sessions`; the search indexer drives the updates. The
+//! aggregates are not session data, so archiving a session keeps its
+//! history in the reports.
+
+pub mod report;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::db::{AnalyticsSession, Db, OpenToolCall, ToolAggregate, UsageAggregate};
+use crate::discovery::scanner::CliType;
+use crate::ecs::components::{MessageRole, MessageType, ToolKind};
+use crate::parser::{self, ClaudeMessage, ContentBlock, CursorKind, MessageContent};
+use crate::proxy::audit::calculate_cost;
+
+pub use report::{Filter, Group, Report, SessionRow, ToolRow, View};
+
+/// Fold what is new in a transcript into the aggregates. Returns the number
+/// of messages read.
+pub async fn update_session(
+    db: &Db,
+    session_id: Uuid,
+    path: &Path,
+    cli: CliType,
+    project: Option<String>,
+) -> anyhow::Result<usize> {
+    let source = parser::registry::for_cli(cli);
+    let metadata = tokio::fs::metadata(path).await?;
+    let file_size = metadata.len();
+    let path_str = path.display().to_string();
+
+    let mut state = db
+        .get_analytics_session(&session_id)
+        .await?
+        .filter(|s| s.path == path_str);
+    if let Some(s) = &state
+        && file_size < s.file_size
+    {
+        debug!(session = %session_id, "transcript shrank, recomputing analytics");
+        state = None;
+    }
+    if state.as_ref().is_some_and(|s| s.pending) {
+        debug!(session = %session_id, "previous update was interrupted, recomputing analytics");
+        state = None;
+    }
+    if source.cursor_kind() == CursorKind::MessageCount
+        && state.as_ref().is_some_and(|s| s.file_size == file_size)
+    {
+        return Ok(0);
+    }
+    let cursor = state.as_ref().map_or(0, |s| s.cursor);
+
+    let (messages, new_cursor) = source.parse_incremental(path, cursor).await?;
+    if state.is_none() || new_cursor < cursor {
+        db.clear_analytics_session(&session_id).await?;
+        state = None;
+    }
+
+    let open = match state {
+        Some(_) => db.get_open_tool_calls(&session_id).await?,
+        None => Vec::new(),
+    };
+    // Transcripts without timestamps are dated by their last write.
+    let fallback_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as i64);
+    let mut fold = Fold::new(session_id, state.as_ref(), open, fallback_ms);
+    for msg in &messages {
+        fold.add(msg);
+    }
+
+    // The aggregate additions cannot be rolled back, so the new cursor is
+    // stored first, marked pending, and cleared once they are all written.
+    // A row still pending on the next update means they were interrupted,
+    // and the session is recomputed from the start.
+    db.put_analytics_session(&AnalyticsSession {
+        session_id,
+        cli: source.name().to_string(),
+        project,
+        path: path_str,
+        cursor: new_cursor,
+        file_size,
+        first_at: fold.first_at,
+        last_at: fold.last_at,
+        last_usage: fold.last_usage,
+        updated_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+        pending: true,
+    })
+    .await?;
+    for usage in fold.usage.values() {
+        db.add_usage_aggregate(usage).await?;
+    }
+    for tool in fold.tools.values() {
+        db.add_tool_aggregate(tool).await?;
+    }
+    let open: Vec<OpenToolCall> = fold.open.into_values().collect();
+    db.put_open_tool_calls(&session_id, &open).await?;
+    db.finish_analytics_session(&session_id).await?;
+    Ok(messages.len())
+}
+
+/// Tools that usually wait for the user's go-ahead before they run.
+fn needs_approval(tool: &str) -> bool {
+    matches!(
+        ToolKind::from_tool_name(tool),
+        ToolKind::Shell | ToolKind::Edit | ToolKind::Web
+    )
+}
+
+/// `YYYY-MM-DD` (UTC) of an epoch-milliseconds timestamp.
+pub fn day_of(ms: i64) -> String {
+    time::OffsetDateTime::from_unix_timestamp(ms.div_euclid(1000))
+        .map(|dt| format!("{:04}-{:02}-{:02}", dt.year(), dt.month() as u8, dt.day()))
+        .unwrap_or_default()
+}
+
+/// Deltas of one incremental read, ready to be added to the stored rows.
+struct Fold {
+    session_id: Uuid,
+    usage: BTreeMap<(String, String), UsageAggregate>,
+    tools: BTreeMap<(String, String), ToolAggregate>,
+    open: HashMap<String, OpenToolCall>,
+    first_at: Option<i64>,
+    last_at: Option<i64>,
+    last_usage: Option<String>,
+    /// Timestamp of the latest message, for messages without one.
+    last_ms: i64,
+}
+
+impl Fold {
+    fn new(
+        session_id: Uuid,
+        state: Option<&AnalyticsSession>,
+        open: Vec<OpenToolCall>,
+        fallback_ms: i64,
+    ) -> Self {
+        Self {
+            session_id,
+            usage: BTreeMap::new(),
+            tools: BTreeMap::new(),
+            open: open
+                .into_iter()
+                .map(|c| (c.tool_use_id.clone(), c))
+                .collect(),
+            first_at: state.and_then(|s| s.first_at),
+            last_at: state.and_then(|s| s.last_at),
+            last_usage: state.and_then(|s| s.last_usage.clone()),
+            last_ms: state
+                .and_then(|s| s.last_at)
+                .map_or(fallback_ms, |s| s * 1000),
+        }
+    }
+
+    fn add(&mut self, msg: &ClaudeMessage) {
+        let Some(component) = parser::message_to_component(msg, self.session_id) else {
+            return;
+        };
+        if component.role == MessageRole::Meta {
+            return;
+        }
+        if let Some(ms) = msg.timestamp.as_deref().and_then(parser::parse_iso_millis) {
+            self.last_ms = ms;
+            let secs = ms.div_euclid(1000);
+            self.first_at = Some(self.first_at.map_or(secs, |f| f.min(secs)));
+            self.last_at = Some(self.last_at.map_or(secs, |l| l.max(secs)));
+        }
+        let ms = self.last_ms;
+        let day = day_of(ms);
+        let model = component.model.clone().unwrap_or_default();
+
+        let usage = self
+            .usage
+            .entry((day.clone(), model.clone()))
+            .or_insert_with(|| UsageAggregate {
+                session_id: self.session_id,
+                day: day.clone(),
+                model: model.clone(),
+                ..Default::default()
+            });
+        usage.messages += 1;
+        if component.role == MessageRole::User
+            && component.message_type == MessageType::Text
+            && !component.content.trim().is_empty()
+        {
+            usage.turns += 1;
+        }
+        if component.role == MessageRole::Assistant {
+            let input = msg.input_tokens.unwrap_or(0);
+            let output = msg.output_tokens.unwrap_or(0);
+            let cache_creation = msg.cache_creation_input_tokens.unwrap_or(0);
+            let cache_read = msg.cache_read_input_tokens.unwrap_or(0);
+            // One API response is split into a line per content block, each
+            // repeating the response's usage.
+            let signature = format!("{model}:{input}:{output}:{cache_creation}:{cache_read}");
+            if input + output + cache_creation + cache_read > 0
+                && self.last_usage.as_deref() != Some(signature.as_str())
+            {
+                usage.input_tokens += input;
+                usage.output_tokens += output;
+                usage.cache_creation_tokens += cache_creation;
+                usage.cache_read_tokens += cache_read;
+                usage.cost_usd += msg
+                    .cost_usd
+                    .unwrap_or_else(|| calculate_cost(&model, input, output));
+                self.last_usage = Some(signature);
+            }
+        }
+
+        let MessageContent::Blocks(blocks) = &msg.content else {
+            return;
+        };
+        for block in blocks {
+            match block {
+                ContentBlock::ToolUse { id, name, .. } => {
+                    self.tool(&day, name).calls += 1;
+                    self.open.insert(
+                        id.clone(),
+                        OpenToolCall {
+                            tool_use_id: id.clone(),
+                            tool: name.clone(),
+                            started_at_ms: ms,
+                        },
+                    );
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    is_error,
+                    ..
+                } => {
+                    let Some(call) = self.open.remove(tool_use_id) else {
+                        continue;
+                    };
+                    let duration = (ms - call.started_at_ms).max(0) as u64;
+                    let stats = self.tool(&day_of(call.started_at_ms), &call.tool);
+                    stats.errors += u64::from(is_error.unwrap_or(false));
+                    stats.duration_ms += duration;
+                    if needs_approval(&call.tool) {
+                        stats.approval_wait_ms += duration;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn tool(&mut self, day: &str, tool: &str) -> &mut ToolAggregate {
+        self.tools
+            .entry((day.to_string(), tool.to_string()))
+            .or_insert_with(|| ToolAggregate {
+                session_id: self.session_id,
+                day: day.to_string(),
+                tool: tool.to_string(),
+                ..Default::default()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn line(uuid: u32, kind: &str, ts: &str, content: serde_json::Value) -> String {
+        let mut message = serde_json::json!({"role": kind, "content": content});
+        if kind == "assistant" {
+            message["model"] = "claude-sonnet-4-5".into();
+            message["usage"] = serde_json::json!({"input_tokens": 100, "output_tokens": 50,
+                "cache_read_input_tokens": 300, "cache_creation_input_tokens": 0});
+        }
+        serde_json::json!({
+            "type": kind,
+            "uuid": format!("00000000-0000-4000-8000-{uuid:012}"),
+            "sessionId": "s",
+            "timestamp": ts,
+            "message": message,
+        })
+        .to_string()
+    }
+
+    fn append(path: &Path, lines: &[String]) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        for l in lines {
+            writeln!(file, "{l}").unwrap();
+        }
+    }
+
+    #[test]
+    fn days_are_utc() {
+        assert_eq!(day_of(1_740_823_202_500), "2025-03-01");
+        assert_eq!(day_of(0), "1970-01-01");
+    }
+
+    #[tokio::test]
+    async fn folds_incrementally_and_pairs_tool_calls_across_reads() {
+        let db = Db::open(":memory:").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let sid = Uuid::new_v4();
+        let project = Some("/work/app".to_string());
+
+        append(
+            &path,
+            &[
+                line(1, "user", "2026-03-01T23:59:00.000Z", "deploy it".into()),
+                line(
+                    2,
+                    "assistant",
+                    "2026-03-01T23:59:10.000Z",
+                    serde_json::json!([{"type": "text", "text": "running"}]),
+                ),
+                // Same response, next content block: usage is not counted twice.
+                line(
+                    3,
+                    "assistant",
+                    "2026-03-01T23:59:10.000Z",
+                    serde_json::json!([{"type": "tool_use", "id": "t1", "name": "Bash",
+                        "input": {"command": "make deploy"}}]),
+                ),
+            ],
+        );
+        update_session(&db, sid, &path, CliType::Claude, project.clone())
+            .await
+            .unwrap();
+        assert_eq!(db.get_open_tool_calls(&sid).await.unwrap().len(), 1);
+
+        append(
+            &path,
+            &[line(
+                4,
+                "user",
+                "2026-03-02T00:00:40.000Z",
+                serde_json::json!([{"type": "tool_result", "tool_use_id": "t1",
+                    "content": "boom", "is_error": true}]),
+            )],
+        );
+        update_session(&db, sid, &path, CliType::Claude, project.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            update_session(&db, sid, &path, CliType::Claude, project)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(db.get_open_tool_calls(&sid).await.unwrap().is_empty());
+
+        let usage = db.get_usage_aggregates(None, None).await.unwrap();
+        let total = |f: fn(&UsageAggregate) -> u64| usage.iter().map(f).sum::<u64>();
+        assert_eq!(total(|u| u.messages), 4);
+        assert_eq!(total(|u| u.turns), 1, "tool results are not turns");
+        assert_eq!(total(|u| u.input_tokens), 100);
+        assert_eq!(total(|u| u.cache_read_tokens), 300);
+        assert!(usage.iter().any(|u| u.day == "2026-03-02"));
+
+        // The call is booked on the day it was made, with its outcome.
+        let tools = db.get_tool_aggregates(None, None).await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].day, "2026-03-01");
+        assert_eq!((tools[0].calls, tools[0].errors), (1, 1));
+        assert_eq!(tools[0].duration_ms, 90_000);
+        assert_eq!(tools[0].approval_wait_ms, 90_000);
+
+        // A rewritten transcript replaces the old aggregates.
+        std::fs::write(&path, "").unwrap();
+        append(
+            &path,
+            &[line(5, "user", "2026-03-05T08:00:00.000Z", "hi".into())],
+        );
+        update_session(&db, sid, &path, CliType::Claude, None)
+            .await
+            .unwrap();
+        let usage = db.get_usage_aggregates(None, None).await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].day, "2026-03-05");
+        assert!(db.get_tool_aggregates(None, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn interrupted_update_is_recomputed() {
+        let db = Db::open(":memory:").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let sid = Uuid::new_v4();
+
+        append(
+            &path,
+            &[line(1, "user", "2026-03-01T10:00:00.000Z", "hi".into())],
+        );
+        update_session(&db, sid, &path, CliType::Claude, None)
+            .await
+            .unwrap();
+        let state = db.get_analytics_session(&sid).await.unwrap().unwrap();
+        assert!(!state.pending);
+
+        // An update that stored its cursor but died half-way through the
+        // aggregates: the totals double-count the first message.
+        let stored = db.get_usage_aggregates(None, None).await.unwrap();
+        db.add_usage_aggregate(&stored[0]).await.unwrap();
+        db.put_analytics_session(&AnalyticsSession {
+            pending: true,
+            ..state
+        })
+        .await
+        .unwrap();
+
+        append(
+            &path,
+            &[line(2, "user", "2026-03-01T10:01:00.000Z", "again".into())],
+        );
+        assert_eq!(
+            update_session(&db, sid, &path, CliType::Claude, None)
+                .await
+                .unwrap(),
+            2
+        );
+        let usage = db.get_usage_aggregates(None, None).await.unwrap();
+        assert_eq!(usage.iter().map(|u| u.messages).sum::<u64>(), 2);
+        assert!(
+            !db.get_analytics_session(&sid)
+                .await
+                .unwrap()
+                .unwrap()
+                .pending
+        );
+    }
+}
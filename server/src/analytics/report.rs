@@ -0,0 +1,513 @@
+//! Reports over the stored aggregates, as JSON or CSV.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{AnalyticsSession, Db, DbResult};
+use crate::ecs::components::ToolKind;
+
+const DEFAULT_TOP_SESSIONS: usize = 20;
+
+/// Which rows go into a report. Days are `YYYY-MM-DD`, inclusive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Filter {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Transcript source name (`claude`, `codex`, …).
+    pub cli: Option<String>,
+    /// Case-insensitive substring of the project path.
+    pub project: Option<String>,
+    pub model: Option<String>,
+    /// Length of `topSessions` (default 20).
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    pub fn validate(&self) -> Result<(), String> {
+        for day in [&self.from, &self.to].into_iter().flatten() {
+            let b = day.as_bytes();
+            let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+            if b.len() != 10
+                || b[4] != b'-'
+                || b[7] != b'-'
+                || !digits(0..4)
+                || !digits(5..7)
+                || !digits(8..10)
+            {
+                return Err(format!("{day:?} is not a YYYY-MM-DD day"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Totals of one group of aggregates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub key: String,
+    pub sessions: u64,
+    pub messages: u64,
+    pub turns: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cost_usd: f64,
+    /// Share of prompt tokens served from the cache.
+    pub cache_hit_ratio: f64,
+    pub avg_turns_per_session: f64,
+    /// Tool aggregates carry no model: zero in `byModel` groups.
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+    pub failure_rate: f64,
+    /// Call-to-result time of permission-gated tools (shell, edits, web).
+    /// Transcripts do not record the prompt itself, so this is an upper
+    /// bound that includes the tools' run time.
+    pub approval_wait_secs: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolRow {
+    pub tool: String,
+    pub kind: &'static str,
+    pub calls: u64,
+    pub errors: u64,
+    pub failure_rate: f64,
+    /// Call-to-result time per call.
+    pub avg_duration_ms: f64,
+    pub approval_wait_secs: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRow {
+    pub session_id: Uuid,
+    pub cli: String,
+    pub project: Option<String>,
+    pub first_at: Option<i64>,
+    pub last_at: Option<i64>,
+    pub messages: u64,
+    pub turns: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    pub tool_calls: u64,
+    pub tool_errors: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub totals: Group,
+    /// Ascending by day.
+    pub by_day: Vec<Group>,
+    /// The remaining groupings are sorted by cost, highest first.
+    pub by_project: Vec<Group>,
+    pub by_model: Vec<Group>,
+    pub by_cli: Vec<Group>,
+    /// Sorted by calls.
+    pub tools: Vec<ToolRow>,
+    /// Ranked by cost.
+    pub top_sessions: Vec<SessionRow>,
+}
+
+/// The tables `GET /api/analytics/export` can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum View {
+    Day,
+    Project,
+    Model,
+    Cli,
+    Tools,
+    Sessions,
+}
+
+/// Build a report from the stored aggregates.
+pub async fn build(db: &Db, filter: &Filter) -> DbResult<Report> {
+    let sessions: HashMap<Uuid, AnalyticsSession> = db
+        .get_analytics_sessions()
+        .await?
+        .into_iter()
+        .map(|s| (s.session_id, s))
+        .collect();
+    let project_filter = filter.project.as_ref().map(|p| p.to_lowercase());
+    let included = |sid: &Uuid| {
+        let Some(s) = sessions.get(sid) else {
+            return false;
+        };
+        filter
+            .cli
+            .as_ref()
+            .is_none_or(|c| s.cli.eq_ignore_ascii_case(c))
+            && project_filter.as_ref().is_none_or(|p| {
+                s.project
+                    .as_ref()
+                    .is_some_and(|project| project.to_lowercase().contains(p))
+            })
+    };
+    let from = filter.from.as_deref();
+    let to = filter.to.as_deref();
+
+    let usage: Vec<_> = db
+        .get_usage_aggregates(from, to)
+        .await?
+        .into_iter()
+        .filter(|u| included(&u.session_id))
+        .filter(|u| filter.model.as_ref().is_none_or(|m| &u.model == m))
+        .collect();
+    let mut tools: Vec<_> = db
+        .get_tool_aggregates(from, to)
+        .await?
+        .into_iter()
+        .filter(|t| included(&t.session_id))
+        .collect();
+    if filter.model.is_some() {
+        // Tools carry no model: keep the session-days that used it.
+        let used: HashSet<(Uuid, &str)> = usage
+            .iter()
+            .map(|u| (u.session_id, u.day.as_str()))
+            .collect();
+        tools.retain(|t| used.contains(&(t.session_id, t.day.as_str())));
+    }
+
+    let project_of = |sid: &Uuid| {
+        sessions
+            .get(sid)
+            .and_then(|s| s.project.clone())
+            .unwrap_or_default()
+    };
+    let cli_of = |sid: &Uuid| sessions.get(sid).map(|s| s.cli.clone()).unwrap_or_default();
+
+    let mut totals = Acc::default();
+    let mut by_day: BTreeMap<String, Acc> = BTreeMap::new();
+    let mut by_project: HashMap<String, Acc> = HashMap::new();
+    let mut by_model: HashMap<String, Acc> = HashMap::new();
+    let mut by_cli: HashMap<String, Acc> = HashMap::new();
+    let mut by_session: HashMap<Uuid, Acc> = HashMap::new();
+    for u in &usage {
+        let sid = &u.session_id;
+        for acc in [
+            &mut totals,
+            by_day.entry(u.day.clone()).or_default(),
+            by_project.entry(project_of(sid)).or_default(),
+            by_cli.entry(cli_of(sid)).or_default(),
+            by_session.entry(*sid).or_default(),
+        ] {
+            acc.add_usage(u);
+        }
+        // User turns have no model; they still count for the fleet.
+        if !u.model.is_empty() {
+            by_model.entry(u.model.clone()).or_default().add_usage(u);
+        }
+    }
+    let mut tool_rows: HashMap<String, ToolRow> = HashMap::new();
+    for t in &tools {
+        let sid = &t.session_id;
+        for acc in [
+            &mut totals,
+            by_day.entry(t.day.clone()).or_default(),
+            by_project.entry(project_of(sid)).or_default(),
+            by_cli.entry(cli_of(sid)).or_default(),
+            by_session.entry(*sid).or_default(),
+        ] {
+            acc.add_tools(t);
+        }
+        let row = tool_rows.entry(t.tool.clone()).or_insert_with(|| ToolRow {
+            tool: t.tool.clone(),
+            kind: ToolKind::from_tool_name(&t.tool).as_str(),
+            ..Default::default()
+        });
+        row.calls += t.calls;
+        row.errors += t.errors;
+        row.avg_duration_ms += t.duration_ms as f64;
+        row.approval_wait_secs += t.approval_wait_ms as f64 / 1000.0;
+    }
+
+    let by_cost = |groups: HashMap<String, Acc>| {
+        let mut groups: Vec<Group> = groups.into_iter().map(|(k, a)| a.finish(k)).collect();
+        groups.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd).then(a.key.cmp(&b.key)));
+        groups
+    };
+    let mut tools: Vec<ToolRow> = tool_rows
+        .into_values()
+        .map(|mut row| {
+            row.failure_rate = ratio(row.errors as f64, row.calls as f64);
+            row.avg_duration_ms = ratio(row.avg_duration_ms, row.calls as f64);
+            row
+        })
+        .collect();
+    tools.sort_by(|a, b| b.calls.cmp(&a.calls).then(a.tool.cmp(&b.tool)));
+
+    let mut top_sessions: Vec<SessionRow> = by_session
+        .into_iter()
+        .map(|(sid, acc)| {
+            let s = sessions.get(&sid);
+            let g = acc.finish(String::new());
+            SessionRow {
+                session_id: sid,
+                cli: s.map(|s| s.cli.clone()).unwrap_or_default(),
+                project: s.and_then(|s| s.project.clone()),
+                first_at: s.and_then(|s| s.first_at),
+                last_at: s.and_then(|s| s.last_at),
+                messages: g.messages,
+                turns: g.turns,
+                input_tokens: g.input_tokens,
+                output_tokens: g.output_tokens,
+                cost_usd: g.cost_usd,
+                tool_calls: g.tool_calls,
+                tool_errors: g.tool_errors,
+            }
+        })
+        .collect();
+    top_sessions.sort_by(|a, b| {
+        b.cost_usd
+            .total_cmp(&a.cost_usd)
+            .then(b.last_at.cmp(&a.last_at))
+    });
+    top_sessions.truncate(filter.limit.unwrap_or(DEFAULT_TOP_SESSIONS));
+
+    Ok(Report {
+        from: filter.from.clone(),
+        to: filter.to.clone(),
+        totals: totals.finish("total".to_string()),
+        by_day: by_day.into_iter().map(|(k, a)| a.finish(k)).collect(),
+        by_project: by_cost(by_project),
+        by_model: by_cost(by_model),
+        by_cli: by_cost(by_cli),
+        tools,
+        top_sessions,
+    })
+}
+
+/// Render one table of a report as CSV.
+pub fn to_csv(report: &Report, view: View) -> String {
+    let mut out = String::new();
+    match view {
+        View::Day | View::Project | View::Model | View::Cli => {
+            let (key, groups) = match view {
+                View::Day => ("day", &report.by_day),
+                View::Project => ("project", &report.by_project),
+                View::Model => ("model", &report.by_model),
+                _ => ("cli", &report.by_cli),
+            };
+            out.push_str(&format!(
+                "{key},sessions,messages,turns,input_tokens,output_tokens,cache_creation_tokens,cache_read_tokens,cost_usd,cache_hit_ratio,avg_turns_per_session,tool_calls,tool_errors,failure_rate,approval_wait_secs\n"
+            ));
+            for g in groups {
+                out.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{:.6},{:.4},{:.2},{},{},{:.4},{:.1}\n",
+                    csv_field(&g.key),
+                    g.sessions,
+                    g.messages,
+                    g.turns,
+                    g.input_tokens,
+                    g.output_tokens,
+                    g.cache_creation_tokens,
+                    g.cache_read_tokens,
+                    g.cost_usd,
+                    g.cache_hit_ratio,
+                    g.avg_turns_per_session,
+                    g.tool_calls,
+                    g.tool_errors,
+                    g.failure_rate,
+                    g.approval_wait_secs,
+                ));
+            }
+        }
+        View::Tools => {
+            out.push_str(
+                "tool,kind,calls,errors,failure_rate,avg_duration_ms,approval_wait_secs\n",
+            );
+            for t in &report.tools {
+                out.push_str(&format!(
+                    "{},{},{},{},{:.4},{:.0},{:.1}\n",
+                    csv_field(&t.tool),
+                    t.kind,
+                    t.calls,
+                    t.errors,
+                    t.failure_rate,
+                    t.avg_duration_ms,
+                    t.approval_wait_secs,
+                ));
+            }
+        }
+        View::Sessions => {
+            out.push_str("session_id,cli,project,first_at,last_at,messages,turns,input_tokens,output_tokens,cost_usd,tool_calls,tool_errors\n");
+            for s in &report.top_sessions {
+                out.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{:.6},{},{}\n",
+                    s.session_id,
+                    csv_field(&s.cli),
+                    csv_field(s.project.as_deref().unwrap_or("")),
+                    s.first_at.map(|t| t.to_string()).unwrap_or_default(),
+                    s.last_at.map(|t| t.to_string()).unwrap_or_default(),
+                    s.messages,
+                    s.turns,
+                    s.input_tokens,
+                    s.output_tokens,
+                    s.cost_usd,
+                    s.tool_calls,
+                    s.tool_errors,
+                ));
+            }
+        }
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn ratio(part: f64, whole: f64) -> f64 {
+    if whole > 0.0 { part / whole } else { 0.0 }
+}
+
+#[derive(Default)]
+struct Acc {
+    group: Group,
+    sessions: HashSet<Uuid>,
+    approval_wait_ms: u64,
+}
+
+impl Acc {
+    fn add_usage(&mut self, u: &crate::db::UsageAggregate) {
+        self.sessions.insert(u.session_id);
+        let g = &mut self.group;
+        g.messages += u.messages;
+        g.turns += u.turns;
+        g.input_tokens += u.input_tokens;
+        g.output_tokens += u.output_tokens;
+        g.cache_creation_tokens += u.cache_creation_tokens;
+        g.cache_read_tokens += u.cache_read_tokens;
+        g.cost_usd += u.cost_usd;
+    }
+
+    fn add_tools(&mut self, t: &crate::db::ToolAggregate) {
+        self.sessions.insert(t.session_id);
+        self.group.tool_calls += t.calls;
+        self.group.tool_errors += t.errors;
+        self.approval_wait_ms += t.approval_wait_ms;
+    }
+
+    fn finish(self, key: String) -> Group {
+        let mut g = self.group;
+        g.key = key;
+        g.sessions = self.sessions.len() as u64;
+        let prompt = g.input_tokens + g.cache_creation_tokens + g.cache_read_tokens;
+        g.cache_hit_ratio = ratio(g.cache_read_tokens as f64, prompt as f64);
+        g.avg_turns_per_session = ratio(g.turns as f64, g.sessions as f64);
+        g.failure_rate = ratio(g.tool_errors as f64, g.tool_calls as f64);
+        g.approval_wait_secs = self.approval_wait_ms as f64 / 1000.0;
+        g
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ToolAggregate, UsageAggregate};
+
+    async fn seed(db: &Db, project: &str, cli: &str, day: &str, model: &str, cost: f64) -> Uuid {
+        let sid = Uuid::new_v4();
+        db.put_analytics_session(&AnalyticsSession {
+            session_id: sid,
+            cli: cli.to_string(),
+            project: Some(project.to_string()),
+            path: format!("/t/{sid}.jsonl"),
+            cursor: 0,
+            file_size: 0,
+            first_at: None,
+            last_at: None,
+            last_usage: None,
+            updated_at: 0,
+            pending: false,
+        })
+        .await
+        .unwrap();
+        db.add_usage_aggregate(&UsageAggregate {
+            session_id: sid,
+            day: day.to_string(),
+            model: model.to_string(),
+            messages: 4,
+            turns: 2,
+            input_tokens: 100,
+            cache_read_tokens: 300,
+            cost_usd: cost,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        db.add_tool_aggregate(&ToolAggregate {
+            session_id: sid,
+            day: day.to_string(),
+            tool: "Bash".to_string(),
+            calls: 4,
+            errors: 1,
+            duration_ms: 2_000,
+            approval_wait_ms: 2_000,
+        })
+        .await
+        .unwrap();
+        sid
+    }
+
+    #[tokio::test]
+    async fn groups_filters_and_ranks() {
+        let db = Db::open(":memory:").await.unwrap();
+        let cheap = seed(&db, "/work/api", "claude", "2026-03-01", "sonnet", 1.0).await;
+        let pricey = seed(&db, "/work/web, app", "codex", "2026-03-02", "gpt-5", 5.0).await;
+        seed(&db, "/work/api", "claude", "2026-02-01", "sonnet", 9.0).await;
+
+        let filter = Filter {
+            from: Some("2026-03-01".into()),
+            ..Default::default()
+        };
+        let report = build(&db, &filter).await.unwrap();
+        assert_eq!(report.totals.sessions, 2);
+        assert_eq!(report.totals.cost_usd, 6.0);
+        assert_eq!(report.totals.cache_hit_ratio, 0.75);
+        assert_eq!(report.totals.avg_turns_per_session, 2.0);
+        assert_eq!(report.totals.failure_rate, 0.25);
+        assert_eq!(report.totals.approval_wait_secs, 4.0);
+        let days: Vec<_> = report.by_day.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(days, ["2026-03-01", "2026-03-02"]);
+        assert_eq!(report.by_model[0].key, "gpt-5");
+        assert_eq!(report.tools[0].avg_duration_ms, 500.0);
+        let ranked: Vec<_> = report.top_sessions.iter().map(|s| s.session_id).collect();
+        assert_eq!(ranked, [pricey, cheap]);
+
+        let filter = Filter {
+            project: Some("API".into()),
+            cli: Some("claude".into()),
+            ..Default::default()
+        };
+        let report = build(&db, &filter).await.unwrap();
+        assert_eq!(report.totals.sessions, 2);
+        assert_eq!(report.totals.cost_usd, 10.0);
+
+        let report = build(&db, &Filter::default()).await.unwrap();
+        let csv = to_csv(&report, View::Project);
+        assert!(csv.starts_with("project,sessions,"));
+        assert!(csv.contains("\n\"/work/web, app\",1,4,2,"));
+        assert_eq!(to_csv(&report, View::Sessions).lines().count(), 4);
+
+        let bad = Filter {
+            to: Some("2026-3-1".into()),
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! The backup container.
+//!
+//! A zstd stream holding one JSON [`Manifest`] line, then the bytes of
+//! every entry back to back, in manifest order. Entry sizes come from the
+//! manifest, so reading the header costs one short decompression and the
+//! rest can be verified entry by entry.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::BackupError;
+
+/// Container layout version.
+pub const FORMAT: u32 = 1;
+
+/// `kind` of every manifest, so other zstd files are told apart.
+const KIND: &str = "noaide-backup";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntryKind {
+    /// `db/<table>.jsonl`: one [`TableRows`](crate::db::TableRows) page per line.
+    Table,
+    /// A file restored as is.
+    File,
+    /// `api-keys.json` with every key sealed under the passphrase.
+    ApiKeys,
+    /// A file sealed whole under the passphrase (the CA private key).
+    Sealed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the entry's bytes.
+    pub sha256: String,
+    /// Row count, for tables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u64>,
+}
+
+/// How the sealed entries were encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sealing {
+    /// Always `pbkdf2-sha256`, feeding AES-256-GCM.
+    pub kdf: String,
+    pub iterations: u32,
+    /// Base64.
+    pub salt: String,
+    /// A known plaintext sealed with the derived key, so a wrong
+    /// passphrase is reported before anything is extracted.
+    pub check: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub kind: String,
+    pub format: u32,
+    pub noaide_version: String,
+    /// Database schema version the tables were exported from.
+    pub schema_version: u32,
+    /// Epoch seconds.
+    pub created_at: i64,
+    pub hostname: Option<String>,
+    pub sealing: Sealing,
+    pub entries: Vec<Entry>,
+    /// Things left out of the backup, e.g. keys this host could not decrypt.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl Manifest {
+    pub fn new(schema_version: u32, created_at: i64, sealing: Sealing) -> Self {
+        Self {
+            kind: KIND.to_string(),
+            format: FORMAT,
+            noaide_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version,
+            created_at,
+            hostname: hostname(),
+            sealing,
+            entries: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|e| e.size).sum()
+    }
+}
+
+fn hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+/// Write the manifest and the entries, read from `dir/<entry name>`.
+pub fn write(path: &Path, manifest: &Manifest, dir: &Path) -> std::io::Result<u64> {
+    let tmp = path.with_extension("tmp");
+    let result = (|| {
+        let file = std::fs::File::create(&tmp)?;
+        let mut encoder = zstd::Encoder::new(file, 3)?;
+        serde_json::to_writer(&mut encoder, manifest)?;
+        encoder.write_all(b"\n")?;
+        for entry in &manifest.entries {
+            let mut file = std::fs::File::open(dir.join(&entry.name))?;
+            std::io::copy(&mut file, &mut encoder)?;
+        }
+        encoder.finish()?.sync_all()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Open a backup: the checked manifest, and a reader positioned at the
+/// first entry.
+pub fn open(path: &Path) -> Result<(Manifest, impl Read + use<>), BackupError> {
+    let file = std::fs::File::open(path)?;
+    let decoder =
+        zstd::Decoder::new(file).map_err(|e| BackupError::Format(format!("not zstd: {e}")))?;
+    let mut reader = BufReader::new(decoder);
+    let mut line = Vec::new();
+    // A manifest is small; anything longer is not one.
+    (&mut reader)
+        .take(16 * 1024 * 1024)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| BackupError::Format(e.to_string()))?;
+    let manifest: Manifest = serde_json::from_slice(&line)
+        .map_err(|e| BackupError::Format(format!("unreadable manifest: {e}")))?;
+    if manifest.kind != KIND {
+        return Err(BackupError::Format(format!("kind is {:?}", manifest.kind)));
+    }
+    if manifest.format > FORMAT {
+        return Err(BackupError::Format(format!(
+            "format {} is newer than this build",
+            manifest.format
+        )));
+    }
+    Ok((manifest, reader))
+}
+
+/// Lowercase hex SHA-256 of everything written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    ctx: ring::digest::Context,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            ctx: ring::digest::Context::new(&ring::digest::SHA256),
+            size: 0,
+        }
+    }
+
+    /// Flush and return `(size, sha256)`.
+    pub fn finish(mut self) -> std::io::Result<(u64, String)> {
+        self.inner.flush()?;
+        let digest = self.ctx.finish();
+        let hex = digest.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+        Ok((self.size, hex))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.ctx.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,970 @@
+//! Backup and restore of the whole noaide state.
+//!
+//! A backup is one file, `noaide-backup-<epoch>.nbk`, holding the database,
+//! the config and data files, the captured-traffic archives and the CA. Its
+//! [`Manifest`] lists every entry with size and SHA-256. Database tables
+//! travel as [`TableRows`] pages, so a backup imports into any later schema.
+//! `api-keys.json` is encrypted with a key derived from `/etc/machine-id`,
+//! which does not survive a move: keys (and the CA private key) are sealed
+//! under a passphrase instead.
+//!
+//! Restoring takes two steps. [`stage`] checks every entry and re-keys the
+//! API keys for this host into a staging directory, leaving live state
+//! alone. [`apply_staged`] moves the staged state into place; the server
+//! runs it on startup, before it opens the database.
+
+mod format;
+
+pub use format::{Entry, EntryKind, FORMAT, Manifest, Sealing};
+
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ring::aead::LessSafeKey;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use tracing::info;
+
+use crate::db::schema::SCHEMA_VERSION;
+use crate::db::{DATA_TABLES, Db, DbError, TableRows};
+use crate::proxy::keys::{self, PersistedKeyStore};
+use crate::proxy::{persist, preflight, profiles, tls_mitm};
+use crate::retention::StoragePaths;
+use format::HashingWriter;
+
+pub const EXTENSION: &str = ".nbk";
+
+/// PBKDF2 rounds for new backups; restores use the count in the manifest.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+/// Most PBKDF2 rounds a manifest may ask for, so a crafted backup can't pin
+/// a CPU for hours before the passphrase check.
+pub const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Rows per exported [`TableRows`] page.
+const PAGE_ROWS: u64 = 500;
+
+const KDF: &str = "pbkdf2-sha256";
+const CHECK_PLAINTEXT: &[u8] = b"noaide-backup";
+
+/// The manifest of a staged restore, written last.
+const STAGED_MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("passphrase must be at least {MIN_PASSPHRASE_LEN} characters")]
+    WeakPassphrase,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("not a valid noaide backup: {0}")]
+    Format(String),
+    #[error("backup has schema version {0}, newer than this build ({SCHEMA_VERSION})")]
+    NewerSchema(u32),
+    #[error("checksum mismatch in {0}")]
+    Checksum(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// Where each piece of state lives on this host.
+#[derive(Debug, Clone)]
+pub struct StateLayout {
+    pub db: PathBuf,
+    pub storage: StoragePaths,
+    pub api_keys: PathBuf,
+    pub proxy_profiles: PathBuf,
+    pub model_limits: PathBuf,
+    pub managed_sessions: PathBuf,
+    pub ca_cert: PathBuf,
+    pub ca_key: PathBuf,
+    /// Backups created or uploaded through the API.
+    pub backups: PathBuf,
+    /// A verified, re-keyed restore waiting for the next start.
+    pub pending: PathBuf,
+}
+
+impl StateLayout {
+    /// The paths the server uses, with the same environment overrides.
+    pub fn from_env() -> Self {
+        let data_dir = persist::config_dir();
+        Self {
+            db: std::env::var("NOAIDE_DB_PATH")
+                .unwrap_or_else(|_| "/data/noaide/ide.db".into())
+                .into(),
+            storage: StoragePaths::default(),
+            api_keys: keys::key_store_path(),
+            proxy_profiles: profiles::profiles_path(),
+            model_limits: preflight::limits_path().into(),
+            managed_sessions: data_dir.join("managed-sessions.json"),
+            ca_cert: ca_path(
+                tls_mitm::find_ca_cert_path(),
+                "NOAIDE_CA_CERT",
+                "certs/rootCA.pem",
+            ),
+            ca_key: ca_path(
+                tls_mitm::find_ca_key_path(),
+                "NOAIDE_CA_KEY",
+                "certs/rootCA-key.pem",
+            ),
+            backups: data_dir.join("backups"),
+            pending: data_dir.join("restore-pending"),
+        }
+    }
+
+    /// Single files, by entry name.
+    fn files(&self) -> [(&'static str, &Path); 5] {
+        [
+            ("files/retention.json", &self.storage.policy),
+            ("files/audit-log.jsonl", &self.storage.audit_log),
+            ("files/proxy-profiles.json", &self.proxy_profiles),
+            ("files/model-limits.json", &self.model_limits),
+            ("files/managed-sessions.json", &self.managed_sessions),
+        ]
+    }
+
+    /// Where an entry is restored to. `None` for names this build does not
+    /// know, including any that would leave their directory.
+    fn target(&self, entry: &Entry) -> Option<Target> {
+        let name = entry.name.as_str();
+        match entry.kind {
+            EntryKind::Table => {
+                let table = name.strip_prefix("db/")?.strip_suffix(".jsonl")?;
+                DATA_TABLES.contains(&table).then_some(Target::Table)
+            }
+            EntryKind::ApiKeys => (name == "api-keys.json").then(|| self.api_keys.clone().into()),
+            EntryKind::Sealed => {
+                (name == "certs/rootCA-key.pem").then(|| self.ca_key.clone().into())
+            }
+            EntryKind::File => {
+                if name == "certs/rootCA.pem" {
+                    return Some(self.ca_cert.clone().into());
+                }
+                if let Some((_, path)) = self.files().into_iter().find(|(n, _)| *n == name) {
+                    return Some(path.to_path_buf().into());
+                }
+                if let Some(file) = name.strip_prefix("proxy-configs/")
+                    && plain_name(file)
+                    && is_proxy_config(file)
+                {
+                    return Some(self.storage.proxy_configs.join(file).into());
+                }
+                let file = name.strip_prefix("archive/")?;
+                plain_name(file).then(|| self.storage.archive.join(file).into())
+            }
+        }
+    }
+}
+
+enum Target {
+    Table,
+    Path(PathBuf),
+}
+
+impl From<PathBuf> for Target {
+    fn from(path: PathBuf) -> Self {
+        Target::Path(path)
+    }
+}
+
+/// The CA in use, else where the server would look for it first.
+fn ca_path(found: Option<String>, env_var: &str, default: &str) -> PathBuf {
+    found
+        .or_else(|| std::env::var(env_var).ok())
+        .unwrap_or_else(|| default.to_string())
+        .into()
+}
+
+fn plain_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+fn is_proxy_config(name: &str) -> bool {
+    name.starts_with("proxy-config-") && name.ends_with(".json")
+}
+
+/// Whether `name` can be a backup in the backups directory.
+pub fn valid_name(name: &str) -> bool {
+    plain_name(name)
+        && name.ends_with(EXTENSION)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}{suffix}", path.display()))
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    pub passphrase: String,
+    pub kdf_iterations: u32,
+}
+
+impl BackupOptions {
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            kdf_iterations: DEFAULT_KDF_ITERATIONS,
+        }
+    }
+}
+
+/// A backup on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSummary {
+    pub name: String,
+    /// Compressed file size.
+    pub bytes: u64,
+    pub manifest: Manifest,
+}
+
+/// What [`apply_staged`] put in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    /// When the backup was made, epoch seconds.
+    pub created_at: i64,
+    pub noaide_version: String,
+    pub rows: u64,
+    pub files: u64,
+    pub api_keys: u64,
+    /// The database that was there before, moved aside.
+    pub previous_db: Option<PathBuf>,
+}
+
+/// Back up everything in `layout` into a new file in `dir`.
+pub async fn create(
+    db: &Db,
+    layout: &StateLayout,
+    options: &BackupOptions,
+    dir: &Path,
+) -> Result<BackupSummary, BackupError> {
+    if options.passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(BackupError::WeakPassphrase);
+    }
+    std::fs::create_dir_all(dir)?;
+    let created_at = now_secs();
+    let name = format!("noaide-backup-{created_at}{EXTENSION}");
+    let staging = dir.join(format!(".{name}.staging"));
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let result = match collect(db, layout, options, &staging, created_at).await {
+        Ok(manifest) => format::write(&dir.join(&name), &manifest, &staging)
+            .map(|bytes| BackupSummary {
+                name,
+                bytes,
+                manifest,
+            })
+            .map_err(BackupError::from),
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_dir_all(&staging);
+    if let Ok(summary) = &result {
+        info!(
+            name = %summary.name,
+            entries = summary.manifest.entries.len(),
+            bytes = summary.bytes,
+            "backup created"
+        );
+    }
+    result
+}
+
+/// Write every entry into `staging` and describe it.
+async fn collect(
+    db: &Db,
+    layout: &StateLayout,
+    options: &BackupOptions,
+    staging: &Path,
+    created_at: i64,
+) -> Result<Manifest, BackupError> {
+    let (key, sealing) = new_sealing(&options.passphrase, options.kdf_iterations);
+    let mut manifest = Manifest::new(SCHEMA_VERSION, created_at, sealing);
+
+    for table in DATA_TABLES {
+        let mut out = EntryWriter::create(staging, &format!("db/{table}.jsonl"))?;
+        let mut offset = 0;
+        loop {
+            let page = db.export_table_page(table, offset, PAGE_ROWS).await?;
+            let n = page.rows.len() as u64;
+            if n > 0 {
+                serde_json::to_writer(&mut out, &page)?;
+                out.write_all(b"\n")?;
+            }
+            offset += n;
+            if n < PAGE_ROWS {
+                break;
+            }
+        }
+        let mut entry = out.finish(EntryKind::Table)?;
+        entry.rows = Some(offset);
+        manifest.entries.push(entry);
+    }
+
+    for (name, path) in layout.files() {
+        manifest.entries.extend(copy_entry(staging, name, path)?);
+    }
+    let dirs = [
+        ("proxy-configs", &layout.storage.proxy_configs, true),
+        ("archive", &layout.storage.archive, false),
+    ];
+    for (prefix, dir, configs_only) in dirs {
+        for file in dir_files(dir) {
+            if (configs_only && !is_proxy_config(&file)) || file.ends_with(".tmp") {
+                continue;
+            }
+            let name = format!("{prefix}/{file}");
+            manifest
+                .entries
+                .extend(copy_entry(staging, &name, &dir.join(&file))?);
+        }
+    }
+
+    if let Some(store) = read_key_store(&layout.api_keys)? {
+        let mut sealed = Vec::new();
+        for mut entry in store.keys {
+            match keys::decrypt_key(&entry.key_encrypted) {
+                Ok(plaintext) => {
+                    entry.key_encrypted = keys::seal(&key, plaintext.as_bytes());
+                    sealed.push(entry);
+                }
+                Err(e) => manifest.warnings.push(format!(
+                    "api key {} ({}) left out: {e}",
+                    entry.label, entry.id
+                )),
+            }
+        }
+        let json = serde_json::to_vec_pretty(&PersistedKeyStore { keys: sealed })?;
+        manifest.entries.push(write_entry(
+            staging,
+            "api-keys.json",
+            EntryKind::ApiKeys,
+            &json,
+        )?);
+    }
+
+    manifest
+        .entries
+        .extend(copy_entry(staging, "certs/rootCA.pem", &layout.ca_cert)?);
+    match std::fs::read(&layout.ca_key) {
+        Ok(pem) => {
+            let sealed = keys::seal(&key, &pem);
+            manifest.entries.push(write_entry(
+                staging,
+                "certs/rootCA-key.pem",
+                EntryKind::Sealed,
+                sealed.as_bytes(),
+            )?);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(manifest)
+}
+
+fn new_sealing(passphrase: &str, iterations: u32) -> (LessSafeKey, Sealing) {
+    let mut salt = [0u8; 16];
+    SystemRandom::new().fill(&mut salt).expect("RNG failed");
+    let key = keys::passphrase_key(passphrase, &salt, iterations);
+    let sealing = Sealing {
+        kdf: KDF.to_string(),
+        iterations,
+        salt: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, salt),
+        check: keys::seal(&key, CHECK_PLAINTEXT),
+    };
+    (key, sealing)
+}
+
+fn unlock(sealing: &Sealing, passphrase: &str) -> Result<LessSafeKey, BackupError> {
+    if sealing.kdf != KDF {
+        return Err(BackupError::Format(format!("unknown kdf {}", sealing.kdf)));
+    }
+    if sealing.iterations > MAX_KDF_ITERATIONS {
+        return Err(BackupError::Format(format!(
+            "{} kdf iterations exceed the limit of {MAX_KDF_ITERATIONS}",
+            sealing.iterations
+        )));
+    }
+    let salt = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &sealing.salt)
+        .map_err(|e| BackupError::Format(format!("salt: {e}")))?;
+    let key = keys::passphrase_key(passphrase, &salt, sealing.iterations);
+    match keys::open(&key, &sealing.check) {
+        Ok(check) if check == CHECK_PLAINTEXT => Ok(key),
+        _ => Err(BackupError::WrongPassphrase),
+    }
+}
+
+fn read_key_store(path: &Path) -> Result<Option<PersistedKeyStore>, BackupError> {
+    match std::fs::read(path) {
+        Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Names of the regular files in `dir`, sorted.
+fn dir_files(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| plain_name(n))
+        .collect();
+    names.sort();
+    names
+}
+
+/// A staged entry being written, hashed on the way.
+struct EntryWriter {
+    name: String,
+    out: HashingWriter<BufWriter<std::fs::File>>,
+}
+
+impl EntryWriter {
+    fn create(staging: &Path, name: &str) -> std::io::Result<Self> {
+        let path = staging.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            name: name.to_string(),
+            out: HashingWriter::new(BufWriter::new(std::fs::File::create(path)?)),
+        })
+    }
+
+    fn finish(self, kind: EntryKind) -> std::io::Result<Entry> {
+        let (size, sha256) = self.out.finish()?;
+        Ok(Entry {
+            name: self.name,
+            kind,
+            size,
+            sha256,
+            rows: None,
+        })
+    }
+}
+
+impl Write for EntryWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn write_entry(staging: &Path, name: &str, kind: EntryKind, data: &[u8]) -> std::io::Result<Entry> {
+    let mut out = EntryWriter::create(staging, name)?;
+    out.write_all(data)?;
+    out.finish(kind)
+}
+
+/// Copy a file into the staging directory; `None` if it does not exist.
+fn copy_entry(staging: &Path, name: &str, src: &Path) -> std::io::Result<Option<Entry>> {
+    let mut file = match std::fs::File::open(src) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut out = EntryWriter::create(staging, name)?;
+    std::io::copy(&mut file, &mut out)?;
+    out.finish(EntryKind::File).map(Some)
+}
+
+/// The manifest of a backup, without checking its entries.
+pub fn read_manifest(path: &Path) -> Result<Manifest, BackupError> {
+    format::open(path).map(|(manifest, _)| manifest)
+}
+
+/// Check every entry against its checksum. Needs no passphrase.
+pub fn verify(path: &Path) -> Result<Manifest, BackupError> {
+    let (manifest, mut reader) = format::open(path)?;
+    for entry in &manifest.entries {
+        read_entry(&mut reader, entry, std::io::sink())?;
+    }
+    ensure_end(&mut reader)?;
+    Ok(manifest)
+}
+
+/// Backups in `dir`, oldest first. Unreadable files are left out.
+pub fn list(dir: &Path) -> Vec<BackupSummary> {
+    let mut out: Vec<BackupSummary> = dir_files(dir)
+        .into_iter()
+        .filter(|name| valid_name(name))
+        .filter_map(|name| {
+            let path = dir.join(&name);
+            let manifest = read_manifest(&path).ok()?;
+            Some(BackupSummary {
+                bytes: std::fs::metadata(&path).ok()?.len(),
+                name,
+                manifest,
+            })
+        })
+        .collect();
+    out.sort_by_key(|b| b.manifest.created_at);
+    out
+}
+
+fn read_entry(reader: &mut impl Read, entry: &Entry, out: impl Write) -> Result<(), BackupError> {
+    let mut out = HashingWriter::new(out);
+    std::io::copy(&mut reader.take(entry.size), &mut out)
+        .map_err(|e| BackupError::Format(format!("{}: {e}", entry.name)))?;
+    let (size, sha256) = out.finish()?;
+    if size != entry.size {
+        return Err(BackupError::Format(format!("{} is truncated", entry.name)));
+    }
+    if sha256 != entry.sha256 {
+        return Err(BackupError::Checksum(entry.name.clone()));
+    }
+    Ok(())
+}
+
+fn ensure_end(reader: &mut impl Read) -> Result<(), BackupError> {
+    let mut byte = [0u8; 1];
+    match reader.read(&mut byte) {
+        Ok(0) => Ok(()),
+        Ok(_) => Err(BackupError::Format("data after the last entry".to_string())),
+        Err(e) => Err(BackupError::Format(e.to_string())),
+    }
+}
+
+/// Verify a backup and stage it for [`apply_staged`]: every checksum is
+/// checked, API keys are re-encrypted for this host and the CA key is
+/// unsealed. Live state is not touched; a previously staged restore is
+/// replaced.
+pub fn stage(path: &Path, layout: &StateLayout, passphrase: &str) -> Result<Manifest, BackupError> {
+    let (manifest, mut reader) = format::open(path)?;
+    if manifest.schema_version > SCHEMA_VERSION {
+        return Err(BackupError::NewerSchema(manifest.schema_version));
+    }
+    let key = unlock(&manifest.sealing, passphrase)?;
+    if let Some(entry) = manifest.entries.iter().find(|e| layout.target(e).is_none()) {
+        return Err(BackupError::Format(format!("unknown entry {}", entry.name)));
+    }
+
+    let staging = with_suffix(&layout.pending, ".partial");
+    let _ = std::fs::remove_dir_all(&staging);
+    let result = (|| {
+        for entry in &manifest.entries {
+            let dest = staging.join(&entry.name);
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let out = BufWriter::new(std::fs::File::create(&dest)?);
+            read_entry(&mut reader, entry, out)?;
+        }
+        ensure_end(&mut reader)?;
+        for entry in &manifest.entries {
+            let staged = staging.join(&entry.name);
+            match entry.kind {
+                EntryKind::ApiKeys => rekey_api_keys(&staged, &key)?,
+                EntryKind::Sealed => unseal_file(&staged, &key)?,
+                EntryKind::Table | EntryKind::File => {}
+            }
+        }
+        std::fs::write(
+            staging.join(STAGED_MANIFEST),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        Ok::<_, BackupError>(())
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    let _ = std::fs::remove_dir_all(&layout.pending);
+    std::fs::rename(&staging, &layout.pending)?;
+    info!(
+        created_at = manifest.created_at,
+        entries = manifest.entries.len(),
+        "restore staged"
+    );
+    Ok(manifest)
+}
+
+/// Re-encrypt passphrase-sealed keys with this host's machine key.
+fn rekey_api_keys(path: &Path, key: &LessSafeKey) -> Result<(), BackupError> {
+    let mut store = read_key_store(path)?.unwrap_or_default();
+    for entry in &mut store.keys {
+        let plaintext = keys::open(key, &entry.key_encrypted)
+            .ok()
+            .and_then(|p| String::from_utf8(p).ok())
+            .ok_or_else(|| BackupError::Format(format!("api key {} cannot be opened", entry.id)))?;
+        entry.key_encrypted = keys::encrypt_key(&plaintext);
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(&store)?)?;
+    Ok(())
+}
+
+fn unseal_file(path: &Path, key: &LessSafeKey) -> Result<(), BackupError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sealed = std::fs::read_to_string(path)?;
+    let plaintext = keys::open(key, sealed.trim())
+        .map_err(|e| BackupError::Format(format!("{}: {e}", path.display())))?;
+    std::fs::write(path, plaintext)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+/// Whether a staged restore is waiting for the next start.
+pub fn has_staged(layout: &StateLayout) -> bool {
+    layout.pending.join(STAGED_MANIFEST).exists()
+}
+
+/// Put a staged restore in place, if there is one. The current database
+/// is moved aside and a new one is built from the backup's tables; files
+/// it replaces are kept with a `.pre-restore` suffix. Must run before the
+/// database is opened. A restore that fails is moved to
+/// `restore-pending.failed-<epoch>` so it is not retried on every start.
+pub async fn apply_staged(layout: &StateLayout) -> Result<Option<RestoreReport>, BackupError> {
+    let Ok(json) = std::fs::read(layout.pending.join(STAGED_MANIFEST)) else {
+        return Ok(None);
+    };
+    let manifest: Manifest = serde_json::from_slice(&json)?;
+    let result = apply(layout, &manifest).await;
+    match &result {
+        Ok(report) => {
+            std::fs::remove_dir_all(&layout.pending)?;
+            info!(
+                created_at = report.created_at,
+                rows = report.rows,
+                files = report.files,
+                api_keys = report.api_keys,
+                "restore applied"
+            );
+        }
+        Err(_) => {
+            let failed = with_suffix(&layout.pending, &format!(".failed-{}", now_secs()));
+            let _ = std::fs::rename(&layout.pending, failed);
+        }
+    }
+    result.map(Some)
+}
+
+async fn apply(layout: &StateLayout, manifest: &Manifest) -> Result<RestoreReport, BackupError> {
+    let mut report = RestoreReport {
+        created_at: manifest.created_at,
+        noaide_version: manifest.noaide_version.clone(),
+        ..Default::default()
+    };
+
+    if layout.db.exists() {
+        let aside = with_suffix(&layout.db, &format!(".pre-restore-{}.bak", now_secs()));
+        std::fs::rename(&layout.db, &aside)?;
+        let wal = with_suffix(&layout.db, "-wal");
+        if wal.exists() {
+            std::fs::rename(&wal, with_suffix(&aside, "-wal"))?;
+        }
+        report.previous_db = Some(aside);
+    }
+    if let Some(parent) = layout.db.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let db = Db::open(&layout.db.to_string_lossy()).await?;
+
+    for entry in &manifest.entries {
+        let staged = layout.pending.join(&entry.name);
+        match layout.target(entry) {
+            Some(Target::Table) => {
+                for line in BufReader::new(std::fs::File::open(&staged)?).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let page: TableRows = serde_json::from_str(&line)?;
+                    report.rows += db.import_rows(&page).await? as u64;
+                }
+            }
+            Some(Target::Path(dest)) => {
+                if entry.kind == EntryKind::ApiKeys {
+                    report.api_keys = read_key_store(&staged)?.map_or(0, |s| s.keys.len() as u64);
+                }
+                put_in_place(&staged, &dest)?;
+                report.files += 1;
+            }
+            None => return Err(BackupError::Format(format!("unknown entry {}", entry.name))),
+        }
+    }
+    Ok(report)
+}
+
+fn put_in_place(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if dest.exists() {
+        std::fs::rename(dest, with_suffix(dest, ".pre-restore"))?;
+    }
+    // Copy, not rename: the staging area may be on another filesystem.
+    std::fs::copy(src, dest)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::{ApiRequestComponent, MessageComponent, MessageRole};
+    use crate::proxy::keys::KeyStore;
+    use uuid::Uuid;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn layout(dir: &Path) -> StateLayout {
+        StateLayout {
+            db: dir.join("ide.db"),
+            storage: StoragePaths {
+                policy: dir.join("retention.json"),
+                audit_log: dir.join("audit-log.jsonl"),
+                proxy_configs: dir.to_path_buf(),
+                archive: dir.join("archive"),
+                recordings: dir.join("recordings"),
+            },
+            api_keys: dir.join("api-keys.json"),
+            proxy_profiles: dir.join("proxy-profiles.json"),
+            model_limits: dir.join("model-limits.json"),
+            managed_sessions: dir.join("managed-sessions.json"),
+            ca_cert: dir.join("certs/rootCA.pem"),
+            ca_key: dir.join("certs/rootCA-key.pem"),
+            backups: dir.join("backups"),
+            pending: dir.join("restore-pending"),
+        }
+    }
+
+    fn options() -> BackupOptions {
+        BackupOptions {
+            passphrase: PASSPHRASE.into(),
+            kdf_iterations: 10,
+        }
+    }
+
+    /// A source install with data in every class; returns the backup path.
+    async fn backup_fixture(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let src = layout(dir);
+        let db = Db::open(&src.db.to_string_lossy()).await.unwrap();
+        let sid = Uuid::new_v4();
+        db.insert_message(&MessageComponent::test(
+            sid,
+            MessageRole::User,
+            "restore me",
+            1_760_000_000,
+        ))
+        .await
+        .unwrap();
+        db.insert_api_request(&ApiRequestComponent::test(sid, 1_760_000_000_000))
+            .await
+            .unwrap();
+
+        std::fs::write(&src.storage.policy, r#"{"intervalHours": 2}"#).unwrap();
+        std::fs::write(&src.storage.audit_log, "{}\n").unwrap();
+        std::fs::write(dir.join(format!("proxy-config-{sid}.json")), "{}").unwrap();
+        std::fs::create_dir_all(&src.storage.archive).unwrap();
+        std::fs::write(src.storage.archive.join("old.jsonl.zst"), b"zst").unwrap();
+        std::fs::create_dir_all(src.ca_key.parent().unwrap()).unwrap();
+        std::fs::write(&src.ca_cert, "CERT").unwrap();
+        std::fs::write(&src.ca_key, "PRIVATE-KEY-PEM").unwrap();
+        let store = KeyStore::new();
+        store.add_key("anthropic", "sk-ant-backup-secret", "main");
+        let keys = PersistedKeyStore {
+            keys: store.list_keys(),
+        };
+        std::fs::write(&src.api_keys, serde_json::to_vec(&keys).unwrap()).unwrap();
+
+        let summary = create(&db, &src, &options(), &src.backups).await.unwrap();
+        let m = &summary.manifest;
+        assert!(m.warnings.is_empty(), "{:?}", m.warnings);
+        let rows = |name: &str| m.entries.iter().find(|e| e.name == name).unwrap().rows;
+        assert_eq!(rows("db/messages.jsonl"), Some(1));
+        assert_eq!(rows("db/api_requests.jsonl"), Some(1));
+        for name in [
+            "files/retention.json",
+            "files/audit-log.jsonl",
+            "archive/old.jsonl.zst",
+            "api-keys.json",
+            "certs/rootCA.pem",
+            "certs/rootCA-key.pem",
+        ] {
+            assert!(m.entries.iter().any(|e| e.name == name), "{name}");
+        }
+        assert!(
+            m.entries
+                .iter()
+                .any(|e| e.name.starts_with("proxy-configs/"))
+        );
+        assert_eq!(list(&src.backups), vec![summary.clone()]);
+        src.backups.join(summary.name)
+    }
+
+    #[tokio::test]
+    async fn backup_restores_on_another_host_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = backup_fixture(&dir.path().join("src")).await;
+
+        // Secrets never appear in the clear.
+        let raw = zstd::decode_all(std::fs::File::open(&path).unwrap()).unwrap();
+        let raw = String::from_utf8_lossy(&raw);
+        assert!(!raw.contains("sk-ant-backup-secret"));
+        assert!(!raw.contains("PRIVATE-KEY-PEM"));
+        assert!(verify(&path).is_ok());
+
+        // The target already has a database of its own.
+        let dst_dir = dir.path().join("dst");
+        std::fs::create_dir_all(&dst_dir).unwrap();
+        let dst = layout(&dst_dir);
+        let existing = Db::open(&dst.db.to_string_lossy()).await.unwrap();
+        let other = Uuid::new_v4();
+        existing
+            .insert_message(&MessageComponent::test(
+                other,
+                MessageRole::User,
+                "local only",
+                1_760_000_000,
+            ))
+            .await
+            .unwrap();
+        drop(existing);
+        std::fs::write(&dst.storage.policy, "{}").unwrap();
+
+        assert!(matches!(
+            stage(&path, &dst, "not the passphrase"),
+            Err(BackupError::WrongPassphrase)
+        ));
+        assert!(!has_staged(&dst));
+        stage(&path, &dst, PASSPHRASE).unwrap();
+        assert!(has_staged(&dst));
+        // Staging leaves live state alone.
+        assert_eq!(std::fs::read_to_string(&dst.storage.policy).unwrap(), "{}");
+
+        let report = apply_staged(&dst).await.unwrap().unwrap();
+        assert!(report.rows >= 2, "{report:?}");
+        assert_eq!(report.api_keys, 1);
+        assert!(report.previous_db.as_ref().unwrap().exists());
+        assert!(!has_staged(&dst));
+        assert!(apply_staged(&dst).await.unwrap().is_none());
+
+        let db = Db::open(&dst.db.to_string_lossy()).await.unwrap();
+        let messages = db.get_all_api_requests().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        let sid = messages[0].session_id;
+        let restored = db.get_messages_by_session(&sid).await.unwrap();
+        assert_eq!(restored[0].content, "restore me");
+        assert!(db.get_messages_by_session(&other).await.unwrap().is_empty());
+
+        let keys: PersistedKeyStore =
+            serde_json::from_slice(&std::fs::read(&dst.api_keys).unwrap()).unwrap();
+        assert_eq!(
+            keys::decrypt_key(&keys.keys[0].key_encrypted).unwrap(),
+            "sk-ant-backup-secret"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&dst.ca_key).unwrap(),
+            "PRIVATE-KEY-PEM"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&dst.storage.policy).unwrap(),
+            r#"{"intervalHours": 2}"#
+        );
+        assert_eq!(
+            std::fs::read_to_string(with_suffix(&dst.storage.policy, ".pre-restore")).unwrap(),
+            "{}"
+        );
+        assert!(dst_dir.join(format!("proxy-config-{sid}.json")).exists());
+        assert!(dst.storage.archive.join("old.jsonl.zst").exists());
+    }
+
+    /// Rewrite a backup's decompressed bytes.
+    fn tamper(path: &Path, edit: impl FnOnce(&mut Vec<u8>)) -> PathBuf {
+        let mut raw = zstd::decode_all(std::fs::File::open(path).unwrap()).unwrap();
+        edit(&mut raw);
+        let out = with_suffix(path, ".tampered");
+        std::fs::write(&out, zstd::encode_all(&raw[..], 3).unwrap()).unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn damaged_or_newer_backups_are_refused_before_staging() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = layout(dir.path());
+        let db = Db::open(":memory:").await.unwrap();
+        let weak = BackupOptions {
+            passphrase: "short".into(),
+            ..options()
+        };
+        assert!(matches!(
+            create(&db, &src, &weak, &src.backups).await,
+            Err(BackupError::WeakPassphrase)
+        ));
+
+        let path = backup_fixture(&dir.path().join("src")).await;
+        let dst = layout(&dir.path().join("dst"));
+
+        let flipped = tamper(&path, |raw| *raw.last_mut().unwrap() ^= 0xff);
+        assert!(matches!(verify(&flipped), Err(BackupError::Checksum(_))));
+        assert!(matches!(
+            stage(&flipped, &dst, PASSPHRASE),
+            Err(BackupError::Checksum(name)) if name == "certs/rootCA-key.pem"
+        ));
+        assert!(!has_staged(&dst));
+        assert!(!with_suffix(&dst.pending, ".partial").exists());
+
+        let truncated = tamper(&path, |raw| raw.truncate(raw.len() - 1));
+        assert!(matches!(verify(&truncated), Err(BackupError::Format(_))));
+
+        let newer = tamper(&path, |raw| {
+            let end = raw.iter().position(|b| *b == b'\n').unwrap();
+            let mut manifest: serde_json::Value = serde_json::from_slice(&raw[..end]).unwrap();
+            manifest["schemaVersion"] = (SCHEMA_VERSION + 1).into();
+            let mut head = serde_json::to_vec(&manifest).unwrap();
+            head.extend_from_slice(&raw[end..]);
+            *raw = head;
+        });
+        assert!(matches!(
+            stage(&newer, &dst, PASSPHRASE),
+            Err(BackupError::NewerSchema(_))
+        ));
+
+        let costly = tamper(&path, |raw| {
+            let end = raw.iter().position(|b| *b == b'\n').unwrap();
+            let mut manifest: serde_json::Value = serde_json::from_slice(&raw[..end]).unwrap();
+            manifest["sealing"]["iterations"] = u32::MAX.into();
+            let mut head = serde_json::to_vec(&manifest).unwrap();
+            head.extend_from_slice(&raw[end..]);
+            *raw = head;
+        });
+        assert!(matches!(
+            stage(&costly, &dst, PASSPHRASE),
+            Err(BackupError::Format(e)) if e.contains("kdf iterations")
+        ));
+
+        let escape = tamper(&path, |raw| {
+            let text = String::from_utf8_lossy(raw).replace("archive/old", "archive/../old");
+            *raw = text.into_bytes();
+        });
+        assert!(matches!(
+            stage(&escape, &dst, PASSPHRASE),
+            Err(BackupError::Format(_))
+        ));
+        assert!(!has_staged(&dst));
+    }
+}
@@ -0,0 +1,128 @@
+//! Back up or restore noaide's state while the server is stopped.
+//!
+//! ```text
+//! noaide-backup create [-o DIR] [--passphrase-file FILE]
+//! noaide-backup inspect <backup>
+//! noaide-backup restore <backup> [--passphrase-file FILE] [--stage-only]
+//! ```
+//!
+//! Paths follow the server's environment (`NOAIDE_DB_PATH`,
+//! `NOAIDE_API_KEYS_PATH`, …). The passphrase is read from
+//! `--passphrase-file`, else `NOAIDE_BACKUP_PASSPHRASE`, else stdin.
+//! A running server is backed up through `POST /api/backups` instead: both
+//! would open the same database.
+
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use noaide_server::backup::{self, BackupOptions, StateLayout};
+use noaide_server::db::Db;
+
+const USAGE: &str = "usage: noaide-backup create [-o DIR] [--passphrase-file FILE]
+       noaide-backup inspect <backup>
+       noaide-backup restore <backup> [--passphrase-file FILE] [--stage-only]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+    let mut file: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    let mut passphrase_file: Option<PathBuf> = None;
+    let mut stage_only = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().with_context(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value("--output")?.into()),
+            "--passphrase-file" => passphrase_file = Some(value("--passphrase-file")?.into()),
+            "--stage-only" => stage_only = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other if other.starts_with('-') => bail!("unknown option {other}\n{USAGE}"),
+            other => file = Some(other.into()),
+        }
+    }
+
+    let layout = StateLayout::from_env();
+    match (command.as_str(), file) {
+        ("create", None) => {
+            let passphrase = read_passphrase(passphrase_file.as_deref())?;
+            let dir = output.unwrap_or_else(|| layout.backups.clone());
+            let db = Db::open(&layout.db.to_string_lossy())
+                .await
+                .with_context(|| format!("opening {}", layout.db.display()))?;
+            let summary =
+                backup::create(&db, &layout, &BackupOptions::new(passphrase), &dir).await?;
+            for warning in &summary.manifest.warnings {
+                eprintln!("warning: {warning}");
+            }
+            println!("{}", dir.join(&summary.name).display());
+        }
+        ("inspect", Some(path)) => {
+            let manifest = backup::verify(&path)?;
+            println!(
+                "noaide {} · schema {} · created {} · {} entries, {} bytes · checksums ok",
+                manifest.noaide_version,
+                manifest.schema_version,
+                manifest.created_at,
+                manifest.entries.len(),
+                manifest.total_size()
+            );
+            for entry in &manifest.entries {
+                let rows = entry
+                    .rows
+                    .map(|r| format!(" ({r} rows)"))
+                    .unwrap_or_default();
+                println!("  {:>12}  {}{rows}", entry.size, entry.name);
+            }
+            for warning in &manifest.warnings {
+                println!("warning: {warning}");
+            }
+        }
+        ("restore", Some(path)) => {
+            let passphrase = read_passphrase(passphrase_file.as_deref())?;
+            backup::stage(&path, &layout, &passphrase)?;
+            if stage_only {
+                println!(
+                    "staged in {}; applied on the next server start",
+                    layout.pending.display()
+                );
+                return Ok(());
+            }
+            let report = backup::apply_staged(&layout)
+                .await?
+                .context("staged restore disappeared")?;
+            println!(
+                "restored {} rows, {} files, {} api keys",
+                report.rows, report.files, report.api_keys
+            );
+            if let Some(previous) = report.previous_db {
+                println!("previous database kept at {}", previous.display());
+            }
+        }
+        _ => bail!("{USAGE}"),
+    }
+    Ok(())
+}
+
+fn read_passphrase(file: Option<&Path>) -> anyhow::Result<String> {
+    let passphrase = match file {
+        Some(path) => {
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?
+        }
+        None => match std::env::var("NOAIDE_BACKUP_PASSPHRASE") {
+            Ok(p) => p,
+            Err(_) => {
+                eprint!("passphrase: ");
+                let mut line = String::new();
+                std::io::stdin().lock().read_line(&mut line)?;
+                line
+            }
+        },
+    };
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -0,0 +1,77 @@
+//! Export a transcript file without a running server.
+//!
+//! ```text
+//! noaide-export <transcript> [--format md|html|json] [--no-redact]
+//!               [--redact-pattern REGEX]... [--hide-meta]
+//!               [--max-output BYTES] [-o FILE]
+//! ```
+
+use std::path::PathBuf;
+
+use anyhow::{Context, bail};
+use noaide_server::export::{self, ExportFormat, ExportOptions};
+use uuid::Uuid;
+
+const USAGE: &str = "usage: noaide-export <transcript> [--format md|html|json] [--no-redact] \
+[--redact-pattern REGEX]... [--hide-meta] [--max-output BYTES] [-o FILE]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let mut transcript: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    let mut patterns = Vec::new();
+    let mut opts = ExportOptions {
+        redact: true,
+        ..Default::default()
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().with_context(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "--format" | "-f" => {
+                let f = value("--format")?;
+                opts.format =
+                    ExportFormat::parse(&f).with_context(|| format!("unknown format: {f}"))?;
+            }
+            "--no-redact" => opts.redact = false,
+            "--redact-pattern" => patterns.push(value("--redact-pattern")?),
+            "--hide-meta" => opts.hide_meta = true,
+            "--max-output" => {
+                opts.max_output_bytes = Some(value("--max-output")?.parse()?);
+            }
+            "-o" | "--output" => output = Some(value("--output")?.into()),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            other if other.starts_with('-') => bail!("unknown option {other}\n{USAGE}"),
+            other => transcript = Some(other.into()),
+        }
+    }
+    let Some(transcript) = transcript else {
+        bail!("{USAGE}");
+    };
+    opts.redact_patterns = ExportOptions::compile_patterns(&patterns)?;
+
+    // Session ids come from the file name where the CLI encodes one.
+    let source = noaide_server::parser::registry::resolve(&transcript);
+    let session_id = source
+        .session_id_from_path(&transcript)
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .unwrap_or_else(|| {
+            noaide_server::parser::stable_uuid("export", &transcript.display().to_string())
+        });
+
+    let session = export::load_transcript(&transcript, session_id)
+        .await
+        .with_context(|| format!("reading {}", transcript.display()))?;
+    let rendered = export::render(&session, &opts);
+
+    match output {
+        Some(path) => std::fs::write(&path, rendered)
+            .with_context(|| format!("writing {}", path.display()))?,
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
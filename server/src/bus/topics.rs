@@ -29,6 +29,9 @@ pub const PLAN_UPDATES: &str = "plan/updates";
 /// Session status changes (active/idle/error — for real-time orbState).
 pub const SESSION_STATUS: &str = "session/status";
 
+/// Headless job queue changes (queued, running, finished).
+pub const JOB_STATUS: &str = "jobs/status";
+
 // ── Backpressure Policies ────────────────────────────────────────────────────
 
 /// Backpressure policy for a topic's bounded queue.
@@ -82,6 +85,10 @@ pub fn topic_config(topic: &str) -> TopicConfig {
             capacity: 100,
             drop_policy: DropPolicy::DropOldest,
         },
+        JOB_STATUS => TopicConfig {
+            capacity: 100,
+            drop_policy: DropPolicy::DropOldest,
+        },
         // Unknown topics get sensible defaults
         _ => TopicConfig {
             capacity: 256,
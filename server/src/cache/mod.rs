@@ -16,7 +16,7 @@ use uuid::Uuid;
 use crate::discovery::scanner::CliType;
 use crate::ecs::components::{CacheMetaComponent, MessageComponent};
 use crate::ecs::world::EcsWorld;
-use crate::parser;
+use crate::parser::{self, CursorKind};
 
 /// Maximum number of sessions with warm (fully-parsed) message caches.
 /// When exceeded, the least-recently-accessed session's messages are evicted.
@@ -99,6 +99,15 @@ async fn lru_evict(ecs: &mut EcsWorld) -> usize {
     evicted
 }
 
+/// Register caches restored from a snapshot, least recently used first,
+/// so they take part in eviction like any other.
+pub async fn adopt_restored(ecs: &mut EcsWorld, session_ids: &[Uuid]) {
+    for &session_id in session_ids {
+        lru_touch(session_id).await;
+    }
+    lru_evict(ecs).await;
+}
+
 /// Ensure a session's cache is warm. If not, do a full parse.
 /// Applies LRU eviction to stay within memory budget.
 /// Returns the number of cached messages.
@@ -171,49 +180,36 @@ pub async fn refresh(
         from_offset
     };
 
-    // Parse new data
-    let (new_messages, new_components, new_offset) = match cli_type {
-        CliType::Claude => {
-            let (msgs, offset) = parser::parse_incremental(jsonl_path, effective_offset).await?;
-            let components: Vec<MessageComponent> = msgs
-                .iter()
-                .filter_map(|m| parser::message_to_component(m, session_id))
-                .collect();
-            (msgs.len(), components, offset)
-        }
-        CliType::Codex => {
-            // Codex files are single JSON — always full parse
-            if effective_offset > 0 && current_file_size == old_file_size {
-                // No change — skip
-                return Ok(ecs.message_count_for_session(session_id));
-            }
-            ecs.invalidate_cache(session_id);
-            let msgs = parser::parse_codex_file(jsonl_path).await?;
-            let components: Vec<MessageComponent> = msgs
-                .iter()
-                .filter_map(|m| parser::message_to_component(m, session_id))
-                .collect();
-            (msgs.len(), components, current_file_size)
-        }
-        CliType::Gemini => {
-            // Gemini files are single JSON — always full parse
-            if effective_offset > 0 && current_file_size == old_file_size {
-                return Ok(ecs.message_count_for_session(session_id));
-            }
-            ecs.invalidate_cache(session_id);
-            let msgs = parser::parse_gemini_file(jsonl_path).await?;
-            let components: Vec<MessageComponent> = msgs
-                .iter()
-                .filter_map(|m| parser::message_to_component(m, session_id))
-                .collect();
-            (msgs.len(), components, current_file_size)
-        }
-    };
+    // Parse new data. The stored offset is the source's cursor: a byte
+    // offset (Claude, Codex) or a message count (see `CursorKind`).
+    let source = parser::registry::for_cli(cli_type);
+    if source.cursor_kind() == CursorKind::MessageCount
+        && effective_offset > 0
+        && current_file_size == old_file_size
+    {
+        // Not byte-addressable and unchanged — skip
+        return Ok(ecs.message_count_for_session(session_id));
+    }
+    let (msgs, new_offset) = source
+        .parse_incremental(jsonl_path, effective_offset)
+        .await?;
+    if new_offset < effective_offset {
+        // Transcript replaced — `msgs` is the whole session again.
+        ecs.invalidate_cache(session_id);
+    }
+    let new_messages = msgs.len();
+    let new_components: Vec<MessageComponent> = msgs
+        .iter()
+        .filter_map(|m| parser::message_to_component(m, session_id))
+        .collect();
 
-    // Spawn new message entities
+    // Spawn new message entities and pair their tool calls
     for component in new_components {
         ecs.spawn_message(component);
     }
+    for msg in &msgs {
+        parser::record_tool_calls(ecs, session_id, msg);
+    }
 
     let total_count = ecs.message_count_for_session(session_id);
 
@@ -244,35 +240,15 @@ pub async fn refresh(
 
 /// Convert a MessageComponent to the JSON shape the API returns.
 pub fn component_to_api_json(m: &MessageComponent) -> serde_json::Value {
-    let role = match m.role {
-        crate::ecs::components::MessageRole::User => "user",
-        crate::ecs::components::MessageRole::Assistant => "assistant",
-        crate::ecs::components::MessageRole::System => "system",
-        crate::ecs::components::MessageRole::Meta => "meta",
-    };
-
-    let message_type = match m.message_type {
-        crate::ecs::components::MessageType::Text => "text",
-        crate::ecs::components::MessageType::ToolUse => "tool_use",
-        crate::ecs::components::MessageType::ToolResult => "tool_result",
-        crate::ecs::components::MessageType::Thinking => "thinking",
-        crate::ecs::components::MessageType::SystemReminder => "system-reminder",
-        crate::ecs::components::MessageType::Error => "error",
-        crate::ecs::components::MessageType::Progress => "progress",
-        crate::ecs::components::MessageType::Summary => "summary",
-        crate::ecs::components::MessageType::FileSnapshot => "file-history-snapshot",
-        crate::ecs::components::MessageType::CompactBoundary => "compact_boundary",
-    };
-
     let mut obj = serde_json::json!({
         "uuid": m.id.to_string(),
         "sessionId": m.session_id.to_string(),
-        "role": role,
+        "role": m.role.as_str(),
         "content": m.content,
         "timestamp": m.timestamp,
         "tokens": m.tokens,
         "hidden": m.hidden,
-        "messageType": message_type,
+        "messageType": m.message_type.as_str(),
         "model": m.model,
         "stopReason": m.stop_reason,
         "inputTokens": m.input_tokens,
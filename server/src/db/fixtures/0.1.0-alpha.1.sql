@@ -0,0 +1,75 @@
+-- Database as written by noaide 0.1.0-alpha.1 (no schema_version table).
+-- FTS5 was attempted but is unavailable in the bundled Limbo build.
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'active',
+    model TEXT,
+    started_at INTEGER NOT NULL,
+    cost REAL
+);
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    tokens INTEGER,
+    hidden INTEGER DEFAULT 0,
+    message_type TEXT NOT NULL DEFAULT 'text'
+);
+CREATE TABLE IF NOT EXISTS files (
+    id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    modified INTEGER NOT NULL,
+    size INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tasks (
+    id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    owner TEXT
+);
+CREATE TABLE IF NOT EXISTS agents (
+    id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    agent_type TEXT NOT NULL,
+    parent_id TEXT
+);
+CREATE TABLE IF NOT EXISTS api_requests (
+    id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    method TEXT NOT NULL,
+    url TEXT NOT NULL,
+    request_body TEXT,
+    response_body TEXT,
+    status_code INTEGER,
+    latency_ms INTEGER,
+    timestamp INTEGER NOT NULL,
+    request_headers TEXT,
+    response_headers TEXT,
+    request_size INTEGER,
+    response_size INTEGER,
+    traffic_category TEXT
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_sessions_id ON sessions(id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_id ON messages(id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_files_id ON files(id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tasks_id ON tasks(id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_agents_id ON agents(id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_requests_id ON api_requests(id);
+CREATE INDEX IF NOT EXISTS idx_messages_session ON messages(session_id);
+CREATE INDEX IF NOT EXISTS idx_files_session ON files(session_id);
+CREATE INDEX IF NOT EXISTS idx_tasks_session ON tasks(session_id);
+CREATE INDEX IF NOT EXISTS idx_agents_session ON agents(session_id);
+CREATE INDEX IF NOT EXISTS idx_api_requests_session ON api_requests(session_id);
+INSERT INTO sessions (id, path, status, model, started_at, cost) VALUES ('6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', '/work/noaide', 'idle', 'claude-opus-4-6', 1776000000, 0.42);
+INSERT INTO messages (id, session_id, role, content, timestamp, tokens, hidden, message_type) VALUES ('0e3b5c1d-2f4a-4b6c-8d9e-1a2b3c4d5e01', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'user', 'add a health endpoint', 1776000001, NULL, 0, 'text');
+INSERT INTO messages (id, session_id, role, content, timestamp, tokens, hidden, message_type) VALUES ('0e3b5c1d-2f4a-4b6c-8d9e-1a2b3c4d5e02', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'assistant', '[tool_use: Edit]', 1776000005, 812, 0, 'tool_use');
+INSERT INTO files (id, session_id, path, modified, size) VALUES ('9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c01', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'server/src/main.rs', 1776000006, 201344);
+INSERT INTO tasks (id, session_id, subject, status, owner) VALUES ('9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c02', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'health endpoint', 'completed', NULL);
+INSERT INTO agents (id, session_id, name, agent_type, parent_id) VALUES ('9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c03', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'main', 'claude', NULL);
+INSERT INTO api_requests (id, session_id, method, url, request_body, response_body, status_code, latency_ms, timestamp, request_headers, response_headers, request_size, response_size, traffic_category) VALUES ('9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c04', '6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10', 'POST', 'https://api.anthropic.com/v1/messages', '{}', '{}', 200, 1830, 1776000004, '{}', '{}', 2, 2, 'api');
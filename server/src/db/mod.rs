@@ -1,4 +1,8 @@
 pub mod queries;
 pub mod schema;
 
-pub use queries::{Db, DbError, DbResult};
+pub use queries::{
+    AnalyticsSession, ApiRequestStat, DATA_TABLES, Db, DbError, DbResult, IndexScan,
+    IndexedMessage, OpenToolCall, SESSION_TABLES, SearchMeta, SearchSource, TableRows,
+    ToolAggregate, UsageAggregate,
+};
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use limbo::{Builder, Connection, Database, Value};
 use tracing::info;
 use uuid::Uuid;
@@ -17,10 +19,170 @@ pub enum DbError {
     },
     #[error("migration failed: {0}")]
     Migration(String),
+    #[error("cannot import rows: {0}")]
+    Import(String),
 }
 
 pub type DbResult<T> = Result<T, DbError>;
 
+/// Search metadata stored next to an indexed message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchMeta {
+    /// Position of the message in its transcript (0-based).
+    pub seq: u64,
+    /// Transcript source name (`claude`, `codex`, …).
+    pub cli: String,
+    pub project: Option<String>,
+    pub model: Option<String>,
+    /// Lowercased names of the tools the message calls.
+    pub tools: Vec<String>,
+}
+
+/// An indexed message together with its [`SearchMeta`].
+#[derive(Debug, Clone)]
+pub struct IndexedMessage {
+    pub message: MessageComponent,
+    pub meta: SearchMeta,
+}
+
+/// Search-indexer progress for one transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSource {
+    pub session_id: Uuid,
+    pub path: String,
+    /// Parser cursor (byte offset or message count, see `CursorKind`).
+    pub cursor: u64,
+    pub file_size: u64,
+    pub message_count: u64,
+    pub indexed_at: i64,
+}
+
+/// Analytics aggregator state for one transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyticsSession {
+    pub session_id: Uuid,
+    /// Transcript source name (`claude`, `codex`, …).
+    pub cli: String,
+    pub project: Option<String>,
+    pub path: String,
+    /// Parser cursor (byte offset or message count, see `CursorKind`).
+    pub cursor: u64,
+    pub file_size: u64,
+    /// Epoch seconds of the first and last timestamped message.
+    pub first_at: Option<i64>,
+    pub last_at: Option<i64>,
+    /// Usage signature of the last assistant message, to skip repeats.
+    pub last_usage: Option<String>,
+    pub updated_at: i64,
+    /// Set while the aggregates of `cursor` are being written; a row left
+    /// pending was interrupted and its session is recomputed.
+    pub pending: bool,
+}
+
+/// Usage totals of one session on one UTC day for one model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageAggregate {
+    pub session_id: Uuid,
+    /// `YYYY-MM-DD`.
+    pub day: String,
+    /// Empty for messages without a model (user turns).
+    pub model: String,
+    pub messages: u64,
+    /// User prompts (not tool results or meta messages).
+    pub turns: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Tool call totals of one session on one UTC day (of the call) for one tool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolAggregate {
+    pub session_id: Uuid,
+    pub day: String,
+    pub tool: String,
+    pub calls: u64,
+    pub errors: u64,
+    /// Call-to-result time of the calls that completed.
+    pub duration_ms: u64,
+    /// The part of `duration_ms` spent in permission-gated tools.
+    pub approval_wait_ms: u64,
+}
+
+/// A tool call whose result has not been read yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenToolCall {
+    pub tool_use_id: String,
+    pub tool: String,
+    pub started_at_ms: i64,
+}
+
+/// SQL-side narrowing for [`Db::scan_search_index`].
+///
+/// This is a pre-filter: `LIKE` treats `%` and `_` as wildcards and FTS5
+/// matches whole tokens, so callers re-check every row they are handed.
+#[derive(Debug, Clone, Default)]
+pub struct IndexScan {
+    /// Strings that must all occur in the content (case-insensitive).
+    pub contains: Vec<String>,
+    pub role: Option<MessageRole>,
+    pub cli: Option<String>,
+    /// Inclusive lower bound on the message timestamp (epoch seconds).
+    pub after: Option<i64>,
+    /// Exclusive upper bound on the message timestamp (epoch seconds).
+    pub before: Option<i64>,
+}
+
+/// Tables whose rows belong to one session (all have a `session_id`
+/// column). `search_sources` is deliberately absent: it tracks the
+/// transcript, not stored data.
+pub const SESSION_TABLES: &[&str] = &[
+    "messages",
+    "message_search",
+    "api_requests",
+    "files",
+    "tasks",
+    "agents",
+];
+
+/// Every table holding data, in the order a full restore imports them.
+pub const DATA_TABLES: &[&str] = &[
+    "sessions",
+    "messages",
+    "message_search",
+    "search_sources",
+    "api_requests",
+    "files",
+    "tasks",
+    "agents",
+    "analytics_sessions",
+    "analytics_usage",
+    "analytics_tools",
+    "analytics_open_calls",
+];
+
+/// Size and age of one stored API request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiRequestStat {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    /// Epoch milliseconds.
+    pub timestamp: i64,
+    /// Stored request plus response body length.
+    pub bytes: u64,
+}
+
+/// Raw rows of one table, as exported for cold storage. Values are JSON
+/// (`null`, numbers, strings; blobs as `{"blob": "<base64>"}`).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TableRows {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
 /// Async database wrapper around Limbo.
 ///
 /// Limbo is single-threaded per connection but its Connection type is
@@ -36,19 +198,25 @@ impl Db {
     /// Open or create a database at the given path. Runs migrations.
     pub async fn open(path: &str) -> DbResult<Self> {
         info!(path, "opening limbo database");
+        if path != ":memory:" {
+            schema::checkpoint(path).await?;
+        }
         let database = Builder::new_local(path).build().await?;
         let conn = database.connect()?;
 
         info!("running schema migrations");
-        let fts5_available = schema::migrate(&conn)
-            .await
-            .map_err(|e| DbError::Migration(e.to_string()))?;
-
-        info!(fts5 = fts5_available, "database ready");
+        let report = schema::migrate(&conn, path).await?;
+
+        info!(
+            fts5 = report.fts5_available,
+            schema_version = report.to_version,
+            migrated_from = report.from_version,
+            "database ready"
+        );
         Ok(Self {
             database,
             conn,
-            fts5_available,
+            fts5_available: report.fts5_available,
         })
     }
 
@@ -137,7 +305,7 @@ impl Db {
     pub async fn insert_message(&self, m: &MessageComponent) -> DbResult<()> {
         self.conn
             .execute(
-                "INSERT INTO messages (id, session_id, role, content, timestamp, tokens, hidden, message_type) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                "INSERT INTO messages (id, session_id, role, content, timestamp, tokens, hidden, message_type, model, stop_reason, input_tokens, output_tokens, cache_creation_input_tokens, cache_read_input_tokens, content_blocks_json) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
                 limbo::params!(
                     m.id.to_string(),
                     m.session_id.to_string(),
@@ -146,7 +314,14 @@ impl Db {
                     m.timestamp,
                     option_u32_to_value(m.tokens),
                     m.hidden as i64,
-                    message_type_to_str(m.message_type)
+                    message_type_to_str(m.message_type),
+                    option_to_value(&m.model),
+                    option_to_value(&m.stop_reason),
+                    option_u32_to_value(m.input_tokens),
+                    option_u32_to_value(m.output_tokens),
+                    option_u32_to_value(m.cache_creation_input_tokens),
+                    option_u32_to_value(m.cache_read_input_tokens),
+                    option_to_value(&m.content_blocks_json)
                 ),
             )
             .await?;
@@ -171,7 +346,10 @@ impl Db {
         let mut rows = self
             .conn
             .query(
-                "SELECT id, session_id, role, content, timestamp, tokens, hidden, message_type FROM messages WHERE session_id = ?1 ORDER BY timestamp",
+                &format!(
+                    "SELECT {} FROM messages WHERE session_id = ?1 ORDER BY timestamp",
+                    message_columns("")
+                ),
                 limbo::params!(session_id.to_string()),
             )
             .await?;
@@ -187,16 +365,22 @@ impl Db {
         let mut rows = if self.fts5_available {
             self.conn
                 .query(
-                    "SELECT m.id, m.session_id, m.role, m.content, m.timestamp, m.tokens, m.hidden, m.message_type FROM messages m INNER JOIN messages_fts f ON m.id = f.message_id WHERE messages_fts MATCH ?1",
+                    &format!(
+                        "SELECT {} FROM messages m INNER JOIN messages_fts f ON m.id = f.message_id WHERE messages_fts MATCH ?1",
+                        message_columns("m.")
+                    ),
                     limbo::params!(query.to_string()),
                 )
                 .await?
         } else {
             // Fallback: LIKE query when FTS5 is unavailable
-            let like_pattern = format!("%{query}%");
+            let like_pattern = like_contains(query);
             self.conn
                 .query(
-                    "SELECT id, session_id, role, content, timestamp, tokens, hidden, message_type FROM messages WHERE content LIKE ?1",
+                    &format!(
+                        "SELECT {} FROM messages WHERE content LIKE ?1 ESCAPE '\\'",
+                        message_columns("")
+                    ),
                     limbo::params!(like_pattern),
                 )
                 .await?
@@ -209,6 +393,510 @@ impl Db {
         Ok(result)
     }
 
+    // === Search index ===
+
+    /// Store a message and its search metadata. Returns `false` without
+    /// writing when a message with the same id is already stored (Claude
+    /// copies earlier messages into resumed transcripts).
+    pub async fn index_message(&self, m: &MessageComponent, meta: &SearchMeta) -> DbResult<bool> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT id FROM messages WHERE id = ?1",
+                limbo::params!(m.id.to_string()),
+            )
+            .await?;
+        if rows.next().await?.is_some() {
+            return Ok(false);
+        }
+
+        self.insert_message(m).await?;
+        self.conn
+            .execute(
+                "INSERT INTO message_search (message_id, session_id, seq, cli, project, model, tools) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                limbo::params!(
+                    m.id.to_string(),
+                    m.session_id.to_string(),
+                    meta.seq as i64,
+                    meta.cli.clone(),
+                    option_to_value(&meta.project),
+                    option_to_value(&meta.model),
+                    meta.tools.join(" ")
+                ),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Remove everything the search indexer stored for a session, e.g.
+    /// before re-indexing a transcript that was truncated or replaced.
+    pub async fn clear_search_session(&self, session_id: &Uuid) -> DbResult<()> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT message_id FROM message_search WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(text_value(&row.get_value(0)?)?);
+        }
+        drop(rows);
+
+        for id in ids {
+            self.conn
+                .execute(
+                    "DELETE FROM messages WHERE id = ?1",
+                    limbo::params!(id.clone()),
+                )
+                .await?;
+            if self.fts5_available {
+                self.conn
+                    .execute(
+                        "DELETE FROM messages_fts WHERE message_id = ?1",
+                        limbo::params!(id),
+                    )
+                    .await?;
+            }
+        }
+        self.conn
+            .execute(
+                "DELETE FROM message_search WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_search_source(&self, session_id: &Uuid) -> DbResult<Option<SearchSource>> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT session_id, path, cursor, file_size, message_count, indexed_at FROM search_sources WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(SearchSource {
+                session_id: text_to_uuid(&row.get_value(0)?)?,
+                path: text_value(&row.get_value(1)?)?,
+                cursor: int_value(&row.get_value(2)?)? as u64,
+                file_size: int_value(&row.get_value(3)?)? as u64,
+                message_count: int_value(&row.get_value(4)?)? as u64,
+                indexed_at: int_value(&row.get_value(5)?)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn put_search_source(&self, src: &SearchSource) -> DbResult<()> {
+        self.conn
+            .execute(
+                "DELETE FROM search_sources WHERE session_id = ?1",
+                limbo::params!(src.session_id.to_string()),
+            )
+            .await?;
+        self.conn
+            .execute(
+                "INSERT INTO search_sources (session_id, path, cursor, file_size, message_count, indexed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                limbo::params!(
+                    src.session_id.to_string(),
+                    src.path.clone(),
+                    src.cursor as i64,
+                    src.file_size as i64,
+                    src.message_count as i64,
+                    src.indexed_at
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    // === Analytics ===
+
+    pub async fn get_analytics_session(
+        &self,
+        session_id: &Uuid,
+    ) -> DbResult<Option<AnalyticsSession>> {
+        let mut rows = self
+            .conn
+            .query(
+                &format!("{ANALYTICS_SESSION_SELECT} WHERE session_id = ?1"),
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row_to_analytics_session(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get_analytics_sessions(&self) -> DbResult<Vec<AnalyticsSession>> {
+        let mut rows = self.conn.query(ANALYTICS_SESSION_SELECT, ()).await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            out.push(row_to_analytics_session(&row)?);
+        }
+        Ok(out)
+    }
+
+    pub async fn put_analytics_session(&self, s: &AnalyticsSession) -> DbResult<()> {
+        self.conn
+            .execute(
+                "DELETE FROM analytics_sessions WHERE session_id = ?1",
+                limbo::params!(s.session_id.to_string()),
+            )
+            .await?;
+        self.conn
+            .execute(
+                "INSERT INTO analytics_sessions (session_id, cli, project, path, cursor, file_size, first_at, last_at, last_usage, updated_at, pending) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                vec![
+                    Value::Text(s.session_id.to_string()),
+                    Value::Text(s.cli.clone()),
+                    option_to_value(&s.project),
+                    Value::Text(s.path.clone()),
+                    Value::Integer(s.cursor as i64),
+                    Value::Integer(s.file_size as i64),
+                    s.first_at.map_or(Value::Null, Value::Integer),
+                    s.last_at.map_or(Value::Null, Value::Integer),
+                    option_to_value(&s.last_usage),
+                    Value::Integer(s.updated_at),
+                    Value::Integer(s.pending as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Clear the pending marker once a session's aggregates are written.
+    pub async fn finish_analytics_session(&self, session_id: &Uuid) -> DbResult<()> {
+        self.conn
+            .execute(
+                "UPDATE analytics_sessions SET pending = 0 WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Drop every aggregate of a session, before it is recomputed.
+    pub async fn clear_analytics_session(&self, session_id: &Uuid) -> DbResult<()> {
+        for table in [
+            "analytics_sessions",
+            "analytics_usage",
+            "analytics_tools",
+            "analytics_open_calls",
+        ] {
+            self.conn
+                .execute(
+                    &format!("DELETE FROM {table} WHERE session_id = ?1"),
+                    limbo::params!(session_id.to_string()),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Add `delta` to the stored totals for its session, day and model.
+    pub async fn add_usage_aggregate(&self, delta: &UsageAggregate) -> DbResult<()> {
+        let key = || {
+            vec![
+                Value::Text(delta.session_id.to_string()),
+                Value::Text(delta.day.clone()),
+                Value::Text(delta.model.clone()),
+            ]
+        };
+        let mut total = delta.clone();
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT messages, turns, input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd FROM analytics_usage WHERE session_id = ?1 AND day = ?2 AND model = ?3",
+                key(),
+            )
+            .await?;
+        if let Some(row) = rows.next().await? {
+            total.messages += int_value(&row.get_value(0)?)? as u64;
+            total.turns += int_value(&row.get_value(1)?)? as u64;
+            total.input_tokens += int_value(&row.get_value(2)?)? as u64;
+            total.output_tokens += int_value(&row.get_value(3)?)? as u64;
+            total.cache_creation_tokens += int_value(&row.get_value(4)?)? as u64;
+            total.cache_read_tokens += int_value(&row.get_value(5)?)? as u64;
+            total.cost_usd += optional_f64(&row.get_value(6)?).unwrap_or(0.0);
+        }
+        drop(rows);
+        self.conn
+            .execute(
+                "DELETE FROM analytics_usage WHERE session_id = ?1 AND day = ?2 AND model = ?3",
+                key(),
+            )
+            .await?;
+        let mut params = key();
+        params.extend([
+            Value::Integer(total.messages as i64),
+            Value::Integer(total.turns as i64),
+            Value::Integer(total.input_tokens as i64),
+            Value::Integer(total.output_tokens as i64),
+            Value::Integer(total.cache_creation_tokens as i64),
+            Value::Integer(total.cache_read_tokens as i64),
+            Value::Real(total.cost_usd),
+        ]);
+        self.conn
+            .execute(
+                "INSERT INTO analytics_usage (session_id, day, model, messages, turns, input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Add `delta` to the stored totals for its session, day and tool.
+    pub async fn add_tool_aggregate(&self, delta: &ToolAggregate) -> DbResult<()> {
+        let key = || {
+            vec![
+                Value::Text(delta.session_id.to_string()),
+                Value::Text(delta.day.clone()),
+                Value::Text(delta.tool.clone()),
+            ]
+        };
+        let mut total = delta.clone();
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT calls, errors, duration_ms, approval_wait_ms FROM analytics_tools WHERE session_id = ?1 AND day = ?2 AND tool = ?3",
+                key(),
+            )
+            .await?;
+        if let Some(row) = rows.next().await? {
+            total.calls += int_value(&row.get_value(0)?)? as u64;
+            total.errors += int_value(&row.get_value(1)?)? as u64;
+            total.duration_ms += int_value(&row.get_value(2)?)? as u64;
+            total.approval_wait_ms += int_value(&row.get_value(3)?)? as u64;
+        }
+        drop(rows);
+        self.conn
+            .execute(
+                "DELETE FROM analytics_tools WHERE session_id = ?1 AND day = ?2 AND tool = ?3",
+                key(),
+            )
+            .await?;
+        let mut params = key();
+        params.extend([
+            Value::Integer(total.calls as i64),
+            Value::Integer(total.errors as i64),
+            Value::Integer(total.duration_ms as i64),
+            Value::Integer(total.approval_wait_ms as i64),
+        ]);
+        self.conn
+            .execute(
+                "INSERT INTO analytics_tools (session_id, day, tool, calls, errors, duration_ms, approval_wait_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Usage aggregates with `from <= day <= to` (`YYYY-MM-DD`, inclusive).
+    pub async fn get_usage_aggregates(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> DbResult<Vec<UsageAggregate>> {
+        let (clause, params) = day_range(from, to);
+        let mut rows = self
+            .conn
+            .query(
+                &format!("SELECT session_id, day, model, messages, turns, input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd FROM analytics_usage{clause}"),
+                params,
+            )
+            .await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            out.push(UsageAggregate {
+                session_id: text_to_uuid(&row.get_value(0)?)?,
+                day: text_value(&row.get_value(1)?)?,
+                model: text_value(&row.get_value(2)?)?,
+                messages: int_value(&row.get_value(3)?)? as u64,
+                turns: int_value(&row.get_value(4)?)? as u64,
+                input_tokens: int_value(&row.get_value(5)?)? as u64,
+                output_tokens: int_value(&row.get_value(6)?)? as u64,
+                cache_creation_tokens: int_value(&row.get_value(7)?)? as u64,
+                cache_read_tokens: int_value(&row.get_value(8)?)? as u64,
+                cost_usd: optional_f64(&row.get_value(9)?).unwrap_or(0.0),
+            });
+        }
+        Ok(out)
+    }
+
+    /// Tool aggregates with `from <= day <= to` (`YYYY-MM-DD`, inclusive).
+    pub async fn get_tool_aggregates(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> DbResult<Vec<ToolAggregate>> {
+        let (clause, params) = day_range(from, to);
+        let mut rows = self
+            .conn
+            .query(
+                &format!("SELECT session_id, day, tool, calls, errors, duration_ms, approval_wait_ms FROM analytics_tools{clause}"),
+                params,
+            )
+            .await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            out.push(ToolAggregate {
+                session_id: text_to_uuid(&row.get_value(0)?)?,
+                day: text_value(&row.get_value(1)?)?,
+                tool: text_value(&row.get_value(2)?)?,
+                calls: int_value(&row.get_value(3)?)? as u64,
+                errors: int_value(&row.get_value(4)?)? as u64,
+                duration_ms: int_value(&row.get_value(5)?)? as u64,
+                approval_wait_ms: int_value(&row.get_value(6)?)? as u64,
+            });
+        }
+        Ok(out)
+    }
+
+    pub async fn get_open_tool_calls(&self, session_id: &Uuid) -> DbResult<Vec<OpenToolCall>> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT tool_use_id, tool, started_at_ms FROM analytics_open_calls WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            out.push(OpenToolCall {
+                tool_use_id: text_value(&row.get_value(0)?)?,
+                tool: text_value(&row.get_value(1)?)?,
+                started_at_ms: int_value(&row.get_value(2)?)?,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Replace the open tool calls of a session.
+    pub async fn put_open_tool_calls(
+        &self,
+        session_id: &Uuid,
+        calls: &[OpenToolCall],
+    ) -> DbResult<()> {
+        self.conn
+            .execute(
+                "DELETE FROM analytics_open_calls WHERE session_id = ?1",
+                limbo::params!(session_id.to_string()),
+            )
+            .await?;
+        for call in calls {
+            self.conn
+                .execute(
+                    "INSERT INTO analytics_open_calls (session_id, tool_use_id, tool, started_at_ms) VALUES (?1, ?2, ?3, ?4)",
+                    limbo::params!(
+                        session_id.to_string(),
+                        call.tool_use_id.clone(),
+                        call.tool.clone(),
+                        call.started_at_ms
+                    ),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Number of indexed sessions and messages.
+    pub async fn search_index_counts(&self) -> DbResult<(u64, u64)> {
+        let mut counts = [0u64; 2];
+        for (i, sql) in [
+            "SELECT count(*) FROM search_sources",
+            "SELECT count(*) FROM message_search",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut rows = self.conn.query(sql, ()).await?;
+            if let Some(row) = rows.next().await? {
+                counts[i] = int_value(&row.get_value(0)?)? as u64;
+            }
+        }
+        Ok((counts[0], counts[1]))
+    }
+
+    /// Walk indexed messages matching `scan`, newest first, until `visit`
+    /// returns `false`.
+    pub async fn scan_search_index(
+        &self,
+        scan: &IndexScan,
+        mut visit: impl FnMut(IndexedMessage) -> bool,
+    ) -> DbResult<()> {
+        let mut sql = format!(
+            "SELECT {}, s.seq, s.cli, s.project, s.model, s.tools FROM messages m INNER JOIN message_search s ON s.message_id = m.id",
+            message_columns("m.")
+        );
+        let mut conditions: Vec<String> = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
+        let bind = |params: &mut Vec<Value>, v: Value| {
+            params.push(v);
+            format!("?{}", params.len())
+        };
+
+        if self.fts5_available && !scan.contains.is_empty() {
+            sql.push_str(" INNER JOIN messages_fts f ON f.message_id = m.id");
+            let phrases: Vec<String> = scan
+                .contains
+                .iter()
+                .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+                .collect();
+            let p = bind(&mut params, Value::Text(phrases.join(" ")));
+            conditions.push(format!("messages_fts MATCH {p}"));
+        } else {
+            for term in &scan.contains {
+                let p = bind(&mut params, Value::Text(like_contains(term)));
+                conditions.push(format!("m.content LIKE {p} ESCAPE '\\'"));
+            }
+        }
+        if let Some(role) = scan.role {
+            let p = bind(&mut params, Value::Text(role_to_str(role).into()));
+            conditions.push(format!("m.role = {p}"));
+        }
+        if let Some(cli) = &scan.cli {
+            let p = bind(&mut params, Value::Text(cli.clone()));
+            conditions.push(format!("s.cli = {p}"));
+        }
+        if let Some(after) = scan.after {
+            let p = bind(&mut params, Value::Integer(after));
+            conditions.push(format!("m.timestamp >= {p}"));
+        }
+        if let Some(before) = scan.before {
+            let p = bind(&mut params, Value::Integer(before));
+            conditions.push(format!("m.timestamp < {p}"));
+        }
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY m.timestamp DESC");
+
+        let mut rows = self.conn.query(&sql, params).await?;
+        while let Some(row) = rows.next().await? {
+            let meta = SearchMeta {
+                seq: int_value(&row.get_value(15)?)? as u64,
+                cli: text_value(&row.get_value(16)?)?,
+                project: optional_text(&row.get_value(17)?),
+                model: optional_text(&row.get_value(18)?),
+                tools: optional_text(&row.get_value(19)?)
+                    .map(|t| t.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default(),
+            };
+            let message = row_to_message(&row)?;
+            if !visit(IndexedMessage { message, meta }) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     // === File CRUD ===
 
     pub async fn insert_file(&self, f: &FileComponent) -> DbResult<()> {
@@ -391,10 +1079,319 @@ impl Db {
         }
         Ok(result)
     }
+
+    /// The `limit` most recent captured API requests, oldest first.
+    pub async fn get_recent_api_requests(
+        &self,
+        limit: usize,
+    ) -> DbResult<Vec<ApiRequestComponent>> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT id, session_id, method, url, request_body, response_body, status_code, latency_ms, timestamp, request_headers, response_headers, request_size, response_size, traffic_category FROM api_requests ORDER BY timestamp DESC LIMIT ?1",
+                limbo::params!(limit as i64),
+            )
+            .await?;
+
+        let mut result = Vec::new();
+        while let Some(row) = rows.next().await? {
+            result.push(row_to_api_request(&row)?);
+        }
+        result.reverse();
+        Ok(result)
+    }
+
+    pub async fn get_api_request(&self, id: &Uuid) -> DbResult<Option<ApiRequestComponent>> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT id, session_id, method, url, request_body, response_body, status_code, latency_ms, timestamp, request_headers, response_headers, request_size, response_size, traffic_category FROM api_requests WHERE id = ?1",
+                limbo::params!(id.to_string()),
+            )
+            .await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(row_to_api_request(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    // === Retention ===
+
+    /// Id, session, timestamp and stored body size of every API request.
+    pub async fn api_request_stats(&self) -> DbResult<Vec<ApiRequestStat>> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT id, session_id, timestamp, length(coalesce(request_body, '')) + length(coalesce(response_body, '')) FROM api_requests",
+                (),
+            )
+            .await?;
+
+        let mut result = Vec::new();
+        while let Some(row) = rows.next().await? {
+            result.push(ApiRequestStat {
+                id: text_to_uuid(&row.get_value(0)?)?,
+                session_id: text_to_uuid(&row.get_value(1)?)?,
+                timestamp: int_value(&row.get_value(2)?)?,
+                bytes: optional_int(&row.get_value(3)?).unwrap_or(0).max(0) as u64,
+            });
+        }
+        Ok(result)
+    }
+
+    pub async fn delete_api_requests(&self, ids: &[Uuid]) -> DbResult<()> {
+        for chunk in ids.chunks(100) {
+            let placeholders: Vec<String> = (1..=chunk.len()).map(|i| format!("?{i}")).collect();
+            let sql = format!(
+                "DELETE FROM api_requests WHERE id IN ({})",
+                placeholders.join(", ")
+            );
+            let params: Vec<Value> = chunk.iter().map(|id| Value::Text(id.to_string())).collect();
+            self.conn.execute(&sql, params).await?;
+        }
+        Ok(())
+    }
+
+    /// Most recent activity per session in epoch seconds, from stored
+    /// messages and API requests. Requests without a session are skipped.
+    pub async fn session_last_activity(&self) -> DbResult<HashMap<Uuid, i64>> {
+        let mut last: HashMap<Uuid, i64> = HashMap::new();
+        // Messages are stamped in seconds, API requests in milliseconds.
+        for (sql, divisor) in [
+            (
+                "SELECT session_id, max(timestamp) FROM messages GROUP BY session_id",
+                1,
+            ),
+            (
+                "SELECT session_id, max(timestamp) FROM api_requests GROUP BY session_id",
+                1000,
+            ),
+        ] {
+            let mut rows = self.conn.query(sql, ()).await?;
+            while let Some(row) = rows.next().await? {
+                let sid = text_to_uuid(&row.get_value(0)?)?;
+                if sid.is_nil() {
+                    continue;
+                }
+                let ts = int_value(&row.get_value(1)?)? / divisor;
+                let entry = last.entry(sid).or_insert(ts);
+                *entry = (*entry).max(ts);
+            }
+        }
+        Ok(last)
+    }
+
+    /// Every stored row that belongs to a session, table by table, with
+    /// all columns of the current schema.
+    pub async fn export_session_rows(&self, session_id: &Uuid) -> DbResult<Vec<TableRows>> {
+        let mut tables = Vec::new();
+        for table in SESSION_TABLES {
+            let mut stmt = self
+                .conn
+                .prepare(&format!("SELECT * FROM {table} WHERE session_id = ?1"))
+                .await?;
+            let columns: Vec<String> = stmt
+                .columns()
+                .iter()
+                .map(|c| c.name().to_string())
+                .collect();
+            let mut rows = stmt.query(limbo::params!(session_id.to_string())).await?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next().await? {
+                let mut values = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    values.push(value_to_json(row.get_value(i)?));
+                }
+                out.push(values);
+            }
+            if !out.is_empty() {
+                tables.push(TableRows {
+                    table: table.to_string(),
+                    columns,
+                    rows: out,
+                });
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Up to `limit` rows of a whole table, starting at row `offset`. Rows
+    /// deleted between pages can shift later rows past a page boundary.
+    pub async fn export_table_page(
+        &self,
+        table: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<TableRows> {
+        if !DATA_TABLES.contains(&table) {
+            return Err(DbError::Import(format!("unknown table {table}")));
+        }
+        let mut stmt = self
+            .conn
+            // Limbo only parses literal OFFSETs.
+            .prepare(&format!(
+                "SELECT * FROM {table} LIMIT {limit} OFFSET {offset}"
+            ))
+            .await?;
+        let columns: Vec<String> = stmt
+            .columns()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect();
+        let mut rows = stmt.query(()).await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let mut values = Vec::with_capacity(columns.len());
+            for i in 0..columns.len() {
+                values.push(value_to_json(row.get_value(i)?));
+            }
+            out.push(values);
+        }
+        Ok(TableRows {
+            table: table.to_string(),
+            columns,
+            rows: out,
+        })
+    }
+
+    /// Insert rows produced by [`Db::export_session_rows`] or
+    /// [`Db::export_table_page`], possibly by an older schema. Rows whose id
+    /// is already stored are skipped. Returns the number of rows inserted.
+    pub async fn import_rows(&self, rows: &TableRows) -> DbResult<usize> {
+        if !DATA_TABLES.contains(&rows.table.as_str()) {
+            return Err(DbError::Import(format!("unknown table {}", rows.table)));
+        }
+        let known = schema::table_columns(&self.conn, &rows.table).await?;
+        if let Some(col) = rows.columns.iter().find(|c| !known.contains(c)) {
+            return Err(DbError::Import(format!(
+                "column {}.{col} does not exist in this schema version",
+                rows.table
+            )));
+        }
+        let placeholders: Vec<String> = (1..=rows.columns.len()).map(|i| format!("?{i}")).collect();
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            rows.table,
+            rows.columns.join(", "),
+            placeholders.join(", ")
+        );
+        let mut inserted = 0;
+        for row in &rows.rows {
+            let params: Vec<Value> = row.iter().map(json_to_value).collect();
+            match self.conn.execute(&sql, params).await {
+                Ok(_) => inserted += 1,
+                Err(e) if e.to_string().contains("UNIQUE constraint failed") => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(inserted)
+    }
+
+    pub async fn count_session_rows(&self, session_id: &Uuid) -> DbResult<u64> {
+        let mut total = 0;
+        for table in SESSION_TABLES {
+            let mut rows = self
+                .conn
+                .query(
+                    &format!("SELECT count(*) FROM {table} WHERE session_id = ?1"),
+                    limbo::params!(session_id.to_string()),
+                )
+                .await?;
+            if let Some(row) = rows.next().await? {
+                total += int_value(&row.get_value(0)?)? as u64;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Delete every row that belongs to a session. The search indexer's
+    /// cursor stays, so the transcript is not indexed again.
+    pub async fn delete_session_rows(&self, session_id: &Uuid) -> DbResult<()> {
+        for table in SESSION_TABLES {
+            self.conn
+                .execute(
+                    &format!("DELETE FROM {table} WHERE session_id = ?1"),
+                    limbo::params!(session_id.to_string()),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+const ANALYTICS_SESSION_SELECT: &str = "SELECT session_id, cli, project, path, cursor, file_size, first_at, last_at, last_usage, updated_at, pending FROM analytics_sessions";
+
+fn row_to_analytics_session(row: &limbo::Row) -> DbResult<AnalyticsSession> {
+    Ok(AnalyticsSession {
+        session_id: text_to_uuid(&row.get_value(0)?)?,
+        cli: text_value(&row.get_value(1)?)?,
+        project: optional_text(&row.get_value(2)?),
+        path: text_value(&row.get_value(3)?)?,
+        cursor: int_value(&row.get_value(4)?)? as u64,
+        file_size: int_value(&row.get_value(5)?)? as u64,
+        first_at: optional_int(&row.get_value(6)?),
+        last_at: optional_int(&row.get_value(7)?),
+        last_usage: optional_text(&row.get_value(8)?),
+        updated_at: int_value(&row.get_value(9)?)?,
+        pending: int_value(&row.get_value(10)?)? != 0,
+    })
+}
+
+/// `WHERE` clause and parameters for an inclusive day range.
+fn day_range(from: Option<&str>, to: Option<&str>) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+    for (op, bound) in [(">=", from), ("<=", to)] {
+        if let Some(day) = bound {
+            params.push(Value::Text(day.to_string()));
+            conditions.push(format!("day {op} ?{}", params.len()));
+        }
+    }
+    if conditions.is_empty() {
+        (String::new(), params)
+    } else {
+        (format!(" WHERE {}", conditions.join(" AND ")), params)
+    }
 }
 
 // === Value conversion helpers ===
 
+fn value_to_json(v: Value) -> serde_json::Value {
+    use base64::Engine;
+    match v {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => i.into(),
+        Value::Real(f) => f.into(),
+        Value::Text(s) => s.into(),
+        Value::Blob(b) => {
+            serde_json::json!({"blob": base64::engine::general_purpose::STANDARD.encode(b)})
+        }
+    }
+}
+
+fn json_to_value(v: &serde_json::Value) -> Value {
+    use base64::Engine;
+    match v {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        serde_json::Value::Object(o) => match o.get("blob").and_then(|b| b.as_str()) {
+            Some(b) => Value::Blob(
+                base64::engine::general_purpose::STANDARD
+                    .decode(b)
+                    .unwrap_or_default(),
+            ),
+            None => Value::Text(v.to_string()),
+        },
+        serde_json::Value::Array(_) => Value::Text(v.to_string()),
+    }
+}
+
 fn text_value(v: &Value) -> DbResult<String> {
     match v {
         Value::Text(s) => Ok(s.clone()),
@@ -584,23 +1581,65 @@ fn row_to_api_request(row: &limbo::Row) -> DbResult<ApiRequestComponent> {
     })
 }
 
+/// Column list matching [`row_to_message`], each prefixed with `prefix`
+/// (a table alias like `"m."`, or `""`).
+/// `%term%` for `LIKE … ESCAPE '\'`: matches `term` literally, even when it
+/// contains `%`, `_` or `\`.
+fn like_contains(term: &str) -> String {
+    let mut pattern = String::with_capacity(term.len() + 2);
+    pattern.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+fn message_columns(prefix: &str) -> String {
+    [
+        "id",
+        "session_id",
+        "role",
+        "content",
+        "timestamp",
+        "tokens",
+        "hidden",
+        "message_type",
+        "model",
+        "stop_reason",
+        "input_tokens",
+        "output_tokens",
+        "cache_creation_input_tokens",
+        "cache_read_input_tokens",
+        "content_blocks_json",
+    ]
+    .map(|c| format!("{prefix}{c}"))
+    .join(", ")
+}
+
 fn row_to_message(row: &limbo::Row) -> DbResult<MessageComponent> {
+    let optional_u32 = |i: usize| -> DbResult<Option<u32>> {
+        Ok(optional_int(&row.get_value(i)?).map(|v| v as u32))
+    };
     Ok(MessageComponent {
         id: text_to_uuid(&row.get_value(0)?)?,
         session_id: text_to_uuid(&row.get_value(1)?)?,
         role: str_to_role(&text_value(&row.get_value(2)?)?),
         content: text_value(&row.get_value(3)?)?,
-        content_blocks_json: None, // DB doesn't store structured blocks (JSONL is SSOT)
+        content_blocks_json: optional_text(&row.get_value(14)?),
         timestamp: int_value(&row.get_value(4)?)?,
-        tokens: optional_int(&row.get_value(5)?).map(|v| v as u32),
+        tokens: optional_u32(5)?,
         hidden: matches!(row.get_value(6)?, Value::Integer(1)),
         message_type: str_to_message_type(&text_value(&row.get_value(7)?)?),
-        model: None,
-        stop_reason: None,
-        input_tokens: None,
-        output_tokens: None,
-        cache_creation_input_tokens: None,
-        cache_read_input_tokens: None,
+        model: optional_text(&row.get_value(8)?),
+        stop_reason: optional_text(&row.get_value(9)?),
+        input_tokens: optional_u32(10)?,
+        output_tokens: optional_u32(11)?,
+        cache_creation_input_tokens: optional_u32(12)?,
+        cache_read_input_tokens: optional_u32(13)?,
     })
 }
 
@@ -775,6 +1814,109 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[tokio::test]
+    async fn like_fallback_matches_wildcards_literally() {
+        let mut db = test_db().await;
+        db.fts5_available = false;
+        let sid = Uuid::new_v4();
+        db.insert_session(&test_session(sid)).await.unwrap();
+
+        let meta = SearchMeta {
+            seq: 0,
+            cli: "claude".to_string(),
+            project: None,
+            model: None,
+            tools: vec![],
+        };
+        for (i, content) in [
+            "coverage at 100% now",
+            "coverage at 1000 now",
+            "snake_case name",
+            "snakeXcase name",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let m = MessageComponent {
+                id: Uuid::new_v4(),
+                session_id: sid,
+                role: MessageRole::User,
+                content: content.to_string(),
+                content_blocks_json: None,
+                timestamp: 1708000000 + i as i64,
+                tokens: None,
+                hidden: false,
+                message_type: MessageType::Text,
+                model: None,
+                stop_reason: None,
+                input_tokens: None,
+                output_tokens: None,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            };
+            db.index_message(
+                &m,
+                &SearchMeta {
+                    seq: i as u64,
+                    ..meta.clone()
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let scan_for = |term: &str| IndexScan {
+            contains: vec![term.to_string()],
+            role: None,
+            cli: None,
+            after: None,
+            before: None,
+        };
+        for (term, expected) in [
+            ("100%", "coverage at 100% now"),
+            ("snake_case", "snake_case name"),
+        ] {
+            let mut found = Vec::new();
+            db.scan_search_index(&scan_for(term), |m| {
+                found.push(m.message.content);
+                true
+            })
+            .await
+            .unwrap();
+            assert_eq!(found, vec![expected.to_string()], "scan for {term}");
+
+            let results = db.search_messages(term).await.unwrap();
+            assert_eq!(results.len(), 1, "search for {term}");
+            assert_eq!(results[0].content, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn table_pages_round_trip_into_another_database() {
+        let db = test_db().await;
+        let sid = Uuid::new_v4();
+        db.insert_session(&test_session(sid)).await.unwrap();
+        for _ in 0..3 {
+            db.insert_message(&test_message(Uuid::new_v4(), sid))
+                .await
+                .unwrap();
+        }
+
+        let first = db.export_table_page("messages", 0, 2).await.unwrap();
+        let second = db.export_table_page("messages", 2, 2).await.unwrap();
+        assert_eq!((first.rows.len(), second.rows.len()), (2, 1));
+        assert_ne!(first.rows[0], second.rows[0]);
+        assert!(db.export_table_page("schema_version", 0, 2).await.is_err());
+
+        let copy = test_db().await;
+        let sessions = db.export_table_page("sessions", 0, 10).await.unwrap();
+        for page in [&sessions, &first, &second] {
+            copy.import_rows(page).await.unwrap();
+        }
+        assert_eq!(copy.get_sessions().await.unwrap().len(), 1);
+        assert_eq!(copy.get_messages_by_session(&sid).await.unwrap().len(), 3);
+    }
+
     #[tokio::test]
     async fn no_data_loss_reconnect() {
         // Use a temp file so we can close and reopen
@@ -1,8 +1,27 @@
-use limbo::Connection;
+use std::path::{Path, PathBuf};
+
+use limbo::{Connection, Value};
+
+use super::queries::{DbError, DbResult};
 
 // Limbo constraint: TEXT PRIMARY KEY not supported.
 // Use rowid (implicit) and CREATE UNIQUE INDEX for UUID id columns.
 // Also: REFERENCES (foreign keys) not enforced by Limbo.
+//
+// The CREATE TABLE constants are frozen at the version that introduced the
+// table; later changes are `Step::AddColumn`s in a new migration, so every
+// database — new or upgraded — ends up with the same columns.
+
+// One row per applied migration.
+pub const CREATE_SCHEMA_VERSION: &str = "\
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    applied_at INTEGER NOT NULL
+)";
+
+pub const CREATE_INDEX_SCHEMA_VERSION: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS idx_schema_version ON schema_version(version)";
 
 pub const CREATE_SESSIONS: &str = "\
 CREATE TABLE IF NOT EXISTS sessions (
@@ -71,6 +90,86 @@ CREATE TABLE IF NOT EXISTS api_requests (
     traffic_category TEXT
 )";
 
+// Search metadata per indexed message (role, content and timestamp live in
+// `messages`). `seq` is the message's position in its transcript, which stays
+// valid for CLIs whose message ids are regenerated on every parse. `tools`
+// holds the tool names the message calls, space-separated and lowercased.
+pub const CREATE_MESSAGE_SEARCH: &str = "\
+CREATE TABLE IF NOT EXISTS message_search (
+    message_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    cli TEXT NOT NULL,
+    project TEXT,
+    model TEXT,
+    tools TEXT
+)";
+
+// Per-transcript progress of the search indexer (parser cursor + file size).
+pub const CREATE_SEARCH_SOURCES: &str = "\
+CREATE TABLE IF NOT EXISTS search_sources (
+    session_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    cursor INTEGER NOT NULL,
+    file_size INTEGER NOT NULL,
+    message_count INTEGER NOT NULL,
+    indexed_at INTEGER NOT NULL
+)";
+
+// Per-transcript state of the analytics aggregator: the session's
+// dimensions, its own parser cursor, and the usage of the last assistant
+// message (Claude repeats it on every content block of one response).
+pub const CREATE_ANALYTICS_SESSIONS: &str = "\
+CREATE TABLE IF NOT EXISTS analytics_sessions (
+    session_id TEXT NOT NULL,
+    cli TEXT NOT NULL,
+    project TEXT,
+    path TEXT NOT NULL,
+    cursor INTEGER NOT NULL,
+    file_size INTEGER NOT NULL,
+    first_at INTEGER,
+    last_at INTEGER,
+    last_usage TEXT,
+    updated_at INTEGER NOT NULL
+)";
+
+// Message, token and cost totals per session, UTC day and model ('' for
+// messages without one).
+pub const CREATE_ANALYTICS_USAGE: &str = "\
+CREATE TABLE IF NOT EXISTS analytics_usage (
+    session_id TEXT NOT NULL,
+    day TEXT NOT NULL,
+    model TEXT NOT NULL,
+    messages INTEGER NOT NULL,
+    turns INTEGER NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    cache_creation_tokens INTEGER NOT NULL,
+    cache_read_tokens INTEGER NOT NULL,
+    cost_usd REAL NOT NULL
+)";
+
+// Tool call totals per session, UTC day (of the call) and tool name.
+pub const CREATE_ANALYTICS_TOOLS: &str = "\
+CREATE TABLE IF NOT EXISTS analytics_tools (
+    session_id TEXT NOT NULL,
+    day TEXT NOT NULL,
+    tool TEXT NOT NULL,
+    calls INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    approval_wait_ms INTEGER NOT NULL
+)";
+
+// Tool calls whose result has not been read yet.
+pub const CREATE_ANALYTICS_OPEN_CALLS: &str = "\
+CREATE TABLE IF NOT EXISTS analytics_open_calls (
+    session_id TEXT NOT NULL,
+    tool_use_id TEXT NOT NULL,
+    tool TEXT NOT NULL,
+    started_at_ms INTEGER NOT NULL
+)";
+
 // Standalone FTS5 table (no content= since Limbo lacks triggers)
 pub const CREATE_MESSAGES_FTS: &str = "\
 CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
@@ -96,6 +195,14 @@ pub const CREATE_INDEX_AGENTS_ID: &str =
 pub const CREATE_INDEX_API_REQUESTS_ID: &str =
     "CREATE UNIQUE INDEX IF NOT EXISTS idx_api_requests_id ON api_requests(id)";
 
+pub const CREATE_INDEX_MESSAGE_SEARCH_ID: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS idx_message_search_id ON message_search(message_id)";
+
+pub const CREATE_INDEX_SEARCH_SOURCES_SESSION: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS idx_search_sources_session ON search_sources(session_id)";
+
+pub const CREATE_INDEX_ANALYTICS_SESSIONS_SESSION: &str = "CREATE UNIQUE INDEX IF NOT EXISTS idx_analytics_sessions_session ON analytics_sessions(session_id)";
+
 // Foreign-key-like indexes on session_id columns
 pub const CREATE_INDEX_MESSAGES_SESSION: &str =
     "CREATE INDEX IF NOT EXISTS idx_messages_session ON messages(session_id)";
@@ -112,53 +219,242 @@ pub const CREATE_INDEX_AGENTS_SESSION: &str =
 pub const CREATE_INDEX_API_REQUESTS_SESSION: &str =
     "CREATE INDEX IF NOT EXISTS idx_api_requests_session ON api_requests(session_id)";
 
-// Tables are safe with IF NOT EXISTS
-const TABLE_STATEMENTS: &[&str] = &[
-    CREATE_SESSIONS,
-    CREATE_MESSAGES,
-    CREATE_FILES,
-    CREATE_TASKS,
-    CREATE_AGENTS,
-    CREATE_API_REQUESTS,
-];
+pub const CREATE_INDEX_MESSAGE_SEARCH_SESSION: &str =
+    "CREATE INDEX IF NOT EXISTS idx_message_search_session ON message_search(session_id)";
+
+pub const CREATE_INDEX_ANALYTICS_USAGE_SESSION: &str =
+    "CREATE INDEX IF NOT EXISTS idx_analytics_usage_session ON analytics_usage(session_id)";
+
+pub const CREATE_INDEX_ANALYTICS_TOOLS_SESSION: &str =
+    "CREATE INDEX IF NOT EXISTS idx_analytics_tools_session ON analytics_tools(session_id)";
+
+pub const CREATE_INDEX_ANALYTICS_OPEN_CALLS_SESSION: &str = "CREATE INDEX IF NOT EXISTS idx_analytics_open_calls_session ON analytics_open_calls(session_id)";
+
+/// One idempotent step of a [`Migration`]. Limbo has no transactional DDL
+/// we can rely on, so a migration interrupted half-way is simply re-run.
+pub enum Step {
+    /// `CREATE TABLE IF NOT EXISTS …`
+    Table(&'static str),
+    /// `CREATE [UNIQUE] INDEX …`. Limbo's IF NOT EXISTS is broken for
+    /// indexes, so "already exists" errors are ignored.
+    Index(&'static str),
+    /// `ALTER TABLE … ADD COLUMN`, skipped when the column exists.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        decl: &'static str,
+    },
+}
 
-// Limbo's IF NOT EXISTS for indexes is broken — ignore "already exists" errors
-const INDEX_STATEMENTS: &[&str] = &[
-    CREATE_INDEX_SESSIONS_ID,
-    CREATE_INDEX_MESSAGES_ID,
-    CREATE_INDEX_FILES_ID,
-    CREATE_INDEX_TASKS_ID,
-    CREATE_INDEX_AGENTS_ID,
-    CREATE_INDEX_API_REQUESTS_ID,
-    CREATE_INDEX_MESSAGES_SESSION,
-    CREATE_INDEX_FILES_SESSION,
-    CREATE_INDEX_TASKS_SESSION,
-    CREATE_INDEX_AGENTS_SESSION,
-    CREATE_INDEX_API_REQUESTS_SESSION,
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub steps: &'static [Step],
+}
+
+/// Forward migrations in version order. Never edit a released entry —
+/// append a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema of 0.1.0-alpha.1",
+        steps: &[
+            Step::Table(CREATE_SESSIONS),
+            Step::Table(CREATE_MESSAGES),
+            Step::Table(CREATE_FILES),
+            Step::Table(CREATE_TASKS),
+            Step::Table(CREATE_AGENTS),
+            Step::Table(CREATE_API_REQUESTS),
+            // Pre-release databases predate the header/size/category columns.
+            Step::AddColumn {
+                table: "api_requests",
+                column: "request_headers",
+                decl: "TEXT",
+            },
+            Step::AddColumn {
+                table: "api_requests",
+                column: "response_headers",
+                decl: "TEXT",
+            },
+            Step::AddColumn {
+                table: "api_requests",
+                column: "request_size",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "api_requests",
+                column: "response_size",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "api_requests",
+                column: "traffic_category",
+                decl: "TEXT",
+            },
+            Step::Index(CREATE_INDEX_SESSIONS_ID),
+            Step::Index(CREATE_INDEX_MESSAGES_ID),
+            Step::Index(CREATE_INDEX_FILES_ID),
+            Step::Index(CREATE_INDEX_TASKS_ID),
+            Step::Index(CREATE_INDEX_AGENTS_ID),
+            Step::Index(CREATE_INDEX_API_REQUESTS_ID),
+            Step::Index(CREATE_INDEX_MESSAGES_SESSION),
+            Step::Index(CREATE_INDEX_FILES_SESSION),
+            Step::Index(CREATE_INDEX_TASKS_SESSION),
+            Step::Index(CREATE_INDEX_AGENTS_SESSION),
+            Step::Index(CREATE_INDEX_API_REQUESTS_SESSION),
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "full-text search index",
+        steps: &[
+            Step::Table(CREATE_MESSAGE_SEARCH),
+            Step::Table(CREATE_SEARCH_SOURCES),
+            Step::Index(CREATE_INDEX_MESSAGE_SEARCH_ID),
+            Step::Index(CREATE_INDEX_SEARCH_SOURCES_SESSION),
+            Step::Index(CREATE_INDEX_MESSAGE_SEARCH_SESSION),
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "message model, stop reason, token breakdown and content blocks",
+        steps: &[
+            Step::AddColumn {
+                table: "messages",
+                column: "model",
+                decl: "TEXT",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "stop_reason",
+                decl: "TEXT",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "input_tokens",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "output_tokens",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "cache_creation_input_tokens",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "cache_read_input_tokens",
+                decl: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "messages",
+                column: "content_blocks_json",
+                decl: "TEXT",
+            },
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "cross-session analytics aggregates",
+        steps: &[
+            Step::Table(CREATE_ANALYTICS_SESSIONS),
+            Step::Table(CREATE_ANALYTICS_USAGE),
+            Step::Table(CREATE_ANALYTICS_TOOLS),
+            Step::Table(CREATE_ANALYTICS_OPEN_CALLS),
+            Step::Index(CREATE_INDEX_ANALYTICS_SESSIONS_SESSION),
+            Step::Index(CREATE_INDEX_ANALYTICS_USAGE_SESSION),
+            Step::Index(CREATE_INDEX_ANALYTICS_TOOLS_SESSION),
+            Step::Index(CREATE_INDEX_ANALYTICS_OPEN_CALLS_SESSION),
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "pending marker for interrupted analytics updates",
+        steps: &[Step::AddColumn {
+            table: "analytics_sessions",
+            column: "pending",
+            decl: "INTEGER NOT NULL DEFAULT 0",
+        }],
+    },
 ];
 
-/// Run all schema migrations. Idempotent.
-/// Returns whether FTS5 is available.
-pub async fn migrate(conn: &Connection) -> Result<bool, limbo::Error> {
-    // Tables: IF NOT EXISTS works correctly
-    for stmt in TABLE_STATEMENTS {
-        conn.execute(stmt, ()).await?;
+/// Schema version this build writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// What [`migrate`] did.
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    /// Version found on open (0 for a new or pre-versioning database).
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Copy of the database taken before migrating, if one was needed.
+    pub backup: Option<PathBuf>,
+    pub fts5_available: bool,
+}
+
+/// Bring the database at `db_path` (opened as `conn`) up to
+/// [`SCHEMA_VERSION`]. Idempotent. An existing database is copied next to
+/// itself before the first pending migration runs.
+pub async fn migrate(conn: &Connection, db_path: &str) -> DbResult<MigrationReport> {
+    let versioned = table_exists(conn, "schema_version").await?;
+    let from_version = if versioned {
+        current_version(conn).await?
+    } else {
+        0
+    };
+    if from_version > SCHEMA_VERSION {
+        return Err(DbError::Migration(format!(
+            "database schema v{from_version} is newer than this build (v{SCHEMA_VERSION})"
+        )));
     }
 
-    // Indexes: IF NOT EXISTS is broken in Limbo — ignore "already exists" errors
-    for stmt in INDEX_STATEMENTS {
-        match conn.execute(stmt, ()).await {
-            Ok(_) => {}
-            Err(e) if e.to_string().contains("already exists") => {
-                // Index already exists from previous run — safe to ignore
-            }
-            Err(e) => return Err(e),
-        }
+    let pending: Vec<&Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from_version)
+        .collect();
+    let mut backup = None;
+    if !pending.is_empty()
+        && db_path != ":memory:"
+        && (versioned || table_exists(conn, "sessions").await?)
+    {
+        let path = backup_files(Path::new(db_path), from_version)?;
+        tracing::info!(backup = %path.display(), "database backed up before migrating");
+        backup = Some(path);
     }
 
-    // No additional column migrations needed — new DBs include `category` in CREATE TABLE.
-    // If an old DB is missing the column, the proxy requests SELECT will fail gracefully
-    // (row_to_api_request uses .ok() for column 13) and old data won't have categories.
+    conn.execute(CREATE_SCHEMA_VERSION, ()).await?;
+    create_index(conn, CREATE_INDEX_SCHEMA_VERSION).await?;
+
+    for migration in pending {
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "applying schema migration"
+        );
+        for step in migration.steps {
+            apply(conn, step).await.map_err(|e| {
+                DbError::Migration(format!(
+                    "v{} ({}): {e}",
+                    migration.version, migration.description
+                ))
+            })?;
+        }
+        conn.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            limbo::params!(
+                migration.version as i64,
+                migration.description,
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0)
+            ),
+        )
+        .await?;
+    }
 
     // FTS5 is optional — Limbo may not support it in all versions
     let fts5_available = match conn.execute(CREATE_MESSAGES_FTS, ()).await {
@@ -175,5 +471,254 @@ pub async fn migrate(conn: &Connection) -> Result<bool, limbo::Error> {
         }
     };
 
-    Ok(fts5_available)
+    Ok(MigrationReport {
+        from_version,
+        to_version: SCHEMA_VERSION,
+        backup,
+        fts5_available,
+    })
+}
+
+async fn apply(conn: &Connection, step: &Step) -> Result<(), limbo::Error> {
+    match step {
+        Step::Table(sql) => {
+            conn.execute(sql, ()).await?;
+        }
+        Step::Index(sql) => create_index(conn, sql).await?,
+        Step::AddColumn {
+            table,
+            column,
+            decl,
+        } => {
+            if !table_columns(conn, table)
+                .await?
+                .iter()
+                .any(|c| c == column)
+            {
+                conn.execute(
+                    &format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"),
+                    (),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn create_index(conn: &Connection, sql: &str) -> Result<(), limbo::Error> {
+    match conn.execute(sql, ()).await {
+        Ok(_) => Ok(()),
+        // Index already exists from previous run — safe to ignore
+        Err(e) if e.to_string().contains("already exists") => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn current_version(conn: &Connection) -> Result<u32, limbo::Error> {
+    let mut rows = conn
+        .query("SELECT max(version) FROM schema_version", ())
+        .await?;
+    Ok(match rows.next().await? {
+        Some(row) => match row.get_value(0)? {
+            Value::Integer(v) => v as u32,
+            _ => 0,
+        },
+        None => 0,
+    })
+}
+
+async fn table_exists(conn: &Connection, table: &str) -> Result<bool, limbo::Error> {
+    let mut rows = conn
+        .query(
+            "SELECT name FROM sqlite_schema WHERE type = 'table' AND name = ?1",
+            limbo::params!(table),
+        )
+        .await?;
+    Ok(rows.next().await?.is_some())
+}
+
+pub(super) async fn table_columns(
+    conn: &Connection,
+    table: &str,
+) -> Result<Vec<String>, limbo::Error> {
+    let mut rows = conn
+        .query(&format!("PRAGMA table_info({table})"), ())
+        .await?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if let Value::Text(name) = row.get_value(1)? {
+            columns.push(name);
+        }
+    }
+    Ok(columns)
+}
+
+/// Copy the database file and its WAL to `<db>.v<version>-<unix>.bak`.
+fn backup_files(db_path: &Path, version: u32) -> DbResult<PathBuf> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let backup = PathBuf::from(format!("{}.v{version}-{now}.bak", db_path.display()));
+    let copy = |from: &Path, to: &Path| {
+        std::fs::copy(from, to)
+            .map_err(|e| DbError::Migration(format!("backup of {} failed: {e}", from.display())))
+    };
+    copy(db_path, &backup)?;
+    let wal = PathBuf::from(format!("{}-wal", db_path.display()));
+    if wal.exists() {
+        copy(&wal, &PathBuf::from(format!("{}-wal", backup.display())))?;
+    }
+    Ok(backup)
+}
+
+/// Fold the WAL into the main file before the real open.
+///
+/// Limbo 0.0.22 takes the page count for new allocations from the header in
+/// the main file, which is stale while committed pages still sit in the WAL.
+/// A table, index or b-tree page allocated in that state reuses a live page
+/// and silently overwrites another table. After a checkpoint the header is
+/// current, and the next open reads it.
+pub async fn checkpoint(path: &str) -> DbResult<()> {
+    let database = limbo::Builder::new_local(path).build().await?;
+    let conn = database.connect()?;
+    let mut rows = conn.query("PRAGMA wal_checkpoint", ()).await?;
+    while rows.next().await?.is_some() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    /// SQL dumps of databases written by each released version (plus the
+    /// development builds before the first release). Add one per release.
+    const FIXTURES: &[(&str, &str)] = &[
+        ("pre-release", include_str!("fixtures/pre-release.sql")),
+        ("0.1.0-alpha.1", include_str!("fixtures/0.1.0-alpha.1.sql")),
+    ];
+
+    const FIXTURE_SESSION: &str = "6f1c2a9e-0b7d-4c1e-9a51-3e2f8d4b7c10";
+
+    /// Open the way [`Db::open`] does, without migrating.
+    async fn connect(path: &Path) -> (limbo::Database, Connection) {
+        checkpoint(path.to_str().unwrap()).await.unwrap();
+        let database = limbo::Builder::new_local(path.to_str().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let conn = database.connect().unwrap();
+        (database, conn)
+    }
+
+    async fn load_fixture(path: &Path, sql: &str) {
+        let (_database, conn) = connect(path).await;
+        for stmt in sql.split(";\n") {
+            let stmt: String = stmt
+                .lines()
+                .filter(|l| !l.starts_with("--"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !stmt.trim().is_empty() {
+                conn.execute(&stmt, ()).await.unwrap();
+            }
+        }
+    }
+
+    fn backups(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| e.ok()?.file_name().into_string().ok())
+            .filter(|n| n.ends_with(".bak"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fixtures_migrate_to_current_version() {
+        for (name, sql) in FIXTURES {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("ide.db");
+            load_fixture(&path, sql).await;
+
+            {
+                let (_database, conn) = connect(&path).await;
+                let report = migrate(&conn, path.to_str().unwrap()).await.unwrap();
+                assert_eq!(report.from_version, 0, "{name}");
+                assert_eq!(report.to_version, SCHEMA_VERSION, "{name}");
+                assert!(report.backup.as_ref().is_some_and(|b| b.exists()), "{name}");
+                assert_eq!(current_version(&conn).await.unwrap(), SCHEMA_VERSION);
+                for table in ["messages", "api_requests"] {
+                    let columns = table_columns(&conn, table).await.unwrap();
+                    for m in MIGRATIONS {
+                        for step in m.steps {
+                            if let Step::AddColumn {
+                                table: t, column, ..
+                            } = step
+                                && *t == table
+                            {
+                                assert!(columns.iter().any(|c| c == column), "{name}: {column}");
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Old rows are intact and the new columns are usable.
+            let db = Db::open(path.to_str().unwrap()).await.unwrap();
+            let sid = uuid::Uuid::parse_str(FIXTURE_SESSION).unwrap();
+            assert_eq!(db.get_sessions().await.unwrap().len(), 1, "{name}");
+            let messages = db.get_messages_by_session(&sid).await.unwrap();
+            assert_eq!(messages.len(), 2, "{name}");
+            assert_eq!(messages[1].tokens, Some(812));
+            assert_eq!(messages[1].model, None);
+            assert_eq!(db.get_all_api_requests().await.unwrap().len(), 1, "{name}");
+
+            let mut m = messages[1].clone();
+            m.id = uuid::Uuid::new_v4();
+            m.model = Some("claude-opus-4-6".into());
+            m.stop_reason = Some("end_turn".into());
+            m.cache_read_input_tokens = Some(40_000);
+            m.content_blocks_json = Some("[]".into());
+            db.insert_message(&m).await.unwrap();
+            let stored = db.get_messages_by_session(&sid).await.unwrap();
+            let stored = stored.iter().find(|s| s.id == m.id).unwrap();
+            assert_eq!(stored.model, m.model);
+            assert_eq!(stored.stop_reason, m.stop_reason);
+            assert_eq!(stored.cache_read_input_tokens, Some(40_000));
+            assert_eq!(stored.content_blocks_json.as_deref(), Some("[]"));
+            drop(db);
+
+            // Already current: nothing to do, no second backup.
+            let before = backups(dir.path());
+            let (_database, conn) = connect(&path).await;
+            let report = migrate(&conn, path.to_str().unwrap()).await.unwrap();
+            assert_eq!(report.from_version, SCHEMA_VERSION);
+            assert!(report.backup.is_none());
+            assert_eq!(backups(dir.path()), before, "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn new_database_needs_no_backup_and_newer_schema_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ide.db");
+        let (_database, conn) = connect(&path).await;
+        let report = migrate(&conn, path.to_str().unwrap()).await.unwrap();
+        assert_eq!(
+            (report.from_version, report.to_version),
+            (0, SCHEMA_VERSION)
+        );
+        assert!(report.backup.is_none());
+
+        conn.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'future', 0)",
+            limbo::params!(SCHEMA_VERSION as i64 + 1),
+        )
+        .await
+        .unwrap();
+        let err = migrate(&conn, path.to_str().unwrap()).await.unwrap_err();
+        assert!(err.to_string().contains("newer than this build"), "{err}");
+    }
 }
@@ -1,6 +1,6 @@
 pub mod scanner;
 
 pub use scanner::{
-    SessionInfo, SessionScanner, SubagentInfo, extract_codex_uuid, extract_first_timestamp,
-    extract_gemini_uuid, parse_iso_to_epoch_secs,
+    SessionInfo, SessionScanner, SubagentInfo, aider_session_id, extract_codex_uuid,
+    extract_first_timestamp, extract_gemini_uuid, opencode_session_uuid, parse_iso_to_epoch_secs,
 };
@@ -11,6 +11,8 @@ pub enum CliType {
     Claude,
     Codex,
     Gemini,
+    Aider,
+    OpenCode,
 }
 
 impl CliType {
@@ -19,6 +21,8 @@ impl CliType {
             CliType::Claude => "claude",
             CliType::Codex => "codex",
             CliType::Gemini => "gemini",
+            CliType::Aider => "aider",
+            CliType::OpenCode => "opencode",
         }
     }
 }
@@ -75,46 +79,22 @@ impl SessionScanner {
 
     /// Scan a CLI directory for all session files.
     ///
-    /// Supports three CLI tools:
+    /// Every registered [`TranscriptSource`](crate::parser::TranscriptSource)
+    /// looks for its own layout below `cli_dir`, e.g.:
     /// - **Claude Code**: `{dir}/projects/{project-dir}/{session-uuid}.jsonl`
     /// - **Codex**: `{dir}/sessions/YYYY/MM/DD/rollout-{timestamp}-{uuid}.jsonl`
     /// - **Gemini**: `{dir}/tmp/{hash}/chats/session-{timestamp}-{uuid}.json`
     pub async fn scan(cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
         let mut sessions = Vec::new();
 
-        // Claude Code: {dir}/projects/...
-        let projects_dir = cli_dir.join("projects");
-        if projects_dir.exists() {
-            match Self::scan_claude_projects(&projects_dir).await {
+        for source in crate::parser::registry::all() {
+            match source.discover(cli_dir).await {
+                Ok(s) if s.is_empty() => {}
                 Ok(s) => {
-                    info!(count = s.len(), cli = "claude", "discovered sessions");
+                    info!(count = s.len(), cli = source.name(), "discovered sessions");
                     sessions.extend(s);
                 }
-                Err(e) => warn!(error = %e, "failed to scan Claude projects"),
-            }
-        }
-
-        // Codex: {dir}/sessions/...
-        let codex_sessions_dir = cli_dir.join("sessions");
-        if codex_sessions_dir.exists() {
-            match Self::scan_codex_sessions(&codex_sessions_dir).await {
-                Ok(s) => {
-                    info!(count = s.len(), cli = "codex", "discovered sessions");
-                    sessions.extend(s);
-                }
-                Err(e) => warn!(error = %e, "failed to scan Codex sessions"),
-            }
-        }
-
-        // Gemini: {dir}/tmp/*/chats/...
-        let gemini_tmp_dir = cli_dir.join("tmp");
-        if gemini_tmp_dir.exists() {
-            match Self::scan_gemini_sessions(&gemini_tmp_dir).await {
-                Ok(s) => {
-                    info!(count = s.len(), cli = "gemini", "discovered sessions");
-                    sessions.extend(s);
-                }
-                Err(e) => warn!(error = %e, "failed to scan Gemini sessions"),
+                Err(e) => warn!(error = %e, cli = source.name(), "failed to scan sessions"),
             }
         }
 
@@ -126,7 +106,9 @@ impl SessionScanner {
     }
 
     /// Scan Claude Code projects directory.
-    async fn scan_claude_projects(projects_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+    pub(crate) async fn scan_claude_projects(
+        projects_dir: &Path,
+    ) -> anyhow::Result<Vec<SessionInfo>> {
         let mut sessions = Vec::new();
         let mut project_entries = tokio::fs::read_dir(projects_dir).await?;
 
@@ -155,7 +137,9 @@ impl SessionScanner {
     }
 
     /// Scan Codex sessions directory (YYYY/MM/DD/rollout-*.jsonl).
-    async fn scan_codex_sessions(sessions_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+    pub(crate) async fn scan_codex_sessions(
+        sessions_dir: &Path,
+    ) -> anyhow::Result<Vec<SessionInfo>> {
         let mut sessions = Vec::new();
         Self::scan_codex_recursive(sessions_dir, &mut sessions).await?;
         Ok(sessions)
@@ -229,7 +213,7 @@ impl SessionScanner {
     }
 
     /// Scan Gemini tmp directory for chat session files.
-    async fn scan_gemini_sessions(tmp_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+    pub(crate) async fn scan_gemini_sessions(tmp_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
         let mut sessions = Vec::new();
         let mut hash_dirs = tokio::fs::read_dir(tmp_dir).await?;
 
@@ -292,6 +276,120 @@ impl SessionScanner {
         Ok(sessions)
     }
 
+    /// Scan for Aider chat histories (`.aider.chat.history.md`) in `root` and
+    /// up to [`AIDER_SCAN_DEPTH`] directory levels below it.
+    ///
+    /// Aider writes its history into the project root, so the server runs
+    /// this over the workspace roots (`NOAIDE_WORKSPACE_ROOTS`) as well as
+    /// the watch paths.
+    pub async fn scan_aider_histories(root: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        use crate::parser::aider::AIDER_HISTORY_FILE;
+
+        let mut sessions = Vec::new();
+        let mut stack = vec![(root.to_path_buf(), 0usize)];
+        while let Some((dir, depth)) = stack.pop() {
+            let history = dir.join(AIDER_HISTORY_FILE);
+            if let Ok(metadata) = tokio::fs::metadata(&history).await
+                && metadata.is_file()
+            {
+                let last_modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                sessions.push(SessionInfo {
+                    id: aider_session_id(&history),
+                    project_path: Some(dir.clone()),
+                    last_modified,
+                    size_bytes: metadata.len(),
+                    cli_type: CliType::Aider,
+                    message_count_hint: estimate_line_count(&history).await,
+                    started_at: extract_aider_start(&history).await,
+                    last_activity_at: last_modified
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs() as i64)
+                        .unwrap_or(0),
+                    jsonl_path: history,
+                });
+            }
+
+            if depth >= AIDER_SCAN_DEPTH {
+                continue;
+            }
+            let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') || name == "node_modules" || name == "target" {
+                    continue;
+                }
+                if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+                    stack.push((entry.path(), depth + 1));
+                }
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Scan OpenCode session documents (`storage/session/{project}/{id}.json`).
+    pub(crate) async fn scan_opencode_sessions(
+        session_dir: &Path,
+    ) -> anyhow::Result<Vec<SessionInfo>> {
+        use crate::parser::opencode::OpenCodeSessionInfo;
+
+        let mut sessions = Vec::new();
+        let mut projects = tokio::fs::read_dir(session_dir).await?;
+        while let Some(project) = projects.next_entry().await? {
+            if !project.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(project.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let Ok(metadata) = entry.metadata().await else {
+                    continue;
+                };
+                let info: OpenCodeSessionInfo = match tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|c| serde_json::from_str(&c).map_err(Into::into))
+                {
+                    Ok(info) => info,
+                    Err(e) => {
+                        warn!(file = %path.display(), error = %e, "failed to read OpenCode session");
+                        continue;
+                    }
+                };
+
+                // storage/message/{id}/ holds one file per message.
+                let message_dir = session_dir
+                    .parent()
+                    .map(|storage| storage.join("message").join(&info.id));
+                let mut message_count = 0;
+                if let Some(dir) = message_dir
+                    && let Ok(mut messages) = tokio::fs::read_dir(dir).await
+                {
+                    while let Ok(Some(_)) = messages.next_entry().await {
+                        message_count += 1;
+                    }
+                }
+
+                sessions.push(SessionInfo {
+                    id: opencode_session_uuid(&info.id),
+                    jsonl_path: path,
+                    project_path: info.directory.map(PathBuf::from),
+                    last_modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    size_bytes: metadata.len(),
+                    cli_type: CliType::OpenCode,
+                    message_count_hint: message_count,
+                    started_at: info.time.created.map_or(0, |ms| ms / 1000),
+                    last_activity_at: info.time.updated.map_or(0, |ms| ms / 1000),
+                });
+            }
+        }
+        Ok(sessions)
+    }
+
     async fn scan_project_dir(
         project_dir: &Path,
         project_path: &str,
@@ -400,6 +498,9 @@ impl SessionScanner {
     }
 }
 
+/// How many directory levels below a watch path are searched for Aider histories.
+const AIDER_SCAN_DEPTH: usize = 2;
+
 /// Estimate message count from file size (no I/O beyond stat).
 ///
 /// Average JSONL line is ~1500 bytes. Using file size avoids reading
@@ -642,6 +743,37 @@ pub fn extract_gemini_uuid(filename: &str) -> Option<String> {
     }
 }
 
+/// Session id for an Aider history file.
+///
+/// Aider has no session ids; the UUID is derived from the file path so the
+/// same project always maps to the same session.
+pub fn aider_session_id(history_path: &Path) -> String {
+    crate::parser::stable_uuid("aider", &history_path.to_string_lossy()).to_string()
+}
+
+/// Session UUID for an OpenCode session id (`ses_…`).
+pub fn opencode_session_uuid(session_id: &str) -> String {
+    crate::parser::stable_uuid("opencode", session_id).to_string()
+}
+
+/// Read the first `# aider chat started at …` header as epoch seconds.
+async fn extract_aider_start(path: &Path) -> i64 {
+    use tokio::io::AsyncReadExt;
+    let Ok(mut file) = tokio::fs::File::open(path).await else {
+        return 0;
+    };
+    let mut buf = vec![0u8; 4096];
+    let Ok(n) = file.read(&mut buf).await else {
+        return 0;
+    };
+    let text = String::from_utf8_lossy(&buf[..n]);
+    text.lines()
+        .find_map(|l| l.strip_prefix("# aider chat started at "))
+        .and_then(|ts| crate::parser::aider::aider_timestamp(ts.trim()))
+        .and_then(|ts| parse_iso_to_epoch_secs(&ts))
+        .unwrap_or(0)
+}
+
 /// Decode a Claude Code project directory name back to a filesystem path.
 ///
 /// Claude Code encodes paths by replacing `/` with `-` and prepending `-`.
@@ -1,8 +1,9 @@
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 // === Session ===
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionComponent {
     pub id: Uuid,
     pub path: String,
@@ -14,7 +15,7 @@ pub struct SessionComponent {
     pub cost: Option<f64>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SessionStatus {
     #[default]
     Active,
@@ -25,7 +26,7 @@ pub enum SessionStatus {
 
 // === Message ===
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageComponent {
     pub id: Uuid,
     pub session_id: Uuid,
@@ -47,7 +48,32 @@ pub struct MessageComponent {
     pub cache_read_input_tokens: Option<u32>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg(test)]
+impl MessageComponent {
+    /// A plain text message without model or token data; tests override
+    /// the rest with struct update syntax.
+    pub(crate) fn test(session_id: Uuid, role: MessageRole, content: &str, timestamp: i64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            session_id,
+            role,
+            content: content.to_string(),
+            content_blocks_json: None,
+            timestamp,
+            tokens: None,
+            hidden: false,
+            message_type: MessageType::Text,
+            model: None,
+            stop_reason: None,
+            input_tokens: None,
+            output_tokens: None,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageRole {
     #[default]
     User,
@@ -57,7 +83,7 @@ pub enum MessageRole {
     Meta,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     #[default]
     Text,
@@ -76,11 +102,160 @@ pub enum MessageType {
     CompactBoundary,
 }
 
+impl MessageRole {
+    /// Name used by the HTTP API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Assistant => "assistant",
+            Self::System => "system",
+            Self::Meta => "meta",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Self::User, Self::Assistant, Self::System, Self::Meta]
+            .into_iter()
+            .find(|r| r.as_str() == s)
+    }
+}
+
+impl MessageType {
+    pub const ALL: [Self; 10] = [
+        Self::Text,
+        Self::ToolUse,
+        Self::ToolResult,
+        Self::Thinking,
+        Self::SystemReminder,
+        Self::Error,
+        Self::Progress,
+        Self::Summary,
+        Self::FileSnapshot,
+        Self::CompactBoundary,
+    ];
+
+    /// Name used by the HTTP API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::ToolUse => "tool_use",
+            Self::ToolResult => "tool_result",
+            Self::Thinking => "thinking",
+            Self::SystemReminder => "system-reminder",
+            Self::Error => "error",
+            Self::Progress => "progress",
+            Self::Summary => "summary",
+            Self::FileSnapshot => "file-history-snapshot",
+            Self::CompactBoundary => "compact_boundary",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == s)
+    }
+}
+
+// === Tool Invocation ===
+
+/// One tool call paired with its result by `tool_use_id`.
+///
+/// Built from `ToolUse`/`ToolResult` content blocks (Claude `toolu_…` ids,
+/// Codex `call_id`s, Gemini tool call ids). The result fields stay `None`
+/// while the call is still running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocationComponent {
+    pub session_id: Uuid,
+    pub tool_use_id: String,
+    /// Message that issued the call.
+    pub message_id: Uuid,
+    pub name: String,
+    pub kind: ToolKind,
+    /// Parsed tool input; long string values are truncated.
+    pub input: serde_json::Value,
+    /// Paths named in the input (edit targets, files read, patch headers).
+    pub files: Vec<String>,
+    /// Epoch milliseconds of the tool_use message.
+    pub started_at_ms: i64,
+    /// Epoch milliseconds of the tool_result message.
+    pub ended_at_ms: Option<i64>,
+    pub duration_ms: Option<i64>,
+    /// Size of the result content in bytes.
+    pub output_size: Option<u64>,
+    pub is_error: bool,
+}
+
+/// Coarse tool category, normalized across CLIs (Claude `Bash`, Codex
+/// `shell`, Gemini `run_shell_command` are all [`ToolKind::Shell`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToolKind {
+    Shell,
+    Edit,
+    Read,
+    Search,
+    Web,
+    /// Sub-agent spawns (`Task`).
+    Agent,
+    #[default]
+    Other,
+}
+
+impl ToolKind {
+    /// Classify a tool by name (case-insensitive).
+    pub fn from_tool_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "bash" | "shell" | "local_shell" | "exec_command" | "run_shell_command"
+            | "container.exec" => Self::Shell,
+            "edit" | "multiedit" | "write" | "notebookedit" | "apply_patch" | "replace"
+            | "write_file" | "patch" => Self::Edit,
+            "read" | "read_file" | "read_many_files" | "notebookread" | "view" => Self::Read,
+            "grep"
+            | "glob"
+            | "ls"
+            | "list"
+            | "list_directory"
+            | "search_file_content"
+            | "codebase_search"
+            | "find" => Self::Search,
+            "webfetch" | "websearch" | "web_fetch" | "web_search" | "google_web_search" => {
+                Self::Web
+            }
+            "task" | "agent" => Self::Agent,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Shell => "shell",
+            Self::Edit => "edit",
+            Self::Read => "read",
+            Self::Search => "search",
+            Self::Web => "web",
+            Self::Agent => "agent",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            Self::Shell,
+            Self::Edit,
+            Self::Read,
+            Self::Search,
+            Self::Web,
+            Self::Agent,
+            Self::Other,
+        ]
+        .into_iter()
+        .find(|k| k.as_str() == s)
+    }
+}
+
 // === Message Cache Meta ===
 
 /// Tracks caching state for a session's JSONL file.
 /// Used by the cache layer to know when to re-parse incrementally.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheMetaComponent {
     pub session_id: Uuid,
     /// Byte offset up to which messages have been cached.
@@ -163,3 +338,26 @@ pub struct ApiRequestComponent {
     /// Traffic category for CONNECT MITM requests (e.g. "telemetry", "auth").
     pub traffic_category: Option<String>,
 }
+
+#[cfg(test)]
+impl ApiRequestComponent {
+    /// A successful `POST /v1/messages` with tiny bodies.
+    pub(crate) fn test(session_id: Uuid, timestamp: i64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            session_id,
+            method: "POST".into(),
+            url: "https://api.anthropic.com/v1/messages".into(),
+            request_body: Some("{}".into()),
+            response_body: Some("ok".into()),
+            status_code: Some(200),
+            latency_ms: Some(10),
+            timestamp,
+            request_headers: None,
+            response_headers: None,
+            request_size: Some(2),
+            response_size: Some(2),
+            traffic_category: Some("api".into()),
+        }
+    }
+}
@@ -1,7 +1,13 @@
 pub mod components;
+pub mod query;
+pub mod snapshot;
 pub mod systems;
 pub mod world;
 
 pub use components::*;
-pub use systems::{SessionStats, collect_session_stats, track_session_status};
+pub use query::{MessageFacets, MessagePage, MessageQuery, MessageQueryError};
+pub use systems::{
+    SessionStats, ToolQuery, ToolSort, collect_session_stats, find_tool_invocations,
+    track_session_status,
+};
 pub use world::{EcsWorld, SharedEcsWorld};
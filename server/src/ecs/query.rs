@@ -0,0 +1,652 @@
+//! Filtered message queries over the ECS cache.
+//!
+//! Every warm session keeps a [`SessionMessageIndex`] next to its message
+//! entities: one compact row per message (role, type, interned model, error
+//! flag, timestamp) plus posting lists of positions per tool name and for
+//! errors. Queries walk the rows (or a posting list when a tool or
+//! `errors_only` narrows them) without touching the components, except for
+//! the substring filter and the returned page.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::components::{MessageComponent, MessageRole, MessageType};
+
+/// Filters for [`EcsWorld::query_messages`](super::EcsWorld::query_messages).
+/// Empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct MessageQuery {
+    pub roles: Vec<MessageRole>,
+    pub types: Vec<MessageType>,
+    /// Tool name, case-insensitive. Matches both the call and its result.
+    pub tool: Option<String>,
+    /// Case-insensitive substring of the model name.
+    pub model: Option<String>,
+    /// Epoch seconds, inclusive.
+    pub after: Option<i64>,
+    /// Epoch seconds, exclusive.
+    pub before: Option<i64>,
+    /// Only `Error` messages and failed tool results.
+    pub errors_only: bool,
+    pub include_hidden: bool,
+    /// Include [`MessageRole::Meta`] entries (progress, snapshots, ...).
+    pub include_meta: bool,
+    /// Case-insensitive substring of the message content.
+    pub text: Option<String>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub newest_first: bool,
+    /// Page size; 0 is treated as 1.
+    pub limit: usize,
+    /// Also count matches per facet value.
+    pub facets: bool,
+}
+
+/// One page of matching messages, in query order.
+#[derive(Debug, Clone)]
+pub struct MessagePage {
+    pub messages: Vec<MessageComponent>,
+    /// Matches across all pages.
+    pub total: usize,
+    /// Cursor for the following page; `None` on the last one.
+    pub next_cursor: Option<String>,
+    pub facets: Option<MessageFacets>,
+}
+
+/// Match counts per facet value. Each facet is counted with every other
+/// filter applied but not its own, so the counts show what selecting a
+/// different value would return.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageFacets {
+    pub roles: BTreeMap<String, usize>,
+    pub types: BTreeMap<String, usize>,
+    pub models: BTreeMap<String, usize>,
+    pub tools: BTreeMap<String, usize>,
+    pub errors: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MessageQueryError {
+    #[error("cursor does not point into this session's messages")]
+    InvalidCursor,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    id: Uuid,
+    timestamp: i64,
+    role: MessageRole,
+    message_type: MessageType,
+    model: Option<u32>,
+    hidden: bool,
+    error: bool,
+}
+
+/// Secondary index over one session's cached messages, by position.
+#[derive(Debug, Default)]
+pub(crate) struct SessionMessageIndex {
+    rows: Vec<Row>,
+    models: Vec<String>,
+    model_ids: HashMap<String, u32>,
+    /// Display name per tool id (first spelling seen).
+    tools: Vec<String>,
+    /// Lowercased tool name → tool id.
+    tool_ids: HashMap<String, u32>,
+    /// Positions per tool id, ascending.
+    tool_rows: Vec<Vec<u32>>,
+    error_rows: Vec<u32>,
+    /// tool_use_id → tool id, to attribute results to their call.
+    tool_use_names: HashMap<String, u32>,
+}
+
+/// The parts of a content block the index needs.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum BlockProbe {
+    #[serde(rename = "tool_use")]
+    ToolUse { id: String, name: String },
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        is_error: Option<bool>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Facet {
+    Role,
+    Type,
+    Model,
+    Tool,
+    Error,
+}
+
+/// A query resolved against one index's interned names.
+struct Compiled {
+    roles: Option<Vec<MessageRole>>,
+    types: Option<Vec<MessageType>>,
+    /// `Some(None)`: the tool never occurs in this session.
+    tool: Option<Option<u32>>,
+    /// Per model id: does it match the model filter.
+    models: Option<Vec<bool>>,
+    after: Option<i64>,
+    before: Option<i64>,
+    errors_only: bool,
+    include_hidden: bool,
+    include_meta: bool,
+}
+
+impl SessionMessageIndex {
+    pub(crate) fn push(&mut self, msg: &MessageComponent) {
+        let pos = self.rows.len() as u32;
+        let mut error = msg.message_type == MessageType::Error;
+
+        if let Some(json) = msg.content_blocks_json.as_deref()
+            && json.contains("\"tool_")
+            && let Ok(blocks) = serde_json::from_str::<Vec<BlockProbe>>(json)
+        {
+            for block in blocks {
+                match block {
+                    BlockProbe::ToolUse { id, name } => {
+                        let tool = self.intern_tool(&name);
+                        self.tool_use_names.insert(id, tool);
+                        self.add_tool_row(tool, pos);
+                    }
+                    BlockProbe::ToolResult {
+                        tool_use_id,
+                        is_error,
+                    } => {
+                        if let Some(&tool) = self.tool_use_names.get(&tool_use_id) {
+                            self.add_tool_row(tool, pos);
+                        }
+                        error |= is_error == Some(true);
+                    }
+                    BlockProbe::Other => {}
+                }
+            }
+        }
+
+        let model = msg.model.as_deref().map(|m| match self.model_ids.get(m) {
+            Some(&id) => id,
+            None => {
+                let id = self.models.len() as u32;
+                self.models.push(m.to_string());
+                self.model_ids.insert(m.to_string(), id);
+                id
+            }
+        });
+        if error {
+            self.error_rows.push(pos);
+        }
+        self.rows.push(Row {
+            id: msg.id,
+            timestamp: msg.timestamp,
+            role: msg.role,
+            message_type: msg.message_type,
+            model,
+            hidden: msg.hidden,
+            error,
+        });
+    }
+
+    fn intern_tool(&mut self, name: &str) -> u32 {
+        let key = name.to_lowercase();
+        if let Some(&id) = self.tool_ids.get(&key) {
+            return id;
+        }
+        let id = self.tools.len() as u32;
+        self.tools.push(name.to_string());
+        self.tool_rows.push(Vec::new());
+        self.tool_ids.insert(key, id);
+        id
+    }
+
+    fn add_tool_row(&mut self, tool: u32, pos: u32) {
+        let rows = &mut self.tool_rows[tool as usize];
+        if rows.last() != Some(&pos) {
+            rows.push(pos);
+        }
+    }
+
+    /// Run `query`. `text_matches(pos)` is asked only for rows that pass
+    /// every other filter, and only when `query.text` is set. Returns the
+    /// page as positions into the session's messages.
+    pub(crate) fn run(
+        &self,
+        query: &MessageQuery,
+        mut text_matches: impl FnMut(usize) -> bool,
+    ) -> Result<IndexPage, MessageQueryError> {
+        let compiled = self.compile(query);
+        let cursor = match query.cursor.as_deref() {
+            Some(cursor) => Some(self.decode_cursor(cursor)?),
+            None => None,
+        };
+
+        let mut text_cache: HashMap<u32, bool> = HashMap::new();
+        let mut text = |pos: u32| {
+            query.text.is_none()
+                || *text_cache
+                    .entry(pos)
+                    .or_insert_with(|| text_matches(pos as usize))
+        };
+
+        let matched: Vec<u32> = self
+            .candidates(&compiled)
+            .filter(|&pos| self.passes(pos, &compiled, None) && text(pos))
+            .collect();
+
+        let limit = query.limit.max(1);
+        let (positions, more) = if query.newest_first {
+            let end = cursor.map_or(matched.len(), |c| matched.partition_point(|&p| p < c));
+            let start = end.saturating_sub(limit);
+            (
+                matched[start..end].iter().rev().copied().collect(),
+                start > 0,
+            )
+        } else {
+            let start = cursor.map_or(0, |c| matched.partition_point(|&p| p <= c));
+            let end = (start + limit).min(matched.len());
+            (matched[start..end].to_vec(), end < matched.len())
+        };
+        let next_cursor = positions
+            .last()
+            .filter(|_| more)
+            .map(|&pos| self.encode_cursor(pos));
+
+        let facets = query
+            .facets
+            .then(|| self.count_facets(&compiled, &mut text));
+
+        Ok(IndexPage {
+            positions: positions.into_iter().map(|p| p as usize).collect(),
+            total: matched.len(),
+            next_cursor,
+            facets,
+        })
+    }
+
+    fn compile(&self, query: &MessageQuery) -> Compiled {
+        Compiled {
+            roles: (!query.roles.is_empty()).then(|| query.roles.clone()),
+            types: (!query.types.is_empty()).then(|| query.types.clone()),
+            tool: query
+                .tool
+                .as_deref()
+                .map(|t| self.tool_ids.get(&t.to_lowercase()).copied()),
+            models: query.model.as_deref().map(|needle| {
+                let needle = needle.to_lowercase();
+                self.models
+                    .iter()
+                    .map(|m| m.to_lowercase().contains(&needle))
+                    .collect()
+            }),
+            after: query.after,
+            before: query.before,
+            errors_only: query.errors_only,
+            include_hidden: query.include_hidden,
+            include_meta: query.include_meta,
+        }
+    }
+
+    /// Positions worth checking: a posting list when one narrows the query.
+    fn candidates<'a>(&'a self, c: &Compiled) -> Box<dyn Iterator<Item = u32> + 'a> {
+        match c.tool {
+            Some(Some(tool)) => Box::new(self.tool_rows[tool as usize].iter().copied()),
+            Some(None) => Box::new(std::iter::empty()),
+            None if c.errors_only => Box::new(self.error_rows.iter().copied()),
+            None => Box::new(0..self.rows.len() as u32),
+        }
+    }
+
+    /// Every filter except the text one and `skip`.
+    fn passes(&self, pos: u32, c: &Compiled, skip: Option<Facet>) -> bool {
+        let row = &self.rows[pos as usize];
+        if (row.hidden && !c.include_hidden)
+            || (row.role == MessageRole::Meta && !c.include_meta)
+            || c.after.is_some_and(|t| row.timestamp < t)
+            || c.before.is_some_and(|t| row.timestamp >= t)
+        {
+            return false;
+        }
+        let check = |facet: Facet| skip != Some(facet);
+        if check(Facet::Role) && c.roles.as_ref().is_some_and(|r| !r.contains(&row.role)) {
+            return false;
+        }
+        if check(Facet::Type)
+            && c.types
+                .as_ref()
+                .is_some_and(|t| !t.contains(&row.message_type))
+        {
+            return false;
+        }
+        if check(Facet::Model)
+            && let Some(models) = &c.models
+            && !row.model.is_some_and(|m| models[m as usize])
+        {
+            return false;
+        }
+        if check(Facet::Error) && c.errors_only && !row.error {
+            return false;
+        }
+        if check(Facet::Tool)
+            && let Some(tool) = c.tool
+            && tool.is_none_or(|t| self.tool_rows[t as usize].binary_search(&pos).is_err())
+        {
+            return false;
+        }
+        true
+    }
+
+    fn count_facets(&self, c: &Compiled, text: &mut impl FnMut(u32) -> bool) -> MessageFacets {
+        let mut roles = [0usize; 4];
+        let mut types = [0usize; MessageType::ALL.len()];
+        let mut models = vec![0usize; self.models.len()];
+        let mut tools = vec![0usize; self.tools.len()];
+        let mut errors = 0;
+
+        for pos in 0..self.rows.len() as u32 {
+            let row = &self.rows[pos as usize];
+            let mut counts = |facet: Facet| self.passes(pos, c, Some(facet)) && text(pos);
+            if counts(Facet::Role) {
+                roles[row.role as usize] += 1;
+            }
+            if counts(Facet::Type) {
+                types[row.message_type as usize] += 1;
+            }
+            if let Some(model) = row.model
+                && counts(Facet::Model)
+            {
+                models[model as usize] += 1;
+            }
+            if row.error && counts(Facet::Error) {
+                errors += 1;
+            }
+        }
+        for (tool, rows) in self.tool_rows.iter().enumerate() {
+            tools[tool] = rows
+                .iter()
+                .filter(|&&pos| self.passes(pos, c, Some(Facet::Tool)) && text(pos))
+                .count();
+        }
+
+        let named = |names: Vec<(String, usize)>| -> BTreeMap<String, usize> {
+            names.into_iter().filter(|(_, n)| *n > 0).collect()
+        };
+        MessageFacets {
+            roles: named(
+                [
+                    MessageRole::User,
+                    MessageRole::Assistant,
+                    MessageRole::System,
+                    MessageRole::Meta,
+                ]
+                .into_iter()
+                .map(|r| (r.as_str().to_string(), roles[r as usize]))
+                .collect(),
+            ),
+            types: named(
+                MessageType::ALL
+                    .into_iter()
+                    .map(|t| (t.as_str().to_string(), types[t as usize]))
+                    .collect(),
+            ),
+            models: named(self.models.iter().cloned().zip(models).collect()),
+            tools: named(self.tools.iter().cloned().zip(tools).collect()),
+            errors,
+        }
+    }
+
+    /// `<position>.<message id>`: the id catches cursors that outlived a
+    /// cache rebuild.
+    fn encode_cursor(&self, pos: u32) -> String {
+        format!("{pos}.{}", self.rows[pos as usize].id.simple())
+    }
+
+    fn decode_cursor(&self, cursor: &str) -> Result<u32, MessageQueryError> {
+        let (pos, id) = cursor
+            .split_once('.')
+            .ok_or(MessageQueryError::InvalidCursor)?;
+        let pos: u32 = pos.parse().map_err(|_| MessageQueryError::InvalidCursor)?;
+        let id = Uuid::parse_str(id).map_err(|_| MessageQueryError::InvalidCursor)?;
+        match self.rows.get(pos as usize) {
+            Some(row) if row.id == id => Ok(pos),
+            _ => Err(MessageQueryError::InvalidCursor),
+        }
+    }
+}
+
+/// [`SessionMessageIndex::run`] result, before the messages are fetched.
+pub(crate) struct IndexPage {
+    pub positions: Vec<usize>,
+    pub total: usize,
+    pub next_cursor: Option<String>,
+    pub facets: Option<MessageFacets>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::EcsWorld;
+
+    fn message(
+        session_id: Uuid,
+        ts: i64,
+        role: MessageRole,
+        message_type: MessageType,
+        content: &str,
+        blocks: Option<serde_json::Value>,
+    ) -> MessageComponent {
+        MessageComponent {
+            content_blocks_json: blocks.map(|b| b.to_string()),
+            message_type,
+            model: (role == MessageRole::Assistant).then(|| "claude-opus-4-6".to_string()),
+            ..MessageComponent::test(session_id, role, content, ts)
+        }
+    }
+
+    fn world() -> (EcsWorld, Uuid) {
+        use MessageRole::*;
+        use MessageType::*;
+        let sid = Uuid::new_v4();
+        let mut world = EcsWorld::new();
+        let tool_use = |id: &str, name: &str| serde_json::json!([{"type": "tool_use", "id": id, "name": name, "input": {}}]);
+        let tool_result = |id: &str, is_error: bool| serde_json::json!([{"type": "tool_result", "tool_use_id": id, "content": "x", "is_error": is_error}]);
+        let messages = [
+            message(sid, 100, User, Text, "Fix main.rs", None),
+            message(
+                sid,
+                110,
+                Assistant,
+                ToolUse,
+                "",
+                Some(tool_use("t1", "Bash")),
+            ),
+            message(
+                sid,
+                120,
+                User,
+                ToolResult,
+                "boom",
+                Some(tool_result("t1", true)),
+            ),
+            message(
+                sid,
+                130,
+                Assistant,
+                ToolUse,
+                "",
+                Some(tool_use("t2", "Read")),
+            ),
+            message(
+                sid,
+                140,
+                User,
+                ToolResult,
+                "fn main",
+                Some(tool_result("t2", false)),
+            ),
+            message(sid, 150, Assistant, Text, "Fixed MAIN.rs", None),
+            message(sid, 160, Meta, Progress, "hook", None),
+            message(sid, 170, System, Error, "API error", None),
+        ];
+        for msg in messages {
+            world.spawn_message(msg);
+        }
+        let mut hidden = message(sid, 180, User, SystemReminder, "main", None);
+        hidden.hidden = true;
+        world.spawn_message(hidden);
+        (world, sid)
+    }
+
+    fn contents(page: &MessagePage) -> Vec<&str> {
+        page.messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_by_tool_errors_text_and_visibility() {
+        let (world, sid) = world();
+        let query = |q: MessageQuery| world.query_messages(sid, &q).unwrap();
+
+        let bash = query(MessageQuery {
+            tool: Some("bash".into()),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(contents(&bash), ["", "boom"]);
+
+        let errors = query(MessageQuery {
+            errors_only: true,
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(contents(&errors), ["boom", "API error"]);
+
+        let text = query(MessageQuery {
+            text: Some("main".into()),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(contents(&text), ["Fix main.rs", "fn main", "Fixed MAIN.rs"]);
+
+        let everything = query(MessageQuery {
+            text: Some("main".into()),
+            include_hidden: true,
+            include_meta: true,
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(everything.total, 4);
+
+        let window = query(MessageQuery {
+            roles: vec![MessageRole::Assistant],
+            model: Some("OPUS".into()),
+            after: Some(110),
+            before: Some(150),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(window.total, 2);
+
+        let unknown_tool = query(MessageQuery {
+            tool: Some("Grep".into()),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(unknown_tool.total, 0);
+    }
+
+    #[test]
+    fn facets_count_each_value_under_the_other_filters() {
+        let (world, sid) = world();
+        let page = world
+            .query_messages(
+                sid,
+                &MessageQuery {
+                    roles: vec![MessageRole::User],
+                    facets: true,
+                    limit: 1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let facets = page.facets.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(facets.roles["user"], 3);
+        assert_eq!(facets.roles["assistant"], 3);
+        assert_eq!(facets.roles["system"], 1);
+        assert!(!facets.roles.contains_key("meta"));
+        assert_eq!(facets.types["tool_result"], 2);
+        assert!(!facets.types.contains_key("tool_use"));
+        assert_eq!(facets.tools["Bash"], 1);
+        assert_eq!(facets.tools["Read"], 1);
+        assert_eq!(facets.errors, 1);
+        assert!(facets.models.is_empty());
+    }
+
+    #[test]
+    fn cursors_walk_both_directions_and_go_stale() {
+        let (mut world, sid) = world();
+        let mut seen = Vec::new();
+        let mut query = MessageQuery {
+            newest_first: true,
+            limit: 2,
+            ..Default::default()
+        };
+        loop {
+            let page = world.query_messages(sid, &query).unwrap();
+            assert_eq!(page.total, 7);
+            seen.extend(page.messages.iter().map(|m| m.timestamp));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, [170, 150, 140, 130, 120, 110, 100]);
+
+        let first = world
+            .query_messages(
+                sid,
+                &MessageQuery {
+                    newest_first: false,
+                    limit: 3,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let next = world
+            .query_messages(
+                sid,
+                &MessageQuery {
+                    newest_first: false,
+                    limit: 3,
+                    cursor: first.next_cursor.clone(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            next.messages
+                .iter()
+                .map(|m| m.timestamp)
+                .collect::<Vec<_>>(),
+            [130, 140, 150]
+        );
+
+        world.invalidate_cache(sid);
+        let stale = world.query_messages(
+            sid,
+            &MessageQuery {
+                cursor: first.next_cursor,
+                limit: 3,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(stale, Err(MessageQueryError::InvalidCursor)));
+    }
+}
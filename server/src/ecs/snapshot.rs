@@ -0,0 +1,312 @@
+//! ECS snapshot for warm restarts.
+//!
+//! Sessions, managed-session aliases and the warm message caches (with
+//! their cache meta and tool invocations) are written to one zstd-compressed
+//! JSON file on shutdown and at intervals. On startup the snapshot is merged
+//! into the freshly discovered world: a cache is only reused if its
+//! transcript still has the size and mtime it had when the cache was last
+//! refreshed; otherwise the session is reported as stale for a re-parse.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::components::{
+    CacheMetaComponent, MessageComponent, SessionComponent, SessionStatus, ToolInvocationComponent,
+};
+use super::world::EcsWorld;
+
+/// Bumped whenever a snapshotted component changes shape.
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub version: u32,
+    /// Epoch seconds.
+    pub created_at: i64,
+    pub sessions: Vec<SessionComponent>,
+    /// `(jsonl_id, managed_id)`.
+    pub aliases: Vec<(Uuid, Uuid)>,
+    pub caches: Vec<CachedSession>,
+}
+
+/// One warm message cache and the transcript state it was built from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedSession {
+    pub meta: CacheMetaComponent,
+    pub path: PathBuf,
+    pub mtime_ns: i64,
+    pub messages: Vec<MessageComponent>,
+    pub tool_invocations: Vec<ToolInvocationComponent>,
+}
+
+/// What [`Snapshot::restore`] did.
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    /// Sessions that were merged into discovered ones or re-created.
+    pub sessions: usize,
+    /// Aliases put back: `(jsonl_id, managed_id)`. The JSONL session was
+    /// folded into the managed one, so its path belongs to the managed id.
+    pub aliases: Vec<(Uuid, Uuid)>,
+    /// Caches restored warm, least recently refreshed first.
+    pub warm: Vec<Uuid>,
+    /// Sessions whose transcript changed since their cache was built.
+    pub stale: Vec<(Uuid, PathBuf)>,
+}
+
+impl EcsWorld {
+    /// Capture sessions, aliases and every warm cache whose transcript is
+    /// still the size the cache was built from. `paths` maps session ids to
+    /// transcripts.
+    pub fn snapshot(&self, paths: &HashMap<Uuid, PathBuf>) -> Snapshot {
+        let mut metas: Vec<CacheMetaComponent> = self
+            .query_cache_metas()
+            .into_iter()
+            .filter(|m| m.is_warm)
+            .collect();
+        metas.sort_by_key(|m| m.last_refreshed);
+        let caches = metas
+            .into_iter()
+            .filter_map(|meta| {
+                let sid = meta.session_id;
+                let path = paths
+                    .get(&sid)
+                    .or_else(|| self.reverse_alias(sid).and_then(|j| paths.get(&j)))?;
+                let stat = std::fs::metadata(path).ok()?;
+                // Behind the file: a snapshot would only be re-parsed.
+                if stat.len() != meta.file_size {
+                    return None;
+                }
+                Some(CachedSession {
+                    path: path.clone(),
+                    mtime_ns: mtime_ns(&stat),
+                    messages: self.query_messages_by_session(sid),
+                    tool_invocations: self.query_tool_invocations_by_session(sid),
+                    meta,
+                })
+            })
+            .collect();
+        Snapshot {
+            version: VERSION,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+            sessions: self.query_sessions(),
+            aliases: self.session_aliases(),
+            caches,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Merge into a world that already holds the discovered sessions.
+    ///
+    /// Discovered sessions keep their fresh timestamps and pick up the
+    /// model and cost they had. Sessions that were not discovered are only
+    /// re-created as alias targets (managed sessions), marked idle; the rest
+    /// lost their transcript.
+    pub fn restore(self, world: &mut EcsWorld) -> RestoreReport {
+        let mut report = RestoreReport::default();
+        let alias_targets: std::collections::HashSet<Uuid> =
+            self.aliases.iter().map(|(_, managed)| *managed).collect();
+
+        for session in self.sessions {
+            let merged = world.modify_session(session.id, |s| {
+                s.model = s.model.take().or(session.model.clone());
+                s.cost = s.cost.or(session.cost);
+            });
+            if merged.is_some() {
+                report.sessions += 1;
+            } else if alias_targets.contains(&session.id) {
+                world.spawn_session(SessionComponent {
+                    status: SessionStatus::Idle,
+                    ..session
+                });
+                report.sessions += 1;
+            }
+        }
+
+        for (jsonl_id, managed_id) in self.aliases {
+            if world.query_session_by_id(managed_id).is_none() {
+                continue;
+            }
+            world.add_session_alias(jsonl_id, managed_id);
+            world.despawn_session(jsonl_id);
+            report.aliases.push((jsonl_id, managed_id));
+        }
+
+        for cache in self.caches {
+            let sid = cache.meta.session_id;
+            if world.query_session_by_id(sid).is_none() || world.is_cache_warm(sid) {
+                continue;
+            }
+            let Ok(stat) = std::fs::metadata(&cache.path) else {
+                continue;
+            };
+            if stat.len() != cache.meta.file_size || mtime_ns(&stat) != cache.mtime_ns {
+                report.stale.push((sid, cache.path));
+                continue;
+            }
+            for msg in cache.messages {
+                world.spawn_message(msg);
+            }
+            for inv in cache.tool_invocations {
+                world.upsert_tool_invocation(inv);
+            }
+            world.upsert_cache_meta(cache.meta);
+            report.warm.push(sid);
+        }
+        report
+    }
+}
+
+/// Where snapshots live: `NOAIDE_SNAPSHOT_PATH`, else
+/// `/data/noaide/ecs-snapshot.json.zst`.
+pub fn snapshot_path() -> PathBuf {
+    std::env::var("NOAIDE_SNAPSHOT_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("ecs-snapshot.json.zst"))
+}
+
+/// Write a snapshot atomically. Returns the compressed size.
+pub fn save(snapshot: &Snapshot, path: &Path) -> std::io::Result<u64> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let result = (|| {
+        let mut encoder = zstd::Encoder::new(std::fs::File::create(&tmp)?, 3)?;
+        serde_json::to_writer(&mut encoder, snapshot)?;
+        encoder.finish()?.sync_all()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Read a snapshot; `None` if there is none or it is from another version.
+pub fn load(path: &Path) -> anyhow::Result<Option<Snapshot>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let snapshot: Snapshot = serde_json::from_reader(zstd::Decoder::new(file)?)?;
+    Ok((snapshot.version == VERSION).then_some(snapshot))
+}
+
+fn mtime_ns(stat: &std::fs::Metadata) -> i64 {
+    stat.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::discovery::scanner::CliType;
+
+    fn session(id: Uuid) -> SessionComponent {
+        SessionComponent {
+            id,
+            path: "/work/app".into(),
+            status: SessionStatus::Active,
+            model: Some("claude-opus-4-6".into()),
+            started_at: 1,
+            last_activity_at: 2,
+            cost: Some(1.5),
+        }
+    }
+
+    #[tokio::test]
+    async fn restores_unchanged_caches_and_flags_changed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let line = |n: u32| {
+            serde_json::json!({
+                "type": "user",
+                "uuid": format!("00000000-0000-4000-8000-{n:012}"),
+                "timestamp": "2026-03-01T10:00:00.000Z",
+                "message": {"role": "user", "content": format!("hello {n}")},
+            })
+            .to_string()
+                + "\n"
+        };
+        let (kept, changed, managed, jsonl) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let kept_path = dir.path().join("kept.jsonl");
+        let changed_path = dir.path().join("changed.jsonl");
+        std::fs::write(&kept_path, line(1) + &line(2)).unwrap();
+        std::fs::write(&changed_path, line(3)).unwrap();
+
+        let mut world = EcsWorld::new();
+        for id in [kept, changed, managed] {
+            world.spawn_session(session(id));
+        }
+        world.add_session_alias(jsonl, managed);
+        crate::cache::refresh(&mut world, kept, &kept_path, CliType::Claude)
+            .await
+            .unwrap();
+        crate::cache::refresh(&mut world, changed, &changed_path, CliType::Claude)
+            .await
+            .unwrap();
+        let paths = HashMap::from([(kept, kept_path.clone()), (changed, changed_path.clone())]);
+
+        let file = dir.path().join("snapshot.json.zst");
+        save(&world.snapshot(&paths), &file).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&changed_path)
+            .unwrap()
+            .write_all(line(4).as_bytes())
+            .unwrap();
+
+        // A restarted server: discovery registers the transcripts' own ids.
+        let mut world = EcsWorld::new();
+        for id in [kept, changed, jsonl] {
+            world.spawn_session(SessionComponent {
+                model: None,
+                cost: None,
+                ..session(id)
+            });
+        }
+        let report = load(&file).unwrap().unwrap().restore(&mut world);
+
+        assert_eq!(report.warm, [kept]);
+        assert_eq!(report.stale, [(changed, changed_path)]);
+        assert_eq!(report.aliases, [(jsonl, managed)]);
+        assert!(world.is_cache_warm(kept));
+        assert_eq!(world.message_count_for_session(kept), 2);
+        assert!(!world.is_cache_warm(changed));
+        assert_eq!(
+            world.query_session_by_id(kept).unwrap().model.as_deref(),
+            Some("claude-opus-4-6")
+        );
+        assert_eq!(
+            world.query_session_by_id(managed).unwrap().status,
+            SessionStatus::Idle
+        );
+        assert!(world.query_session_by_id(jsonl).is_none());
+        assert_eq!(world.resolve_alias(jsonl), managed);
+    }
+
+    #[test]
+    fn missing_snapshot_is_not_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(&dir.path().join("none.zst")).unwrap().is_none());
+    }
+}
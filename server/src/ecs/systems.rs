@@ -1,6 +1,6 @@
 use uuid::Uuid;
 
-use super::components::SessionStatus;
+use super::components::{SessionStatus, ToolInvocationComponent, ToolKind};
 use super::world::EcsWorld;
 
 /// Track session status based on message activity.
@@ -63,6 +63,66 @@ pub fn collect_session_stats(world: &EcsWorld) -> SessionStats {
     }
 }
 
+/// Filter for [`find_tool_invocations`]. Empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ToolQuery {
+    pub session_id: Option<Uuid>,
+    /// Exact tool name, case-insensitive (`Bash`, `shell`).
+    pub name: Option<String>,
+    pub kind: Option<ToolKind>,
+    /// `Some(true)`: only failed calls; `Some(false)`: only successful ones.
+    pub failed: Option<bool>,
+    /// Matches invocations touching this path: an exact match, or a
+    /// relative path matching the end of an absolute one.
+    pub path: Option<String>,
+    pub sort: ToolSort,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolSort {
+    /// Newest calls first.
+    #[default]
+    Recent,
+    /// Longest duration first (unfinished calls excluded).
+    Slowest,
+}
+
+/// Tool invocations matching `query`, across sessions unless scoped.
+pub fn find_tool_invocations(world: &EcsWorld, query: &ToolQuery) -> Vec<ToolInvocationComponent> {
+    let candidates = match query.session_id {
+        Some(sid) => world.query_tool_invocations_by_session(world.resolve_alias(sid)),
+        None => world.query_tool_invocations(),
+    };
+    let path_matches = |inv: &ToolInvocationComponent, path: &str| {
+        let suffix = format!("/{}", path.trim_start_matches("./"));
+        inv.files.iter().any(|f| f == path || f.ends_with(&suffix))
+    };
+
+    let mut found: Vec<ToolInvocationComponent> = candidates
+        .into_iter()
+        .filter(|inv| {
+            query
+                .name
+                .as_ref()
+                .is_none_or(|n| inv.name.eq_ignore_ascii_case(n))
+        })
+        .filter(|inv| query.kind.is_none_or(|k| inv.kind == k))
+        .filter(|inv| query.failed.is_none_or(|f| inv.is_error == f))
+        .filter(|inv| query.path.as_deref().is_none_or(|p| path_matches(inv, p)))
+        .filter(|inv| query.sort != ToolSort::Slowest || inv.duration_ms.is_some())
+        .collect();
+
+    match query.sort {
+        ToolSort::Recent => found.sort_by_key(|inv| std::cmp::Reverse(inv.started_at_ms)),
+        ToolSort::Slowest => found.sort_by_key(|inv| std::cmp::Reverse(inv.duration_ms)),
+    }
+    if query.limit > 0 {
+        found.truncate(query.limit);
+    }
+    found
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,23 +141,7 @@ mod tests {
     }
 
     fn make_message(session_id: Uuid, timestamp: i64) -> MessageComponent {
-        MessageComponent {
-            id: Uuid::new_v4(),
-            session_id,
-            role: MessageRole::User,
-            content: "test".to_string(),
-            content_blocks_json: None,
-            timestamp,
-            tokens: None,
-            hidden: false,
-            message_type: MessageType::Text,
-            model: None,
-            stop_reason: None,
-            input_tokens: None,
-            output_tokens: None,
-            cache_creation_input_tokens: None,
-            cache_read_input_tokens: None,
-        }
+        MessageComponent::test(session_id, MessageRole::User, "test", timestamp)
     }
 
     #[test]
@@ -162,4 +206,62 @@ mod tests {
         assert_eq!(stats.active_sessions, 1);
         assert_eq!(stats.total_messages, 3);
     }
+
+    #[test]
+    fn find_failed_shell_calls_and_slowest() {
+        let mut world = EcsWorld::new();
+        let (s1, s2) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut call = |sid, id: &str, name: &str, file: &str, dur: i64, failed: bool| {
+            world.upsert_tool_invocation(ToolInvocationComponent {
+                session_id: sid,
+                tool_use_id: id.to_string(),
+                message_id: Uuid::new_v4(),
+                name: name.to_string(),
+                kind: ToolKind::from_tool_name(name),
+                input: serde_json::Value::Null,
+                files: vec![file.to_string()],
+                started_at_ms: 1000,
+                ended_at_ms: None,
+                duration_ms: None,
+                output_size: None,
+                is_error: false,
+            });
+            world.complete_tool_invocation(sid, id, 1000 + dur, 0, failed);
+        };
+        call(s1, "a", "Bash", "", 50, true);
+        call(s2, "b", "shell", "", 900, true);
+        call(s2, "c", "Bash", "", 10, false);
+        call(s1, "d", "Edit", "/work/src/lib.rs", 5, false);
+
+        let failed_shell = find_tool_invocations(
+            &world,
+            &ToolQuery {
+                kind: Some(ToolKind::Shell),
+                failed: Some(true),
+                ..Default::default()
+            },
+        );
+        assert_eq!(failed_shell.len(), 2);
+
+        let slowest = find_tool_invocations(
+            &world,
+            &ToolQuery {
+                sort: ToolSort::Slowest,
+                limit: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(slowest[0].tool_use_id, "b");
+
+        let edits = find_tool_invocations(
+            &world,
+            &ToolQuery {
+                kind: Some(ToolKind::Edit),
+                path: Some("src/lib.rs".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].session_id, s1);
+    }
 }
@@ -6,6 +6,7 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use super::components::*;
+use super::query::{MessagePage, MessageQuery, MessageQueryError, SessionMessageIndex};
 
 /// Thread-safe ECS world handle for use with tokio.
 pub type SharedEcsWorld = Arc<RwLock<EcsWorld>>;
@@ -19,10 +20,16 @@ pub struct EcsWorld {
     world: World,
     session_index: HashMap<Uuid, Entity>,
     message_index: HashMap<Uuid, Vec<Entity>>,
+    /// session_id → filter index over the same messages, by position.
+    message_facets: HashMap<Uuid, SessionMessageIndex>,
     file_index: HashMap<Uuid, Vec<Entity>>,
     task_index: HashMap<Uuid, Vec<Entity>>,
     agent_index: HashMap<Uuid, Vec<Entity>>,
     api_request_index: HashMap<Uuid, Vec<Entity>>,
+    /// session_id → tool invocations, in call order.
+    tool_index: HashMap<Uuid, Vec<Entity>>,
+    /// (session_id, tool_use_id) → invocation, for pairing results with calls.
+    tool_call_index: HashMap<(Uuid, String), Entity>,
     cache_meta_index: HashMap<Uuid, Entity>,
     /// Maps JSONL session UUID → managed session UUID.
     /// When a managed session spawns a CLI process, the CLI creates its own
@@ -38,10 +45,13 @@ impl EcsWorld {
             world: World::new(),
             session_index: HashMap::new(),
             message_index: HashMap::new(),
+            message_facets: HashMap::new(),
             file_index: HashMap::new(),
             task_index: HashMap::new(),
             agent_index: HashMap::new(),
             api_request_index: HashMap::new(),
+            tool_index: HashMap::new(),
+            tool_call_index: HashMap::new(),
             cache_meta_index: HashMap::new(),
             session_aliases: HashMap::new(),
         }
@@ -84,10 +94,17 @@ impl EcsWorld {
         entity
     }
 
+    /// Remove a session and its tool invocations, which otherwise outlive
+    /// the session in the cross-session tool queries.
     pub fn despawn_session(&mut self, session_id: Uuid) {
         if let Some(entity) = self.session_index.remove(&session_id) {
             let _ = self.world.despawn(entity);
         }
+        for entity in self.tool_index.remove(&session_id).unwrap_or_default() {
+            let _ = self.world.despawn(entity);
+        }
+        self.tool_call_index
+            .retain(|(sid, _), _| *sid != session_id);
     }
 
     pub fn spawn_message(&mut self, mut msg: MessageComponent) -> Entity {
@@ -95,6 +112,10 @@ impl EcsWorld {
         // to a managed session, redirect it to the managed session.
         let session_id = self.resolve_alias(msg.session_id);
         msg.session_id = session_id;
+        self.message_facets
+            .entry(session_id)
+            .or_default()
+            .push(&msg);
         let entity = self.world.spawn((msg,));
         self.message_index
             .entry(session_id)
@@ -138,6 +159,60 @@ impl EcsWorld {
         entity
     }
 
+    /// Record a tool call. Keyed by `tool_use_id`, so re-parsing a session
+    /// (cache re-warm) replaces the invocation instead of duplicating it.
+    ///
+    /// Unlike messages, invocations survive cache eviction: they are small and
+    /// back the cross-session tool queries.
+    pub fn upsert_tool_invocation(&mut self, mut inv: ToolInvocationComponent) -> Entity {
+        let session_id = self.resolve_alias(inv.session_id);
+        inv.session_id = session_id;
+        let key = (session_id, inv.tool_use_id.clone());
+        if let Some(&entity) = self.tool_call_index.get(&key)
+            && let Ok(mut existing) = self.world.get::<&mut ToolInvocationComponent>(entity)
+        {
+            *existing = inv;
+            return entity;
+        }
+        let entity = self.world.spawn((inv,));
+        self.tool_index.entry(session_id).or_default().push(entity);
+        self.tool_call_index.insert(key, entity);
+        entity
+    }
+
+    /// Attach a tool result to its call. Returns `None` if the call is unknown.
+    pub fn complete_tool_invocation(
+        &mut self,
+        session_id: Uuid,
+        tool_use_id: &str,
+        ended_at_ms: i64,
+        output_size: u64,
+        is_error: bool,
+    ) -> Option<()> {
+        let session_id = self.resolve_alias(session_id);
+        let entity = *self
+            .tool_call_index
+            .get(&(session_id, tool_use_id.to_string()))?;
+        let mut inv = self
+            .world
+            .get::<&mut ToolInvocationComponent>(entity)
+            .ok()?;
+        inv.ended_at_ms = Some(ended_at_ms);
+        inv.duration_ms = (ended_at_ms > 0 && inv.started_at_ms > 0)
+            .then(|| (ended_at_ms - inv.started_at_ms).max(0));
+        inv.output_size = Some(output_size);
+        inv.is_error = is_error;
+        Some(())
+    }
+
+    /// Every alias as `(jsonl_id, managed_id)`.
+    pub fn session_aliases(&self) -> Vec<(Uuid, Uuid)> {
+        self.session_aliases
+            .iter()
+            .map(|(jsonl_id, managed_id)| (*jsonl_id, *managed_id))
+            .collect()
+    }
+
     /// Check if a JSONL session ID is already aliased to a managed session.
     pub fn is_aliased(&self, jsonl_id: Uuid) -> bool {
         self.session_aliases.contains_key(&jsonl_id)
@@ -231,6 +306,30 @@ impl EcsWorld {
             .collect()
     }
 
+    pub fn query_tool_invocations_by_session(
+        &self,
+        session_id: Uuid,
+    ) -> Vec<ToolInvocationComponent> {
+        let Some(entities) = self.tool_index.get(&session_id) else {
+            return Vec::new();
+        };
+        entities
+            .iter()
+            .filter_map(|e| {
+                self.world
+                    .get::<&ToolInvocationComponent>(*e)
+                    .ok()
+                    .map(|r| (*r).clone())
+            })
+            .collect()
+    }
+
+    /// All tool invocations across sessions (unordered).
+    pub fn query_tool_invocations(&self) -> Vec<ToolInvocationComponent> {
+        let mut query = self.world.query::<&ToolInvocationComponent>();
+        query.iter().cloned().collect()
+    }
+
     // === Update ===
 
     pub fn update_session_status(&mut self, session_id: Uuid, status: SessionStatus) -> Option<()> {
@@ -240,6 +339,18 @@ impl EcsWorld {
         Some(())
     }
 
+    /// Edit a session in place.
+    pub fn modify_session(
+        &mut self,
+        session_id: Uuid,
+        f: impl FnOnce(&mut SessionComponent),
+    ) -> Option<()> {
+        let entity = *self.session_index.get(&session_id)?;
+        let mut session = self.world.get::<&mut SessionComponent>(entity).ok()?;
+        f(&mut session);
+        Some(())
+    }
+
     /// Update the last activity timestamp for a session (e.g., when new messages arrive).
     pub fn update_last_activity_at(&mut self, session_id: Uuid, ts: i64) -> Option<()> {
         let entity = *self.session_index.get(&session_id)?;
@@ -393,6 +504,12 @@ impl EcsWorld {
             .map(|r| (*r).clone())
     }
 
+    /// Cache meta of every session that has one.
+    pub fn query_cache_metas(&self) -> Vec<CacheMetaComponent> {
+        let mut query = self.world.query::<&CacheMetaComponent>();
+        query.iter().cloned().collect()
+    }
+
     /// Check if a session's cache is warm.
     pub fn is_cache_warm(&self, session_id: Uuid) -> bool {
         self.query_cache_meta(session_id).is_some_and(|m| m.is_warm)
@@ -406,6 +523,7 @@ impl EcsWorld {
                 let _ = self.world.despawn(entity);
             }
         }
+        self.message_facets.remove(&session_id);
         // Remove cache meta
         if let Some(entity) = self.cache_meta_index.remove(&session_id) {
             let _ = self.world.despawn(entity);
@@ -440,6 +558,66 @@ impl EcsWorld {
         (messages, total, has_more)
     }
 
+    /// Filtered, cursor-paginated query over a session's cached messages.
+    pub fn query_messages(
+        &self,
+        session_id: Uuid,
+        query: &MessageQuery,
+    ) -> Result<MessagePage, MessageQueryError> {
+        let (Some(index), Some(entities)) = (
+            self.message_facets.get(&session_id),
+            self.message_index.get(&session_id),
+        ) else {
+            if query.cursor.is_some() {
+                return Err(MessageQueryError::InvalidCursor);
+            }
+            return Ok(MessagePage {
+                messages: Vec::new(),
+                total: 0,
+                next_cursor: None,
+                facets: query.facets.then(Default::default),
+            });
+        };
+        let needle = query.text.as_deref().map(str::to_lowercase);
+        let page = index.run(query, |pos| {
+            let Some(needle) = needle.as_deref() else {
+                return true;
+            };
+            self.world
+                .get::<&MessageComponent>(entities[pos])
+                .is_ok_and(|m| m.content.to_lowercase().contains(needle))
+        })?;
+        let messages = page
+            .positions
+            .iter()
+            .filter_map(|&pos| {
+                self.world
+                    .get::<&MessageComponent>(entities[pos])
+                    .ok()
+                    .map(|r| (*r).clone())
+            })
+            .collect();
+        Ok(MessagePage {
+            messages,
+            total: page.total,
+            next_cursor: page.next_cursor,
+            facets: page.facets,
+        })
+    }
+
+    /// Position of the first cached message of a session at or after
+    /// `timestamp` (epoch seconds), for jumping to a point in time.
+    pub fn message_position_at(&self, session_id: Uuid, timestamp: i64) -> usize {
+        let Some(entities) = self.message_index.get(&session_id) else {
+            return 0;
+        };
+        entities.partition_point(|e| {
+            self.world
+                .get::<&MessageComponent>(*e)
+                .is_ok_and(|m| m.timestamp < timestamp)
+        })
+    }
+
     // === Stats ===
 
     pub fn session_count(&self) -> usize {
@@ -480,20 +658,8 @@ mod tests {
     fn make_message(id: Uuid, session_id: Uuid, role: MessageRole) -> MessageComponent {
         MessageComponent {
             id,
-            session_id,
-            role,
-            content: format!("Message {id}"),
-            content_blocks_json: None,
-            timestamp: 1708000001,
             tokens: Some(100),
-            hidden: false,
-            message_type: MessageType::Text,
-            model: None,
-            stop_reason: None,
-            input_tokens: None,
-            output_tokens: None,
-            cache_creation_input_tokens: None,
-            cache_read_input_tokens: None,
+            ..MessageComponent::test(session_id, role, &format!("Message {id}"), 1708000001)
         }
     }
 
@@ -707,4 +873,57 @@ mod tests {
         assert_eq!(world.query_agents_by_session(sid).len(), 1);
         assert_eq!(world.query_api_requests_by_session(sid).len(), 1);
     }
+
+    #[test]
+    fn tool_invocation_pairs_result_and_dedups() {
+        let mut world = EcsWorld::new();
+        let sid = Uuid::new_v4();
+        let call = |started_at_ms| ToolInvocationComponent {
+            session_id: sid,
+            tool_use_id: "toolu_1".to_string(),
+            message_id: Uuid::new_v4(),
+            name: "Bash".to_string(),
+            kind: ToolKind::Shell,
+            input: serde_json::json!({"command": "false"}),
+            files: Vec::new(),
+            started_at_ms,
+            ended_at_ms: None,
+            duration_ms: None,
+            output_size: None,
+            is_error: false,
+        };
+        world.upsert_tool_invocation(call(1_000));
+
+        assert!(
+            world
+                .complete_tool_invocation(sid, "toolu_1", 3_500, 12, true)
+                .is_some()
+        );
+        assert!(
+            world
+                .complete_tool_invocation(sid, "toolu_unknown", 3_500, 1, false)
+                .is_none()
+        );
+        let inv = &world.query_tool_invocations_by_session(sid)[0];
+        assert_eq!(inv.duration_ms, Some(2_500));
+        assert_eq!(inv.output_size, Some(12));
+        assert!(inv.is_error);
+
+        // Re-parsing the session replaces the invocation.
+        world.upsert_tool_invocation(call(1_000));
+        assert_eq!(world.query_tool_invocations().len(), 1);
+        assert_eq!(
+            world.query_tool_invocations_by_session(sid)[0].ended_at_ms,
+            None
+        );
+
+        // Deleting the session takes its invocations with it.
+        world.despawn_session(sid);
+        assert!(world.query_tool_invocations().is_empty());
+        assert!(
+            world
+                .complete_tool_invocation(sid, "toolu_1", 4_000, 1, false)
+                .is_none()
+        );
+    }
 }
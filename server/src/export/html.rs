@@ -0,0 +1,182 @@
+//! Single-file HTML renderer: inline CSS, no scripts or external assets,
+//! collapsible `<details>` sections for thinking, tool results and subagents.
+
+use std::fmt::Write;
+
+use super::markdown::usage_line;
+use super::{Block, Entry, ExportDocument, Turn};
+
+const STYLE: &str = "\
+body{font:14px/1.5 system-ui,sans-serif;max-width:960px;margin:2em auto;padding:0 1em;color:#1f2328}\
+h1{font-size:1.4em}table{border-collapse:collapse}td{padding:2px 10px;border-bottom:1px solid #d0d7de}\
+section.turn{border-top:2px solid #d0d7de;margin-top:1.5em}\
+.entry{margin:.8em 0;padding:.5em .8em;border-radius:6px;background:#f6f8fa}\
+.entry.user{background:#ddf4ff}.entry.meta,.entry.system{background:#fff8c5}\
+.head{font-weight:600;font-size:.9em;color:#57606a}\
+pre{white-space:pre-wrap;word-break:break-word;background:#fff;padding:.5em;border:1px solid #d0d7de;border-radius:4px}\
+.error>summary{color:#cf222e}.usage{font-size:.8em;color:#57606a}\
+blockquote{margin:.3em 0;padding-left:.8em;border-left:3px solid #d4a72c;color:#57606a}";
+
+pub(super) fn render(doc: &ExportDocument) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <title>Session {id}</title><style>{STYLE}</style></head><body>\n\
+         <h1>Session <code>{id}</code></h1>\n<table>",
+        id = escape(&doc.session_id)
+    );
+    let mut row = |label: &str, value: &str| {
+        let _ = write!(out, "<tr><td>{label}</td><td>{}</td></tr>", escape(value));
+    };
+    row("CLI", &doc.cli);
+    if let Some(path) = &doc.path {
+        row("Transcript", path);
+    }
+    if let Some(started) = &doc.started_at {
+        row("Started", started);
+    }
+    if !doc.models.is_empty() {
+        row("Models", &doc.models.join(", "));
+    }
+    row("Turns", &doc.turns.len().to_string());
+    row("Tokens", &usage_line(&doc.usage));
+    if !doc.recordings.is_empty() {
+        let list: Vec<String> = doc
+            .recordings
+            .iter()
+            .map(|r| format!("{} ({:.1} s)", r.info.name, r.info.duration_secs))
+            .collect();
+        row("Recordings", &list.join(", "));
+    }
+    if let Some(exported) = &doc.exported_at {
+        row("Exported", exported);
+    }
+    out.push_str("</table>\n");
+
+    render_turns(&mut out, &doc.turns);
+    for thread in &doc.subagents {
+        let _ = writeln!(
+            out,
+            "<details class=\"subagent\"><summary>Subagent <code>{}</code> · {}</summary>",
+            escape(&thread.agent_id),
+            escape(&usage_line(&thread.usage))
+        );
+        render_turns(&mut out, &thread.turns);
+        out.push_str("</details>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn render_turns(out: &mut String, turns: &[Turn]) {
+    for turn in turns {
+        let _ = write!(out, "<section class=\"turn\"><h2>Turn {}</h2>", turn.index);
+        if !turn.usage.is_empty() {
+            let _ = write!(
+                out,
+                "<div class=\"usage\">{}</div>",
+                escape(&usage_line(&turn.usage))
+            );
+        }
+        for entry in &turn.entries {
+            render_entry(out, entry);
+        }
+        out.push_str("</section>\n");
+    }
+}
+
+fn render_entry(out: &mut String, entry: &Entry) {
+    let mut head = entry.role.clone();
+    if entry.role == "meta" || entry.role == "system" {
+        let _ = write!(head, " ({})", entry.message_type);
+    }
+    if let Some(model) = &entry.model {
+        let _ = write!(head, " · {model}");
+    }
+    if let Some(ts) = &entry.timestamp {
+        let _ = write!(head, " · {ts}");
+    }
+    let _ = write!(
+        out,
+        "<div class=\"entry {}\"><div class=\"head\">{}</div>",
+        escape(&entry.role),
+        escape(&head)
+    );
+
+    for block in &entry.blocks {
+        match block {
+            Block::Text { text } => {
+                let _ = write!(out, "<pre>{}</pre>", escape(text));
+            }
+            Block::Thinking { text } => {
+                let _ = write!(
+                    out,
+                    "<details><summary>Thinking</summary><pre>{}</pre></details>",
+                    escape(text)
+                );
+            }
+            Block::ToolUse { id, name, input } => {
+                let json = serde_json::to_string_pretty(input).unwrap_or_default();
+                let _ = write!(
+                    out,
+                    "<div>Tool call <b>{}</b> <code>{}</code></div><pre>{}</pre>",
+                    escape(name),
+                    escape(id),
+                    escape(&json)
+                );
+            }
+            Block::ToolResult {
+                tool_use_id,
+                output,
+                is_error,
+                truncated_bytes,
+            } => {
+                let (class, status) = if *is_error {
+                    (" class=\"error\"", " (error)")
+                } else {
+                    ("", "")
+                };
+                let _ = write!(
+                    out,
+                    "<details{class}><summary>Tool result{status} · <code>{}</code></summary><pre>{}</pre>",
+                    escape(tool_use_id),
+                    escape(output)
+                );
+                if *truncated_bytes > 0 {
+                    let _ = write!(out, "<i>… {truncated_bytes} bytes truncated</i>");
+                }
+                out.push_str("</details>");
+            }
+            Block::Image { media_type } => {
+                let _ = write!(out, "<i>[image: {}]</i>", escape(media_type));
+            }
+            Block::Note { text } => {
+                let _ = write!(out, "<blockquote><pre>{}</pre></blockquote>", escape(text));
+            }
+        }
+    }
+    if let Some(usage) = &entry.usage {
+        let _ = write!(
+            out,
+            "<div class=\"usage\">{}</div>",
+            escape(&usage_line(usage))
+        );
+    }
+    out.push_str("</div>\n");
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,161 @@
+//! Markdown renderer. Thinking and tool results are folded into
+//! `<details>` blocks, which GitHub and most review tools render.
+
+use std::fmt::Write;
+
+use super::{Block, Entry, ExportDocument, Turn, Usage};
+
+pub(super) fn render(doc: &ExportDocument) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Session `{}`\n", doc.session_id);
+    let _ = writeln!(out, "| | |\n|---|---|");
+    let _ = writeln!(out, "| CLI | {} |", doc.cli);
+    if let Some(path) = &doc.path {
+        let _ = writeln!(out, "| Transcript | `{path}` |");
+    }
+    if let Some(started) = &doc.started_at {
+        let _ = writeln!(out, "| Started | {started} |");
+    }
+    if !doc.models.is_empty() {
+        let _ = writeln!(out, "| Models | {} |", doc.models.join(", "));
+    }
+    let _ = writeln!(out, "| Turns | {} |", doc.turns.len());
+    let _ = writeln!(out, "| Tokens | {} |", usage_line(&doc.usage));
+    if !doc.recordings.is_empty() {
+        let list: Vec<String> = doc
+            .recordings
+            .iter()
+            .map(|r| format!("`{}` ({:.1} s)", r.info.name, r.info.duration_secs))
+            .collect();
+        let _ = writeln!(out, "| Recordings | {} |", list.join(", "));
+    }
+    if let Some(exported) = &doc.exported_at {
+        let _ = writeln!(out, "| Exported | {exported} |");
+    }
+    out.push('\n');
+
+    render_turns(&mut out, &doc.turns, 2);
+
+    for thread in &doc.subagents {
+        let _ = writeln!(
+            out,
+            "## Subagent `{}` · {}\n",
+            thread.agent_id,
+            usage_line(&thread.usage)
+        );
+        render_turns(&mut out, &thread.turns, 3);
+    }
+    out
+}
+
+fn render_turns(out: &mut String, turns: &[Turn], level: usize) {
+    let heading = "#".repeat(level);
+    for turn in turns {
+        if turn.usage.is_empty() {
+            let _ = writeln!(out, "{heading} Turn {}\n", turn.index);
+        } else {
+            let _ = writeln!(
+                out,
+                "{heading} Turn {} · {}\n",
+                turn.index,
+                usage_line(&turn.usage)
+            );
+        }
+        for entry in &turn.entries {
+            render_entry(out, entry, level + 1);
+        }
+    }
+}
+
+fn render_entry(out: &mut String, entry: &Entry, level: usize) {
+    let mut title = format!("{} {}", "#".repeat(level), capitalize(&entry.role));
+    if entry.role == "meta" || entry.role == "system" {
+        let _ = write!(title, " ({})", entry.message_type);
+    }
+    if let Some(model) = &entry.model {
+        let _ = write!(title, " · {model}");
+    }
+    if let Some(ts) = &entry.timestamp {
+        let _ = write!(title, " · {ts}");
+    }
+    let _ = writeln!(out, "{title}\n");
+
+    for block in &entry.blocks {
+        match block {
+            Block::Text { text } => {
+                let _ = writeln!(out, "{}\n", text.trim_end());
+            }
+            Block::Thinking { text } => {
+                let _ = writeln!(
+                    out,
+                    "<details><summary>Thinking</summary>\n\n{}\n\n</details>\n",
+                    text.trim_end()
+                );
+            }
+            Block::ToolUse { id, name, input } => {
+                let json = serde_json::to_string_pretty(input).unwrap_or_default();
+                let fence = fence_for(&json);
+                let _ = writeln!(
+                    out,
+                    "**Tool call `{name}`** (`{id}`)\n\n{fence}json\n{json}\n{fence}\n"
+                );
+            }
+            Block::ToolResult {
+                tool_use_id,
+                output,
+                is_error,
+                truncated_bytes,
+            } => {
+                let status = if *is_error { " (error)" } else { "" };
+                let fence = fence_for(output);
+                let _ = writeln!(
+                    out,
+                    "<details><summary>Tool result{status} · <code>{tool_use_id}</code></summary>\n\n{fence}\n{}\n{fence}\n",
+                    output.trim_end()
+                );
+                if *truncated_bytes > 0 {
+                    let _ = writeln!(out, "_… {truncated_bytes} bytes truncated_\n");
+                }
+                out.push_str("</details>\n\n");
+            }
+            Block::Image { media_type } => {
+                let _ = writeln!(out, "_[image: {media_type}]_\n");
+            }
+            Block::Note { text } => {
+                for line in text.trim_end().lines() {
+                    let _ = writeln!(out, "> {line}");
+                }
+                out.push('\n');
+            }
+        }
+    }
+
+    if let Some(usage) = &entry.usage {
+        let _ = writeln!(out, "<sub>{}</sub>\n", usage_line(usage));
+    }
+}
+
+pub(super) fn usage_line(usage: &Usage) -> String {
+    format!(
+        "{} in · {} out · {} cache write · {} cache read · ~${:.4}",
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_creation_tokens,
+        usage.cache_read_tokens,
+        usage.cost_usd
+    )
+}
+
+/// A backtick fence longer than any backtick run inside `content`.
+fn fence_for(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
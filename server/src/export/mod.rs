@@ -0,0 +1,707 @@
+//! Session transcript export — Markdown, single-file HTML and JSON.
+//!
+//! An export is built in two steps: [`build_document`] turns the session's
+//! `MessageComponent`s into a normalized [`ExportDocument`] (turns, content
+//! blocks, per-turn usage) while applying the [`ExportOptions`] — redaction,
+//! hiding meta entries, truncating tool output. The renderers then only
+//! format that document, so all three formats carry the same content.
+//! Terminal recordings of managed sessions are listed, and the JSON format
+//! can carry their asciicast.
+
+mod html;
+mod markdown;
+
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::ecs::components::{MessageComponent, MessageRole, MessageType};
+use crate::parser::{self, ContentBlock};
+use crate::session::recording::{self, RecordingInfo};
+
+/// Version of the JSON export layout.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Apply `mitm::redact` (API keys, bearer tokens) to all text.
+    pub redact: bool,
+    /// Extra patterns replaced with `[REDACTED]` (applied even without `redact`).
+    pub redact_patterns: Vec<Regex>,
+    /// Drop progress, snapshot and other meta entries.
+    pub hide_meta: bool,
+    /// Cut tool results to this many bytes.
+    pub max_output_bytes: Option<usize>,
+}
+
+impl ExportOptions {
+    /// Compile user-supplied redaction patterns.
+    pub fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>, regex::Error> {
+        patterns
+            .iter()
+            .filter(|p| !p.is_empty())
+            .map(|p| Regex::new(p))
+            .collect()
+    }
+
+    fn scrub(&self, text: &str) -> String {
+        let mut out = if self.redact {
+            crate::proxy::mitm::redact(text)
+        } else {
+            text.to_string()
+        };
+        for re in &self.redact_patterns {
+            if re.is_match(&out) {
+                out = re.replace_all(&out, "[REDACTED]").into_owned();
+            }
+        }
+        out
+    }
+
+    fn scrub_value(&self, value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::String(s) => serde_json::Value::String(self.scrub(&s)),
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.into_iter().map(|v| self.scrub_value(v)).collect())
+            }
+            serde_json::Value::Object(obj) => serde_json::Value::Object(
+                obj.into_iter()
+                    .map(|(k, v)| (k, self.scrub_value(v)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+}
+
+/// Session transcript to export.
+#[derive(Debug, Clone, Default)]
+pub struct ExportSession {
+    pub session_id: Uuid,
+    /// Transcript source name (`claude`, `codex`, …).
+    pub cli: String,
+    pub path: Option<String>,
+    pub messages: Vec<MessageComponent>,
+    pub subagents: Vec<SubagentThread>,
+    pub recordings: Vec<ExportRecording>,
+}
+
+/// A terminal recording of the session.
+#[derive(Debug, Clone)]
+pub struct ExportRecording {
+    pub info: RecordingInfo,
+    /// The asciicast itself, if it is to be embedded.
+    pub cast: Option<String>,
+}
+
+/// Messages of one subagent (Claude `subagents/agent-<id>.jsonl`).
+#[derive(Debug, Clone, Default)]
+pub struct SubagentThread {
+    pub agent_id: String,
+    pub messages: Vec<MessageComponent>,
+}
+
+// ── Normalized document ─────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportDocument {
+    pub version: u32,
+    pub session_id: String,
+    pub cli: String,
+    pub path: Option<String>,
+    pub exported_at: Option<String>,
+    pub started_at: Option<String>,
+    pub models: Vec<String>,
+    pub usage: Usage,
+    pub turns: Vec<Turn>,
+    pub subagents: Vec<Thread>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    #[serde(flatten)]
+    pub info: RecordingInfo,
+    /// Asciicast v2, with output and input scrubbed like the transcript.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cast: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thread {
+    pub agent_id: String,
+    pub usage: Usage,
+    pub turns: Vec<Turn>,
+}
+
+/// A user prompt and everything up to the next prompt.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Turn {
+    pub index: usize,
+    pub usage: Usage,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cache_read_tokens: u64,
+    /// Estimated from tokens with the proxy's pricing table.
+    pub cost_usd: f64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_tokens += other.cache_creation_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input_tokens + self.output_tokens + self.cache_creation_tokens + self.cache_read_tokens
+            == 0
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub id: String,
+    pub role: String,
+    pub message_type: String,
+    pub timestamp: Option<String>,
+    pub model: Option<String>,
+    pub usage: Option<Usage>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Text {
+        text: String,
+    },
+    Thinking {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename_all = "camelCase")]
+    ToolResult {
+        tool_use_id: String,
+        output: String,
+        is_error: bool,
+        /// Bytes cut by `max_output_bytes`.
+        truncated_bytes: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    Image {
+        media_type: String,
+    },
+    /// System reminders, summaries, compaction markers and other meta text.
+    Note {
+        text: String,
+    },
+}
+
+// ── Building ────────────────────────────────────────────────────────────────
+
+/// Normalize a session for rendering, applying `opts`.
+pub fn build_document(session: &ExportSession, opts: &ExportOptions) -> ExportDocument {
+    let turns = build_turns(&session.messages, opts);
+    let subagents: Vec<Thread> = session
+        .subagents
+        .iter()
+        .map(|thread| {
+            let turns = build_turns(&thread.messages, opts);
+            Thread {
+                agent_id: thread.agent_id.clone(),
+                usage: total_usage(&turns),
+                turns,
+            }
+        })
+        .collect();
+
+    let mut usage = total_usage(&turns);
+    for thread in &subagents {
+        usage.add(&thread.usage);
+    }
+    let mut models: Vec<String> = Vec::new();
+    for msg in session
+        .messages
+        .iter()
+        .chain(session.subagents.iter().flat_map(|t| t.messages.iter()))
+    {
+        if let Some(model) = &msg.model
+            && !models.contains(model)
+        {
+            models.push(model.clone());
+        }
+    }
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    ExportDocument {
+        version: EXPORT_VERSION,
+        session_id: session.session_id.to_string(),
+        cli: session.cli.clone(),
+        path: session.path.as_deref().map(|p| opts.scrub(p)),
+        exported_at: parser::epoch_millis_to_iso(now_ms),
+        started_at: session
+            .messages
+            .iter()
+            .map(|m| m.timestamp)
+            .find(|&ts| ts > 0)
+            .and_then(seconds_to_iso),
+        models,
+        usage,
+        turns,
+        subagents,
+        recordings: session
+            .recordings
+            .iter()
+            .map(|r| Recording {
+                info: r.info.clone(),
+                cast: r
+                    .cast
+                    .as_deref()
+                    .map(|cast| recording::map_data(cast, |data| opts.scrub(data))),
+            })
+            .collect(),
+    }
+}
+
+/// Render a session in the requested format.
+pub fn render(session: &ExportSession, opts: &ExportOptions) -> String {
+    let doc = build_document(session, opts);
+    match opts.format {
+        ExportFormat::Markdown => markdown::render(&doc),
+        ExportFormat::Html => html::render(&doc),
+        ExportFormat::Json => serde_json::to_string_pretty(&doc).unwrap_or_default(),
+    }
+}
+
+fn total_usage(turns: &[Turn]) -> Usage {
+    let mut usage = Usage::default();
+    for turn in turns {
+        usage.add(&turn.usage);
+    }
+    usage
+}
+
+fn build_turns(messages: &[MessageComponent], opts: &ExportOptions) -> Vec<Turn> {
+    let mut turns: Vec<Turn> = Vec::new();
+    for msg in messages {
+        if opts.hide_meta && msg.role == MessageRole::Meta {
+            continue;
+        }
+        let starts_turn = msg.role == MessageRole::User && msg.message_type == MessageType::Text;
+        if starts_turn || turns.is_empty() {
+            turns.push(Turn {
+                index: turns.len() + 1,
+                usage: Usage::default(),
+                entries: Vec::new(),
+            });
+        }
+        let entry = build_entry(msg, opts);
+        let turn = turns.last_mut().expect("turn pushed above");
+        if let Some(usage) = &entry.usage {
+            turn.usage.add(usage);
+        }
+        turn.entries.push(entry);
+    }
+    turns
+}
+
+fn build_entry(msg: &MessageComponent, opts: &ExportOptions) -> Entry {
+    let api = crate::cache::component_to_api_json(msg);
+    let label = |key: &str| {
+        api.get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let usage = Usage {
+        input_tokens: msg.input_tokens.unwrap_or(0) as u64,
+        output_tokens: msg.output_tokens.unwrap_or(0) as u64,
+        cache_creation_tokens: msg.cache_creation_input_tokens.unwrap_or(0) as u64,
+        cache_read_tokens: msg.cache_read_input_tokens.unwrap_or(0) as u64,
+        cost_usd: 0.0,
+    };
+    let usage = (!usage.is_empty()).then(|| Usage {
+        cost_usd: crate::proxy::audit::calculate_cost(
+            msg.model.as_deref().unwrap_or(""),
+            usage.input_tokens + usage.cache_creation_tokens,
+            usage.output_tokens,
+        ),
+        ..usage
+    });
+
+    Entry {
+        id: msg.id.to_string(),
+        role: label("role"),
+        message_type: label("messageType"),
+        timestamp: (msg.timestamp > 0)
+            .then_some(msg.timestamp)
+            .and_then(seconds_to_iso),
+        model: msg.model.clone(),
+        usage,
+        blocks: build_blocks(msg, opts),
+    }
+}
+
+fn build_blocks(msg: &MessageComponent, opts: &ExportOptions) -> Vec<Block> {
+    let blocks: Vec<ContentBlock> = msg
+        .content_blocks_json
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_else(|| {
+            vec![ContentBlock::Text {
+                text: msg.content.clone(),
+            }]
+        });
+    let is_note = matches!(msg.role, MessageRole::Meta | MessageRole::System)
+        || msg.message_type == MessageType::SystemReminder;
+
+    blocks
+        .into_iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } if text.trim().is_empty() => None,
+            ContentBlock::Text { text } if is_note => Some(Block::Note {
+                text: opts.scrub(&text),
+            }),
+            ContentBlock::Text { text } => Some(Block::Text {
+                text: opts.scrub(&text),
+            }),
+            ContentBlock::Thinking { thinking } => Some(Block::Thinking {
+                text: opts.scrub(&thinking),
+            }),
+            ContentBlock::ToolUse { id, name, input } => Some(Block::ToolUse {
+                id,
+                name,
+                input: opts.scrub_value(input),
+            }),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => {
+                let output = opts.scrub(&tool_output_text(&content));
+                let (output, truncated_bytes) = truncate(output, opts.max_output_bytes);
+                Some(Block::ToolResult {
+                    tool_use_id,
+                    output,
+                    is_error: is_error.unwrap_or(false),
+                    truncated_bytes,
+                })
+            }
+            ContentBlock::Image { source } => Some(Block::Image {
+                media_type: source.media_type,
+            }),
+        })
+        .collect()
+}
+
+/// Text of a tool result: the string itself, joined text blocks, or JSON.
+fn tool_output_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| match item.get("text").and_then(|t| t.as_str()) {
+                Some(text) => text.to_string(),
+                None => item.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        serde_json::Value::Null => String::new(),
+        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+    }
+}
+
+fn truncate(mut text: String, max: Option<usize>) -> (String, usize) {
+    let Some(max) = max.filter(|&m| text.len() > m) else {
+        return (text, 0);
+    };
+    let cut = (0..=max)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    let removed = text.len() - cut;
+    text.truncate(cut);
+    (text, removed)
+}
+
+fn seconds_to_iso(secs: i64) -> Option<String> {
+    parser::epoch_millis_to_iso(secs * 1000)
+}
+
+// ── Loading ─────────────────────────────────────────────────────────────────
+
+/// Parse a transcript file (any supported CLI) into an [`ExportSession`],
+/// including Claude subagent threads.
+pub async fn load_transcript(path: &Path, session_id: Uuid) -> anyhow::Result<ExportSession> {
+    let source = parser::registry::resolve(path);
+    let messages = source
+        .parse_file(path)
+        .await?
+        .iter()
+        .filter_map(|m| parser::message_to_component(m, session_id))
+        .collect();
+    Ok(ExportSession {
+        session_id,
+        cli: source.name().to_string(),
+        path: Some(path.display().to_string()),
+        messages,
+        subagents: load_subagents(path, session_id).await,
+        recordings: Vec::new(),
+    })
+}
+
+/// Subagent transcripts stored next to a Claude session file.
+pub async fn load_subagents(path: &Path, session_id: Uuid) -> Vec<SubagentThread> {
+    let Some(dir) = parser::tree::subagents_dir(path) else {
+        return Vec::new();
+    };
+    let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(agent_id) = name
+            .strip_prefix("agent-")
+            .and_then(|n| n.strip_suffix(".jsonl"))
+        {
+            files.push((agent_id.to_string(), entry.path()));
+        }
+    }
+    files.sort();
+
+    let mut threads = Vec::new();
+    for (agent_id, file) in files {
+        if let Ok(messages) = parser::parse_file(&file).await {
+            threads.push(SubagentThread {
+                agent_id,
+                messages: messages
+                    .iter()
+                    .filter_map(|m| parser::message_to_component(m, session_id))
+                    .collect(),
+            });
+        }
+    }
+    threads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(
+        role: MessageRole,
+        message_type: MessageType,
+        blocks: serde_json::Value,
+    ) -> MessageComponent {
+        MessageComponent {
+            content_blocks_json: Some(blocks.to_string()),
+            message_type,
+            ..MessageComponent::test(Uuid::nil(), role, "", 1_771_668_000)
+        }
+    }
+
+    fn session() -> ExportSession {
+        let mut reply = component(
+            MessageRole::Assistant,
+            MessageType::ToolUse,
+            serde_json::json!([
+                {"type": "thinking", "thinking": "check the env"},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash",
+                 "input": {"command": "echo $KEY sk-ant-api03-secretsecret"}},
+            ]),
+        );
+        reply.model = Some("claude-sonnet-4".to_string());
+        reply.input_tokens = Some(1000);
+        reply.output_tokens = Some(200);
+        ExportSession {
+            session_id: Uuid::nil(),
+            cli: "claude".to_string(),
+            path: None,
+            messages: vec![
+                component(
+                    MessageRole::User,
+                    MessageType::Text,
+                    serde_json::json!([{"type": "text", "text": "print the key for ticket ACME-1234"}]),
+                ),
+                reply,
+                component(
+                    MessageRole::User,
+                    MessageType::ToolResult,
+                    serde_json::json!([{"type": "tool_result", "tool_use_id": "toolu_1",
+                        "content": "x".repeat(100), "is_error": false}]),
+                ),
+                component(
+                    MessageRole::Meta,
+                    MessageType::Progress,
+                    serde_json::json!([{"type": "text", "text": "{\"type\":\"hook_progress\"}"}]),
+                ),
+                component(
+                    MessageRole::User,
+                    MessageType::Text,
+                    serde_json::json!([{"type": "text", "text": "thanks"}]),
+                ),
+            ],
+            subagents: Vec::new(),
+            recordings: vec![ExportRecording {
+                info: RecordingInfo {
+                    name: "1771668000000.cast".to_string(),
+                    started_at: 1_771_668_000,
+                    width: 80,
+                    height: 24,
+                    duration_secs: 1.5,
+                    bytes: 120,
+                    title: None,
+                },
+                cast: Some(
+                    [
+                        r#"{"version":2,"width":80,"height":24}"#,
+                        r#"[0.5,"i","echo ACME-1234\r"]"#,
+                        r#"[1.5,"o","ACME-1234\r\n"]"#,
+                    ]
+                    .join("\n"),
+                ),
+            }],
+        }
+    }
+
+    #[test]
+    fn document_groups_turns_and_applies_options() {
+        let opts = ExportOptions {
+            format: ExportFormat::Json,
+            redact: true,
+            redact_patterns: ExportOptions::compile_patterns(&["ACME-\\d+".to_string()]).unwrap(),
+            hide_meta: true,
+            max_output_bytes: Some(10),
+        };
+        let doc = build_document(&session(), &opts);
+
+        assert_eq!(doc.turns.len(), 2);
+        let first = &doc.turns[0];
+        assert_eq!(first.entries.len(), 3, "progress entry hidden");
+        assert_eq!(first.usage.input_tokens, 1000);
+        assert!(first.usage.cost_usd > 0.0);
+        assert_eq!(doc.models, vec!["claude-sonnet-4"]);
+
+        let Block::Text { text } = &first.entries[0].blocks[0] else {
+            panic!("expected prompt text");
+        };
+        assert_eq!(text, "print the key for ticket [REDACTED]");
+        let Block::ToolUse { input, .. } = &first.entries[1].blocks[1] else {
+            panic!("expected tool use");
+        };
+        assert!(!input["command"].as_str().unwrap().contains("secret"));
+        let Block::ToolResult {
+            output,
+            truncated_bytes,
+            ..
+        } = &first.entries[2].blocks[0]
+        else {
+            panic!("expected tool result");
+        };
+        assert_eq!(output.len(), 10);
+        assert_eq!(*truncated_bytes, 90);
+
+        let cast = doc.recordings[0].cast.as_deref().unwrap();
+        assert!(!cast.contains("ACME"), "{cast}");
+        assert_eq!(cast.lines().count(), 3);
+    }
+
+    #[test]
+    fn renders_all_formats() {
+        let session = session();
+        let md = render(&session, &ExportOptions::default());
+        assert!(md.contains("## Turn 1"));
+        assert!(md.contains("<summary>Thinking</summary>"));
+        assert!(md.contains("hook_progress"));
+        assert!(md.contains("| Recordings | `1771668000000.cast` (1.5 s) |"));
+
+        let html = render(
+            &session,
+            &ExportOptions {
+                format: ExportFormat::Html,
+                ..Default::default()
+            },
+        );
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<details"));
+        assert!(!html.contains("<script"));
+
+        let json = render(
+            &session,
+            &ExportOptions {
+                format: ExportFormat::Json,
+                ..Default::default()
+            },
+        );
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], EXPORT_VERSION);
+        assert_eq!(value["recordings"][0]["durationSecs"], 1.5);
+        assert_eq!(
+            value["turns"][0]["entries"][1]["blocks"][1]["type"],
+            "tool_use"
+        );
+    }
+}
@@ -1,8 +1,10 @@
 pub mod blame;
 pub mod status;
+pub mod worktree;
 
 pub use blame::{BlameError, BlameLine, blame_file};
 pub use status::{
-    BranchInfo, CommitInfo, DiffHunk, FileStatus, GitError, branches, checkout, commit,
-    create_branch, diff_hunks, log, stage, stage_hunk, status, unstage,
+    BranchInfo, CommitInfo, DiffHunk, DiffStat, FileStatus, GitError, branches, checkout, commit,
+    create_branch, diff_hunks, diff_since, head_commit, log, stage, stage_hunk, status, unstage,
 };
+pub use worktree::{Finished, Worktree, WorktreeAction};
@@ -35,6 +35,8 @@ pub enum GitError {
     NotRepo(String),
     #[error("branch not found: {0}")]
     BranchNotFound(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
     #[error("{0}")]
     Other(String),
 }
@@ -330,6 +332,7 @@ pub fn stage_hunk(repo_path: &Path, file_path: &str, hunk_index: usize) -> Resul
     let output = std::process::Command::new("git")
         .args(["apply", "--cached", "--unidiff-zero", "-"])
         .current_dir(repo_path)
+        .envs(crate::proxy::upstream::global().child_env())
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
@@ -349,6 +352,57 @@ pub fn stage_hunk(repo_path: &Path, file_path: &str, hunk_index: usize) -> Resul
     Ok(())
 }
 
+/// Size of a diff.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Commit id of `HEAD`; `None` before the first commit.
+pub fn head_commit(repo_path: &Path) -> Result<Option<String>, GitError> {
+    let repo = Repository::discover(repo_path)?;
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?.id().to_string())),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Everything that changed since commit `base` (since the empty tree if
+/// `None`) as a unified patch: commits made in between, staged and unstaged
+/// edits and untracked files.
+pub fn diff_since(repo_path: &Path, base: Option<&str>) -> Result<(String, DiffStat), GitError> {
+    let repo = Repository::discover(repo_path)?;
+    let base_tree = match base {
+        Some(id) => Some(repo.revparse_single(id)?.peel_to_tree()?),
+        None => None,
+    };
+    let mut opts = git2::DiffOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let diff = repo.diff_tree_to_workdir_with_index(base_tree.as_ref(), Some(&mut opts))?;
+
+    let stats = diff.stats()?;
+    let stat = DiffStat {
+        files_changed: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    };
+    let mut patch = String::new();
+    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok((patch, stat))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -509,4 +563,29 @@ mod tests {
         assert_eq!(commits.len(), 2, "should have 2 commits now");
         assert_eq!(commits[0].message, "Add new file");
     }
+
+    #[test]
+    fn diff_since_covers_commits_edits_and_new_files() {
+        let dir = create_test_repo();
+        let base = head_commit(dir.path()).unwrap().unwrap();
+
+        std::fs::write(dir.path().join("committed.txt"), "one\n").unwrap();
+        stage(dir.path(), &["committed.txt"]).unwrap();
+        commit(dir.path(), "Add committed.txt").unwrap();
+        std::fs::write(dir.path().join("test.txt"), "line 1\nline two\n").unwrap();
+        std::fs::write(dir.path().join("untracked.txt"), "new\n").unwrap();
+
+        let (patch, stat) = diff_since(dir.path(), Some(&base)).unwrap();
+        assert_eq!(
+            stat,
+            DiffStat {
+                files_changed: 3,
+                insertions: 3,
+                deletions: 1,
+            }
+        );
+        assert!(patch.contains("+++ b/committed.txt"));
+        assert!(patch.contains("-line 2\n+line two\n"));
+        assert!(patch.contains("+++ b/untracked.txt"));
+    }
 }
@@ -0,0 +1,476 @@
+//! Dedicated worktrees for managed sessions.
+//!
+//! Agents sharing one checkout trample each other's edits. A managed session
+//! can instead run in its own `git worktree` on a new branch
+//! `noaide/<session-id>`, forked from whatever the repository has checked
+//! out. When the session is done its branch is merged back, pushed as a pull
+//! request, kept, or discarded, and the checkout is removed.
+//!
+//! Reads go through libgit2; creating and removing worktrees, merging and
+//! pushing use the git CLI, which handles locking, hooks and credentials.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::{IndexAddOption, Repository, StatusOptions};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::status::{GitError, commit};
+
+/// Identity for commits noaide makes itself when the repository has none.
+const FALLBACK_NAME: &str = "noaide";
+const FALLBACK_EMAIL: &str = "noaide@localhost";
+
+/// A session's worktree and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Worktree {
+    /// Root of the repository the worktree was added to.
+    pub repo: PathBuf,
+    /// Root of the worktree checkout.
+    pub path: PathBuf,
+    /// Directory inside the worktree the session runs in, relative to
+    /// `path`; empty when the session was started at the repository root.
+    #[serde(default, skip_serializing_if = "is_empty_path")]
+    pub subdir: PathBuf,
+    pub branch: String,
+    /// Branch checked out in `repo` when the worktree was added; merges and
+    /// pull requests target it. `None` if `repo` was on a detached HEAD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_branch: Option<String>,
+    /// Commit the branch started from.
+    pub base_commit: String,
+}
+
+fn is_empty_path(path: &Path) -> bool {
+    path.as_os_str().is_empty()
+}
+
+impl Worktree {
+    /// Where the session's CLI runs.
+    pub fn session_dir(&self) -> PathBuf {
+        self.path.join(&self.subdir)
+    }
+
+    /// The repository's shared git directory, which commits made in the
+    /// worktree write to.
+    pub fn common_dir(&self) -> Result<PathBuf, GitError> {
+        Ok(Repository::open(&self.path)?.commondir().to_path_buf())
+    }
+}
+
+/// What to do with a session's worktree once it is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorktreeAction {
+    /// Merge the branch into the base branch, then delete both.
+    Merge,
+    /// Push the branch and open a pull request against the base branch.
+    Pr,
+    /// Remove the checkout but keep the branch.
+    Keep,
+    /// Remove the checkout and delete the branch, dropping its changes.
+    Discard,
+}
+
+impl WorktreeAction {
+    pub const ALL: [WorktreeAction; 4] = [Self::Merge, Self::Pr, Self::Keep, Self::Discard];
+}
+
+/// Result of [`finish`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finished {
+    /// Commit made from changes left uncommitted in the worktree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub committed: Option<String>,
+    /// Head of the base branch after a merge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged: Option<String>,
+    /// URL of the opened pull request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Whether the session branch still exists.
+    pub branch_kept: bool,
+}
+
+/// Branch a session's worktree is created on.
+pub fn branch_name(session_id: Uuid) -> String {
+    format!("noaide/{session_id}")
+}
+
+/// Where session worktrees are created: `NOAIDE_WORKTREES_DIR`, else
+/// `/data/noaide/worktrees`.
+pub fn worktrees_dir() -> PathBuf {
+    std::env::var("NOAIDE_WORKTREES_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("worktrees"))
+}
+
+/// Add a worktree for `session_id` under `dir`, on a new branch from the
+/// current HEAD of the repository containing `working_dir`.
+pub fn create(working_dir: &Path, session_id: Uuid, dir: &Path) -> Result<Worktree, GitError> {
+    let working_dir = working_dir
+        .canonicalize()
+        .map_err(|e| GitError::Other(format!("{}: {e}", working_dir.display())))?;
+    let repo = Repository::discover(&working_dir)
+        .map_err(|_| GitError::NotRepo(working_dir.display().to_string()))?;
+    let root = repo
+        .workdir()
+        .ok_or_else(|| GitError::NotRepo(working_dir.display().to_string()))?
+        .canonicalize()
+        .map_err(|e| GitError::Other(e.to_string()))?;
+    let head = repo
+        .head()
+        .map_err(|_| GitError::Other("repository has no commits yet".to_string()))?;
+    let base_commit = head.peel_to_commit()?.id().to_string();
+    let base_branch = if head.is_branch() {
+        head.shorthand().map(String::from)
+    } else {
+        None
+    };
+    let subdir = working_dir
+        .strip_prefix(&root)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let name = root
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "repo".to_string());
+    let path = dir.join(format!("{name}-{session_id}"));
+    std::fs::create_dir_all(dir).map_err(|e| GitError::Other(format!("{}: {e}", dir.display())))?;
+    let branch = branch_name(session_id);
+    git(
+        &root,
+        &[
+            "worktree",
+            "add",
+            "-b",
+            &branch,
+            &path.to_string_lossy(),
+            &base_commit,
+        ],
+    )?;
+
+    Ok(Worktree {
+        repo: root,
+        path,
+        subdir,
+        branch,
+        base_branch,
+        base_commit,
+    })
+}
+
+/// Commit everything left uncommitted in the worktree, untracked files
+/// included. Returns the new commit, or `None` if the worktree was clean.
+pub fn commit_pending(worktree: &Worktree, message: &str) -> Result<Option<String>, GitError> {
+    let repo = Repository::open(&worktree.path)?;
+    let mut index = repo.index()?;
+    index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"], None)?;
+    index.write()?;
+    let head_tree = repo.head()?.peel_to_tree()?;
+    let diff = repo.diff_tree_to_index(Some(&head_tree), Some(&index), None)?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    commit(&worktree.path, message).map(Some)
+}
+
+/// Wrap up a session's worktree. `message` names the commit made from
+/// uncommitted changes and titles the pull request; `body` describes it.
+///
+/// A merge that conflicts is aborted and fails with
+/// [`GitError::Conflict`], leaving the worktree in place to retry or pick
+/// another action.
+pub fn finish(
+    worktree: &Worktree,
+    action: WorktreeAction,
+    message: &str,
+    body: Option<&str>,
+) -> Result<Finished, GitError> {
+    let mut finished = Finished::default();
+    if action != WorktreeAction::Discard && worktree.path.is_dir() {
+        finished.committed = commit_pending(worktree, message)?;
+    }
+    match action {
+        WorktreeAction::Merge => {
+            let base = worktree.base_branch.as_deref().ok_or_else(|| {
+                GitError::Other("worktree was created from a detached HEAD".to_string())
+            })?;
+            let current = Repository::open(&worktree.repo)?
+                .head()
+                .ok()
+                .and_then(|h| h.shorthand().map(String::from));
+            if current.as_deref() != Some(base) {
+                return Err(GitError::Conflict(format!(
+                    "{} has {} checked out, not {base}",
+                    worktree.repo.display(),
+                    current.as_deref().unwrap_or("a detached HEAD"),
+                )));
+            }
+            // Aborting a failed merge would take uncommitted changes with it
+            let dirty = uncommitted(&worktree.repo)?;
+            if !dirty.is_empty() {
+                return Err(GitError::Conflict(format!(
+                    "{} has uncommitted changes: {}",
+                    worktree.repo.display(),
+                    dirty.join(", "),
+                )));
+            }
+            if let Err(e) = git(
+                &worktree.repo,
+                &["merge", "--no-ff", "--no-edit", &worktree.branch],
+            ) {
+                let _ = git(&worktree.repo, &["merge", "--abort"]);
+                return Err(GitError::Conflict(e.to_string()));
+            }
+            finished.merged = super::status::head_commit(&worktree.repo)?;
+            remove(worktree)?;
+            git(&worktree.repo, &["branch", "-d", &worktree.branch])?;
+        }
+        WorktreeAction::Pr => {
+            git(&worktree.path, &["push", "-u", "origin", &worktree.branch])?;
+            let mut args = vec![
+                "pr",
+                "create",
+                "--head",
+                &worktree.branch,
+                "--title",
+                message,
+                "--body",
+                body.unwrap_or_default(),
+            ];
+            if let Some(base) = worktree.base_branch.as_deref() {
+                args.extend(["--base", base]);
+            }
+            finished.url = Some(run(&worktree.path, "gh", &args)?);
+            remove(worktree)?;
+            finished.branch_kept = true;
+        }
+        WorktreeAction::Keep => {
+            remove(worktree)?;
+            finished.branch_kept = true;
+        }
+        WorktreeAction::Discard => {
+            remove(worktree)?;
+            git(&worktree.repo, &["branch", "-D", &worktree.branch])?;
+        }
+    }
+    Ok(finished)
+}
+
+/// Tracked files with staged or unstaged changes in `repo`'s checkout.
+fn uncommitted(repo: &Path) -> Result<Vec<String>, GitError> {
+    let repo = Repository::open(repo)?;
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut opts))?;
+    Ok(statuses
+        .iter()
+        .filter_map(|entry| entry.path().map(String::from))
+        .collect())
+}
+
+/// Remove the checkout; only prune the bookkeeping if it is already gone.
+fn remove(worktree: &Worktree) -> Result<(), GitError> {
+    if worktree.path.exists() {
+        git(
+            &worktree.repo,
+            &[
+                "worktree",
+                "remove",
+                "--force",
+                &worktree.path.to_string_lossy(),
+            ],
+        )?;
+    } else {
+        git(&worktree.repo, &["worktree", "prune"])?;
+    }
+    Ok(())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, GitError> {
+    run(dir, "git", args)
+}
+
+/// Run `program` in `dir` and return its trimmed stdout.
+fn run(dir: &Path, program: &str, args: &[&str]) -> Result<String, GitError> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(dir)
+        .envs(crate::proxy::upstream::global().child_env());
+    // Same fallback identity as `commit` for merge commits.
+    if program == "git" && Repository::discover(dir).is_ok_and(|r| r.signature().is_err()) {
+        command.envs([
+            ("GIT_AUTHOR_NAME", FALLBACK_NAME),
+            ("GIT_AUTHOR_EMAIL", FALLBACK_EMAIL),
+            ("GIT_COMMITTER_NAME", FALLBACK_NAME),
+            ("GIT_COMMITTER_EMAIL", FALLBACK_EMAIL),
+        ]);
+    }
+    let output = command
+        .output()
+        .map_err(|e| GitError::Other(format!("{program} failed: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(GitError::Other(format!(
+            "{program} {} failed: {}",
+            args.first().unwrap_or(&""),
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("app");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        git(&root, &["init", "-q", "-b", "main"]).unwrap();
+        git(&root, &["config", "user.name", "Test Author"]).unwrap();
+        git(&root, &["config", "user.email", "test@example.com"]).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn a() {}\n").unwrap();
+        git(&root, &["add", "-A"]).unwrap();
+        git(&root, &["commit", "-q", "-m", "initial"]).unwrap();
+        dir
+    }
+
+    fn branch_exists(repo: &Path, branch: &str) -> bool {
+        Repository::open(repo)
+            .unwrap()
+            .find_branch(branch, git2::BranchType::Local)
+            .is_ok()
+    }
+
+    #[test]
+    fn create_forks_a_session_branch_in_its_own_checkout() {
+        let dir = create_test_repo();
+        let id = Uuid::new_v4();
+        let wt = create(&dir.path().join("app/src"), id, &dir.path().join("wt")).unwrap();
+
+        assert_eq!(wt.branch, format!("noaide/{id}"));
+        assert_eq!(wt.base_branch.as_deref(), Some("main"));
+        assert_eq!(wt.subdir, PathBuf::from("src"));
+        assert!(wt.session_dir().join("lib.rs").is_file());
+        assert_eq!(
+            wt.common_dir().unwrap().canonicalize().unwrap(),
+            wt.repo.join(".git")
+        );
+
+        // Edits in the worktree leave the original checkout alone
+        std::fs::write(wt.session_dir().join("lib.rs"), "fn b() {}\n").unwrap();
+        let original = std::fs::read_to_string(wt.repo.join("src/lib.rs")).unwrap();
+        assert_eq!(original, "fn a() {}\n");
+
+        let json = serde_json::to_value(&wt).unwrap();
+        assert_eq!(json["baseBranch"], "main");
+        let back: Worktree = serde_json::from_value(json).unwrap();
+        assert_eq!(back, wt);
+    }
+
+    #[test]
+    fn create_outside_a_repository_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = create(dir.path(), Uuid::new_v4(), &dir.path().join("wt")).unwrap_err();
+        assert!(matches!(err, GitError::NotRepo(_)));
+    }
+
+    #[test]
+    fn merge_commits_leftovers_and_cleans_up() {
+        let dir = create_test_repo();
+        let wt = create(
+            &dir.path().join("app"),
+            Uuid::new_v4(),
+            &dir.path().join("wt"),
+        )
+        .unwrap();
+        std::fs::write(wt.path.join("src/new.rs"), "fn c() {}\n").unwrap();
+
+        let finished = finish(&wt, WorktreeAction::Merge, "add c", None).unwrap();
+        assert!(finished.committed.is_some());
+        assert!(finished.merged.is_some());
+        assert!(!finished.branch_kept);
+        assert!(wt.repo.join("src/new.rs").is_file());
+        assert!(!wt.path.exists());
+        assert!(!branch_exists(&wt.repo, &wt.branch));
+    }
+
+    #[test]
+    fn conflicting_merge_is_aborted_and_keeps_the_worktree() {
+        let dir = create_test_repo();
+        let wt = create(
+            &dir.path().join("app"),
+            Uuid::new_v4(),
+            &dir.path().join("wt"),
+        )
+        .unwrap();
+        std::fs::write(wt.path.join("src/lib.rs"), "fn ours() {}\n").unwrap();
+        std::fs::write(wt.repo.join("src/lib.rs"), "fn theirs() {}\n").unwrap();
+        git(&wt.repo, &["commit", "-q", "-am", "diverge"]).unwrap();
+
+        let err = finish(&wt, WorktreeAction::Merge, "edit", None).unwrap_err();
+        assert!(matches!(err, GitError::Conflict(_)));
+        assert!(wt.path.is_dir());
+        assert!(super::super::status::status(&wt.repo).unwrap().is_empty());
+
+        // Discarding afterwards still works
+        let finished = finish(&wt, WorktreeAction::Discard, "", None).unwrap();
+        assert_eq!(finished, Finished::default());
+        assert!(!wt.path.exists());
+        assert!(!branch_exists(&wt.repo, &wt.branch));
+    }
+
+    #[test]
+    fn merge_into_a_dirty_checkout_is_refused() {
+        let dir = create_test_repo();
+        let wt = create(
+            &dir.path().join("app"),
+            Uuid::new_v4(),
+            &dir.path().join("wt"),
+        )
+        .unwrap();
+        std::fs::write(wt.path.join("src/new.rs"), "fn c() {}\n").unwrap();
+        std::fs::write(wt.repo.join("src/lib.rs"), "fn wip() {}\n").unwrap();
+
+        let err = finish(&wt, WorktreeAction::Merge, "add c", None).unwrap_err();
+        assert!(matches!(err, GitError::Conflict(msg) if msg.contains("src/lib.rs")));
+        assert!(wt.path.is_dir());
+        assert!(!wt.repo.join("src/new.rs").exists());
+        let wip = std::fs::read_to_string(wt.repo.join("src/lib.rs")).unwrap();
+        assert_eq!(wip, "fn wip() {}\n");
+
+        // Untracked files in the checkout don't block the merge
+        git(&wt.repo, &["checkout", "--", "src/lib.rs"]).unwrap();
+        std::fs::write(wt.repo.join("notes.txt"), "todo\n").unwrap();
+        let finished = finish(&wt, WorktreeAction::Merge, "add c", None).unwrap();
+        assert!(finished.merged.is_some());
+        assert!(wt.repo.join("src/new.rs").is_file());
+    }
+
+    #[test]
+    fn keep_leaves_the_branch_with_the_changes() {
+        let dir = create_test_repo();
+        let wt = create(
+            &dir.path().join("app"),
+            Uuid::new_v4(),
+            &dir.path().join("wt"),
+        )
+        .unwrap();
+        std::fs::remove_file(wt.path.join("src/lib.rs")).unwrap();
+
+        let finished = finish(&wt, WorktreeAction::Keep, "remove a", None).unwrap();
+        assert!(finished.committed.is_some());
+        assert!(finished.branch_kept);
+        assert!(!wt.path.exists());
+        assert!(branch_exists(&wt.repo, &wt.branch));
+    }
+}
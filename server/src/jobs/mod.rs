@@ -0,0 +1,694 @@
+//! Headless prompt queue.
+//!
+//! A job is a prompt run without a human: each attempt spawns the CLI as a
+//! managed session in its non-interactive mode (`claude -p`, `codex exec`,
+//! ...), so it goes through the per-session proxy, its profile, activity
+//! tracking and terminal recording like any interactive session. The queue
+//! decides what runs next under the concurrency limits, applies the retry
+//! policy and writes every change through to `jobs.json`, so queued work
+//! survives a restart. The server drives attempts through
+//! [`JobQueue::start_next`] and [`JobQueue::finish`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, RwLock, broadcast};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::bus::{EventEnvelope, EventSource};
+use crate::git::DiffStat;
+use crate::session::ExitStatus;
+
+/// Lifecycle of a job and of each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    TimedOut,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string())).ok()
+    }
+}
+
+fn default_cli_type() -> String {
+    "claude".to_string()
+}
+
+/// What a job runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSpec {
+    /// Empty on submission when `prompt_file` is given.
+    #[serde(default)]
+    pub prompt: String,
+    /// File the prompt was read from, relative to `working_dir` or absolute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_file: Option<PathBuf>,
+    pub working_dir: PathBuf,
+    /// Source name as accepted by the managed-session API ("claude", ...).
+    #[serde(default = "default_cli_type")]
+    pub cli_type: String,
+    /// Proxy profile; defaults to the one matching `working_dir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Skip the CLI's permission prompts. Without it, tools that need
+    /// approval are refused in non-interactive mode.
+    #[serde(default)]
+    pub auto_approve: bool,
+    /// Per-attempt timeout; [`JobLimits::default_timeout`] if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Further attempts after one fails or times out.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Extra environment for the CLI process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl JobSpec {
+    /// Check the spec and load the prompt from `prompt_file` if no prompt
+    /// was given inline.
+    pub fn prepare(&mut self) -> Result<(), String> {
+        if !self.working_dir.is_dir() {
+            return Err("workingDir does not exist or is not a directory".to_string());
+        }
+        let source = crate::parser::registry::by_name(&self.cli_type)
+            .ok_or_else(|| format!("unknown cliType: {}", self.cli_type))?;
+        if source.headless_args("").is_none() {
+            return Err(format!("{} has no non-interactive mode", self.cli_type));
+        }
+        if self.prompt.trim().is_empty()
+            && let Some(file) = &self.prompt_file
+        {
+            let path = self.working_dir.join(file);
+            self.prompt = std::fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read prompt file {}: {e}", path.display()))?;
+        }
+        if self.prompt.trim().is_empty() {
+            return Err("prompt or promptFile is required".to_string());
+        }
+        Ok(())
+    }
+
+    /// CLI arguments that run the prompt non-interactively.
+    pub fn headless_args(&self) -> Option<Vec<String>> {
+        crate::parser::registry::by_name(&self.cli_type)?.headless_args(&self.prompt)
+    }
+}
+
+/// One run of a job in its own managed session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobAttempt {
+    pub session_id: Uuid,
+    /// Epoch milliseconds.
+    pub started_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+    pub status: JobStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The CLI's transcript, once the watcher linked it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+/// How an attempt ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Exited(ExitStatus),
+    TimedOut,
+    Cancelled,
+    /// Could not spawn, or the session went away without an exit status.
+    Failed(String),
+}
+
+/// What the server collected after an attempt.
+#[derive(Debug, Clone, Default)]
+pub struct AttemptResults {
+    pub transcript: Option<PathBuf>,
+    pub cost_usd: Option<f64>,
+    /// Changes since the job's first attempt started.
+    pub diff_stat: Option<DiffStat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub spec: JobSpec,
+    pub status: JobStatus,
+    /// Epoch milliseconds.
+    pub submitted_at: i64,
+    /// A retry does not start before this (epoch milliseconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+    #[serde(default)]
+    pub attempts: Vec<JobAttempt>,
+    /// `HEAD` of `working_dir` when the first attempt started; the diff is
+    /// taken against it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_stat: Option<DiffStat>,
+}
+
+impl Job {
+    pub fn new(spec: JobSpec, now_ms: i64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            spec,
+            status: JobStatus::Queued,
+            submitted_at: now_ms,
+            not_before: None,
+            finished_at: None,
+            attempts: Vec::new(),
+            base_commit: None,
+            diff_stat: None,
+        }
+    }
+
+    /// Timeout of one attempt.
+    pub fn timeout(&self, limits: &JobLimits) -> Duration {
+        self.spec
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(limits.default_timeout)
+    }
+
+    /// Session of the current or last attempt.
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.attempts.last().map(|a| a.session_id)
+    }
+
+    /// Summed over all attempts that reported a cost.
+    pub fn cost_usd(&self) -> Option<f64> {
+        self.attempts
+            .iter()
+            .filter_map(|a| a.cost_usd)
+            .reduce(|a, b| a + b)
+    }
+
+    fn start(&mut self, now_ms: i64) {
+        self.status = JobStatus::Running;
+        self.not_before = None;
+        self.attempts.push(JobAttempt {
+            session_id: Uuid::new_v4(),
+            started_at: now_ms,
+            finished_at: None,
+            status: JobStatus::Running,
+            exit_code: None,
+            signal: None,
+            error: None,
+            transcript: None,
+            cost_usd: None,
+        });
+    }
+
+    /// Close the running attempt and decide whether to retry.
+    fn apply(&mut self, outcome: Outcome, now_ms: i64, limits: &JobLimits) {
+        let Some(attempt) = self.attempts.last_mut() else {
+            return;
+        };
+        let status = match &outcome {
+            Outcome::Exited(status) if status.success() => JobStatus::Succeeded,
+            Outcome::Exited(_) | Outcome::Failed(_) => JobStatus::Failed,
+            Outcome::TimedOut => JobStatus::TimedOut,
+            Outcome::Cancelled => JobStatus::Cancelled,
+        };
+        attempt.status = status;
+        attempt.finished_at = Some(now_ms);
+        match outcome {
+            Outcome::Exited(ExitStatus::Code(code)) => attempt.exit_code = Some(code),
+            Outcome::Exited(ExitStatus::Signal(signal)) => attempt.signal = Some(signal),
+            Outcome::Failed(error) => attempt.error = Some(error),
+            Outcome::TimedOut | Outcome::Cancelled => {}
+        }
+
+        let retries_used = self.attempts.len() as u32 - 1;
+        if matches!(status, JobStatus::Failed | JobStatus::TimedOut)
+            && retries_used < self.spec.max_retries
+        {
+            self.status = JobStatus::Queued;
+            let delay = limits.retry_delay * self.attempts.len() as u32;
+            self.not_before = Some(now_ms + delay.as_millis() as i64);
+        } else {
+            self.status = status;
+            self.finished_at = Some(now_ms);
+        }
+    }
+
+    /// The `jobs/status` event for the job's current state.
+    pub fn envelope(&self) -> EventEnvelope {
+        let attempt = self.attempts.last();
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "type": "job_status",
+            "job_id": self.id.to_string(),
+            "status": self.status,
+            "attempt": self.attempts.len(),
+            "session_id": attempt.map(|a| a.session_id.to_string()),
+            "working_dir": self.spec.working_dir,
+            "cli_type": self.spec.cli_type,
+            "exit_code": attempt.and_then(|a| a.exit_code),
+            "cost_usd": self.cost_usd(),
+        }))
+        .unwrap_or_default();
+        EventEnvelope::new(EventSource::User, 0, 0, self.session_id(), payload)
+    }
+}
+
+/// Scheduling limits, from the environment.
+#[derive(Debug, Clone)]
+pub struct JobLimits {
+    /// Jobs running at once (`NOAIDE_JOB_CONCURRENCY`, default 2). Jobs in
+    /// the same working directory never run at the same time.
+    pub max_concurrent: usize,
+    /// Attempt timeout for jobs without their own (`NOAIDE_JOB_TIMEOUT`
+    /// seconds, default 3600).
+    pub default_timeout: Duration,
+    /// Wait before a retry, multiplied by the attempts so far
+    /// (`NOAIDE_JOB_RETRY_DELAY` seconds, default 60).
+    pub retry_delay: Duration,
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 2,
+            default_timeout: Duration::from_secs(3600),
+            retry_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl JobLimits {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let defaults = Self::default();
+        Self {
+            max_concurrent: var("NOAIDE_JOB_CONCURRENCY")
+                .map(|n| n.max(1) as usize)
+                .unwrap_or(defaults.max_concurrent),
+            default_timeout: var("NOAIDE_JOB_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.default_timeout),
+            retry_delay: var("NOAIDE_JOB_RETRY_DELAY")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.retry_delay),
+        }
+    }
+}
+
+/// The oldest queued job that may start now.
+fn pick_next(jobs: &HashMap<Uuid, Job>, now_ms: i64, limits: &JobLimits) -> Option<Uuid> {
+    let running: Vec<&Job> = jobs
+        .values()
+        .filter(|j| j.status == JobStatus::Running)
+        .collect();
+    if running.len() >= limits.max_concurrent {
+        return None;
+    }
+    let busy: HashSet<&Path> = running
+        .iter()
+        .map(|j| j.spec.working_dir.as_path())
+        .collect();
+    jobs.values()
+        .filter(|j| j.status == JobStatus::Queued)
+        .filter(|j| j.not_before.is_none_or(|t| t <= now_ms))
+        .filter(|j| !busy.contains(j.spec.working_dir.as_path()))
+        .min_by_key(|j| (j.submitted_at, j.id))
+        .map(|j| j.id)
+}
+
+/// The job queue, written through to `{dir}/jobs.json`.
+#[derive(Clone)]
+pub struct JobQueue {
+    dir: Arc<PathBuf>,
+    jobs: Arc<RwLock<HashMap<Uuid, Job>>>,
+    wake: Arc<Notify>,
+    cancels: broadcast::Sender<Uuid>,
+}
+
+impl JobQueue {
+    /// Read the queue in `dir`. Attempts that were running when the server
+    /// stopped count as failed; their jobs are retried if they have retries
+    /// left.
+    pub fn load(dir: PathBuf, now_ms: i64, limits: &JobLimits) -> Self {
+        let path = dir.join("jobs.json");
+        let mut jobs: HashMap<Uuid, Job> = match std::fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str::<Vec<Job>>(&data) {
+                Ok(list) => list.into_iter().map(|j| (j.id, j)).collect(),
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "ignoring unreadable job queue");
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+        for job in jobs.values_mut() {
+            if job.status == JobStatus::Running {
+                job.apply(
+                    Outcome::Failed("interrupted by a server restart".to_string()),
+                    now_ms,
+                    limits,
+                );
+            }
+        }
+        let (cancels, _) = broadcast::channel(64);
+        Self {
+            dir: Arc::new(dir),
+            jobs: Arc::new(RwLock::new(jobs)),
+            wake: Arc::new(Notify::new()),
+            cancels,
+        }
+    }
+
+    pub async fn submit(&self, spec: JobSpec, now_ms: i64) -> Job {
+        let job = Job::new(spec, now_ms);
+        let mut jobs = self.jobs.write().await;
+        jobs.insert(job.id, job.clone());
+        self.save(&jobs).await;
+        self.wake.notify_one();
+        job
+    }
+
+    pub async fn get(&self, id: Uuid) -> Option<Job> {
+        self.jobs.read().await.get(&id).cloned()
+    }
+
+    /// All jobs, oldest submission first.
+    pub async fn list(&self) -> Vec<Job> {
+        let mut list: Vec<Job> = self.jobs.read().await.values().cloned().collect();
+        list.sort_by_key(|j| (j.submitted_at, j.id));
+        list
+    }
+
+    /// Start the next runnable job: it becomes `Running` with a new attempt
+    /// whose session id the caller spawns under.
+    pub async fn start_next(&self, now_ms: i64, limits: &JobLimits) -> Option<Job> {
+        let mut jobs = self.jobs.write().await;
+        let id = pick_next(&jobs, now_ms, limits)?;
+        let job = jobs.get_mut(&id)?;
+        job.start(now_ms);
+        let job = job.clone();
+        self.save(&jobs).await;
+        Some(job)
+    }
+
+    /// Remember the commit the job's diff is taken against.
+    pub async fn set_base_commit(&self, id: Uuid, commit: Option<String>) {
+        let mut jobs = self.jobs.write().await;
+        if let Some(job) = jobs.get_mut(&id) {
+            job.base_commit = commit;
+            self.save(&jobs).await;
+        }
+    }
+
+    /// End the running attempt of `id`, store what was collected and
+    /// requeue the job if it has retries left.
+    pub async fn finish(
+        &self,
+        id: Uuid,
+        outcome: Outcome,
+        results: AttemptResults,
+        now_ms: i64,
+        limits: &JobLimits,
+    ) -> Option<Job> {
+        let mut jobs = self.jobs.write().await;
+        let job = jobs.get_mut(&id)?;
+        job.apply(outcome, now_ms, limits);
+        if let Some(attempt) = job.attempts.last_mut() {
+            attempt.transcript = results.transcript;
+            attempt.cost_usd = results.cost_usd;
+        }
+        if results.diff_stat.is_some() {
+            job.diff_stat = results.diff_stat;
+        }
+        let job = job.clone();
+        self.save(&jobs).await;
+        self.wake.notify_one();
+        Some(job)
+    }
+
+    /// Cancel a job. A queued job ends at once; a running one ends when its
+    /// runner sees the request (see [`Self::cancellations`]). Returns the
+    /// job, or `None` if it does not exist.
+    pub async fn cancel(&self, id: Uuid, now_ms: i64) -> Option<Job> {
+        let mut jobs = self.jobs.write().await;
+        let job = jobs.get_mut(&id)?;
+        match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Cancelled;
+                job.not_before = None;
+                job.finished_at = Some(now_ms);
+                let job = job.clone();
+                self.save(&jobs).await;
+                Some(job)
+            }
+            JobStatus::Running => {
+                let _ = self.cancels.send(id);
+                Some(job.clone())
+            }
+            _ => Some(job.clone()),
+        }
+    }
+
+    /// Ids of running jobs that were asked to stop.
+    pub fn cancellations(&self) -> broadcast::Receiver<Uuid> {
+        self.cancels.subscribe()
+    }
+
+    /// Forget a finished job and its diff. Returns `None` for unknown or
+    /// unfinished jobs.
+    pub async fn remove(&self, id: Uuid) -> Option<Job> {
+        let mut jobs = self.jobs.write().await;
+        if !jobs.get(&id)?.status.is_finished() {
+            return None;
+        }
+        let job = jobs.remove(&id)?;
+        self.save(&jobs).await;
+        let _ = tokio::fs::remove_file(self.diff_path(id)).await;
+        Some(job)
+    }
+
+    /// Wait until a job was submitted or finished.
+    pub async fn changed(&self) {
+        self.wake.notified().await;
+    }
+
+    /// Where the patch of a job is kept.
+    pub fn diff_path(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{id}.diff"))
+    }
+
+    async fn save(&self, jobs: &HashMap<Uuid, Job>) {
+        let mut list: Vec<&Job> = jobs.values().collect();
+        list.sort_by_key(|j| (j.submitted_at, j.id));
+        let path = self.dir.join("jobs.json");
+        let result = async {
+            tokio::fs::create_dir_all(&*self.dir).await?;
+            let json = serde_json::to_string_pretty(&list).map_err(std::io::Error::other)?;
+            let tmp = path.with_extension("tmp");
+            tokio::fs::write(&tmp, json + "\n").await?;
+            tokio::fs::rename(&tmp, &path).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!(path = %path.display(), error = %e, "failed to persist job queue");
+        }
+    }
+}
+
+/// Where the queue and job diffs live: `NOAIDE_JOBS_DIR`, else
+/// `/data/noaide/jobs`.
+pub fn jobs_dir() -> PathBuf {
+    std::env::var("NOAIDE_JOBS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("jobs"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(dir: &Path) -> JobSpec {
+        JobSpec {
+            prompt: "update the dependencies".into(),
+            prompt_file: None,
+            working_dir: dir.to_path_buf(),
+            cli_type: "claude".into(),
+            profile: None,
+            auto_approve: true,
+            timeout_secs: None,
+            max_retries: 1,
+            env: BTreeMap::new(),
+        }
+    }
+
+    fn limits() -> JobLimits {
+        JobLimits {
+            max_concurrent: 2,
+            default_timeout: Duration::from_secs(60),
+            retry_delay: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn prompt_files_are_read_relative_to_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("nightly.md"), "run the linters\n").unwrap();
+        let mut job = spec(dir.path());
+        job.prompt.clear();
+        job.prompt_file = Some("nightly.md".into());
+        job.prepare().unwrap();
+        assert_eq!(job.prompt, "run the linters\n");
+        assert_eq!(
+            job.headless_args(),
+            Some(vec!["-p".to_string(), "run the linters\n".to_string()])
+        );
+
+        job.prompt.clear();
+        job.prompt_file = None;
+        assert!(job.prepare().is_err());
+        job.prompt = "x".into();
+        job.cli_type = "nano".into();
+        assert!(job.prepare().is_err());
+    }
+
+    #[test]
+    fn failures_are_retried_after_a_delay_then_given_up() {
+        let mut job = Job::new(spec(Path::new("/work/app")), 0);
+        job.start(1_000);
+        job.apply(Outcome::Exited(ExitStatus::Code(1)), 2_000, &limits());
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.not_before, Some(12_000));
+        assert_eq!(job.attempts[0].exit_code, Some(1));
+
+        job.start(12_000);
+        assert_ne!(job.attempts[0].session_id, job.attempts[1].session_id);
+        job.apply(Outcome::TimedOut, 20_000, &limits());
+        assert_eq!(job.status, JobStatus::TimedOut);
+        assert_eq!(job.finished_at, Some(20_000));
+
+        let mut ok = Job::new(spec(Path::new("/work/app")), 0);
+        ok.start(0);
+        ok.apply(Outcome::Exited(ExitStatus::Code(0)), 5, &limits());
+        assert_eq!(ok.status, JobStatus::Succeeded);
+        let mut cancelled = Job::new(spec(Path::new("/work/app")), 0);
+        cancelled.start(0);
+        cancelled.apply(Outcome::Cancelled, 5, &limits());
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn scheduling_respects_limits_projects_and_backoff() {
+        let limits = JobLimits {
+            max_concurrent: 2,
+            ..limits()
+        };
+        let mut jobs = HashMap::new();
+        let mut add = |dir: &str, submitted_at: i64| {
+            let job = Job::new(spec(Path::new(dir)), submitted_at);
+            let id = job.id;
+            jobs.insert(id, job);
+            id
+        };
+        let a1 = add("/work/a", 1);
+        let a2 = add("/work/a", 2);
+        let b = add("/work/b", 3);
+        let c = add("/work/c", 4);
+
+        assert_eq!(pick_next(&jobs, 0, &limits), Some(a1));
+        jobs.get_mut(&a1).unwrap().start(0);
+        // a2 waits for a1: same working directory.
+        assert_eq!(pick_next(&jobs, 0, &limits), Some(b));
+        jobs.get_mut(&b).unwrap().start(0);
+        assert_eq!(pick_next(&jobs, 0, &limits), None, "two running");
+
+        // b fails and backs off for 10 s, so c goes first.
+        let failed = Outcome::Failed("boom".into());
+        jobs.get_mut(&b).unwrap().apply(failed, 0, &limits);
+        assert_eq!(pick_next(&jobs, 0, &limits), Some(c));
+        jobs.get_mut(&c).unwrap().start(0);
+
+        let done = Outcome::Exited(ExitStatus::Code(0));
+        jobs.get_mut(&a1).unwrap().apply(done, 5_000, &limits);
+        assert_eq!(pick_next(&jobs, 5_000, &limits), Some(a2));
+        assert_eq!(jobs[&b].not_before, Some(10_000));
+        jobs.get_mut(&a2).unwrap().start(5_000);
+        assert_eq!(pick_next(&jobs, 10_000, &limits), None, "two running");
+    }
+
+    #[tokio::test]
+    async fn queue_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let queue = JobQueue::load(dir.path().to_path_buf(), 0, &limits());
+        let first = queue.submit(spec(project.path()), 1).await;
+        let second = queue.submit(spec(project.path()), 2).await;
+        let running = queue.start_next(3, &limits()).await.unwrap();
+        assert_eq!(running.id, first.id);
+        assert_eq!(queue.start_next(3, &limits()).await, None);
+        assert_eq!(
+            queue.cancel(second.id, 4).await.unwrap().status,
+            JobStatus::Cancelled
+        );
+
+        let reloaded = JobQueue::load(dir.path().to_path_buf(), 100, &limits());
+        let job = reloaded.get(first.id).await.unwrap();
+        assert_eq!(job.status, JobStatus::Queued, "interrupted, one retry left");
+        assert_eq!(
+            job.attempts[0].error.as_deref(),
+            Some("interrupted by a server restart")
+        );
+        assert_eq!(job.not_before, Some(10_100));
+        assert_eq!(reloaded.start_next(10_000, &limits()).await, None);
+        assert_eq!(
+            reloaded.start_next(10_100, &limits()).await.unwrap().id,
+            first.id
+        );
+
+        let finished = reloaded
+            .finish(
+                first.id,
+                Outcome::Exited(ExitStatus::Code(0)),
+                AttemptResults {
+                    cost_usd: Some(0.25),
+                    ..Default::default()
+                },
+                10_200,
+                &limits(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(finished.status, JobStatus::Succeeded);
+        assert_eq!(finished.cost_usd(), Some(0.25));
+        assert!(reloaded.remove(second.id).await.is_some());
+        assert_eq!(reloaded.list().await.len(), 1);
+    }
+}
@@ -1,13 +1,19 @@
+pub mod analytics;
+pub mod backup;
 pub mod bus;
 pub mod cache;
 pub mod db;
 pub mod discovery;
 pub mod ecs;
+pub mod export;
 pub mod files;
 pub mod git;
+pub mod jobs;
 pub mod parser;
 pub mod plan;
 pub mod proxy;
+pub mod retention;
+pub mod search;
 pub mod session;
 pub mod teams;
 pub mod transport;
@@ -7,7 +7,7 @@ use axum::Router;
 use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
 use axum::extract::{Path, Query, State};
 use axum::http::{HeaderValue, StatusCode};
-use axum::routing::{delete, get, post};
+use axum::routing::{delete, get, post, put};
 use tokio::sync::RwLock;
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
@@ -22,6 +22,9 @@ use noaide_server::ecs::components::{ApiRequestComponent, SessionComponent, Sess
 use noaide_server::ecs::{EcsWorld, SharedEcsWorld};
 use noaide_server::parser;
 use noaide_server::session::SessionManager;
+use noaide_server::session::activity::{Activity, ActivityBoard, Observation};
+use noaide_server::session::recording;
+use noaide_server::session::resume::{LaunchStore, ManagedLaunch};
 use noaide_server::teams::{AgentStatus, TeamDiscovery, TopologyBuilder, load_inboxes, load_tasks};
 use noaide_server::transport::TransportServer;
 use noaide_server::watcher::FileEventKind;
@@ -62,6 +65,18 @@ struct AppState {
     plan_base_dir: Arc<PathBuf>,
     /// Maps session UUID → plan name (for auto-selecting plan in Plan tab).
     session_plan_mapping: Arc<RwLock<HashMap<Uuid, String>>>,
+    /// Limbo database (proxy captures, search index).
+    db: Arc<Db>,
+    /// Retention policy and compaction job.
+    retention: noaide_server::retention::Compactor,
+    /// State locations for backups and restores.
+    backup_layout: Arc<noaide_server::backup::StateLayout>,
+    /// Per-session agent activity (thinking, running a tool, waiting, ...).
+    activity: ActivityBoard,
+    /// How each managed session was launched, for respawning after a restart.
+    launches: LaunchStore,
+    /// Headless prompts waiting for or running in a managed session.
+    jobs: noaide_server::jobs::JobQueue,
 }
 
 const MANAGED_SESSIONS_FILE: &str = "/data/noaide/managed-sessions.json";
@@ -327,7 +342,11 @@ async fn main() -> anyhow::Result<()> {
     // ECS World
     let ecs = EcsWorld::new().shared();
 
-    // Database
+    // Database — a restore staged through /api/backups goes in place first.
+    let backup_layout = noaide_server::backup::StateLayout::from_env();
+    if let Err(e) = noaide_server::backup::apply_staged(&backup_layout).await {
+        warn!(error = %e, "failed to apply staged restore, starting with current state");
+    }
     let db_path = std::env::var("NOAIDE_DB_PATH").unwrap_or_else(|_| "/data/noaide/ide.db".into());
     let db = Arc::new(Db::open(&db_path).await?);
 
@@ -361,32 +380,46 @@ async fn main() -> anyhow::Result<()> {
     // API Proxy State (in-memory captured requests)
     let (proxy_state, proxy_rx) = noaide_server::proxy::create_proxy_state();
 
-    // Recovery: load persisted proxy requests from DB into in-memory cache
-    match db.get_all_api_requests().await {
+    // Recovery: only the most recent proxy requests go into the in-memory
+    // cache; older ones are read from the DB on demand.
+    match db
+        .get_recent_api_requests(noaide_server::proxy::handler::MAX_CAPTURED_REQUESTS)
+        .await
+    {
         Ok(persisted) => {
             if !persisted.is_empty() {
                 let mut cap = proxy_state.captured.write().await;
                 for component in &persisted {
                     cap.push_back(component_to_proxy_log(component));
                 }
-                info!(count = persisted.len(), "recovered proxy requests from DB");
+                info!(
+                    count = persisted.len(),
+                    "recovered recent proxy requests from DB"
+                );
             }
         }
         Err(e) => {
-            warn!(error = %e, "failed to load proxy requests from DB (fresh schema?)");
+            warn!(error = %e, "failed to load proxy requests from DB");
         }
     }
 
+    // Retention: stale proxy configs go before the rest are loaded; the
+    // scheduled compaction handles everything else.
+    let retention = noaide_server::retention::Compactor::new(
+        db.clone(),
+        noaide_server::retention::StoragePaths::default(),
+    );
+    let expired = retention.expire_proxy_configs(&retention.policy().await.proxy_configs, false);
+    if expired.removed > 0 {
+        info!(deleted = expired.removed, "cleaned up old proxy configs");
+    }
+    tokio::spawn(retention.clone().run());
+
     // Recovery: load persisted proxy configs (modes, inject, rewrite) from disk
-    noaide_server::proxy::persist::startup_cleanup();
-    noaide_server::proxy::persist::load_all_into_stores(
-        &proxy_state.proxy_modes,
-        &proxy_state.inject_store,
-        &proxy_state.rewrite_store,
-        &proxy_state.network_rules,
-        &proxy_state.intercept_modes,
-    )
-    .await;
+    noaide_server::proxy::persist::load_all_into_stores(&proxy_state).await;
+    if let Err(e) = noaide_server::proxy::profiles::load_from_disk(&proxy_state.profiles) {
+        warn!(error = %e, "failed to restore proxy profiles from disk");
+    }
 
     let proxy_port: u16 = std::env::var("NOAIDE_PROXY_PORT")
         .ok()
@@ -444,6 +477,14 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    let activity = ActivityBoard::default();
+    let launches = LaunchStore::load(noaide_server::session::resume::launches_path());
+    let job_limits = noaide_server::jobs::JobLimits::from_env();
+    let jobs = noaide_server::jobs::JobQueue::load(
+        noaide_server::jobs::jobs_dir(),
+        epoch_ms(),
+        &job_limits,
+    );
     let app_state = AppState {
         ecs: ecs.clone(),
         session_paths: session_paths.clone(),
@@ -465,6 +506,12 @@ async fn main() -> anyhow::Result<()> {
         project_watches: project_watches.clone(),
         plan_base_dir: plan_base_dir.clone(),
         session_plan_mapping: session_plan_mapping.clone(),
+        db: db.clone(),
+        retention: retention.clone(),
+        backup_layout: Arc::new(backup_layout),
+        activity: activity.clone(),
+        launches: launches.clone(),
+        jobs,
     };
     let mut app = Router::new()
         .route(
@@ -476,14 +523,33 @@ async fn main() -> anyhow::Result<()> {
         )
         .route("/api/sessions", get(api_get_sessions))
         .route("/api/sessions/managed", post(api_create_managed_session))
+        .route("/api/sessions/resumable", get(api_list_resumable_sessions))
         .route("/api/sessions/{id}/messages", get(api_get_messages))
+        .route("/api/sessions/{id}/messages/query", get(api_query_messages))
         .route("/api/sessions/{id}/append", post(api_append_message))
         .route("/api/sessions/{id}/images", post(api_queue_images))
         .route("/api/sessions/{id}/send", post(api_send_message))
         .route("/api/sessions/{id}/input", post(api_send_input))
         .route("/api/sessions/{id}/stats", get(api_get_session_stats))
+        .route("/api/sessions/{id}/tools", get(api_get_session_tools))
+        .route("/api/sessions/{id}/activity", get(api_get_session_activity))
+        .route("/api/sessions/{id}/recordings", get(api_list_recordings))
+        .route(
+            "/api/sessions/{id}/recordings/{name}",
+            get(api_get_recording),
+        )
+        .route("/api/sessions/{id}/terminal", get(api_session_terminal))
+        .route("/api/sessions/{id}/resize", post(api_resize_session))
+        .route("/api/sessions/{id}/tree", get(api_get_session_tree))
+        .route("/api/sessions/{id}/export", get(api_export_session))
         .route("/api/sessions/{id}/close", post(api_close_session))
+        .route("/api/sessions/{id}/worktree", post(api_finish_worktree))
+        .route("/api/sessions/{id}/resume", post(api_resume_session))
         .route("/api/sessions/{id}", delete(api_delete_session))
+        .route("/api/jobs", get(api_list_jobs).post(api_submit_job))
+        .route("/api/jobs/{id}", get(api_get_job).delete(api_delete_job))
+        .route("/api/jobs/{id}/diff", get(api_get_job_diff))
+        .route("/api/jobs/{id}/cancel", post(api_cancel_job))
         .route("/api/proxy/requests", get(api_get_proxy_requests))
         .route("/api/proxy/requests", delete(api_clear_proxy_requests))
         .route(
@@ -558,7 +624,22 @@ async fn main() -> anyhow::Result<()> {
             "/api/proxy/config/{session_id}",
             get(api_get_proxy_config).put(api_set_proxy_config),
         )
+        .route(
+            "/api/proxy/config/{session_id}/profile",
+            get(api_get_session_profile).put(api_set_session_profile),
+        )
+        .route("/api/proxy/profiles", get(api_list_profiles))
+        .route(
+            "/api/proxy/profiles/{name}",
+            put(api_put_profile).delete(api_delete_profile),
+        )
         .route("/api/proxy/presets", get(api_list_presets))
+        .route("/api/proxy/upstream", get(api_get_upstream_proxy))
+        .route(
+            "/api/proxy/preflight/{session_id}",
+            get(api_get_preflight_policy).put(api_set_preflight_policy),
+        )
+        .route("/api/proxy/model-limits", get(api_get_model_limits))
         .route("/api/proxy/keys", get(api_list_keys).post(api_add_key))
         .route(
             "/api/proxy/keys/{key_id}",
@@ -586,6 +667,31 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/git/diff-hunks", get(api_git_diff_hunks))
         .route("/api/git/prs", get(api_git_pr_list))
         .route("/api/git/prs", post(api_git_pr_create))
+        .route("/api/tools", get(api_get_tools))
+        .route("/api/search", get(api_search))
+        .route("/api/analytics", get(api_analytics))
+        .route("/api/analytics/export", get(api_export_analytics))
+        .route("/api/retention", get(api_get_retention))
+        .route("/api/retention", put(api_put_retention))
+        .route("/api/retention/report", get(api_retention_report))
+        .route("/api/retention/compact", post(api_retention_compact))
+        .route("/api/retention/archives", get(api_list_archives))
+        .route(
+            "/api/retention/archives/{id}/restore",
+            post(api_restore_archive),
+        )
+        .route(
+            "/api/backups",
+            get(api_list_backups).post(api_create_backup),
+        )
+        .route(
+            "/api/backups/{name}",
+            get(api_download_backup)
+                .put(api_upload_backup)
+                .layer(axum::extract::DefaultBodyLimit::disable()),
+        )
+        .route("/api/backups/{name}/restore", post(api_restore_backup))
+        .route("/api/schema-drift", get(api_get_schema_drift))
         .route("/api/browse", get(api_browse_directories))
         .route("/api/sessions/{id}/files", get(api_list_session_files))
         .route(
@@ -612,7 +718,7 @@ async fn main() -> anyhow::Result<()> {
                 }
             }),
         )
-        .with_state(app_state);
+        .with_state(app_state.clone());
 
     // Production mode: serve the prebuilt frontend bundle and emit
     // hardened security headers. Triggered by NOAIDE_STATIC_DIR being
@@ -721,7 +827,39 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    // Schema drift → SYSTEM_EVENTS: announce each unrecognized transcript
+    // element the first time a parser reports it.
+    {
+        let bus_drift = event_bus.clone();
+        let mut rx = parser::drift::subscribe();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(record) => {
+                        let payload = serde_json::json!({
+                            "type": "schema_drift",
+                            "drift": record,
+                        });
+                        let envelope = bus::EventEnvelope::new(
+                            bus::EventSource::Jsonl,
+                            0,
+                            0,
+                            None,
+                            serde_json::to_vec(&payload).unwrap_or_default(),
+                        );
+                        let _ = bus_drift.publish(bus::SYSTEM_EVENTS, envelope).await;
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(skipped = n, "schema drift notifications lagged");
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
     // API Proxy Server (intercepting Claude API calls on separate port)
+    let activity_proxy_rx = proxy_state.event_tx.subscribe();
     let proxy_handle = proxy_state;
     tokio::spawn(async move {
         if let Err(e) = noaide_server::proxy::start_proxy(proxy_handle).await {
@@ -758,6 +896,47 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    // Proxy → activity: rate limits and the responses that end them
+    {
+        let activity = activity.clone();
+        let bus_activity = event_bus.clone();
+        let ecs_activity = ecs.clone();
+        let mut rx = activity_proxy_rx;
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(log) => {
+                        let Some(session_id) = log
+                            .session_id
+                            .as_deref()
+                            .and_then(|s| Uuid::parse_str(s).ok())
+                        else {
+                            continue;
+                        };
+                        if log.category.is_some() {
+                            continue;
+                        }
+                        if let Some(obs) = Observation::from_api_status(log.status_code) {
+                            observe_activity(
+                                &activity,
+                                &bus_activity,
+                                &ecs_activity,
+                                session_id,
+                                &obs,
+                                EventSource::Proxy,
+                            )
+                            .await;
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(missed = n, "proxy activity listener lagged");
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
     // ── Whisper Sidecar (voice transcription) ───────────────────────────────
     let enable_whisper = std::env::var("ENABLE_WHISPER")
         .map(|v| v != "false")
@@ -833,7 +1012,7 @@ async fn main() -> anyhow::Result<()> {
 
     let watch_paths = std::env::var("NOAIDE_WATCH_PATHS").unwrap_or_else(|_| {
         let home = std::env::var("HOME").unwrap_or_else(|_| "/root".into());
-        format!("{home}/.claude:{home}/.codex:{home}/.gemini")
+        format!("{home}/.claude:{home}/.codex:{home}/.gemini:{home}/.local/share/opencode")
     });
 
     let mut cli_dirs: Vec<PathBuf> = Vec::new();
@@ -858,6 +1037,36 @@ async fn main() -> anyhow::Result<()> {
         all_sessions.extend(sessions);
     }
 
+    // Aider writes its history into each project: find those with a shallow
+    // scan of the workspace roots (their projects are watched in Phase 2b)
+    // instead of watching the workspace recursively.
+    let workspace_roots =
+        std::env::var("NOAIDE_WORKSPACE_ROOTS").unwrap_or_else(|_| "/work".to_string());
+    for root in workspace_roots.split(':').map(PathBuf::from) {
+        if !root.is_dir() {
+            continue;
+        }
+        match SessionScanner::scan_aider_histories(&root).await {
+            Ok(sessions) => {
+                let before = all_sessions.len();
+                for session in sessions {
+                    if !all_sessions
+                        .iter()
+                        .any(|s| s.jsonl_path == session.jsonl_path)
+                    {
+                        all_sessions.push(session);
+                    }
+                }
+                info!(
+                    root = %root.display(),
+                    count = all_sessions.len() - before,
+                    "discovered aider histories"
+                );
+            }
+            Err(e) => tracing::warn!(root = %root.display(), error = %e, "aider scan failed"),
+        }
+    }
+
     let restored_plan_by_path: HashMap<String, String> = {
         let managed_paths = managed_session_paths.read().await;
         let plan_mapping = session_plan_mapping.read().await;
@@ -917,6 +1126,130 @@ async fn main() -> anyhow::Result<()> {
             "sessions registered (parsing in background)"
         );
     }
+    // Phase 2a: Warm restart — merge the last ECS snapshot into the
+    // discovered sessions; caches of changed transcripts are re-parsed.
+    {
+        let snapshot_path = noaide_server::ecs::snapshot::snapshot_path();
+        let loaded =
+            tokio::task::spawn_blocking(move || noaide_server::ecs::snapshot::load(&snapshot_path))
+                .await?;
+        match loaded {
+            Ok(Some(snapshot)) => {
+                let mut world = ecs.write().await;
+                let report = snapshot.restore(&mut world);
+                noaide_server::cache::adopt_restored(&mut world, &report.warm).await;
+                drop(world);
+                {
+                    let mut paths = session_paths.write().await;
+                    let mut cli_types = session_cli_types.write().await;
+                    for (jsonl_id, managed_id) in &report.aliases {
+                        if let Some(path) = paths.remove(jsonl_id) {
+                            paths.insert(*managed_id, path);
+                        }
+                        if let Some(cli_type) = cli_types.remove(jsonl_id) {
+                            cli_types.insert(*managed_id, cli_type);
+                        }
+                    }
+                }
+                info!(
+                    sessions = report.sessions,
+                    aliases = report.aliases.len(),
+                    warm = report.warm.len(),
+                    stale = report.stale.len(),
+                    "ECS snapshot restored"
+                );
+                let ecs_stale = ecs.clone();
+                let cli_types_stale = session_cli_types.clone();
+                tokio::spawn(async move {
+                    for (session_id, path) in report.stale {
+                        let cli_type = cli_types_stale
+                            .read()
+                            .await
+                            .get(&session_id)
+                            .copied()
+                            .unwrap_or_default();
+                        let mut world = ecs_stale.write().await;
+                        if let Err(e) = noaide_server::cache::ensure_warm(
+                            &mut world, session_id, &path, cli_type,
+                        )
+                        .await
+                        {
+                            tracing::debug!(session = %session_id, error = %e, "stale cache not re-warmed");
+                        }
+                    }
+                });
+            }
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "ignoring unreadable ECS snapshot"),
+        }
+    }
+    // Phase 2a': Managed launches — every recorded managed session stays in
+    // the list (idle until resumed), with its CLI transcript folded back into
+    // it as the watcher did while it ran.
+    {
+        let records = launches.list().await;
+        let mut world = ecs.write().await;
+        let mut paths = session_paths.write().await;
+        let mut cli_types = session_cli_types.write().await;
+        let mut relinked = 0usize;
+        for launch in &records {
+            let managed_id = launch.session_id;
+            if world.query_session_by_id(managed_id).is_none() {
+                world.spawn_session(SessionComponent {
+                    id: managed_id,
+                    path: launch.working_dir.to_string_lossy().into_owned(),
+                    status: SessionStatus::Idle,
+                    model: None,
+                    started_at: launch.started_at,
+                    last_activity_at: launch.resumed_at.unwrap_or(launch.started_at),
+                    cost: None,
+                });
+            }
+            if let Some(jsonl_id) = launch.cli_session_id
+                && !world.is_aliased(jsonl_id)
+            {
+                world.add_session_alias(jsonl_id, managed_id);
+                world.despawn_session(jsonl_id);
+                if let Some(path) = paths.remove(&jsonl_id) {
+                    paths.insert(managed_id, path);
+                } else if let Some(transcript) = launch.transcript.as_ref().filter(|t| t.exists()) {
+                    paths.insert(managed_id, transcript.clone());
+                }
+                if let Some(cli_type) = cli_types.remove(&jsonl_id) {
+                    cli_types.insert(managed_id, cli_type);
+                }
+                relinked += 1;
+            }
+            if let Some(source) = parser::registry::by_name(&launch.cli_type) {
+                cli_types.entry(managed_id).or_insert(source.cli_type());
+            }
+        }
+        if !records.is_empty() {
+            info!(
+                launches = records.len(),
+                relinked, "managed launches restored"
+            );
+        }
+    }
+    if std::env::var("NOAIDE_AUTO_RESUME").is_ok_and(|v| v == "true" || v == "1") {
+        // Only sessions that were still running when the server stopped
+        for launch in launches.list().await {
+            if launch.exited_at.is_some() {
+                continue;
+            }
+            let session_id = launch.session_id;
+            match resume_managed(&app_state, launch).await {
+                Ok(continued) => {
+                    info!(session = %session_id, continued, "auto-resumed managed session")
+                }
+                Err((_, e)) => {
+                    warn!(session = %session_id, error = %e, "failed to auto-resume managed session")
+                }
+            }
+        }
+    }
+    spawn_job_runner(app_state.clone(), job_limits);
+
     if !restored_jsonl_plan_bindings.is_empty() {
         let mut plan_mapping = session_plan_mapping.write().await;
         for (session_id, plan) in restored_jsonl_plan_bindings {
@@ -974,7 +1307,11 @@ async fn main() -> anyhow::Result<()> {
         let mut total_msgs = 0usize;
         for session_info in &all_sessions {
             let msg_count = (session_info.size_bytes / 1500).max(1) as usize;
-            offset_map.insert(session_info.jsonl_path.clone(), session_info.size_bytes);
+            if parser::registry::for_cli(session_info.cli_type).cursor_kind()
+                == parser::CursorKind::ByteOffset
+            {
+                offset_map.insert(session_info.jsonl_path.clone(), session_info.size_bytes);
+            }
             total_msgs += msg_count;
         }
         info!(
@@ -1012,10 +1349,24 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    // Phase 5: Search index — backfill every session, then follow new messages
+    {
+        let rx = event_bus.subscribe(bus::SESSION_MESSAGES).await?;
+        let indexer = noaide_server::search::SearchIndexer::new(
+            db.clone(),
+            ecs.clone(),
+            session_paths.clone(),
+            session_cli_types.clone(),
+        );
+        tokio::spawn(indexer.run(rx));
+    }
+
     // ── Watcher event loop — react to live file changes ─────────────────────
 
     let ecs_handle = ecs.clone();
     let bus_handle = event_bus.clone();
+    let activity_watch = activity.clone();
+    let launches_watch = launches.clone();
     let offsets_watch = offsets.clone();
     let paths_watch = session_paths.clone();
     let cli_types_watch = session_cli_types.clone();
@@ -1033,8 +1384,8 @@ async fn main() -> anyhow::Result<()> {
             match events_rx.recv().await {
                 Ok(event) => {
                     let path = &event.path;
-                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                    if ext == "jsonl" || ext == "json" {
+                    let is_transcript = parser::registry::is_transcript_file(path);
+                    if is_transcript {
                         tracing::info!(
                             path = %path.display(),
                             kind = ?event.kind,
@@ -1043,7 +1394,7 @@ async fn main() -> anyhow::Result<()> {
                     }
                     // (Plan watcher removed — plans are served via nginx from /work/plan/)
 
-                    if ext != "jsonl" && ext != "json" {
+                    if !is_transcript {
                         // ── Project file change → FILE_CHANGES bus (WP-10) ────────
                         let watches = project_watches_handle.read().await;
                         let match_result = watches
@@ -1170,25 +1521,21 @@ async fn main() -> anyhow::Result<()> {
                     match event.kind {
                         FileEventKind::Created | FileEventKind::Modified => {
                             // Extract session UUID from filename — format varies by CLI type
-                            let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-                            let session_id = if ext == "json" {
-                                // Gemini: session-{timestamp}-{uuid}.json
-                                discovery::extract_gemini_uuid(filename)
-                                    .and_then(|s| Uuid::parse_str(&s).ok())
-                            } else if filename.starts_with("rollout-") {
-                                // Codex: rollout-{timestamp}-{uuid}.jsonl
-                                discovery::extract_codex_uuid(filename)
-                                    .and_then(|s| Uuid::parse_str(&s).ok())
-                            } else {
-                                // Claude: {uuid}.jsonl
-                                path.file_stem()
-                                    .and_then(|s| s.to_str())
-                                    .and_then(|s| Uuid::parse_str(s).ok())
-                            };
+                            let source = parser::registry::resolve(path);
+                            let session_id = source
+                                .session_id_from_path(path)
+                                .and_then(|s| Uuid::parse_str(&s).ok());
 
                             let Some(sid) = session_id else {
                                 continue;
                             };
+                            // Sources with multi-file layouts (OpenCode) report
+                            // changes on a per-message file; parse the session
+                            // document it belongs to instead.
+                            let Some(transcript) = source.transcript_path(path) else {
+                                continue;
+                            };
+                            let path = &transcript;
 
                             // Check if this JSONL belongs to a managed session.
                             // If so, link it (alias) instead of creating a duplicate.
@@ -1236,6 +1583,8 @@ async fn main() -> anyhow::Result<()> {
                                             // Link JSONL session to managed session
                                             let mut world = ecs_handle.write().await;
                                             world.add_session_alias(sid, mid);
+                                            drop(world);
+                                            launches_watch.link(mid, sid, path).await;
                                             info!(
                                                 jsonl_session = %sid,
                                                 managed_session = %mid,
@@ -1281,7 +1630,12 @@ async fn main() -> anyhow::Result<()> {
                                     .insert(effective_sid, cli_type);
 
                                 if !exists {
-                                    let project_path = extract_project_path_from_jsonl(path);
+                                    let project_path = extract_project_path_from_jsonl(path)
+                                        .or_else(|| {
+                                            source
+                                                .project_path(path)
+                                                .map(|p| p.to_string_lossy().to_string())
+                                        });
                                     let _metadata = tokio::fs::metadata(path).await.ok();
 
                                     let mut world = ecs_handle.write().await;
@@ -1338,93 +1692,38 @@ async fn main() -> anyhow::Result<()> {
                                 }
                             }
 
-                            // Parse new messages — dispatch to the right parser by CLI type.
-                            // Claude: incremental byte-offset JSONL parsing.
-                            // Gemini/Codex: full re-parse, diff by message count.
+                            // Parse new messages — dispatch to the transcript source.
+                            // The stored cursor is a byte offset (Claude, Codex) or a
+                            // message count (Gemini, Aider, OpenCode), see `CursorKind`.
+                            // Message-count cursors aren't pre-populated at startup; fall
+                            // back to what the ECS cache has already consumed.
                             let cli_type = cli_type_from_path(path);
+                            let source = parser::registry::for_cli(cli_type);
+                            let known = offsets_watch.lock().await.get(path).copied();
+                            let cursor = match known {
+                                Some(cursor) => cursor,
+                                None => ecs_handle
+                                    .read()
+                                    .await
+                                    .query_cache_meta(effective_sid)
+                                    .filter(|m| m.is_warm)
+                                    .map_or(0, |m| m.file_offset),
+                            };
                             let parse_result: Result<Vec<parser::ClaudeMessage>, anyhow::Error> =
-                                match cli_type {
-                                    noaide_server::discovery::scanner::CliType::Claude => {
-                                        // Incremental JSONL parse (byte-offset based)
-                                        let from_offset = {
-                                            offsets_watch
-                                                .lock()
-                                                .await
-                                                .get(path)
-                                                .copied()
-                                                .unwrap_or(0)
-                                        };
-                                        match parser::parse_incremental(path, from_offset).await {
-                                            Ok((messages, new_offset)) => {
-                                                offsets_watch
-                                                    .lock()
-                                                    .await
-                                                    .insert(path.clone(), new_offset);
-                                                Ok(messages)
-                                            }
-                                            Err(e) => Err(e),
-                                        }
-                                    }
-                                    noaide_server::discovery::scanner::CliType::Gemini => {
-                                        // Full re-parse JSON, diff by message count
-                                        let prev_count = {
-                                            offsets_watch
-                                                .lock()
-                                                .await
-                                                .get(path)
-                                                .copied()
-                                                .unwrap_or(0)
-                                                as usize
-                                        };
-                                        match parser::parse_gemini_file(path).await {
-                                            Ok(all_messages) => {
-                                                let total = all_messages.len();
-                                                offsets_watch
-                                                    .lock()
-                                                    .await
-                                                    .insert(path.clone(), total as u64);
-                                                if total > prev_count {
-                                                    Ok(all_messages
-                                                        .into_iter()
-                                                        .skip(prev_count)
-                                                        .collect())
-                                                } else {
-                                                    Ok(Vec::new())
-                                                }
-                                            }
-                                            Err(e) => Err(e),
-                                        }
-                                    }
-                                    noaide_server::discovery::scanner::CliType::Codex => {
-                                        // Full re-parse JSONL (different schema), diff by count
-                                        let prev_count = {
-                                            offsets_watch
-                                                .lock()
+                                match source.parse_incremental(path, cursor).await {
+                                    Ok((messages, new_cursor)) => {
+                                        if new_cursor < cursor {
+                                            // Transcript replaced — messages are the
+                                            // whole session, drop the stale cache.
+                                            ecs_handle
+                                                .write()
                                                 .await
-                                                .get(path)
-                                                .copied()
-                                                .unwrap_or(0)
-                                                as usize
-                                        };
-                                        match parser::parse_codex_file(path).await {
-                                            Ok(all_messages) => {
-                                                let total = all_messages.len();
-                                                offsets_watch
-                                                    .lock()
-                                                    .await
-                                                    .insert(path.clone(), total as u64);
-                                                if total > prev_count {
-                                                    Ok(all_messages
-                                                        .into_iter()
-                                                        .skip(prev_count)
-                                                        .collect())
-                                                } else {
-                                                    Ok(Vec::new())
-                                                }
-                                            }
-                                            Err(e) => Err(e),
+                                                .invalidate_cache(effective_sid);
                                         }
+                                        offsets_watch.lock().await.insert(path.clone(), new_cursor);
+                                        Ok(messages)
                                     }
+                                    Err(e) => Err(e),
                                 };
 
                             match parse_result {
@@ -1444,6 +1743,7 @@ async fn main() -> anyhow::Result<()> {
 
                                     // Convert to components, store in ECS cache, and push to bus.
                                     let mut serialized_messages = Vec::new();
+                                    let mut observations = Vec::new();
                                     {
                                         let mut world = ecs_handle.write().await;
                                         for msg in &messages {
@@ -1452,9 +1752,16 @@ async fn main() -> anyhow::Result<()> {
                                             {
                                                 serialized_messages
                                                     .push(component_to_json(&component));
+                                                observations
+                                                    .extend(Observation::from_message(&component));
                                                 // Store in ECS for cache-first API serving
                                                 world.spawn_message(component);
                                             }
+                                            parser::record_tool_calls(
+                                                &mut world,
+                                                effective_sid,
+                                                msg,
+                                            );
                                         }
                                         // Update cache meta with new offset
                                         let file_size = tokio::fs::metadata(path)
@@ -1533,6 +1840,18 @@ async fn main() -> anyhow::Result<()> {
                                         }
                                     }
 
+                                    for obs in &observations {
+                                        observe_activity(
+                                            &activity_watch,
+                                            &bus_handle,
+                                            &ecs_handle,
+                                            effective_sid,
+                                            obs,
+                                            EventSource::Jsonl,
+                                        )
+                                        .await;
+                                    }
+
                                     if !serialized_messages.is_empty() {
                                         let payload = serde_json::to_vec(&serde_json::json!({
                                             "type": "new_messages",
@@ -1602,6 +1921,7 @@ async fn main() -> anyhow::Result<()> {
     let cli_types_rescan = session_cli_types.clone();
     let cli_dirs_rescan = cli_dirs.clone();
     let msp_rescan = managed_session_paths.clone();
+    let launches_rescan = launches.clone();
     let pending_cli_rescan = managed_pending_by_cli.clone();
     let hints_rescan = message_count_hints.clone();
     tokio::spawn(async move {
@@ -1659,6 +1979,10 @@ async fn main() -> anyhow::Result<()> {
                                     let mut world = ecs_rescan.write().await;
                                     if !world.is_aliased(sid) {
                                         world.add_session_alias(sid, mid);
+                                        drop(world);
+                                        launches_rescan
+                                            .link(mid, sid, &session_info.jsonl_path)
+                                            .await;
                                         info!(
                                             jsonl_session = %sid,
                                             managed_session = %mid,
@@ -1747,7 +2071,11 @@ async fn main() -> anyhow::Result<()> {
                     let path = session_info.jsonl_path.clone();
                     let size = session_info.size_bytes;
                     let msg_count = (size / 1500).max(1) as usize;
-                    offsets_bg.lock().await.insert(path, size);
+                    if parser::registry::for_cli(session_info.cli_type).cursor_kind()
+                        == parser::CursorKind::ByteOffset
+                    {
+                        offsets_bg.lock().await.insert(path, size);
+                    }
 
                     if msg_count > 0 {
                         let payload = serde_json::to_vec(&serde_json::json!({
@@ -1777,53 +2105,181 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    // ── ECS snapshot — periodic, and once more on shutdown ──────────────────
+
+    let snapshot_interval = std::env::var("NOAIDE_SNAPSHOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(300)
+        .max(30);
+    {
+        let ecs = ecs.clone();
+        let session_paths = session_paths.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(snapshot_interval));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                save_ecs_snapshot(&ecs, &session_paths).await;
+            }
+        });
+    }
+
     // Keep handles alive
     let _watcher = watcher;
 
     info!("noaide-server ready");
 
-    // Wait for shutdown signal
-    tokio::signal::ctrl_c().await?;
+    shutdown_signal().await?;
     info!("noaide-server shutting down");
+    save_ecs_snapshot(&ecs, &session_paths).await;
 
     Ok(())
 }
 
 // ── Helpers ─────────────────────────────────────────────────────────────────
 
+/// Wait for SIGINT or SIGTERM. In a container the server runs as PID 1 and
+/// `docker stop` / systemd send SIGTERM, not SIGINT.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = sigterm.recv() => {}
+    }
+    Ok(())
+}
+
+/// Feed one observation into a session's activity and publish the
+/// transition, if any, on `session/status`. A crash also marks the session
+/// as errored.
+async fn observe_activity(
+    activity: &ActivityBoard,
+    bus: &Arc<dyn bus::EventBus>,
+    ecs: &SharedEcsWorld,
+    session_id: Uuid,
+    obs: &Observation,
+    source: EventSource,
+) {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let Some(transition) = activity.observe(session_id, obs, now_ms).await else {
+        return;
+    };
+    if matches!(transition.to, Activity::Crashed { .. }) {
+        ecs.write()
+            .await
+            .update_session_status(session_id, SessionStatus::Error);
+    }
+    let _ = bus
+        .publish(bus::SESSION_STATUS, transition.envelope(source))
+        .await;
+}
+
+/// Turn a managed session's PTY events into activity observations until
+/// the session closes.
+fn spawn_pty_activity(state: &AppState, session: &dyn noaide_server::session::Session) {
+    /// Silence after which a thinking agent counts as waiting for input.
+    const QUIET: std::time::Duration = std::time::Duration::from_secs(3);
+
+    let session_id = session.id().0;
+    let mut rx = session.events();
+    let (activity, bus, ecs, launches) = (
+        state.activity.clone(),
+        state.event_bus.clone(),
+        state.ecs.clone(),
+        state.launches.clone(),
+    );
+    tokio::spawn(async move {
+        use noaide_server::session::SessionEvent;
+        loop {
+            let observations = match tokio::time::timeout(QUIET, rx.recv()).await {
+                Ok(Ok(SessionEvent::Output(output))) => Observation::from_pty(&output),
+                Ok(Ok(SessionEvent::Exited(status))) => {
+                    // Exited on its own: not respawned by NOAIDE_AUTO_RESUME
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs() as i64)
+                        .unwrap_or(0);
+                    launches.mark_exited(session_id, now).await;
+                    vec![Observation::ProcessExited(status)]
+                }
+                Ok(Ok(SessionEvent::SandboxViolation(violation))) => {
+                    let now_ms = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_millis() as i64)
+                        .unwrap_or(0);
+                    let _ = bus
+                        .publish(bus::SESSION_STATUS, violation.envelope(session_id, now_ms))
+                        .await;
+                    continue;
+                }
+                Ok(Ok(SessionEvent::Closed))
+                | Ok(Err(tokio::sync::broadcast::error::RecvError::Closed)) => {
+                    observe_activity(
+                        &activity,
+                        &bus,
+                        &ecs,
+                        session_id,
+                        &Observation::Closed,
+                        EventSource::Pty,
+                    )
+                    .await;
+                    // `closed` is published above; the tracker goes with the session
+                    activity.remove(session_id).await;
+                    break;
+                }
+                Ok(Ok(_)) | Ok(Err(_)) => continue,
+                Err(_) => vec![Observation::Quiet],
+            };
+            for obs in &observations {
+                observe_activity(&activity, &bus, &ecs, session_id, obs, EventSource::Pty).await;
+            }
+        }
+    });
+}
+
+/// Write the ECS snapshot used for warm restarts; failures are only logged.
+async fn save_ecs_snapshot(
+    ecs: &noaide_server::ecs::SharedEcsWorld,
+    session_paths: &RwLock<HashMap<Uuid, PathBuf>>,
+) {
+    let snapshot = {
+        let paths = session_paths.read().await;
+        ecs.read().await.snapshot(&paths)
+    };
+    let path = noaide_server::ecs::snapshot::snapshot_path();
+    let caches = snapshot.caches.len();
+    match tokio::task::spawn_blocking(move || noaide_server::ecs::snapshot::save(&snapshot, &path))
+        .await
+    {
+        Ok(Ok(bytes)) => tracing::debug!(caches, bytes, "ECS snapshot saved"),
+        Ok(Err(e)) => warn!(error = %e, "ECS snapshot failed"),
+        Err(e) => warn!(error = %e, "ECS snapshot task failed"),
+    }
+}
+
 /// Detect which CLI tool owns a JSONL/JSON file based on its path.
 ///
-/// Returns "claude", "codex", or "gemini" based on the directory structure.
+/// Returns the registry name ("claude", "codex", "gemini", ...).
 fn detect_cli_type_from_path(path: &std::path::Path) -> Option<&'static str> {
-    let path_str = path.to_str()?;
-    if path_str.contains("/.claude/") {
-        Some("claude")
-    } else if path_str.contains("/.codex/") {
-        Some("codex")
-    } else if path_str.contains("/.gemini/") {
-        Some("gemini")
-    } else {
-        None
-    }
+    parser::registry::detect(path).map(|s| s.name())
+}
+
+/// Infer CLI type from the JSONL/JSON file path (Claude if unrecognised).
+fn cli_type_from_path(path: &std::path::Path) -> noaide_server::discovery::scanner::CliType {
+    parser::registry::resolve(path).cli_type()
 }
 
 /// Extract decoded project path from a JSONL file path.
 ///
 /// Given a path like `~/.claude/projects/-work-noaide/UUID.jsonl`,
 /// extracts `-work-noaide` and decodes it to `/work/noaide`.
-/// Infer CLI type from the JSONL/JSON file path.
-fn cli_type_from_path(path: &std::path::Path) -> noaide_server::discovery::scanner::CliType {
-    use noaide_server::discovery::scanner::CliType;
-    let s = path.to_string_lossy();
-    if s.contains("/.codex/") || s.contains("/codex/") {
-        CliType::Codex
-    } else if s.contains("/.gemini/") || s.contains("/gemini/") {
-        CliType::Gemini
-    } else {
-        CliType::Claude
-    }
-}
-
 fn extract_project_path_from_jsonl(jsonl_path: &std::path::Path) -> Option<String> {
     let parent = jsonl_path.parent()?;
     let dir_name = parent.file_name()?.to_str()?;
@@ -2033,6 +2489,31 @@ async fn send_via_stream_json(
 // ── HTTP API Handlers ───────────────────────────────────────────────────────
 
 async fn api_get_sessions(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    // Collected first: spawning holds the manager while it registers in the ECS
+    let sandboxes: HashMap<Uuid, serde_json::Value> = state
+        .session_manager
+        .read()
+        .await
+        .list_active()
+        .into_iter()
+        .filter_map(|session| {
+            let sandbox = session.sandbox()?;
+            Some((
+                session.id().0,
+                serde_json::json!({
+                    "config": sandbox.config(),
+                    "violations": sandbox.violation_count(),
+                }),
+            ))
+        })
+        .collect();
+    let worktrees: HashMap<Uuid, noaide_server::git::Worktree> = state
+        .launches
+        .list()
+        .await
+        .into_iter()
+        .filter_map(|launch| Some((launch.session_id, launch.worktree?)))
+        .collect();
     let world = state.ecs.read().await;
     let sessions = world.query_sessions();
     let cli_types = state.session_cli_types.read().await;
@@ -2062,6 +2543,8 @@ async fn api_get_sessions(State(state): State<AppState>) -> axum::Json<serde_jso
                 "cost": s.cost,
                 "messageCount": message_count,
                 "cliType": cli,
+                "sandbox": sandboxes.get(&s.id),
+                "worktree": worktrees.get(&s.id),
             })
         })
         .collect();
@@ -2074,6 +2557,23 @@ struct MessagesQuery {
     limit: Option<usize>,
     /// Offset from the END of the list (0 = last N entries)
     offset: Option<usize>,
+    /// Jump to a point in time (ISO 8601 or epoch ms): the page starts at
+    /// the first message at or after it, and `offset` is ignored.
+    at: Option<String>,
+}
+
+/// A time query parameter: ISO 8601 or epoch milliseconds.
+fn parse_time_param(value: &str) -> Option<i64> {
+    value
+        .parse::<i64>()
+        .ok()
+        .or_else(|| parser::parse_iso_millis(value))
+}
+
+/// Turn an `at` jump into the offset-from-end of the page starting at
+/// `position`.
+fn offset_for_position(total: usize, position: usize, limit: usize) -> usize {
+    total.saturating_sub(position.saturating_add(limit))
 }
 
 async fn api_get_messages(
@@ -2091,6 +2591,13 @@ async fn api_get_messages(
         Some(n) => n,
     };
     let offset = query.offset.unwrap_or(0);
+    let at_ms = match query.at.as_deref() {
+        Some(at) => match parse_time_param(at) {
+            Some(ms) => Some(ms),
+            None => return axum::Json(serde_json::json!({"error": "invalid at timestamp"})),
+        },
+        None => None,
+    };
 
     // Resolve JSONL path (handle managed session aliases)
     let jsonl_path = {
@@ -2126,8 +2633,16 @@ async fn api_get_messages(
         // Cache-first: ensure warm, then refresh incrementally
         let mut world = state.ecs.write().await;
         match noaide_server::cache::ensure_warm(&mut world, uuid, path, cli_type).await {
-            Ok(_) => {
+            Ok(total) => {
                 // Serve from ECS cache — all message types included
+                let offset = match at_ms {
+                    Some(ms) => offset_for_position(
+                        total,
+                        world.message_position_at(uuid, ms.div_euclid(1000)),
+                        limit,
+                    ),
+                    None => offset,
+                };
                 let (messages, total, has_more) = world.query_messages_range(uuid, offset, limit);
                 let json: Vec<serde_json::Value> = messages
                     .iter()
@@ -2158,6 +2673,44 @@ async fn api_get_messages(
         let use_tail = cli_type == noaide_server::discovery::scanner::CliType::Claude
             && file_size > 10 * 1024 * 1024;
 
+        // Large Claude transcripts are paged through their line index: one
+        // read of the requested lines instead of a parse of the whole file.
+        if use_tail {
+            match parser::line_index::open(path).await {
+                Ok(index) => {
+                    let total = index.len();
+                    let offset = match at_ms {
+                        Some(ms) => offset_for_position(total, index.position_at(ms), limit),
+                        None => offset,
+                    };
+                    let end = total.saturating_sub(offset);
+                    let start = end.saturating_sub(limit);
+                    match index.read_range(path, start..end).await {
+                        Ok(messages) => {
+                            let json: Vec<serde_json::Value> = messages
+                                .iter()
+                                .filter_map(|msg| parser::message_to_component(msg, uuid))
+                                .map(|m| component_to_json(&m))
+                                .collect();
+                            return axum::Json(serde_json::json!({
+                                "messages": json,
+                                "total": total,
+                                "offset": offset,
+                                "limit": limit,
+                                "hasMore": start > 0,
+                            }));
+                        }
+                        Err(e) => {
+                            tracing::warn!(session = %uuid, error = %e, "line index read failed, parsing tail");
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(session = %uuid, error = %e, "line index unavailable, parsing tail");
+                }
+            }
+        }
+
         if use_tail {
             // Parse only the last 2MB (~1300 messages) — enough for any reasonable limit+offset
             let tail_bytes = (2 * 1024 * 1024u64).min(file_size);
@@ -2182,17 +2735,22 @@ async fn api_get_messages(
             }
         }
 
-        let parse_result = match cli_type {
-            noaide_server::discovery::scanner::CliType::Codex => {
-                parser::parse_codex_file(path).await
-            }
-            noaide_server::discovery::scanner::CliType::Gemini => {
-                parser::parse_gemini_file(path).await
-            }
-            noaide_server::discovery::scanner::CliType::Claude => parser::parse_file(path).await,
-        };
+        let parse_result = parser::registry::for_cli(cli_type).parse_file(path).await;
         if let Ok(messages) = parse_result {
             let total = messages.len();
+            let offset = match at_ms {
+                Some(ms) => offset_for_position(
+                    total,
+                    messages.partition_point(|m| {
+                        m.timestamp
+                            .as_deref()
+                            .and_then(parser::parse_iso_millis)
+                            .is_some_and(|t| t < ms)
+                    }),
+                    limit,
+                ),
+                None => offset,
+            };
             let start = total.saturating_sub(offset);
             let range_start = start.saturating_sub(limit);
             let json: Vec<serde_json::Value> = messages[range_start..start]
@@ -2226,13 +2784,177 @@ async fn api_get_messages(
     }))
 }
 
-/// GET /api/sessions/{id}/stats — Session statistics computed from cached messages.
-async fn api_get_session_stats(
+#[derive(serde::Deserialize, Default)]
+struct MessageSearchQuery {
+    /// Comma-separated roles: user, assistant, system, meta.
+    role: Option<String>,
+    /// Comma-separated message types as returned in `messageType`.
+    #[serde(rename = "type")]
+    message_type: Option<String>,
+    /// Tool name, case-insensitive; matches calls and their results.
+    tool: Option<String>,
+    /// Substring of the model name, case-insensitive.
+    model: Option<String>,
+    /// Window start, inclusive (ISO 8601 or epoch ms).
+    after: Option<String>,
+    /// Window end, exclusive (ISO 8601 or epoch ms).
+    before: Option<String>,
+    /// Only error messages and failed tool results.
+    errors: Option<bool>,
+    hidden: Option<bool>,
+    meta: Option<bool>,
+    /// Substring of the content, case-insensitive.
+    q: Option<String>,
+    cursor: Option<String>,
+    /// newest (default) | oldest
+    order: Option<String>,
+    /// Page size (default 50, max 500).
+    limit: Option<usize>,
+    /// Include per-facet match counts.
+    facets: Option<bool>,
+}
+
+/// Parse a comma-separated list with `parse`, naming the first bad value.
+fn parse_list<T>(value: Option<&str>, parse: fn(&str) -> Option<T>) -> Result<Vec<T>, String> {
+    value
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| parse(v).ok_or_else(|| v.to_string()))
+        .collect()
+}
+
+/// GET /api/sessions/{id}/messages/query — Filtered messages with cursor
+/// pagination and facet counts, served from the session's warm cache.
+///
+/// Examples: failed Bash calls `?tool=bash&errors=true`, assistant text
+/// mentioning a file `?role=assistant&type=text&q=main.rs`.
+async fn api_query_messages(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> axum::Json<serde_json::Value> {
+    axum::extract::Query(query): axum::extract::Query<MessageSearchQuery>,
+) -> impl axum::response::IntoResponse {
+    let bad_request = |error: String| {
+        (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": error})),
+        )
+    };
     let Ok(uuid) = Uuid::parse_str(&id) else {
-        return axum::Json(serde_json::json!({"error": "invalid session id"}));
+        return bad_request("invalid session id".into());
+    };
+    let roles = match parse_list(
+        query.role.as_deref(),
+        noaide_server::ecs::MessageRole::parse,
+    ) {
+        Ok(roles) => roles,
+        Err(v) => return bad_request(format!("unknown role: {v}")),
+    };
+    let types = match parse_list(
+        query.message_type.as_deref(),
+        noaide_server::ecs::MessageType::parse,
+    ) {
+        Ok(types) => types,
+        Err(v) => return bad_request(format!("unknown message type: {v}")),
+    };
+    let mut window = [None, None];
+    for (slot, value) in window.iter_mut().zip([&query.after, &query.before]) {
+        if let Some(value) = value.as_deref() {
+            match parse_time_param(value) {
+                Some(ms) => *slot = Some(ms.div_euclid(1000)),
+                None => return bad_request(format!("invalid timestamp: {value}")),
+            }
+        }
+    }
+    let newest_first = match query.order.as_deref() {
+        None | Some("newest") => true,
+        Some("oldest") => false,
+        Some(other) => return bad_request(format!("unknown order: {other}")),
+    };
+
+    let jsonl_path = {
+        let paths = state.session_paths.read().await;
+        match paths.get(&uuid).cloned() {
+            Some(p) => Some(p),
+            None => {
+                let world = state.ecs.read().await;
+                world
+                    .reverse_alias(uuid)
+                    .and_then(|jsonl_id| paths.get(&jsonl_id).cloned())
+            }
+        }
+    };
+    let Some(path) = jsonl_path else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "session not found"})),
+        );
+    };
+    let cli_type = {
+        let types = state.session_cli_types.read().await;
+        let world = state.ecs.read().await;
+        types
+            .get(&uuid)
+            .or_else(|| types.get(&world.reverse_alias(uuid)?))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let mut world = state.ecs.write().await;
+    if let Err(e) = noaide_server::cache::ensure_warm(&mut world, uuid, &path, cli_type).await {
+        return (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            axum::Json(serde_json::json!({
+                "error": "session cannot be cached for queries",
+                "detail": e.to_string(),
+            })),
+        );
+    }
+    let message_query = noaide_server::ecs::MessageQuery {
+        roles,
+        types,
+        tool: query.tool,
+        model: query.model,
+        after: window[0],
+        before: window[1],
+        errors_only: query.errors.unwrap_or(false),
+        include_hidden: query.hidden.unwrap_or(false),
+        include_meta: query.meta.unwrap_or(false),
+        text: query.q.filter(|q| !q.is_empty()),
+        cursor: query.cursor,
+        newest_first,
+        limit: query.limit.unwrap_or(50).clamp(1, 500),
+        facets: query.facets.unwrap_or(false),
+    };
+    match world.query_messages(uuid, &message_query) {
+        Ok(page) => {
+            let json: Vec<serde_json::Value> = page
+                .messages
+                .iter()
+                .map(noaide_server::cache::component_to_api_json)
+                .collect();
+            (
+                axum::http::StatusCode::OK,
+                axum::Json(serde_json::json!({
+                    "messages": json,
+                    "total": page.total,
+                    "nextCursor": page.next_cursor,
+                    "facets": page.facets,
+                })),
+            )
+        }
+        Err(e) => bad_request(e.to_string()),
+    }
+}
+
+/// GET /api/sessions/{id}/stats — Session statistics computed from cached messages.
+async fn api_get_session_stats(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> axum::Json<serde_json::Value> {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return axum::Json(serde_json::json!({"error": "invalid session id"}));
     };
 
     // Ensure cache is warm before computing stats
@@ -2261,6 +2983,11 @@ async fn api_get_session_stats(
         std::collections::HashMap::new();
     let mut tool_counts: std::collections::HashMap<String, usize> =
         std::collections::HashMap::new();
+    let mut tool_errors = 0usize;
+    for inv in world.query_tool_invocations_by_session(uuid) {
+        *tool_counts.entry(inv.name).or_insert(0) += 1;
+        tool_errors += usize::from(inv.is_error);
+    }
     let mut user_messages = 0u64;
     let mut assistant_messages = 0u64;
     let mut thinking_messages = 0u64;
@@ -2285,16 +3012,6 @@ async fn api_get_session_stats(
         if msg.message_type == noaide_server::ecs::components::MessageType::Thinking {
             thinking_messages += 1;
         }
-        if msg.message_type == noaide_server::ecs::components::MessageType::ToolUse {
-            // Extract tool name from content if possible
-            if let Some(name) = msg
-                .content
-                .strip_prefix("[tool_use: ")
-                .and_then(|s| s.split(']').next())
-            {
-                *tool_counts.entry(name.to_string()).or_insert(0) += 1;
-            }
-        }
 
         if msg.timestamp > 0 {
             if first_ts.is_none() || msg.timestamp < first_ts.unwrap() {
@@ -2333,690 +3050,2667 @@ async fn api_get_session_stats(
         "totalCostUsd": total_cost,
         "modelBreakdown": model_counts,
         "toolBreakdown": tool_counts,
+        "toolErrors": tool_errors,
         "firstMessageAt": first_ts,
         "lastMessageAt": last_ts,
         "durationSecs": duration_secs,
     }))
 }
 
-// ═══════════════════════════════════════════════════════════════
-// TOGAF Plan API Endpoints
-// Plans live in /work/plan/{name}/ — nginx serves plan.json,
-// the server only provides discovery and edit-write-back.
-// ═══════════════════════════════════════════════════════════════
+// ── Tool Invocation API ─────────────────────────────────────────────────────
 
-/// GET /api/plans — List available plans in the plan base directory.
-async fn api_list_plans(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
-    let base = &*state.plan_base_dir;
-    let mut plans = Vec::new();
+#[derive(serde::Deserialize, Default)]
+struct ToolsQuery {
+    session_id: Option<String>,
+    /// Exact tool name, case-insensitive (`Bash`).
+    name: Option<String>,
+    /// shell | edit | read | search | web | agent | other
+    kind: Option<String>,
+    failed: Option<bool>,
+    /// File path (absolute, or relative to match the end of one).
+    path: Option<String>,
+    /// recent (default) | slowest
+    sort: Option<String>,
+    /// Max results (default 100, 0 = unlimited)
+    limit: Option<usize>,
+}
 
-    if let Ok(mut entries) = tokio::fs::read_dir(base).await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            if let Ok(ft) = entry.file_type().await
-                && ft.is_dir()
-            {
-                let name = entry.file_name().to_string_lossy().to_string();
-                // Validate name (alphanumeric + dash + underscore only)
-                if name
-                    .chars()
-                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-                {
-                    let plan_json = base.join(&name).join("plan.json");
-                    let has_plan = tokio::fs::metadata(&plan_json).await.is_ok();
-                    let edits_json = base.join(&name).join("plan-edits.json");
-                    let has_edits = tokio::fs::metadata(&edits_json).await.is_ok();
-                    plans.push(serde_json::json!({
-                        "name": name,
-                        "has_plan_json": has_plan,
-                        "has_edits": has_edits,
-                    }));
-                }
+fn tool_invocation_json(inv: &noaide_server::ecs::ToolInvocationComponent) -> serde_json::Value {
+    serde_json::json!({
+        "sessionId": inv.session_id.to_string(),
+        "toolUseId": inv.tool_use_id,
+        "messageId": inv.message_id.to_string(),
+        "name": inv.name,
+        "kind": inv.kind.as_str(),
+        "input": inv.input,
+        "files": inv.files,
+        "startedAtMs": inv.started_at_ms,
+        "endedAtMs": inv.ended_at_ms,
+        "durationMs": inv.duration_ms,
+        "outputSize": inv.output_size,
+        "isError": inv.is_error,
+    })
+}
+
+/// GET /api/tools — Tool invocations across all parsed sessions.
+///
+/// Examples: failed Bash calls `?kind=shell&failed=true`, slowest tools
+/// `?sort=slowest`, edits to a file `?kind=edit&path=src/main.rs`.
+async fn api_get_tools(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<ToolsQuery>,
+) -> impl axum::response::IntoResponse {
+    let session_id = match query.session_id.as_deref().map(Uuid::parse_str) {
+        None => None,
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({"error": "invalid session id"})),
+            );
+        }
+    };
+    let kind = match query.kind.as_deref() {
+        None => None,
+        Some(k) => match noaide_server::ecs::ToolKind::parse(k) {
+            Some(kind) => Some(kind),
+            None => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    axum::Json(serde_json::json!({"error": format!("unknown tool kind: {k}")})),
+                );
             }
+        },
+    };
+    let sort = match query.sort.as_deref() {
+        None | Some("recent") => noaide_server::ecs::ToolSort::Recent,
+        Some("slowest") => noaide_server::ecs::ToolSort::Slowest,
+        Some(other) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({"error": format!("unknown sort: {other}")})),
+            );
         }
-    }
-
-    // Sort by name
-    plans.sort_by(|a, b| {
-        a["name"]
-            .as_str()
-            .unwrap_or("")
-            .cmp(b["name"].as_str().unwrap_or(""))
-    });
+    };
 
-    axum::Json(serde_json::json!(plans))
+    let tool_query = noaide_server::ecs::ToolQuery {
+        session_id,
+        name: query.name,
+        kind,
+        failed: query.failed,
+        path: query.path,
+        sort,
+        limit: query.limit.unwrap_or(100),
+    };
+    let world = state.ecs.read().await;
+    let found = noaide_server::ecs::find_tool_invocations(&world, &tool_query);
+    let items: Vec<serde_json::Value> = found.iter().map(tool_invocation_json).collect();
+    (
+        axum::http::StatusCode::OK,
+        axum::Json(serde_json::json!({
+            "invocations": items,
+            "count": items.len(),
+        })),
+    )
 }
 
-/// GET /api/plans/for-session/{session_id} — Return the plan name bound to a session.
-async fn api_plan_for_session(
+/// GET /api/sessions/{id}/tools — Tool invocations of one session, in call order.
+async fn api_get_session_tools(
     State(state): State<AppState>,
-    Path(session_id): Path<String>,
+    Path(id): Path<String>,
 ) -> axum::Json<serde_json::Value> {
-    let uuid = match Uuid::parse_str(&session_id) {
-        Ok(u) => u,
-        Err(_) => return axum::Json(serde_json::json!({"plan": null})),
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return axum::Json(serde_json::json!({"error": "invalid session id"}));
     };
-    let mapping = state.session_plan_mapping.read().await;
-    match mapping.get(&uuid) {
-        Some(name) => axum::Json(serde_json::json!({"plan": name})),
-        None => axum::Json(serde_json::json!({"plan": null})),
-    }
-}
-
-/// GET /api/plans/{name}/plan.json — Serve plan.json from /work/plan/{name}/.
-async fn api_get_plan_json(
-    State(state): State<AppState>,
-    Path(name): Path<String>,
-) -> axum::response::Response {
-    use axum::http::{StatusCode, header};
-    use axum::response::IntoResponse;
 
-    if !name
-        .chars()
-        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-    {
-        return (StatusCode::BAD_REQUEST, "invalid plan name").into_response();
+    // Tool invocations are derived while parsing — make sure the session is.
+    let cli_type = {
+        let types = state.session_cli_types.read().await;
+        types.get(&uuid).copied().unwrap_or_default()
+    };
+    let jsonl_path = {
+        let paths = state.session_paths.read().await;
+        paths.get(&uuid).cloned()
+    };
+    if let Some(ref path) = jsonl_path {
+        let mut world = state.ecs.write().await;
+        let _ = noaide_server::cache::ensure_warm(&mut world, uuid, path, cli_type).await;
     }
 
-    let path = state.plan_base_dir.join(&name).join("plan.json");
-    match tokio::fs::read(&path).await {
-        Ok(bytes) => (
-            StatusCode::OK,
-            [
-                (header::CONTENT_TYPE, "application/json"),
-                (header::CACHE_CONTROL, "no-cache"),
-            ],
-            bytes,
-        )
-            .into_response(),
-        Err(_) => (StatusCode::NOT_FOUND, "plan.json not found").into_response(),
-    }
+    let world = state.ecs.read().await;
+    let items: Vec<serde_json::Value> = world
+        .query_tool_invocations_by_session(world.resolve_alias(uuid))
+        .iter()
+        .map(tool_invocation_json)
+        .collect();
+    axum::Json(serde_json::json!({
+        "sessionId": uuid.to_string(),
+        "invocations": items,
+    }))
 }
 
-fn append_incoming_edit_values(target: &mut Vec<serde_json::Value>, value: serde_json::Value) {
-    match value {
-        serde_json::Value::Array(items) => target.extend(items),
-        serde_json::Value::Null => {}
-        other => target.push(other),
+/// GET /api/sessions/{id}/activity — What the agent is doing right now and
+/// the time spent per activity state.
+async fn api_get_session_activity(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+    let session_id = state.ecs.read().await.resolve_alias(uuid);
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    match state.activity.report(session_id, now_ms).await {
+        Some(report) => (
+            axum::http::StatusCode::OK,
+            axum::Json(serde_json::json!({
+                "sessionId": session_id.to_string(),
+                "activity": report.activity,
+                "sinceMs": report.since_ms,
+                "totalsMs": report.totals_ms,
+                "transitions": report.transitions,
+            })),
+        ),
+        None => (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "no activity recorded for this session"})),
+        ),
     }
 }
 
-fn append_existing_edit_values(
-    target: &mut Vec<serde_json::Value>,
-    value: serde_json::Value,
-) -> Result<(), String> {
-    match value {
-        serde_json::Value::Array(items) => {
-            target.extend(items);
-            Ok(())
+/// GET /api/sessions/{id}/tree — Conversation DAG: branches from edits and
+/// rewinds, compaction boundaries, subagent sidechains and the live branch.
+async fn api_get_session_tree(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+    let Some(path) = state.session_paths.read().await.get(&uuid).cloned() else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "session not found"})),
+        );
+    };
+    let cli_type = {
+        let types = state.session_cli_types.read().await;
+        types.get(&uuid).copied().unwrap_or_default()
+    };
+
+    let tree = if cli_type == noaide_server::discovery::scanner::CliType::Claude {
+        parser::tree::build_claude_tree(&path).await
+    } else {
+        parser::registry::for_cli(cli_type)
+            .parse_file(&path)
+            .await
+            .map(|messages| {
+                let mut builder = parser::tree::TreeBuilder::new();
+                for msg in &messages {
+                    builder.push_message(msg);
+                }
+                builder.build()
+            })
+    };
+    let tree = match tree {
+        Ok(tree) => tree,
+        Err(e) => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": "failed to read transcript",
+                    "detail": e.to_string(),
+                })),
+            );
         }
-        serde_json::Value::Null => Ok(()),
-        _ => Err("existing plan-edits.json `edits` field must be an array".to_string()),
-    }
-}
+    };
 
-fn merge_plan_edit_payloads(
-    existing: Option<serde_json::Value>,
-    incoming: serde_json::Value,
-) -> Result<serde_json::Value, String> {
-    let mut base = serde_json::Map::new();
-    let mut edits = Vec::new();
+    let continues_from_session = match &tree.continues_from {
+        Some(parent) => parser::tree::find_continued_session(&path, parent).await,
+        None => None,
+    };
+    (
+        axum::http::StatusCode::OK,
+        axum::Json(serde_json::json!({
+            "sessionId": uuid.to_string(),
+            "liveCount": tree.live_count(),
+            "abandonedCount": tree.abandoned_count(),
+            "continuesFromSession": continues_from_session,
+            "roots": tree.roots,
+            "leaf": tree.leaf,
+            "branchPoints": tree.branch_points,
+            "continuesFrom": tree.continues_from,
+            "nodes": tree.nodes,
+        })),
+    )
+}
 
-    match existing {
-        Some(serde_json::Value::Object(mut object)) => {
-            if let Some(existing_edits) = object.remove("edits") {
-                append_existing_edit_values(&mut edits, existing_edits)?;
+#[derive(serde::Deserialize, Default)]
+struct ExportQuery {
+    format: Option<String>,
+    redact: Option<bool>,
+    /// Extra redaction regex (use `a|b` for several).
+    pattern: Option<String>,
+    hide_meta: Option<bool>,
+    max_output: Option<usize>,
+    /// Embed the terminal recordings (JSON format).
+    recordings: Option<bool>,
+}
+
+/// GET /api/sessions/{id}/export — Download a session as Markdown, single-file
+/// HTML or normalized JSON (`?format=md|html|json`).
+async fn api_export_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ExportQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    use noaide_server::export::{self, ExportFormat, ExportOptions};
+
+    let bad_request = |error: String| {
+        (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": error})),
+        )
+            .into_response()
+    };
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return bad_request("invalid session id".to_string());
+    };
+    let format = match query.format.as_deref() {
+        None => ExportFormat::Markdown,
+        Some(f) => match ExportFormat::parse(f) {
+            Some(format) => format,
+            None => return bad_request(format!("unknown format: {f}")),
+        },
+    };
+    let patterns: Vec<String> = query.pattern.into_iter().collect();
+    let redact_patterns = match ExportOptions::compile_patterns(&patterns) {
+        Ok(p) => p,
+        Err(e) => return bad_request(format!("invalid pattern: {e}")),
+    };
+    let opts = ExportOptions {
+        format,
+        redact: query.redact.unwrap_or(true),
+        redact_patterns,
+        hide_meta: query.hide_meta.unwrap_or(false),
+        max_output_bytes: query.max_output,
+    };
+
+    let Some(path) = state.session_paths.read().await.get(&uuid).cloned() else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "session not found"})),
+        )
+            .into_response();
+    };
+    let cli_type = {
+        let types = state.session_cli_types.read().await;
+        types.get(&uuid).copied().unwrap_or_default()
+    };
+
+    // Render from the ECS cache when the session fits; large transcripts are
+    // parsed directly.
+    let messages = {
+        let mut world = state.ecs.write().await;
+        let _ = noaide_server::cache::ensure_warm(&mut world, uuid, &path, cli_type).await;
+        world.query_messages_by_session(world.resolve_alias(uuid))
+    };
+    let mut session = if messages.is_empty() {
+        match export::load_transcript(&path, uuid).await {
+            Ok(session) => session,
+            Err(e) => {
+                return (
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    axum::Json(serde_json::json!({
+                        "error": "failed to read transcript",
+                        "detail": e.to_string(),
+                    })),
+                )
+                    .into_response();
             }
-            base = object;
         }
-        Some(serde_json::Value::Array(items)) => edits.extend(items),
-        Some(serde_json::Value::Null) | None => {}
-        Some(_) => {
-            return Err("existing plan-edits.json must be a JSON object or array".to_string());
+    } else {
+        export::ExportSession {
+            session_id: uuid,
+            cli: cli_type.as_str().to_string(),
+            path: Some(path.display().to_string()),
+            messages,
+            subagents: export::load_subagents(&path, uuid).await,
+            recordings: Vec::new(),
         }
-    }
+    };
+    let recorded_id = state.ecs.read().await.resolve_alias(uuid);
+    let embed = query.recordings.unwrap_or(false) && format == ExportFormat::Json;
+    session.recordings = tokio::task::spawn_blocking(move || {
+        let dir = recording::recordings_dir();
+        recording::list(&dir, recorded_id)
+            .into_iter()
+            .map(|info| export::ExportRecording {
+                cast: embed
+                    .then(|| recording::recording_file(&dir, recorded_id, &info.name))
+                    .flatten()
+                    .and_then(|file| std::fs::read_to_string(file).ok()),
+                info,
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default();
 
-    match incoming {
-        serde_json::Value::Object(mut object) => {
-            if let Some(incoming_edits) = object.remove("edits") {
-                append_incoming_edit_values(&mut edits, incoming_edits);
-                for (key, value) in object {
-                    base.insert(key, value);
-                }
-            } else {
-                edits.push(serde_json::Value::Object(object));
-            }
-        }
-        serde_json::Value::Array(items) => edits.extend(items),
-        serde_json::Value::Null => {}
-        other => edits.push(other),
-    }
+    let body = export::render(&session, &opts);
+    axum::response::Response::builder()
+        .header("content-type", format.content_type())
+        .header(
+            "content-disposition",
+            format!(
+                "attachment; filename=\"session-{uuid}.{}\"",
+                format.extension()
+            ),
+        )
+        .body(axum::body::Body::from(body))
+        .unwrap()
+}
 
-    base.insert("edits".to_string(), serde_json::Value::Array(edits));
-    Ok(serde_json::Value::Object(base))
+#[derive(serde::Deserialize, Default)]
+struct SchemaDriftQuery {
+    source: Option<String>,
 }
 
-fn atomic_json_tmp_path(path: &std::path::Path) -> PathBuf {
-    let stamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_nanos())
-        .unwrap_or_default();
-    let file_name = path
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("plan-edits.json");
-    path.with_file_name(format!("{file_name}.tmp-{}-{stamp}", std::process::id()))
+/// GET /api/schema-drift — Transcript elements the parsers didn't recognize,
+/// newest first. Optional `?source=claude|codex|gemini|opencode`.
+async fn api_get_schema_drift(
+    axum::extract::Query(query): axum::extract::Query<SchemaDriftQuery>,
+) -> axum::Json<serde_json::Value> {
+    let records: Vec<_> = parser::drift::records()
+        .into_iter()
+        .filter(|r| query.source.as_deref().is_none_or(|s| r.source == s))
+        .collect();
+    axum::Json(serde_json::json!({
+        "count": records.len(),
+        "drift": records,
+    }))
 }
 
-async fn write_json_atomic(path: &std::path::Path, json: &str) -> std::io::Result<()> {
-    let tmp_path = atomic_json_tmp_path(path);
-    tokio::fs::write(&tmp_path, json).await?;
-    match tokio::fs::rename(&tmp_path, path).await {
-        Ok(()) => Ok(()),
-        Err(err) => {
-            let _ = tokio::fs::remove_file(&tmp_path).await;
-            Err(err)
-        }
-    }
+#[derive(serde::Deserialize, Default)]
+struct SearchQuery {
+    q: Option<String>,
+    /// Max hits (default 50, at most 500).
+    limit: Option<usize>,
+    offset: Option<usize>,
 }
 
-/// POST /api/plans/{name}/edits — Append plan-edits.json for the session to pick up.
-async fn api_post_plan_edits(
+/// GET /api/search?q= — Full-text search over all indexed sessions, newest
+/// first. See `search::Query` for the query language.
+async fn api_search(
     State(state): State<AppState>,
-    Path(name): Path<String>,
-    axum::Json(edits): axum::Json<serde_json::Value>,
-) -> axum::Json<serde_json::Value> {
-    // Validate plan name (prevent path traversal)
-    if !name
-        .chars()
-        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-    {
-        return axum::Json(serde_json::json!({"error": "invalid plan name"}));
-    }
-
-    let edits_path = state.plan_base_dir.join(&name).join("plan-edits.json");
-
-    // Ensure directory exists
-    if let Some(parent) = edits_path.parent()
-        && let Err(e) = tokio::fs::create_dir_all(parent).await
-    {
-        tracing::warn!(error = %e, "failed to create plan directory");
-        return axum::Json(serde_json::json!({"error": "failed to create directory"}));
-    }
+    axum::extract::Query(query): axum::extract::Query<SearchQuery>,
+) -> impl axum::response::IntoResponse {
+    use noaide_server::search;
 
-    let existing = match tokio::fs::read_to_string(&edits_path).await {
-        Ok(raw) => match serde_json::from_str::<serde_json::Value>(&raw) {
-            Ok(value) => Some(value),
-            Err(e) => {
-                tracing::warn!(
-                    error = %e,
-                    plan = %name,
-                    "refusing to overwrite invalid plan-edits.json"
-                );
-                return axum::Json(serde_json::json!({"error": "existing edits invalid"}));
-            }
-        },
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+    let q = query.q.unwrap_or_default();
+    let parsed = match search::Query::parse(&q) {
+        Ok(parsed) => parsed,
         Err(e) => {
-            tracing::warn!(error = %e, plan = %name, "failed to read plan-edits.json");
-            return axum::Json(serde_json::json!({"error": "read failed"}));
+            return (
+                StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({
+                    "error": "invalid query",
+                    "detail": e.to_string(),
+                })),
+            );
         }
     };
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let offset = query.offset.unwrap_or(0);
 
-    let merged_edits = match merge_plan_edit_payloads(existing, edits) {
-        Ok(value) => value,
+    let results = match search::search(&state.db, &parsed, offset, limit).await {
+        Ok(results) => results,
         Err(e) => {
-            tracing::warn!(error = %e, plan = %name, "failed to merge plan-edits payload");
-            return axum::Json(serde_json::json!({"error": e}));
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": "search failed",
+                    "detail": e.to_string(),
+                })),
+            );
         }
     };
-    let edit_count = merged_edits
-        .get("edits")
-        .and_then(|value| value.as_array())
-        .map(|items| items.len())
-        .unwrap_or_default();
-
-    match serde_json::to_string_pretty(&merged_edits) {
-        Ok(json_str) => {
-            if let Err(e) = write_json_atomic(&edits_path, &json_str).await {
-                tracing::warn!(error = %e, plan = %name, "failed to write plan-edits.json");
-                return axum::Json(serde_json::json!({"error": "write failed"}));
-            }
-            info!(
-                plan = %name,
-                path = %edits_path.display(),
-                edits = edit_count,
-                "wrote plan-edits.json"
-            );
+    let (sessions, messages) = state.db.search_index_counts().await.unwrap_or((0, 0));
+    (
+        StatusCode::OK,
+        axum::Json(serde_json::json!({
+            "query": q,
+            "hits": results.hits,
+            "hasMore": results.has_more,
+            "offset": offset,
+            "limit": limit,
+            "index": {
+                "sessions": sessions,
+                "messages": messages,
+                "fts5": state.db.fts5_available(),
+            },
+        })),
+    )
+}
 
-            // Publish plan update via WebTransport bus so connected clients
-            // receive the change instantly without polling.
-            let plan_json_path = state.plan_base_dir.join(&name).join("plan.json");
-            if let Ok(plan_data) = tokio::fs::read(&plan_json_path).await {
-                let envelope =
-                    bus::EventEnvelope::new(bus::EventSource::User, 0, 0, None, plan_data);
-                let _ = state.event_bus.publish(bus::PLAN_UPDATES, envelope).await;
-                info!(plan = %name, "published plan update via WebTransport");
-            }
+// ── Analytics ───────────────────────────────────────────────────────────────
 
-            axum::Json(serde_json::json!({"ok": true, "plan": name, "edits": edit_count}))
-        }
-        Err(e) => axum::Json(serde_json::json!({"error": e.to_string()})),
+/// GET /api/analytics — Cost, tokens, turns and tool usage across sessions,
+/// grouped by day, project, model and CLI. See `analytics::Filter`.
+async fn api_analytics(
+    State(state): State<AppState>,
+    axum::extract::Query(filter): axum::extract::Query<noaide_server::analytics::Filter>,
+) -> impl axum::response::IntoResponse {
+    match analytics_report(&state, &filter).await {
+        Ok(report) => (StatusCode::OK, axum::Json(serde_json::json!(report))),
+        Err(e) => e,
     }
 }
 
-#[cfg(test)]
-mod plan_edits_tests {
-    use serde_json::json;
-
-    use super::{merge_plan_edit_payloads, write_json_atomic};
+#[derive(serde::Deserialize)]
+struct AnalyticsExportQuery {
+    view: noaide_server::analytics::View,
+    format: Option<String>,
+}
 
-    #[test]
-    fn plan_edits_merge_appends_incoming_edits_without_dropping_existing() {
-        let existing = json!({
-            "version": 1,
-            "edits": [
-                {"type": "gate_status", "id": 1, "status": "pending"}
-            ]
-        });
-        let incoming = json!({
-            "source": "ui",
-            "edits": [
-                {"type": "gate_status", "id": 1, "status": "pass"}
-            ]
-        });
+/// GET /api/analytics/export?view=day|project|model|cli|tools|sessions —
+/// One table of the report, as CSV unless `format=json`.
+async fn api_export_analytics(
+    State(state): State<AppState>,
+    axum::extract::Query(filter): axum::extract::Query<noaide_server::analytics::Filter>,
+    axum::extract::Query(query): axum::extract::Query<AnalyticsExportQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    use noaide_server::analytics::{View, report};
 
-        let merged = merge_plan_edit_payloads(Some(existing), incoming).unwrap();
-        assert_eq!(merged["version"], json!(1));
-        assert_eq!(merged["source"], json!("ui"));
-        assert_eq!(merged["edits"].as_array().unwrap().len(), 2);
-        assert_eq!(merged["edits"][0]["status"], json!("pending"));
-        assert_eq!(merged["edits"][1]["status"], json!("pass"));
+    let report = match analytics_report(&state, &filter).await {
+        Ok(report) => report,
+        Err(e) => return e.into_response(),
+    };
+    if query.format.as_deref() == Some("json") {
+        let rows = match query.view {
+            View::Day => serde_json::json!(report.by_day),
+            View::Project => serde_json::json!(report.by_project),
+            View::Model => serde_json::json!(report.by_model),
+            View::Cli => serde_json::json!(report.by_cli),
+            View::Tools => serde_json::json!(report.tools),
+            View::Sessions => serde_json::json!(report.top_sessions),
+        };
+        return axum::Json(rows).into_response();
     }
+    let view = serde_json::to_value(query.view)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default();
+    axum::response::Response::builder()
+        .header("content-type", "text/csv")
+        .header(
+            "content-disposition",
+            format!("attachment; filename=\"noaide-analytics-{view}.csv\""),
+        )
+        .body(axum::body::Body::from(report::to_csv(&report, query.view)))
+        .unwrap()
+}
 
-    #[test]
-    fn plan_edits_merge_accepts_legacy_array_and_single_edit_payloads() {
-        let existing = json!([
-            {"type": "wp_status", "id": "wp-1", "status": "todo"}
-        ]);
-        let incoming = json!({"type": "section_status", "id": "b-1", "status": "done"});
-
-        let merged = merge_plan_edit_payloads(Some(existing), incoming).unwrap();
-        assert_eq!(merged["edits"].as_array().unwrap().len(), 2);
-        assert_eq!(merged["edits"][0]["type"], json!("wp_status"));
-        assert_eq!(merged["edits"][1]["type"], json!("section_status"));
+async fn analytics_report(
+    state: &AppState,
+    filter: &noaide_server::analytics::Filter,
+) -> Result<noaide_server::analytics::Report, (StatusCode, axum::Json<serde_json::Value>)> {
+    if let Err(detail) = filter.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid filter", "detail": detail})),
+        ));
     }
+    noaide_server::analytics::report::build(&state.db, filter)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": "analytics query failed",
+                    "detail": e.to_string(),
+                })),
+            )
+        })
+}
 
-    #[test]
-    fn plan_edits_merge_rejects_invalid_existing_edit_shape() {
-        let existing = json!({"edits": "not-an-array"});
-        let incoming = json!({"edits": []});
+// ── Retention ───────────────────────────────────────────────────────────────
 
-        assert!(merge_plan_edit_payloads(Some(existing), incoming).is_err());
-    }
-
-    #[tokio::test]
-    async fn plan_edits_atomic_write_replaces_target_and_removes_temp_file() {
-        let dir = tempfile::tempdir().unwrap();
-        let path = dir.path().join("plan-edits.json");
-        tokio::fs::write(&path, r#"{"edits":[{"id":1}]}"#)
-            .await
-            .unwrap();
-
-        write_json_atomic(&path, r#"{"edits":[{"id":2}]}"#)
-            .await
-            .unwrap();
-
-        let raw = tokio::fs::read_to_string(&path).await.unwrap();
-        assert_eq!(raw, r#"{"edits":[{"id":2}]}"#);
+/// GET /api/retention — Active policy and the report of the last run.
+async fn api_get_retention(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({
+        "policy": state.retention.policy().await,
+        "lastRun": state.retention.last_report().await,
+    }))
+}
 
-        let mut entries = tokio::fs::read_dir(dir.path()).await.unwrap();
-        let mut file_count = 0;
-        while let Some(entry) = entries.next_entry().await.unwrap() {
-            file_count += 1;
-            assert_eq!(entry.file_name().to_string_lossy(), "plan-edits.json");
-        }
-        assert_eq!(file_count, 1);
+/// PUT /api/retention — Replace the policy (persisted to `retention.json`).
+async fn api_put_retention(
+    State(state): State<AppState>,
+    axum::Json(policy): axum::Json<noaide_server::retention::RetentionPolicy>,
+) -> impl axum::response::IntoResponse {
+    if let Err(e) = policy.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({
+                "error": "invalid retention policy",
+                "detail": e.to_string(),
+            })),
+        );
+    }
+    match state.retention.set_policy(policy.clone()).await {
+        Ok(()) => (StatusCode::OK, axum::Json(serde_json::json!(policy))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({
+                "error": "failed to save retention policy",
+                "detail": e.to_string(),
+            })),
+        ),
     }
 }
 
-/// Convert a MessageComponent to JSON for the API response.
-fn component_to_json(m: &noaide_server::ecs::components::MessageComponent) -> serde_json::Value {
-    let content_blocks = m
-        .content_blocks_json
-        .as_ref()
-        .and_then(|json_str| serde_json::from_str::<serde_json::Value>(json_str).ok());
-
-    serde_json::json!({
-        "uuid": m.id.to_string(),
-        "sessionId": m.session_id.to_string(),
-        "role": format!("{:?}", m.role).to_lowercase(),
-        "content": m.content,
-        "contentBlocks": content_blocks,
-        "timestamp": m.timestamp,
-        "tokens": m.tokens,
-        "hidden": m.hidden,
-        "messageType": format!("{:?}", m.message_type),
-        "model": m.model,
-        "stopReason": m.stop_reason,
-        "inputTokens": m.input_tokens,
-        "outputTokens": m.output_tokens,
-        "cacheCreationInputTokens": m.cache_creation_input_tokens,
-        "cacheReadInputTokens": m.cache_read_input_tokens,
-    })
+/// GET /api/retention/report — Dry run: what compaction would remove now.
+async fn api_retention_report(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!(state.retention.compact(true).await))
 }
 
-// ── Send Message Handler (stream-json) ──────────────────────────────────────
+/// POST /api/retention/compact — Run compaction now.
+async fn api_retention_compact(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!(state.retention.compact(false).await))
+}
 
-#[derive(serde::Deserialize)]
-struct SendMessageRequest {
-    text: String,
+/// GET /api/retention/archives — Sessions in cold storage.
+async fn api_list_archives(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!(state.retention.archives()))
 }
 
-/// Send a text message to a Claude Code session via `claude -p --resume`.
-///
-/// Spawns a `claude -p --resume <session-id>` process with `--input-format stream-json`.
-/// The process loads the session's conversation, processes the new message via the
-/// Anthropic API, and writes the response to the session's JSONL file.
-/// The file watcher picks up the changes for the normal streaming pipeline.
-async fn api_send_message(
+/// POST /api/retention/archives/{id}/restore — Move an archived session
+/// back into the database.
+async fn api_restore_archive(
     State(state): State<AppState>,
     Path(id): Path<String>,
-    axum::Json(body): axum::Json<SendMessageRequest>,
 ) -> impl axum::response::IntoResponse {
-    let Ok(uuid) = Uuid::parse_str(&id) else {
+    let Ok(session_id) = Uuid::parse_str(&id) else {
         return (
-            axum::http::StatusCode::BAD_REQUEST,
+            StatusCode::BAD_REQUEST,
             axum::Json(serde_json::json!({"error": "invalid session id"})),
         );
     };
-
-    if body.text.is_empty() {
+    if !state.retention.has_archive(&session_id) {
         return (
-            axum::http::StatusCode::BAD_REQUEST,
-            axum::Json(serde_json::json!({"error": "text must not be empty"})),
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "no archive for this session"})),
         );
     }
+    match state.retention.restore(session_id).await {
+        Ok(rows) => (
+            StatusCode::OK,
+            axum::Json(serde_json::json!({"sessionId": session_id, "rows": rows})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({
+                "error": "restore failed",
+                "detail": e.to_string(),
+            })),
+        ),
+    }
+}
 
-    // Priority 1: If this is a managed session, send via PTY input directly.
-    // Managed sessions have a live PTY — no need for stream-json.
-    {
-        let session_id = noaide_server::session::SessionId(uuid);
-        let mgr = state.session_manager.read().await;
-        if let Some(session) = mgr.get(&session_id) {
-            let cli_type = {
-                let types = state.session_cli_types.read().await;
-                types.get(&uuid).copied()
-            };
+// ── Backup ──────────────────────────────────────────────────────────────────
 
-            let send_result = async {
-                if cli_type == Some(noaide_server::discovery::scanner::CliType::Gemini) {
-                    // Gemini CLI treats multi-character PTY writes as an untrusted
-                    // paste. In that mode Enter is downgraded to newline instead
-                    // of submit, so the prompt just grows a blank line and never
-                    // issues generateContent/streamGenerateContent. Type each
-                    // character as an individual keypress to avoid paste mode.
-                    for ch in body.text.chars() {
-                        let mut buf = [0u8; 4];
-                        session.send_input(ch.encode_utf8(&mut buf)).await?;
-                        tokio::time::sleep(std::time::Duration::from_millis(15)).await;
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
-                    session.send_input("\r").await
-                } else {
-                    // Send text and CR separately. Ink-based TUIs parse raw PTY
-                    // input in chunks — if text and \r arrive in the same read(),
-                    // \r can end up as literal newline text instead of submit.
-                    session.send_input(&body.text).await?;
-                    tokio::time::sleep(std::time::Duration::from_millis(30)).await;
-                    session.send_input("\r").await
-                }
-            }
-            .await;
+#[derive(serde::Deserialize)]
+struct BackupPassphrase {
+    passphrase: String,
+}
 
-            match send_result {
-                Ok(()) => {
-                    info!(
-                        session = %uuid,
-                        text_len = body.text.len(),
-                        "message sent via PTY input (managed session)"
-                    );
-                    return (
-                        axum::http::StatusCode::OK,
-                        axum::Json(serde_json::json!({
-                            "ok": true,
-                            "method": "pty-input",
-                        })),
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        session = %uuid,
-                        error = %e,
-                        "PTY input failed for managed session"
-                    );
-                    return (
-                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                        axum::Json(serde_json::json!({
-                            "error": format!("PTY input failed: {e}")
-                        })),
-                    );
-                }
-            }
+fn backup_error(
+    error: &str,
+    e: noaide_server::backup::BackupError,
+) -> (StatusCode, axum::Json<serde_json::Value>) {
+    use noaide_server::backup::BackupError;
+    let status = match e {
+        BackupError::WrongPassphrase => StatusCode::FORBIDDEN,
+        BackupError::WeakPassphrase
+        | BackupError::Format(_)
+        | BackupError::NewerSchema(_)
+        | BackupError::Checksum(_) => StatusCode::BAD_REQUEST,
+        BackupError::Io(_) | BackupError::Json(_) | BackupError::Db(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
         }
+    };
+    (
+        status,
+        axum::Json(serde_json::json!({"error": error, "detail": e.to_string()})),
+    )
+}
+
+/// The path of backup `name`, if the name is acceptable.
+fn backup_path(state: &AppState, name: &str) -> Option<PathBuf> {
+    noaide_server::backup::valid_name(name).then(|| state.backup_layout.backups.join(name))
+}
+
+/// GET /api/backups — Backups on this host, and whether a restore is
+/// waiting for the next start.
+async fn api_list_backups(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    let layout = &state.backup_layout;
+    axum::Json(serde_json::json!({
+        "backups": noaide_server::backup::list(&layout.backups),
+        "restorePending": noaide_server::backup::has_staged(layout),
+    }))
+}
+
+/// POST /api/backups — Back up the whole state. Body: `{"passphrase": …}`,
+/// which seals API keys and the CA key.
+async fn api_create_backup(
+    State(state): State<AppState>,
+    axum::Json(body): axum::Json<BackupPassphrase>,
+) -> impl axum::response::IntoResponse {
+    let db = Arc::clone(&state.db);
+    let layout = Arc::clone(&state.backup_layout);
+    let options = noaide_server::backup::BackupOptions::new(body.passphrase);
+    // Key derivation, copying and compression block; keep them off the
+    // runtime's worker threads.
+    let runtime = tokio::runtime::Handle::current();
+    let created = tokio::task::spawn_blocking(move || {
+        runtime.block_on(noaide_server::backup::create(
+            &db,
+            &layout,
+            &options,
+            &layout.backups,
+        ))
+    })
+    .await;
+    match created {
+        Ok(Ok(summary)) => (StatusCode::CREATED, axum::Json(serde_json::json!(summary))),
+        Ok(Err(e)) => backup_error("backup failed", e),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({"error": "backup failed", "detail": e.to_string()})),
+        ),
     }
+}
 
-    // Priority 2: Observed session — look up JSONL path → stream-json
-    let jsonl_path = {
-        let paths = state.session_paths.read().await;
-        paths.get(&uuid).cloned()
+/// GET /api/backups/{name} — Download a backup.
+async fn api_download_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let Some(path) = backup_path(&state, &name) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid backup name"})),
+        )
+            .into_response();
     };
+    match tokio::fs::File::open(&path).await {
+        Ok(file) => {
+            let mut response = axum::response::Response::builder()
+                .header("content-type", "application/octet-stream")
+                .header(
+                    "content-disposition",
+                    format!("attachment; filename=\"{name}\""),
+                );
+            if let Ok(metadata) = file.metadata().await {
+                response = response.header("content-length", metadata.len());
+            }
+            response
+                .body(axum::body::Body::from_stream(
+                    tokio_util::io::ReaderStream::new(file),
+                ))
+                .unwrap()
+        }
+        Err(_) => (
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "backup not found"})),
+        )
+            .into_response(),
+    }
+}
 
-    let Some(jsonl_path) = jsonl_path else {
+/// Largest backup `PUT /api/backups/{name}` accepts (the route has no
+/// body limit of its own).
+const MAX_BACKUP_UPLOAD: u64 = 8 << 30;
+
+/// Stream a request body into `path`. `Ok(None)` once it grows past
+/// `limit` bytes; the partial file is left for the caller to remove.
+async fn write_body_capped(
+    body: axum::body::Body,
+    path: &std::path::Path,
+    limit: u64,
+) -> std::io::Result<Option<u64>> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut stream = body.into_data_stream();
+    let mut written = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        written += chunk.len() as u64;
+        if written > limit {
+            return Ok(None);
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(Some(written))
+}
+
+/// PUT /api/backups/{name} — Upload a backup from another host. The body
+/// is the file (at most [`MAX_BACKUP_UPLOAD`] bytes); it is kept only if
+/// its manifest is readable.
+async fn api_upload_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: axum::body::Body,
+) -> impl axum::response::IntoResponse {
+    let Some(path) = backup_path(&state, &name) else {
         return (
-            axum::http::StatusCode::NOT_FOUND,
-            axum::Json(serde_json::json!({"error": "session not found"})),
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid backup name"})),
         );
     };
-
-    let cwd = match resolve_session_cwd(&jsonl_path) {
-        Some(cwd) => cwd,
-        None => {
+    if path.exists() {
+        return (
+            StatusCode::CONFLICT,
+            axum::Json(serde_json::json!({"error": "a backup with this name exists"})),
+        );
+    }
+    let upload = state.backup_layout.backups.join(format!(".{name}.upload"));
+    let written = async {
+        tokio::fs::create_dir_all(&state.backup_layout.backups).await?;
+        write_body_capped(body, &upload, MAX_BACKUP_UPLOAD).await
+    };
+    let bytes = match written.await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            let _ = tokio::fs::remove_file(&upload).await;
             return (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::PAYLOAD_TOO_LARGE,
                 axum::Json(serde_json::json!({
-                    "error": "could not resolve project directory from session path"
+                    "error": "backup too large",
+                    "limit": MAX_BACKUP_UPLOAD,
                 })),
             );
         }
-    };
-
-    // Spawn claude -p --resume to deliver the message
-    match send_via_stream_json(&id, &cwd, &body.text).await {
-        Ok(()) => {
-            info!(
-                session = %uuid,
-                cwd = %cwd.display(),
-                text_len = body.text.len(),
-                "message sent via stream-json"
-            );
-            (
-                axum::http::StatusCode::OK,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&upload).await;
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
                 axum::Json(serde_json::json!({
-                    "ok": true,
-                    "method": "stream-json",
-                    "cwd": cwd.display().to_string(),
+                    "error": "failed to store upload",
+                    "detail": e.to_string(),
                 })),
-            )
-        }
-        Err(e) => {
-            warn!(
-                session = %uuid,
-                error = %e,
-                "stream-json send failed"
             );
-            (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    };
+    match noaide_server::backup::read_manifest(&upload) {
+        Ok(manifest) => match tokio::fs::rename(&upload, &path).await {
+            Ok(()) => (
+                StatusCode::CREATED,
                 axum::Json(serde_json::json!({
-                    "error": format!("failed to spawn claude: {}", e)
+                    "name": name,
+                    "bytes": bytes,
+                    "manifest": manifest,
                 })),
-            )
+            ),
+            Err(e) => backup_error("failed to store upload", e.into()),
+        },
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&upload).await;
+            backup_error("not a noaide backup", e)
         }
     }
 }
 
-// ── Create Managed Session Handler ───────────────────────────────────────────
-
-#[derive(serde::Deserialize)]
-struct CreateManagedSessionRequest {
-    working_dir: String,
-    /// CLI type to spawn: "claude" (default), "codex", or "gemini".
-    cli_type: Option<String>,
-    /// Skip all tool permission prompts (--dangerously-skip-permissions).
-    auto_approve: Option<bool>,
+/// POST /api/backups/{name}/restore — Verify a backup and stage it,
+/// re-keyed for this host. Body: `{"passphrase": …}`. The staged state
+/// replaces the current one on the next start.
+async fn api_restore_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    axum::Json(body): axum::Json<BackupPassphrase>,
+) -> impl axum::response::IntoResponse {
+    let Some(path) = backup_path(&state, &name) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid backup name"})),
+        );
+    };
+    if !path.exists() {
+        return (
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "backup not found"})),
+        );
+    }
+    let layout = state.backup_layout.clone();
+    let staged = tokio::task::spawn_blocking(move || {
+        noaide_server::backup::stage(&path, &layout, &body.passphrase)
+    })
+    .await;
+    match staged {
+        Ok(Ok(manifest)) => (
+            StatusCode::ACCEPTED,
+            axum::Json(serde_json::json!({
+                "staged": true,
+                "restartRequired": true,
+                "manifest": manifest,
+            })),
+        ),
+        Ok(Err(e)) => backup_error("restore failed", e),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({"error": "restore failed", "detail": e.to_string()})),
+        ),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// TOGAF Plan API Endpoints
+// Plans live in /work/plan/{name}/ — nginx serves plan.json,
+// the server only provides discovery and edit-write-back.
+// ═══════════════════════════════════════════════════════════════
+
+/// GET /api/plans — List available plans in the plan base directory.
+async fn api_list_plans(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    let base = &*state.plan_base_dir;
+    let mut plans = Vec::new();
+
+    if let Ok(mut entries) = tokio::fs::read_dir(base).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(ft) = entry.file_type().await
+                && ft.is_dir()
+            {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Validate name (alphanumeric + dash + underscore only)
+                if name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                {
+                    let plan_json = base.join(&name).join("plan.json");
+                    let has_plan = tokio::fs::metadata(&plan_json).await.is_ok();
+                    let edits_json = base.join(&name).join("plan-edits.json");
+                    let has_edits = tokio::fs::metadata(&edits_json).await.is_ok();
+                    plans.push(serde_json::json!({
+                        "name": name,
+                        "has_plan_json": has_plan,
+                        "has_edits": has_edits,
+                    }));
+                }
+            }
+        }
+    }
+
+    // Sort by name
+    plans.sort_by(|a, b| {
+        a["name"]
+            .as_str()
+            .unwrap_or("")
+            .cmp(b["name"].as_str().unwrap_or(""))
+    });
+
+    axum::Json(serde_json::json!(plans))
+}
+
+/// GET /api/plans/for-session/{session_id} — Return the plan name bound to a session.
+async fn api_plan_for_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> axum::Json<serde_json::Value> {
+    let uuid = match Uuid::parse_str(&session_id) {
+        Ok(u) => u,
+        Err(_) => return axum::Json(serde_json::json!({"plan": null})),
+    };
+    let mapping = state.session_plan_mapping.read().await;
+    match mapping.get(&uuid) {
+        Some(name) => axum::Json(serde_json::json!({"plan": name})),
+        None => axum::Json(serde_json::json!({"plan": null})),
+    }
+}
+
+/// GET /api/plans/{name}/plan.json — Serve plan.json from /work/plan/{name}/.
+async fn api_get_plan_json(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> axum::response::Response {
+    use axum::http::{StatusCode, header};
+    use axum::response::IntoResponse;
+
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return (StatusCode::BAD_REQUEST, "invalid plan name").into_response();
+    }
+
+    let path = state.plan_base_dir.join(&name).join("plan.json");
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/json"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "plan.json not found").into_response(),
+    }
+}
+
+fn append_incoming_edit_values(target: &mut Vec<serde_json::Value>, value: serde_json::Value) {
+    match value {
+        serde_json::Value::Array(items) => target.extend(items),
+        serde_json::Value::Null => {}
+        other => target.push(other),
+    }
+}
+
+fn append_existing_edit_values(
+    target: &mut Vec<serde_json::Value>,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    match value {
+        serde_json::Value::Array(items) => {
+            target.extend(items);
+            Ok(())
+        }
+        serde_json::Value::Null => Ok(()),
+        _ => Err("existing plan-edits.json `edits` field must be an array".to_string()),
+    }
+}
+
+fn merge_plan_edit_payloads(
+    existing: Option<serde_json::Value>,
+    incoming: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let mut base = serde_json::Map::new();
+    let mut edits = Vec::new();
+
+    match existing {
+        Some(serde_json::Value::Object(mut object)) => {
+            if let Some(existing_edits) = object.remove("edits") {
+                append_existing_edit_values(&mut edits, existing_edits)?;
+            }
+            base = object;
+        }
+        Some(serde_json::Value::Array(items)) => edits.extend(items),
+        Some(serde_json::Value::Null) | None => {}
+        Some(_) => {
+            return Err("existing plan-edits.json must be a JSON object or array".to_string());
+        }
+    }
+
+    match incoming {
+        serde_json::Value::Object(mut object) => {
+            if let Some(incoming_edits) = object.remove("edits") {
+                append_incoming_edit_values(&mut edits, incoming_edits);
+                for (key, value) in object {
+                    base.insert(key, value);
+                }
+            } else {
+                edits.push(serde_json::Value::Object(object));
+            }
+        }
+        serde_json::Value::Array(items) => edits.extend(items),
+        serde_json::Value::Null => {}
+        other => edits.push(other),
+    }
+
+    base.insert("edits".to_string(), serde_json::Value::Array(edits));
+    Ok(serde_json::Value::Object(base))
+}
+
+fn atomic_json_tmp_path(path: &std::path::Path) -> PathBuf {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("plan-edits.json");
+    path.with_file_name(format!("{file_name}.tmp-{}-{stamp}", std::process::id()))
+}
+
+async fn write_json_atomic(path: &std::path::Path, json: &str) -> std::io::Result<()> {
+    let tmp_path = atomic_json_tmp_path(path);
+    tokio::fs::write(&tmp_path, json).await?;
+    match tokio::fs::rename(&tmp_path, path).await {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            Err(err)
+        }
+    }
+}
+
+/// POST /api/plans/{name}/edits — Append plan-edits.json for the session to pick up.
+async fn api_post_plan_edits(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    axum::Json(edits): axum::Json<serde_json::Value>,
+) -> axum::Json<serde_json::Value> {
+    // Validate plan name (prevent path traversal)
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return axum::Json(serde_json::json!({"error": "invalid plan name"}));
+    }
+
+    let edits_path = state.plan_base_dir.join(&name).join("plan-edits.json");
+
+    // Ensure directory exists
+    if let Some(parent) = edits_path.parent()
+        && let Err(e) = tokio::fs::create_dir_all(parent).await
+    {
+        tracing::warn!(error = %e, "failed to create plan directory");
+        return axum::Json(serde_json::json!({"error": "failed to create directory"}));
+    }
+
+    let existing = match tokio::fs::read_to_string(&edits_path).await {
+        Ok(raw) => match serde_json::from_str::<serde_json::Value>(&raw) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::warn!(
+                    error = %e,
+                    plan = %name,
+                    "refusing to overwrite invalid plan-edits.json"
+                );
+                return axum::Json(serde_json::json!({"error": "existing edits invalid"}));
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            tracing::warn!(error = %e, plan = %name, "failed to read plan-edits.json");
+            return axum::Json(serde_json::json!({"error": "read failed"}));
+        }
+    };
+
+    let merged_edits = match merge_plan_edit_payloads(existing, edits) {
+        Ok(value) => value,
+        Err(e) => {
+            tracing::warn!(error = %e, plan = %name, "failed to merge plan-edits payload");
+            return axum::Json(serde_json::json!({"error": e}));
+        }
+    };
+    let edit_count = merged_edits
+        .get("edits")
+        .and_then(|value| value.as_array())
+        .map(|items| items.len())
+        .unwrap_or_default();
+
+    match serde_json::to_string_pretty(&merged_edits) {
+        Ok(json_str) => {
+            if let Err(e) = write_json_atomic(&edits_path, &json_str).await {
+                tracing::warn!(error = %e, plan = %name, "failed to write plan-edits.json");
+                return axum::Json(serde_json::json!({"error": "write failed"}));
+            }
+            info!(
+                plan = %name,
+                path = %edits_path.display(),
+                edits = edit_count,
+                "wrote plan-edits.json"
+            );
+
+            // Publish plan update via WebTransport bus so connected clients
+            // receive the change instantly without polling.
+            let plan_json_path = state.plan_base_dir.join(&name).join("plan.json");
+            if let Ok(plan_data) = tokio::fs::read(&plan_json_path).await {
+                let envelope =
+                    bus::EventEnvelope::new(bus::EventSource::User, 0, 0, None, plan_data);
+                let _ = state.event_bus.publish(bus::PLAN_UPDATES, envelope).await;
+                info!(plan = %name, "published plan update via WebTransport");
+            }
+
+            axum::Json(serde_json::json!({"ok": true, "plan": name, "edits": edit_count}))
+        }
+        Err(e) => axum::Json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+#[cfg(test)]
+mod plan_edits_tests {
+    use serde_json::json;
+
+    use super::{merge_plan_edit_payloads, write_json_atomic};
+
+    #[test]
+    fn plan_edits_merge_appends_incoming_edits_without_dropping_existing() {
+        let existing = json!({
+            "version": 1,
+            "edits": [
+                {"type": "gate_status", "id": 1, "status": "pending"}
+            ]
+        });
+        let incoming = json!({
+            "source": "ui",
+            "edits": [
+                {"type": "gate_status", "id": 1, "status": "pass"}
+            ]
+        });
+
+        let merged = merge_plan_edit_payloads(Some(existing), incoming).unwrap();
+        assert_eq!(merged["version"], json!(1));
+        assert_eq!(merged["source"], json!("ui"));
+        assert_eq!(merged["edits"].as_array().unwrap().len(), 2);
+        assert_eq!(merged["edits"][0]["status"], json!("pending"));
+        assert_eq!(merged["edits"][1]["status"], json!("pass"));
+    }
+
+    #[test]
+    fn plan_edits_merge_accepts_legacy_array_and_single_edit_payloads() {
+        let existing = json!([
+            {"type": "wp_status", "id": "wp-1", "status": "todo"}
+        ]);
+        let incoming = json!({"type": "section_status", "id": "b-1", "status": "done"});
+
+        let merged = merge_plan_edit_payloads(Some(existing), incoming).unwrap();
+        assert_eq!(merged["edits"].as_array().unwrap().len(), 2);
+        assert_eq!(merged["edits"][0]["type"], json!("wp_status"));
+        assert_eq!(merged["edits"][1]["type"], json!("section_status"));
+    }
+
+    #[test]
+    fn plan_edits_merge_rejects_invalid_existing_edit_shape() {
+        let existing = json!({"edits": "not-an-array"});
+        let incoming = json!({"edits": []});
+
+        assert!(merge_plan_edit_payloads(Some(existing), incoming).is_err());
+    }
+
+    #[tokio::test]
+    async fn plan_edits_atomic_write_replaces_target_and_removes_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan-edits.json");
+        tokio::fs::write(&path, r#"{"edits":[{"id":1}]}"#)
+            .await
+            .unwrap();
+
+        write_json_atomic(&path, r#"{"edits":[{"id":2}]}"#)
+            .await
+            .unwrap();
+
+        let raw = tokio::fs::read_to_string(&path).await.unwrap();
+        assert_eq!(raw, r#"{"edits":[{"id":2}]}"#);
+
+        let mut entries = tokio::fs::read_dir(dir.path()).await.unwrap();
+        let mut file_count = 0;
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            file_count += 1;
+            assert_eq!(entry.file_name().to_string_lossy(), "plan-edits.json");
+        }
+        assert_eq!(file_count, 1);
+    }
+}
+
+/// Convert a MessageComponent to JSON for the API response.
+fn component_to_json(m: &noaide_server::ecs::components::MessageComponent) -> serde_json::Value {
+    let content_blocks = m
+        .content_blocks_json
+        .as_ref()
+        .and_then(|json_str| serde_json::from_str::<serde_json::Value>(json_str).ok());
+
+    serde_json::json!({
+        "uuid": m.id.to_string(),
+        "sessionId": m.session_id.to_string(),
+        "role": format!("{:?}", m.role).to_lowercase(),
+        "content": m.content,
+        "contentBlocks": content_blocks,
+        "timestamp": m.timestamp,
+        "tokens": m.tokens,
+        "hidden": m.hidden,
+        "messageType": format!("{:?}", m.message_type),
+        "model": m.model,
+        "stopReason": m.stop_reason,
+        "inputTokens": m.input_tokens,
+        "outputTokens": m.output_tokens,
+        "cacheCreationInputTokens": m.cache_creation_input_tokens,
+        "cacheReadInputTokens": m.cache_read_input_tokens,
+    })
+}
+
+// ── Send Message Handler (stream-json) ──────────────────────────────────────
+
+#[derive(serde::Deserialize)]
+struct SendMessageRequest {
+    text: String,
+}
+
+/// Send a text message to a Claude Code session via `claude -p --resume`.
+///
+/// Spawns a `claude -p --resume <session-id>` process with `--input-format stream-json`.
+/// The process loads the session's conversation, processes the new message via the
+/// Anthropic API, and writes the response to the session's JSONL file.
+/// The file watcher picks up the changes for the normal streaming pipeline.
+async fn api_send_message(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::Json(body): axum::Json<SendMessageRequest>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+
+    if body.text.is_empty() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "text must not be empty"})),
+        );
+    }
+
+    // Priority 1: If this is a managed session, send via PTY input directly.
+    // Managed sessions have a live PTY — no need for stream-json.
+    {
+        let session_id = noaide_server::session::SessionId(uuid);
+        let mgr = state.session_manager.read().await;
+        if let Some(session) = mgr.get(&session_id) {
+            let cli_type = {
+                let types = state.session_cli_types.read().await;
+                types.get(&uuid).copied()
+            };
+
+            let send_result = async {
+                let input_mode =
+                    parser::registry::for_cli(cli_type.unwrap_or_default()).input_mode();
+                if input_mode == parser::InputMode::Keystrokes {
+                    // Gemini CLI treats multi-character PTY writes as an untrusted
+                    // paste. In that mode Enter is downgraded to newline instead
+                    // of submit, so the prompt just grows a blank line and never
+                    // issues generateContent/streamGenerateContent. Type each
+                    // character as an individual keypress to avoid paste mode.
+                    for ch in body.text.chars() {
+                        let mut buf = [0u8; 4];
+                        session.send_input(ch.encode_utf8(&mut buf)).await?;
+                        tokio::time::sleep(std::time::Duration::from_millis(15)).await;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+                    session.send_input("\r").await
+                } else {
+                    // Send text and CR separately. Ink-based TUIs parse raw PTY
+                    // input in chunks — if text and \r arrive in the same read(),
+                    // \r can end up as literal newline text instead of submit.
+                    session.send_input(&body.text).await?;
+                    tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+                    session.send_input("\r").await
+                }
+            }
+            .await;
+
+            match send_result {
+                Ok(()) => {
+                    info!(
+                        session = %uuid,
+                        text_len = body.text.len(),
+                        "message sent via PTY input (managed session)"
+                    );
+                    observe_activity(
+                        &state.activity,
+                        &state.event_bus,
+                        &state.ecs,
+                        uuid,
+                        &Observation::Input,
+                        EventSource::User,
+                    )
+                    .await;
+                    return (
+                        axum::http::StatusCode::OK,
+                        axum::Json(serde_json::json!({
+                            "ok": true,
+                            "method": "pty-input",
+                        })),
+                    );
+                }
+                Err(e) => {
+                    warn!(
+                        session = %uuid,
+                        error = %e,
+                        "PTY input failed for managed session"
+                    );
+                    return (
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        axum::Json(serde_json::json!({
+                            "error": format!("PTY input failed: {e}")
+                        })),
+                    );
+                }
+            }
+        }
+    }
+
+    // Priority 2: Observed session — look up JSONL path → stream-json
+    let jsonl_path = {
+        let paths = state.session_paths.read().await;
+        paths.get(&uuid).cloned()
+    };
+
+    let Some(jsonl_path) = jsonl_path else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "session not found"})),
+        );
+    };
+
+    let cwd = match resolve_session_cwd(&jsonl_path) {
+        Some(cwd) => cwd,
+        None => {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": "could not resolve project directory from session path"
+                })),
+            );
+        }
+    };
+
+    // Spawn claude -p --resume to deliver the message
+    match send_via_stream_json(&id, &cwd, &body.text).await {
+        Ok(()) => {
+            info!(
+                session = %uuid,
+                cwd = %cwd.display(),
+                text_len = body.text.len(),
+                "message sent via stream-json"
+            );
+            (
+                axum::http::StatusCode::OK,
+                axum::Json(serde_json::json!({
+                    "ok": true,
+                    "method": "stream-json",
+                    "cwd": cwd.display().to_string(),
+                })),
+            )
+        }
+        Err(e) => {
+            warn!(
+                session = %uuid,
+                error = %e,
+                "stream-json send failed"
+            );
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": format!("failed to spawn claude: {}", e)
+                })),
+            )
+        }
+    }
+}
+
+// ── Create Managed Session Handler ───────────────────────────────────────────
+
+#[derive(serde::Deserialize)]
+struct CreateManagedSessionRequest {
+    working_dir: String,
+    /// CLI type to spawn: "claude" (default), "codex", or "gemini".
+    cli_type: Option<String>,
+    /// Skip all tool permission prompts (--dangerously-skip-permissions).
+    auto_approve: Option<bool>,
+    /// Proxy profile to apply. Defaults to the profile whose project pattern
+    /// matches `working_dir`, if any.
+    profile: Option<String>,
+    /// Extra environment for the CLI process. Recorded for resumes.
+    #[serde(default)]
+    env: std::collections::BTreeMap<String, String>,
+    /// Initial terminal size; 80x24 unless both are given.
+    cols: Option<u16>,
+    rows: Option<u16>,
+    /// How attached terminal viewers size the PTY: "smallest" (default) or
+    /// "owner".
+    #[serde(default)]
+    size_policy: noaide_server::session::SizePolicy,
+    /// Run the CLI in a dedicated git worktree on a new branch
+    /// `noaide/<session id>` instead of in `working_dir` itself.
+    #[serde(default)]
+    worktree: bool,
+}
+
+/// Spawn a new managed CLI session (claude, codex, or gemini) via PTY.
+///
+/// The session process gets per-session proxy URLs (`ANTHROPIC_BASE_URL`,
+/// `OPENAI_BASE_URL`, `CODE_ASSIST_ENDPOINT`) so all API traffic is
+/// routed through the noaide proxy and attributed to the session.
+///
+/// With `worktree`, the session gets its own checkout of the repository
+/// containing `working_dir` and runs at the same place inside it, so several
+/// agents can work on one project without touching each other's files.
+async fn api_create_managed_session(
+    State(state): State<AppState>,
+    axum::Json(body): axum::Json<CreateManagedSessionRequest>,
+) -> impl axum::response::IntoResponse {
+    let working_dir = PathBuf::from(&body.working_dir);
+    if !working_dir.is_dir() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(
+                serde_json::json!({"error": "working_dir does not exist or is not a directory"}),
+            ),
+        );
+    }
+
+    let size = body.cols.zip(body.rows);
+    if let Some((cols, rows)) = size
+        && let Err(e) = noaide_server::session::check_size(cols, rows)
+    {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": e.to_string()})),
+        );
+    }
+
+    let profile = match body.profile.as_deref() {
+        Some(name) => match state.proxy.profiles.get(name) {
+            Some(profile) => Some(profile),
+            None => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    axum::Json(serde_json::json!({"error": format!("unknown profile: {name}")})),
+                );
+            }
+        },
+        None => state.proxy.profiles.match_project(&body.working_dir),
+    };
+
+    let new_id = Uuid::new_v4();
+    let worktree = if body.worktree {
+        let dir = working_dir.clone();
+        let created = tokio::task::spawn_blocking(move || {
+            noaide_server::git::worktree::create(
+                &dir,
+                new_id,
+                &noaide_server::git::worktree::worktrees_dir(),
+            )
+        })
+        .await
+        .unwrap_or_else(|e| Err(noaide_server::git::GitError::Other(e.to_string())));
+        match created {
+            Ok(worktree) => Some(worktree),
+            Err(e) => {
+                let status = match e {
+                    noaide_server::git::GitError::NotRepo(_) => axum::http::StatusCode::BAD_REQUEST,
+                    _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                return (
+                    status,
+                    axum::Json(
+                        serde_json::json!({"error": format!("failed to create worktree: {e}")}),
+                    ),
+                );
+            }
+        }
+    } else {
+        None
+    };
+    // Everything that follows the CLI (transcript linking, file browser,
+    // resume) uses the directory it actually runs in.
+    let session_dir = worktree
+        .as_ref()
+        .map_or_else(|| working_dir.clone(), |wt| wt.session_dir());
+    let session_dir_str = session_dir.to_string_lossy().into_owned();
+
+    let cli_type = body.cli_type.as_deref().unwrap_or("claude");
+    let base_url = state.proxy_base_url.as_str();
+    let auto_approve = body.auto_approve.unwrap_or(false);
+    let options = noaide_server::session::LaunchOptions {
+        session_id: Some(new_id),
+        env: body
+            .env
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+        record_to: recording_path_for(new_id),
+        size,
+        size_policy: body.size_policy,
+        sandbox: session_sandbox(profile.as_ref(), worktree.as_ref()),
+        ..Default::default()
+    };
+    // Spawned before taking the manager lock: a sandboxed spawn waits for
+    // the child's namespace setup.
+    let spawned = noaide_server::session::ManagedSession::spawn_blocking(
+        session_dir.clone(),
+        Some(base_url.to_string()),
+        cli_type.to_string(),
+        auto_approve,
+        options,
+    )
+    .await;
+    let mut mgr = state.session_manager.write().await;
+    match spawned.map(|session| mgr.register_managed(session)) {
+        Ok(session_id) => {
+            let sid = session_id.0;
+            if let Some(session) = mgr.get(&session_id) {
+                let now_ms = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or(0);
+                state.activity.start(sid, now_ms).await;
+                spawn_pty_activity(&state, session);
+            }
+            // Apply the proxy profile right away so the CLI's first requests see it
+            if let Some(ref profile) = profile {
+                let config = noaide_server::proxy::profiles::apply_profile(
+                    &state.proxy,
+                    &sid.to_string(),
+                    profile,
+                )
+                .await;
+                noaide_server::proxy::persist::schedule_save(sid.to_string(), config);
+            }
+            let now_epoch = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            // Record the launch so the session can be resumed after a restart
+            state
+                .launches
+                .record(ManagedLaunch {
+                    session_id: sid,
+                    cli_type: cli_type.to_string(),
+                    working_dir: session_dir.clone(),
+                    auto_approve,
+                    profile: profile.as_ref().map(|p| p.name.clone()),
+                    env: body.env.clone(),
+                    size_policy: body.size_policy,
+                    worktree: worktree.clone(),
+                    cli_session_id: None,
+                    transcript: None,
+                    started_at: now_epoch,
+                    resumed_at: None,
+                    exited_at: None,
+                })
+                .await;
+            // Register in ECS world so it shows up in session list
+            {
+                let mut world = state.ecs.write().await;
+                world.spawn_session(SessionComponent {
+                    id: sid,
+                    path: session_dir_str.clone(),
+                    status: SessionStatus::Active,
+                    model: None,
+                    started_at: now_epoch,
+                    last_activity_at: now_epoch,
+                    cost: None,
+                });
+            }
+            // Register path → managed session mapping so the file watcher
+            // can link JSONL sessions (created by the CLI) to this managed session.
+            // Key is the encoded form (e.g., `-tmp-test-session`) to match JSONL dir names.
+            {
+                let mut msp = state.managed_session_paths.write().await;
+                msp.insert(encode_project_dir(&session_dir_str), sid);
+            }
+            // Register cli_type immediately so the API exposes the correct
+            // badge (CLD/CDX/GEM) before the file watcher discovers the JSONL.
+            {
+                let ct = parser::registry::by_name(cli_type)
+                    .map(|s| s.cli_type())
+                    .unwrap_or_default();
+                let mut types = state.session_cli_types.write().await;
+                types.insert(sid, ct);
+            }
+            // Register project watch so the file browser works immediately
+            {
+                let project_root = session_dir.clone();
+                let mut watches = state.project_watches.write().await;
+                if !watches.values().any(|r| *r == project_root) {
+                    // Note: watcher registration happens lazily when JSONL is discovered
+                    info!(root = %project_root.display(), session = %sid, "registered project watch for managed session");
+                }
+                watches.insert(sid, project_root);
+            }
+            // Auto-detect plan: scan /work/plan/ for a plan whose IMPL-PLAN.md
+            // symlinks or matches the session working directory
+            {
+                let base = &*state.plan_base_dir;
+                if let Ok(mut entries) = tokio::fs::read_dir(base).await {
+                    while let Ok(Some(entry)) = entries.next_entry().await {
+                        if let Ok(ft) = entry.file_type().await
+                            && ft.is_dir()
+                        {
+                            let name = entry.file_name().to_string_lossy().to_string();
+                            let plan_dir = entry.path();
+                            let impl_plan = plan_dir.join("IMPL-PLAN.md");
+                            let plan_json = plan_dir.join("plan.json");
+                            // Match only the explicit plan directory, not the first unrelated
+                            // plan that happens to contain an IMPL-PLAN.md.
+                            if plan_json.exists()
+                                && plan_dir_matches_working_dir(
+                                    &name,
+                                    &plan_dir,
+                                    &impl_plan,
+                                    &working_dir,
+                                )
+                            {
+                                let mut mapping = state.session_plan_mapping.write().await;
+                                mapping.insert(sid, name.clone());
+                                info!(session = %sid, plan = %name, "auto-bound session to plan");
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            // For Codex/Gemini: also register by CLI type as fallback
+            // (their JSONL paths don't encode the project directory)
+            if cli_type != "claude" {
+                let mut pending = state.managed_pending_by_cli.write().await;
+                pending.insert(cli_type.to_string(), sid);
+            }
+            info!(
+                session = %sid,
+                working_dir = %session_dir_str,
+                branch = worktree.as_ref().map(|wt| wt.branch.as_str()),
+                "managed session created via API"
+            );
+            // Persist session→plan mapping for server restart recovery
+            {
+                let mapping = state.session_plan_mapping.read().await;
+                let msp = state.managed_session_paths.read().await;
+                let persist = build_managed_session_persist_entries(&msp, &mapping);
+                if let Err(e) = persist_managed_session_entries(&persist).await {
+                    warn!(
+                        error = %e,
+                        session = %sid,
+                        "failed to persist managed session mappings"
+                    );
+                }
+            }
+            (
+                axum::http::StatusCode::OK,
+                axum::Json(serde_json::json!({
+                    "ok": true,
+                    "sessionId": sid.to_string(),
+                    "profile": profile.map(|p| p.name),
+                    "worktree": worktree,
+                })),
+            )
+        }
+        Err(e) => {
+            drop(mgr);
+            warn!(error = %e, working_dir = %body.working_dir, "failed to create managed session");
+            if let Some(worktree) = worktree {
+                let _ = tokio::task::spawn_blocking(move || {
+                    noaide_server::git::worktree::finish(
+                        &worktree,
+                        noaide_server::git::WorktreeAction::Discard,
+                        "",
+                        None,
+                    )
+                })
+                .await;
+            }
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "error": format!("failed to spawn session: {e}")
+                })),
+            )
+        }
+    }
+}
+
+/// The profile's sandbox for a session. A session in a worktree also needs
+/// the repository's git directory writable to commit.
+fn session_sandbox(
+    profile: Option<&noaide_server::proxy::profiles::ProxyProfile>,
+    worktree: Option<&noaide_server::git::Worktree>,
+) -> Option<noaide_server::session::SandboxConfig> {
+    let mut config = profile?.sandbox.clone()?;
+    if let Some(dir) = worktree.and_then(|wt| wt.common_dir().ok()) {
+        config.writable.push(dir.to_string_lossy().into_owned());
+    }
+    Some(config)
+}
+
+/// Where a managed session launched now records its terminal; `None` when
+/// recording is off.
+fn recording_path_for(session_id: Uuid) -> Option<PathBuf> {
+    recording::enabled()
+        .then(|| recording::new_recording_path(&recording::recordings_dir(), session_id))
+}
+
+// ── Job Queue ────────────────────────────────────────────────────────────────
+
+fn epoch_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+async fn publish_job(state: &AppState, job: &noaide_server::jobs::Job) {
+    let _ = state
+        .event_bus
+        .publish(bus::JOB_STATUS, job.envelope())
+        .await;
+}
+
+/// Start queued jobs whenever a slot frees up or a retry becomes due.
+fn spawn_job_runner(state: AppState, limits: noaide_server::jobs::JobLimits) {
+    let limits = Arc::new(limits);
+    tokio::spawn(async move {
+        loop {
+            while let Some(job) = state.jobs.start_next(epoch_ms(), &limits).await {
+                publish_job(&state, &job).await;
+                tokio::spawn(run_job(state.clone(), job, limits.clone()));
+            }
+            // Retries wait for their backoff without a wake-up
+            tokio::select! {
+                _ = state.jobs.changed() => {}
+                _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {}
+            }
+        }
+    });
+}
+
+/// Run the current attempt of `job` and record its result.
+async fn run_job(
+    state: AppState,
+    mut job: noaide_server::jobs::Job,
+    limits: Arc<noaide_server::jobs::JobLimits>,
+) {
+    use noaide_server::jobs::AttemptResults;
+
+    let Some(sid) = job.session_id() else {
+        return;
+    };
+    if job.attempts.len() == 1 {
+        let dir = job.spec.working_dir.clone();
+        job.base_commit =
+            tokio::task::spawn_blocking(move || noaide_server::git::head_commit(&dir))
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .flatten();
+        state
+            .jobs
+            .set_base_commit(job.id, job.base_commit.clone())
+            .await;
+    }
+
+    let outcome = run_job_attempt(&state, &job, sid, job.timeout(&limits)).await;
+    info!(job = %job.id, session = %sid, outcome = ?outcome, "job attempt finished");
+
+    // The watcher links the transcript shortly after the CLI writes it
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    let transcript = state.session_paths.read().await.get(&sid).cloned();
+    let cost_usd = match &transcript {
+        Some(path) => noaide_server::export::load_transcript(path, sid)
+            .await
+            .ok()
+            .map(|session| {
+                noaide_server::export::build_document(&session, &Default::default())
+                    .usage
+                    .cost_usd
+            }),
+        None => None,
+    };
+    let diff_stat = {
+        let dir = job.spec.working_dir.clone();
+        let base = job.base_commit.clone();
+        let diff = tokio::task::spawn_blocking(move || {
+            noaide_server::git::diff_since(&dir, base.as_deref())
+        })
+        .await;
+        match diff {
+            Ok(Ok((patch, stat))) => {
+                let path = state.jobs.diff_path(job.id);
+                if let Err(e) = tokio::fs::write(&path, patch).await {
+                    warn!(job = %job.id, error = %e, "failed to write job diff");
+                }
+                Some(stat)
+            }
+            // Not a git repository
+            Ok(Err(_)) | Err(_) => None,
+        }
+    };
+
+    let results = AttemptResults {
+        transcript,
+        cost_usd,
+        diff_stat,
+    };
+    if let Some(job) = state
+        .jobs
+        .finish(job.id, outcome, results, epoch_ms(), &limits)
+        .await
+    {
+        publish_job(&state, &job).await;
+    }
+}
+
+/// Spawn the attempt's session and wait until it exits, times out or is
+/// cancelled.
+async fn run_job_attempt(
+    state: &AppState,
+    job: &noaide_server::jobs::Job,
+    sid: Uuid,
+    timeout: std::time::Duration,
+) -> noaide_server::jobs::Outcome {
+    use noaide_server::jobs::Outcome;
+    use noaide_server::session::{SessionEvent, SessionId, SessionState};
+
+    let spec = &job.spec;
+    let Some(args) = spec.headless_args() else {
+        return Outcome::Failed(format!("{} has no non-interactive mode", spec.cli_type));
+    };
+    let profile = match spec.profile.as_deref() {
+        Some(name) => state.proxy.profiles.get(name),
+        None => state
+            .proxy
+            .profiles
+            .match_project(&spec.working_dir.to_string_lossy()),
+    };
+    if let Some(ref profile) = profile {
+        let config =
+            noaide_server::proxy::profiles::apply_profile(&state.proxy, &sid.to_string(), profile)
+                .await;
+        noaide_server::proxy::persist::schedule_save(sid.to_string(), config);
+    }
+
+    let mut cancels = state.jobs.cancellations();
+    let session_id = SessionId(sid);
+    let mut events = {
+        let options = noaide_server::session::LaunchOptions {
+            session_id: Some(sid),
+            extra_args: args,
+            env: spec
+                .env
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            record_to: recording_path_for(sid),
+            sandbox: profile.as_ref().and_then(|p| p.sandbox.clone()),
+            ..Default::default()
+        };
+        let spawned = noaide_server::session::ManagedSession::spawn_blocking(
+            spec.working_dir.clone(),
+            Some(state.proxy_base_url.to_string()),
+            spec.cli_type.clone(),
+            spec.auto_approve,
+            options,
+        )
+        .await;
+        let mut mgr = state.session_manager.write().await;
+        if let Err(e) = spawned.map(|session| mgr.register_managed(session)) {
+            return Outcome::Failed(format!("failed to spawn session: {e}"));
+        }
+        let Some(session) = mgr.get(&session_id) else {
+            return Outcome::Failed("session vanished after spawn".to_string());
+        };
+        state.activity.start(sid, epoch_ms()).await;
+        spawn_pty_activity(state, session);
+        let events = session.events();
+        if session.state() == SessionState::Closed {
+            return Outcome::Failed("session closed right after spawn".to_string());
+        }
+        events
+    };
+    register_managed_session(
+        state,
+        sid,
+        &spec.cli_type,
+        &spec.working_dir,
+        job.submitted_at / 1000,
+    )
+    .await;
+    info!(job = %job.id, session = %sid, cli = %spec.cli_type, "job attempt started");
+
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    let stop = loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(SessionEvent::Exited(status)) => break Outcome::Exited(status),
+                Ok(SessionEvent::Closed)
+                | Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                    break Outcome::Failed("session closed without an exit status".to_string());
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+            },
+            () = &mut deadline => break Outcome::TimedOut,
+            cancelled = cancels.recv() => {
+                if matches!(cancelled, Ok(id) if id == job.id) {
+                    break Outcome::Cancelled;
+                }
+            }
+        }
+    };
+
+    // Stop the CLI's whole process group and wait for it, so nothing still
+    // writes to the checkout while the diff is taken
+    if let Some(session) = state.session_manager.read().await.get(&session_id)
+        && let Err(e) = session.close().await
+    {
+        warn!(job = %job.id, session = %sid, error = %e, "failed to stop job session");
+        return Outcome::Failed(format!("failed to stop the CLI: {e}"));
+    }
+    stop
+}
+
+/// GET /api/jobs query.
+#[derive(serde::Deserialize)]
+struct JobListQuery {
+    /// Comma-separated statuses to keep (e.g. `queued,running`).
+    status: Option<String>,
+}
+
+fn job_not_found() -> axum::response::Response {
+    use axum::response::IntoResponse;
+    (
+        StatusCode::NOT_FOUND,
+        axum::Json(serde_json::json!({"error": "job not found"})),
+    )
+        .into_response()
+}
+
+fn invalid_job_id() -> axum::response::Response {
+    use axum::response::IntoResponse;
+    (
+        StatusCode::BAD_REQUEST,
+        axum::Json(serde_json::json!({"error": "invalid job id"})),
+    )
+        .into_response()
+}
+
+/// POST /api/jobs — Queue a prompt to run headless.
+async fn api_submit_job(
+    State(state): State<AppState>,
+    axum::Json(mut spec): axum::Json<noaide_server::jobs::JobSpec>,
+) -> impl axum::response::IntoResponse {
+    if let Err(e) = spec.prepare() {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": e})),
+        );
+    }
+    if let Some(ref name) = spec.profile
+        && state.proxy.profiles.get(name).is_none()
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": format!("unknown profile: {name}")})),
+        );
+    }
+    let job = state.jobs.submit(spec, epoch_ms()).await;
+    info!(job = %job.id, working_dir = %job.spec.working_dir.display(), "job queued");
+    publish_job(&state, &job).await;
+    (
+        StatusCode::OK,
+        axum::Json(serde_json::json!({"ok": true, "job": job})),
+    )
+}
+
+/// GET /api/jobs — All jobs, oldest first, optionally filtered by status.
+async fn api_list_jobs(
+    State(state): State<AppState>,
+    Query(query): Query<JobListQuery>,
+) -> impl axum::response::IntoResponse {
+    let wanted: Option<Vec<noaide_server::jobs::JobStatus>> = query.status.map(|s| {
+        s.split(',')
+            .filter_map(|s| noaide_server::jobs::JobStatus::parse(s.trim()))
+            .collect()
+    });
+    let jobs: Vec<_> = state
+        .jobs
+        .list()
+        .await
+        .into_iter()
+        .filter(|j| wanted.as_ref().is_none_or(|w| w.contains(&j.status)))
+        .collect();
+    axum::Json(serde_json::json!({"jobs": jobs}))
+}
+
+/// GET /api/jobs/{id}
+async fn api_get_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return invalid_job_id();
+    };
+    match state.jobs.get(id).await {
+        Some(job) => axum::Json(job).into_response(),
+        None => job_not_found(),
+    }
+}
+
+/// GET /api/jobs/{id}/diff — Changes in the working directory since the
+/// job's first attempt started, as a unified diff.
+async fn api_get_job_diff(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return invalid_job_id();
+    };
+    if state.jobs.get(id).await.is_none() {
+        return job_not_found();
+    }
+    match tokio::fs::read(state.jobs.diff_path(id)).await {
+        Ok(patch) => ([(axum::http::header::CONTENT_TYPE, "text/x-diff")], patch).into_response(),
+        Err(_) => (
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "no diff recorded for this job"})),
+        )
+            .into_response(),
+    }
+}
+
+/// POST /api/jobs/{id}/cancel — Drop a queued job or stop a running one.
+async fn api_cancel_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return invalid_job_id();
+    };
+    match state.jobs.cancel(id, epoch_ms()).await {
+        Some(job) => {
+            if job.status == noaide_server::jobs::JobStatus::Cancelled {
+                publish_job(&state, &job).await;
+            }
+            axum::Json(serde_json::json!({"ok": true, "job": job})).into_response()
+        }
+        None => job_not_found(),
+    }
+}
+
+/// DELETE /api/jobs/{id} — Forget a finished job and its diff.
+async fn api_delete_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return invalid_job_id();
+    };
+    match state.jobs.get(id).await {
+        None => job_not_found(),
+        Some(job) if !job.status.is_finished() => (
+            StatusCode::CONFLICT,
+            axum::Json(serde_json::json!({"error": "job is still queued or running"})),
+        )
+            .into_response(),
+        Some(_) => {
+            state.jobs.remove(id).await;
+            axum::Json(serde_json::json!({"ok": true})).into_response()
+        }
+    }
 }
 
-/// Spawn a new managed CLI session (claude, codex, or gemini) via PTY.
-///
-/// The session process gets per-session proxy URLs (`ANTHROPIC_BASE_URL`,
-/// `OPENAI_BASE_URL`, `CODE_ASSIST_ENDPOINT`) so all API traffic is
-/// routed through the noaide proxy and attributed to the session.
-async fn api_create_managed_session(
+// ── PTY Recording Handlers ───────────────────────────────────────────────────
+
+/// GET /api/sessions/{id}/recordings — Terminal recordings of a managed
+/// session, one per launch, oldest first.
+async fn api_list_recordings(
     State(state): State<AppState>,
-    axum::Json(body): axum::Json<CreateManagedSessionRequest>,
+    Path(id): Path<String>,
 ) -> impl axum::response::IntoResponse {
-    let working_dir = PathBuf::from(&body.working_dir);
-    if !working_dir.is_dir() {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
         return (
             axum::http::StatusCode::BAD_REQUEST,
-            axum::Json(
-                serde_json::json!({"error": "working_dir does not exist or is not a directory"}),
-            ),
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
         );
+    };
+    let session_id = state.ecs.read().await.resolve_alias(uuid);
+    let recordings = tokio::task::spawn_blocking(move || {
+        recording::list(&recording::recordings_dir(), session_id)
+    })
+    .await
+    .unwrap_or_default();
+    (
+        axum::http::StatusCode::OK,
+        axum::Json(serde_json::json!({
+            "sessionId": session_id.to_string(),
+            "recordings": recordings,
+        })),
+    )
+}
+
+#[derive(serde::Deserialize, Default)]
+struct RecordingQuery {
+    /// Seek point in seconds; earlier output is replayed at once.
+    from: Option<f64>,
+    /// End of the clip in seconds.
+    to: Option<f64>,
+    /// Redact secrets in output and input (default `true`).
+    redact: Option<bool>,
+}
+
+/// GET /api/sessions/{id}/recordings/{name} — Stream one recording as
+/// asciicast v2, optionally clipped to `from`..`to`.
+async fn api_get_recording(
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+    axum::extract::Query(query): axum::extract::Query<RecordingQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    use futures_util::StreamExt;
+    use tokio::io::AsyncBufReadExt;
+
+    let error = |status: axum::http::StatusCode, error: &str| {
+        (status, axum::Json(serde_json::json!({ "error": error }))).into_response()
+    };
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return error(axum::http::StatusCode::BAD_REQUEST, "invalid session id");
+    };
+    let from = query.from.unwrap_or(0.0);
+    if !from.is_finite() || from < 0.0 || query.to.is_some_and(|to| !to.is_finite() || to < from) {
+        return error(axum::http::StatusCode::BAD_REQUEST, "invalid from/to");
     }
+    let session_id = state.ecs.read().await.resolve_alias(uuid);
+    let Some(path) = recording::recording_file(&recording::recordings_dir(), session_id, &name)
+    else {
+        return error(
+            axum::http::StatusCode::BAD_REQUEST,
+            "invalid recording name",
+        );
+    };
+    let Ok(file) = tokio::fs::File::open(&path).await else {
+        return error(axum::http::StatusCode::NOT_FOUND, "recording not found");
+    };
 
-    let cli_type = body.cli_type.as_deref().unwrap_or("claude");
-    let base_url = state.proxy_base_url.as_str();
-    let mut mgr = state.session_manager.write().await;
-    let auto_approve = body.auto_approve.unwrap_or(false);
-    match mgr.spawn_managed(&working_dir, Some(base_url), cli_type, auto_approve) {
-        Ok(session_id) => {
-            let sid = session_id.0;
-            // Register in ECS world so it shows up in session list
-            {
-                let mut world = state.ecs.write().await;
-                let now_epoch = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|d| d.as_secs() as i64)
-                    .unwrap_or(0);
-                world.spawn_session(SessionComponent {
-                    id: sid,
-                    path: body.working_dir.clone(),
-                    status: SessionStatus::Active,
-                    model: None,
-                    started_at: now_epoch,
-                    last_activity_at: now_epoch,
-                    cost: None,
-                });
+    let clipper = recording::Clipper::new(
+        recording::Clip { from, to: query.to },
+        query.redact.unwrap_or(true),
+    );
+    let lines = tokio::io::BufReader::new(file).lines();
+    let body = futures_util::stream::unfold(
+        (lines, clipper, false),
+        |(mut lines, mut clipper, finished)| async move {
+            if finished {
+                return None;
             }
-            // Register path → managed session mapping so the file watcher
-            // can link JSONL sessions (created by the CLI) to this managed session.
-            // Key is the encoded form (e.g., `-tmp-test-session`) to match JSONL dir names.
-            {
-                let mut msp = state.managed_session_paths.write().await;
-                msp.insert(encode_project_dir(&body.working_dir), sid);
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        let chunk = clipper.push(&line);
+                        if clipper.is_done() {
+                            return Some((Ok(chunk), (lines, clipper, true)));
+                        }
+                        if !chunk.is_empty() {
+                            return Some((Ok(chunk), (lines, clipper, false)));
+                        }
+                    }
+                    Ok(None) => {
+                        let chunk = clipper.finish();
+                        return Some((Ok(chunk), (lines, clipper, true)));
+                    }
+                    Err(e) => return Some((Err(e), (lines, clipper, true))),
+                }
             }
-            // Register cli_type immediately so the API exposes the correct
-            // badge (CLD/CDX/GEM) before the file watcher discovers the JSONL.
-            {
-                use noaide_server::discovery::scanner::CliType;
-                let ct = match cli_type {
-                    "codex" => CliType::Codex,
-                    "gemini" => CliType::Gemini,
-                    _ => CliType::Claude,
+        },
+    )
+    .map(|chunk: std::io::Result<String>| chunk.map(bytes::Bytes::from));
+
+    axum::response::Response::builder()
+        .header("content-type", recording::CONTENT_TYPE)
+        .header(
+            "content-disposition",
+            format!("inline; filename=\"session-{session_id}-{name}\""),
+        )
+        .body(axum::body::Body::from_stream(body))
+        .unwrap()
+}
+
+// ── Terminal Handlers ────────────────────────────────────────────────────────
+
+/// The terminal of a managed session, looked up by session or alias id.
+async fn session_terminal(
+    state: &AppState,
+    uuid: Uuid,
+) -> Result<(Uuid, Arc<noaide_server::session::Terminal>), (axum::http::StatusCode, &'static str)> {
+    let uuid = state.ecs.read().await.resolve_alias(uuid);
+    let mgr = state.session_manager.read().await;
+    let Some(session) = mgr.get(&noaide_server::session::SessionId(uuid)) else {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            "managed session not found",
+        ));
+    };
+    match session.terminal() {
+        Some(terminal) => Ok((uuid, terminal)),
+        None => Err((axum::http::StatusCode::CONFLICT, "session has no terminal")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ResizeRequest {
+    cols: u16,
+    rows: u16,
+}
+
+/// POST /api/sessions/{id}/resize — Set the PTY size of a managed session.
+/// Attached viewers take over again on their next join, leave or resize.
+async fn api_resize_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::Json(body): axum::Json<ResizeRequest>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+    let terminal = match session_terminal(&state, uuid).await {
+        Ok((_, terminal)) => terminal,
+        Err((status, error)) => return (status, axum::Json(serde_json::json!({"error": error}))),
+    };
+    match terminal.resize(body.cols, body.rows) {
+        Ok(changed) => (
+            axum::http::StatusCode::OK,
+            axum::Json(serde_json::json!({
+                "ok": true,
+                "changed": changed,
+                "cols": body.cols,
+                "rows": body.rows,
+            })),
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": e.to_string()})),
+        ),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({"error": format!("resize failed: {e}")})),
+        ),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TerminalQuery {
+    cols: Option<u16>,
+    rows: Option<u16>,
+}
+
+/// Control messages a terminal viewer sends as WebSocket text frames.
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TerminalControl {
+    Resize { cols: u16, rows: u16 },
+    Input { data: String },
+}
+
+/// GET /api/sessions/{id}/terminal — Attach to a managed session's PTY over
+/// WebSocket.
+///
+/// The viewer first gets a `size` text frame and the current screen as a
+/// binary frame, then the live output as binary frames. Binary frames from
+/// the viewer are keystrokes; text frames are `{"type":"resize",...}` or
+/// `{"type":"input","data":...}`.
+async fn api_session_terminal(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TerminalQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        )
+            .into_response();
+    };
+    let (session_id, terminal) = match session_terminal(&state, uuid).await {
+        Ok(found) => found,
+        Err((status, error)) => {
+            return (status, axum::Json(serde_json::json!({"error": error}))).into_response();
+        }
+    };
+    let (default_cols, default_rows) = terminal.size();
+    let cols = query.cols.filter(|c| *c > 0).unwrap_or(default_cols);
+    let rows = query.rows.filter(|r| *r > 0).unwrap_or(default_rows);
+    if let Err(e) = noaide_server::session::check_size(cols, rows) {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+    ws.on_upgrade(move |socket| {
+        terminal_viewer(socket, state, session_id, terminal.attach(cols, rows))
+    })
+}
+
+fn terminal_size_frame(viewer: &noaide_server::session::Viewer) -> WsMessage {
+    let (cols, rows) = viewer.terminal().size();
+    WsMessage::Text(
+        serde_json::json!({
+            "type": "size",
+            "cols": cols,
+            "rows": rows,
+            "policy": viewer.terminal().policy(),
+            "owner": viewer.is_owner(),
+            "viewers": viewer.terminal().viewer_count(),
+        })
+        .to_string()
+        .into(),
+    )
+}
+
+async fn terminal_viewer(
+    socket: WebSocket,
+    state: AppState,
+    session_id: Uuid,
+    mut viewer: noaide_server::session::Viewer,
+) {
+    use futures_util::{SinkExt, StreamExt as FutStreamExt};
+    use noaide_server::session::TerminalEvent;
+
+    let (mut tx, mut rx) = socket.split();
+    let screen = bytes::Bytes::copy_from_slice(viewer.screen());
+    if tx.send(terminal_size_frame(&viewer)).await.is_err()
+        || tx.send(WsMessage::Binary(screen)).await.is_err()
+    {
+        return;
+    }
+    loop {
+        tokio::select! {
+            event = viewer.recv() => {
+                let frame = match event {
+                    Some(TerminalEvent::Output(bytes)) => WsMessage::Binary(bytes),
+                    Some(TerminalEvent::Resize { .. }) => terminal_size_frame(&viewer),
+                    Some(TerminalEvent::Closed) | None => {
+                        let _ = tx
+                            .send(WsMessage::Text(r#"{"type":"closed"}"#.into()))
+                            .await;
+                        let _ = tx.send(WsMessage::Close(None)).await;
+                        break;
+                    }
                 };
-                let mut types = state.session_cli_types.write().await;
-                types.insert(sid, ct);
-            }
-            // Register project watch so the file browser works immediately
-            {
-                let project_root = PathBuf::from(&body.working_dir);
-                let mut watches = state.project_watches.write().await;
-                if !watches.values().any(|r| *r == project_root) {
-                    // Note: watcher registration happens lazily when JSONL is discovered
-                    info!(root = %project_root.display(), session = %sid, "registered project watch for managed session");
+                if tx.send(frame).await.is_err() {
+                    break;
                 }
-                watches.insert(sid, project_root);
             }
-            // Auto-detect plan: scan /work/plan/ for a plan whose IMPL-PLAN.md
-            // symlinks or matches the session working directory
-            {
-                let base = &*state.plan_base_dir;
-                if let Ok(mut entries) = tokio::fs::read_dir(base).await {
-                    while let Ok(Some(entry)) = entries.next_entry().await {
-                        if let Ok(ft) = entry.file_type().await
-                            && ft.is_dir()
-                        {
-                            let name = entry.file_name().to_string_lossy().to_string();
-                            let plan_dir = entry.path();
-                            let impl_plan = plan_dir.join("IMPL-PLAN.md");
-                            let plan_json = plan_dir.join("plan.json");
-                            // Match only the explicit plan directory, not the first unrelated
-                            // plan that happens to contain an IMPL-PLAN.md.
-                            if plan_json.exists()
-                                && plan_dir_matches_working_dir(
-                                    &name,
-                                    &plan_dir,
-                                    &impl_plan,
-                                    &working_dir,
-                                )
-                            {
-                                let mut mapping = state.session_plan_mapping.write().await;
-                                mapping.insert(sid, name.clone());
-                                info!(session = %sid, plan = %name, "auto-bound session to plan");
-                                break;
+            msg = FutStreamExt::next(&mut rx) => {
+                let input = match msg {
+                    Some(Ok(WsMessage::Binary(data))) => String::from_utf8_lossy(&data).into_owned(),
+                    Some(Ok(WsMessage::Text(text))) => {
+                        match serde_json::from_str::<TerminalControl>(text.as_str()) {
+                            Ok(TerminalControl::Resize { cols, rows }) => {
+                                // Out-of-range sizes are ignored
+                                if noaide_server::session::check_size(cols, rows).is_ok() {
+                                    viewer.resize(cols, rows);
+                                }
+                                continue;
                             }
+                            Ok(TerminalControl::Input { data }) => data,
+                            Err(_) => continue,
                         }
                     }
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let sent = {
+                    let mgr = state.session_manager.read().await;
+                    match mgr.get(&noaide_server::session::SessionId(session_id)) {
+                        Some(session) => session.send_input(&input).await.is_ok(),
+                        None => false,
+                    }
+                };
+                if sent {
+                    observe_activity(
+                        &state.activity,
+                        &state.event_bus,
+                        &state.ecs,
+                        session_id,
+                        &Observation::Input,
+                        EventSource::User,
+                    )
+                    .await;
                 }
             }
-            // For Codex/Gemini: also register by CLI type as fallback
-            // (their JSONL paths don't encode the project directory)
-            if cli_type != "claude" {
-                let mut pending = state.managed_pending_by_cli.write().await;
-                pending.insert(cli_type.to_string(), sid);
-            }
-            info!(session = %sid, working_dir = %body.working_dir, "managed session created via API");
-            // Persist session→plan mapping for server restart recovery
-            {
-                let mapping = state.session_plan_mapping.read().await;
-                let msp = state.managed_session_paths.read().await;
-                let persist = build_managed_session_persist_entries(&msp, &mapping);
-                if let Err(e) = persist_managed_session_entries(&persist).await {
-                    warn!(
-                        error = %e,
-                        session = %sid,
-                        "failed to persist managed session mappings"
-                    );
-                }
-            }
-            (
-                axum::http::StatusCode::OK,
-                axum::Json(serde_json::json!({
-                    "ok": true,
-                    "sessionId": sid.to_string(),
-                })),
-            )
         }
-        Err(e) => {
-            warn!(error = %e, working_dir = %body.working_dir, "failed to create managed session");
-            (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                axum::Json(serde_json::json!({
-                    "error": format!("failed to spawn session: {e}")
-                })),
-            )
+    }
+}
+
+// ── Resume Managed Session Handlers ──────────────────────────────────────────
+
+/// Respawn a recorded managed session under its old id, so proxy attribution
+/// (`/s/{id}`), the ECS entry and its transcript alias carry on. Continues
+/// the CLI's conversation when its transcript is known; returns whether it
+/// does.
+async fn resume_managed(
+    state: &AppState,
+    launch: ManagedLaunch,
+) -> Result<bool, (axum::http::StatusCode, String)> {
+    if !launch.working_dir.is_dir() {
+        return Err((
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            "working_dir no longer exists".to_string(),
+        ));
+    }
+    let sid = launch.session_id;
+    let session_id = noaide_server::session::SessionId(sid);
+    let resume_args = {
+        let launch = launch.clone();
+        tokio::task::spawn_blocking(move || launch.resume_args())
+            .await
+            .ok()
+            .flatten()
+    };
+    let continued = resume_args.is_some();
+
+    let mut mgr = state.session_manager.write().await;
+    if mgr
+        .get(&session_id)
+        .is_some_and(|s| s.state() != noaide_server::session::SessionState::Closed)
+    {
+        return Err((
+            axum::http::StatusCode::CONFLICT,
+            "session is still running".to_string(),
+        ));
+    }
+    let options = noaide_server::session::LaunchOptions {
+        record_to: recording_path_for(sid),
+        sandbox: session_sandbox(
+            launch
+                .profile
+                .as_deref()
+                .and_then(|name| state.proxy.profiles.get(name))
+                .as_ref(),
+            launch.worktree.as_ref(),
+        ),
+        ..launch.launch_options(resume_args)
+    };
+    // The lock stays held so the running check above and the spawn are one
+    // step; the spawn itself runs on the blocking pool.
+    noaide_server::session::ManagedSession::spawn_blocking(
+        launch.working_dir.clone(),
+        Some(state.proxy_base_url.to_string()),
+        launch.cli_type.clone(),
+        launch.auto_approve,
+        options,
+    )
+    .await
+    .map(|session| mgr.register_managed(session))
+    .map_err(|e| {
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to spawn session: {e}"),
+        )
+    })?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    state.activity.start(sid, now.as_millis() as i64).await;
+    if let Some(session) = mgr.get(&session_id) {
+        spawn_pty_activity(state, session);
+    }
+    drop(mgr);
+
+    // The persisted proxy config normally outlives the process; re-derive it
+    // from the profile if retention already removed it.
+    if let Some(ref name) = launch.profile
+        && noaide_server::proxy::persist::load_config(&sid.to_string()).is_none()
+        && let Some(profile) = state.proxy.profiles.get(name)
+    {
+        let config =
+            noaide_server::proxy::profiles::apply_profile(&state.proxy, &sid.to_string(), &profile)
+                .await;
+        noaide_server::proxy::persist::schedule_save(sid.to_string(), config);
+    }
+    // A CLI that writes a new transcript instead of appending to the old one
+    // gets linked like on a fresh launch.
+    register_managed_session(
+        state,
+        sid,
+        &launch.cli_type,
+        &launch.working_dir,
+        launch.started_at,
+    )
+    .await;
+    state.launches.mark_resumed(sid, now.as_secs() as i64).await;
+    info!(session = %sid, cli = %launch.cli_type, continued, "managed session resumed");
+    Ok(continued)
+}
+
+/// Make a freshly spawned managed session visible: ECS entry, transcript
+/// linking by project directory (or CLI type), CLI badge and project watch.
+async fn register_managed_session(
+    state: &AppState,
+    sid: Uuid,
+    cli_type: &str,
+    working_dir: &FsPath,
+    started_at: i64,
+) {
+    let now_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let working_dir_str = working_dir.to_string_lossy().into_owned();
+    {
+        let mut world = state.ecs.write().await;
+        let updated = world.modify_session(sid, |s| {
+            s.status = SessionStatus::Active;
+            s.last_activity_at = now_epoch;
+        });
+        if updated.is_none() {
+            world.spawn_session(SessionComponent {
+                id: sid,
+                path: working_dir_str.clone(),
+                status: SessionStatus::Active,
+                model: None,
+                started_at,
+                last_activity_at: now_epoch,
+                cost: None,
+            });
         }
     }
+    state
+        .managed_session_paths
+        .write()
+        .await
+        .insert(encode_project_dir(&working_dir_str), sid);
+    if cli_type != "claude" {
+        state
+            .managed_pending_by_cli
+            .write()
+            .await
+            .insert(cli_type.to_string(), sid);
+    }
+    if let Some(source) = parser::registry::by_name(cli_type) {
+        state
+            .session_cli_types
+            .write()
+            .await
+            .insert(sid, source.cli_type());
+    }
+    state
+        .project_watches
+        .write()
+        .await
+        .insert(sid, working_dir.to_path_buf());
+}
+
+/// GET /api/sessions/resumable — Recorded managed sessions that are not
+/// running, e.g. after a server restart.
+async fn api_list_resumable_sessions(
+    State(state): State<AppState>,
+) -> impl axum::response::IntoResponse {
+    let launches = state.launches.list().await;
+    let mgr = state.session_manager.read().await;
+    let sessions: Vec<serde_json::Value> = launches
+        .into_iter()
+        .filter(|l| {
+            mgr.get(&noaide_server::session::SessionId(l.session_id))
+                .is_none_or(|s| s.state() == noaide_server::session::SessionState::Closed)
+        })
+        .map(|l| {
+            serde_json::json!({
+                "sessionId": l.session_id.to_string(),
+                "cliType": l.cli_type,
+                "workingDir": l.working_dir,
+                "autoApprove": l.auto_approve,
+                "profile": l.profile,
+                "envKeys": l.env.keys().collect::<Vec<_>>(),
+                "cliSessionId": l.cli_session_id.map(|id| id.to_string()),
+                "transcript": l.transcript,
+                "canContinue": l.transcript.is_some(),
+                "startedAt": l.started_at,
+                "resumedAt": l.resumed_at,
+                "worktree": l.worktree,
+            })
+        })
+        .collect();
+    axum::Json(serde_json::json!({ "sessions": sessions }))
+}
+
+/// POST /api/sessions/{id}/resume — Respawn a managed session that died with
+/// the server (or exited) under the same session id.
+async fn api_resume_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+    let session_id = state.ecs.read().await.resolve_alias(uuid);
+    let Some(launch) = state.launches.get(session_id).await else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "no managed launch recorded for this session"})),
+        );
+    };
+    match resume_managed(&state, launch).await {
+        Ok(continued) => (
+            axum::http::StatusCode::OK,
+            axum::Json(serde_json::json!({
+                "ok": true,
+                "sessionId": session_id.to_string(),
+                "continued": continued,
+            })),
+        ),
+        Err((status, error)) => (status, axum::Json(serde_json::json!({ "error": error }))),
+    }
 }
 
 // ── Send Input to Managed Session Handler ────────────────────────────────────
@@ -3046,10 +5740,21 @@ async fn api_send_input(
     let session_id = noaide_server::session::SessionId(uuid);
     match mgr.get(&session_id) {
         Some(session) => match session.send_input(&body.text).await {
-            Ok(()) => (
-                axum::http::StatusCode::OK,
-                axum::Json(serde_json::json!({"ok": true})),
-            ),
+            Ok(()) => {
+                observe_activity(
+                    &state.activity,
+                    &state.event_bus,
+                    &state.ecs,
+                    uuid,
+                    &Observation::Input,
+                    EventSource::User,
+                )
+                .await;
+                (
+                    axum::http::StatusCode::OK,
+                    axum::Json(serde_json::json!({"ok": true})),
+                )
+            }
             Err(e) => (
                 axum::http::StatusCode::INTERNAL_SERVER_ERROR,
                 axum::Json(serde_json::json!({"error": format!("send_input failed: {e}")})),
@@ -3065,6 +5770,10 @@ async fn api_send_input(
 // ── Close Managed Session Handler ────────────────────────────────────────────
 
 /// Close a managed session (sends Ctrl-C + Ctrl-D to the PTY).
+///
+/// A session running in its own worktree stays recorded, so it can still be
+/// resumed there; the response offers the ways to wrap the worktree up via
+/// `POST /api/sessions/{id}/worktree`.
 async fn api_close_session(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -3081,11 +5790,20 @@ async fn api_close_session(
     match mgr.get(&session_id) {
         Some(session) => match session.close().await {
             Ok(()) => {
+                let mut response = serde_json::json!({"ok": true});
+                match state.launches.get(uuid).await.and_then(|l| l.worktree) {
+                    Some(worktree) => {
+                        response["worktree"] = serde_json::json!(worktree);
+                        response["worktreeActions"] =
+                            serde_json::json!(noaide_server::git::WorktreeAction::ALL);
+                    }
+                    // Closed on purpose: no longer offered for resume
+                    None => {
+                        state.launches.remove(uuid).await;
+                    }
+                }
                 info!(session = %uuid, "managed session closed via API");
-                (
-                    axum::http::StatusCode::OK,
-                    axum::Json(serde_json::json!({"ok": true})),
-                )
+                (axum::http::StatusCode::OK, axum::Json(response))
             }
             Err(e) => (
                 axum::http::StatusCode::INTERNAL_SERVER_ERROR,
@@ -3099,7 +5817,91 @@ async fn api_close_session(
     }
 }
 
-/// DELETE /api/sessions/{id} — Delete a session and its JSONL files.
+#[derive(serde::Deserialize)]
+struct FinishWorktreeRequest {
+    action: noaide_server::git::WorktreeAction,
+    /// Commit message for changes left uncommitted, and the pull request
+    /// title. Defaults to "noaide session <id>".
+    message: Option<String>,
+    /// Pull request body.
+    body: Option<String>,
+}
+
+/// POST /api/sessions/{id}/worktree — Wrap up a session's worktree: `merge`
+/// its branch into the base branch, open a `pr`, `keep` the branch or
+/// `discard` it. Closes the session first if it is still running. A merge
+/// that conflicts is aborted (409) and the worktree stays.
+async fn api_finish_worktree(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::Json(body): axum::Json<FinishWorktreeRequest>,
+) -> impl axum::response::IntoResponse {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({"error": "invalid session id"})),
+        );
+    };
+    let Some(worktree) = state.launches.get(uuid).await.and_then(|l| l.worktree) else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "session has no worktree"})),
+        );
+    };
+
+    // Returns once the CLI's process group is gone (a no-op if it already
+    // exited), so nothing writes to the worktree while it is committed
+    {
+        let mgr = state.session_manager.read().await;
+        if let Some(session) = mgr.get(&noaide_server::session::SessionId(uuid))
+            && let Err(e) = session.close().await
+        {
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({"error": format!("close failed: {e}")})),
+            );
+        }
+    }
+
+    let action = body.action;
+    let message = body
+        .message
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or_else(|| format!("noaide session {uuid}"));
+    let branch = worktree.branch.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        noaide_server::git::worktree::finish(&worktree, action, &message, body.body.as_deref())
+    })
+    .await
+    .unwrap_or_else(|e| Err(noaide_server::git::GitError::Other(e.to_string())));
+    match result {
+        Ok(finished) => {
+            state.launches.remove(uuid).await;
+            state.project_watches.write().await.remove(&uuid);
+            info!(session = %uuid, branch = %branch, ?action, "session worktree finished");
+            let mut response = serde_json::json!(finished);
+            response["ok"] = serde_json::json!(true);
+            response["action"] = serde_json::json!(action);
+            response["branch"] = serde_json::json!(branch);
+            (axum::http::StatusCode::OK, axum::Json(response))
+        }
+        Err(e) => {
+            warn!(error = %e, session = %uuid, ?action, "failed to finish session worktree");
+            let status = match e {
+                noaide_server::git::GitError::Conflict(_) => axum::http::StatusCode::CONFLICT,
+                _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (
+                status,
+                axum::Json(serde_json::json!({"error": e.to_string()})),
+            )
+        }
+    }
+}
+
+/// DELETE /api/sessions/{id} — Delete a session, its JSONL files and its
+/// terminal recordings.
+/// Refused with `409` while the session still has a worktree.
 async fn api_delete_session(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -3111,10 +5913,36 @@ async fn api_delete_session(
         );
     };
 
+    // A worktree would be orphaned (checkout and `noaide/<id>` branch):
+    // it has to be merged, kept or discarded first
+    if let Some(worktree) = state.launches.get(uuid).await.and_then(|l| l.worktree) {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            axum::Json(serde_json::json!({
+                "error": "session has a worktree; finish it via POST /api/sessions/{id}/worktree first",
+                "worktree": worktree,
+                "worktreeActions": noaide_server::git::WorktreeAction::ALL,
+            })),
+        );
+    }
+
     // Remove from ECS world
-    {
+    let resolved = {
         let mut world = state.ecs.write().await;
+        let resolved = world.resolve_alias(uuid);
+        state.activity.remove(resolved).await;
+        state.activity.remove(uuid).await;
         world.despawn_session(uuid);
+        resolved
+    };
+    state.launches.remove(uuid).await;
+
+    // Terminal recordings are kept under the managed session's id
+    let recordings = recording::recordings_dir();
+    for id in [uuid, resolved] {
+        if let Err(e) = recording::remove_session(&recordings, id) {
+            tracing::warn!(error = %e, session = %id, "failed to delete recordings");
+        }
     }
 
     // Delete JSONL file(s)
@@ -3345,6 +6173,7 @@ fn component_to_proxy_log(c: &ApiRequestComponent) -> noaide_server::proxy::ApiR
         request_size: c.request_size.unwrap_or(0) as usize,
         response_size: c.response_size.unwrap_or(0) as usize,
         category: c.traffic_category.clone(),
+        preflight: None,
     }
 }
 
@@ -3357,38 +6186,70 @@ struct ProxyRequestsQuery {
 
 /// List captured proxy requests (summary without bodies).
 /// Optional `?session_id=...` query parameter filters by session.
+///
+/// The in-memory cache only holds the most recent requests, so a session
+/// listing also reads the session's older requests from the DB.
 async fn api_get_proxy_requests(
     State(state): State<AppState>,
     axum::extract::Query(query): axum::extract::Query<ProxyRequestsQuery>,
 ) -> axum::Json<serde_json::Value> {
-    let cap = state.proxy.captured.read().await;
-    let items: Vec<serde_json::Value> = cap
+    let stored = match query.session_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(sid)) => state
+            .db
+            .get_api_requests_by_session(&sid)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(session = %sid, error = %e, "failed to read proxy requests from DB");
+                Vec::new()
+            }),
+        _ => Vec::new(),
+    };
+    let stored_ids: std::collections::HashSet<String> =
+        stored.iter().map(|c| c.id.to_string()).collect();
+
+    let mut items: Vec<(i64, serde_json::Value)> = stored
         .iter()
-        .filter(|r| match &query.session_id {
-            Some(sid) => r.session_id.as_deref() == Some(sid.as_str()),
-            None => true,
-        })
-        .map(|r| {
-            // Extract useful preview from request body (model + first message)
-            let req_preview = extract_request_preview(&r.request_body);
-            let res_preview = truncate_preview(&r.response_body, 120);
-            serde_json::json!({
-                "id": r.id,
-                "sessionId": r.session_id,
-                "method": r.method,
-                "url": r.url,
-                "statusCode": r.status_code,
-                "latencyMs": r.latency_ms,
-                "requestSize": r.request_size,
-                "responseSize": r.response_size,
-                "timestamp": r.timestamp,
-                "requestPreview": req_preview,
-                "responsePreview": res_preview,
-                "category": r.category,
-            })
+        .map(|c| {
+            let r = component_to_proxy_log(c);
+            (r.timestamp, proxy_request_summary(&r))
         })
         .collect();
-    axum::Json(serde_json::json!(items))
+    let cap = state.proxy.captured.read().await;
+    items.extend(
+        cap.iter()
+            .filter(|r| match &query.session_id {
+                Some(sid) => r.session_id.as_deref() == Some(sid.as_str()),
+                None => true,
+            })
+            // Not yet persisted, or not persisted at all.
+            .filter(|r| !stored_ids.contains(&r.id))
+            .map(|r| (r.timestamp, proxy_request_summary(r))),
+    );
+    drop(cap);
+    items.sort_by_key(|(ts, _)| *ts);
+    axum::Json(serde_json::json!(
+        items.into_iter().map(|(_, v)| v).collect::<Vec<_>>()
+    ))
+}
+
+fn proxy_request_summary(r: &noaide_server::proxy::ApiRequestLog) -> serde_json::Value {
+    // Extract useful preview from request body (model + first message)
+    let req_preview = extract_request_preview(&r.request_body);
+    let res_preview = truncate_preview(&r.response_body, 120);
+    serde_json::json!({
+        "id": r.id,
+        "sessionId": r.session_id,
+        "method": r.method,
+        "url": r.url,
+        "statusCode": r.status_code,
+        "latencyMs": r.latency_ms,
+        "requestSize": r.request_size,
+        "responseSize": r.response_size,
+        "timestamp": r.timestamp,
+        "requestPreview": req_preview,
+        "responsePreview": res_preview,
+        "category": r.category,
+    })
 }
 
 /// Extract a human-readable preview from an API request body JSON.
@@ -3453,8 +6314,29 @@ async fn api_get_proxy_request_detail(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> impl axum::response::IntoResponse {
-    let cap = state.proxy.captured.read().await;
-    match cap.iter().find(|r| r.id == id) {
+    let cached = state
+        .proxy
+        .captured
+        .read()
+        .await
+        .iter()
+        .find(|r| r.id == id)
+        .cloned();
+    // Older requests are only in the DB.
+    let found = match cached {
+        Some(r) => Some(r),
+        None => match Uuid::parse_str(&id) {
+            Ok(uuid) => state
+                .db
+                .get_api_request(&uuid)
+                .await
+                .ok()
+                .flatten()
+                .map(|c| component_to_proxy_log(&c)),
+            Err(_) => None,
+        },
+    };
+    match found {
         Some(r) => (
             axum::http::StatusCode::OK,
             axum::Json(serde_json::json!({
@@ -3840,6 +6722,11 @@ async fn resolve_session_project_root(
     }
     drop(watches);
 
+    // A worktree session's files live in its worktree, also before a resume
+    if let Some(worktree) = session_worktree(state, uuid).await {
+        return Some(worktree.session_dir());
+    }
+
     // Try direct JSONL path lookup
     let paths = state.session_paths.read().await;
     if let Some(jsonl_path) = paths.get(&uuid).cloned() {
@@ -4226,6 +7113,10 @@ fn build_proxy_config_snapshot(
         inject: state.proxy.inject_store.get(session_id),
         rewrite: state.proxy.rewrite_store.get(session_id),
         rules: state.proxy.network_rules.get_rules(session_id),
+        budget: state.proxy.budgets.get(session_id),
+        keys: state.proxy.key_store.session_keys(session_id),
+        preflight: state.proxy.preflight.get(session_id),
+        profile: state.proxy.profiles.assigned(session_id),
     }
 }
 
@@ -4252,11 +7143,14 @@ async fn set_session_intercept_mode(
         .await
         .insert(session_id.to_string(), mode);
 
-    if mode != noaide_server::proxy::InterceptMode::Auto {
-        return;
+    if mode == noaide_server::proxy::InterceptMode::Auto {
+        forward_pending_intercepts(state, session_id).await;
     }
+}
 
-    // Auto-forward all pending requests when switching to Auto.
+/// Forward every request and response of a session still held for manual
+/// review, e.g. after it switched to auto mode.
+async fn forward_pending_intercepts(state: &AppState, session_id: &str) {
     let mut pending = state.proxy.pending_intercepts.write().await;
     let request_ids: Vec<String> = pending
         .iter()
@@ -4380,11 +7274,168 @@ async fn api_set_proxy_config(
         .proxy
         .network_rules
         .set_rules(&session_id, config.rules.clone());
+    state
+        .proxy
+        .budgets
+        .set(session_id.clone(), config.budget.clone());
+    state
+        .proxy
+        .key_store
+        .set_session_keys(session_id.clone(), config.keys.clone());
+    state
+        .proxy
+        .preflight
+        .set(session_id.clone(), config.preflight);
+    match config.profile {
+        Some(ref profile) => state
+            .proxy
+            .profiles
+            .assign(session_id.clone(), profile.clone()),
+        None => state.proxy.profiles.unassign(&session_id),
+    }
     // Persist to disk (debounced)
     noaide_server::proxy::persist::schedule_save(session_id, config);
     axum::Json(serde_json::json!({ "ok": true }))
 }
 
+// ── Proxy Profile Endpoints ─────────────────────────────────────────────────
+
+async fn api_list_profiles(State(state): State<AppState>) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({ "profiles": state.proxy.profiles.list() }))
+}
+
+async fn api_put_profile(
+    State(state): State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::Json(mut profile): axum::Json<noaide_server::proxy::profiles::ProxyProfile>,
+) -> impl axum::response::IntoResponse {
+    if let Some(ref sandbox) = profile.sandbox
+        && let Err(e) = sandbox.validate()
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({ "error": e })),
+        );
+    }
+    profile.name = name.clone();
+    state.proxy.profiles.upsert(profile);
+    if let Err(e) = noaide_server::proxy::profiles::save_to_disk(&state.proxy.profiles) {
+        warn!(error = %e, profile = %name, "failed to persist proxy profiles");
+    }
+    (
+        StatusCode::OK,
+        axum::Json(serde_json::json!({ "ok": true })),
+    )
+}
+
+async fn api_delete_profile(
+    State(state): State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> axum::Json<serde_json::Value> {
+    let removed = state.proxy.profiles.remove(&name);
+    if removed && let Err(e) = noaide_server::proxy::profiles::save_to_disk(&state.proxy.profiles) {
+        warn!(error = %e, profile = %name, "failed to persist proxy profiles after delete");
+    }
+    axum::Json(serde_json::json!({ "removed": removed }))
+}
+
+/// Session profile view: assigned profile, per-session overrides relative to
+/// it, and current budget usage.
+async fn api_get_session_profile(
+    State(state): State<AppState>,
+    axum::extract::Path(session_id): axum::extract::Path<String>,
+) -> axum::Json<serde_json::Value> {
+    let effective = build_proxy_config_snapshot(&state, &session_id);
+    let profile = effective
+        .profile
+        .as_deref()
+        .and_then(|name| state.proxy.profiles.get(name));
+    let overrides = profile
+        .as_ref()
+        .map(|p| noaide_server::proxy::profiles::diff_config(&p.to_config(&session_id), &effective))
+        .unwrap_or_default();
+    axum::Json(serde_json::json!({
+        "profile": effective.profile,
+        "overrides": overrides,
+        "budget": effective.budget,
+        "usage": state.proxy.budgets.usage(&session_id),
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct SetSessionProfileRequest {
+    profile: String,
+}
+
+/// (Re-)apply a profile to a session, discarding any per-session overrides.
+async fn api_set_session_profile(
+    State(state): State<AppState>,
+    axum::extract::Path(session_id): axum::extract::Path<String>,
+    axum::Json(body): axum::Json<SetSessionProfileRequest>,
+) -> impl axum::response::IntoResponse {
+    let Some(profile) = state.proxy.profiles.get(&body.profile) else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({"error": "unknown profile"})),
+        );
+    };
+    // Sets the intercept mode along with the rest of the config
+    let config =
+        noaide_server::proxy::profiles::apply_profile(&state.proxy, &session_id, &profile).await;
+    if intercept_mode_for_proxy_mode(config.mode) == noaide_server::proxy::InterceptMode::Auto {
+        forward_pending_intercepts(&state, &session_id).await;
+    }
+    noaide_server::proxy::persist::schedule_save(session_id, config);
+    (
+        axum::http::StatusCode::OK,
+        axum::Json(serde_json::json!({ "ok": true })),
+    )
+}
+
+// ── Pre-flight Check Endpoints ──────────────────────────────────────────────
+
+async fn api_get_preflight_policy(
+    State(state): State<AppState>,
+    axum::extract::Path(session_id): axum::extract::Path<String>,
+) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({ "policy": state.proxy.preflight.get(&session_id) }))
+}
+
+#[derive(serde::Deserialize)]
+struct SetPreflightRequest {
+    policy: noaide_server::proxy::preflight::PreflightPolicy,
+}
+
+async fn api_set_preflight_policy(
+    State(state): State<AppState>,
+    axum::extract::Path(session_id): axum::extract::Path<String>,
+    axum::Json(body): axum::Json<SetPreflightRequest>,
+) -> axum::Json<serde_json::Value> {
+    state.proxy.preflight.set(session_id.clone(), body.policy);
+    noaide_server::proxy::persist::schedule_save(
+        session_id.clone(),
+        build_proxy_config_snapshot(&state, &session_id),
+    );
+    axum::Json(serde_json::json!({ "ok": true }))
+}
+
+/// Per-model context table used by pre-flight checks.
+async fn api_get_model_limits() -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({
+        "limits": noaide_server::proxy::preflight::limits_table(),
+    }))
+}
+
+/// Upstream egress settings (credentials redacted).
+async fn api_get_upstream_proxy() -> axum::Json<serde_json::Value> {
+    let upstream = noaide_server::proxy::upstream::global();
+    axum::Json(serde_json::json!({
+        "proxy": upstream.proxy.as_ref().map(|p| p.redacted_url()),
+        "bypass": upstream.bypass,
+        "caBundle": upstream.ca_bundle,
+    }))
+}
+
 async fn api_list_presets() -> axum::Json<serde_json::Value> {
     use noaide_server::proxy::inject::Preset;
     let presets: Vec<serde_json::Value> = [
@@ -4612,12 +7663,29 @@ struct GitCommitBody {
     message: String,
 }
 
-/// Resolve the git repo path from a session ID (via JSONL path → project dir).
-/// Falls back to the noaide project root if no session is specified.
+/// The worktree a managed session runs in, looked up by its managed or its
+/// transcript session id.
+async fn session_worktree(state: &AppState, id: Uuid) -> Option<noaide_server::git::Worktree> {
+    if let Some(launch) = state.launches.get(id).await {
+        return launch.worktree;
+    }
+    let managed = state.ecs.read().await.resolve_alias(id);
+    if managed == id {
+        return None;
+    }
+    state.launches.get(managed).await?.worktree
+}
+
+/// Resolve the git repo path from a session ID (session worktree, else JSONL
+/// path → project dir). Falls back to the noaide project root if no session
+/// is specified.
 async fn resolve_git_repo(state: &AppState, session_id: Option<&str>) -> Option<PathBuf> {
     if let Some(sid) = session_id
         && let Ok(uuid) = Uuid::parse_str(sid)
     {
+        if let Some(worktree) = session_worktree(state, uuid).await {
+            return Some(worktree.path);
+        }
         let paths = state.session_paths.read().await;
         if let Some(jsonl_path) = paths.get(&uuid).cloned() {
             drop(paths);
@@ -4896,6 +7964,7 @@ async fn api_git_pr_list(
             "20",
         ])
         .current_dir(&repo_path)
+        .envs(noaide_server::proxy::upstream::global().child_env())
         .output()
         .await;
     match output {
@@ -4942,6 +8011,7 @@ async fn api_git_pr_create(
     let output = tokio::process::Command::new("gh")
         .args(&args)
         .current_dir(&repo_path)
+        .envs(noaide_server::proxy::upstream::global().child_env())
         .output()
         .await;
     match output {
@@ -5091,16 +8161,16 @@ async fn api_serve_file(
 
     let under_session_cwd = if let Some(ref sid) = params.session_id {
         if let Ok(uuid) = Uuid::parse_str(sid) {
-            let paths = state.session_paths.read().await;
-            if let Some(jsonl_path) = paths.get(&uuid).cloned() {
-                drop(paths);
+            let jsonl_path = state.session_paths.read().await.get(&uuid).cloned();
+            if let Some(worktree) = session_worktree(&state, uuid).await {
+                canonical.starts_with(&worktree.path)
+            } else if let Some(jsonl_path) = jsonl_path {
                 if let Some(cwd) = resolve_session_cwd(&jsonl_path) {
                     canonical_str.starts_with(cwd.to_string_lossy().as_ref())
                 } else {
                     false
                 }
             } else {
-                drop(paths);
                 // Try reverse alias for managed sessions
                 let world = state.ecs.read().await;
                 if let Some(jsonl_id) = world.reverse_alias(uuid) {
@@ -0,0 +1,733 @@
+//! Aider chat history parser.
+//!
+//! Aider appends every chat to `.aider.chat.history.md` in the project root
+//! and the raw prompts, with timestamps, to `.aider.input.history`:
+//!
+//! ```text
+//! # aider chat started at 2025-03-01 10:00:00
+//!
+//! > Model: claude-3-7-sonnet-20250219 with diff edit format
+//!
+//! #### fix the failing test
+//!
+//! The assertion compares the wrong field.
+//!
+//! tests/test_app.py
+//! <<<<<<< SEARCH
+//!     assert user.name == "x"
+//! =======
+//!     assert user.login == "x"
+//! >>>>>>> REPLACE
+//!
+//! > Tokens: 2.1k sent, 310 received. Cost: $0.01 message, $0.02 session.
+//! > Applied edit to tests/test_app.py
+//! ```
+//!
+//! `####` lines are user prompts, `>` lines are aider's own output, everything
+//! else is assistant text. SEARCH/REPLACE blocks become `Edit` tool uses,
+//! `/run` and `!` prompts (and `Running …` output) become `Bash` tool uses
+//! paired with their output, and token/cost reports are attached to the
+//! preceding assistant turn.
+//!
+//! One history file is one noaide session; its UUID is derived from the
+//! file path because aider has no session ids of its own.
+
+use std::path::{Path, PathBuf};
+
+use super::registry::TranscriptSource;
+use super::stable_uuid;
+use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+use crate::discovery::scanner::{CliType, SessionInfo, SessionScanner, aider_session_id};
+
+/// File name of the chat transcript aider writes into the project root.
+pub const AIDER_HISTORY_FILE: &str = ".aider.chat.history.md";
+/// File name of aider's prompt history (timestamped user input).
+const AIDER_INPUT_HISTORY_FILE: &str = ".aider.input.history";
+
+/// Aider: `{project}/.aider.chat.history.md`, one transcript per project.
+pub struct AiderSource;
+
+#[async_trait::async_trait]
+impl TranscriptSource for AiderSource {
+    fn cli_type(&self) -> CliType {
+        CliType::Aider
+    }
+
+    fn is_transcript_file(&self, path: &Path) -> bool {
+        path.file_name().and_then(|n| n.to_str()) == Some(AIDER_HISTORY_FILE)
+    }
+
+    fn owns_path(&self, path: &Path) -> bool {
+        self.is_transcript_file(path)
+    }
+
+    fn session_id_from_path(&self, path: &Path) -> Option<String> {
+        self.is_transcript_file(path)
+            .then(|| aider_session_id(path))
+    }
+
+    fn project_path(&self, path: &Path) -> Option<PathBuf> {
+        path.parent().map(Path::to_path_buf)
+    }
+
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        SessionScanner::scan_aider_histories(cli_dir).await
+    }
+
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+        parse_aider_file(path).await
+    }
+
+    fn binary(&self) -> &'static str {
+        "aider"
+    }
+
+    fn auto_approve_flag(&self) -> Option<&'static str> {
+        Some("--yes-always")
+    }
+
+    fn resume_args(&self, _transcript: &Path) -> Option<Vec<String>> {
+        // Aider keeps one history per project and replays it on request.
+        Some(vec!["--restore-chat-history".to_string()])
+    }
+
+    fn headless_args(&self, prompt: &str) -> Option<Vec<String>> {
+        Some(vec!["--message".to_string(), prompt.to_string()])
+    }
+
+    fn proxy_env(&self, session_proxy_url: &str) -> Vec<(String, String)> {
+        // litellm reads OPENAI_API_BASE; the OpenAI SDK reads OPENAI_BASE_URL.
+        // Both point at the plain session prefix (not the Codex backend path).
+        vec![
+            ("OPENAI_API_BASE".to_string(), session_proxy_url.to_string()),
+            ("OPENAI_BASE_URL".to_string(), session_proxy_url.to_string()),
+        ]
+    }
+}
+
+/// Parse an Aider chat history file into ClaudeMessage format.
+pub async fn parse_aider_file(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let input_history = match path.parent() {
+        Some(dir) => tokio::fs::read_to_string(dir.join(AIDER_INPUT_HISTORY_FILE))
+            .await
+            .map(|s| parse_input_history(&s))
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    Ok(parse_history(
+        &content,
+        &input_history,
+        &path.to_string_lossy(),
+    ))
+}
+
+/// Kind of the block currently being accumulated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Block {
+    None,
+    User,
+    Assistant,
+    Output,
+}
+
+/// Line-oriented state machine over the history Markdown.
+struct HistoryParser<'a> {
+    key: &'a str,
+    input_history: &'a [(String, String)],
+    input_cursor: usize,
+    messages: Vec<ClaudeMessage>,
+    block: Block,
+    lines: Vec<&'a str>,
+    timestamp: Option<String>,
+    model: Option<String>,
+    last_assistant: Option<usize>,
+    /// Tool-use id of a shell command whose output is expected next.
+    pending_command: Option<String>,
+    tool_seq: usize,
+}
+
+/// Parse the history Markdown. `key` namespaces the deterministic UUIDs;
+/// `input_history` holds `(timestamp, prompt)` pairs used to date user turns.
+fn parse_history(
+    content: &str,
+    input_history: &[(String, String)],
+    key: &str,
+) -> Vec<ClaudeMessage> {
+    let mut p = HistoryParser {
+        key,
+        input_history,
+        input_cursor: 0,
+        messages: Vec::new(),
+        block: Block::None,
+        lines: Vec::new(),
+        timestamp: None,
+        model: None,
+        last_assistant: None,
+        pending_command: None,
+        tool_seq: 0,
+    };
+
+    for line in content.lines() {
+        if let Some(started) = line.strip_prefix("# aider chat started at ") {
+            p.flush();
+            p.timestamp = aider_timestamp(started.trim());
+            p.last_assistant = None;
+            let meta = serde_json::json!({
+                "type": "session_start",
+                "cli": "aider",
+                "startTime": p.timestamp,
+            });
+            p.push(ClaudeMessage {
+                message_type: "progress".to_string(),
+                content: MessageContent::Text(meta.to_string()),
+                timestamp: p.timestamp.clone(),
+                ..Default::default()
+            });
+        } else if let Some(rest) = strip_marker(line, "####") {
+            p.switch(Block::User);
+            p.lines.push(rest);
+        } else if let Some(rest) = strip_marker(line, ">") {
+            p.switch(Block::Output);
+            p.lines.push(rest);
+        } else if line.trim().is_empty() {
+            if p.block != Block::None {
+                p.lines.push(line);
+            }
+        } else {
+            p.switch(Block::Assistant);
+            p.lines.push(line);
+        }
+    }
+    p.flush();
+    p.messages
+}
+
+/// Strip a `####`/`>` marker followed by a space or end of line.
+fn strip_marker<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(marker)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix(' ')
+    }
+}
+
+impl<'a> HistoryParser<'a> {
+    fn switch(&mut self, block: Block) {
+        if self.block != block {
+            self.flush();
+            self.block = block;
+        }
+    }
+
+    fn push(&mut self, mut msg: ClaudeMessage) {
+        msg.uuid = stable_uuid(self.key, &self.messages.len().to_string()).to_string();
+        self.messages.push(msg);
+    }
+
+    fn next_tool_id(&mut self) -> String {
+        self.tool_seq += 1;
+        format!("aider_{}", self.tool_seq)
+    }
+
+    fn flush(&mut self) {
+        let block = std::mem::replace(&mut self.block, Block::None);
+        let lines = std::mem::take(&mut self.lines);
+        let text = lines.join("\n").trim().to_string();
+        match block {
+            Block::None => {}
+            Block::User => self.flush_user(text),
+            Block::Assistant => self.flush_assistant(&lines),
+            Block::Output => self.flush_output(&lines),
+        }
+    }
+
+    fn flush_user(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(ts) = self.match_input_timestamp(&text) {
+            self.timestamp = Some(ts);
+        }
+        let command = text
+            .strip_prefix("/run ")
+            .or_else(|| text.strip_prefix('!'))
+            .map(|c| c.trim().to_string());
+        self.push(ClaudeMessage {
+            message_type: "user".to_string(),
+            role: Some("user".to_string()),
+            content: MessageContent::Blocks(vec![ContentBlock::Text { text }]),
+            timestamp: self.timestamp.clone(),
+            ..Default::default()
+        });
+        if let Some(command) = command {
+            let id = self.next_tool_id();
+            self.push_tool_use(
+                id.clone(),
+                "Bash",
+                serde_json::json!({ "command": command }),
+            );
+            self.pending_command = Some(id);
+        }
+    }
+
+    fn flush_assistant(&mut self, lines: &[&str]) {
+        let blocks = self.assistant_blocks(lines);
+        if blocks.is_empty() {
+            return;
+        }
+        self.push(ClaudeMessage {
+            message_type: "assistant".to_string(),
+            role: Some("assistant".to_string()),
+            content: MessageContent::Blocks(blocks),
+            timestamp: self.timestamp.clone(),
+            model: self.model.clone(),
+            ..Default::default()
+        });
+        self.last_assistant = Some(self.messages.len() - 1);
+    }
+
+    /// Split assistant text into text blocks and SEARCH/REPLACE `Edit` tool uses.
+    fn assistant_blocks(&mut self, lines: &[&str]) -> Vec<ContentBlock> {
+        let mut blocks = Vec::new();
+        let mut text: Vec<&str> = Vec::new();
+        let mut i = 0;
+        while i < lines.len() {
+            if lines[i].trim_end() != "<<<<<<< SEARCH" {
+                text.push(lines[i]);
+                i += 1;
+                continue;
+            }
+            let Some((search, replace, end)) = split_search_replace(lines, i + 1) else {
+                text.push(lines[i]);
+                i += 1;
+                continue;
+            };
+            // The file name is the last non-fence line before the block.
+            let mut file_path = String::new();
+            while let Some(prev) = text.pop() {
+                let prev = prev.trim();
+                if prev.starts_with("```") {
+                    continue;
+                }
+                if !prev.is_empty() {
+                    file_path = prev.to_string();
+                    break;
+                }
+            }
+            push_text(&mut blocks, &text);
+            text.clear();
+            blocks.push(ContentBlock::ToolUse {
+                id: self.next_tool_id(),
+                name: "Edit".to_string(),
+                input: serde_json::json!({
+                    "file_path": file_path,
+                    "old_string": search,
+                    "new_string": replace,
+                }),
+            });
+            i = end + 1;
+            // Drop the closing fence of a fenced block.
+            if lines.get(i).is_some_and(|l| l.trim() == "```") {
+                i += 1;
+            }
+        }
+        push_text(&mut blocks, &text);
+        blocks
+    }
+
+    fn flush_output(&mut self, lines: &[&str]) {
+        let mut rest: Vec<&str> = Vec::new();
+        for line in lines {
+            let trimmed = line.trim();
+            if let Some(report) = trimmed.strip_prefix("Tokens: ") {
+                self.apply_token_report(report);
+            } else if let Some(model) = trimmed.strip_prefix("Model: ") {
+                let name = model.split(" with ").next().unwrap_or(model).trim();
+                self.model = Some(name.to_string());
+            } else if let Some(command) = trimmed.strip_prefix("Running ") {
+                if self.pending_command.is_none() {
+                    let command = command.trim().to_string();
+                    let id = self.next_tool_id();
+                    self.push_tool_use(
+                        id.clone(),
+                        "Bash",
+                        serde_json::json!({ "command": command }),
+                    );
+                    self.pending_command = Some(id);
+                }
+            } else {
+                rest.push(line);
+            }
+        }
+        let text = rest.join("\n").trim().to_string();
+
+        if let Some(id) = self.pending_command.take() {
+            self.push(ClaudeMessage {
+                message_type: "user".to_string(),
+                role: Some("user".to_string()),
+                content: MessageContent::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: id,
+                    content: serde_json::Value::String(text),
+                    is_error: None,
+                }]),
+                timestamp: self.timestamp.clone(),
+                ..Default::default()
+            });
+            return;
+        }
+        if text.is_empty() {
+            return;
+        }
+        let meta = serde_json::json!({ "type": "aider_output", "text": text });
+        self.push(ClaudeMessage {
+            message_type: "progress".to_string(),
+            content: MessageContent::Text(meta.to_string()),
+            timestamp: self.timestamp.clone(),
+            ..Default::default()
+        });
+    }
+
+    fn push_tool_use(&mut self, id: String, name: &str, input: serde_json::Value) {
+        self.push(ClaudeMessage {
+            message_type: "assistant".to_string(),
+            role: Some("assistant".to_string()),
+            content: MessageContent::Blocks(vec![ContentBlock::ToolUse {
+                id,
+                name: name.to_string(),
+                input,
+            }]),
+            timestamp: self.timestamp.clone(),
+            model: self.model.clone(),
+            ..Default::default()
+        });
+    }
+
+    /// Attach `2.1k sent, 310 received. Cost: $0.01 message, …` to the last reply.
+    fn apply_token_report(&mut self, report: &str) {
+        let Some(idx) = self.last_assistant else {
+            return;
+        };
+        let report = parse_token_report(report);
+        let msg = &mut self.messages[idx];
+        msg.input_tokens = report.sent.or(msg.input_tokens);
+        msg.output_tokens = report.received.or(msg.output_tokens);
+        msg.cache_creation_input_tokens = report.cache_write.or(msg.cache_creation_input_tokens);
+        msg.cache_read_input_tokens = report.cache_hit.or(msg.cache_read_input_tokens);
+        msg.cost_usd = report.cost_usd.or(msg.cost_usd);
+    }
+
+    /// Find the timestamp of this prompt in `.aider.input.history`, scanning
+    /// forward so repeated prompts map to successive entries.
+    fn match_input_timestamp(&mut self, text: &str) -> Option<String> {
+        let offset = self.input_history[self.input_cursor..]
+            .iter()
+            .position(|(_, prompt)| prompt.trim() == text)?;
+        let (ts, _) = &self.input_history[self.input_cursor + offset];
+        self.input_cursor += offset + 1;
+        Some(ts.clone())
+    }
+}
+
+fn push_text(blocks: &mut Vec<ContentBlock>, lines: &[&str]) {
+    let text = lines.join("\n").trim().to_string();
+    if !text.is_empty() {
+        blocks.push(ContentBlock::Text { text });
+    }
+}
+
+/// Split the body of a SEARCH/REPLACE block starting after `<<<<<<< SEARCH`.
+/// Returns `(search, replace, index of the >>>>>>> REPLACE line)`.
+fn split_search_replace(lines: &[&str], start: usize) -> Option<(String, String, usize)> {
+    let divider = (start..lines.len()).find(|&i| lines[i].trim_end() == "=======")?;
+    let end = (divider + 1..lines.len()).find(|&i| lines[i].trim_end() == ">>>>>>> REPLACE")?;
+    Some((
+        lines[start..divider].join("\n"),
+        lines[divider + 1..end].join("\n"),
+        end,
+    ))
+}
+
+/// Numbers from an aider `Tokens:` line.
+#[derive(Debug, Default, PartialEq)]
+struct TokenReport {
+    sent: Option<u64>,
+    received: Option<u64>,
+    cache_write: Option<u64>,
+    cache_hit: Option<u64>,
+    cost_usd: Option<f64>,
+}
+
+/// Parse `12k sent, 1.2k cache write, 3.4k cache hit, 310 received.
+/// Cost: $0.01 message, $0.05 session.`
+fn parse_token_report(report: &str) -> TokenReport {
+    let mut out = TokenReport::default();
+    let (tokens, cost) = match report.split_once("Cost:") {
+        Some((t, c)) => (t, Some(c)),
+        None => (report, None),
+    };
+    for part in tokens.trim().trim_end_matches('.').split(',') {
+        let Some((num, label)) = part.trim().split_once(' ') else {
+            continue;
+        };
+        let value = parse_token_count(num);
+        match label.trim() {
+            "sent" => out.sent = value,
+            "received" => out.received = value,
+            "cache write" => out.cache_write = value,
+            "cache hit" => out.cache_hit = value,
+            _ => {}
+        }
+    }
+    if let Some(cost) = cost {
+        out.cost_usd = cost.split(',').find_map(|part| {
+            let (amount, label) = part.trim().split_once(' ')?;
+            if label.trim_end_matches('.') != "message" {
+                return None;
+            }
+            amount.trim_start_matches('$').parse().ok()
+        });
+    }
+    out
+}
+
+/// `310` → 310, `2.1k` → 2100, `1.5M` → 1_500_000.
+fn parse_token_count(s: &str) -> Option<u64> {
+    let s = s.trim().replace(',', "");
+    let (num, factor) = if let Some(n) = s.strip_suffix('k') {
+        (n, 1_000.0)
+    } else if let Some(n) = s.strip_suffix('M') {
+        (n, 1_000_000.0)
+    } else {
+        (s.as_str(), 1.0)
+    };
+    num.parse::<f64>().ok().map(|v| (v * factor).round() as u64)
+}
+
+/// `2025-03-01 10:00:00[.123456]` → `2025-03-01T09:00:00Z` under TZ=Europe/Berlin.
+///
+/// Aider logs local wall-clock time, so it is converted to UTC through the
+/// local offset in effect at that time (DST included).
+pub(crate) fn aider_timestamp(s: &str) -> Option<String> {
+    use nix::libc;
+
+    let (date, time) = s.split_once(' ')?;
+    let time = time.split('.').next()?;
+    if date.len() != 10 || time.len() != 8 {
+        return None;
+    }
+    let field = |s: &str, range: std::ops::Range<usize>| s.get(range)?.parse::<libc::c_int>().ok();
+    // SAFETY: all-zero is a valid `tm` (null `tm_zone`).
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = field(date, 0..4)? - 1900;
+    tm.tm_mon = field(date, 5..7)? - 1;
+    tm.tm_mday = field(date, 8..10)?;
+    tm.tm_hour = field(time, 0..2)?;
+    tm.tm_min = field(time, 3..5)?;
+    tm.tm_sec = field(time, 6..8)?;
+    tm.tm_isdst = -1;
+    if !(0..12).contains(&tm.tm_mon)
+        || !(1..=31).contains(&tm.tm_mday)
+        || tm.tm_hour > 23
+        || tm.tm_min > 59
+        || tm.tm_sec > 60
+    {
+        return None;
+    }
+    // SAFETY: `tm` is a valid, exclusively borrowed struct; mktime only
+    // normalizes it and reads the process time zone.
+    let secs = unsafe { libc::mktime(&mut tm) };
+    if secs == -1 {
+        return None;
+    }
+    let utc = ::time::OffsetDateTime::from_unix_timestamp(secs).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        utc.year(),
+        utc.month() as u8,
+        utc.day(),
+        utc.hour(),
+        utc.minute(),
+        utc.second()
+    ))
+}
+
+/// Parse `.aider.input.history` into `(timestamp, prompt)` pairs.
+///
+/// ```text
+/// # 2025-03-01 10:00:05.123456
+/// +first line
+/// +second line
+/// ```
+fn parse_input_history(content: &str) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for line in content.lines() {
+        if let Some(ts) = line.strip_prefix("# ") {
+            if let Some((ts, lines)) = current.take() {
+                entries.push((ts, lines.join("\n")));
+            }
+            current = aider_timestamp(ts.trim()).map(|ts| (ts, Vec::new()));
+        } else if let Some(text) = line.strip_prefix('+')
+            && let Some((_, lines)) = current.as_mut()
+        {
+            lines.push(text);
+        }
+    }
+    if let Some((ts, lines)) = current {
+        entries.push((ts, lines.join("\n")));
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: &str = "\
+# aider chat started at 2025-03-01 10:00:00
+
+> Aider v0.82.0
+> Model: claude-3-7-sonnet-20250219 with diff edit format
+
+#### fix the failing test
+
+The assertion compares the wrong field.
+
+tests/test_app.py
+```python
+<<<<<<< SEARCH
+    assert user.name == \"x\"
+=======
+    assert user.login == \"x\"
+>>>>>>> REPLACE
+```
+
+> Tokens: 2.1k sent, 310 received. Cost: $0.01 message, $0.02 session.
+> Applied edit to tests/test_app.py
+
+#### /run pytest -q
+
+> 1 passed in 0.12s
+";
+
+    const INPUT_HISTORY: &str = "
+# 2025-03-01 10:00:05.123456
++fix the failing test
+
+# 2025-03-01 10:01:00.000000
++/run pytest -q
+";
+
+    fn parse() -> Vec<ClaudeMessage> {
+        parse_history(HISTORY, &parse_input_history(INPUT_HISTORY), "/work/app")
+    }
+
+    #[test]
+    fn maps_turns_edits_and_commands() {
+        let msgs = parse();
+        let types: Vec<&str> = msgs.iter().map(|m| m.message_type.as_str()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "progress",  // session_start
+                "progress",  // Aider v0.82.0
+                "user",      // fix the failing test
+                "assistant", // text + Edit
+                "progress",  // Applied edit
+                "user",      // /run pytest -q
+                "assistant", // Bash tool_use
+                "user",      // tool_result
+            ]
+        );
+
+        let MessageContent::Blocks(blocks) = &msgs[3].content else {
+            panic!("assistant should have blocks");
+        };
+        assert!(matches!(&blocks[0], ContentBlock::Text { text } if text.contains("wrong field")));
+        let ContentBlock::ToolUse { name, input, .. } = &blocks[1] else {
+            panic!("expected Edit tool use");
+        };
+        assert_eq!(name, "Edit");
+        assert_eq!(input["file_path"], "tests/test_app.py");
+        assert!(input["old_string"].as_str().unwrap().contains("user.name"));
+        assert!(input["new_string"].as_str().unwrap().contains("user.login"));
+
+        let MessageContent::Blocks(blocks) = &msgs[6].content else {
+            panic!("expected blocks");
+        };
+        let ContentBlock::ToolUse { id, input, .. } = &blocks[0] else {
+            panic!("expected Bash tool use");
+        };
+        assert_eq!(input["command"], "pytest -q");
+        let MessageContent::Blocks(results) = &msgs[7].content else {
+            panic!("expected blocks");
+        };
+        assert!(matches!(
+            &results[0],
+            ContentBlock::ToolResult { tool_use_id, content, .. }
+                if tool_use_id == id && content.as_str().unwrap().contains("1 passed")
+        ));
+    }
+
+    #[test]
+    fn attaches_tokens_model_and_timestamps() {
+        let msgs = parse();
+        let reply = &msgs[3];
+        assert_eq!(reply.model.as_deref(), Some("claude-3-7-sonnet-20250219"));
+        assert_eq!(reply.input_tokens, Some(2100));
+        assert_eq!(reply.output_tokens, Some(310));
+        assert_eq!(reply.cost_usd, Some(0.01));
+        assert_eq!(msgs[0].timestamp, aider_timestamp("2025-03-01 10:00:00"));
+        assert_eq!(
+            msgs[2].timestamp,
+            aider_timestamp("2025-03-01 10:00:05.123456")
+        );
+        assert_eq!(msgs[5].timestamp, aider_timestamp("2025-03-01 10:01:00"));
+    }
+
+    #[test]
+    fn timestamps_are_converted_from_local_time() {
+        use nix::libc;
+
+        let utc = aider_timestamp("2025-07-01 12:34:56.5").unwrap();
+        assert!(utc.ends_with('Z'));
+        let secs = crate::discovery::scanner::parse_iso_to_epoch_secs(&utc).unwrap();
+        // Back to local wall-clock time: the fields aider wrote.
+        // SAFETY: both structs are valid and owned by this frame.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        let secs = secs as libc::time_t;
+        assert!(!unsafe { libc::localtime_r(&secs, &mut tm) }.is_null());
+        assert_eq!((tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday), (2025, 7, 1));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (12, 34, 56));
+        assert!(aider_timestamp("2025-07-01").is_none());
+        assert!(aider_timestamp("2025-13-01 12:34:56").is_none());
+    }
+
+    #[test]
+    fn uuids_are_stable_across_parses() {
+        let a: Vec<String> = parse().into_iter().map(|m| m.uuid).collect();
+        let b: Vec<String> = parse().into_iter().map(|m| m.uuid).collect();
+        assert_eq!(a, b);
+        let unique: std::collections::HashSet<_> = a.iter().collect();
+        assert_eq!(unique.len(), a.len());
+    }
+
+    #[test]
+    fn token_report_with_cache_fields() {
+        let r = parse_token_report(
+            "12k sent, 1.2k cache write, 3.4k cache hit, 310 received. Cost: $0.05 message, $0.20 session.",
+        );
+        assert_eq!(
+            r,
+            TokenReport {
+                sent: Some(12_000),
+                received: Some(310),
+                cache_write: Some(1_200),
+                cache_hit: Some(3_400),
+                cost_usd: Some(0.05),
+            }
+        );
+    }
+}
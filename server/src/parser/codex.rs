@@ -9,14 +9,84 @@
 //! event_msg subtypes: user_message, agent_message, agent_reasoning,
 //!                     token_count, turn_aborted, context_compacted
 
+use std::collections::VecDeque;
 use std::path::Path;
+use std::sync::LazyLock;
 
 use serde::Deserialize;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tracing::warn;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use super::drift::{self, DriftKind};
+use super::path_state::{MAX_PATHS, PathState};
+use super::registry::{CursorKind, TranscriptSource};
 use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+use crate::discovery::scanner::{CliType, SessionInfo, SessionScanner, extract_codex_uuid};
+
+/// Codex CLI: `~/.codex/sessions/YYYY/MM/DD/rollout-{timestamp}-{uuid}.jsonl`.
+pub struct CodexSource;
+
+#[async_trait::async_trait]
+impl TranscriptSource for CodexSource {
+    fn cli_type(&self) -> CliType {
+        CliType::Codex
+    }
+
+    fn is_transcript_file(&self, path: &Path) -> bool {
+        path.extension().and_then(|e| e.to_str()) == Some("jsonl")
+    }
+
+    fn owns_path(&self, path: &Path) -> bool {
+        let s = path.to_string_lossy();
+        let is_rollout = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("rollout-") && n.ends_with(".jsonl"));
+        s.contains("/.codex/") || s.contains("/codex/") || is_rollout
+    }
+
+    fn session_id_from_path(&self, path: &Path) -> Option<String> {
+        extract_codex_uuid(path.file_name()?.to_str()?)
+    }
+
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        let sessions_dir = cli_dir.join("sessions");
+        if !sessions_dir.exists() {
+            return Ok(Vec::new());
+        }
+        SessionScanner::scan_codex_sessions(&sessions_dir).await
+    }
+
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+        parse_codex_file(path).await
+    }
+
+    fn cursor_kind(&self) -> CursorKind {
+        CursorKind::ByteOffset
+    }
+
+    async fn parse_incremental(
+        &self,
+        path: &Path,
+        cursor: u64,
+    ) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+        parse_codex_incremental(path, cursor).await
+    }
+
+    fn binary(&self) -> &'static str {
+        "codex"
+    }
+
+    fn resume_args(&self, transcript: &Path) -> Option<Vec<String>> {
+        let id = self.session_id_from_path(transcript)?;
+        Some(vec!["resume".to_string(), id])
+    }
+
+    fn headless_args(&self, prompt: &str) -> Option<Vec<String>> {
+        Some(vec!["exec".to_string(), prompt.to_string()])
+    }
+}
 
 /// Raw Codex JSONL line structure.
 #[derive(Deserialize)]
@@ -27,34 +97,153 @@ struct CodexLine {
     payload: serde_json::Value,
 }
 
+/// Model from the most recent `turn_context` before a byte offset, so an
+/// incremental parse starting mid-file attributes messages to the right
+/// model. Rollouts are append-only, so the model at an offset is the same
+/// for every consumer (cache, watcher, search index, analytics) that
+/// reached it; each rollout keeps the last [`CARRIED_OFFSETS`] offsets.
+static CARRIED_MODEL: LazyLock<PathState<CarriedModels>> =
+    LazyLock::new(|| PathState::new(MAX_PATHS));
+
+/// `(offset, model)` pairs remembered for one rollout, oldest first.
+type CarriedModels = VecDeque<(u64, Option<String>)>;
+
+/// Offsets remembered per rollout in [`CARRIED_MODEL`].
+const CARRIED_OFFSETS: usize = 8;
+
 /// Parse a Codex JSONL file into ClaudeMessage format.
 pub async fn parse_codex_file(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
     let file = tokio::fs::File::open(path).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+    let mut reader = BufReader::new(file);
+    let (messages, _consumed, _model) = parse_codex_chunk(&mut reader, None, false).await?;
+    Ok(messages)
+}
+
+/// Parse a Codex rollout from byte `from_offset`, returning the new offset.
+///
+/// Rollouts are append-only, so only the bytes written since the last call
+/// are read. The `turn_context` model is carried over from the previous
+/// chunk; if the caller's offset doesn't match the remembered one (e.g.
+/// after a restart) the model is recovered by scanning earlier
+/// `turn_context` lines without parsing the rest. A trailing line that is
+/// still being written is left for the next call. If the file shrank below
+/// `from_offset` it is re-parsed from the start and the returned offset is
+/// smaller than `from_offset`.
+pub async fn parse_codex_incremental(
+    path: &Path,
+    from_offset: u64,
+) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let file_len = file.metadata().await?.len();
+    let offset = if from_offset > file_len {
+        debug!(
+            from_offset,
+            file_len, "codex: rollout truncated, restarting"
+        );
+        0
+    } else {
+        from_offset
+    };
+
+    if offset < from_offset {
+        CARRIED_MODEL.remove(path);
+    }
+    let remembered = CARRIED_MODEL
+        .get(path, |offsets| {
+            offsets
+                .iter()
+                .find(|(at, _)| *at == offset)
+                .map(|(_, model)| model.clone())
+        })
+        .flatten();
+    let carried = match remembered {
+        Some(model) => model,
+        None if offset == 0 => None,
+        None => recover_model(path, offset).await?,
+    };
+
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut reader = BufReader::new(file);
+    let (messages, consumed, model) = parse_codex_chunk(&mut reader, carried, true).await?;
+    let new_offset = offset + consumed;
+    CARRIED_MODEL.update(path, |offsets| {
+        offsets.retain(|(at, _)| *at != new_offset);
+        if offsets.len() == CARRIED_OFFSETS {
+            offsets.pop_front();
+        }
+        offsets.push_back((new_offset, model));
+    });
+    Ok((messages, new_offset))
+}
+
+/// Find the model of the last `turn_context` before `offset`.
+async fn recover_model(path: &Path, offset: u64) -> anyhow::Result<Option<String>> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut lines = BufReader::new(file.take(offset)).lines();
+    let mut model = None;
+    while let Some(line) = lines.next_line().await? {
+        if !line.contains("\"turn_context\"") {
+            continue;
+        }
+        if let Ok(entry) = serde_json::from_str::<CodexLine>(&line)
+            && entry.line_type == "turn_context"
+            && let Some(m) = entry.payload.get("model").and_then(|v| v.as_str())
+        {
+            model = Some(m.to_string());
+        }
+    }
+    Ok(model)
+}
+
+/// Parse rollout lines until EOF, starting with `current_model` in effect.
+///
+/// Returns the messages, the number of bytes consumed and the model in
+/// effect afterwards. With `hold_partial`, an unterminated last line that
+/// doesn't parse yet is not consumed.
+async fn parse_codex_chunk<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    mut current_model: Option<String>,
+    hold_partial: bool,
+) -> anyhow::Result<(Vec<ClaudeMessage>, u64, Option<String>)> {
     let mut messages = Vec::new();
+    let mut consumed = 0u64;
     let mut line_num = 0u64;
-    let mut current_model: Option<String> = None;
+    let mut buf = String::new();
 
-    while let Some(line) = lines.next_line().await? {
+    loop {
+        buf.clear();
+        let bytes_read = reader.read_line(&mut buf).await?;
+        if bytes_read == 0 {
+            break;
+        }
         line_num += 1;
-        let line = line.trim().to_string();
+        let line = buf.trim();
         if line.is_empty() {
+            consumed += bytes_read as u64;
             continue;
         }
 
-        let entry: CodexLine = match serde_json::from_str(&line) {
+        let entry: CodexLine = match serde_json::from_str(line) {
             Ok(e) => e,
             Err(e) => {
+                if hold_partial && !buf.ends_with('\n') {
+                    // Still being appended — pick it up on the next call.
+                    break;
+                }
+                consumed += bytes_read as u64;
                 if line_num <= 3 {
                     warn!(line = line_num, error = %e, "codex: malformed line");
                 }
                 continue;
             }
         };
+        consumed += bytes_read as u64;
 
         match entry.line_type.as_str() {
             "session_meta" => {
+                if let Some(version) = entry.payload.get("cli_version").and_then(|v| v.as_str()) {
+                    drift::note_version("codex", version);
+                }
                 messages.push(codex_session_meta(&entry));
             }
             "event_msg" => {
@@ -80,6 +269,9 @@ pub async fn parse_codex_file(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>>
             }
             other => {
                 // Unknown type — preserve as meta
+                drift::record("codex", DriftKind::EntryType, "line", other, || {
+                    line.to_string()
+                });
                 messages.push(ClaudeMessage {
                     uuid: Uuid::new_v4().to_string(),
                     message_type: other.to_string(),
@@ -95,7 +287,7 @@ pub async fn parse_codex_file(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>>
         }
     }
 
-    Ok(messages)
+    Ok((messages, consumed, current_model))
 }
 
 /// Convert session_meta to a meta message.
@@ -263,6 +455,13 @@ fn codex_event_msg(entry: &CodexLine, model: &Option<String>) -> Option<ClaudeMe
         }
         _ => {
             // Unknown event_msg subtype — preserve as meta
+            drift::record(
+                "codex",
+                DriftKind::EventSubtype,
+                "event_msg",
+                subtype,
+                || entry.payload.to_string(),
+            );
             Some(ClaudeMessage {
                 uuid: Uuid::new_v4().to_string(),
                 message_type: "progress".to_string(),
@@ -278,8 +477,30 @@ fn codex_event_msg(entry: &CodexLine, model: &Option<String>) -> Option<ClaudeMe
     }
 }
 
+/// `response_item` payload types with known shapes (tool calls aside).
+const KNOWN_RESPONSE_ITEMS: [&str; 3] = ["message", "reasoning", "web_search_call"];
+
 /// Convert response_item to a message.
 fn codex_response_item(entry: &CodexLine, model: &Option<String>) -> Option<ClaudeMessage> {
+    match entry.payload.get("type").and_then(|v| v.as_str()) {
+        Some("function_call" | "custom_tool_call" | "local_shell_call") => {
+            return codex_tool_call(entry, model);
+        }
+        Some("function_call_output" | "custom_tool_call_output") => {
+            return codex_tool_output(entry);
+        }
+        Some(other) if !KNOWN_RESPONSE_ITEMS.contains(&other) => {
+            drift::record(
+                "codex",
+                DriftKind::EventSubtype,
+                "response_item",
+                other,
+                || entry.payload.to_string(),
+            );
+        }
+        _ => {}
+    }
+
     let role = entry
         .payload
         .get("role")
@@ -307,6 +528,13 @@ fn codex_response_item(entry: &CodexLine, model: &Option<String>) -> Option<Clau
             }
             _ => {
                 // Unknown content type — preserve as text
+                drift::record(
+                    "codex",
+                    DriftKind::ContentBlock,
+                    "response_item",
+                    ct,
+                    || entry.payload.to_string(),
+                );
                 let json = serde_json::to_string(item).unwrap_or_default();
                 if !json.is_empty() {
                     blocks.push(ContentBlock::Text { text: json });
@@ -336,6 +564,89 @@ fn codex_response_item(entry: &CodexLine, model: &Option<String>) -> Option<Clau
     })
 }
 
+/// Convert a function/custom/local-shell call into an assistant `ToolUse`.
+///
+/// `function_call` arguments are a JSON string; `custom_tool_call` input
+/// (e.g. `apply_patch`) is raw text and kept as a string.
+fn codex_tool_call(entry: &CodexLine, model: &Option<String>) -> Option<ClaudeMessage> {
+    let payload = &entry.payload;
+    let call_id = payload.get("call_id").and_then(|v| v.as_str())?.to_string();
+    let name = payload
+        .get("name")
+        .and_then(|v| v.as_str())
+        .unwrap_or("local_shell")
+        .to_string();
+    let input = if let Some(args) = payload.get("arguments").and_then(|v| v.as_str()) {
+        serde_json::from_str(args).unwrap_or_else(|_| serde_json::Value::String(args.to_string()))
+    } else if let Some(input) = payload.get("input") {
+        input.clone()
+    } else {
+        payload
+            .get("action")
+            .cloned()
+            .unwrap_or(serde_json::Value::Null)
+    };
+
+    Some(ClaudeMessage {
+        uuid: Uuid::new_v4().to_string(),
+        message_type: "assistant".to_string(),
+        role: Some("assistant".to_string()),
+        content: MessageContent::Blocks(vec![ContentBlock::ToolUse {
+            id: call_id,
+            name,
+            input,
+        }]),
+        timestamp: entry.timestamp.clone(),
+        model: model.clone(),
+        stop_reason: Some("tool_use".to_string()),
+        ..Default::default()
+    })
+}
+
+/// Convert a call output into a user `ToolResult`.
+fn codex_tool_output(entry: &CodexLine) -> Option<ClaudeMessage> {
+    let payload = &entry.payload;
+    let call_id = payload.get("call_id").and_then(|v| v.as_str())?.to_string();
+    let output = match payload.get("output") {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(other) => other
+            .get("content")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| other.to_string()),
+        None => String::new(),
+    };
+    let is_error = codex_exit_code(&output).is_some_and(|code| code != 0);
+
+    Some(ClaudeMessage {
+        uuid: Uuid::new_v4().to_string(),
+        message_type: "user".to_string(),
+        role: Some("user".to_string()),
+        content: MessageContent::Blocks(vec![ContentBlock::ToolResult {
+            tool_use_id: call_id,
+            content: serde_json::Value::String(output),
+            is_error: Some(is_error),
+        }]),
+        timestamp: entry.timestamp.clone(),
+        ..Default::default()
+    })
+}
+
+/// Exit code of a shell call output: either JSON with
+/// `metadata.exit_code` or text starting with `Exit code: N`.
+fn codex_exit_code(output: &str) -> Option<i64> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(output) {
+        return json.get("metadata")?.get("exit_code")?.as_i64();
+    }
+    output
+        .lines()
+        .next()?
+        .strip_prefix("Exit code: ")?
+        .trim()
+        .parse()
+        .ok()
+}
+
 /// Convert turn_context to a meta message.
 fn codex_turn_context(entry: &CodexLine) -> ClaudeMessage {
     let model = entry
@@ -421,6 +732,30 @@ mod tests {
         assert_eq!(msg.message_type, "assistant");
     }
 
+    #[test]
+    fn parse_codex_function_call_pairs_with_output() {
+        let call = r#"{"timestamp":"2025-10-26T11:55:00Z","type":"response_item","payload":{"type":"function_call","name":"shell","arguments":"{\"command\":[\"bash\",\"-lc\",\"false\"]}","call_id":"call_1"}}"#;
+        let output = r#"{"timestamp":"2025-10-26T11:55:02Z","type":"response_item","payload":{"type":"function_call_output","call_id":"call_1","output":"{\"output\":\"\",\"metadata\":{\"exit_code\":1,\"duration_seconds\":0.1}}"}}"#;
+
+        let entry: CodexLine = serde_json::from_str(call).unwrap();
+        let msg = codex_response_item(&entry, &None).unwrap();
+        assert!(matches!(
+            &msg.content,
+            MessageContent::Blocks(b) if matches!(&b[0], ContentBlock::ToolUse { id, name, input }
+                if id == "call_1" && name == "shell" && input["command"][2] == "false")
+        ));
+
+        let entry: CodexLine = serde_json::from_str(output).unwrap();
+        let msg = codex_response_item(&entry, &None).unwrap();
+        assert_eq!(msg.message_type, "user");
+        assert!(matches!(
+            &msg.content,
+            MessageContent::Blocks(b) if matches!(&b[0], ContentBlock::ToolResult { tool_use_id, is_error, .. }
+                if tool_use_id == "call_1" && *is_error == Some(true))
+        ));
+        assert_eq!(codex_exit_code("Exit code: 0\nWall time: 1s"), Some(0));
+    }
+
     #[test]
     fn parse_codex_turn_context_extracts_model() {
         let line = r#"{"timestamp":"2025-10-26T11:55:00Z","type":"turn_context","payload":{"model":"gpt-5.2-codex","cwd":"/home/user"}}"#;
@@ -466,6 +801,85 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn parse_codex_incremental_carries_model() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let ctx = r#"{"timestamp":"2025-10-26T11:54:00Z","type":"turn_context","payload":{"model":"gpt-5.2-codex"}}"#;
+        let user = r#"{"timestamp":"2025-10-26T11:54:30Z","type":"event_msg","payload":{"type":"user_message","message":"hi"}}"#;
+        let reply = r#"{"timestamp":"2025-10-26T11:55:00Z","type":"response_item","payload":{"type":"message","role":"assistant","content":[{"type":"output_text","text":"Hello!"}]}}"#;
+        writeln!(file, "{ctx}").unwrap();
+        writeln!(file, "{user}").unwrap();
+
+        let (first, offset) = parse_codex_incremental(file.path(), 0).await.unwrap();
+        assert_eq!(first.len(), 2);
+
+        // A half-written line is left for the next call.
+        write!(file, "{}", &reply[..40]).unwrap();
+        file.flush().unwrap();
+        let (none, same) = parse_codex_incremental(file.path(), offset).await.unwrap();
+        assert!(none.is_empty());
+        assert_eq!(same, offset);
+
+        writeln!(file, "{}", &reply[40..]).unwrap();
+        let (new, next) = parse_codex_incremental(file.path(), offset).await.unwrap();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].model.as_deref(), Some("gpt-5.2-codex"));
+        assert!(next > offset);
+
+        // Without remembered state (e.g. after a restart) the model is
+        // recovered from the earlier turn_context.
+        CARRIED_MODEL.remove(file.path());
+        let (again, _) = parse_codex_incremental(file.path(), offset).await.unwrap();
+        assert_eq!(again[0].model.as_deref(), Some("gpt-5.2-codex"));
+    }
+
+    #[tokio::test]
+    async fn interleaved_consumers_keep_their_models() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let ctx = |model: &str| {
+            format!(
+                r#"{{"timestamp":"2025-10-26T11:54:00Z","type":"turn_context","payload":{{"model":"{model}"}}}}"#
+            )
+        };
+        let reply = |text: &str| {
+            format!(
+                r#"{{"timestamp":"2025-10-26T11:55:00Z","type":"response_item","payload":{{"type":"message","role":"assistant","content":[{{"type":"output_text","text":"{text}"}}]}}}}"#
+            )
+        };
+        writeln!(file, "{}", ctx("gpt-5.2-codex")).unwrap();
+        writeln!(file, "{}", reply("one")).unwrap();
+
+        // Consumer A stops after the first turn, consumer B after the second.
+        let (_, a) = parse_codex_incremental(file.path(), 0).await.unwrap();
+        writeln!(file, "{}", ctx("gpt-5.3-codex")).unwrap();
+        writeln!(file, "{}", reply("two")).unwrap();
+        let (_, b) = parse_codex_incremental(file.path(), 0).await.unwrap();
+        writeln!(file, "{}", reply("three")).unwrap();
+
+        let (a_new, _) = parse_codex_incremental(file.path(), a).await.unwrap();
+        let (b_new, _) = parse_codex_incremental(file.path(), b).await.unwrap();
+        assert!(
+            a_new
+                .iter()
+                .all(|m| m.model.as_deref() == Some("gpt-5.3-codex"))
+        );
+        assert_eq!(b_new.len(), 1);
+        assert_eq!(b_new[0].model.as_deref(), Some("gpt-5.3-codex"));
+
+        // Both offsets were remembered, so neither had to rescan the rollout.
+        let remembered = CARRIED_MODEL
+            .get(file.path(), |offsets| {
+                [a, b].map(|o| offsets.iter().any(|(at, _)| *at == o))
+            })
+            .unwrap();
+        assert_eq!(remembered, [true, true]);
+        CARRIED_MODEL.remove(file.path());
+    }
+
     #[tokio::test]
     async fn parse_codex_e2e_fixture_rollout() {
         let fixture = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
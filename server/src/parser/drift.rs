@@ -0,0 +1,294 @@
+//! Transcript schema-drift detection.
+//!
+//! The CLIs change their transcript formats without notice. Parsers report
+//! entry types, fields, event subtypes and content-block types they don't
+//! recognize here; each distinct finding is kept once with its first-seen
+//! time, the CLI version in effect and the shape of a sample line, and is
+//! announced to [`subscribe`]rs the first time it shows up.
+//!
+//! Samples never hold transcript content: strings and numbers are replaced
+//! by their type (only `type` discriminators are kept), and the outline is
+//! cut to a short excerpt.
+
+use std::sync::LazyLock;
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Sample outlines are cut to this many bytes.
+const MAX_SAMPLE: usize = 256;
+
+/// Nesting below this depth is elided from sample outlines.
+const MAX_SAMPLE_DEPTH: usize = 4;
+
+/// Capacity of the new-finding notification channel.
+const NOTIFY_CAPACITY: usize = 64;
+
+/// What kind of unrecognized element was seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftKind {
+    /// Top-level entry / line / message type.
+    EntryType,
+    /// Field of an entry that the parser doesn't read or know.
+    Field,
+    /// Subtype of an event envelope (Codex `event_msg`, `response_item`).
+    EventSubtype,
+    /// Content-block / part type inside a message.
+    ContentBlock,
+}
+
+/// One distinct unrecognized element.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftRecord {
+    /// Transcript source name (`claude`, `codex`, …).
+    pub source: String,
+    pub kind: DriftKind,
+    /// Where it was seen, e.g. the entry type a field belongs to.
+    pub context: String,
+    pub name: String,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    pub count: u64,
+    /// CLI version last reported by the source when first seen.
+    pub cli_version: Option<String>,
+    /// Redacted outline of the first line it was seen in.
+    pub sample: String,
+}
+
+type DriftKey = (String, DriftKind, String, String);
+
+static RECORDS: LazyLock<DashMap<DriftKey, DriftRecord>> = LazyLock::new(DashMap::new);
+
+/// Latest CLI version reported per source.
+static VERSIONS: LazyLock<DashMap<String, String>> = LazyLock::new(DashMap::new);
+
+static NOTIFY: LazyLock<broadcast::Sender<DriftRecord>> =
+    LazyLock::new(|| broadcast::channel(NOTIFY_CAPACITY).0);
+
+/// Remember the CLI version a source's transcripts were written by.
+pub fn note_version(source: &str, version: &str) {
+    if version.is_empty() || VERSIONS.get(source).is_some_and(|v| *v == version) {
+        return;
+    }
+    VERSIONS.insert(source.to_string(), version.to_string());
+}
+
+/// Record an unrecognized element. `sample` (the raw line) is only evaluated
+/// the first time the element is seen, and only its redacted shape is kept.
+pub fn record(
+    source: &str,
+    kind: DriftKind,
+    context: &str,
+    name: &str,
+    sample: impl FnOnce() -> String,
+) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let key = (
+        source.to_string(),
+        kind,
+        context.to_string(),
+        name.to_string(),
+    );
+    if let Some(mut existing) = RECORDS.get_mut(&key) {
+        existing.count += 1;
+        existing.last_seen_ms = now;
+        return;
+    }
+
+    let record = DriftRecord {
+        source: source.to_string(),
+        kind,
+        context: context.to_string(),
+        name: name.to_string(),
+        first_seen_ms: now,
+        last_seen_ms: now,
+        count: 1,
+        cli_version: VERSIONS.get(source).map(|v| v.clone()),
+        sample: truncate(outline(&sample())),
+    };
+    // Another parser may have raced us to the same finding.
+    if RECORDS.insert(key, record.clone()).is_none() {
+        tracing::warn!(
+            source,
+            kind = ?kind,
+            context,
+            name,
+            "transcript schema drift: unrecognized element"
+        );
+        let _ = NOTIFY.send(record);
+    }
+}
+
+/// Record every key of `obj` that is not in `known` as a [`DriftKind::Field`].
+pub fn check_fields(
+    source: &str,
+    context: &str,
+    obj: &serde_json::Value,
+    known: &[&str],
+    sample: impl Fn() -> String,
+) {
+    let Some(map) = obj.as_object() else {
+        return;
+    };
+    for key in map.keys() {
+        if !known.contains(&key.as_str()) {
+            record(source, DriftKind::Field, context, key, &sample);
+        }
+    }
+}
+
+/// All findings, newest first.
+pub fn records() -> Vec<DriftRecord> {
+    let mut all: Vec<DriftRecord> = RECORDS.iter().map(|r| r.value().clone()).collect();
+    all.sort_by_key(|r| std::cmp::Reverse(r.first_seen_ms));
+    all
+}
+
+/// Receive each finding the first time it is recorded.
+pub fn subscribe() -> broadcast::Receiver<DriftRecord> {
+    NOTIFY.subscribe()
+}
+
+/// The shape of a raw sample line, without its content.
+fn outline(raw: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(raw) {
+        Ok(value) => shape(&value, 0).to_string(),
+        Err(_) => format!("<{} bytes, not JSON>", raw.len()),
+    }
+}
+
+fn shape(value: &serde_json::Value, depth: usize) -> serde_json::Value {
+    use serde_json::Value;
+
+    if depth >= MAX_SAMPLE_DEPTH && (value.is_object() || value.is_array()) {
+        return Value::String("<…>".into());
+    }
+    match value {
+        Value::String(_) => Value::String("<string>".into()),
+        Value::Number(_) => Value::String("<number>".into()),
+        Value::Array(items) => {
+            let mut out: Vec<Value> = items.iter().take(1).map(|v| shape(v, depth + 1)).collect();
+            if items.len() > 1 {
+                out.push(Value::String(format!("<{} more>", items.len() - 1)));
+            }
+            Value::Array(out)
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| {
+                    let v = match v {
+                        Value::String(t) if key == "type" => Value::String(t.clone()),
+                        v => shape(v, depth + 1),
+                    };
+                    (key.clone(), v)
+                })
+                .collect(),
+        ),
+        Value::Bool(_) | Value::Null => value.clone(),
+    }
+}
+
+fn truncate(mut sample: String) -> String {
+    if sample.len() > MAX_SAMPLE {
+        let cut = (0..=MAX_SAMPLE)
+            .rev()
+            .find(|&i| sample.is_char_boundary(i))
+            .unwrap_or(0);
+        sample.truncate(cut);
+        sample.push('…');
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sighting_is_kept_and_announced_once() {
+        let mut rx = subscribe();
+        note_version("drift-test", "1.2.3");
+        for _ in 0..3 {
+            record(
+                "drift-test",
+                DriftKind::EntryType,
+                "line",
+                "hologram",
+                || "x".repeat(5000),
+            );
+        }
+        check_fields(
+            "drift-test",
+            "user",
+            &serde_json::json!({"uuid": "u1", "mood": "curious"}),
+            &["uuid"],
+            String::new,
+        );
+
+        let mine: Vec<DriftRecord> = records()
+            .into_iter()
+            .filter(|r| r.source == "drift-test")
+            .collect();
+        assert_eq!(mine.len(), 2);
+        let entry = mine.iter().find(|r| r.name == "hologram").unwrap();
+        assert_eq!(entry.count, 3);
+        assert_eq!(entry.cli_version.as_deref(), Some("1.2.3"));
+        assert_eq!(entry.sample, "<5000 bytes, not JSON>");
+        assert!(
+            mine.iter()
+                .any(|r| r.kind == DriftKind::Field && r.name == "mood")
+        );
+
+        let mood = mine.iter().find(|r| r.name == "mood").unwrap();
+        assert_eq!(mood.sample, "<0 bytes, not JSON>");
+
+        let mut announced = Vec::new();
+        while let Ok(r) = rx.try_recv() {
+            if r.source == "drift-test" {
+                announced.push(r.name);
+            }
+        }
+        assert_eq!(announced, vec!["hologram", "mood"]);
+    }
+
+    #[test]
+    fn samples_keep_the_shape_but_not_the_content() {
+        let line = serde_json::json!({
+            "type": "assistant",
+            "secret": "sk-ant-api03-do-not-keep",
+            "score": 0.5,
+            "ok": true,
+            "message": {"content": [
+                {"type": "text", "text": "private prompt"},
+                {"type": "text", "text": "more"},
+            ]},
+            "deep": {"a": {"b": {"c": {"d": "x"}}}},
+        });
+        let sample = outline(&line.to_string());
+        assert!(!sample.contains("sk-ant"));
+        assert!(!sample.contains("private prompt"));
+        assert!(!sample.contains("0.5"));
+        let shape: serde_json::Value = serde_json::from_str(&sample).unwrap();
+        assert_eq!(shape["type"], "assistant");
+        assert_eq!(shape["secret"], "<string>");
+        assert_eq!(shape["score"], "<number>");
+        assert_eq!(shape["ok"], true);
+        assert_eq!(
+            shape["message"]["content"],
+            serde_json::json!([{"type": "text", "text": "<string>"}, "<1 more>"])
+        );
+        assert_eq!(shape["deep"]["a"]["b"]["c"], "<…>");
+
+        let wide: serde_json::Map<String, serde_json::Value> = (0..100)
+            .map(|i| (format!("field{i}"), serde_json::json!(i)))
+            .collect();
+        let sample = truncate(outline(&serde_json::Value::Object(wide).to_string()));
+        assert!(sample.len() <= MAX_SAMPLE + '…'.len_utf8());
+    }
+}
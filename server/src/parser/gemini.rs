@@ -7,17 +7,97 @@
 //! Message types: user, gemini, info, error
 
 use std::path::Path;
+use std::sync::LazyLock;
 
 use serde::Deserialize;
-use tracing::warn;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use super::drift::{self, DriftKind};
+use super::path_state::{MAX_PATHS, PathState};
+use super::registry::{InputMode, TranscriptSource};
 use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+use crate::discovery::scanner::{CliType, SessionInfo, SessionScanner, extract_gemini_uuid};
+
+/// Gemini CLI: `~/.gemini/tmp/{project}/chats/session-{timestamp}-{id}.json`,
+/// a single JSON document rewritten on every turn.
+pub struct GeminiSource;
+
+#[async_trait::async_trait]
+impl TranscriptSource for GeminiSource {
+    fn cli_type(&self) -> CliType {
+        CliType::Gemini
+    }
+
+    fn is_transcript_file(&self, path: &Path) -> bool {
+        path.extension().and_then(|e| e.to_str()) == Some("json")
+    }
+
+    fn owns_path(&self, path: &Path) -> bool {
+        let s = path.to_string_lossy();
+        let is_chat = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("session-") && n.ends_with(".json"));
+        s.contains("/.gemini/") || s.contains("/gemini/") || is_chat
+    }
+
+    fn session_id_from_path(&self, path: &Path) -> Option<String> {
+        let id = extract_gemini_uuid(path.file_name()?.to_str()?)?;
+        uuid::Uuid::parse_str(&id).ok()?;
+        Some(id)
+    }
+
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        let tmp_dir = cli_dir.join("tmp");
+        if !tmp_dir.exists() {
+            return Ok(Vec::new());
+        }
+        SessionScanner::scan_gemini_sessions(&tmp_dir).await
+    }
+
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+        parse_gemini_file(path).await
+    }
+
+    async fn parse_incremental(
+        &self,
+        path: &Path,
+        cursor: u64,
+    ) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+        parse_gemini_incremental(path, cursor).await
+    }
+
+    fn binary(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn input_mode(&self) -> InputMode {
+        // Multi-character PTY writes are treated as an untrusted paste in
+        // which Enter inserts a newline instead of submitting.
+        InputMode::Keystrokes
+    }
+
+    fn resume_args(&self, transcript: &Path) -> Option<Vec<String>> {
+        // The file name only carries a short id; `--resume` wants the full
+        // `sessionId` from the document.
+        let data = std::fs::read(transcript).ok()?;
+        let doc: GeminiSession<serde::de::IgnoredAny> = serde_json::from_slice(&data).ok()?;
+        Some(vec!["--resume".to_string(), doc.session_id?])
+    }
+
+    fn headless_args(&self, prompt: &str) -> Option<Vec<String>> {
+        Some(vec!["--prompt".to_string(), prompt.to_string()])
+    }
+}
 
 /// Top-level Gemini session JSON structure.
+///
+/// Incremental parsing reads `messages` as raw values and only converts the
+/// new tail into [`GeminiMessage`]s.
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct GeminiSession {
+#[serde(rename_all = "camelCase", bound(deserialize = "M: Deserialize<'de>"))]
+struct GeminiSession<M = GeminiMessage> {
     #[serde(default)]
     session_id: Option<String>,
     #[allow(dead_code)]
@@ -25,15 +105,26 @@ struct GeminiSession {
     project_hash: Option<String>,
     #[serde(default)]
     start_time: Option<String>,
-    #[allow(dead_code)]
     #[serde(default)]
     last_updated: Option<String>,
     #[serde(default)]
-    messages: Vec<GeminiMessage>,
+    messages: Vec<M>,
     #[serde(default)]
     summary: Option<String>,
 }
 
+/// Message keys read by [`GeminiMessage`] (plus tool-call bookkeeping).
+const KNOWN_MESSAGE_FIELDS: [&str; 8] = [
+    "id",
+    "timestamp",
+    "type",
+    "content",
+    "thoughts",
+    "tokens",
+    "model",
+    "toolCalls",
+];
+
 /// A single Gemini message.
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -72,81 +163,245 @@ struct GeminiTokens {
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiToolCall {
+    #[serde(default)]
+    id: Option<String>,
     #[serde(default)]
     name: Option<String>,
     #[serde(default)]
     args: Option<serde_json::Value>,
+    /// `functionResponse` parts returned to the model.
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    /// "success", "error" or "cancelled" once the call finished; while it
+    /// runs, a transient one like "scheduled", "awaiting_approval" or
+    /// "executing".
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    timestamp: Option<String>,
+}
+
+/// Cursor bit set once the session summary was emitted; the bits below
+/// count the raw Gemini messages converted so far. Carrying this in the
+/// cursor rather than per file lets every consumer (cache, watcher, search
+/// index, analytics) parse the same file from its own cursor.
+const SUMMARY_SEEN: u64 = 1 << 48;
+
+/// What the last incremental parse of a session file saw.
+///
+/// Only used to skip unchanged documents: the result of a parse depends on
+/// nothing but the document and the cursor, so whichever consumer parsed
+/// the file last, a caller holding the same cursor would get nothing new.
+struct GeminiDelta {
+    /// The cursor that parse returned.
+    cursor: u64,
+    last_updated: Option<String>,
+    file_len: u64,
+}
+
+/// Per-file delta state for [`parse_gemini_incremental`].
+static DELTAS: LazyLock<PathState<GeminiDelta>> = LazyLock::new(|| PathState::new(MAX_PATHS));
+
+/// Index of the last turn if one of its tool calls hasn't finished yet.
+///
+/// Only the last `user`/`gemini` message counts: a call left `executing`
+/// in an earlier turn was abandoned and never gets a result.
+fn running_turn(messages: &[serde_json::Value]) -> Option<usize> {
+    let last = messages.iter().rposition(|m| {
+        matches!(
+            m.get("type").and_then(|t| t.as_str()),
+            Some("user" | "gemini")
+        )
+    })?;
+    let calls = messages[last].get("toolCalls")?.as_array()?;
+    calls
+        .iter()
+        .any(|tc| {
+            !matches!(
+                tc.get("status").and_then(|s| s.as_str()),
+                Some("success" | "error" | "cancelled")
+            )
+        })
+        .then_some(last)
 }
 
 /// Parse a Gemini JSON session file into ClaudeMessage format.
 pub async fn parse_gemini_file(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
     let content = tokio::fs::read_to_string(path).await?;
     let session: GeminiSession = serde_json::from_str(&content)?;
-    let mut messages = Vec::new();
-
-    // Session start meta message
-    if let Some(start) = &session.start_time {
-        messages.push(ClaudeMessage {
-            uuid: session
-                .session_id
-                .clone()
-                .unwrap_or_else(|| Uuid::new_v4().to_string()),
-            message_type: "progress".to_string(),
-            role: None,
-            content: MessageContent::Text(format!(
-                "{{\"type\":\"session_start\",\"cli\":\"gemini\",\"startTime\":\"{start}\"}}"
-            )),
-            timestamp: Some(start.clone()),
-            ..Default::default()
-        });
-    }
+    let mut messages: Vec<ClaudeMessage> = gemini_session_start(&session).into_iter().collect();
 
     for msg in &session.messages {
-        match msg.msg_type.as_str() {
-            "user" => {
-                messages.push(gemini_user_message(msg));
-            }
-            "gemini" => {
-                let converted = gemini_assistant_message(msg);
-                messages.extend(converted);
-            }
-            "info" => {
-                messages.push(gemini_info_message(msg));
-            }
-            "error" => {
-                messages.push(gemini_error_message(msg));
-            }
-            other => {
-                warn!(msg_type = other, "gemini: unknown message type");
-                messages.push(ClaudeMessage {
-                    uuid: msg.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string()),
-                    message_type: "progress".to_string(),
-                    role: None,
-                    content: MessageContent::Text(
-                        serde_json::to_string(&msg.content).unwrap_or_default(),
-                    ),
-                    timestamp: msg.timestamp.clone(),
-                    ..Default::default()
-                });
-            }
-        }
+        messages.extend(convert_gemini_message(msg));
     }
 
     // Session summary (if present)
-    if let Some(summary) = &session.summary
-        && !summary.is_empty()
+    if let Some(summary) = gemini_summary(&session) {
+        messages.push(summary);
+    }
+
+    Ok(messages)
+}
+
+/// Parse only the Gemini messages added since `cursor` (a raw message index,
+/// plus [`SUMMARY_SEEN`] once the summary was emitted).
+///
+/// Gemini rewrites the whole document on every turn. If neither
+/// `lastUpdated` nor the file size changed since a parse that returned
+/// `cursor` the document isn't parsed at all; otherwise only messages from
+/// index `cursor` on are converted. A message count below `cursor` means the file was replaced:
+/// the full transcript is returned with a cursor smaller than the input.
+///
+/// Gemini updates tool calls in place (`executing` → `success`), so the
+/// cursor stops before the last turn while one of its calls is still
+/// running and that turn is converted once the call has finished. Other
+/// in-place edits of already converted messages are not re-emitted.
+pub async fn parse_gemini_incremental(
+    path: &Path,
+    cursor: u64,
+) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let file_len = content.len() as u64;
+    let last_updated = peek_last_updated(&content);
+
+    if cursor > 0
+        && DELTAS
+            .get(path, |delta| {
+                delta.cursor == cursor
+                    && delta.file_len == file_len
+                    && delta.last_updated.as_deref() == last_updated
+            })
+            .unwrap_or(false)
     {
-        messages.push(ClaudeMessage {
-            uuid: Uuid::new_v4().to_string(),
-            message_type: "summary".to_string(),
-            role: None,
-            content: MessageContent::Text(summary.clone()),
-            timestamp: session.start_time.clone(),
-            ..Default::default()
+        return Ok((Vec::new(), cursor));
+    }
+
+    let session: GeminiSession<serde_json::Value> = serde_json::from_str(&content)?;
+    let count = session.messages.len() as u64;
+    let (from, mut summary_seen) = if count < cursor & !SUMMARY_SEEN {
+        debug!(cursor, count, "gemini: session rewritten, restarting");
+        (0, false)
+    } else {
+        (cursor & !SUMMARY_SEEN, cursor & SUMMARY_SEEN != 0)
+    };
+    if from == count && from > 0 {
+        debug!(
+            messages = count,
+            last_updated = ?session.last_updated,
+            "gemini: document updated without new messages"
+        );
+    }
+
+    let until = running_turn(&session.messages)
+        .map(|i| i as u64)
+        .filter(|&i| i >= from && i > 0)
+        .unwrap_or(count);
+
+    let mut messages: Vec<ClaudeMessage> = if from == 0 {
+        gemini_session_start(&session).into_iter().collect()
+    } else {
+        Vec::new()
+    };
+    for value in &session.messages[from as usize..until as usize] {
+        let msg_type = value.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        drift::check_fields("gemini", msg_type, value, &KNOWN_MESSAGE_FIELDS, || {
+            value.to_string()
         });
+        match GeminiMessage::deserialize(value) {
+            Ok(msg) => messages.extend(convert_gemini_message(&msg)),
+            Err(e) => warn!(error = %e, "gemini: malformed message"),
+        }
+    }
+    if until == count
+        && !summary_seen
+        && let Some(summary) = gemini_summary(&session)
+    {
+        messages.push(summary);
+        summary_seen = true;
     }
 
-    Ok(messages)
+    let cursor = if summary_seen {
+        until | SUMMARY_SEEN
+    } else {
+        until
+    };
+    DELTAS.insert(
+        path,
+        GeminiDelta {
+            cursor,
+            last_updated: session.last_updated.clone(),
+            file_len,
+        },
+    );
+    Ok((messages, cursor))
+}
+
+/// Read the top-level `lastUpdated` without parsing the document.
+///
+/// Gemini writes it before `messages`, so only that prefix is searched.
+fn peek_last_updated(content: &str) -> Option<&str> {
+    let header = &content[..content.find("\"messages\"")?];
+    let rest = &header[header.find("\"lastUpdated\"")? + "\"lastUpdated\"".len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Session start meta message.
+fn gemini_session_start<M>(session: &GeminiSession<M>) -> Option<ClaudeMessage> {
+    let start = session.start_time.as_ref()?;
+    Some(ClaudeMessage {
+        uuid: session
+            .session_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        message_type: "progress".to_string(),
+        role: None,
+        content: MessageContent::Text(format!(
+            "{{\"type\":\"session_start\",\"cli\":\"gemini\",\"startTime\":\"{start}\"}}"
+        )),
+        timestamp: Some(start.clone()),
+        ..Default::default()
+    })
+}
+
+/// Session summary message, if Gemini wrote one.
+fn gemini_summary<M>(session: &GeminiSession<M>) -> Option<ClaudeMessage> {
+    let summary = session.summary.as_ref().filter(|s| !s.is_empty())?;
+    Some(ClaudeMessage {
+        uuid: Uuid::new_v4().to_string(),
+        message_type: "summary".to_string(),
+        role: None,
+        content: MessageContent::Text(summary.clone()),
+        timestamp: session.start_time.clone(),
+        ..Default::default()
+    })
+}
+
+/// Convert one Gemini message into one or more ClaudeMessages.
+fn convert_gemini_message(msg: &GeminiMessage) -> Vec<ClaudeMessage> {
+    match msg.msg_type.as_str() {
+        "user" => vec![gemini_user_message(msg)],
+        "gemini" => gemini_assistant_message(msg),
+        "info" => vec![gemini_info_message(msg)],
+        "error" => vec![gemini_error_message(msg)],
+        other => {
+            warn!(msg_type = other, "gemini: unknown message type");
+            drift::record("gemini", DriftKind::EntryType, "message", other, || {
+                serde_json::json!({"id": msg.id, "type": other, "content": msg.content}).to_string()
+            });
+            vec![ClaudeMessage {
+                uuid: msg.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string()),
+                message_type: "progress".to_string(),
+                role: None,
+                content: MessageContent::Text(
+                    serde_json::to_string(&msg.content).unwrap_or_default(),
+                ),
+                timestamp: msg.timestamp.clone(),
+                ..Default::default()
+            }]
+        }
+    }
 }
 
 /// Convert a Gemini "user" message.
@@ -197,13 +452,33 @@ fn gemini_assistant_message(msg: &GeminiMessage) -> Vec<ClaudeMessage> {
         blocks.push(ContentBlock::Text { text });
     }
 
-    // Tool calls
+    // Tool calls. Finished calls carry their result inline; it is split
+    // into a following user ToolResult message like Claude's transcripts.
+    let mut results = Vec::new();
+    let mut results_ts = msg.timestamp.clone();
     if let Some(tool_calls) = &msg.tool_calls {
-        for tc in tool_calls {
+        for (i, tc) in tool_calls.iter().enumerate() {
             let name = tc.name.as_deref().unwrap_or("unknown_tool").to_string();
             let input = tc.args.clone().unwrap_or(serde_json::Value::Null);
+            let call_id = tc.id.clone().unwrap_or_else(|| format!("{id}-tool-{i}"));
+            let is_error = match tc.status.as_deref() {
+                Some("success") => Some(false),
+                Some("error" | "cancelled") => Some(true),
+                // Still running: no result yet
+                _ => None,
+            };
+            if let Some(is_error) = is_error {
+                results.push(ContentBlock::ToolResult {
+                    tool_use_id: call_id.clone(),
+                    content: gemini_tool_output(tc.result.as_ref()),
+                    is_error: Some(is_error),
+                });
+                if tc.timestamp.is_some() {
+                    results_ts = tc.timestamp.clone();
+                }
+            }
             blocks.push(ContentBlock::ToolUse {
-                id: Uuid::new_v4().to_string(),
+                id: call_id,
                 name,
                 input,
             });
@@ -216,7 +491,7 @@ fn gemini_assistant_message(msg: &GeminiMessage) -> Vec<ClaudeMessage> {
             .any(|b| matches!(b, ContentBlock::ToolUse { .. }));
 
         result.push(ClaudeMessage {
-            uuid: id,
+            uuid: id.clone(),
             message_type: "assistant".to_string(),
             role: Some("assistant".to_string()),
             content: MessageContent::Blocks(blocks),
@@ -233,9 +508,38 @@ fn gemini_assistant_message(msg: &GeminiMessage) -> Vec<ClaudeMessage> {
         });
     }
 
+    if !results.is_empty() {
+        result.push(ClaudeMessage {
+            uuid: format!("{id}-results"),
+            message_type: "user".to_string(),
+            role: Some("user".to_string()),
+            content: MessageContent::Blocks(results),
+            timestamp: results_ts,
+            ..Default::default()
+        });
+    }
+
     result
 }
 
+/// Flatten `functionResponse.response` output/error into the result content.
+fn gemini_tool_output(result: Option<&serde_json::Value>) -> serde_json::Value {
+    let Some(parts) = result.and_then(|r| r.as_array()) else {
+        return result.cloned().unwrap_or(serde_json::Value::Null);
+    };
+    let text: Vec<String> = parts
+        .iter()
+        .filter_map(|p| p.get("functionResponse")?.get("response"))
+        .map(|r| {
+            r.get("output")
+                .or_else(|| r.get("error"))
+                .and_then(|v| v.as_str())
+                .map_or_else(|| r.to_string(), str::to_string)
+        })
+        .collect();
+    serde_json::Value::String(text.join("\n"))
+}
+
 /// Convert a Gemini "info" message (e.g., "Request cancelled.").
 fn gemini_info_message(msg: &GeminiMessage) -> ClaudeMessage {
     let text = extract_text_content(&msg.content);
@@ -378,6 +682,61 @@ mod tests {
         assert_eq!(results[1].model, Some("gemini-2.5-pro".to_string()));
     }
 
+    #[test]
+    fn parse_gemini_tool_call_with_result() {
+        let msg: GeminiMessage = serde_json::from_value(serde_json::json!({
+            "id": "m1",
+            "timestamp": "2025-11-19T06:17:10.000Z",
+            "type": "gemini",
+            "content": "",
+            "toolCalls": [{
+                "id": "run_shell_command-1",
+                "name": "run_shell_command",
+                "args": {"command": "ls"},
+                "status": "error",
+                "timestamp": "2025-11-19T06:17:12.000Z",
+                "result": [{"functionResponse": {"id": "run_shell_command-1", "name": "run_shell_command", "response": {"error": "exit 2"}}}],
+            }],
+        }))
+        .unwrap();
+
+        let results = gemini_assistant_message(&msg);
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            &results[1].content,
+            MessageContent::Blocks(b) if matches!(&b[0], ContentBlock::ToolResult { tool_use_id, content, is_error }
+                if tool_use_id == "run_shell_command-1" && content == "exit 2" && *is_error == Some(true))
+        ));
+        assert_eq!(
+            results[1].timestamp.as_deref(),
+            Some("2025-11-19T06:17:12.000Z")
+        );
+    }
+
+    #[test]
+    fn parse_gemini_tool_call_in_progress() {
+        let msg: GeminiMessage = serde_json::from_value(serde_json::json!({
+            "id": "m1",
+            "timestamp": "2025-11-19T06:17:10.000Z",
+            "type": "gemini",
+            "content": "",
+            "toolCalls": [{
+                "id": "run_shell_command-1",
+                "name": "run_shell_command",
+                "args": {"command": "cargo build"},
+                "status": "executing",
+            }],
+        }))
+        .unwrap();
+
+        let results = gemini_assistant_message(&msg);
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            &results[0].content,
+            MessageContent::Blocks(b) if b.len() == 1 && matches!(&b[0], ContentBlock::ToolUse { id, .. } if id == "run_shell_command-1")
+        ));
+    }
+
     #[test]
     fn parse_gemini_error_msg() {
         let msg = GeminiMessage {
@@ -412,4 +771,146 @@ mod tests {
         let val = serde_json::json!(null);
         assert_eq!(extract_text_content(&val), "");
     }
+
+    #[tokio::test]
+    async fn incremental_converts_only_new_messages() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("session-2025-11-19T06-14-3f2a9c1e.json");
+        let msg = |id: &str, kind: &str| serde_json::json!({"id": id, "type": kind, "content": [{"text": id}]});
+        let write = |updated: &str, messages: Vec<serde_json::Value>| {
+            let doc = serde_json::json!({
+                "sessionId": "3f2a9c1e",
+                "startTime": "2025-11-19T06:14:00Z",
+                "lastUpdated": updated,
+                "messages": messages,
+            });
+            std::fs::write(&path, doc.to_string()).unwrap();
+        };
+
+        write("t1", vec![msg("u1", "user"), msg("a1", "gemini")]);
+        let (first, cursor) = parse_gemini_incremental(&path, 0).await.unwrap();
+        assert_eq!(cursor, 2);
+        assert_eq!(first.len(), 3, "session start + two messages");
+
+        let (none, same) = parse_gemini_incremental(&path, cursor).await.unwrap();
+        assert!(none.is_empty());
+        assert_eq!(same, cursor);
+
+        write(
+            "t2",
+            vec![msg("u1", "user"), msg("a1", "gemini"), msg("u2", "user")],
+        );
+        let (new, next) = parse_gemini_incremental(&path, cursor).await.unwrap();
+        assert_eq!(next, 3);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].uuid, "u2");
+
+        // Replaced by a shorter session: full transcript, cursor goes back.
+        write("t3", vec![msg("x1", "user")]);
+        let (all, reset) = parse_gemini_incremental(&path, next).await.unwrap();
+        assert_eq!(reset, 1);
+        assert_eq!(all.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn interleaved_consumers_each_get_the_summary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("session-2025-11-19T06-14-5b7d0e2a.json");
+        let write = |updated: &str, ids: &[&str]| {
+            let messages: Vec<_> = ids
+                .iter()
+                .map(|id| serde_json::json!({"id": id, "type": "user", "content": [{"text": id}]}))
+                .collect();
+            let doc = serde_json::json!({
+                "sessionId": "5b7d0e2a",
+                "startTime": "2025-11-19T06:14:00Z",
+                "lastUpdated": updated,
+                "messages": messages,
+                "summary": "Fixed the build",
+            });
+            std::fs::write(&path, doc.to_string()).unwrap();
+        };
+        let summaries = |messages: &[ClaudeMessage]| {
+            messages
+                .iter()
+                .filter(|m| m.message_type == "summary")
+                .count()
+        };
+
+        write("t1", &["u1"]);
+        let (a_msgs, a) = parse_gemini_incremental(&path, 0).await.unwrap();
+        let (b_msgs, b) = parse_gemini_incremental(&path, 0).await.unwrap();
+        assert_eq!(summaries(&a_msgs), 1);
+        assert_eq!(
+            summaries(&b_msgs),
+            1,
+            "second consumer must see the summary too"
+        );
+        assert_eq!(a, b);
+
+        write("t2", &["u1", "u2"]);
+        let (a_new, a) = parse_gemini_incremental(&path, a).await.unwrap();
+        let (b_new, b) = parse_gemini_incremental(&path, b).await.unwrap();
+        assert_eq!(a_new.len(), 1);
+        assert_eq!(a_new[0].uuid, "u2");
+        assert_eq!(b_new.len(), 1, "no summary again, only the new message");
+        assert_eq!(a, b);
+        assert!(
+            parse_gemini_incremental(&path, a)
+                .await
+                .unwrap()
+                .0
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn incremental_waits_for_running_tool_calls() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("session-2025-11-19T06-14-9c4e1b7f.json");
+        let write = |updated: &str, status: &str| {
+            let doc = serde_json::json!({
+                "sessionId": "9c4e1b7f",
+                "startTime": "2025-11-19T06:14:00Z",
+                "lastUpdated": updated,
+                "messages": [
+                    {"id": "u1", "type": "user", "content": [{"text": "build it"}]},
+                    {"id": "g1", "type": "gemini", "content": "", "toolCalls": [{
+                        "id": "run_shell_command-1",
+                        "name": "run_shell_command",
+                        "args": {"command": "cargo build"},
+                        "status": status,
+                        "result": [{"functionResponse": {"response": {"output": "Finished"}}}],
+                    }]},
+                ],
+            });
+            std::fs::write(&path, doc.to_string()).unwrap();
+        };
+        let has_result = |messages: &[ClaudeMessage]| {
+            messages.iter().any(|m| {
+                matches!(&m.content, MessageContent::Blocks(b)
+                    if b.iter().any(|b| matches!(b, ContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == "run_shell_command-1")))
+            })
+        };
+
+        write("t1", "executing");
+        let (first, cursor) = parse_gemini_incremental(&path, 0).await.unwrap();
+        assert_eq!(cursor, 1, "cursor stops before the running turn");
+        assert_eq!(first.len(), 2, "session start + user message");
+
+        write("t2", "success");
+        let (second, cursor) = parse_gemini_incremental(&path, cursor).await.unwrap();
+        assert_eq!(cursor, 2);
+        assert!(second.iter().any(|m| m.uuid == "g1"));
+        assert!(has_result(&second), "finished call must yield its result");
+        assert!(!second.iter().any(|m| m.uuid == "u1"));
+    }
+
+    #[test]
+    fn peek_last_updated_reads_header_only() {
+        let doc = r#"{"sessionId":"s","lastUpdated": "2025-11-19T06:17:10Z","messages":[{"lastUpdated":"x"}]}"#;
+        assert_eq!(peek_last_updated(doc), Some("2025-11-19T06:17:10Z"));
+        let doc = r#"{"messages":[{"lastUpdated":"x"}]}"#;
+        assert_eq!(peek_last_updated(doc), None);
+    }
 }
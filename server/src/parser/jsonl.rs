@@ -3,7 +3,78 @@ use std::path::Path;
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
 use tracing::{debug, instrument, warn};
 
+use super::registry::{CursorKind, TranscriptSource};
 use super::types::{ClaudeMessage, parse_raw_to_message};
+use crate::discovery::scanner::{CliType, SessionInfo, SessionScanner};
+
+/// Claude Code: `~/.claude/projects/{encoded-project-dir}/{session-uuid}.jsonl`,
+/// append-only, parsed incrementally by byte offset.
+pub struct ClaudeSource;
+
+#[async_trait::async_trait]
+impl TranscriptSource for ClaudeSource {
+    fn cli_type(&self) -> CliType {
+        CliType::Claude
+    }
+
+    fn is_transcript_file(&self, path: &Path) -> bool {
+        path.extension().and_then(|e| e.to_str()) == Some("jsonl")
+    }
+
+    fn owns_path(&self, path: &Path) -> bool {
+        path.to_string_lossy().contains("/.claude/") || self.session_id_from_path(path).is_some()
+    }
+
+    fn session_id_from_path(&self, path: &Path) -> Option<String> {
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        uuid::Uuid::parse_str(stem).ok()?;
+        Some(stem.to_string())
+    }
+
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        let projects_dir = cli_dir.join("projects");
+        if !projects_dir.exists() {
+            return Ok(Vec::new());
+        }
+        SessionScanner::scan_claude_projects(&projects_dir).await
+    }
+
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+        parse_file(path).await
+    }
+
+    fn cursor_kind(&self) -> CursorKind {
+        CursorKind::ByteOffset
+    }
+
+    async fn parse_incremental(
+        &self,
+        path: &Path,
+        cursor: u64,
+    ) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+        parse_incremental(path, cursor).await
+    }
+
+    fn binary(&self) -> &'static str {
+        "claude"
+    }
+
+    fn auto_approve_flag(&self) -> Option<&'static str> {
+        Some("--dangerously-skip-permissions")
+    }
+
+    fn resume_args(&self, transcript: &Path) -> Option<Vec<String>> {
+        let id = self.session_id_from_path(transcript)?;
+        Some(vec!["--resume".to_string(), id])
+    }
+
+    fn headless_args(&self, prompt: &str) -> Option<Vec<String>> {
+        Some(vec!["-p".to_string(), prompt.to_string()])
+    }
+}
 
 /// Parse a complete JSONL file, returning all messages.
 ///
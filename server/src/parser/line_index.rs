@@ -0,0 +1,471 @@
+//! Sidecar line-offset index for Claude JSONL transcripts too large for the
+//! ECS cache.
+//!
+//! One record per message line (byte offset, length, timestamp, entry type),
+//! persisted under `/data/noaide/line-index/` and extended from where it
+//! stopped when the transcript grows. A page of messages is then one seek
+//! and one read, whatever the file size, and a timestamp is a binary search.
+//!
+//! The index is trusted while the transcript's size and mtime match the
+//! header. Otherwise the first 4 KiB and the last indexed line are compared
+//! against fingerprints: if both still match, the file was appended to and
+//! only the new lines are indexed; if not, it was rewritten and the index is
+//! rebuilt.
+
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use dashmap::DashMap;
+use tracing::debug;
+
+use super::jsonl::parse_line;
+use super::types::ClaudeMessage;
+
+const MAGIC: &[u8; 4] = b"NLIX";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 72;
+const RECORD_LEN: usize = 21;
+/// Bytes of the transcript covered by the head fingerprint.
+const HEAD_LEN: u64 = 4096;
+
+/// Entry type of an indexed line, from the JSONL `type` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LineKind {
+    Other = 0,
+    User = 1,
+    Assistant = 2,
+    System = 3,
+    Progress = 4,
+    Summary = 5,
+    FileSnapshot = 6,
+}
+
+impl LineKind {
+    fn of(message_type: &str) -> Self {
+        match message_type {
+            "user" => Self::User,
+            "assistant" => Self::Assistant,
+            "system" | "system-reminder" => Self::System,
+            "progress" => Self::Progress,
+            "summary" => Self::Summary,
+            "file-history-snapshot" => Self::FileSnapshot,
+            _ => Self::Other,
+        }
+    }
+
+    fn from_u8(b: u8) -> Self {
+        match b {
+            1 => Self::User,
+            2 => Self::Assistant,
+            3 => Self::System,
+            4 => Self::Progress,
+            5 => Self::Summary,
+            6 => Self::FileSnapshot,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Where one message sits in the transcript. Its ordinal is its position
+/// in [`LineIndex::checkpoints`], the same as in `parse_file`'s output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub offset: u64,
+    /// Line length without the newline.
+    pub len: u32,
+    /// Epoch milliseconds; lines without a timestamp carry the previous one.
+    pub timestamp_ms: i64,
+    pub kind: LineKind,
+}
+
+/// Line index of one transcript.
+#[derive(Debug, Clone, Default)]
+pub struct LineIndex {
+    /// Transcript size and mtime (nanoseconds) when last indexed.
+    file_size: u64,
+    mtime_ns: i64,
+    /// End of the last complete line that was indexed.
+    indexed_bytes: u64,
+    head_hash: [u8; 16],
+    tail_hash: [u8; 16],
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl LineIndex {
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Ordinal of the first message at or after `timestamp_ms` (`len()` if
+    /// there is none).
+    pub fn position_at(&self, timestamp_ms: i64) -> usize {
+        self.checkpoints
+            .partition_point(|c| c.timestamp_ms < timestamp_ms)
+    }
+
+    /// Parse the messages with ordinals in `range`, reading only their lines.
+    pub async fn read_range(
+        &self,
+        path: &Path,
+        range: Range<usize>,
+    ) -> anyhow::Result<Vec<ClaudeMessage>> {
+        let end = range.end.min(self.checkpoints.len());
+        let start = range.start.min(end);
+        let lines = self.checkpoints[start..end].to_vec();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || read_lines(&path, &lines)).await?
+    }
+
+    /// Bring the index of `path` up to date, rebuilding it when the
+    /// transcript was rewritten. `stored` is written back when it changed.
+    pub fn refresh(mut self, path: &Path, stored: &Path) -> anyhow::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        let file_size = meta.len();
+        let mtime_ns = mtime_ns(&meta);
+        if file_size == self.file_size && mtime_ns == self.mtime_ns {
+            return Ok(self);
+        }
+
+        let mut file = std::fs::File::open(path)?;
+        let appended = file_size >= self.indexed_bytes
+            && self.indexed_bytes > 0
+            && fingerprint(&mut file, 0, HEAD_LEN.min(self.indexed_bytes))? == self.head_hash
+            && self.tail_fingerprint(&mut file)? == self.tail_hash;
+        let previous = self.checkpoints.len();
+        if !appended {
+            debug!(path = %path.display(), "building line index");
+            self = Self::default();
+        }
+
+        file.seek(SeekFrom::Start(self.indexed_bytes))?;
+        let mut reader = BufReader::with_capacity(256 * 1024, file);
+        let mut line = Vec::new();
+        let mut offset = self.indexed_bytes;
+        let mut last_ms = self.checkpoints.last().map_or(0, |c| c.timestamp_ms);
+        loop {
+            line.clear();
+            let n = reader.read_until(b'\n', &mut line)?;
+            // A line still being written is indexed once it is complete.
+            if n == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            let start = offset;
+            offset += n as u64;
+            self.indexed_bytes = offset;
+            let text = String::from_utf8_lossy(&line);
+            let trimmed = text.trim();
+            // Same rules as `parse_incremental`: blank and malformed lines
+            // are not messages.
+            let Ok(msg) = parse_line(trimmed) else {
+                continue;
+            };
+            if let Some(ms) = msg.timestamp.as_deref().and_then(super::parse_iso_millis) {
+                last_ms = ms;
+            }
+            self.checkpoints.push(Checkpoint {
+                offset: start,
+                len: line_len(&line),
+                timestamp_ms: last_ms,
+                kind: LineKind::of(&msg.message_type),
+            });
+        }
+
+        let mut file = reader.into_inner();
+        if self.indexed_bytes < HEAD_LEN || !appended {
+            self.head_hash = fingerprint(&mut file, 0, HEAD_LEN.min(self.indexed_bytes))?;
+        }
+        self.tail_hash = self.tail_fingerprint(&mut file)?;
+        self.file_size = file_size;
+        self.mtime_ns = mtime_ns;
+        if appended {
+            self.append_to(stored, previous)?;
+        } else {
+            self.write(stored)?;
+        }
+        Ok(self)
+    }
+
+    /// Load a stored index; `None` if it is missing or unreadable.
+    pub fn load(stored: &Path) -> Option<Self> {
+        let bytes = std::fs::read(stored).ok()?;
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return None;
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        if u32::from_le_bytes(bytes[4..8].try_into().unwrap()) != VERSION {
+            return None;
+        }
+        let count = u64_at(32) as usize;
+        // Records are written before the header, so a torn append leaves
+        // extra records behind, never missing ones.
+        let records = bytes.get(HEADER_LEN..HEADER_LEN + count.checked_mul(RECORD_LEN)?)?;
+        let checkpoints = records
+            .chunks_exact(RECORD_LEN)
+            .map(|r| Checkpoint {
+                offset: u64::from_le_bytes(r[0..8].try_into().unwrap()),
+                len: u32::from_le_bytes(r[8..12].try_into().unwrap()),
+                timestamp_ms: i64::from_le_bytes(r[12..20].try_into().unwrap()),
+                kind: LineKind::from_u8(r[20]),
+            })
+            .collect();
+        Some(Self {
+            file_size: u64_at(8),
+            mtime_ns: u64_at(16) as i64,
+            indexed_bytes: u64_at(24),
+            head_hash: bytes[40..56].try_into().unwrap(),
+            tail_hash: bytes[56..72].try_into().unwrap(),
+            checkpoints,
+        })
+    }
+
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut h = [0u8; HEADER_LEN];
+        h[..4].copy_from_slice(MAGIC);
+        h[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        h[8..16].copy_from_slice(&self.file_size.to_le_bytes());
+        h[16..24].copy_from_slice(&self.mtime_ns.to_le_bytes());
+        h[24..32].copy_from_slice(&self.indexed_bytes.to_le_bytes());
+        h[32..40].copy_from_slice(&(self.checkpoints.len() as u64).to_le_bytes());
+        h[40..56].copy_from_slice(&self.head_hash);
+        h[56..72].copy_from_slice(&self.tail_hash);
+        h
+    }
+
+    fn write(&self, stored: &Path) -> std::io::Result<()> {
+        if let Some(dir) = stored.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = stored.with_extension("tmp");
+        let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        out.write_all(&self.header())?;
+        for c in &self.checkpoints {
+            out.write_all(&record(c))?;
+        }
+        out.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, stored)
+    }
+
+    /// Append the records from `from` on, then commit them in the header.
+    fn append_to(&self, stored: &Path, from: usize) -> std::io::Result<()> {
+        let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(stored) else {
+            return self.write(stored);
+        };
+        file.seek(SeekFrom::Start((HEADER_LEN + from * RECORD_LEN) as u64))?;
+        let mut out = std::io::BufWriter::new(&mut file);
+        for c in &self.checkpoints[from..] {
+            out.write_all(&record(c))?;
+        }
+        out.flush()?;
+        drop(out);
+        file.sync_data()?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&self.header())
+    }
+
+    fn tail_fingerprint(&self, file: &mut std::fs::File) -> std::io::Result<[u8; 16]> {
+        match self.checkpoints.last() {
+            Some(c) => fingerprint(file, c.offset, c.len as u64),
+            None => Ok([0; 16]),
+        }
+    }
+}
+
+/// Where the index of `transcript` is stored: `NOAIDE_LINE_INDEX_DIR`, else
+/// `/data/noaide/line-index/`, named after a hash of the transcript path.
+pub fn index_path(transcript: &Path) -> PathBuf {
+    let dir = std::env::var("NOAIDE_LINE_INDEX_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("line-index"));
+    let digest = ring::digest::digest(
+        &ring::digest::SHA256,
+        transcript.to_string_lossy().as_bytes(),
+    );
+    let name: String = digest.as_ref()[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    dir.join(format!("{name}.lidx"))
+}
+
+/// The index of one transcript, locked while it is refreshed.
+type Slot = Arc<tokio::sync::Mutex<Option<Arc<LineIndex>>>>;
+
+/// Indexes in use, so a page request costs no index load. Each transcript
+/// has its own lock, so indexing a large file does not stall other files.
+static INDEXES: LazyLock<DashMap<PathBuf, Slot>> = LazyLock::new(DashMap::new);
+
+fn slot(path: &Path) -> Slot {
+    if let Some(slot) = INDEXES.get(path) {
+        return slot.clone();
+    }
+    INDEXES.entry(path.to_path_buf()).or_default().clone()
+}
+
+/// The up-to-date index of a transcript, built or extended as needed.
+pub async fn open(path: &Path) -> anyhow::Result<Arc<LineIndex>> {
+    // Held across the refresh so concurrent requests do not index twice.
+    let slot = slot(path);
+    let mut entry = slot.lock().await;
+    let current = entry.clone();
+    let path_buf = path.to_path_buf();
+    let index = tokio::task::spawn_blocking(move || {
+        let stored = index_path(&path_buf);
+        let index = match current {
+            Some(index) => (*index).clone(),
+            None => LineIndex::load(&stored).unwrap_or_default(),
+        };
+        index.refresh(&path_buf, &stored)
+    })
+    .await??;
+    let index = Arc::new(index);
+    *entry = Some(index.clone());
+    Ok(index)
+}
+
+fn record(c: &Checkpoint) -> [u8; RECORD_LEN] {
+    let mut r = [0u8; RECORD_LEN];
+    r[0..8].copy_from_slice(&c.offset.to_le_bytes());
+    r[8..12].copy_from_slice(&c.len.to_le_bytes());
+    r[12..20].copy_from_slice(&c.timestamp_ms.to_le_bytes());
+    r[20] = c.kind as u8;
+    r
+}
+
+fn line_len(line: &[u8]) -> u32 {
+    let mut len = line.len();
+    while len > 0 && matches!(line[len - 1], b'\n' | b'\r') {
+        len -= 1;
+    }
+    len as u32
+}
+
+fn mtime_ns(meta: &std::fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as i64)
+}
+
+/// First 16 bytes of the SHA-256 of `len` bytes at `offset`.
+fn fingerprint(file: &mut std::fs::File, offset: u64, len: u64) -> std::io::Result<[u8; 16]> {
+    let mut buf = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, &buf);
+    Ok(digest.as_ref()[..16].try_into().unwrap())
+}
+
+fn read_lines(path: &Path, lines: &[Checkpoint]) -> anyhow::Result<Vec<ClaudeMessage>> {
+    let (Some(first), Some(last)) = (lines.first(), lines.last()) else {
+        return Ok(Vec::new());
+    };
+    let mut file = std::fs::File::open(path)?;
+    let mut buf = vec![0u8; (last.offset + last.len as u64 - first.offset) as usize];
+    file.seek(SeekFrom::Start(first.offset))?;
+    file.read_exact(&mut buf)?;
+    let mut messages = Vec::with_capacity(lines.len());
+    for c in lines {
+        let start = (c.offset - first.offset) as usize;
+        let line = String::from_utf8_lossy(&buf[start..start + c.len as usize]);
+        messages.push(parse_line(line.trim())?);
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: u32, kind: &str, minute: u32) -> String {
+        serde_json::json!({
+            "type": kind,
+            "uuid": format!("00000000-0000-4000-8000-{n:012}"),
+            "timestamp": format!("2026-03-01T10:{minute:02}:00.000Z"),
+            "message": {"role": kind, "content": format!("message {n}")},
+        })
+        .to_string()
+            + "\n"
+    }
+
+    #[tokio::test]
+    async fn transcripts_are_locked_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("big.jsonl");
+        let small = dir.path().join("small.jsonl");
+
+        // A long refresh of one transcript holds only that transcript's lock.
+        let busy = slot(&big);
+        let _refreshing = busy.lock().await;
+        let other = slot(&small);
+        let free = tokio::time::timeout(std::time::Duration::from_secs(1), other.lock()).await;
+        assert!(free.is_ok());
+        assert!(Arc::ptr_eq(&busy, &slot(&big)));
+        assert!(!Arc::ptr_eq(&busy, &other));
+    }
+
+    #[tokio::test]
+    async fn pages_extends_and_rebuilds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.jsonl");
+        let stored = dir.path().join("big.lidx");
+
+        let mut body = String::new();
+        for n in 0..6 {
+            body.push_str(&line(n, if n % 2 == 0 { "user" } else { "assistant" }, n));
+        }
+        body.push_str("not json\n\n");
+        std::fs::write(&path, &body).unwrap();
+
+        let index = LineIndex::default().refresh(&path, &stored).unwrap();
+        assert_eq!(index.len(), 6, "malformed and blank lines are skipped");
+        assert_eq!(index.checkpoints()[1].kind, LineKind::Assistant);
+        let page = index.read_range(&path, 2..4).await.unwrap();
+        let all = crate::parser::parse_file(&path).await.unwrap();
+        assert_eq!(page[0].uuid, all[2].uuid);
+        assert_eq!(page[1].uuid, all[3].uuid);
+        let at = crate::parser::parse_iso_millis("2026-03-01T10:04:00Z").unwrap();
+        assert_eq!(index.position_at(at), 4);
+        assert_eq!(index.position_at(i64::MAX), 6);
+
+        // Appended lines are indexed from where the index stopped; a line
+        // still being written is left for later.
+        body.push_str(&line(6, "user", 6));
+        body.push_str("{\"type\":\"user\"");
+        std::fs::write(&path, &body).unwrap();
+        let stored_index = LineIndex::load(&stored).unwrap();
+        let index = stored_index.refresh(&path, &stored).unwrap();
+        assert_eq!(index.len(), 7);
+        assert_eq!(LineIndex::load(&stored).unwrap().len(), 7);
+        let page = index.read_range(&path, 6..10).await.unwrap();
+        assert_eq!(page.len(), 1);
+
+        // Same size, different content: rebuilt rather than trusted.
+        let rewritten = body.replace("message 5", "message X");
+        std::fs::write(&path, &rewritten).unwrap();
+        let index = LineIndex::load(&stored)
+            .unwrap()
+            .refresh(&path, &stored)
+            .unwrap();
+        let page = index.read_range(&path, 5..6).await.unwrap();
+        assert_eq!(page[0].content.as_text(), Some("message X"));
+
+        // Truncated: rebuilt from scratch.
+        std::fs::write(&path, line(9, "user", 9)).unwrap();
+        let index = LineIndex::load(&stored)
+            .unwrap()
+            .refresh(&path, &stored)
+            .unwrap();
+        assert_eq!(index.len(), 1);
+    }
+}
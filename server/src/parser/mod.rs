@@ -1,11 +1,23 @@
+pub mod aider;
 pub mod codex;
+pub mod drift;
 pub mod gemini;
 pub mod jsonl;
+pub mod line_index;
+pub mod opencode;
+mod path_state;
+pub mod registry;
+pub mod tools;
+pub mod tree;
 pub mod types;
 
+pub use aider::parse_aider_file;
 pub use codex::parse_codex_file;
 pub use gemini::parse_gemini_file;
 pub use jsonl::{parse_file, parse_incremental, parse_line, parse_tail};
+pub use opencode::parse_opencode_session;
+pub use registry::{CursorKind, InputMode, TranscriptSource};
+pub use tools::record_tool_calls;
 pub use types::{ClaudeMessage, ContentBlock, ImageSource, MessageContent};
 
 use uuid::Uuid;
@@ -86,6 +98,33 @@ pub fn message_to_component(msg: &ClaudeMessage, session_id: Uuid) -> Option<Mes
     })
 }
 
+/// Deterministic UUID for transcripts whose CLI has no UUIDs of its own
+/// (Aider messages, OpenCode `ses_…`/`msg_…` ids). Stable across re-parses.
+pub fn stable_uuid(namespace: &str, key: &str) -> Uuid {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA256,
+        format!("{namespace}\0{key}").as_bytes(),
+    );
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest.as_ref()[..16]);
+    uuid::Builder::from_sha1_bytes(bytes).into_uuid()
+}
+
+/// Format epoch milliseconds as `2026-02-21T10:00:00.000Z`.
+pub fn epoch_millis_to_iso(ms: i64) -> Option<String> {
+    let dt = time::OffsetDateTime::from_unix_timestamp_nanos(ms as i128 * 1_000_000).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        dt.year(),
+        dt.month() as u8,
+        dt.day(),
+        dt.hour(),
+        dt.minute(),
+        dt.second(),
+        dt.millisecond()
+    ))
+}
+
 /// Extract text content and determine message type from MessageContent.
 fn extract_content_and_type(content: &MessageContent, msg_type: &str) -> (String, MessageType) {
     match content {
@@ -177,6 +216,22 @@ fn parse_iso_timestamp(ts: &str) -> Option<i64> {
     Some(days * 86400 + hour * 3600 + minute * 60 + second)
 }
 
+/// Parse an ISO-8601 timestamp to Unix milliseconds (fraction truncated to ms).
+pub fn parse_iso_millis(ts: &str) -> Option<i64> {
+    let secs = parse_iso_timestamp(ts)?;
+    let frac = ts
+        .trim_end_matches('Z')
+        .split_once('.')
+        .map_or("", |(_, f)| f);
+    let digits: String = frac
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .chain("000".chars())
+        .take(3)
+        .collect();
+    Some(secs * 1000 + digits.parse::<i64>().unwrap_or(0))
+}
+
 fn is_leap_year(year: i64) -> bool {
     (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
 }
@@ -193,6 +248,25 @@ mod tests {
         assert!(ts < 2_000_000_000); // before 2033
     }
 
+    #[test]
+    fn epoch_millis_round_trip() {
+        let iso = epoch_millis_to_iso(1_740_823_202_500).unwrap();
+        assert_eq!(iso, "2025-03-01T10:00:02.500Z");
+        assert_eq!(parse_iso_timestamp(&iso), Some(1_740_823_202));
+        assert_eq!(parse_iso_millis(&iso), Some(1_740_823_202_500));
+        assert_eq!(
+            parse_iso_millis("2025-03-01T10:00:02Z"),
+            Some(1_740_823_202_000)
+        );
+    }
+
+    #[test]
+    fn stable_uuid_is_deterministic_and_namespaced() {
+        assert_eq!(stable_uuid("aider", "/a"), stable_uuid("aider", "/a"));
+        assert_ne!(stable_uuid("aider", "/a"), stable_uuid("opencode", "/a"));
+        assert_eq!(stable_uuid("aider", "/a").get_version_num(), 5);
+    }
+
     #[test]
     fn timestamp_without_millis() {
         let ts = parse_iso_timestamp("2026-02-21T10:30:00Z").unwrap();
@@ -0,0 +1,491 @@
+//! OpenCode session storage parser.
+//!
+//! OpenCode keeps one JSON document per entity under
+//! `~/.local/share/opencode/storage/`:
+//!
+//! - `session/{projectID}/{sessionID}.json` — `{ id, directory, title, time }`
+//! - `message/{sessionID}/{messageID}.json` — `{ id, role, time, modelID, cost, tokens }`
+//! - `part/{messageID}/{partID}.json` — `{ type: text|reasoning|tool|…, … }`
+//!
+//! The session document is the transcript path noaide tracks; messages and
+//! parts are read from the sibling directories. Tool parts become a
+//! `tool_use` on the assistant turn plus a `tool_result` user turn, the way
+//! Claude transcripts pair them. Assistant messages that have not completed
+//! yet are skipped so re-parses only ever append.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use super::drift::{self, DriftKind};
+use super::registry::TranscriptSource;
+use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+use super::{epoch_millis_to_iso, stable_uuid};
+use crate::discovery::scanner::{CliType, SessionInfo, SessionScanner, opencode_session_uuid};
+
+/// OpenCode: `~/.local/share/opencode/storage/session/{project}/{id}.json`.
+pub struct OpenCodeSource;
+
+#[async_trait::async_trait]
+impl TranscriptSource for OpenCodeSource {
+    fn cli_type(&self) -> CliType {
+        CliType::OpenCode
+    }
+
+    fn is_transcript_file(&self, path: &Path) -> bool {
+        self.owns_path(path) && path.extension().and_then(|e| e.to_str()) == Some("json")
+    }
+
+    fn owns_path(&self, path: &Path) -> bool {
+        let in_storage = storage_root(path)
+            .is_some_and(|root| root.file_name().and_then(|n| n.to_str()) == Some("storage"));
+        in_storage
+            && storage_location(path)
+                .is_some_and(|(kind, _)| matches!(kind, "session" | "message" | "part"))
+    }
+
+    fn session_id_from_path(&self, path: &Path) -> Option<String> {
+        // session/{project}/{ses}.json or message/{ses}/{msg}.json.
+        // Part files only carry the message id in their path.
+        let (kind, id) = storage_location(path)?;
+        match kind {
+            "session" => Some(opencode_session_uuid(path.file_stem()?.to_str()?)),
+            "message" => Some(opencode_session_uuid(id)),
+            _ => None,
+        }
+    }
+
+    fn transcript_path(&self, path: &Path) -> Option<PathBuf> {
+        let (kind, id) = storage_location(path)?;
+        match kind {
+            "session" => Some(path.to_path_buf()),
+            "message" => find_session_file(&storage_root(path)?, id),
+            _ => None,
+        }
+    }
+
+    fn project_path(&self, path: &Path) -> Option<PathBuf> {
+        let content = std::fs::read_to_string(path).ok()?;
+        let info: OpenCodeSessionInfo = serde_json::from_str(&content).ok()?;
+        info.directory.map(PathBuf::from)
+    }
+
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>> {
+        let session_dir = cli_dir.join("storage").join("session");
+        if !session_dir.exists() {
+            return Ok(Vec::new());
+        }
+        SessionScanner::scan_opencode_sessions(&session_dir).await
+    }
+
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+        parse_opencode_session(path).await
+    }
+
+    fn binary(&self) -> &'static str {
+        "opencode"
+    }
+
+    fn headless_args(&self, prompt: &str) -> Option<Vec<String>> {
+        Some(vec!["run".to_string(), prompt.to_string()])
+    }
+
+    fn proxy_env(&self, session_proxy_url: &str) -> Vec<(String, String)> {
+        // OpenCode reads provider base URLs from its config; inline config
+        // overrides them for this process only.
+        let config = serde_json::json!({
+            "provider": {
+                "anthropic": { "options": { "baseURL": format!("{session_proxy_url}/v1") } },
+                "openai": { "options": { "baseURL": session_proxy_url } },
+            }
+        });
+        vec![
+            ("OPENCODE_CONFIG_CONTENT".to_string(), config.to_string()),
+            ("OPENAI_BASE_URL".to_string(), session_proxy_url.to_string()),
+        ]
+    }
+}
+
+/// `session/{project}/{id}.json` etc. → `("session", "{project}")`.
+fn storage_location(path: &Path) -> Option<(&str, &str)> {
+    let parent = path.parent()?;
+    let id = parent.file_name()?.to_str()?;
+    let kind = parent.parent()?.file_name()?.to_str()?;
+    Some((kind, id))
+}
+
+/// The `storage/` dir containing a session/message/part file.
+fn storage_root(path: &Path) -> Option<PathBuf> {
+    path.parent()?.parent()?.parent().map(Path::to_path_buf)
+}
+
+/// Locate `storage/session/*/{session_id}.json`.
+fn find_session_file(storage: &Path, session_id: &str) -> Option<PathBuf> {
+    let file_name = format!("{session_id}.json");
+    std::fs::read_dir(storage.join("session"))
+        .ok()?
+        .flatten()
+        .map(|project| project.path().join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct OpenCodeSessionInfo {
+    pub id: String,
+    #[serde(default)]
+    pub directory: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub time: OpenCodeTime,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct OpenCodeTime {
+    #[serde(default)]
+    pub created: Option<i64>,
+    #[serde(default)]
+    pub updated: Option<i64>,
+    #[serde(default)]
+    pub completed: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenCodeMessage {
+    id: String,
+    role: String,
+    #[serde(default)]
+    time: OpenCodeTime,
+    #[serde(default, rename = "modelID")]
+    model_id: Option<String>,
+    #[serde(default)]
+    cost: Option<f64>,
+    #[serde(default)]
+    tokens: Option<OpenCodeTokens>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenCodeTokens {
+    #[serde(default)]
+    input: u64,
+    #[serde(default)]
+    output: u64,
+    #[serde(default)]
+    reasoning: u64,
+    #[serde(default)]
+    cache: OpenCodeCacheTokens,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenCodeCacheTokens {
+    #[serde(default)]
+    read: u64,
+    #[serde(default)]
+    write: u64,
+}
+
+/// Read every `*.json` in a directory, sorted by file name (OpenCode ids
+/// are time-ordered, so this is creation order).
+async fn read_json_dir<T: serde::de::DeserializeOwned>(dir: &Path) -> Vec<T> {
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut items = Vec::with_capacity(paths.len());
+    for path in paths {
+        let Ok(content) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        match serde_json::from_str(&content) {
+            Ok(item) => items.push(item),
+            Err(e) => warn!(path = %path.display(), error = %e, "opencode: malformed entry"),
+        }
+    }
+    items
+}
+
+/// Parse an OpenCode session (given its `session/{project}/{id}.json`).
+pub async fn parse_opencode_session(path: &Path) -> anyhow::Result<Vec<ClaudeMessage>> {
+    let info: OpenCodeSessionInfo = serde_json::from_str(&tokio::fs::read_to_string(path).await?)?;
+    let storage = storage_root(path)
+        .ok_or_else(|| anyhow::anyhow!("not an OpenCode storage path: {}", path.display()))?;
+
+    let mut messages = Vec::new();
+    let start = info.time.created.and_then(epoch_millis_to_iso);
+    let meta = serde_json::json!({
+        "type": "session_start",
+        "cli": "opencode",
+        "title": info.title,
+        "directory": info.directory,
+        "startTime": start,
+    });
+    messages.push(ClaudeMessage {
+        uuid: stable_uuid("opencode", &info.id).to_string(),
+        message_type: "progress".to_string(),
+        content: MessageContent::Text(meta.to_string()),
+        timestamp: start,
+        ..Default::default()
+    });
+
+    let mut oc_messages: Vec<OpenCodeMessage> =
+        read_json_dir(&storage.join("message").join(&info.id)).await;
+    oc_messages.sort_by(|a, b| (a.time.created, &a.id).cmp(&(b.time.created, &b.id)));
+
+    for msg in &oc_messages {
+        // Still streaming — wait for completion so earlier output never changes.
+        if msg.role == "assistant" && msg.time.completed.is_none() && msg.error.is_none() {
+            break;
+        }
+        let parts: Vec<serde_json::Value> =
+            read_json_dir(&storage.join("part").join(&msg.id)).await;
+        convert_message(msg, &parts, &mut messages);
+    }
+
+    Ok(messages)
+}
+
+/// Part types that carry no transcript content.
+const BOOKKEEPING_PARTS: [&str; 6] = [
+    "step-start",
+    "step-finish",
+    "snapshot",
+    "patch",
+    "agent",
+    "retry",
+];
+
+/// Convert one OpenCode message and its parts, appending to `out`.
+fn convert_message(
+    msg: &OpenCodeMessage,
+    parts: &[serde_json::Value],
+    out: &mut Vec<ClaudeMessage>,
+) {
+    let timestamp = msg.time.created.and_then(epoch_millis_to_iso);
+    let mut blocks = Vec::new();
+    let mut results = Vec::new();
+
+    for part in parts {
+        let str_field = |key: &str| part.get(key).and_then(|v| v.as_str()).unwrap_or("");
+        match str_field("type") {
+            "text" => {
+                let text = str_field("text");
+                if !text.is_empty() {
+                    blocks.push(ContentBlock::Text {
+                        text: text.to_string(),
+                    });
+                }
+            }
+            "reasoning" => blocks.push(ContentBlock::Thinking {
+                thinking: str_field("text").to_string(),
+            }),
+            "file" => blocks.push(ContentBlock::Text {
+                text: format!("[file: {}]", str_field("filename")),
+            }),
+            "tool" => {
+                let state = part.get("state").cloned().unwrap_or_default();
+                let call_id = str_field("callID").to_string();
+                blocks.push(ContentBlock::ToolUse {
+                    id: call_id.clone(),
+                    name: str_field("tool").to_string(),
+                    input: state.get("input").cloned().unwrap_or_default(),
+                });
+                let status = state.get("status").and_then(|v| v.as_str()).unwrap_or("");
+                if status == "completed" || status == "error" {
+                    let content = state
+                        .get("output")
+                        .or_else(|| state.get("error"))
+                        .cloned()
+                        .unwrap_or_default();
+                    results.push(ContentBlock::ToolResult {
+                        tool_use_id: call_id,
+                        content,
+                        is_error: (status == "error").then_some(true),
+                    });
+                }
+            }
+            // step-start / step-finish / snapshot / patch: bookkeeping only
+            other => {
+                if !BOOKKEEPING_PARTS.contains(&other) {
+                    drift::record("opencode", DriftKind::ContentBlock, "part", other, || {
+                        part.to_string()
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(error) = &msg.error {
+        let message = error
+            .pointer("/data/message")
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| {
+                error
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("error")
+            });
+        blocks.push(ContentBlock::Text {
+            text: format!("[error: {message}]"),
+        });
+    }
+
+    let is_assistant = msg.role == "assistant";
+    let tokens = msg.tokens.as_ref();
+    out.push(ClaudeMessage {
+        uuid: stable_uuid("opencode", &msg.id).to_string(),
+        message_type: if is_assistant { "assistant" } else { "user" }.to_string(),
+        role: Some(msg.role.clone()),
+        content: MessageContent::Blocks(blocks),
+        timestamp: timestamp.clone(),
+        model: msg.model_id.clone(),
+        cost_usd: msg.cost,
+        duration_ms: match (msg.time.created, msg.time.completed) {
+            (Some(start), Some(end)) if end >= start => Some((end - start) as u64),
+            _ => None,
+        },
+        input_tokens: tokens.map(|t| t.input),
+        output_tokens: tokens.map(|t| t.output + t.reasoning),
+        cache_read_input_tokens: tokens.map(|t| t.cache.read),
+        cache_creation_input_tokens: tokens.map(|t| t.cache.write),
+        ..Default::default()
+    });
+
+    if !results.is_empty() {
+        out.push(ClaudeMessage {
+            uuid: stable_uuid("opencode", &format!("{}:results", msg.id)).to_string(),
+            message_type: "user".to_string(),
+            role: Some("user".to_string()),
+            content: MessageContent::Blocks(results),
+            timestamp: msg
+                .time
+                .completed
+                .and_then(epoch_millis_to_iso)
+                .or(timestamp),
+            ..Default::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, rel: &str, value: serde_json::Value) {
+        let path = root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, value.to_string()).unwrap();
+    }
+
+    fn sample_storage() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let storage = dir.path().join("opencode").join("storage");
+        write(
+            &storage,
+            "session/prj_1/ses_1.json",
+            serde_json::json!({
+                "id": "ses_1", "directory": "/work/app", "title": "Fix tests",
+                "time": {"created": 1_740_823_200_000i64, "updated": 1_740_823_260_000i64}
+            }),
+        );
+        write(
+            &storage,
+            "message/ses_1/msg_1.json",
+            serde_json::json!({"id": "msg_1", "role": "user", "time": {"created": 1_740_823_201_000i64}}),
+        );
+        write(
+            &storage,
+            "part/msg_1/prt_1.json",
+            serde_json::json!({"id": "prt_1", "type": "text", "text": "run the tests"}),
+        );
+        write(
+            &storage,
+            "message/ses_1/msg_2.json",
+            serde_json::json!({
+                "id": "msg_2", "role": "assistant", "modelID": "claude-sonnet-4",
+                "time": {"created": 1_740_823_202_000i64, "completed": 1_740_823_210_000i64},
+                "cost": 0.012,
+                "tokens": {"input": 1200, "output": 80, "reasoning": 20, "cache": {"read": 900, "write": 0}}
+            }),
+        );
+        write(
+            &storage,
+            "part/msg_2/prt_2.json",
+            serde_json::json!({"id": "prt_2", "type": "reasoning", "text": "Use bun test"}),
+        );
+        write(
+            &storage,
+            "part/msg_2/prt_3.json",
+            serde_json::json!({
+                "id": "prt_3", "type": "tool", "tool": "bash", "callID": "call_1",
+                "state": {"status": "error", "input": {"command": "bun test"}, "error": "exit 1"}
+            }),
+        );
+        // Still streaming — must not be emitted yet.
+        write(
+            &storage,
+            "message/ses_1/msg_3.json",
+            serde_json::json!({"id": "msg_3", "role": "assistant", "time": {"created": 1_740_823_211_000i64}}),
+        );
+        let session = storage.join("session/prj_1/ses_1.json");
+        (dir, session)
+    }
+
+    #[tokio::test]
+    async fn parses_messages_parts_and_tool_results() {
+        let (_dir, session) = sample_storage();
+        let msgs = parse_opencode_session(&session).await.unwrap();
+        let types: Vec<&str> = msgs.iter().map(|m| m.message_type.as_str()).collect();
+        assert_eq!(types, vec!["progress", "user", "assistant", "user"]);
+
+        let reply = &msgs[2];
+        assert_eq!(reply.model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(reply.input_tokens, Some(1200));
+        assert_eq!(reply.output_tokens, Some(100));
+        assert_eq!(reply.cache_read_input_tokens, Some(900));
+        assert_eq!(reply.duration_ms, Some(8000));
+        assert_eq!(reply.timestamp.as_deref(), Some("2025-03-01T10:00:02.000Z"));
+
+        let MessageContent::Blocks(results) = &msgs[3].content else {
+            panic!("expected tool result blocks");
+        };
+        assert!(matches!(
+            &results[0],
+            ContentBlock::ToolResult { tool_use_id, is_error: Some(true), .. } if tool_use_id == "call_1"
+        ));
+    }
+
+    #[test]
+    fn paths_map_to_session_and_transcript() {
+        let (dir, session) = sample_storage();
+        let storage = dir.path().join("opencode").join("storage");
+        let message = storage.join("message/ses_1/msg_2.json");
+        let part = storage.join("part/msg_2/prt_2.json");
+        let source = OpenCodeSource;
+
+        let expected = opencode_session_uuid("ses_1");
+        assert_eq!(
+            source.session_id_from_path(&session),
+            Some(expected.clone())
+        );
+        assert_eq!(source.session_id_from_path(&message), Some(expected));
+        assert_eq!(source.session_id_from_path(&part), None);
+        assert_eq!(source.transcript_path(&message), Some(session.clone()));
+        assert_eq!(
+            source.project_path(&session),
+            Some(PathBuf::from("/work/app"))
+        );
+    }
+}
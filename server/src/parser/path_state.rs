@@ -0,0 +1,113 @@
+//! Per-transcript state that parsers keep between incremental parses.
+//!
+//! The state only saves work (a model lookup, a re-parse), so it is kept for
+//! the most recently parsed transcripts and the least recently used one is
+//! dropped once [`MAX_PATHS`] is reached.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+/// Transcripts remembered per parser.
+pub(crate) const MAX_PATHS: usize = 1024;
+
+struct Entry<V> {
+    used: u64,
+    value: V,
+}
+
+/// Parser state keyed by transcript path, bounded to `cap` paths.
+pub(crate) struct PathState<V> {
+    entries: DashMap<PathBuf, Entry<V>>,
+    clock: AtomicU64,
+    cap: usize,
+}
+
+impl<V> PathState<V> {
+    pub(crate) fn new(cap: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            clock: AtomicU64::new(0),
+            cap,
+        }
+    }
+
+    /// Look at the state of `path`, if any.
+    pub(crate) fn get<R>(&self, path: &Path, f: impl FnOnce(&V) -> R) -> Option<R> {
+        let mut entry = self.entries.get_mut(path)?;
+        entry.used = self.tick();
+        Some(f(&entry.value))
+    }
+
+    /// Change the state of `path`, starting from `V::default()`.
+    pub(crate) fn update<R>(&self, path: &Path, f: impl FnOnce(&mut V) -> R) -> R
+    where
+        V: Default,
+    {
+        let result = {
+            let mut entry = self
+                .entries
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Entry {
+                    used: 0,
+                    value: V::default(),
+                });
+            entry.used = self.tick();
+            f(&mut entry.value)
+        };
+        self.evict();
+        result
+    }
+
+    pub(crate) fn insert(&self, path: &Path, value: V) {
+        let used = self.tick();
+        self.entries
+            .insert(path.to_path_buf(), Entry { used, value });
+        self.evict();
+    }
+
+    pub(crate) fn remove(&self, path: &Path) {
+        self.entries.remove(path);
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn evict(&self) {
+        while self.entries.len() > self.cap {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|e| e.used)
+                .map(|e| e.key().clone())
+            else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_path_is_dropped() {
+        let state: PathState<u32> = PathState::new(2);
+        let (a, b, c) = (Path::new("/a"), Path::new("/b"), Path::new("/c"));
+        state.insert(a, 1);
+        state.update(b, |v| *v += 2);
+        // Reading `a` makes `b` the least recently used.
+        assert_eq!(state.get(a, |v| *v), Some(1));
+        state.insert(c, 3);
+
+        assert_eq!(state.get(a, |v| *v), Some(1));
+        assert_eq!(state.get(b, |v| *v), None);
+        assert_eq!(state.get(c, |v| *v), Some(3));
+        state.remove(c);
+        assert_eq!(state.get(c, |v| *v), None);
+    }
+}
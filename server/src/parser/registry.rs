@@ -0,0 +1,492 @@
+//! Transcript source registry — one entry per supported agent CLI.
+//!
+//! A [`TranscriptSource`] bundles everything noaide needs to know about a
+//! CLI: where its sessions live, how a session id is derived from a file
+//! name, how the transcript is parsed (fully and incrementally) into
+//! `ClaudeMessage`, and how the CLI is spawned and driven in a PTY.
+//!
+//! Adding a CLI means one parser module implementing the trait, a `CliType`
+//! variant and an entry in [`all`]. The conformance tests at the bottom of
+//! this file run against every registered source.
+
+use std::path::{Path, PathBuf};
+
+use crate::discovery::scanner::{CliType, SessionInfo};
+
+use super::aider::AiderSource;
+use super::codex::CodexSource;
+use super::gemini::GeminiSource;
+use super::jsonl::ClaudeSource;
+use super::opencode::OpenCodeSource;
+use super::types::ClaudeMessage;
+
+/// What the incremental-parse cursor of a source counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorKind {
+    /// Byte offset into an append-only file (only new lines are read).
+    ByteOffset,
+    /// Number of messages already emitted (the file is re-parsed). A source
+    /// may keep flags above the count; cursors still only grow until the
+    /// file is replaced.
+    MessageCount,
+}
+
+/// How text is written to the CLI's PTY when sending a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Write the text in one chunk, then submit with a separate `\r`.
+    Paste,
+    /// Write one character at a time — for TUIs that downgrade multi-char
+    /// writes to a bracketed paste where Enter no longer submits.
+    Keystrokes,
+}
+
+/// A CLI whose session transcripts noaide can discover, parse and drive.
+#[async_trait::async_trait]
+pub trait TranscriptSource: Send + Sync {
+    /// The CLI this source describes.
+    fn cli_type(&self) -> CliType;
+
+    /// Stable lowercase name used in APIs and logs ("claude", "codex", ...).
+    fn name(&self) -> &'static str {
+        self.cli_type().as_str()
+    }
+
+    /// Whether a changed file is (part of) a transcript the watcher should
+    /// parse, as opposed to a project file.
+    fn is_transcript_file(&self, path: &Path) -> bool;
+
+    /// Whether a transcript file path belongs to this CLI.
+    fn owns_path(&self, path: &Path) -> bool;
+
+    /// Extract the session UUID from a transcript path.
+    fn session_id_from_path(&self, path: &Path) -> Option<String>;
+
+    /// Map a changed file to the transcript path that is tracked and parsed.
+    ///
+    /// Identity for single-file transcripts; storage layouts that split a
+    /// session over many files resolve to their session document.
+    fn transcript_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+
+    /// Project directory a transcript belongs to, when the CLI records it
+    /// somewhere other than Claude's encoded directory name.
+    fn project_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Discover all sessions of this CLI below a CLI home dir (`~/.codex`, ...).
+    ///
+    /// Returns an empty list when the expected layout does not exist.
+    async fn discover(&self, cli_dir: &Path) -> anyhow::Result<Vec<SessionInfo>>;
+
+    /// Parse a complete transcript.
+    async fn parse_file(&self, path: &Path) -> anyhow::Result<Vec<ClaudeMessage>>;
+
+    /// What the cursor of [`parse_incremental`](Self::parse_incremental) counts.
+    fn cursor_kind(&self) -> CursorKind {
+        CursorKind::MessageCount
+    }
+
+    /// Parse only what is new since `cursor`, returning the new cursor.
+    ///
+    /// A returned cursor smaller than `cursor` means the transcript was
+    /// truncated or replaced: the messages are then the complete transcript
+    /// and callers must drop what they cached before appending them.
+    ///
+    /// The default re-parses the whole file and skips the first `cursor`
+    /// messages, which is correct for any format but costs a full parse.
+    async fn parse_incremental(
+        &self,
+        path: &Path,
+        cursor: u64,
+    ) -> anyhow::Result<(Vec<ClaudeMessage>, u64)> {
+        let all = self.parse_file(path).await?;
+        let total = all.len() as u64;
+        let new = if total < cursor {
+            all
+        } else {
+            all.into_iter().skip(cursor as usize).collect()
+        };
+        Ok((new, total))
+    }
+
+    /// Executable spawned for managed sessions.
+    fn binary(&self) -> &'static str;
+
+    /// Flag that disables permission prompts, if the CLI has one.
+    fn auto_approve_flag(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Extra environment for managed sessions, applied after the generic
+    /// per-session proxy variables (and overriding them).
+    ///
+    /// `session_proxy_url` is `{proxy}/s/{session-uuid}`.
+    fn proxy_env(&self, _session_proxy_url: &str) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// How `/send` writes text into the PTY.
+    fn input_mode(&self) -> InputMode {
+        InputMode::Paste
+    }
+
+    /// Arguments that make [`Self::binary`] continue the conversation
+    /// recorded in `transcript`. `None` if the CLI cannot resume it.
+    fn resume_args(&self, _transcript: &Path) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Arguments that make [`Self::binary`] run `prompt` without a human and
+    /// exit when done. `None` if the CLI has no non-interactive mode.
+    fn headless_args(&self, _prompt: &str) -> Option<Vec<String>> {
+        None
+    }
+}
+
+static CLAUDE: ClaudeSource = ClaudeSource;
+static CODEX: CodexSource = CodexSource;
+static GEMINI: GeminiSource = GeminiSource;
+static AIDER: AiderSource = AiderSource;
+static OPENCODE: OpenCodeSource = OpenCodeSource;
+
+/// All registered sources, in path-detection priority order.
+///
+/// Sources with distinctive layouts come before the extension-based
+/// fallbacks of Claude/Codex/Gemini.
+pub fn all() -> [&'static dyn TranscriptSource; 5] {
+    [&AIDER, &OPENCODE, &CLAUDE, &CODEX, &GEMINI]
+}
+
+/// The source for a CLI type.
+pub fn for_cli(cli_type: CliType) -> &'static dyn TranscriptSource {
+    match cli_type {
+        CliType::Claude => &CLAUDE,
+        CliType::Codex => &CODEX,
+        CliType::Gemini => &GEMINI,
+        CliType::Aider => &AIDER,
+        CliType::OpenCode => &OPENCODE,
+    }
+}
+
+/// Look up a source by its name (as accepted by the managed-session API).
+pub fn by_name(name: &str) -> Option<&'static dyn TranscriptSource> {
+    all().into_iter().find(|s| s.name() == name)
+}
+
+/// The source that owns a transcript path, if any.
+pub fn detect(path: &Path) -> Option<&'static dyn TranscriptSource> {
+    all().into_iter().find(|s| s.owns_path(path))
+}
+
+/// Whether any source treats a changed file as a transcript.
+pub fn is_transcript_file(path: &Path) -> bool {
+    all().into_iter().any(|s| s.is_transcript_file(path))
+}
+
+/// Like [`detect`], falling back to Claude for unrecognised paths.
+pub fn resolve(path: &Path) -> &'static dyn TranscriptSource {
+    detect(path).unwrap_or(&CLAUDE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::scanner::{aider_session_id, opencode_session_uuid};
+    use tempfile::TempDir;
+
+    /// A transcript before and after the CLI appended a turn.
+    struct Fixture {
+        /// Transcript path relative to the CLI home dir.
+        transcript: &'static str,
+        session_id: String,
+        /// Files (relative path, content) written initially.
+        before: Vec<(&'static str, String)>,
+        /// Files written (or overwritten) when the next turn lands.
+        after: Vec<(&'static str, String)>,
+    }
+
+    /// One fixture per CLI — the exhaustive match makes a new `CliType`
+    /// fail to compile until it ships conformance data.
+    fn fixture(cli_type: CliType, root: &Path) -> Fixture {
+        match cli_type {
+            CliType::Claude => {
+                let first = concat!(
+                    r#"{"type":"user","uuid":"11111111-1111-1111-1111-111111111111","timestamp":"2026-02-21T10:00:00Z","message":{"role":"user","content":"hello"}}"#,
+                    "\n",
+                    r#"{"type":"assistant","uuid":"22222222-2222-2222-2222-222222222222","timestamp":"2026-02-21T10:00:05Z","message":{"role":"assistant","model":"claude-opus-4-6","content":[{"type":"text","text":"hi"}]}}"#,
+                    "\n",
+                );
+                let more = concat!(
+                    r#"{"type":"user","uuid":"33333333-3333-3333-3333-333333333333","timestamp":"2026-02-21T10:01:00Z","message":{"role":"user","content":"again"}}"#,
+                    "\n",
+                );
+                let transcript = "projects/-work-demo/a1b2c3d4-e5f6-7890-abcd-ef1234567890.jsonl";
+                Fixture {
+                    transcript,
+                    session_id: "a1b2c3d4-e5f6-7890-abcd-ef1234567890".to_string(),
+                    before: vec![(transcript, first.to_string())],
+                    after: vec![(transcript, format!("{first}{more}"))],
+                }
+            }
+            CliType::Codex => {
+                let first = concat!(
+                    r#"{"timestamp":"2025-10-26T11:54:26Z","type":"session_meta","payload":{"id":"0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b","cwd":"/work/demo"}}"#,
+                    "\n",
+                    r#"{"timestamp":"2025-10-26T11:54:30Z","type":"event_msg","payload":{"type":"user_message","message":"hello","images":[]}}"#,
+                    "\n",
+                );
+                let more = concat!(
+                    r#"{"timestamp":"2025-10-26T11:55:00Z","type":"response_item","payload":{"type":"message","role":"assistant","content":[{"type":"output_text","text":"Hello!"}]}}"#,
+                    "\n",
+                );
+                let transcript = "sessions/2025/10/26/rollout-2025-10-26T12-54-26-0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b.jsonl";
+                Fixture {
+                    transcript,
+                    session_id: "0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b".to_string(),
+                    before: vec![(transcript, first.to_string())],
+                    after: vec![(transcript, format!("{first}{more}"))],
+                }
+            }
+            CliType::Gemini => {
+                let user = serde_json::json!({
+                    "id": "m1", "timestamp": "2025-11-19T06:17:08Z",
+                    "type": "user", "content": [{"text": "hello"}]
+                });
+                let reply = serde_json::json!({
+                    "id": "m2", "timestamp": "2025-11-19T06:17:10Z",
+                    "type": "gemini", "content": "hi", "model": "gemini-2.5-pro"
+                });
+                let session = |messages: Vec<serde_json::Value>| {
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "sessionId": "3f2a9c1e-0000-0000-0000-000000000000",
+                        "projectHash": "abc",
+                        "startTime": "2025-11-19T06:17:00Z",
+                        "lastUpdated": "2025-11-19T06:17:10Z",
+                        "messages": messages,
+                    }))
+                    .unwrap()
+                };
+                let transcript = "tmp/demo/chats/session-2025-11-19T06-14-3f2a9c1e.json";
+                Fixture {
+                    transcript,
+                    session_id: "3f2a9c1e-0000-0000-0000-000000000000".to_string(),
+                    before: vec![(transcript, session(vec![user.clone()]))],
+                    after: vec![(transcript, session(vec![user, reply]))],
+                }
+            }
+            CliType::Aider => {
+                let first =
+                    "# aider chat started at 2025-03-01 10:00:00\n\n#### hello\n\nHi there.\n";
+                let more = "\n#### add a test\n\nDone.\n";
+                let transcript = "work/app/.aider.chat.history.md";
+                Fixture {
+                    transcript,
+                    session_id: aider_session_id(&root.join(transcript)),
+                    before: vec![(transcript, first.to_string())],
+                    after: vec![(transcript, format!("{first}{more}"))],
+                }
+            }
+            CliType::OpenCode => {
+                let json = |v: serde_json::Value| v.to_string();
+                let transcript = "storage/session/prj_1/ses_1.json";
+                Fixture {
+                    transcript,
+                    session_id: opencode_session_uuid("ses_1"),
+                    before: vec![
+                        (
+                            transcript,
+                            json(serde_json::json!({
+                                "id": "ses_1", "directory": "/work/app",
+                                "time": {"created": 1_740_823_200_000i64, "updated": 1_740_823_200_000i64}
+                            })),
+                        ),
+                        (
+                            "storage/message/ses_1/msg_1.json",
+                            json(
+                                serde_json::json!({"id": "msg_1", "role": "user", "time": {"created": 1_740_823_201_000i64}}),
+                            ),
+                        ),
+                        (
+                            "storage/part/msg_1/prt_1.json",
+                            json(
+                                serde_json::json!({"id": "prt_1", "type": "text", "text": "hello"}),
+                            ),
+                        ),
+                    ],
+                    after: vec![
+                        (
+                            "storage/message/ses_1/msg_2.json",
+                            json(serde_json::json!({
+                                "id": "msg_2", "role": "assistant", "modelID": "gpt-5",
+                                "time": {"created": 1_740_823_202_000i64, "completed": 1_740_823_203_000i64}
+                            })),
+                        ),
+                        (
+                            "storage/part/msg_2/prt_2.json",
+                            json(serde_json::json!({"id": "prt_2", "type": "text", "text": "hi"})),
+                        ),
+                    ],
+                }
+            }
+        }
+    }
+
+    fn write_files(dir: &Path, files: &[(&'static str, String)]) {
+        for (rel, content) in files {
+            let path = dir.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn registry_names_and_types_round_trip() {
+        for source in all() {
+            assert_eq!(for_cli(source.cli_type()).name(), source.name());
+            assert_eq!(
+                by_name(source.name()).unwrap().cli_type(),
+                source.cli_type()
+            );
+            assert!(!source.binary().is_empty());
+        }
+        assert!(by_name("nonexistent").is_none());
+    }
+
+    #[test]
+    fn unknown_paths_fall_back_to_claude() {
+        let path = Path::new("/tmp/somewhere/notes.txt");
+        assert!(detect(path).is_none());
+        assert!(!is_transcript_file(path));
+        assert_eq!(resolve(path).cli_type(), CliType::Claude);
+    }
+
+    #[tokio::test]
+    async fn conformance_discovery_and_path_detection() {
+        for source in all() {
+            let dir = TempDir::new().unwrap();
+            let fx = fixture(source.cli_type(), dir.path());
+            write_files(dir.path(), &fx.before);
+            let path = dir.path().join(fx.transcript);
+
+            let found = source.discover(dir.path()).await.unwrap();
+            assert_eq!(found.len(), 1, "{}: discover", source.name());
+            assert_eq!(found[0].id, fx.session_id, "{}: id", source.name());
+            assert_eq!(found[0].cli_type, source.cli_type());
+            assert_eq!(found[0].jsonl_path, path);
+
+            assert!(
+                is_transcript_file(&path),
+                "{}: transcript file",
+                source.name()
+            );
+            assert_eq!(
+                resolve(&path).cli_type(),
+                source.cli_type(),
+                "{}: path detection",
+                source.name()
+            );
+            assert_eq!(
+                source.session_id_from_path(&path).as_deref(),
+                Some(fx.session_id.as_str()),
+                "{}: session id from path",
+                source.name()
+            );
+            assert_eq!(source.transcript_path(&path), Some(path.clone()));
+
+            // Other sources must not pick up this layout.
+            for other in all() {
+                if other.cli_type() != source.cli_type() {
+                    assert!(
+                        other.discover(dir.path()).await.unwrap().is_empty(),
+                        "{} discovered a {} session",
+                        other.name(),
+                        source.name()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resume_args_name_the_recorded_session() {
+        for source in all() {
+            let dir = TempDir::new().unwrap();
+            let fx = fixture(source.cli_type(), dir.path());
+            write_files(dir.path(), &fx.before);
+            let args = source.resume_args(&dir.path().join(fx.transcript));
+            let expected: Option<&[&str]> = match source.cli_type() {
+                CliType::Claude => Some(&["--resume", "a1b2c3d4-e5f6-7890-abcd-ef1234567890"]),
+                CliType::Codex => Some(&["resume", "0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b"]),
+                CliType::Gemini => Some(&["--resume", "3f2a9c1e-0000-0000-0000-000000000000"]),
+                CliType::Aider => Some(&["--restore-chat-history"]),
+                CliType::OpenCode => None,
+            };
+            assert_eq!(
+                args.as_deref(),
+                expected
+                    .map(|a| a.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+                    .as_deref(),
+                "{}: resume args",
+                source.name()
+            );
+        }
+    }
+
+    #[test]
+    fn headless_args_carry_the_prompt() {
+        for source in all() {
+            let args = source.headless_args("fix the build").unwrap();
+            assert_eq!(
+                args.last().map(String::as_str),
+                Some("fix the build"),
+                "{}",
+                source.name()
+            );
+        }
+        assert_eq!(
+            by_name("claude").unwrap().headless_args("hi"),
+            Some(vec!["-p".to_string(), "hi".to_string()])
+        );
+        assert_eq!(
+            by_name("codex").unwrap().headless_args("hi").unwrap()[0],
+            "exec"
+        );
+    }
+
+    #[tokio::test]
+    async fn conformance_full_and_incremental_parse_agree() {
+        for source in all() {
+            let dir = TempDir::new().unwrap();
+            let fx = fixture(source.cli_type(), dir.path());
+            write_files(dir.path(), &fx.before);
+            let path = dir.path().join(fx.transcript);
+
+            let full = source.parse_file(&path).await.unwrap();
+            assert!(!full.is_empty(), "{}: full parse", source.name());
+            assert!(full.iter().all(|m| !m.message_type.is_empty()));
+            assert!(full.iter().all(|m| !m.uuid.is_empty()));
+
+            let (first, cursor) = source.parse_incremental(&path, 0).await.unwrap();
+            assert_eq!(first.len(), full.len(), "{}: first pass", source.name());
+
+            let (none, same) = source.parse_incremental(&path, cursor).await.unwrap();
+            assert!(none.is_empty(), "{}: unchanged file", source.name());
+            assert_eq!(same, cursor);
+
+            write_files(dir.path(), &fx.after);
+            let full_after = source.parse_file(&path).await.unwrap();
+            let (new, next) = source.parse_incremental(&path, cursor).await.unwrap();
+            assert_eq!(
+                new.len(),
+                full_after.len() - full.len(),
+                "{}: appended turn",
+                source.name()
+            );
+            assert!(!new.is_empty());
+            assert_ne!(next, cursor);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Tool invocation extraction — pairs `ToolUse` blocks with their
+//! `ToolResult` by `tool_use_id` and records them in the ECS.
+//!
+//! Every transcript source emits tool calls as `ContentBlock::ToolUse` in an
+//! assistant message and the outcome as `ContentBlock::ToolResult` in a later
+//! user message, so the pairing is format-independent.
+
+use uuid::Uuid;
+
+use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+use crate::ecs::components::{ToolInvocationComponent, ToolKind};
+use crate::ecs::world::EcsWorld;
+
+/// String values in a stored tool input are cut to this many bytes
+/// (`Write` contents, patches) to keep invocations cheap to retain.
+const MAX_INPUT_STRING: usize = 2048;
+
+/// Input keys that name a single file across the supported CLIs.
+const FILE_KEYS: [&str; 5] = [
+    "file_path",
+    "filePath",
+    "notebook_path",
+    "absolute_path",
+    "path",
+];
+
+/// Record the tool calls and results contained in `msg`.
+pub fn record_tool_calls(world: &mut EcsWorld, session_id: Uuid, msg: &ClaudeMessage) {
+    let MessageContent::Blocks(blocks) = &msg.content else {
+        return;
+    };
+    let ts_ms = msg
+        .timestamp
+        .as_deref()
+        .and_then(super::parse_iso_millis)
+        .unwrap_or(0);
+
+    for block in blocks {
+        match block {
+            ContentBlock::ToolUse { id, name, input } => {
+                world.upsert_tool_invocation(ToolInvocationComponent {
+                    session_id,
+                    tool_use_id: id.clone(),
+                    message_id: msg.uuid.parse().unwrap_or_else(|_| Uuid::new_v4()),
+                    name: name.clone(),
+                    kind: ToolKind::from_tool_name(name),
+                    input: truncate_strings(input.clone()),
+                    files: files_touched(input),
+                    started_at_ms: ts_ms,
+                    ended_at_ms: None,
+                    duration_ms: None,
+                    output_size: None,
+                    is_error: false,
+                });
+            }
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => {
+                let _ = world.complete_tool_invocation(
+                    session_id,
+                    tool_use_id,
+                    ts_ms,
+                    output_size(content),
+                    is_error.unwrap_or(false),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Paths named in a tool input: the usual file keys, `paths` arrays and
+/// `*** Update File:` headers of `apply_patch` patches.
+pub fn files_touched(input: &serde_json::Value) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut push = |path: &str| {
+        if !path.is_empty() && !files.iter().any(|f| f == path) {
+            files.push(path.to_string());
+        }
+    };
+
+    if let Some(obj) = input.as_object() {
+        for key in FILE_KEYS {
+            if let Some(path) = obj.get(key).and_then(|v| v.as_str()) {
+                push(path);
+            }
+        }
+        for key in ["paths", "file_paths"] {
+            for path in obj
+                .get(key)
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(path) = path.as_str() {
+                    push(path);
+                }
+            }
+        }
+    }
+
+    for text in string_leaves(input) {
+        if !text.contains("*** Begin Patch") {
+            continue;
+        }
+        for line in text.lines() {
+            if let Some(path) = line
+                .strip_prefix("*** Update File: ")
+                .or_else(|| line.strip_prefix("*** Add File: "))
+                .or_else(|| line.strip_prefix("*** Delete File: "))
+                .or_else(|| line.strip_prefix("*** Move to: "))
+            {
+                push(path.trim());
+            }
+        }
+    }
+    files
+}
+
+/// Top-level string values of an input (the value itself, object fields,
+/// array elements such as Codex's `["apply_patch", "<patch>"]`).
+fn string_leaves(input: &serde_json::Value) -> Vec<&str> {
+    match input {
+        serde_json::Value::String(s) => vec![s.as_str()],
+        serde_json::Value::Array(items) => items.iter().filter_map(|v| v.as_str()).collect(),
+        serde_json::Value::Object(obj) => obj
+            .values()
+            .flat_map(|v| -> Vec<&str> {
+                match v {
+                    serde_json::Value::Array(items) => {
+                        items.iter().filter_map(|v| v.as_str()).collect()
+                    }
+                    other => other.as_str().into_iter().collect(),
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Byte size of a tool result: text length for strings and text blocks,
+/// serialized length otherwise.
+fn output_size(content: &serde_json::Value) -> u64 {
+    match content {
+        serde_json::Value::String(s) => s.len() as u64,
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| match item.get("text").and_then(|t| t.as_str()) {
+                Some(text) => text.len() as u64,
+                None => item.to_string().len() as u64,
+            })
+            .sum(),
+        serde_json::Value::Null => 0,
+        other => other.to_string().len() as u64,
+    }
+}
+
+fn truncate_strings(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) if s.len() > MAX_INPUT_STRING => {
+            let cut = (0..=MAX_INPUT_STRING)
+                .rev()
+                .find(|&i| s.is_char_boundary(i))
+                .unwrap_or(0);
+            serde_json::Value::String(format!("{}…", &s[..cut]))
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(truncate_strings).collect())
+        }
+        serde_json::Value::Object(obj) => serde_json::Value::Object(
+            obj.into_iter()
+                .map(|(k, v)| (k, truncate_strings(v)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(uuid: &str, ts: &str, blocks: Vec<ContentBlock>) -> ClaudeMessage {
+        ClaudeMessage {
+            uuid: uuid.to_string(),
+            message_type: "assistant".to_string(),
+            content: MessageContent::Blocks(blocks),
+            timestamp: Some(ts.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pairs_tool_use_with_result() {
+        let mut world = EcsWorld::new();
+        let sid = Uuid::new_v4();
+        let call = message(
+            "6f1c2b7e-0a4d-4c8e-9f3a-1b2c3d4e5f60",
+            "2026-02-21T10:00:00.250Z",
+            vec![ContentBlock::ToolUse {
+                id: "toolu_1".to_string(),
+                name: "Edit".to_string(),
+                input: serde_json::json!({
+                    "file_path": "/work/src/main.rs",
+                    "old_string": "a",
+                    "new_string": "x".repeat(5000),
+                }),
+            }],
+        );
+        let result = message(
+            "7a2d3c8f-1b5e-4d9f-8a4b-2c3d4e5f6071",
+            "2026-02-21T10:00:01.750Z",
+            vec![ContentBlock::ToolResult {
+                tool_use_id: "toolu_1".to_string(),
+                content: serde_json::json!([{"type": "text", "text": "ok"}]),
+                is_error: Some(true),
+            }],
+        );
+        record_tool_calls(&mut world, sid, &call);
+        record_tool_calls(&mut world, sid, &result);
+
+        let invs = world.query_tool_invocations_by_session(sid);
+        assert_eq!(invs.len(), 1);
+        let inv = &invs[0];
+        assert_eq!(inv.kind, ToolKind::Edit);
+        assert_eq!(inv.files, vec!["/work/src/main.rs"]);
+        assert_eq!(inv.duration_ms, Some(1500));
+        assert_eq!(inv.output_size, Some(2));
+        assert!(inv.is_error);
+        assert!(inv.input["new_string"].as_str().unwrap().len() < 2100);
+    }
+
+    #[test]
+    fn patch_headers_count_as_files() {
+        let input = serde_json::json!({
+            "command": ["apply_patch", "*** Begin Patch\n*** Update File: src/a.rs\n@@\n-a\n+b\n*** Add File: src/b.rs\n+new\n*** End Patch"],
+        });
+        assert_eq!(files_touched(&input), vec!["src/a.rs", "src/b.rs"]);
+    }
+}
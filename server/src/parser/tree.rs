@@ -0,0 +1,752 @@
+//! Conversation tree reconstruction.
+//!
+//! Claude Code transcripts are append-only, but the conversation they record
+//! is a tree: every entry names its predecessor via `parentUuid`. Editing a
+//! prompt or rewinding starts a new branch from an earlier entry, compaction
+//! starts a fresh chain linked back through `logicalParentUuid`, a resumed
+//! session can continue a chain that lives in an earlier file, and subagents
+//! run in sidechains (inline, or under `<session>/subagents/`) that hang off
+//! the `Task` call that spawned them.
+//!
+//! The builder works on raw JSONL values because `ClaudeMessage` drops the
+//! fields needed for linking (`logicalParentUuid`, `toolUseResult`). Other
+//! transcript sources have no parent pointers and yield a single chain.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::types::{ClaudeMessage, ContentBlock, MessageContent};
+
+/// Characters of message text kept as a node preview.
+const PREVIEW_CHARS: usize = 120;
+
+/// Tool names that spawn a subagent sidechain.
+const AGENT_TOOLS: [&str; 2] = ["Task", "Agent"];
+
+/// How a node is attached to its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EdgeKind {
+    /// No parent — the start of the transcript.
+    Root,
+    /// Regular `parentUuid` link.
+    Parent,
+    /// Compaction boundary linked through `logicalParentUuid`.
+    Compaction,
+    /// Root of a subagent thread, attached under the spawning `Task` call.
+    Sidechain,
+    /// `parentUuid` points at an entry outside this transcript — the
+    /// session was resumed from an earlier file.
+    Resume,
+}
+
+/// One transcript entry in the tree.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    pub uuid: String,
+    /// Resolved parent (`None` for roots and dangling resume links).
+    pub parent: Option<String>,
+    pub edge: EdgeKind,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    pub role: Option<String>,
+    pub timestamp: Option<String>,
+    pub is_sidechain: bool,
+    pub agent_id: Option<String>,
+    /// `tool_use_id` of the `Task` call a sidechain root was spawned by.
+    pub spawned_by: Option<String>,
+    /// On the path to the current leaf (or in a sidechain spawned from it).
+    pub live: bool,
+    pub children: Vec<String>,
+    pub preview: String,
+}
+
+/// The reconstructed conversation DAG. Nodes are in transcript order.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationTree {
+    pub nodes: Vec<TreeNode>,
+    pub roots: Vec<String>,
+    /// Last main-thread entry; the live branch is its ancestor chain.
+    pub leaf: Option<String>,
+    /// Nodes with more than one conversational child (edits, rewinds).
+    pub branch_points: Vec<String>,
+    /// Dangling parent of a resumed transcript's first entry.
+    pub continues_from: Option<String>,
+}
+
+impl ConversationTree {
+    pub fn live_count(&self) -> usize {
+        self.nodes.iter().filter(|n| n.live).count()
+    }
+
+    pub fn abandoned_count(&self) -> usize {
+        self.nodes.len() - self.live_count()
+    }
+
+    pub fn node(&self, uuid: &str) -> Option<&TreeNode> {
+        self.nodes.iter().find(|n| n.uuid == uuid)
+    }
+}
+
+/// The linking-relevant subset of a transcript entry.
+#[derive(Debug, Clone, Default)]
+struct TreeEntry {
+    uuid: String,
+    parent_uuid: Option<String>,
+    logical_parent_uuid: Option<String>,
+    entry_type: String,
+    role: Option<String>,
+    timestamp: Option<String>,
+    is_sidechain: bool,
+    agent_id: Option<String>,
+    /// `(tool_use_id, prompt)` of agent-spawning tool calls in this entry.
+    task_calls: Vec<(String, String)>,
+    /// `(tool_use_id, agent_id)` when this entry reports which agent a
+    /// `Task` call started (tool result or agent progress).
+    spawned_agent: Option<(String, String)>,
+    text: String,
+}
+
+impl TreeEntry {
+    fn from_value(value: &serde_json::Value) -> Option<Self> {
+        let str_field = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let uuid = str_field("uuid").filter(|u| !u.is_empty())?;
+        let base_type = str_field("type").unwrap_or_default();
+        let entry_type = match (
+            base_type.as_str(),
+            value.get("subtype").and_then(|v| v.as_str()),
+        ) {
+            ("system", Some(subtype)) => subtype.to_string(),
+            _ => base_type,
+        };
+
+        let message = value.get("message");
+        let content = message.and_then(|m| m.get("content"));
+        let mut entry = TreeEntry {
+            uuid,
+            parent_uuid: str_field("parentUuid"),
+            logical_parent_uuid: str_field("logicalParentUuid"),
+            role: message
+                .and_then(|m| m.get("role"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            timestamp: str_field("timestamp"),
+            is_sidechain: value
+                .get("isSidechain")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            agent_id: str_field("agentId"),
+            text: content.map(content_text).unwrap_or_default(),
+            entry_type,
+            ..Default::default()
+        };
+
+        for block in content.and_then(|c| c.as_array()).into_iter().flatten() {
+            match block.get("type").and_then(|t| t.as_str()) {
+                Some("tool_use")
+                    if block
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .is_some_and(|n| AGENT_TOOLS.contains(&n)) =>
+                {
+                    let id = block.get("id").and_then(|v| v.as_str()).unwrap_or("");
+                    let prompt = block
+                        .pointer("/input/prompt")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    entry.task_calls.push((id.to_string(), prompt.to_string()));
+                }
+                Some("tool_result") => {
+                    if let Some(id) = block.get("tool_use_id").and_then(|v| v.as_str())
+                        && let Some(agent) = value
+                            .pointer("/toolUseResult/agentId")
+                            .and_then(|v| v.as_str())
+                    {
+                        entry.spawned_agent = Some((id.to_string(), agent.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Agent progress entries name the agent before the Task call returns.
+        if entry.spawned_agent.is_none()
+            && let Some(tool_use_id) = value
+                .get("parentToolUseID")
+                .or_else(|| value.get("toolUseID"))
+                .and_then(|v| v.as_str())
+            && let Some(agent) = value.pointer("/data/agentId").and_then(|v| v.as_str())
+        {
+            entry.spawned_agent = Some((tool_use_id.to_string(), agent.to_string()));
+        }
+
+        Some(entry)
+    }
+
+    fn from_message(msg: &ClaudeMessage) -> Self {
+        let text = match &msg.content {
+            MessageContent::Text(s) => s.clone(),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .find_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        };
+        TreeEntry {
+            uuid: msg.uuid.clone(),
+            parent_uuid: msg.parent_uuid.clone(),
+            entry_type: msg.message_type.clone(),
+            role: msg.role.clone(),
+            timestamp: msg.timestamp.clone(),
+            is_sidechain: msg.is_sidechain.unwrap_or(false),
+            agent_id: msg.agent_id.clone(),
+            text,
+            ..Default::default()
+        }
+    }
+
+    /// User/assistant/system turns — progress and bookkeeping entries hang
+    /// off the chain without forming branches of their own.
+    fn is_conversational(&self) -> bool {
+        matches!(
+            self.entry_type.as_str(),
+            "user" | "assistant" | "system" | "compact_boundary" | "summary"
+        )
+    }
+}
+
+/// First text of a `message.content` value (string or block array).
+fn content_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .find_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+fn preview(text: &str) -> String {
+    let trimmed = text.trim();
+    match trimmed.char_indices().nth(PREVIEW_CHARS) {
+        Some((cut, _)) => format!("{}…", &trimmed[..cut]),
+        None => trimmed.to_string(),
+    }
+}
+
+/// Collects transcript entries and links them into a [`ConversationTree`].
+#[derive(Debug, Default)]
+pub struct TreeBuilder {
+    entries: Vec<TreeEntry>,
+    seen: HashSet<String>,
+    /// Chain entries without a parent to the previous entry (sources
+    /// without `parentUuid`).
+    chain_orphans: bool,
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one raw Claude JSONL line. Entries without a `uuid` (summaries,
+    /// file-history snapshots) and malformed lines are ignored.
+    pub fn push_line(&mut self, line: &str) {
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+            return;
+        };
+        if let Some(entry) = TreeEntry::from_value(&value) {
+            self.push(entry);
+        }
+    }
+
+    /// Add a subagent transcript (`subagents/agent-<id>.jsonl`). Its entries
+    /// are sidechain entries of `agent_id` even if the lines omit the flags.
+    pub fn push_sidechain(&mut self, agent_id: &str, content: &str) {
+        for line in content.lines() {
+            let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+                continue;
+            };
+            if let Some(mut entry) = TreeEntry::from_value(&value) {
+                entry.is_sidechain = true;
+                entry.agent_id.get_or_insert_with(|| agent_id.to_string());
+                self.push(entry);
+            }
+        }
+    }
+
+    /// Add a parsed message from a source without parent pointers; such
+    /// messages are chained in transcript order.
+    pub fn push_message(&mut self, msg: &ClaudeMessage) {
+        if msg.uuid.is_empty() {
+            return;
+        }
+        self.chain_orphans = true;
+        self.push(TreeEntry::from_message(msg));
+    }
+
+    fn push(&mut self, entry: TreeEntry) {
+        // Resumed transcripts may repeat entries of the file they continue.
+        if self.seen.insert(entry.uuid.clone()) {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn build(self) -> ConversationTree {
+        let entries = self.entries;
+        let index: HashMap<&str, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.uuid.as_str(), i))
+            .collect();
+
+        // tool_use_id → index of the entry holding the Task call.
+        let mut task_owner: HashMap<&str, usize> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            for (id, _) in &entry.task_calls {
+                task_owner.insert(id.as_str(), i);
+            }
+        }
+        // agent_id → spawning tool_use_id.
+        let agent_tool: HashMap<&str, &str> = entries
+            .iter()
+            .filter_map(|e| e.spawned_agent.as_ref())
+            .map(|(tool, agent)| (agent.as_str(), tool.as_str()))
+            .collect();
+
+        // Parents always precede children in the transcript; only earlier
+        // entries are accepted as parents, which rules out cycles.
+        let mut parents: Vec<Option<usize>> = vec![None; entries.len()];
+        let mut edges = vec![EdgeKind::Root; entries.len()];
+        let mut spawned_by: Vec<Option<String>> = vec![None; entries.len()];
+        let mut continues_from = None;
+        let mut last_main: Option<usize> = None;
+
+        for (i, entry) in entries.iter().enumerate() {
+            let lookup = |uuid: &Option<String>| {
+                uuid.as_deref()
+                    .and_then(|u| index.get(u).copied())
+                    .filter(|&p| p < i)
+            };
+
+            if let Some(p) = lookup(&entry.parent_uuid) {
+                parents[i] = Some(p);
+                edges[i] = EdgeKind::Parent;
+            } else if let Some(parent) = &entry.parent_uuid
+                && !index.contains_key(parent.as_str())
+            {
+                edges[i] = EdgeKind::Resume;
+                continues_from.get_or_insert_with(|| entry.parent_uuid.clone().unwrap_or_default());
+            } else if let Some(p) = lookup(&entry.logical_parent_uuid) {
+                parents[i] = Some(p);
+                edges[i] = EdgeKind::Compaction;
+            } else if entry.is_sidechain {
+                let tool = entry
+                    .agent_id
+                    .as_deref()
+                    .and_then(|a| agent_tool.get(a).copied())
+                    .map(str::to_string)
+                    .or_else(|| prompt_match(&entries, entry));
+                if let Some(tool) = tool
+                    && let Some(&owner) = task_owner.get(tool.as_str())
+                {
+                    parents[i] = Some(owner);
+                    edges[i] = EdgeKind::Sidechain;
+                    spawned_by[i] = Some(tool);
+                }
+            } else if self.chain_orphans
+                && let Some(prev) = last_main
+            {
+                parents[i] = Some(prev);
+                edges[i] = EdgeKind::Parent;
+            }
+
+            if !entry.is_sidechain {
+                last_main = Some(i);
+            }
+        }
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(p) = parent {
+                children[*p].push(i);
+            }
+        }
+
+        // Live branch: ancestors of the last main-thread entry. Sidechains
+        // are live along their own latest path when spawned from a live node.
+        let mut live = vec![false; entries.len()];
+        let mark_path = |live: &mut Vec<bool>, from: usize| {
+            let mut cur = Some(from);
+            while let Some(c) = cur {
+                if live[c] {
+                    break;
+                }
+                live[c] = true;
+                cur = parents[c];
+            }
+        };
+        if let Some(leaf) = last_main {
+            mark_path(&mut live, leaf);
+        }
+        let mut agent_leaf: HashMap<&str, usize> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.is_sidechain {
+                agent_leaf.insert(entry.agent_id.as_deref().unwrap_or(""), i);
+            }
+        }
+        for &leaf in agent_leaf.values() {
+            let mut root = leaf;
+            while edges[root] != EdgeKind::Sidechain
+                && let Some(p) = parents[root]
+            {
+                root = p;
+            }
+            if edges[root] == EdgeKind::Sidechain && parents[root].is_some_and(|p| live[p]) {
+                mark_path(&mut live, leaf);
+            }
+        }
+        // Non-conversational entries follow the liveness of their parent.
+        for i in 0..entries.len() {
+            if !entries[i].is_conversational()
+                && let Some(p) = parents[i]
+            {
+                live[i] = live[p];
+            }
+        }
+
+        let branch_points = (0..entries.len())
+            .filter(|&i| {
+                children[i]
+                    .iter()
+                    .filter(|&&c| edges[c] == EdgeKind::Parent && entries[c].is_conversational())
+                    .count()
+                    > 1
+            })
+            .map(|i| entries[i].uuid.clone())
+            .collect();
+
+        let roots = (0..entries.len())
+            .filter(|&i| parents[i].is_none())
+            .map(|i| entries[i].uuid.clone())
+            .collect();
+
+        let nodes = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| TreeNode {
+                uuid: e.uuid.clone(),
+                parent: parents[i].map(|p| entries[p].uuid.clone()),
+                edge: edges[i],
+                entry_type: e.entry_type.clone(),
+                role: e.role.clone(),
+                timestamp: e.timestamp.clone(),
+                is_sidechain: e.is_sidechain,
+                agent_id: e.agent_id.clone(),
+                spawned_by: spawned_by[i].clone(),
+                live: live[i],
+                children: children[i]
+                    .iter()
+                    .map(|&c| entries[c].uuid.clone())
+                    .collect(),
+                preview: preview(&e.text),
+            })
+            .collect();
+
+        ConversationTree {
+            nodes,
+            roots,
+            leaf: last_main.map(|i| entries[i].uuid.clone()),
+            branch_points,
+            continues_from,
+        }
+    }
+}
+
+/// Older transcripts carry no agent id: match a sidechain's opening prompt
+/// against the prompts of `Task` calls.
+fn prompt_match(entries: &[TreeEntry], root: &TreeEntry) -> Option<String> {
+    let text = root.text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    entries
+        .iter()
+        .flat_map(|e| e.task_calls.iter())
+        .find(|(_, prompt)| prompt.trim() == text)
+        .map(|(id, _)| id.clone())
+}
+
+/// Directory holding subagent transcripts of a Claude session file
+/// (`<dir>/<session-id>/subagents/`).
+pub fn subagents_dir(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?;
+    Some(path.parent()?.join(stem).join("subagents"))
+}
+
+/// Build the tree of a Claude JSONL transcript including its subagent files.
+pub async fn build_claude_tree(path: &Path) -> anyhow::Result<ConversationTree> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let mut builder = TreeBuilder::new();
+    for line in content.lines() {
+        builder.push_line(line);
+    }
+
+    if let Some(dir) = subagents_dir(path)
+        && let Ok(mut entries) = tokio::fs::read_dir(&dir).await
+    {
+        let mut files = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(agent_id) = name
+                .strip_prefix("agent-")
+                .and_then(|n| n.strip_suffix(".jsonl"))
+            {
+                files.push((agent_id.to_string(), entry.path()));
+            }
+        }
+        files.sort();
+        for (agent_id, file) in files {
+            if let Ok(content) = tokio::fs::read_to_string(&file).await {
+                builder.push_sidechain(&agent_id, &content);
+            }
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// Lines read from the start of a sibling by [`find_continued_session`].
+const CONTINUATION_HEAD_LINES: usize = 64;
+
+/// Bytes read from the end of a sibling by [`find_continued_session`].
+const CONTINUATION_TAIL_BYTES: u64 = 256 * 1024;
+
+/// Find the sibling transcript that contains entry `uuid` — the file a
+/// resumed session continues. Returns its session id (file stem).
+///
+/// A resume continues from what was the leaf of the earlier file, so only
+/// the end of each sibling is read, plus its first lines, where a file
+/// that was itself resumed repeats entries of the one before.
+pub async fn find_continued_session(path: &Path, uuid: &str) -> Option<String> {
+    let dir = path.parent()?;
+    let needle = format!("\"uuid\":\"{uuid}\"");
+    let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let candidate = entry.path();
+        if candidate == path || candidate.extension().is_none_or(|e| e != "jsonl") {
+            continue;
+        }
+        if head_or_tail_contains(&candidate, &needle)
+            .await
+            .unwrap_or(false)
+        {
+            return candidate
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string());
+        }
+    }
+    None
+}
+
+async fn head_or_tail_contains(path: &Path, needle: &str) -> std::io::Result<bool> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt};
+
+    let mut reader = tokio::io::BufReader::new(tokio::fs::File::open(path).await?);
+    let mut line = String::new();
+    for _ in 0..CONTINUATION_HEAD_LINES {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(false);
+        }
+        if line.contains(needle) {
+            return Ok(true);
+        }
+    }
+
+    let len = reader.get_ref().metadata().await?.len();
+    let start = len
+        .saturating_sub(CONTINUATION_TAIL_BYTES)
+        .max(reader.stream_position().await?);
+    reader.seek(std::io::SeekFrom::Start(start)).await?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).await?;
+    Ok(String::from_utf8_lossy(&tail).contains(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(lines: &[serde_json::Value]) -> ConversationTree {
+        let mut builder = TreeBuilder::new();
+        for line in lines {
+            builder.push_line(&line.to_string());
+        }
+        builder.build()
+    }
+
+    fn user(uuid: &str, parent: Option<&str>, text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "user", "uuid": uuid, "parentUuid": parent,
+            "message": {"role": "user", "content": text},
+        })
+    }
+
+    fn assistant(uuid: &str, parent: &str, text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "assistant", "uuid": uuid, "parentUuid": parent,
+            "message": {"role": "assistant", "content": [{"type": "text", "text": text}]},
+        })
+    }
+
+    #[test]
+    fn rewind_creates_abandoned_branch() {
+        let tree = build(&[
+            user("u1", None, "hello"),
+            assistant("a1", "u1", "hi"),
+            user("u2", Some("a1"), "first try"),
+            assistant("a2", "u2", "answer"),
+            // Rewind: edit the second prompt.
+            user("u3", Some("a1"), "second try"),
+            assistant("a3", "u3", "better answer"),
+        ]);
+
+        assert_eq!(tree.roots, vec!["u1"]);
+        assert_eq!(tree.leaf.as_deref(), Some("a3"));
+        assert_eq!(tree.branch_points, vec!["a1"]);
+        let live: Vec<&str> = tree
+            .nodes
+            .iter()
+            .filter(|n| n.live)
+            .map(|n| n.uuid.as_str())
+            .collect();
+        assert_eq!(live, vec!["u1", "a1", "u3", "a3"]);
+        assert_eq!(tree.abandoned_count(), 2);
+        assert_eq!(tree.node("a1").unwrap().children, vec!["u2", "u3"]);
+    }
+
+    #[test]
+    fn compaction_links_through_logical_parent() {
+        let tree = build(&[
+            user("u1", None, "hello"),
+            assistant("a1", "u1", "hi"),
+            serde_json::json!({
+                "type": "system", "subtype": "compact_boundary", "uuid": "c1",
+                "parentUuid": null, "logicalParentUuid": "a1",
+                "compactMetadata": {"trigger": "auto", "preTokens": 150000},
+            }),
+            user("u2", Some("c1"), "continue"),
+        ]);
+
+        let boundary = tree.node("c1").unwrap();
+        assert_eq!(boundary.edge, EdgeKind::Compaction);
+        assert_eq!(boundary.entry_type, "compact_boundary");
+        assert_eq!(boundary.parent.as_deref(), Some("a1"));
+        assert_eq!(tree.roots, vec!["u1"]);
+        assert_eq!(tree.abandoned_count(), 0);
+    }
+
+    #[test]
+    fn sidechain_attaches_under_task_call() {
+        let mut builder = TreeBuilder::new();
+        for line in [
+            user("u1", None, "explore the repo"),
+            serde_json::json!({
+                "type": "assistant", "uuid": "a1", "parentUuid": "u1",
+                "message": {"role": "assistant", "content": [{
+                    "type": "tool_use", "id": "toolu_task", "name": "Task",
+                    "input": {"description": "explore", "prompt": "List the crates"},
+                }]},
+            }),
+            serde_json::json!({
+                "type": "user", "uuid": "u2", "parentUuid": "a1",
+                "message": {"role": "user", "content": [{
+                    "type": "tool_result", "tool_use_id": "toolu_task", "content": "done",
+                }]},
+                "toolUseResult": {"status": "completed", "agentId": "a08ef36"},
+            }),
+        ] {
+            builder.push_line(&line.to_string());
+        }
+        let agent = [
+            user("s1", None, "List the crates"),
+            assistant("s2", "s1", "server, common"),
+        ]
+        .map(|v| v.to_string())
+        .join("\n");
+        builder.push_sidechain("a08ef36", &agent);
+        // Older inline sidechain without agent id, matched by prompt.
+        let mut inline = user("s3", None, "List the crates");
+        inline["isSidechain"] = true.into();
+        builder.push_line(&inline.to_string());
+        let tree = builder.build();
+
+        let root = tree.node("s1").unwrap();
+        assert_eq!(root.edge, EdgeKind::Sidechain);
+        assert_eq!(root.parent.as_deref(), Some("a1"));
+        assert_eq!(root.spawned_by.as_deref(), Some("toolu_task"));
+        assert_eq!(root.agent_id.as_deref(), Some("a08ef36"));
+        assert!(tree.node("s2").unwrap().live);
+        assert_eq!(tree.node("s3").unwrap().parent.as_deref(), Some("a1"));
+        assert_eq!(tree.leaf.as_deref(), Some("u2"));
+        assert!(tree.branch_points.is_empty());
+    }
+
+    #[test]
+    fn dangling_parent_marks_resume() {
+        let tree = build(&[
+            user("u5", Some("from-earlier-file"), "where were we"),
+            assistant("a5", "u5", "here"),
+        ]);
+        assert_eq!(tree.node("u5").unwrap().edge, EdgeKind::Resume);
+        assert_eq!(tree.continues_from.as_deref(), Some("from-earlier-file"));
+        assert_eq!(tree.roots, vec!["u5"]);
+    }
+
+    #[tokio::test]
+    async fn continued_session_is_found_at_the_end_of_a_sibling() {
+        let dir = tempfile::tempdir().unwrap();
+        let line = |uuid: &str| format!("{}\n", user(uuid, None, "x"));
+        let earlier: String = (0..200).map(|i| line(&format!("e{i}"))).collect();
+        std::fs::write(dir.path().join("earlier.jsonl"), &earlier).unwrap();
+        std::fs::write(dir.path().join("other.jsonl"), line("o1")).unwrap();
+        let resumed = dir.path().join("resumed.jsonl");
+        std::fs::write(&resumed, line("r1")).unwrap();
+
+        assert_eq!(
+            find_continued_session(&resumed, "e199").await.as_deref(),
+            Some("earlier")
+        );
+        assert_eq!(
+            find_continued_session(&resumed, "e3").await.as_deref(),
+            Some("earlier")
+        );
+        assert_eq!(find_continued_session(&resumed, "r1").await, None);
+        assert_eq!(find_continued_session(&resumed, "missing").await, None);
+    }
+
+    #[test]
+    fn messages_without_parents_form_a_chain() {
+        let mut builder = TreeBuilder::new();
+        for uuid in ["m1", "m2", "m3"] {
+            builder.push_message(&ClaudeMessage {
+                uuid: uuid.to_string(),
+                message_type: "user".to_string(),
+                ..Default::default()
+            });
+        }
+        let tree = builder.build();
+        assert_eq!(tree.roots, vec!["m1"]);
+        assert_eq!(tree.node("m3").unwrap().parent.as_deref(), Some("m2"));
+        assert_eq!(tree.live_count(), 3);
+    }
+}
@@ -229,6 +229,95 @@ pub(crate) struct RawFileHistorySnapshot {
     pub timestamp: Option<String>,
 }
 
+// ============================================================
+// Schema drift: what the raw types above know about
+// ============================================================
+
+const KNOWN_ENTRY_TYPES: [&str; 6] = [
+    "user",
+    "assistant",
+    "system",
+    "progress",
+    "summary",
+    "file-history-snapshot",
+];
+
+/// Top-level keys of all entry types (envelope plus per-type fields).
+const KNOWN_FIELDS: [&str; 38] = [
+    "uuid",
+    "parentUuid",
+    "logicalParentUuid",
+    "sessionId",
+    "isSidechain",
+    "timestamp",
+    "agentId",
+    "type",
+    "version",
+    "cwd",
+    "gitBranch",
+    "userType",
+    "slug",
+    "message",
+    "permissionMode",
+    "thinkingMetadata",
+    "todos",
+    "toolUseResult",
+    "isCompactSummary",
+    "isMeta",
+    "isVisibleInTranscriptOnly",
+    "sourceToolAssistantUUID",
+    "requestId",
+    "isApiErrorMessage",
+    "subtype",
+    "content",
+    "level",
+    "durationMs",
+    "hookCount",
+    "compactMetadata",
+    "data",
+    "parentToolUseID",
+    "toolUseID",
+    "summary",
+    "leafUuid",
+    "messageId",
+    "snapshot",
+    "isSnapshotUpdate",
+];
+
+const KNOWN_BLOCK_TYPES: [&str; 5] = ["text", "tool_use", "tool_result", "thinking", "image"];
+
+/// Report entry types, fields and content blocks the raw types don't model.
+fn check_drift(value: &serde_json::Value, entry_type: &str) {
+    use super::drift::{self, DriftKind};
+
+    if let Some(version) = value.get("version").and_then(|v| v.as_str()) {
+        drift::note_version("claude", version);
+    }
+    let sample = || value.to_string();
+    if !KNOWN_ENTRY_TYPES.contains(&entry_type) {
+        drift::record("claude", DriftKind::EntryType, "entry", entry_type, sample);
+        return;
+    }
+    drift::check_fields("claude", entry_type, value, &KNOWN_FIELDS, sample);
+    let blocks = value
+        .pointer("/message/content")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten();
+    for block in blocks {
+        let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        if !KNOWN_BLOCK_TYPES.contains(&block_type) {
+            drift::record(
+                "claude",
+                DriftKind::ContentBlock,
+                entry_type,
+                block_type,
+                sample,
+            );
+        }
+    }
+}
+
 // ============================================================
 // Two-pass parsing: Value → type dispatch → typed struct → ClaudeMessage
 // ============================================================
@@ -245,6 +334,7 @@ pub fn parse_raw_to_message(value: serde_json::Value) -> Result<ClaudeMessage, s
         .and_then(|v| v.as_str())
         .unwrap_or("unknown")
         .to_string();
+    check_drift(&value, &entry_type);
 
     match entry_type.as_str() {
         "user" => {
@@ -670,6 +760,33 @@ mod tests {
         assert_eq!(msg.message_type, "progress");
     }
 
+    #[test]
+    fn unknown_fields_and_blocks_are_reported_as_drift() {
+        let json = r#"{
+            "type": "assistant",
+            "uuid": "drift-1",
+            "version": "9.9.9-drift",
+            "sentimentScore": 0.5,
+            "message": {"role": "assistant", "content": [
+                {"type": "text", "text": "hi"},
+                {"type": "hologram_block", "data": "…"}
+            ]}
+        }"#;
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        parse_raw_to_message(value).unwrap();
+
+        let found = crate::parser::drift::records();
+        let field = found
+            .iter()
+            .find(|r| r.source == "claude" && r.name == "sentimentScore")
+            .expect("unknown field recorded");
+        assert_eq!(field.context, "assistant");
+        assert!(field.sample.contains("sentimentScore"));
+        assert!(!field.sample.contains("drift-1"), "values are redacted");
+        assert!(found.iter().any(|r| r.name == "hologram_block"
+            && r.kind == crate::parser::drift::DriftKind::ContentBlock));
+    }
+
     #[test]
     fn parse_sidechain_subagent() {
         let json = r#"{
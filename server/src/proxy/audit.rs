@@ -3,7 +3,8 @@
 
 use serde::{Deserialize, Serialize};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tracing::warn;
 
 /// Audit log entry with token usage and cost.
@@ -154,6 +155,25 @@ pub fn extract_tokens(body: &str) -> (String, u64, u64, u64, u64) {
                 }
             }
 
+            // OpenAI Responses API (Codex): response.completed carries the totals
+            if json.get("type").and_then(|t| t.as_str()) == Some("response.completed")
+                && let Some(response) = json.get("response")
+            {
+                if let Some(usage) = response.get("usage") {
+                    input_tokens = usage
+                        .get("input_tokens")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(input_tokens);
+                    output_tokens = usage
+                        .get("output_tokens")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(output_tokens);
+                }
+                if let Some(m) = response.get("model").and_then(|m| m.as_str()) {
+                    model = m.to_string();
+                }
+            }
+
             // Gemini: usageMetadata
             if let Some(meta) = json.get("usageMetadata") {
                 input_tokens = meta
@@ -181,12 +201,17 @@ pub fn extract_tokens(body: &str) -> (String, u64, u64, u64, u64) {
 }
 
 /// Audit log file path.
-fn audit_log_path() -> PathBuf {
+pub fn audit_log_path() -> PathBuf {
     PathBuf::from("/data/noaide/audit-log.jsonl")
 }
 
+/// Held while the log is appended to or rewritten, so a rewrite can't drop
+/// an entry appended in the meantime.
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
 /// Append an audit entry to the JSONL log file.
 pub fn append_entry(entry: &AuditEntry) {
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     let path = audit_log_path();
     if let Some(parent) = path.parent() {
         let _ = std::fs::create_dir_all(parent);
@@ -243,6 +268,52 @@ pub fn query_entries(
     entries
 }
 
+/// Rewrite the log at `path`, keeping the entries `select` marks `true`.
+///
+/// `select` gets every parseable entry, oldest first, with the size of its
+/// line, and returns one flag per entry. Lines that don't parse are kept.
+/// Returns the number of entries and bytes removed.
+pub fn retain_entries(
+    path: &Path,
+    select: impl FnOnce(&[(AuditEntry, u64)]) -> Vec<bool>,
+) -> std::io::Result<(usize, u64)> {
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e),
+    };
+
+    let lines: Vec<(&str, Option<AuditEntry>)> = content
+        .lines()
+        .map(|line| (line, serde_json::from_str(line).ok()))
+        .collect();
+    let entries: Vec<(AuditEntry, u64)> = lines
+        .iter()
+        .filter_map(|(line, e)| Some((e.clone()?, line.len() as u64 + 1)))
+        .collect();
+    let keep = select(&entries);
+
+    let mut out = String::with_capacity(content.len());
+    let (mut removed, mut bytes) = (0, 0);
+    let mut flags = keep.into_iter();
+    for (line, entry) in &lines {
+        if entry.is_some() && !flags.next().unwrap_or(true) {
+            removed += 1;
+            bytes += line.len() as u64 + 1;
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if removed > 0 {
+        let tmp = path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, path)?;
+    }
+    Ok((removed, bytes))
+}
+
 /// Export entries as CSV string.
 pub fn export_csv(entries: &[AuditEntry]) -> String {
     let mut csv = String::from(
@@ -303,6 +374,15 @@ data: [DONE]
         assert_eq!(output, 300);
     }
 
+    #[test]
+    fn extract_responses_api_tokens() {
+        let body = r#"{"type":"response.completed","response":{"model":"gpt-5-codex","usage":{"input_tokens":120,"output_tokens":80}}}"#;
+        let (model, input, output, _, _) = extract_tokens(body);
+        assert_eq!(model, "gpt-5-codex");
+        assert_eq!(input, 120);
+        assert_eq!(output, 80);
+    }
+
     #[test]
     fn extract_gemini_tokens() {
         let body = r#"data: {"candidates":[],"usageMetadata":{"promptTokenCount":100,"candidatesTokenCount":200},"modelVersion":"gemini-2.0-flash"}
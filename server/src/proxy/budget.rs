@@ -0,0 +1,264 @@
+//! Per-session spend budgets — request and cost ceilings enforced by the proxy.
+//!
+//! Limits come from the session's proxy config (usually via a profile).
+//! Usage is counted as requests are forwarded (whether through the reverse
+//! proxy, a MITM'd CONNECT tunnel or a WebSocket) and as the audit log
+//! extracts token usage from responses. Usage of sessions with limits is
+//! saved shortly after it changes, so a restart doesn't reset their spend.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// Spend limits for a session. `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetLimits {
+    /// Maximum estimated spend in USD (from audit cost calculation).
+    pub max_cost_usd: Option<f64>,
+    /// Maximum number of upstream API requests.
+    pub max_requests: Option<u64>,
+}
+
+impl BudgetLimits {
+    /// Whether any limit is configured.
+    pub fn is_limited(&self) -> bool {
+        self.max_cost_usd.is_some() || self.max_requests.is_some()
+    }
+}
+
+/// Accumulated usage for a session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    pub requests: u64,
+    pub cost_usd: f64,
+}
+
+/// Per-session budget limits and usage counters.
+pub struct BudgetStore {
+    limits: Arc<DashMap<String, BudgetLimits>>,
+    usage: Arc<DashMap<String, BudgetUsage>>,
+    /// Where usage is saved; unset until [`BudgetStore::restore`].
+    path: OnceLock<PathBuf>,
+    save_pending: Arc<AtomicBool>,
+}
+
+impl BudgetStore {
+    pub fn new() -> Self {
+        Self {
+            limits: Arc::new(DashMap::new()),
+            usage: Arc::new(DashMap::new()),
+            path: OnceLock::new(),
+            save_pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> BudgetLimits {
+        self.limits
+            .get(session_id)
+            .map(|r| r.value().clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, session_id: String, limits: BudgetLimits) {
+        self.limits.insert(session_id, limits);
+    }
+
+    pub fn usage(&self, session_id: &str) -> BudgetUsage {
+        self.usage
+            .get(session_id)
+            .map(|r| r.value().clone())
+            .unwrap_or_default()
+    }
+
+    /// Count one forwarded request against the session.
+    pub fn record_request(&self, session_id: &str) {
+        self.usage
+            .entry(session_id.to_string())
+            .or_default()
+            .requests += 1;
+        self.schedule_save(session_id);
+    }
+
+    /// Add the audited cost of a completed request.
+    pub fn record_cost(&self, session_id: &str, cost_usd: f64) {
+        self.usage
+            .entry(session_id.to_string())
+            .or_default()
+            .cost_usd += cost_usd;
+        self.schedule_save(session_id);
+    }
+
+    /// Load usage saved at `path` and keep saving there from now on.
+    pub fn restore(&self, path: PathBuf) -> usize {
+        let saved: BTreeMap<String, BudgetUsage> = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "failed to parse budget usage");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        let restored = saved.len();
+        for (session_id, usage) in saved {
+            self.usage.insert(session_id, usage);
+        }
+        let _ = self.path.set(path);
+        restored
+    }
+
+    /// Save usage now (normally done 1s after it changes).
+    pub fn flush(&self) -> std::io::Result<()> {
+        match self.path.get() {
+            Some(path) => write_usage(path, &self.limits, &self.usage),
+            None => Ok(()),
+        }
+    }
+
+    /// Save usage 1s from now, unless a save is already pending.
+    fn schedule_save(&self, session_id: &str) {
+        let Some(path) = self.path.get() else {
+            return;
+        };
+        if !self.limits.contains_key(session_id) || self.save_pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let path = path.clone();
+        let limits = Arc::clone(&self.limits);
+        let usage = Arc::clone(&self.usage);
+        let pending = Arc::clone(&self.save_pending);
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            pending.store(false, Ordering::Release);
+            if let Err(e) = write_usage(&path, &limits, &usage) {
+                warn!(path = %path.display(), error = %e, "failed to save budget usage");
+            }
+        });
+    }
+
+    /// Returns a human-readable reason if the session has exhausted its budget.
+    pub fn exceeded(&self, session_id: &str) -> Option<String> {
+        let limits = self.limits.get(session_id)?;
+        let usage = self.usage(session_id);
+        if let Some(max) = limits.max_requests
+            && usage.requests >= max
+        {
+            return Some(format!(
+                "request budget exhausted ({}/{max})",
+                usage.requests
+            ));
+        }
+        if let Some(max) = limits.max_cost_usd
+            && usage.cost_usd >= max
+        {
+            return Some(format!(
+                "cost budget exhausted (${:.4}/${max:.2})",
+                usage.cost_usd
+            ));
+        }
+        None
+    }
+}
+
+/// Write the usage of every session that has limits, atomically.
+fn write_usage(
+    path: &Path,
+    limits: &DashMap<String, BudgetLimits>,
+    usage: &DashMap<String, BudgetUsage>,
+) -> std::io::Result<()> {
+    let snapshot: BTreeMap<String, BudgetUsage> = usage
+        .iter()
+        .filter(|entry| limits.contains_key(entry.key()))
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&snapshot)?)?;
+    std::fs::rename(&tmp, path)?;
+    debug!(path = %path.display(), sessions = snapshot.len(), "saved budget usage");
+    Ok(())
+}
+
+impl Default for BudgetStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_session_never_exceeds() {
+        let store = BudgetStore::new();
+        for _ in 0..100 {
+            store.record_request("s1");
+        }
+        store.record_cost("s1", 1000.0);
+        assert!(store.exceeded("s1").is_none());
+    }
+
+    #[test]
+    fn request_limit_is_enforced() {
+        let store = BudgetStore::new();
+        store.set(
+            "s1".to_string(),
+            BudgetLimits {
+                max_requests: Some(2),
+                ..Default::default()
+            },
+        );
+        store.record_request("s1");
+        assert!(store.exceeded("s1").is_none());
+        store.record_request("s1");
+        assert!(store.exceeded("s1").unwrap().contains("request budget"));
+    }
+
+    #[test]
+    fn cost_limit_is_enforced() {
+        let store = BudgetStore::new();
+        store.set(
+            "s1".to_string(),
+            BudgetLimits {
+                max_cost_usd: Some(0.5),
+                ..Default::default()
+            },
+        );
+        store.record_cost("s1", 0.3);
+        assert!(store.exceeded("s1").is_none());
+        store.record_cost("s1", 0.3);
+        assert!(store.exceeded("s1").unwrap().contains("cost budget"));
+        assert!(store.exceeded("other").is_none());
+    }
+
+    #[test]
+    fn usage_of_limited_sessions_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("budget-usage.json");
+        let limits = BudgetLimits {
+            max_requests: Some(2),
+            ..Default::default()
+        };
+
+        let store = BudgetStore::new();
+        assert_eq!(store.restore(path.clone()), 0);
+        store.set("s1".to_string(), limits.clone());
+        store.usage.entry("s1".to_string()).or_default().requests = 2;
+        store.usage.entry("s2".to_string()).or_default().requests = 5;
+        store.flush().unwrap();
+
+        let restarted = BudgetStore::new();
+        restarted.set("s1".to_string(), limits);
+        assert_eq!(restarted.restore(path), 1);
+        assert_eq!(restarted.usage("s1").requests, 2);
+        assert!(restarted.exceeded("s1").is_some());
+        assert_eq!(restarted.usage("s2"), BudgetUsage::default());
+    }
+}
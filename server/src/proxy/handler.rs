@@ -144,12 +144,15 @@ fn apply_rotated_api_key(
     headers: &mut Vec<(String, String)>,
     provider: ApiProvider,
     key_store: &super::keys::KeyStore,
+    session_id: Option<&str>,
 ) {
     if !key_store.has_active_keys(provider.label()) {
         return;
     }
 
-    let Some((_key_id, plaintext_key)) = key_store.select_key(provider.label()) else {
+    let Some((_key_id, plaintext_key)) =
+        key_store.select_key_for_session(provider.label(), session_id)
+    else {
         return;
     };
 
@@ -171,7 +174,7 @@ fn apply_rotated_api_key(
 }
 
 /// Maximum number of captured requests kept in memory
-pub(crate) const MAX_CAPTURED_REQUESTS: usize = 1000;
+pub const MAX_CAPTURED_REQUESTS: usize = 1000;
 
 /// Supported upstream API providers
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -200,6 +203,18 @@ impl ApiProvider {
         }
     }
 
+    /// Provider whose API is served from `host` (as seen in CONNECT tunnels).
+    pub fn from_host(host: &str) -> Option<ApiProvider> {
+        match host {
+            "api.anthropic.com" => Some(ApiProvider::Anthropic),
+            "api.openai.com" => Some(ApiProvider::OpenAI),
+            "chatgpt.com" => Some(ApiProvider::ChatGPT),
+            "generativelanguage.googleapis.com" => Some(ApiProvider::Google),
+            "cloudcode-pa.googleapis.com" => Some(ApiProvider::GoogleCodeAssist),
+            _ => None,
+        }
+    }
+
     pub fn label(&self) -> &'static str {
         match self {
             ApiProvider::Anthropic => "anthropic",
@@ -323,6 +338,254 @@ pub struct ProxyState {
     pub rewrite_store: super::rewrite::RewriteStore,
     /// API key store for key rotation.
     pub key_store: super::keys::KeyStore,
+    /// Per-session spend budgets (request/cost ceilings).
+    pub budgets: super::budget::BudgetStore,
+    /// Per-session pre-flight policy (context-size and schema checks).
+    pub preflight: super::preflight::PreflightStore,
+    /// Named proxy profiles (built-in + user-defined).
+    pub profiles: super::profiles::ProfileStore,
+}
+
+// ── Shared Request Checks ───────────────────────────────────────────────────
+// The reverse proxy, MITM'd CONNECT tunnels and WebSocket frames all send API
+// traffic upstream; these checks and counters apply to each of them.
+
+/// An API request about to be forwarded upstream.
+pub(crate) struct Outbound<'a> {
+    pub request_id: &'a str,
+    pub session_id: Option<&'a str>,
+    pub method: &'a str,
+    pub url: &'a str,
+    pub category: &'a str,
+    /// Upstream API, when known; pre-flight checks need it.
+    pub provider: Option<ApiProvider>,
+    /// Upstream path (without any session prefix).
+    pub path: &'a str,
+    pub start: Instant,
+}
+
+/// A request refused before it reached upstream (already captured).
+#[derive(Debug)]
+pub(crate) struct Refusal {
+    pub status: StatusCode,
+    /// Short reason, used as the WebSocket close reason.
+    pub reason: String,
+    /// Plain-text or JSON body sent back to the client.
+    pub body: serde_json::Value,
+}
+
+impl Refusal {
+    /// Response for a client speaking hyper directly (MITM tunnels).
+    pub(crate) fn into_hyper(self) -> hyper::Response<http_body_util::Full<Bytes>> {
+        let (body, content_type) = match self.body {
+            serde_json::Value::String(text) => (Bytes::from(text), "text/plain"),
+            json => (Bytes::from(json.to_string()), "application/json"),
+        };
+        let mut resp = hyper::Response::new(http_body_util::Full::new(body));
+        *resp.status_mut() = self.status;
+        resp.headers_mut().insert(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static(content_type),
+        );
+        resp
+    }
+}
+
+impl IntoResponse for Refusal {
+    fn into_response(self) -> Response {
+        match self.body {
+            serde_json::Value::String(text) => (self.status, text).into_response(),
+            json => (self.status, axum::Json(json)).into_response(),
+        }
+    }
+}
+
+/// Check whether a request may go upstream. Refusals are captured and logged.
+///
+/// With a `body`, the session's pre-flight policy also runs; in trim mode the
+/// body may be replaced (the report's action is then `Trimmed`).
+pub(crate) async fn admit(
+    state: &ProxyState,
+    req: &Outbound<'_>,
+    body: Option<&mut Bytes>,
+) -> Result<Option<super::preflight::PreflightReport>, Refusal> {
+    // Profile budgets: once a session has spent its request/cost allowance,
+    // refuse further upstream calls until the limits are raised.
+    if let Some(sid) = req.session_id
+        && let Some(reason) = state.budgets.exceeded(sid)
+    {
+        info!(
+            request_id = %req.request_id,
+            target_url = %req.url,
+            session = %sid,
+            reason = %reason,
+            "request blocked by session budget"
+        );
+        refuse(
+            state,
+            req,
+            429,
+            0,
+            format!("Blocked by budget: {reason}"),
+            None,
+        )
+        .await;
+        return Err(Refusal {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            reason: format!("Blocked by session budget: {reason}"),
+            body: "Blocked by session budget".into(),
+        });
+    }
+
+    // ── Pre-flight Checks ─────────────────────────────────────────────
+    // Estimate input tokens against the model's context window and validate
+    // the (already rewritten) body. Policy decides warn / block / auto-trim.
+    let (Some(body), Some(provider)) = (body, req.provider) else {
+        return Ok(None);
+    };
+    let policy = req
+        .session_id
+        .map(|sid| state.preflight.get(sid))
+        .unwrap_or_default();
+    if body.is_empty() || policy == super::preflight::PreflightPolicy::Off {
+        return Ok(None);
+    }
+    let Ok(mut body_json) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return Ok(None);
+    };
+    let Some(report) = super::preflight::run(&mut body_json, provider, req.path, policy) else {
+        return Ok(None);
+    };
+
+    use super::preflight::PreflightAction;
+    match report.action {
+        PreflightAction::Pass => {}
+        PreflightAction::Warn => warn!(
+            request_id = %req.request_id,
+            session = ?req.session_id,
+            model = ?report.model,
+            estimated_input_tokens = report.estimated_input_tokens,
+            issues = ?report.issues,
+            "preflight check found issues, forwarding"
+        ),
+        PreflightAction::Trimmed => {
+            info!(
+                request_id = %req.request_id,
+                session = ?req.session_id,
+                model = ?report.model,
+                fixes = ?report.fixes,
+                "preflight auto-trimmed request"
+            );
+            if let Ok(modified) = serde_json::to_vec(&body_json) {
+                *body = Bytes::from(modified);
+            }
+        }
+        PreflightAction::Block => {
+            info!(
+                request_id = %req.request_id,
+                session = ?req.session_id,
+                model = ?report.model,
+                issues = ?report.issues,
+                "request blocked by preflight check"
+            );
+            let error_body = super::preflight::block_response_body(provider, &report);
+            let reason = report.issues.first().map_or_else(
+                || "preflight check failed".to_string(),
+                |i| i.message.clone(),
+            );
+            refuse(
+                state,
+                req,
+                400,
+                body.len(),
+                error_body.to_string(),
+                Some(report),
+            )
+            .await;
+            return Err(Refusal {
+                status: StatusCode::BAD_REQUEST,
+                reason,
+                body: error_body,
+            });
+        }
+    }
+    Ok(Some(report))
+}
+
+/// Capture a refused request so it shows up in the network panel.
+async fn refuse(
+    state: &ProxyState,
+    req: &Outbound<'_>,
+    status_code: u16,
+    request_size: usize,
+    response_body: String,
+    preflight: Option<super::preflight::PreflightReport>,
+) {
+    capture(
+        state,
+        ApiRequestLog {
+            id: req.request_id.to_string(),
+            session_id: req.session_id.map(String::from),
+            method: req.method.to_string(),
+            url: req.url.to_string(),
+            status_code,
+            latency_ms: req.start.elapsed().as_millis() as u64,
+            request_size,
+            response_size: 0,
+            request_body: String::new(),
+            response_body,
+            request_headers: vec![],
+            response_headers: vec![],
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64,
+            category: Some(req.category.to_string()),
+            preflight,
+        },
+    )
+    .await;
+}
+
+/// Push a log entry into the capture ring buffer and broadcast it.
+pub(crate) async fn capture(state: &ProxyState, log_entry: ApiRequestLog) {
+    {
+        let mut cap = state.captured.write().await;
+        if cap.len() >= MAX_CAPTURED_REQUESTS {
+            cap.pop_front();
+        }
+        cap.push_back(log_entry.clone());
+    }
+    let _ = state.event_tx.send(log_entry);
+}
+
+/// Audit the token usage in a completed response and charge it to the session.
+pub(crate) fn record_usage(state: &ProxyState, log_entry: &ApiRequestLog, provider: &str) {
+    let (model, input_tokens, output_tokens, cache_creation, cache_read) =
+        super::audit::extract_tokens(&log_entry.response_body);
+    if input_tokens == 0 && output_tokens == 0 {
+        return;
+    }
+    let cost = super::audit::calculate_cost(&model, input_tokens, output_tokens);
+    let audit_entry = super::audit::AuditEntry {
+        id: log_entry.id.clone(),
+        session_id: log_entry.session_id.clone(),
+        method: log_entry.method.clone(),
+        url: log_entry.url.clone(),
+        model,
+        provider: provider.to_string(),
+        input_tokens,
+        output_tokens,
+        cache_creation_tokens: cache_creation,
+        cache_read_tokens: cache_read,
+        cost_usd: cost,
+        timestamp: log_entry.timestamp,
+        latency_ms: log_entry.latency_ms,
+    };
+    super::audit::append_entry(&audit_entry);
+    if let Some(ref sid) = audit_entry.session_id {
+        state.budgets.record_cost(sid, cost);
+    }
 }
 
 /// Extract session UUID from `/s/{uuid}/...` proxy path prefix.
@@ -425,6 +688,7 @@ pub async fn proxy_handler(
                 .unwrap_or_default()
                 .as_millis() as i64,
             category: Some(request_category.to_string()),
+            preflight: None,
         };
         {
             let mut cap = state.captured.write().await;
@@ -438,6 +702,18 @@ pub async fn proxy_handler(
         return (StatusCode::FORBIDDEN, "Blocked by proxy mode").into_response();
     }
 
+    let category_label = request_category.to_string();
+    let outbound = Outbound {
+        request_id: &request_id,
+        session_id: session_id.as_deref(),
+        method: method.as_str(),
+        url: &target_url,
+        category: &category_label,
+        provider: Some(provider),
+        path: effective_path,
+        start,
+    };
+
     // ── WebSocket Upgrade Detection ────────────────────────────────────
     // Check BEFORE body.collect() — consuming the body prevents hyper upgrade.
     // Codex WebSocket goes through reverse proxy (chatgpt.com in NO_PROXY).
@@ -448,6 +724,10 @@ pub async fn proxy_handler(
             session_id = ?session_id,
             "WebSocket upgrade detected, establishing upstream connection"
         );
+        // Budgets apply to the upgrade; frames are checked by the relay.
+        if let Err(refusal) = admit(&state, &outbound, None).await {
+            return refusal.into_response();
+        }
         return handle_websocket_proxy(state, session_id, target_url, req).await;
     }
 
@@ -672,6 +952,18 @@ pub async fn proxy_handler(
         }
     }
 
+    // ── Shared Checks: budget + pre-flight ────────────────────────────
+    let preflight_report = match admit(&state, &outbound, Some(&mut transform_bytes)).await {
+        Ok(report) => report,
+        Err(refusal) => return refusal.into_response(),
+    };
+    if preflight_report
+        .as_ref()
+        .is_some_and(|r| r.action == super::preflight::PreflightAction::Trimmed)
+    {
+        request_body_modified = true;
+    }
+
     if request_body_modified {
         request_bytes = match try_recompress_request(&transform_bytes, &request_headers) {
             Some(reencoded) => Bytes::from(reencoded),
@@ -764,7 +1056,16 @@ pub async fn proxy_handler(
     // ── API Key Rotation ──────────────────────────────────────────────
     // Apply the selected provider key to the mutable header set so the
     // actual forwarded headers and the captured/logged headers stay aligned.
-    apply_rotated_api_key(&mut request_headers, provider, &state.key_store);
+    if let Some(ref sid) = session_id {
+        state.budgets.record_request(sid);
+    }
+
+    apply_rotated_api_key(
+        &mut request_headers,
+        provider,
+        &state.key_store,
+        session_id.as_deref(),
+    );
 
     // ── Build forwarding request ────────────────────────────────────────
 
@@ -934,7 +1235,7 @@ pub async fn proxy_handler(
             // Log the intercepted streaming request
             let log_req_body = try_decompress_request(&request_bytes, &request_headers);
             let log_req_bytes = log_req_body.as_deref().unwrap_or(&request_bytes);
-            let log_entry = mitm::build_log(
+            let mut log_entry = mitm::build_log(
                 request_id,
                 session_id,
                 method.as_str(),
@@ -946,6 +1247,7 @@ pub async fn proxy_handler(
                 status.as_u16(),
                 start,
             );
+            log_entry.preflight = preflight_report.clone();
 
             info!(
                 method = log_entry.method,
@@ -983,30 +1285,7 @@ pub async fn proxy_handler(
             let _ = state.event_tx.send(log_entry.clone());
 
             // ── Audit Log: extract tokens + append ──
-            {
-                let response_str = &log_entry.response_body;
-                let (model, input_tokens, output_tokens, cache_creation, cache_read) =
-                    super::audit::extract_tokens(response_str);
-                if input_tokens > 0 || output_tokens > 0 {
-                    let cost = super::audit::calculate_cost(&model, input_tokens, output_tokens);
-                    let audit_entry = super::audit::AuditEntry {
-                        id: log_entry.id.clone(),
-                        session_id: log_entry.session_id.clone(),
-                        method: log_entry.method.clone(),
-                        url: log_entry.url.clone(),
-                        model,
-                        provider: provider.label().to_string(),
-                        input_tokens,
-                        output_tokens,
-                        cache_creation_tokens: cache_creation,
-                        cache_read_tokens: cache_read,
-                        cost_usd: cost,
-                        timestamp: log_entry.timestamp,
-                        latency_ms: log_entry.latency_ms,
-                    };
-                    super::audit::append_entry(&audit_entry);
-                }
-            }
+            record_usage(&state, &log_entry, provider.label());
 
             // Replay the buffered SSE data as the response body.
             // The content-type (text/event-stream) is preserved, so the client's
@@ -1032,6 +1311,7 @@ pub async fn proxy_handler(
         let req_bytes = request_bytes.clone();
         let target = target_url.clone();
         let log_session_id = session_id.clone();
+        let log_preflight = preflight_report.clone();
 
         let (chunk_tx, chunk_rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(64);
         let log_state = state.clone();
@@ -1063,7 +1343,7 @@ pub async fn proxy_handler(
             let response_bytes = Bytes::from(collected);
             let log_req_body = try_decompress_request(&req_bytes, &req_headers);
             let log_req_bytes = log_req_body.as_deref().unwrap_or(&req_bytes);
-            let log_entry = mitm::build_log(
+            let mut log_entry = mitm::build_log(
                 request_id,
                 log_session_id,
                 &method_str,
@@ -1075,6 +1355,7 @@ pub async fn proxy_handler(
                 status_code,
                 start,
             );
+            log_entry.preflight = log_preflight;
 
             info!(
                 method = log_entry.method,
@@ -1111,30 +1392,7 @@ pub async fn proxy_handler(
             let _ = log_state.event_tx.send(log_entry.clone());
 
             // ── Audit Log: extract tokens from streaming response ──
-            {
-                let response_str = &log_entry.response_body;
-                let (model, input_tokens, output_tokens, cache_creation, cache_read) =
-                    super::audit::extract_tokens(response_str);
-                if input_tokens > 0 || output_tokens > 0 {
-                    let cost = super::audit::calculate_cost(&model, input_tokens, output_tokens);
-                    let audit_entry = super::audit::AuditEntry {
-                        id: log_entry.id.clone(),
-                        session_id: log_entry.session_id.clone(),
-                        method: log_entry.method.clone(),
-                        url: log_entry.url.clone(),
-                        model,
-                        provider: provider_label,
-                        input_tokens,
-                        output_tokens,
-                        cache_creation_tokens: cache_creation,
-                        cache_read_tokens: cache_read,
-                        cost_usd: cost,
-                        timestamp: log_entry.timestamp,
-                        latency_ms: log_entry.latency_ms,
-                    };
-                    super::audit::append_entry(&audit_entry);
-                }
-            }
+            record_usage(&log_state, &log_entry, &provider_label);
         });
 
         // Build streaming response back to caller
@@ -1254,7 +1512,7 @@ pub async fn proxy_handler(
     let log_req_bytes = log_req_body.as_deref().unwrap_or(&request_bytes);
 
     // Build redacted log entry (uses potentially modified response)
-    let log_entry = mitm::build_log(
+    let mut log_entry = mitm::build_log(
         request_id,
         session_id,
         method.as_str(),
@@ -1266,6 +1524,7 @@ pub async fn proxy_handler(
         final_status.as_u16(),
         start,
     );
+    log_entry.preflight = preflight_report;
 
     info!(
         method = log_entry.method,
@@ -1410,6 +1669,7 @@ pub async fn connect_handler(
                 .unwrap_or_default()
                 .as_millis() as i64,
             category: Some(category.to_string()),
+            preflight: None,
         };
         {
             let mut cap = state.captured.write().await;
@@ -1455,6 +1715,7 @@ pub async fn connect_handler(
                 .unwrap_or_default()
                 .as_millis() as i64,
             category: Some(category.to_string()),
+            preflight: None,
         };
         {
             let mut cap = state.captured.write().await;
@@ -1481,8 +1742,8 @@ pub async fn connect_handler(
         "CONNECT tunnel requested"
     );
 
-    // Connect to the target
-    let target_stream = match tokio::net::TcpStream::connect(&target_addr).await {
+    // Connect to the target (through the upstream proxy when configured)
+    let target_stream = match super::upstream::global().connect_addr(&target_addr).await {
         Ok(stream) => stream,
         Err(e) => {
             warn!(target = %target_addr, error = %e, "CONNECT: failed to connect to target");
@@ -1709,6 +1970,39 @@ where
     }
 
     // 6. HTTP/1.1 proxy loop: parse individual requests, forward, log bodies
+    // Prepend peeked bytes back into the stream for hyper to parse
+    let prefix = bytes::Bytes::copy_from_slice(&peek_buf[..peek_len]);
+    mitm_http1(
+        PrefixedIo::new(prefix, client_tls),
+        target_tls,
+        target_addr,
+        request_id,
+        session_id,
+        category,
+        start,
+        state,
+    )
+    .await
+}
+
+/// Serve cleartext HTTP/1.1 from a MITM'd client, forwarding each request to
+/// the target. API requests go through the shared checks ([`admit`]) and are
+/// counted against the session budget like reverse-proxy requests.
+#[allow(clippy::too_many_arguments)]
+async fn mitm_http1<C, T>(
+    client_io: C,
+    target_io: T,
+    target_addr: &str,
+    request_id: &str,
+    session_id: Option<&str>,
+    category: super::classify::TrafficCategory,
+    start: Instant,
+    state: &Arc<ProxyState>,
+) -> anyhow::Result<()>
+where
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
     info!(
         target = %target_addr,
         session = ?session_id,
@@ -1716,10 +2010,8 @@ where
         "HTTP/1.1 MITM active"
     );
 
-    // Prepend peeked bytes back into the stream for hyper to parse
-    let prefix = bytes::Bytes::copy_from_slice(&peek_buf[..peek_len]);
-    let prefixed_client = hyper_util::rt::TokioIo::new(PrefixedIo::new(prefix, client_tls));
-    let target_io = hyper_util::rt::TokioIo::new(target_tls);
+    let prefixed_client = hyper_util::rt::TokioIo::new(client_io);
+    let target_io = hyper_util::rt::TokioIo::new(target_io);
 
     // Establish hyper HTTP/1.1 client connection to real target
     let (target_sender, target_conn) =
@@ -1741,7 +2033,6 @@ where
     let state_clone = state.clone();
     let sid = session_id.map(String::from);
     let addr = target_addr.to_string();
-    let cat = category;
     let rid_base = request_id.to_string();
     let start_time = start;
 
@@ -1756,6 +2047,10 @@ where
             let req_start = Instant::now();
             let method = req.method().clone();
             let uri = req.uri().clone();
+            let url = mitm::redact(&format!("https://{addr}{uri}"));
+            let host = addr.split(':').next().unwrap_or(&addr);
+            let cat = super::classify::classify_request(host, uri.path());
+            let is_api = cat == super::classify::TrafficCategory::Api;
             let req_headers: Vec<(String, String)> = req
                 .headers()
                 .iter()
@@ -1763,18 +2058,54 @@ where
                 .collect();
 
             // Read full request body (infallible — collect errors become empty)
-            let (parts, body) = req.into_parts();
-            let req_body_bytes = body
+            let (mut parts, body) = req.into_parts();
+            let mut req_body_bytes = body
                 .collect()
                 .await
-                .map(|c| c.to_bytes().to_vec())
+                .map(|c| c.to_bytes())
                 .unwrap_or_default();
+
+            // API requests get the same budget and pre-flight checks as the
+            // reverse proxy (encoded bodies are not parsed and skip pre-flight).
+            let mut preflight = None;
+            if is_api {
+                let category_label = cat.to_string();
+                let outbound = Outbound {
+                    request_id: &rid,
+                    session_id: sid.as_deref(),
+                    method: method.as_str(),
+                    url: &url,
+                    category: &category_label,
+                    provider: ApiProvider::from_host(host),
+                    path: uri.path(),
+                    start: req_start,
+                };
+                let body = (!parts.headers.contains_key(hyper::header::CONTENT_ENCODING))
+                    .then_some(&mut req_body_bytes);
+                preflight = match admit(&state, &outbound, body).await {
+                    Ok(report) => report,
+                    Err(refusal) => return Ok(refusal.into_hyper()),
+                };
+                if preflight
+                    .as_ref()
+                    .is_some_and(|r| r.action == super::preflight::PreflightAction::Trimmed)
+                {
+                    parts.headers.insert(
+                        hyper::header::CONTENT_LENGTH,
+                        hyper::header::HeaderValue::from(req_body_bytes.len()),
+                    );
+                }
+            }
             let req_body_str = mitm::redact(&String::from_utf8_lossy(&req_body_bytes));
 
             // Rebuild request for forwarding to target
-            let fwd_body = http_body_util::Full::new(bytes::Bytes::from(req_body_bytes));
+            let fwd_body = http_body_util::Full::new(req_body_bytes);
             let fwd_req = hyper::Request::from_parts(parts, fwd_body);
 
+            if is_api && let Some(ref sid) = sid {
+                state.budgets.record_request(sid);
+            }
+
             // Forward to target
             let mut sender_guard = sender.lock().await;
             let response = sender_guard.send_request(fwd_req).await?;
@@ -1801,7 +2132,7 @@ where
                 id: rid,
                 session_id: sid,
                 method: method.to_string(),
-                url: mitm::redact(&format!("https://{addr}{uri}")),
+                url,
                 status_code: status.as_u16(),
                 latency_ms: req_start.elapsed().as_millis() as u64,
                 request_size: req_body_str.len(),
@@ -1815,16 +2146,14 @@ where
                     .unwrap_or_default()
                     .as_millis() as i64,
                 category: Some(cat.to_string()),
+                preflight,
             };
 
-            {
-                let mut cap = state.captured.write().await;
-                if cap.len() >= MAX_CAPTURED_REQUESTS {
-                    cap.pop_front();
-                }
-                cap.push_back(log_entry.clone());
+            if is_api {
+                let provider = ApiProvider::from_host(host).map_or("mitm", |p| p.label());
+                record_usage(&state, &log_entry, provider);
             }
-            let _ = state.event_tx.send(log_entry);
+            capture(&state, log_entry).await;
 
             metrics::counter!("proxy_requests_total",
                 "method" => method.to_string(),
@@ -1928,6 +2257,7 @@ where
             .unwrap_or_default()
             .as_millis() as i64,
         category: Some(category.to_string()),
+        preflight: None,
     };
 
     {
@@ -1990,6 +2320,7 @@ async fn log_tunnel_metadata(
             .unwrap_or_default()
             .as_millis() as i64,
         category: category.map(|c| c.to_string()),
+        preflight: None,
     };
 
     {
@@ -2057,19 +2388,19 @@ async fn handle_websocket_proxy(
         }
     }
 
-    // Connect to upstream WebSocket
-    let (upstream_ws, _upstream_response) = match tokio_tungstenite::connect_async(ws_request).await
-    {
-        Ok(pair) => pair,
-        Err(e) => {
-            warn!(error = %e, url = %ws_url, "failed to connect to upstream WebSocket");
-            return (
-                StatusCode::BAD_GATEWAY,
-                "WebSocket upstream connection failed",
-            )
-                .into_response();
-        }
-    };
+    // Connect to upstream WebSocket (through the upstream proxy when configured)
+    let (upstream_ws, _upstream_response) =
+        match super::upstream::connect_websocket(ws_request).await {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!(error = %e, url = %ws_url, "failed to connect to upstream WebSocket");
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    "WebSocket upstream connection failed",
+                )
+                    .into_response();
+            }
+        };
 
     info!(url = %ws_url, session_id = ?session_id, "upstream WebSocket connected");
 
@@ -2252,7 +2583,7 @@ mod tests {
         );
 
         let mut anthropic_headers = vec![("authorization".to_string(), "Bearer stale".to_string())];
-        apply_rotated_api_key(&mut anthropic_headers, ApiProvider::Anthropic, &store);
+        apply_rotated_api_key(&mut anthropic_headers, ApiProvider::Anthropic, &store, None);
         assert!(
             anthropic_headers
                 .iter()
@@ -2265,7 +2596,7 @@ mod tests {
         );
 
         let mut openai_headers = vec![("authorization".to_string(), "Bearer stale".to_string())];
-        apply_rotated_api_key(&mut openai_headers, ApiProvider::OpenAI, &store);
+        apply_rotated_api_key(&mut openai_headers, ApiProvider::OpenAI, &store, None);
         assert!(
             openai_headers
                 .iter()
@@ -2279,7 +2610,12 @@ mod tests {
             ),
             ("authorization".to_string(), "Bearer stale".to_string()),
         ];
-        apply_rotated_api_key(&mut google_headers, ApiProvider::GoogleCodeAssist, &store);
+        apply_rotated_api_key(
+            &mut google_headers,
+            ApiProvider::GoogleCodeAssist,
+            &store,
+            None,
+        );
         assert!(google_headers.iter().any(|(name, value)| {
             name == "x-goog-api-key" && value == "AIzaSyB1234567890abcdefghijklmnopqrst"
         }));
@@ -2383,4 +2719,135 @@ mod tests {
         let manual: InterceptMode = serde_json::from_str("\"manual\"").unwrap();
         assert_eq!(manual, InterceptMode::Manual);
     }
+
+    /// Start a MITM HTTP/1.1 loop for `api.anthropic.com` over in-memory pipes.
+    /// Returns a client connected to it and the number of requests upstream saw.
+    async fn mitm_over_pipes(
+        state: &Arc<ProxyState>,
+        session_id: &str,
+    ) -> (
+        hyper::client::conn::http1::SendRequest<http_body_util::Full<Bytes>>,
+        Arc<std::sync::atomic::AtomicUsize>,
+    ) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (client_io, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (proxy_target, upstream_io) = tokio::io::duplex(64 * 1024);
+
+        let seen = Arc::new(AtomicUsize::new(0));
+        let upstream_seen = seen.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |_req| {
+                upstream_seen.fetch_add(1, Ordering::SeqCst);
+                async {
+                    Ok::<_, hyper::Error>(hyper::Response::new(http_body_util::Full::new(
+                        Bytes::from_static(b"{}"),
+                    )))
+                }
+            });
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(upstream_io), service)
+                .await;
+        });
+
+        let state = state.clone();
+        let session_id = session_id.to_string();
+        tokio::spawn(async move {
+            let _ = mitm_http1(
+                proxy_client,
+                proxy_target,
+                "api.anthropic.com:443",
+                "test",
+                Some(&session_id),
+                super::super::classify::TrafficCategory::Api,
+                Instant::now(),
+                &state,
+            )
+            .await;
+        });
+
+        let (sender, conn) =
+            hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(client_io))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+        (sender, seen)
+    }
+
+    fn messages_request(body: &str) -> hyper::Request<http_body_util::Full<Bytes>> {
+        hyper::Request::post("/v1/messages")
+            .header("host", "api.anthropic.com")
+            .header("content-type", "application/json")
+            .body(http_body_util::Full::new(Bytes::from(body.to_string())))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn mitm_requests_count_against_the_session_budget() {
+        let (state, _rx) = crate::proxy::create_proxy_state();
+        let sid = "550e8400-e29b-41d4-a716-446655440000";
+        state.budgets.set(
+            sid.to_string(),
+            super::super::budget::BudgetLimits {
+                max_requests: Some(1),
+                ..Default::default()
+            },
+        );
+        let (mut client, seen) = mitm_over_pipes(&state, sid).await;
+
+        let first = client.send_request(messages_request("{}")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        first.into_body().collect().await.unwrap();
+        assert_eq!(state.budgets.usage(sid).requests, 1);
+
+        let second = client.send_request(messages_request("{}")).await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(seen.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(
+            state
+                .captured
+                .read()
+                .await
+                .iter()
+                .any(|entry| entry.status_code == 429)
+        );
+    }
+
+    #[tokio::test]
+    async fn mitm_requests_get_preflight_checks() {
+        let (state, _rx) = crate::proxy::create_proxy_state();
+        let sid = "550e8400-e29b-41d4-a716-446655440000";
+        state.preflight.set(
+            sid.to_string(),
+            super::super::preflight::PreflightPolicy::Block,
+        );
+        let (mut client, seen) = mitm_over_pipes(&state, sid).await;
+
+        let overflow = serde_json::json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "abcd".repeat(210_000)}]
+        });
+        let resp = client
+            .send_request(messages_request(&overflow.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(error["error"]["message"].is_string());
+
+        let ok = client.send_request(messages_request(r#"{"model":"claude-sonnet-4-6","max_tokens":16,"messages":[{"role":"user","content":"hi"}]}"#)).await.unwrap();
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(seen.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let captured = state.captured.read().await;
+        let blocked = captured.iter().find(|e| e.status_code == 400).unwrap();
+        assert!(blocked.preflight.is_some());
+        assert!(
+            captured
+                .iter()
+                .any(|e| e.status_code == 200 && e.preflight.is_some())
+        );
+    }
 }
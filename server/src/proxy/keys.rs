@@ -1,6 +1,7 @@
 //! API key rotation — round-robin/least-used key selection with rate-limit tracking.
 //!
 //! Keys are encrypted at rest using AES-256-GCM with a key derived from /etc/machine-id.
+//! Backups seal them under a passphrase-derived key instead, see [`passphrase_key`].
 //! Rate limits are tracked from response headers and auto-switch happens on 429.
 
 use dashmap::DashMap;
@@ -44,12 +45,69 @@ fn derive_key() -> aead::LessSafeKey {
     aead::LessSafeKey::new(unbound_key)
 }
 
+/// Derive an AES-256 key from a passphrase with PBKDF2-HMAC-SHA256.
+pub fn passphrase_key(passphrase: &str, salt: &[u8], iterations: u32) -> aead::LessSafeKey {
+    let iterations = std::num::NonZeroU32::new(iterations.max(1)).expect("non-zero");
+    let mut key_bytes = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key_bytes,
+    );
+    let unbound_key =
+        aead::UnboundKey::new(&aead::AES_256_GCM, &key_bytes).expect("AES key creation failed");
+    aead::LessSafeKey::new(unbound_key)
+}
+
+/// Encrypt with an explicit key. Output is base64 of nonce + ciphertext.
+pub fn seal(key: &aead::LessSafeKey, plaintext: &[u8]) -> String {
+    let rng = SystemRandom::new();
+    let mut nonce_bytes = [0u8; 12];
+    rng.fill(&mut nonce_bytes).expect("RNG failed");
+    let nonce = aead::Nonce::assume_unique_for_key(nonce_bytes);
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
+        .expect("encryption failed");
+
+    // Prepend nonce to ciphertext
+    let mut result = nonce_bytes.to_vec();
+    result.extend_from_slice(&in_out);
+    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &result)
+}
+
+/// Decrypt the output of [`seal`].
+pub fn open(key: &aead::LessSafeKey, encrypted: &str) -> Result<Vec<u8>, String> {
+    let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encrypted)
+        .map_err(|e| format!("base64 decode error: {e}"))?;
+
+    if data.len() < 12 {
+        return Err("ciphertext too short".to_string());
+    }
+
+    let (nonce_bytes, ciphertext) = data.split_at(12);
+    let nonce = aead::Nonce::assume_unique_for_key(
+        nonce_bytes
+            .try_into()
+            .map_err(|_| "invalid nonce".to_string())?,
+    );
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+        .map_err(|_| "decryption failed".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+/// On-disk form of `api-keys.json`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
-struct PersistedKeyStore {
-    keys: Vec<ApiKeyEntry>,
+pub struct PersistedKeyStore {
+    pub keys: Vec<ApiKeyEntry>,
 }
 
-fn key_store_path() -> PathBuf {
+pub fn key_store_path() -> PathBuf {
     std::env::var("NOAIDE_API_KEYS_PATH")
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("/data/noaide/api-keys.json"))
@@ -105,45 +163,13 @@ pub fn load_from_disk(store: &KeyStore) -> Result<usize, std::io::Error> {
 
 /// Encrypt an API key string.
 pub fn encrypt_key(plaintext: &str) -> String {
-    let key = derive_key();
-    let rng = SystemRandom::new();
-    let mut nonce_bytes = [0u8; 12];
-    rng.fill(&mut nonce_bytes).expect("RNG failed");
-    let nonce = aead::Nonce::assume_unique_for_key(nonce_bytes);
-
-    let mut in_out = plaintext.as_bytes().to_vec();
-    key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
-        .expect("encryption failed");
-
-    // Prepend nonce to ciphertext
-    let mut result = nonce_bytes.to_vec();
-    result.extend_from_slice(&in_out);
-    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &result)
+    seal(&derive_key(), plaintext.as_bytes())
 }
 
 /// Decrypt an API key string.
 pub fn decrypt_key(encrypted: &str) -> Result<String, String> {
-    let key = derive_key();
-    let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encrypted)
-        .map_err(|e| format!("base64 decode error: {e}"))?;
-
-    if data.len() < 12 {
-        return Err("ciphertext too short".to_string());
-    }
-
-    let (nonce_bytes, ciphertext) = data.split_at(12);
-    let nonce = aead::Nonce::assume_unique_for_key(
-        nonce_bytes
-            .try_into()
-            .map_err(|_| "invalid nonce".to_string())?,
-    );
-
-    let mut in_out = ciphertext.to_vec();
-    let plaintext = key
-        .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
-        .map_err(|_| "decryption failed".to_string())?;
-
-    String::from_utf8(plaintext.to_vec()).map_err(|e| format!("UTF-8 error: {e}"))
+    let plaintext = open(&derive_key(), encrypted)?;
+    String::from_utf8(plaintext).map_err(|e| format!("UTF-8 error: {e}"))
 }
 
 /// Key store with round-robin selection and rate-limit tracking.
@@ -151,6 +177,9 @@ pub struct KeyStore {
     keys: DashMap<String, ApiKeyEntry>,
     /// Round-robin counters keyed by provider label.
     provider_counters: DashMap<String, u64>,
+    /// Per-session key allow-lists (key ids or labels). Sessions without an
+    /// entry (or with an empty list) may use any active key.
+    session_keys: DashMap<String, Vec<String>>,
 }
 
 impl KeyStore {
@@ -158,6 +187,7 @@ impl KeyStore {
         Self {
             keys: DashMap::new(),
             provider_counters: DashMap::new(),
+            session_keys: DashMap::new(),
         }
     }
 
@@ -203,10 +233,46 @@ impl KeyStore {
     /// Select the next active key for a provider using round-robin.
     /// Returns (key_id, decrypted_key) or None if no active keys.
     pub fn select_key(&self, provider: &str) -> Option<(String, String)> {
+        self.select_key_from(provider, &[])
+    }
+
+    /// Restrict a session to a subset of keys (matched by id or label).
+    pub fn set_session_keys(&self, session_id: String, allowed: Vec<String>) {
+        self.session_keys.insert(session_id, allowed);
+    }
+
+    /// Key allow-list for a session (empty = all keys).
+    pub fn session_keys(&self, session_id: &str) -> Vec<String> {
+        self.session_keys
+            .get(session_id)
+            .map(|r| r.value().clone())
+            .unwrap_or_default()
+    }
+
+    /// Like [`select_key`](Self::select_key), but honours the session's key
+    /// allow-list when one is configured.
+    pub fn select_key_for_session(
+        &self,
+        provider: &str,
+        session_id: Option<&str>,
+    ) -> Option<(String, String)> {
+        let allowed = session_id
+            .map(|sid| self.session_keys(sid))
+            .unwrap_or_default();
+        self.select_key_from(provider, &allowed)
+    }
+
+    fn select_key_from(&self, provider: &str, allowed: &[String]) -> Option<(String, String)> {
         let mut active: Vec<_> = self
             .keys
             .iter()
             .filter(|r| r.value().provider == provider && r.value().active)
+            .filter(|r| {
+                allowed.is_empty()
+                    || allowed
+                        .iter()
+                        .any(|a| *a == r.value().id || *a == r.value().label)
+            })
             .map(|r| (r.key().clone(), r.value().key_encrypted.clone()))
             .collect();
 
@@ -347,6 +413,16 @@ mod tests {
         let _ = std::fs::remove_file(path);
     }
 
+    #[test]
+    fn passphrase_sealing_needs_the_same_passphrase_and_salt() {
+        let key = passphrase_key("correct horse", b"salt-1", 10);
+        let sealed = seal(&key, b"sk-ant-backup");
+        let same = passphrase_key("correct horse", b"salt-1", 10);
+        assert_eq!(open(&same, &sealed).unwrap(), b"sk-ant-backup");
+        assert!(open(&passphrase_key("wrong horse", b"salt-1", 10), &sealed).is_err());
+        assert!(open(&passphrase_key("correct horse", b"salt-2", 10), &sealed).is_err());
+    }
+
     #[test]
     fn encrypt_decrypt_roundtrip() {
         let key = "sk-ant-REDACTED";
@@ -427,6 +503,30 @@ mod tests {
         assert_eq!(key, "key-oai");
     }
 
+    #[test]
+    fn session_key_allow_list_restricts_selection() {
+        let store = KeyStore::new();
+        store.add_key("anthropic", "ant-team", "team");
+        store.add_key("anthropic", "ant-ci", "ci");
+        store.set_session_keys("s1".to_string(), vec!["ci".to_string()]);
+
+        for _ in 0..4 {
+            let (_, key) = store
+                .select_key_for_session("anthropic", Some("s1"))
+                .unwrap();
+            assert_eq!(key, "ant-ci");
+        }
+        // Unrestricted sessions still rotate over all keys
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..4 {
+            let (_, key) = store
+                .select_key_for_session("anthropic", Some("s2"))
+                .unwrap();
+            seen.insert(key);
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
     #[test]
     fn round_robin_is_independent_per_provider() {
         let store = KeyStore::new();
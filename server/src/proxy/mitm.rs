@@ -47,6 +47,9 @@ pub struct ApiRequestLog {
     /// Traffic category (Api, Telemetry, Auth, Update, Git, Unknown).
     /// None for regular reverse-proxy API requests, Some for CONNECT MITM requests.
     pub category: Option<String>,
+    /// Pre-flight check outcome (reverse-proxy LLM requests only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<super::preflight::PreflightReport>,
 }
 
 /// Builds a redacted log entry from request/response data
@@ -91,6 +94,7 @@ pub fn build_log(
         request_size: request_body.len(),
         response_size: response_body.len(),
         category: Some(super::classify::classify_url(url).to_string()),
+        preflight: None,
     }
 }
 
@@ -1,4 +1,5 @@
 pub mod audit;
+pub mod budget;
 pub mod classify;
 pub mod handler;
 pub mod inject;
@@ -6,9 +7,12 @@ pub mod keys;
 pub mod mitm;
 pub mod modes;
 pub mod persist;
+pub mod preflight;
+pub mod profiles;
 pub mod rewrite;
 pub mod rules;
 pub mod tls_mitm;
+pub mod upstream;
 pub mod websocket;
 
 use std::collections::{HashMap, VecDeque};
@@ -38,14 +42,17 @@ const EVENT_CHANNEL_CAPACITY: usize = 256;
 /// Attempts to load the mkcert CA for CONNECT MITM. If CA cert+key are not found,
 /// MITM is disabled and CONNECT tunnels fall back to transparent forwarding.
 pub fn create_proxy_state() -> (Arc<ProxyState>, broadcast::Receiver<ApiRequestLog>) {
-    let client = reqwest::Client::builder()
+    let builder = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(300))
         // Disable auto-decompression so we forward raw bytes 1:1 to the client.
         // The client's accept-encoding is forwarded as-is, and the upstream
         // response (possibly compressed) is passed through transparently.
         .no_gzip()
         .no_deflate()
-        .no_brotli()
+        .no_brotli();
+    // Egress via the corporate upstream proxy / CA bundle when configured.
+    let client = upstream::global()
+        .apply_to_client(builder)
         .build()
         .expect("failed to create HTTP client");
 
@@ -83,6 +90,9 @@ pub fn create_proxy_state() -> (Arc<ProxyState>, broadcast::Receiver<ApiRequestL
         inject_store: inject::InjectStore::new(),
         rewrite_store: rewrite::RewriteStore::new(),
         key_store: keys::KeyStore::new(),
+        budgets: budget::BudgetStore::new(),
+        preflight: preflight::PreflightStore::new(),
+        profiles: profiles::ProfileStore::new(),
     });
 
     (state, event_rx)
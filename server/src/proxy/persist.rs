@@ -1,10 +1,12 @@
 //! Config persistence — save/load per-session proxy configuration to disk.
 //!
-//! Saves proxy config (rules, mode, inject, rewrite) as JSON files in /data/noaide/.
-//! Debounced save (1s after last change), 30-day TTL cleanup on startup.
+//! Saves proxy config (rules, mode, inject, rewrite, budget, keys, preflight, profile) as JSON
+//! files in /data/noaide/.
+//! Debounced save (1s after last change). Old configs are removed by the
+//! retention job (`crate::retention`), 30 days by default.
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::{debug, info, warn};
 
 /// Combined proxy configuration for a session.
@@ -15,13 +17,30 @@ pub struct ProxyConfig {
     pub rewrite: super::rewrite::RewriteConfig,
     #[serde(default)]
     pub rules: Vec<super::rules::NetworkRule>,
+    /// Spend limits enforced by the reverse proxy.
+    #[serde(default)]
+    pub budget: super::budget::BudgetLimits,
+    /// Key ids or labels this session may rotate through (empty = all keys).
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Pre-flight check policy (warn / block / trim).
+    #[serde(default)]
+    pub preflight: super::preflight::PreflightPolicy,
+    /// Name of the profile this config was derived from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
 }
 
 /// Config directory for proxy persistence.
-fn config_dir() -> PathBuf {
+pub fn config_dir() -> PathBuf {
     PathBuf::from("/data/noaide")
 }
 
+/// Budget usage of the sessions that have limits (see [`super::budget`]).
+pub fn budget_usage_path() -> PathBuf {
+    config_dir().join("budget-usage.json")
+}
+
 /// Config file path for a session.
 fn config_path(session_id: &str) -> PathBuf {
     config_dir().join(format!("proxy-config-{session_id}.json"))
@@ -57,19 +76,16 @@ pub fn load_config(session_id: &str) -> Option<ProxyConfig> {
     }
 }
 
-/// Clean up config files older than `max_age` from the config directory.
-///
-/// Returns the number of files deleted.
-pub fn cleanup_old_configs(max_age: std::time::Duration) -> usize {
-    let dir = config_dir();
-    let mut deleted = 0;
-
-    let entries = match std::fs::read_dir(&dir) {
+/// Config files in `dir` last modified more than `max_age` ago, with their
+/// sizes.
+pub fn stale_configs(dir: &Path, max_age: std::time::Duration) -> Vec<(PathBuf, u64)> {
+    let entries = match std::fs::read_dir(dir) {
         Ok(e) => e,
-        Err(_) => return 0,
+        Err(_) => return vec![],
     };
 
     let now = std::time::SystemTime::now();
+    let mut stale = Vec::new();
 
     for entry in entries.flatten() {
         let path = entry.path();
@@ -85,14 +101,12 @@ pub fn cleanup_old_configs(max_age: std::time::Duration) -> usize {
             && let Ok(modified) = metadata.modified()
             && let Ok(age) = now.duration_since(modified)
             && age > max_age
-            && std::fs::remove_file(&path).is_ok()
         {
-            info!(path = %path.display(), age_days = age.as_secs() / 86400, "deleted old proxy config");
-            deleted += 1;
+            stale.push((path, metadata.len()));
         }
     }
 
-    deleted
+    stale
 }
 
 /// List all session IDs that have saved configs.
@@ -128,43 +142,40 @@ pub fn schedule_save(session_id: String, config: ProxyConfig) {
     });
 }
 
-/// Run cleanup of configs older than 30 days. Call at server startup.
-pub fn startup_cleanup() {
-    let max_age = std::time::Duration::from_secs(30 * 24 * 3600); // 30 days
-    let deleted = cleanup_old_configs(max_age);
-    if deleted > 0 {
-        info!(deleted = deleted, "cleaned up old proxy configs");
-    }
-}
-
 /// Load all persisted configs into the proxy state stores.
 /// Call at server startup after cleanup.
-pub async fn load_all_into_stores(
-    modes: &super::modes::ProxyModeStore,
-    inject: &super::inject::InjectStore,
-    rewrite: &super::rewrite::RewriteStore,
-    rules: &super::rules::NetworkRulesEngine,
-    intercept_modes: &tokio::sync::RwLock<
-        std::collections::HashMap<String, super::handler::InterceptMode>,
-    >,
-) -> usize {
+pub async fn load_all_into_stores(state: &super::ProxyState) -> usize {
     let sessions = list_saved_sessions();
     let mut loaded = 0;
-    let mut intercept_modes_guard = intercept_modes.write().await;
+    let mut intercept_modes_guard = state.intercept_modes.write().await;
     for sid in &sessions {
         if let Some(config) = load_config(sid) {
-            modes.set(sid.clone(), config.mode);
+            state.proxy_modes.set(sid.clone(), config.mode);
             if config.mode == super::modes::ProxyMode::Manual {
                 intercept_modes_guard.insert(sid.clone(), super::handler::InterceptMode::Manual);
             }
-            inject.set(sid.clone(), config.inject);
-            rewrite.set(sid.clone(), config.rewrite);
+            state.inject_store.set(sid.clone(), config.inject);
+            state.rewrite_store.set(sid.clone(), config.rewrite);
             if !config.rules.is_empty() {
-                rules.set_rules(sid, config.rules);
+                state.network_rules.set_rules(sid, config.rules);
+            }
+            if config.budget.is_limited() {
+                state.budgets.set(sid.clone(), config.budget);
+            }
+            state.preflight.set(sid.clone(), config.preflight);
+            if !config.keys.is_empty() {
+                state.key_store.set_session_keys(sid.clone(), config.keys);
+            }
+            if let Some(profile) = config.profile {
+                state.profiles.assign(sid.clone(), profile);
             }
             loaded += 1;
         }
     }
+    let usage = state.budgets.restore(budget_usage_path());
+    if usage > 0 {
+        info!(sessions = usage, "restored budget usage from disk");
+    }
     if loaded > 0 {
         info!(
             loaded = loaded,
@@ -206,7 +217,7 @@ mod tests {
                 model_override: Some("claude-sonnet-4-6".to_string()),
                 ..Default::default()
             },
-            rules: vec![],
+            ..Default::default()
         };
 
         let json = serde_json::to_string_pretty(&config).unwrap();
@@ -231,6 +242,15 @@ mod tests {
         assert_eq!(parsed.mode, super::super::modes::ProxyMode::Auto);
     }
 
+    #[test]
+    fn legacy_config_without_profile_fields_loads() {
+        let json = r#"{"mode":"auto","inject":{"presets":[],"custom_text":null},"rewrite":{}}"#;
+        let parsed: ProxyConfig = serde_json::from_str(json).unwrap();
+        assert!(parsed.profile.is_none());
+        assert!(parsed.keys.is_empty());
+        assert!(!parsed.budget.is_limited());
+    }
+
     #[test]
     fn cleanup_old_files() {
         let dir = test_dir();
@@ -0,0 +1,835 @@
+//! Pre-flight request checks — catch oversized or malformed requests before
+//! they reach the provider.
+//!
+//! Each outgoing LLM request gets a cheap input-token estimate (characters / 4,
+//! fixed cost per image) that is checked against a per-model context table, and
+//! its body is validated against provider request rules (`max_tokens` ranges,
+//! thinking configs made inconsistent by `rewrite` overrides, ...).
+//!
+//! The per-session [`PreflightPolicy`] decides what happens next: warn and
+//! forward, block locally, or auto-trim (fix what can be fixed and drop the
+//! oldest conversation turns until the request fits). The resulting
+//! [`PreflightReport`] is attached to the captured request log.
+//!
+//! The context table can be extended via `NOAIDE_MODEL_LIMITS_PATH`
+//! (default `/data/noaide/model-limits.json`, a JSON array of [`ModelLimits`]);
+//! entries there take precedence over the built-in table.
+
+use std::sync::OnceLock;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use super::handler::ApiProvider;
+
+/// Rough token cost of one image block (Anthropic: ~1.6k for a 1MP image).
+const IMAGE_TOKENS: u64 = 1600;
+
+/// Per-message framing overhead (role markers, separators).
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+
+/// Minimum extended-thinking budget accepted by Anthropic.
+const MIN_THINKING_BUDGET: u64 = 1024;
+
+/// What to do when a request fails pre-flight checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PreflightPolicy {
+    /// Skip checks entirely.
+    Off,
+    /// Log the findings and forward unchanged.
+    #[default]
+    Warn,
+    /// Reject locally with a provider-style 400.
+    Block,
+    /// Repair fixable fields and drop the oldest turns until the request fits.
+    Trim,
+}
+
+/// Context window and output ceiling for a model family.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelLimits {
+    /// Model name prefix, e.g. `claude-sonnet-4`.
+    pub pattern: String,
+    pub context_window: u64,
+    pub max_output_tokens: u64,
+}
+
+/// Final decision for a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreflightAction {
+    Pass,
+    Warn,
+    Block,
+    Trimmed,
+}
+
+/// A single finding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreflightIssue {
+    /// Stable machine-readable code, e.g. `context_overflow`.
+    pub code: String,
+    pub message: String,
+}
+
+/// Pre-flight outcome, attached to the request log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreflightReport {
+    pub policy: PreflightPolicy,
+    pub action: PreflightAction,
+    pub model: Option<String>,
+    pub estimated_input_tokens: u64,
+    pub context_window: Option<u64>,
+    pub issues: Vec<PreflightIssue>,
+    /// Fixes applied in trim mode (field repairs and dropped turns).
+    #[serde(default)]
+    pub fixes: Vec<String>,
+}
+
+/// Built-in context table. Longest matching prefix wins.
+pub fn default_limits() -> Vec<ModelLimits> {
+    [
+        ("claude-opus-4", 200_000, 32_000),
+        ("claude-sonnet-4", 200_000, 64_000),
+        ("claude-haiku-4", 200_000, 64_000),
+        ("claude-3-7-sonnet", 200_000, 64_000),
+        ("claude-3-5", 200_000, 8_192),
+        ("claude", 200_000, 8_192),
+        ("gpt-5", 400_000, 128_000),
+        ("gpt-4.1", 1_047_576, 32_768),
+        ("gpt-4o", 128_000, 16_384),
+        ("o3", 200_000, 100_000),
+        ("o4-mini", 200_000, 100_000),
+        ("gemini-2.5", 1_048_576, 65_536),
+        ("gemini", 1_048_576, 8_192),
+    ]
+    .into_iter()
+    .map(|(pattern, context_window, max_output_tokens)| ModelLimits {
+        pattern: pattern.to_string(),
+        context_window,
+        max_output_tokens,
+    })
+    .collect()
+}
+
+/// Location of the user's context table overrides.
+pub fn limits_path() -> String {
+    std::env::var("NOAIDE_MODEL_LIMITS_PATH")
+        .unwrap_or_else(|_| "/data/noaide/model-limits.json".to_string())
+}
+
+/// Effective context table: user overrides first, then built-ins.
+pub fn limits_table() -> &'static [ModelLimits] {
+    static TABLE: OnceLock<Vec<ModelLimits>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let path = limits_path();
+        let mut table: Vec<ModelLimits> = match std::fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(custom) => custom,
+                Err(e) => {
+                    warn!(path = %path, error = %e, "failed to parse model limits table");
+                    vec![]
+                }
+            },
+            Err(_) => vec![],
+        };
+        if !table.is_empty() {
+            info!(path = %path, entries = table.len(), "loaded custom model limits");
+        }
+        table.extend(default_limits());
+        table
+    })
+}
+
+/// Look up limits for `model`. Custom entries shadow built-ins with the same
+/// prefix; otherwise the longest matching prefix wins.
+pub fn lookup<'a>(table: &'a [ModelLimits], model: &str) -> Option<&'a ModelLimits> {
+    let model = model.strip_prefix("models/").unwrap_or(model);
+    let mut best: Option<&ModelLimits> = None;
+    for entry in table.iter().filter(|e| model.starts_with(&e.pattern)) {
+        if best.is_none_or(|b| entry.pattern.len() > b.pattern.len()) {
+            best = Some(entry);
+        }
+    }
+    best
+}
+
+/// Heuristic input-token estimate for a request body.
+///
+/// Counts ~4 characters per token over every string in the body, a fixed cost
+/// per image block (base64 payloads are not text), and a small per-message
+/// overhead.
+pub fn estimate_input_tokens(body: &Value) -> u64 {
+    let mut chars = 0u64;
+    let mut extra = 0u64;
+    walk_for_estimate(body, &mut chars, &mut extra);
+    let messages = conversation(body)
+        .map(|(_, items)| items.len())
+        .unwrap_or(0) as u64;
+    chars.div_ceil(4) + extra + messages * MESSAGE_OVERHEAD_TOKENS
+}
+
+fn walk_for_estimate(value: &Value, chars: &mut u64, extra: &mut u64) {
+    match value {
+        Value::String(s) => *chars += s.chars().count() as u64,
+        Value::Array(items) => {
+            for item in items {
+                walk_for_estimate(item, chars, extra);
+            }
+        }
+        Value::Object(map) => {
+            let is_image = map.get("type").and_then(|t| t.as_str()) == Some("image")
+                || map.get("type").and_then(|t| t.as_str()) == Some("image_url")
+                || map.get("type").and_then(|t| t.as_str()) == Some("input_image")
+                || map.contains_key("inlineData");
+            if is_image {
+                *extra += IMAGE_TOKENS;
+                return;
+            }
+            for (key, v) in map {
+                // Skip embedded binary payloads outside image blocks (documents).
+                if key == "data" && v.as_str().is_some_and(|s| s.len() > 1024) {
+                    continue;
+                }
+                walk_for_estimate(v, chars, extra);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Model named by the body, or by a Gemini-style path (`/models/{model}:generateContent`).
+pub fn extract_model(body: &Value, path: &str) -> Option<String> {
+    if let Some(m) = body.get("model").and_then(|m| m.as_str()) {
+        return Some(m.to_string());
+    }
+    let after = path.split("/models/").nth(1)?;
+    let model = after.split([':', '/', '?']).next()?;
+    (!model.is_empty()).then(|| model.to_string())
+}
+
+/// Locate the conversation array: (key path, items).
+fn conversation(body: &Value) -> Option<(&'static str, &Vec<Value>)> {
+    for key in ["messages", "input", "contents"] {
+        if let Some(items) = body.get(key).and_then(|v| v.as_array()) {
+            return Some((key, items));
+        }
+    }
+    body.get("request")
+        .and_then(|r| r.get("contents"))
+        .and_then(|v| v.as_array())
+        .map(|items| ("request.contents", items))
+}
+
+fn conversation_mut(body: &mut Value) -> Option<&mut Vec<Value>> {
+    let key = conversation(body)?.0;
+    match key {
+        "request.contents" => body.get_mut("request")?.get_mut("contents")?.as_array_mut(),
+        _ => body.get_mut(key)?.as_array_mut(),
+    }
+}
+
+/// Pointer to the output-token limit field for this body, if present.
+fn max_output_field(body: &Value, provider: ApiProvider) -> Option<&'static str> {
+    let candidates: &[&'static str] = match provider {
+        ApiProvider::Anthropic => &["/max_tokens"],
+        ApiProvider::OpenAI | ApiProvider::ChatGPT => &[
+            "/max_completion_tokens",
+            "/max_tokens",
+            "/max_output_tokens",
+        ],
+        ApiProvider::Google | ApiProvider::GoogleCodeAssist => &[
+            "/generationConfig/maxOutputTokens",
+            "/request/generationConfig/maxOutputTokens",
+        ],
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|ptr| body.pointer(ptr).is_some())
+}
+
+fn issue(code: &str, message: String) -> PreflightIssue {
+    PreflightIssue {
+        code: code.to_string(),
+        message,
+    }
+}
+
+/// Validate a request body against provider rules and model limits.
+pub fn validate(
+    body: &Value,
+    provider: ApiProvider,
+    limits: Option<&ModelLimits>,
+    estimated_input: u64,
+) -> Vec<PreflightIssue> {
+    let mut issues = Vec::new();
+    let max_field = max_output_field(body, provider);
+    let requested_output = max_field
+        .and_then(|ptr| body.pointer(ptr))
+        .and_then(|v| v.as_u64());
+
+    if provider == ApiProvider::Anthropic && body.get("messages").is_some() && max_field.is_none() {
+        issues.push(issue(
+            "max_tokens_missing",
+            "max_tokens is required by the Messages API".to_string(),
+        ));
+    }
+    if let Some(ptr) = max_field {
+        match body.pointer(ptr).and_then(|v| v.as_u64()) {
+            None | Some(0) => issues.push(issue(
+                "max_tokens_range",
+                format!("{} must be a positive integer", &ptr[1..]),
+            )),
+            Some(n) => {
+                if let Some(l) = limits
+                    && n > l.max_output_tokens
+                {
+                    issues.push(issue(
+                        "max_tokens_range",
+                        format!(
+                            "{} = {n} exceeds the {} output limit of {}",
+                            &ptr[1..],
+                            l.pattern,
+                            l.max_output_tokens
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    if matches!(provider, ApiProvider::OpenAI | ApiProvider::ChatGPT)
+        && body.get("max_tokens").is_some()
+        && body.get("max_completion_tokens").is_some()
+    {
+        issues.push(issue(
+            "max_tokens_conflict",
+            "max_tokens and max_completion_tokens are mutually exclusive".to_string(),
+        ));
+    }
+
+    if provider == ApiProvider::Anthropic
+        && let Some(thinking) = body.get("thinking")
+    {
+        let kind = thinking.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let budget = thinking.get("budget_tokens").and_then(|b| b.as_u64());
+        match kind {
+            "enabled" => {
+                match budget {
+                    None => issues.push(issue(
+                        "thinking_budget_missing",
+                        "thinking.type=enabled requires thinking.budget_tokens".to_string(),
+                    )),
+                    Some(b) if b < MIN_THINKING_BUDGET => issues.push(issue(
+                        "thinking_budget_range",
+                        format!("thinking.budget_tokens = {b} is below {MIN_THINKING_BUDGET}"),
+                    )),
+                    Some(b) if requested_output.is_some_and(|max| b >= max) => issues.push(issue(
+                        "thinking_budget_exceeds_max_tokens",
+                        format!(
+                            "thinking.budget_tokens = {b} must be below max_tokens = {}",
+                            requested_output.unwrap_or_default()
+                        ),
+                    )),
+                    _ => {}
+                }
+                if body
+                    .get("temperature")
+                    .and_then(|t| t.as_f64())
+                    .is_some_and(|t| (t - 1.0).abs() > f64::EPSILON)
+                {
+                    issues.push(issue(
+                        "thinking_temperature",
+                        "temperature must be 1 when extended thinking is enabled".to_string(),
+                    ));
+                }
+                if body.get("top_k").is_some() {
+                    issues.push(issue(
+                        "thinking_top_k",
+                        "top_k is not supported with extended thinking".to_string(),
+                    ));
+                }
+            }
+            "disabled" if budget.is_some() => issues.push(issue(
+                "thinking_mixed_config",
+                "thinking.type=disabled must not carry budget_tokens".to_string(),
+            )),
+            _ => {}
+        }
+    }
+
+    if let Some(l) = limits {
+        let total = estimated_input + requested_output.unwrap_or(0);
+        if total > l.context_window {
+            issues.push(issue(
+                "context_overflow",
+                format!(
+                    "~{estimated_input} input + {} output tokens exceed the {} context window of {}",
+                    requested_output.unwrap_or(0),
+                    l.pattern,
+                    l.context_window
+                ),
+            ));
+        }
+    }
+    issues
+}
+
+/// Repair fixable fields in place. Returns a description of each fix.
+fn apply_fixes(
+    body: &mut Value,
+    provider: ApiProvider,
+    limits: Option<&ModelLimits>,
+    issues: &[PreflightIssue],
+) -> Vec<String> {
+    let mut fixes = Vec::new();
+    let has = |code: &str| issues.iter().any(|i| i.code == code);
+
+    if has("max_tokens_conflict")
+        && let Some(obj) = body.as_object_mut()
+    {
+        obj.remove("max_tokens");
+        fixes.push("removed max_tokens (max_completion_tokens kept)".to_string());
+    }
+    if let Some(l) = limits {
+        if has("max_tokens_missing") {
+            body["max_tokens"] = Value::from(l.max_output_tokens);
+            fixes.push(format!("set max_tokens = {}", l.max_output_tokens));
+        } else if has("max_tokens_range")
+            && let Some(ptr) = max_output_field(body, provider)
+            && let Some(slot) = body.pointer_mut(ptr)
+        {
+            let clamped = slot.as_u64().unwrap_or(0).clamp(1, l.max_output_tokens);
+            *slot = Value::from(clamped);
+            fixes.push(format!("clamped {} to {clamped}", &ptr[1..]));
+        }
+    }
+
+    if provider == ApiProvider::Anthropic {
+        if has("thinking_mixed_config")
+            && let Some(thinking) = body.get_mut("thinking").and_then(|t| t.as_object_mut())
+        {
+            thinking.remove("budget_tokens");
+            fixes.push("removed thinking.budget_tokens from disabled thinking".to_string());
+        }
+        if has("thinking_temperature")
+            && let Some(obj) = body.as_object_mut()
+        {
+            obj.remove("temperature");
+            fixes.push("removed temperature (thinking enabled)".to_string());
+        }
+        if has("thinking_top_k")
+            && let Some(obj) = body.as_object_mut()
+        {
+            obj.remove("top_k");
+            fixes.push("removed top_k (thinking enabled)".to_string());
+        }
+        if has("thinking_budget_missing")
+            || has("thinking_budget_range")
+            || has("thinking_budget_exceeds_max_tokens")
+        {
+            let mut max_tokens = body.get("max_tokens").and_then(|m| m.as_u64()).unwrap_or(0);
+            if max_tokens <= MIN_THINKING_BUDGET {
+                max_tokens = MIN_THINKING_BUDGET * 2;
+                body["max_tokens"] = Value::from(max_tokens);
+            }
+            let budget = body
+                .pointer("/thinking/budget_tokens")
+                .and_then(|b| b.as_u64())
+                .unwrap_or(max_tokens / 2)
+                .clamp(MIN_THINKING_BUDGET, max_tokens - 1);
+            body["thinking"]["budget_tokens"] = Value::from(budget);
+            fixes.push(format!(
+                "set thinking.budget_tokens = {budget} (max_tokens = {max_tokens})"
+            ));
+        }
+    }
+    fixes
+}
+
+/// Whether a conversation may legally start with `item`.
+fn valid_first_turn(item: &Value, provider: ApiProvider) -> bool {
+    let role = item.get("role").and_then(|r| r.as_str()).unwrap_or("");
+    match provider {
+        ApiProvider::Anthropic => {
+            role == "user"
+                && !item
+                    .get("content")
+                    .and_then(|c| c.as_array())
+                    .is_some_and(|blocks| {
+                        blocks
+                            .iter()
+                            .any(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+                    })
+        }
+        ApiProvider::OpenAI | ApiProvider::ChatGPT => {
+            role == "user" || role == "system" || role == "developer"
+        }
+        ApiProvider::Google | ApiProvider::GoogleCodeAssist => {
+            role == "user"
+                && !item
+                    .get("parts")
+                    .and_then(|p| p.as_array())
+                    .is_some_and(|parts| parts.iter().any(|p| p.get("functionResponse").is_some()))
+        }
+    }
+}
+
+/// Drop the oldest turns until the request fits `context_window`, keeping
+/// leading system/developer messages and the latest turn. Returns the number
+/// of dropped items.
+fn trim_conversation(
+    body: &mut Value,
+    provider: ApiProvider,
+    context_window: u64,
+    requested_output: u64,
+) -> usize {
+    let is_system = |item: &Value| {
+        matches!(
+            item.get("role").and_then(|r| r.as_str()),
+            Some("system") | Some("developer")
+        )
+    };
+    let mut dropped = 0;
+    loop {
+        if estimate_input_tokens(body) + requested_output <= context_window {
+            break;
+        }
+        let Some(items) = conversation_mut(body) else {
+            break;
+        };
+        let first = items
+            .iter()
+            .position(|i| !is_system(i))
+            .unwrap_or(items.len());
+        if items.len() - first <= 1 {
+            break;
+        }
+        items.remove(first);
+        dropped += 1;
+        // Keep the conversation well-formed: skip orphaned assistant turns and
+        // tool results whose tool calls were just dropped.
+        while items.len() - first > 1 && !valid_first_turn(&items[first], provider) {
+            items.remove(first);
+            dropped += 1;
+        }
+    }
+    dropped
+}
+
+/// Run pre-flight checks on a request body. Returns `None` when the policy is
+/// `Off` or the body is not an LLM conversation request. In trim mode the body
+/// may be modified in place (check `action == Trimmed`).
+pub fn run(
+    body: &mut Value,
+    provider: ApiProvider,
+    path: &str,
+    policy: PreflightPolicy,
+) -> Option<PreflightReport> {
+    if policy == PreflightPolicy::Off {
+        return None;
+    }
+    conversation(body)?;
+    let model = extract_model(body, path);
+    let limits = model.as_deref().and_then(|m| lookup(limits_table(), m));
+    let estimated = estimate_input_tokens(body);
+    let issues = validate(body, provider, limits, estimated);
+
+    let mut report = PreflightReport {
+        policy,
+        action: PreflightAction::Pass,
+        model,
+        estimated_input_tokens: estimated,
+        context_window: limits.map(|l| l.context_window),
+        issues,
+        fixes: vec![],
+    };
+    if report.issues.is_empty() {
+        return Some(report);
+    }
+
+    report.action = match policy {
+        PreflightPolicy::Off | PreflightPolicy::Warn => PreflightAction::Warn,
+        PreflightPolicy::Block => PreflightAction::Block,
+        PreflightPolicy::Trim => {
+            let mut fixes = apply_fixes(body, provider, limits, &report.issues);
+            if let Some(l) = limits
+                && report.issues.iter().any(|i| i.code == "context_overflow")
+            {
+                let output = max_output_field(body, provider)
+                    .and_then(|ptr| body.pointer(ptr))
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0);
+                let dropped = trim_conversation(body, provider, l.context_window, output);
+                if dropped > 0 {
+                    fixes.push(format!("dropped {dropped} oldest conversation items"));
+                }
+            }
+            if fixes.is_empty() {
+                PreflightAction::Warn
+            } else {
+                report.fixes = fixes;
+                PreflightAction::Trimmed
+            }
+        }
+    };
+    Some(report)
+}
+
+/// Provider-style error body for a blocked request, so CLIs surface the reason.
+pub fn block_response_body(provider: ApiProvider, report: &PreflightReport) -> Value {
+    let message = format!(
+        "noaide preflight blocked request: {}",
+        report
+            .issues
+            .iter()
+            .map(|i| i.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    );
+    match provider {
+        ApiProvider::Anthropic => serde_json::json!({
+            "type": "error",
+            "error": {"type": "invalid_request_error", "message": message},
+        }),
+        ApiProvider::OpenAI | ApiProvider::ChatGPT => serde_json::json!({
+            "error": {"type": "invalid_request_error", "code": "preflight_blocked", "message": message},
+        }),
+        ApiProvider::Google | ApiProvider::GoogleCodeAssist => serde_json::json!({
+            "error": {"code": 400, "status": "INVALID_ARGUMENT", "message": message},
+        }),
+    }
+}
+
+/// Per-session pre-flight policy.
+pub struct PreflightStore {
+    policies: DashMap<String, PreflightPolicy>,
+}
+
+impl PreflightStore {
+    pub fn new() -> Self {
+        Self {
+            policies: DashMap::new(),
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> PreflightPolicy {
+        self.policies
+            .get(session_id)
+            .map(|r| *r.value())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, session_id: String, policy: PreflightPolicy) {
+        self.policies.insert(session_id, policy);
+    }
+}
+
+impl Default for PreflightStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn long_text(tokens: usize) -> String {
+        "abcd".repeat(tokens)
+    }
+
+    #[test]
+    fn lookup_prefers_longest_prefix() {
+        let table = default_limits();
+        assert_eq!(
+            lookup(&table, "claude-sonnet-4-6").unwrap().pattern,
+            "claude-sonnet-4"
+        );
+        assert_eq!(lookup(&table, "claude-2.1").unwrap().pattern, "claude");
+        assert_eq!(
+            lookup(&table, "models/gemini-2.5-pro").unwrap().pattern,
+            "gemini-2.5"
+        );
+        assert!(lookup(&table, "llama-3").is_none());
+    }
+
+    #[test]
+    fn estimate_counts_text_and_images() {
+        let body = json!({
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": long_text(100)},
+                    {"type": "image", "source": {"type": "base64", "data": "x".repeat(100_000)}}
+                ]}
+            ]
+        });
+        let est = estimate_input_tokens(&body);
+        // 100 text tokens + image + small overhead (role strings, type tags)
+        assert!((1700..1800).contains(&est), "estimate was {est}");
+    }
+
+    #[test]
+    fn extract_model_from_body_or_path() {
+        assert_eq!(
+            extract_model(&json!({"model": "gpt-4o"}), "/v1/chat/completions").as_deref(),
+            Some("gpt-4o")
+        );
+        assert_eq!(
+            extract_model(
+                &json!({"contents": []}),
+                "/v1beta/models/gemini-2.5-flash:streamGenerateContent"
+            )
+            .as_deref(),
+            Some("gemini-2.5-flash")
+        );
+    }
+
+    #[test]
+    fn detects_mixed_thinking_config_after_rewrite() {
+        let body = json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 4000,
+            "temperature": 0.2,
+            "thinking": {"type": "enabled", "budget_tokens": 8000},
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let limits = lookup(&default_limits(), "claude-sonnet-4-6").cloned();
+        let codes: Vec<_> = validate(&body, ApiProvider::Anthropic, limits.as_ref(), 10)
+            .into_iter()
+            .map(|i| i.code)
+            .collect();
+        assert!(codes.contains(&"thinking_budget_exceeds_max_tokens".to_string()));
+        assert!(codes.contains(&"thinking_temperature".to_string()));
+    }
+
+    #[test]
+    fn detects_max_tokens_out_of_range_and_conflict() {
+        let limits = lookup(&default_limits(), "gpt-4o").cloned();
+        let body = json!({
+            "model": "gpt-4o",
+            "max_tokens": 100,
+            "max_completion_tokens": 50_000,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let codes: Vec<_> = validate(&body, ApiProvider::OpenAI, limits.as_ref(), 10)
+            .into_iter()
+            .map(|i| i.code)
+            .collect();
+        assert_eq!(codes, vec!["max_tokens_range", "max_tokens_conflict"]);
+    }
+
+    #[test]
+    fn valid_request_passes() {
+        let mut body = json!({
+            "model": "claude-opus-4-6",
+            "max_tokens": 8000,
+            "messages": [{"role": "user", "content": "hello"}]
+        });
+        let report = run(
+            &mut body,
+            ApiProvider::Anthropic,
+            "/v1/messages",
+            PreflightPolicy::Block,
+        )
+        .unwrap();
+        assert_eq!(report.action, PreflightAction::Pass);
+        assert_eq!(report.context_window, Some(200_000));
+    }
+
+    #[test]
+    fn off_policy_and_non_conversation_bodies_skip() {
+        let mut body = json!({"model": "claude-opus-4-6", "messages": []});
+        assert!(run(&mut body, ApiProvider::Anthropic, "/", PreflightPolicy::Off).is_none());
+        let mut other = json!({"event": "telemetry"});
+        assert!(
+            run(
+                &mut other,
+                ApiProvider::Anthropic,
+                "/",
+                PreflightPolicy::Warn
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn block_policy_blocks_overflow() {
+        let mut body = json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": long_text(130_000)}]
+        });
+        let report = run(
+            &mut body,
+            ApiProvider::OpenAI,
+            "/v1/chat/completions",
+            PreflightPolicy::Block,
+        )
+        .unwrap();
+        assert_eq!(report.action, PreflightAction::Block);
+        assert_eq!(report.issues[0].code, "context_overflow");
+        let err = block_response_body(ApiProvider::OpenAI, &report);
+        assert!(
+            err["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("context window")
+        );
+    }
+
+    #[test]
+    fn trim_drops_oldest_turns_and_keeps_conversation_valid() {
+        let mut body = json!({
+            "model": "claude-haiku-4-5",
+            "max_tokens": 1000,
+            "messages": [
+                {"role": "user", "content": long_text(90_000)},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "Read", "input": {}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": long_text(90_000)}]},
+                {"role": "assistant", "content": "done"},
+                {"role": "user", "content": long_text(50_000)},
+                {"role": "assistant", "content": "ok"},
+                {"role": "user", "content": "next step"}
+            ]
+        });
+        let report = run(
+            &mut body,
+            ApiProvider::Anthropic,
+            "/v1/messages",
+            PreflightPolicy::Trim,
+        )
+        .unwrap();
+        assert_eq!(report.action, PreflightAction::Trimmed);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "user");
+        assert!(estimate_input_tokens(&body) + 1000 <= 200_000);
+    }
+
+    #[test]
+    fn trim_repairs_thinking_and_max_tokens() {
+        let mut body = json!({
+            "model": "claude-opus-4-6",
+            "max_tokens": 100_000,
+            "temperature": 0.5,
+            "thinking": {"type": "disabled", "budget_tokens": 2000},
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let report = run(
+            &mut body,
+            ApiProvider::Anthropic,
+            "/v1/messages",
+            PreflightPolicy::Trim,
+        )
+        .unwrap();
+        assert_eq!(report.action, PreflightAction::Trimmed);
+        assert_eq!(body["max_tokens"], 32_000);
+        assert!(body["thinking"].get("budget_tokens").is_none());
+        // temperature is fine while thinking is disabled
+        assert_eq!(body["temperature"], 0.5);
+    }
+}
@@ -0,0 +1,541 @@
+//! Named proxy profiles — reusable bundles of per-session proxy configuration.
+//!
+//! A profile captures mode, network rules, injection, rewrites, budget, key
+//! selection and an optional process sandbox under a name ("locked-down-ci",
+//! "cheap-exploration", ...). Profiles are applied when a managed session is
+//! spawned, either by explicit choice or by matching the session's working
+//! directory against `project_patterns`. Later per-session edits act as
+//! overrides on top; [`diff_config`] reports them.
+//!
+//! User-defined profiles persist to `/data/noaide/proxy-profiles.json`. Built-in
+//! profiles are always available and can be shadowed by a user profile of the
+//! same name.
+
+use std::path::{Path, PathBuf};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::budget::BudgetLimits;
+use super::inject::{InjectConfig, Preset};
+use super::modes::ProxyMode;
+use super::persist::ProxyConfig;
+use super::preflight::PreflightPolicy;
+use super::rewrite::RewriteConfig;
+use super::rules::NetworkRule;
+use crate::session::sandbox::SandboxConfig;
+
+/// A named, reusable proxy configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyProfile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Glob patterns (`*`, `?`) matched against a session's working directory.
+    #[serde(default)]
+    pub project_patterns: Vec<String>,
+    #[serde(default)]
+    pub mode: ProxyMode,
+    #[serde(default)]
+    pub inject: InjectConfig,
+    #[serde(default)]
+    pub rewrite: RewriteConfig,
+    #[serde(default)]
+    pub rules: Vec<NetworkRule>,
+    #[serde(default)]
+    pub budget: BudgetLimits,
+    /// Key ids or labels sessions under this profile may use (empty = all).
+    #[serde(default)]
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub preflight: PreflightPolicy,
+    /// Run managed sessions under this profile in a sandbox.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+    /// Shipped with noaide (not persisted, cannot be deleted).
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+impl ProxyProfile {
+    /// The per-session config this profile expands to.
+    pub fn to_config(&self, session_id: &str) -> ProxyConfig {
+        ProxyConfig {
+            mode: self.mode,
+            inject: self.inject.clone(),
+            rewrite: self.rewrite.clone(),
+            rules: self
+                .rules
+                .iter()
+                .cloned()
+                .map(|mut rule| {
+                    rule.session_id = session_id.to_string();
+                    rule
+                })
+                .collect(),
+            budget: self.budget.clone(),
+            keys: self.keys.clone(),
+            preflight: self.preflight,
+            profile: Some(self.name.clone()),
+        }
+    }
+
+    /// Whether any of this profile's project patterns match `path`.
+    pub fn matches_project(&self, path: &str) -> bool {
+        self.project_patterns.iter().any(|p| glob_match(p, path))
+    }
+}
+
+/// Profiles shipped with noaide.
+pub fn builtin_profiles() -> Vec<ProxyProfile> {
+    vec![
+        ProxyProfile {
+            name: "locked-down-ci".to_string(),
+            description:
+                "Lockdown mode, no injected context and a hard spend cap for unattended runs"
+                    .to_string(),
+            project_patterns: vec![],
+            mode: ProxyMode::Lockdown,
+            inject: InjectConfig {
+                presets: vec![],
+                custom_text: None,
+            },
+            rewrite: RewriteConfig::default(),
+            rules: vec![],
+            budget: BudgetLimits {
+                max_cost_usd: Some(5.0),
+                max_requests: Some(500),
+            },
+            keys: vec![],
+            preflight: PreflightPolicy::Block,
+            sandbox: None,
+            builtin: true,
+        },
+        ProxyProfile {
+            name: "cheap-exploration".to_string(),
+            description: "Small model, capped output and a low budget for throwaway exploration"
+                .to_string(),
+            project_patterns: vec![],
+            mode: ProxyMode::Auto,
+            inject: InjectConfig {
+                presets: vec![Preset::NoaideContext, Preset::Speed],
+                custom_text: None,
+            },
+            rewrite: RewriteConfig {
+                model_override: Some("claude-haiku-4-5".to_string()),
+                max_tokens: Some(8192),
+                ..Default::default()
+            },
+            rules: vec![],
+            budget: BudgetLimits {
+                max_cost_usd: Some(1.0),
+                max_requests: None,
+            },
+            keys: vec![],
+            preflight: PreflightPolicy::Trim,
+            sandbox: None,
+            builtin: true,
+        },
+        ProxyProfile {
+            name: "pair-review".to_string(),
+            description: "Manual intercept with evidence-first answers for reviewing together"
+                .to_string(),
+            project_patterns: vec![],
+            mode: ProxyMode::Manual,
+            inject: InjectConfig {
+                presets: vec![Preset::NoaideContext, Preset::VerifyEvidence],
+                custom_text: None,
+            },
+            rewrite: RewriteConfig::default(),
+            rules: vec![],
+            budget: BudgetLimits::default(),
+            keys: vec![],
+            preflight: PreflightPolicy::Warn,
+            sandbox: None,
+            builtin: true,
+        },
+    ]
+}
+
+/// Profile definitions plus the session → profile assignment.
+pub struct ProfileStore {
+    profiles: DashMap<String, ProxyProfile>,
+    assignments: DashMap<String, String>,
+}
+
+impl ProfileStore {
+    pub fn new() -> Self {
+        let profiles = DashMap::new();
+        for profile in builtin_profiles() {
+            profiles.insert(profile.name.clone(), profile);
+        }
+        Self {
+            profiles,
+            assignments: DashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<ProxyProfile> {
+        self.profiles.get(name).map(|r| r.value().clone())
+    }
+
+    /// All profiles, sorted by name.
+    pub fn list(&self) -> Vec<ProxyProfile> {
+        let mut list: Vec<_> = self.profiles.iter().map(|r| r.value().clone()).collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Insert or replace a user profile (shadows a built-in of the same name).
+    pub fn upsert(&self, mut profile: ProxyProfile) {
+        profile.builtin = false;
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+
+    /// Remove a user profile. Removing a shadowed built-in restores the
+    /// original; built-ins themselves cannot be removed.
+    pub fn remove(&self, name: &str) -> bool {
+        let Some(existing) = self.get(name) else {
+            return false;
+        };
+        if existing.builtin {
+            return false;
+        }
+        match builtin_profiles().into_iter().find(|p| p.name == name) {
+            Some(original) => {
+                self.profiles.insert(name.to_string(), original);
+            }
+            None => {
+                self.profiles.remove(name);
+            }
+        }
+        true
+    }
+
+    /// Most specific profile whose project pattern matches `path`
+    /// (longest matching pattern wins).
+    pub fn match_project(&self, path: &str) -> Option<ProxyProfile> {
+        self.profiles
+            .iter()
+            .filter_map(|r| {
+                let best = r
+                    .value()
+                    .project_patterns
+                    .iter()
+                    .filter(|p| glob_match(p, path))
+                    .map(|p| p.len())
+                    .max()?;
+                Some((best, r.value().clone()))
+            })
+            .max_by(|(la, a), (lb, b)| la.cmp(lb).then_with(|| b.name.cmp(&a.name)))
+            .map(|(_, profile)| profile)
+    }
+
+    pub fn assign(&self, session_id: String, profile: String) {
+        self.assignments.insert(session_id, profile);
+    }
+
+    pub fn unassign(&self, session_id: &str) {
+        self.assignments.remove(session_id);
+    }
+
+    /// Profile name assigned to a session, if any.
+    pub fn assigned(&self, session_id: &str) -> Option<String> {
+        self.assignments.get(session_id).map(|r| r.value().clone())
+    }
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Apply a profile to a session: populate every per-session store and record
+/// the assignment. Returns the resulting config so the caller can persist it.
+pub async fn apply_profile(
+    state: &super::ProxyState,
+    session_id: &str,
+    profile: &ProxyProfile,
+) -> ProxyConfig {
+    let config = profile.to_config(session_id);
+    let sid = session_id.to_string();
+    state.proxy_modes.set(sid.clone(), config.mode);
+    let intercept = if config.mode == ProxyMode::Manual {
+        super::handler::InterceptMode::Manual
+    } else {
+        super::handler::InterceptMode::Auto
+    };
+    state
+        .intercept_modes
+        .write()
+        .await
+        .insert(sid.clone(), intercept);
+    state.inject_store.set(sid.clone(), config.inject.clone());
+    state.rewrite_store.set(sid.clone(), config.rewrite.clone());
+    state.network_rules.set_rules(&sid, config.rules.clone());
+    state.budgets.set(sid.clone(), config.budget.clone());
+    state
+        .key_store
+        .set_session_keys(sid.clone(), config.keys.clone());
+    state.preflight.set(sid.clone(), config.preflight);
+    state.profiles.assign(sid, profile.name.clone());
+    info!(session = %session_id, profile = %profile.name, "applied proxy profile");
+    config
+}
+
+/// A single field where a session's effective config departs from its profile.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigOverride {
+    /// Dotted path, e.g. `rewrite.model_override`.
+    pub field: String,
+    pub profile: serde_json::Value,
+    pub effective: serde_json::Value,
+}
+
+/// Compare a session's effective config against the config its profile would
+/// produce. Objects are compared field by field; arrays and scalars as a whole.
+pub fn diff_config(profile: &ProxyConfig, effective: &ProxyConfig) -> Vec<ConfigOverride> {
+    let base = serde_json::to_value(profile).unwrap_or_default();
+    let current = serde_json::to_value(effective).unwrap_or_default();
+    let mut out = Vec::new();
+    diff_values("", &base, &current, &mut out);
+    out.retain(|o| o.field != "profile");
+    out
+}
+
+fn diff_values(
+    prefix: &str,
+    base: &serde_json::Value,
+    current: &serde_json::Value,
+    out: &mut Vec<ConfigOverride>,
+) {
+    if let (Some(a), Some(b)) = (base.as_object(), current.as_object()) {
+        let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let null = serde_json::Value::Null;
+        for key in keys {
+            let field = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            diff_values(
+                &field,
+                a.get(key).unwrap_or(&null),
+                b.get(key).unwrap_or(&null),
+                out,
+            );
+        }
+    } else if base != current {
+        out.push(ConfigOverride {
+            field: prefix.to_string(),
+            profile: base.clone(),
+            effective: current.clone(),
+        });
+    }
+}
+
+/// Minimal glob matcher: `*` matches any run of characters (including `/`),
+/// `?` matches exactly one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+// ── Persistence ─────────────────────────────────────────────────────────────
+
+#[derive(Serialize, Deserialize)]
+struct PersistedProfiles {
+    profiles: Vec<ProxyProfile>,
+}
+
+pub fn profiles_path() -> PathBuf {
+    std::env::var("NOAIDE_PROXY_PROFILES_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("/data/noaide/proxy-profiles.json"))
+}
+
+fn save_to_path(store: &ProfileStore, path: &Path) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let profiles: Vec<_> = store.list().into_iter().filter(|p| !p.builtin).collect();
+    let json = serde_json::to_string_pretty(&PersistedProfiles { profiles })
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    std::fs::write(path, json)
+}
+
+fn load_from_path(store: &ProfileStore, path: &Path) -> Result<usize, std::io::Error> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let payload: PersistedProfiles = serde_json::from_str(&json)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let count = payload.profiles.len();
+    for profile in payload.profiles {
+        store.upsert(profile);
+    }
+    Ok(count)
+}
+
+pub fn save_to_disk(store: &ProfileStore) -> Result<(), std::io::Error> {
+    save_to_path(store, &profiles_path())
+}
+
+pub fn load_from_disk(store: &ProfileStore) -> Result<usize, std::io::Error> {
+    let path = profiles_path();
+    let loaded = load_from_path(store, &path)?;
+    if loaded > 0 {
+        info!(path = %path.display(), loaded = loaded, "restored proxy profiles from disk");
+    }
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(name: &str, patterns: &[&str]) -> ProxyProfile {
+        ProxyProfile {
+            name: name.to_string(),
+            description: String::new(),
+            project_patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            mode: ProxyMode::Auto,
+            inject: InjectConfig::default(),
+            rewrite: RewriteConfig::default(),
+            rules: vec![],
+            budget: BudgetLimits::default(),
+            keys: vec![],
+            preflight: PreflightPolicy::Warn,
+            sandbox: None,
+            builtin: false,
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("/work/*", "/work/noaide"));
+        assert!(glob_match("*/ci-*", "/home/runner/ci-42"));
+        assert!(glob_match("/work/proj-?", "/work/proj-a"));
+        assert!(!glob_match("/work/proj-?", "/work/proj-ab"));
+        assert!(!glob_match("/work/*", "/home/work"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn builtins_are_present_and_protected() {
+        let store = ProfileStore::new();
+        let names: Vec<_> = store.list().into_iter().map(|p| p.name).collect();
+        assert_eq!(
+            names,
+            vec!["cheap-exploration", "locked-down-ci", "pair-review"]
+        );
+        assert!(!store.remove("locked-down-ci"));
+        assert!(store.get("locked-down-ci").is_some());
+    }
+
+    #[test]
+    fn removing_shadowed_builtin_restores_original() {
+        let store = ProfileStore::new();
+        store.upsert(custom("pair-review", &[]));
+        assert_eq!(store.get("pair-review").unwrap().mode, ProxyMode::Auto);
+        assert!(store.remove("pair-review"));
+        let restored = store.get("pair-review").unwrap();
+        assert!(restored.builtin);
+        assert_eq!(restored.mode, ProxyMode::Manual);
+    }
+
+    #[test]
+    fn most_specific_project_pattern_wins() {
+        let store = ProfileStore::new();
+        store.upsert(custom("broad", &["/work/*"]));
+        store.upsert(custom("narrow", &["/work/ci/*"]));
+        assert_eq!(store.match_project("/work/ci/job").unwrap().name, "narrow");
+        assert_eq!(store.match_project("/work/app").unwrap().name, "broad");
+        assert!(store.match_project("/tmp/x").is_none());
+    }
+
+    #[test]
+    fn diff_reports_only_overrides() {
+        let profile = builtin_profiles()
+            .into_iter()
+            .find(|p| p.name == "cheap-exploration")
+            .unwrap();
+        let base = profile.to_config("s1");
+        assert!(diff_config(&base, &base).is_empty());
+
+        let mut effective = base.clone();
+        effective.rewrite.model_override = Some("claude-sonnet-4-6".to_string());
+        effective.budget.max_requests = Some(10);
+        let diff = diff_config(&base, &effective);
+        let fields: Vec<_> = diff.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["budget.max_requests", "rewrite.model_override"]
+        );
+        assert_eq!(diff[1].profile, serde_json::json!("claude-haiku-4-5"));
+    }
+
+    #[test]
+    fn persist_roundtrip_skips_builtins() {
+        let path = std::env::temp_dir().join(format!(
+            "noaide-profiles-test-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        let store = ProfileStore::new();
+        store.upsert(custom("team", &["/work/team-*"]));
+        save_to_path(&store, &path).unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("locked-down-ci"));
+
+        let restored = ProfileStore::new();
+        assert_eq!(load_from_path(&restored, &path).unwrap(), 1);
+        assert_eq!(
+            restored.get("team").unwrap().project_patterns,
+            vec!["/work/team-*"]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn apply_profile_populates_session_stores() {
+        let (state, _rx) = crate::proxy::create_proxy_state();
+        let profile = state.profiles.get("pair-review").unwrap();
+        let config = apply_profile(&state, "s1", &profile).await;
+        assert_eq!(config.profile.as_deref(), Some("pair-review"));
+        assert_eq!(state.proxy_modes.get("s1"), ProxyMode::Manual);
+        assert_eq!(
+            state.intercept_modes.read().await.get("s1"),
+            Some(&super::super::handler::InterceptMode::Manual)
+        );
+        assert_eq!(
+            state.profiles.assigned("s1").as_deref(),
+            Some("pair-review")
+        );
+    }
+}
@@ -39,7 +39,7 @@ pub struct CaAuthority {
     ca_cert_der: CertificateDer<'static>,
     /// Per-hostname cert cache (lock-free concurrent reads).
     cert_cache: Arc<DashMap<String, CachedCert>>,
-    /// Shared TLS client config for connecting to target hosts (platform roots).
+    /// Shared TLS client config for connecting to target hosts (platform + upstream roots).
     target_tls_config: Arc<rustls::ClientConfig>,
 }
 
@@ -90,16 +90,9 @@ impl CaAuthority {
         let ca_cert_pem = pem::parse(cert_pem).map_err(|e| anyhow::anyhow!("parse CA PEM: {e}"))?;
         let ca_cert_der = CertificateDer::from(ca_cert_pem.contents().to_vec());
 
-        // Build TLS client config for connecting to real target hosts
-        let mut root_store = rustls::RootCertStore::empty();
-        for cert in rustls_native_certs::load_native_certs().certs {
-            let _ = root_store.add(cert);
-        }
-        let target_tls_config = Arc::new(
-            rustls::ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth(),
-        );
+        // TLS client config for connecting to real target hosts (platform roots
+        // plus the upstream CA bundle for TLS-inspecting corporate gateways)
+        let target_tls_config = super::upstream::tls_client_config();
 
         let cert_cache = Arc::new(DashMap::new());
 
@@ -242,13 +235,22 @@ fn find_file(env_var: &str, explicit_paths: &[&str], mkcert_filename: &str) -> O
 ///
 /// Search order: `NOAIDE_CA_CERT` env -> `./certs/rootCA.pem` -> `~/.local/share/mkcert/rootCA.pem`
 pub fn find_ca_cert_path() -> Option<String> {
-    if let Ok(path) = std::env::var("NOAIDE_CA_CERT")
+    find_path("NOAIDE_CA_CERT", "./certs/rootCA.pem", "rootCA.pem")
+}
+
+/// Find the CA private key file path, in the same order as
+/// [`find_ca_cert_path`] (`NOAIDE_CA_KEY`, `./certs/rootCA-key.pem`, mkcert).
+pub fn find_ca_key_path() -> Option<String> {
+    find_path("NOAIDE_CA_KEY", "./certs/rootCA-key.pem", "rootCA-key.pem")
+}
+
+fn find_path(env_var: &str, certs_path: &str, mkcert_filename: &str) -> Option<String> {
+    if let Ok(path) = std::env::var(env_var)
         && std::fs::metadata(&path).is_ok()
     {
         return Some(path);
     }
 
-    let certs_path = "./certs/rootCA.pem";
     if std::fs::metadata(certs_path).is_ok() {
         // Return absolute path for child processes
         if let Ok(abs) = std::fs::canonicalize(certs_path) {
@@ -258,7 +260,7 @@ pub fn find_ca_cert_path() -> Option<String> {
     }
 
     if let Ok(home) = std::env::var("HOME") {
-        let mkcert_path = format!("{home}/.local/share/mkcert/rootCA.pem");
+        let mkcert_path = format!("{home}/.local/share/mkcert/{mkcert_filename}");
         if std::fs::metadata(&mkcert_path).is_ok() {
             return Some(mkcert_path);
         }
@@ -5,6 +5,10 @@
 //! bidirectionally. Text frames are logged as ApiRequestLog entries with method
 //! "WS-OUT" (client→upstream) and "WS-IN" (upstream→client). Binary frames are
 //! logged as metadata only (size + opcode).
+//!
+//! Each `response.create` frame is an API request: it goes through the same
+//! checks as reverse-proxy requests ([`super::handler::admit`]) and counts
+//! against the session budget. A refused frame closes the socket with 1008.
 
 use std::sync::Arc;
 use std::time::Instant;
@@ -38,7 +42,7 @@ pub async fn relay_frames(
     let url_for_out = url.clone();
     let state_for_out = state.clone();
 
-    // Client → Upstream (WS-OUT)
+    // Client → Upstream (WS-OUT). Yields the refusal if a frame was refused.
     let client_to_upstream = async {
         while let Some(msg_result) = client_rx.next().await {
             let msg = match msg_result {
@@ -58,6 +62,29 @@ pub async fn relay_frames(
                         &state_for_out,
                     );
 
+                    if let Some(ref sid) = session_for_out
+                        && starts_response(&outbound)
+                    {
+                        let request_id = uuid::Uuid::new_v4().to_string();
+                        let req = super::handler::Outbound {
+                            request_id: &request_id,
+                            session_id: Some(sid),
+                            method: "WS-OUT",
+                            url: &url_for_out,
+                            category: "api",
+                            provider: detect_ws_provider(&url_for_out),
+                            path: "",
+                            start: Instant::now(),
+                        };
+                        if let Err(refusal) =
+                            super::handler::admit(&state_for_out, &req, None).await
+                        {
+                            let _ = upstream_tx.send(TungMessage::Close(None)).await;
+                            return Some(refusal);
+                        }
+                        state_for_out.budgets.record_request(sid);
+                    }
+
                     // Log text frame as WS-OUT
                     log_ws_frame(
                         &session_for_out,
@@ -115,6 +142,7 @@ pub async fn relay_frames(
                 }
             }
         }
+        None
     };
 
     // Upstream → Client (WS-IN)
@@ -131,7 +159,7 @@ pub async fn relay_frames(
             match msg {
                 TungMessage::Text(text) => {
                     // Log text frame as WS-IN
-                    log_ws_frame(
+                    let mut log_entry = log_ws_frame(
                         &session_id,
                         &url,
                         "WS-IN",
@@ -141,6 +169,12 @@ pub async fn relay_frames(
                     )
                     .await;
 
+                    // Charge the finished response (the logged body may be truncated)
+                    if text.contains("\"response.completed\"") {
+                        log_entry.response_body = text.to_string();
+                        super::handler::record_usage(&state, &log_entry, "chatgpt");
+                    }
+
                     if client_tx
                         .send(axum::extract::ws::Message::Text(text.to_string().into()))
                         .await
@@ -196,9 +230,19 @@ pub async fn relay_frames(
     };
 
     // Run both directions concurrently; stop when either side closes
-    tokio::select! {
-        _ = client_to_upstream => {}
-        _ = upstream_to_client => {}
+    let refused = tokio::select! {
+        refused = client_to_upstream => refused,
+        _ = upstream_to_client => None,
+    };
+    if let Some(refusal) = refused {
+        let _ = client_tx
+            .send(axum::extract::ws::Message::Close(Some(
+                axum::extract::ws::CloseFrame {
+                    code: axum::extract::ws::close_code::POLICY,
+                    reason: refusal.reason.into(),
+                },
+            )))
+            .await;
     }
 
     info!(url = %url_for_out, "WebSocket relay ended");
@@ -256,6 +300,12 @@ fn transform_outgoing_text_frame(
     }
 }
 
+/// Whether a (transformed) outgoing frame asks the upstream for a response.
+fn starts_response(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text)
+        .is_ok_and(|json| json.get("type").and_then(|t| t.as_str()) == Some("response.create"))
+}
+
 fn detect_ws_provider(url: &str) -> Option<super::handler::ApiProvider> {
     let parsed = reqwest::Url::parse(url).ok()?;
     let host = parsed.host_str()?;
@@ -271,6 +321,7 @@ fn detect_ws_provider(url: &str) -> Option<super::handler::ApiProvider> {
 /// Log a single WebSocket frame as an ApiRequestLog entry.
 ///
 /// Text frames include the (redacted) body content; binary frames log only metadata.
+/// Returns the captured entry.
 async fn log_ws_frame(
     session_id: &Option<String>,
     url: &str,
@@ -278,7 +329,7 @@ async fn log_ws_frame(
     text_body: Option<&str>,
     frame_size: usize,
     state: &super::handler::ProxyState,
-) {
+) -> ApiRequestLog {
     let start = Instant::now();
 
     let body_str = match text_body {
@@ -320,17 +371,12 @@ async fn log_ws_frame(
         request_size: if method == "WS-OUT" { frame_size } else { 0 },
         response_size: if method == "WS-IN" { frame_size } else { 0 },
         category: Some("Api".to_string()),
+        preflight: None,
     };
 
     // Store in captured buffer + broadcast
-    {
-        let mut captured = state.captured.write().await;
-        if captured.len() >= super::handler::MAX_CAPTURED_REQUESTS {
-            captured.pop_front();
-        }
-        captured.push_back(log_entry.clone());
-    }
-    let _ = state.event_tx.send(log_entry);
+    super::handler::capture(state, log_entry.clone()).await;
+    log_entry
 }
 
 /// Check if request headers indicate a WebSocket upgrade.
@@ -554,6 +600,15 @@ mod tests {
         assert_eq!(transformed, original_text);
     }
 
+    #[test]
+    fn only_response_create_frames_start_a_response() {
+        assert!(starts_response(
+            r#"{"type":"response.create","response":{}}"#
+        ));
+        assert!(!starts_response(r#"{"type":"session.update"}"#));
+        assert!(!starts_response("not json"));
+    }
+
     #[test]
     fn detects_chatgpt_ws_provider() {
         assert_eq!(
@@ -582,6 +637,9 @@ mod tests {
             inject_store: super::super::inject::InjectStore::new(),
             rewrite_store: super::super::rewrite::RewriteStore::new(),
             key_store: super::super::keys::KeyStore::new(),
+            budgets: super::super::budget::BudgetStore::new(),
+            preflight: super::super::preflight::PreflightStore::new(),
+            profiles: super::super::profiles::ProfileStore::new(),
         }
     }
 }
@@ -0,0 +1,121 @@
+//! Cold storage for archived sessions.
+//!
+//! One zstd-compressed JSON Lines file per session,
+//! `<archive_dir>/<session-id>.jsonl.zst`. The first line is an
+//! [`ArchiveHeader`] so listing only decompresses a few bytes per file;
+//! every further line is the [`TableRows`] of one table.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::TableRows;
+
+/// Archive layout version.
+pub const FORMAT: u32 = 1;
+
+const EXTENSION: &str = ".jsonl.zst";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveHeader {
+    pub format: u32,
+    /// Database schema version the rows were exported from.
+    pub schema_version: u32,
+    pub session_id: Uuid,
+    /// Epoch seconds.
+    pub archived_at: i64,
+    /// Last message or request before archiving, epoch seconds.
+    pub last_activity: i64,
+    /// Row count per table.
+    pub rows: BTreeMap<String, u64>,
+}
+
+/// An archive on disk.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSummary {
+    #[serde(flatten)]
+    pub header: ArchiveHeader,
+    /// Compressed file size.
+    pub bytes: u64,
+}
+
+pub fn archive_path(dir: &Path, session_id: &Uuid) -> PathBuf {
+    dir.join(format!("{session_id}{EXTENSION}"))
+}
+
+/// Write an archive, replacing any previous one for the session.
+pub fn write(dir: &Path, header: &ArchiveHeader, tables: &[TableRows]) -> std::io::Result<u64> {
+    std::fs::create_dir_all(dir)?;
+    let path = archive_path(dir, &header.session_id);
+    let tmp = path.with_extension("zst.tmp");
+    {
+        let file = std::fs::File::create(&tmp)?;
+        let mut encoder = zstd::Encoder::new(file, 9)?;
+        serde_json::to_writer(&mut encoder, header)?;
+        encoder.write_all(b"\n")?;
+        for table in tables {
+            serde_json::to_writer(&mut encoder, table)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.sync_all()?;
+    }
+    std::fs::rename(&tmp, &path)?;
+    Ok(std::fs::metadata(&path)?.len())
+}
+
+fn open(path: &Path) -> std::io::Result<(ArchiveHeader, impl BufRead + use<>)> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = BufReader::new(zstd::Decoder::new(file)?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let header: ArchiveHeader = serde_json::from_str(&line)?;
+    if header.format > FORMAT {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("archive format {} is newer than this build", header.format),
+        ));
+    }
+    Ok((header, reader))
+}
+
+/// Read a whole archive.
+pub fn read(dir: &Path, session_id: &Uuid) -> std::io::Result<(ArchiveHeader, Vec<TableRows>)> {
+    let (header, reader) = open(&archive_path(dir, session_id))?;
+    let mut tables = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            tables.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok((header, tables))
+}
+
+/// Every readable archive in `dir`, oldest archive first.
+pub fn list(dir: &Path) -> Vec<ArchiveSummary> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<ArchiveSummary> = entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with(EXTENSION))
+        .filter_map(|e| {
+            let (header, _) = open(&e.path()).ok()?;
+            Some(ArchiveSummary {
+                header,
+                bytes: e.metadata().ok()?.len(),
+            })
+        })
+        .collect();
+    out.sort_by_key(|a| a.header.archived_at);
+    out
+}
+
+pub fn remove(dir: &Path, session_id: &Uuid) -> std::io::Result<()> {
+    std::fs::remove_file(archive_path(dir, session_id))
+}
@@ -0,0 +1,751 @@
+//! Retention policies and compaction for stored data.
+//!
+//! Captured API requests, the audit log, proxy configs and terminal
+//! recordings are each trimmed against their [`Limits`]. Sessions that have been idle longer than the
+//! archive threshold leave the database for compressed [`archive`] files
+//! and can be restored on demand. Every run produces a
+//! [`CompactionReport`]; a dry run computes the same report and changes
+//! nothing.
+
+pub mod archive;
+mod policy;
+
+pub use policy::{ArchivePolicy, Limits, PolicyError, RetentionPolicy};
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::db::{Db, schema};
+use crate::proxy::{audit, persist};
+use crate::session::recording;
+use archive::{ArchiveHeader, ArchiveSummary};
+
+/// Delay before the first scheduled run, so startup isn't slowed down.
+const FIRST_RUN_DELAY: Duration = Duration::from_secs(120);
+
+const DAY_MS: i64 = 86_400_000;
+
+/// Where each data class lives on disk.
+#[derive(Debug, Clone)]
+pub struct StoragePaths {
+    pub policy: PathBuf,
+    pub audit_log: PathBuf,
+    /// Directory holding `proxy-config-*.json`.
+    pub proxy_configs: PathBuf,
+    pub archive: PathBuf,
+    pub recordings: PathBuf,
+}
+
+impl Default for StoragePaths {
+    fn default() -> Self {
+        let data_dir = persist::config_dir();
+        Self {
+            policy: data_dir.join("retention.json"),
+            audit_log: audit::audit_log_path(),
+            archive: data_dir.join("archive"),
+            recordings: recording::recordings_dir(),
+            proxy_configs: data_dir,
+        }
+    }
+}
+
+/// What a run removed from one data class (or would have, in a dry run).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassReport {
+    pub removed: u64,
+    pub removed_bytes: u64,
+    /// Removed for being older than `maxAgeDays`.
+    pub by_age: u64,
+    /// Removed for exceeding `maxPerSession`.
+    pub by_count: u64,
+    /// Removed for exceeding `maxBytes`.
+    pub by_size: u64,
+    pub kept: u64,
+    pub kept_bytes: u64,
+}
+
+/// A session moved to cold storage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedSession {
+    pub session_id: Uuid,
+    /// Epoch seconds.
+    pub last_activity: i64,
+    pub rows: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveReport {
+    pub archived: Vec<ArchivedSession>,
+    /// Archives deleted for exceeding `deleteAfterDays`.
+    pub expired: Vec<Uuid>,
+    pub expired_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionReport {
+    pub dry_run: bool,
+    /// Epoch seconds.
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub archive: ArchiveReport,
+    pub api_requests: ClassReport,
+    pub audit_log: ClassReport,
+    pub proxy_configs: ClassReport,
+    pub recordings: ClassReport,
+    /// Steps that failed; the rest of the run still happened.
+    pub errors: Vec<String>,
+}
+
+/// One item of a data class, as seen by [`select`].
+struct Item {
+    session: Option<String>,
+    /// Epoch milliseconds.
+    timestamp: i64,
+    bytes: u64,
+}
+
+/// Decide which items to keep: newest first, each item is kept unless it
+/// is too old, its session already has `max_per_session` newer items, or
+/// keeping it would exceed `max_bytes`.
+fn select(items: &[Item], limits: &Limits, now_ms: i64) -> (Vec<bool>, ClassReport) {
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(items[i].timestamp));
+    let cutoff = limits.max_age_days.map(|d| now_ms - d as i64 * DAY_MS);
+
+    let mut keep = vec![false; items.len()];
+    let mut report = ClassReport::default();
+    let mut per_session: HashMap<&str, u64> = HashMap::new();
+    for i in order {
+        let item = &items[i];
+        let count = item
+            .session
+            .as_deref()
+            .map(|s| per_session.entry(s).or_default());
+        if cutoff.is_some_and(|c| item.timestamp < c) {
+            report.by_age += 1;
+        } else if let (Some(max), Some(n)) = (limits.max_per_session, &count)
+            && **n >= max
+        {
+            report.by_count += 1;
+        } else if limits
+            .max_bytes
+            .is_some_and(|max| report.kept_bytes + item.bytes > max)
+        {
+            report.by_size += 1;
+        } else {
+            keep[i] = true;
+            report.kept += 1;
+            report.kept_bytes += item.bytes;
+            if let Some(n) = count {
+                *n += 1;
+            }
+            continue;
+        }
+        report.removed += 1;
+        report.removed_bytes += item.bytes;
+    }
+    (keep, report)
+}
+
+/// Runs compaction on a schedule or on demand and owns the live policy.
+#[derive(Clone)]
+pub struct Compactor {
+    db: Arc<Db>,
+    paths: Arc<StoragePaths>,
+    policy: Arc<RwLock<RetentionPolicy>>,
+    last_report: Arc<RwLock<Option<CompactionReport>>>,
+    /// Serializes runs (scheduled and manual).
+    running: Arc<Mutex<()>>,
+}
+
+impl Compactor {
+    /// Load the policy from `paths.policy` (defaults if absent).
+    pub fn new(db: Arc<Db>, paths: StoragePaths) -> Self {
+        let policy = RetentionPolicy::load(&paths.policy);
+        Self {
+            db,
+            paths: Arc::new(paths),
+            policy: Arc::new(RwLock::new(policy)),
+            last_report: Arc::new(RwLock::new(None)),
+            running: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn policy(&self) -> RetentionPolicy {
+        self.policy.read().await.clone()
+    }
+
+    /// Validate, persist and activate a new policy.
+    pub async fn set_policy(&self, policy: RetentionPolicy) -> anyhow::Result<()> {
+        policy.validate()?;
+        policy.save(&self.paths.policy)?;
+        *self.policy.write().await = policy;
+        Ok(())
+    }
+
+    /// The report of the last applied (not dry) run.
+    pub async fn last_report(&self) -> Option<CompactionReport> {
+        self.last_report.read().await.clone()
+    }
+
+    /// Compact every so often, as the policy says.
+    pub async fn run(self) {
+        tokio::time::sleep(FIRST_RUN_DELAY).await;
+        loop {
+            let policy = self.policy().await;
+            if policy.enabled {
+                let report = self.compact(false).await;
+                info!(
+                    archived = report.archive.archived.len(),
+                    api_requests = report.api_requests.removed,
+                    audit_entries = report.audit_log.removed,
+                    proxy_configs = report.proxy_configs.removed,
+                    recordings = report.recordings.removed,
+                    errors = report.errors.len(),
+                    "retention compaction finished"
+                );
+            }
+            tokio::time::sleep(Duration::from_secs(policy.interval_hours * 3600)).await;
+        }
+    }
+
+    /// Apply the policy once. With `dry_run`, only report.
+    pub async fn compact(&self, dry_run: bool) -> CompactionReport {
+        let _running = self.running.lock().await;
+        let started = std::time::Instant::now();
+        let policy = self.policy().await;
+        let now_ms = now_ms();
+        let mut report = CompactionReport {
+            dry_run,
+            started_at: now_ms / 1000,
+            ..Default::default()
+        };
+
+        let archived = match self.archive_idle(&policy.archive, now_ms, dry_run).await {
+            Ok(r) => {
+                let ids: HashSet<Uuid> = r.archived.iter().map(|a| a.session_id).collect();
+                report.archive = r;
+                ids
+            }
+            Err(e) => {
+                report.errors.push(format!("archive: {e}"));
+                HashSet::new()
+            }
+        };
+        match self
+            .trim_api_requests(&policy.api_requests, &archived, now_ms, dry_run)
+            .await
+        {
+            Ok(r) => report.api_requests = r,
+            Err(e) => report.errors.push(format!("apiRequests: {e}")),
+        }
+        match self.trim_audit_log(policy.audit_log, now_ms, dry_run).await {
+            Ok(r) => report.audit_log = r,
+            Err(e) => report.errors.push(format!("auditLog: {e}")),
+        }
+        report.proxy_configs = self.expire_proxy_configs(&policy.proxy_configs, dry_run);
+        match self
+            .trim_recordings(policy.recordings, now_ms, dry_run)
+            .await
+        {
+            Ok(r) => report.recordings = r,
+            Err(e) => report.errors.push(format!("recordings: {e}")),
+        }
+
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        for e in &report.errors {
+            warn!(error = %e, dry_run, "retention step failed");
+        }
+        if !dry_run {
+            *self.last_report.write().await = Some(report.clone());
+        }
+        report
+    }
+
+    /// Move idle sessions to cold storage and drop expired archives.
+    async fn archive_idle(
+        &self,
+        policy: &ArchivePolicy,
+        now_ms: i64,
+        dry_run: bool,
+    ) -> anyhow::Result<ArchiveReport> {
+        let mut report = ArchiveReport::default();
+        let dir = self.paths.archive.clone();
+
+        if let Some(days) = policy.delete_after_days {
+            let cutoff = now_ms / 1000 - days as i64 * 86_400;
+            for a in archive::list(&dir) {
+                if a.header.archived_at < cutoff {
+                    if !dry_run {
+                        archive::remove(&dir, &a.header.session_id)?;
+                    }
+                    report.expired.push(a.header.session_id);
+                    report.expired_bytes += a.bytes;
+                }
+            }
+        }
+
+        let Some(days) = policy.after_days else {
+            return Ok(report);
+        };
+        let cutoff = now_ms / 1000 - days as i64 * 86_400;
+        let mut idle: Vec<(Uuid, i64)> = self
+            .db
+            .session_last_activity()
+            .await?
+            .into_iter()
+            .filter(|&(sid, last)| last < cutoff && restored_at(&dir, &sid) < cutoff)
+            .collect();
+        idle.sort_by_key(|&(_, last)| last);
+
+        for (session_id, last_activity) in idle {
+            let rows = if dry_run {
+                self.db.count_session_rows(&session_id).await?
+            } else {
+                self.archive_session(session_id, last_activity, now_ms / 1000)
+                    .await?
+            };
+            report.archived.push(ArchivedSession {
+                session_id,
+                last_activity,
+                rows,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Write one session's rows to its archive (merging an earlier
+    /// archive of the same session), then delete them from the database.
+    async fn archive_session(
+        &self,
+        session_id: Uuid,
+        last_activity: i64,
+        now: i64,
+    ) -> anyhow::Result<u64> {
+        let dir = self.paths.archive.clone();
+        if archive::archive_path(&dir, &session_id).exists() {
+            let (_, tables) = archive::read(&dir, &session_id)?;
+            for t in &tables {
+                self.db.import_rows(t).await?;
+            }
+        }
+        let tables = self.db.export_session_rows(&session_id).await?;
+        let header = ArchiveHeader {
+            format: archive::FORMAT,
+            schema_version: schema::SCHEMA_VERSION,
+            session_id,
+            archived_at: now,
+            last_activity,
+            rows: tables
+                .iter()
+                .map(|t| (t.table.clone(), t.rows.len() as u64))
+                .collect(),
+        };
+        let rows = header.rows.values().sum();
+        let write_dir = dir.clone();
+        tokio::task::spawn_blocking(move || archive::write(&write_dir, &header, &tables)).await??;
+        self.db.delete_session_rows(&session_id).await?;
+        let _ = std::fs::remove_file(restored_marker(&dir, &session_id));
+        info!(session = %session_id, rows, "archived idle session");
+        Ok(rows)
+    }
+
+    async fn trim_api_requests(
+        &self,
+        limits: &Limits,
+        skip_sessions: &HashSet<Uuid>,
+        now_ms: i64,
+        dry_run: bool,
+    ) -> anyhow::Result<ClassReport> {
+        let stats: Vec<_> = self
+            .db
+            .api_request_stats()
+            .await?
+            .into_iter()
+            .filter(|s| !skip_sessions.contains(&s.session_id))
+            .collect();
+        let items: Vec<Item> = stats
+            .iter()
+            .map(|s| Item {
+                session: (!s.session_id.is_nil()).then(|| s.session_id.to_string()),
+                timestamp: s.timestamp,
+                bytes: s.bytes,
+            })
+            .collect();
+        let (keep, report) = select(&items, limits, now_ms);
+        if !dry_run && report.removed > 0 {
+            let ids: Vec<Uuid> = stats
+                .iter()
+                .zip(&keep)
+                .filter(|(_, k)| !**k)
+                .map(|(s, _)| s.id)
+                .collect();
+            self.db.delete_api_requests(&ids).await?;
+        }
+        Ok(report)
+    }
+
+    async fn trim_audit_log(
+        &self,
+        limits: Limits,
+        now_ms: i64,
+        dry_run: bool,
+    ) -> anyhow::Result<ClassReport> {
+        let path = self.paths.audit_log.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            let mut report = ClassReport::default();
+            audit::retain_entries(&path, |entries| {
+                let items: Vec<Item> = entries
+                    .iter()
+                    .map(|(e, bytes)| Item {
+                        session: e.session_id.clone(),
+                        timestamp: e.timestamp,
+                        bytes: *bytes,
+                    })
+                    .collect();
+                let (keep, r) = select(&items, &limits, now_ms);
+                report = r;
+                // A dry run keeps everything.
+                if dry_run {
+                    vec![true; keep.len()]
+                } else {
+                    keep
+                }
+            })?;
+            anyhow::Ok(report)
+        })
+        .await??;
+        Ok(report)
+    }
+
+    async fn trim_recordings(
+        &self,
+        limits: Limits,
+        now_ms: i64,
+        dry_run: bool,
+    ) -> anyhow::Result<ClassReport> {
+        let dir = self.paths.recordings.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            let stored = recording::stored(&dir);
+            let items: Vec<Item> = stored
+                .iter()
+                .map(|r| Item {
+                    session: Some(r.session_id.to_string()),
+                    timestamp: r.modified_ms,
+                    bytes: r.bytes,
+                })
+                .collect();
+            let (keep, report) = select(&items, &limits, now_ms);
+            if !dry_run {
+                for (r, _) in stored.iter().zip(&keep).filter(|(_, k)| !**k) {
+                    std::fs::remove_file(&r.path)?;
+                    // Only succeeds once the session has no recordings left
+                    if let Some(session_dir) = r.path.parent() {
+                        let _ = std::fs::remove_dir(session_dir);
+                    }
+                }
+            }
+            anyhow::Ok(report)
+        })
+        .await??;
+        Ok(report)
+    }
+
+    /// Remove proxy configs past `maxAgeDays`. Also run at startup, before
+    /// configs are loaded.
+    pub fn expire_proxy_configs(&self, limits: &Limits, dry_run: bool) -> ClassReport {
+        let mut report = ClassReport::default();
+        let Some(days) = limits.max_age_days else {
+            return report;
+        };
+        let max_age = Duration::from_secs(days * 86_400);
+        for (path, bytes) in persist::stale_configs(&self.paths.proxy_configs, max_age) {
+            if dry_run || std::fs::remove_file(&path).is_ok() {
+                report.removed += 1;
+                report.removed_bytes += bytes;
+                report.by_age += 1;
+            }
+        }
+        report
+    }
+
+    pub fn has_archive(&self, session_id: &Uuid) -> bool {
+        archive::archive_path(&self.paths.archive, session_id).exists()
+    }
+
+    /// Archived sessions, oldest archive first.
+    pub fn archives(&self) -> Vec<ArchiveSummary> {
+        archive::list(&self.paths.archive)
+    }
+
+    /// Move an archived session back into the database. It is not archived
+    /// again until it has been idle for the archive threshold once more.
+    pub async fn restore(&self, session_id: Uuid) -> anyhow::Result<u64> {
+        let _running = self.running.lock().await;
+        let dir = self.paths.archive.clone();
+        let (_, tables) = archive::read(&dir, &session_id)?;
+        let mut restored = 0;
+        for t in &tables {
+            restored += self.db.import_rows(t).await? as u64;
+        }
+        std::fs::write(restored_marker(&dir, &session_id), b"")?;
+        archive::remove(&dir, &session_id)?;
+        info!(session = %session_id, rows = restored, "restored archived session");
+        Ok(restored)
+    }
+}
+
+fn restored_marker(dir: &Path, session_id: &Uuid) -> PathBuf {
+    dir.join(format!("{session_id}.restored"))
+}
+
+/// When the session was last restored, epoch seconds (0 if never).
+fn restored_at(dir: &Path, session_id: &Uuid) -> i64 {
+    std::fs::metadata(restored_marker(dir, session_id))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::{ApiRequestComponent, MessageComponent, MessageRole};
+
+    const NOW_MS: i64 = 1_800_000_000_000;
+
+    fn item(session: &str, days_ago: i64, bytes: u64) -> Item {
+        Item {
+            session: Some(session.into()),
+            timestamp: NOW_MS - days_ago * DAY_MS,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn select_drops_oldest_by_age_count_and_size() {
+        let items = [
+            item("a", 0, 10),
+            item("a", 1, 10),
+            item("a", 2, 10),
+            item("b", 3, 50),
+            item("b", 40, 10),
+        ];
+        let limits = Limits {
+            max_age_days: Some(30),
+            max_per_session: Some(2),
+            max_bytes: Some(60),
+        };
+        let (keep, report) = select(&items, &limits, NOW_MS);
+        assert_eq!(keep, vec![true, true, false, false, false]);
+        assert_eq!((report.by_age, report.by_count, report.by_size), (1, 1, 1));
+        assert_eq!((report.kept, report.kept_bytes), (2, 20));
+        assert_eq!((report.removed, report.removed_bytes), (3, 70));
+
+        let (keep, report) = select(&items, &Limits::default(), NOW_MS);
+        assert!(keep.iter().all(|k| *k));
+        assert_eq!(report.removed, 0);
+    }
+
+    #[test]
+    fn policy_validation() {
+        assert!(RetentionPolicy::default().validate().is_ok());
+        let p = RetentionPolicy {
+            interval_hours: 0,
+            ..Default::default()
+        };
+        assert_eq!(p.validate(), Err(PolicyError::Interval));
+        let mut p = RetentionPolicy::default();
+        p.audit_log.max_bytes = Some(0);
+        assert_eq!(p.validate(), Err(PolicyError::Zero("auditLog")));
+        let mut p = RetentionPolicy::default();
+        p.proxy_configs.max_per_session = Some(5);
+        assert_eq!(p.validate(), Err(PolicyError::ConfigLimits));
+        // Unknown or missing fields fall back to defaults.
+        let p: RetentionPolicy = serde_json::from_str(r#"{"intervalHours": 2}"#).unwrap();
+        assert_eq!(p.interval_hours, 2);
+        assert_eq!(p.api_requests, RetentionPolicy::default().api_requests);
+        // Scheduled compaction deletes data, so it needs an explicit opt-in.
+        assert!(!p.enabled);
+        assert!(!RetentionPolicy::default().enabled);
+    }
+
+    fn message(session_id: Uuid, ts_secs: i64) -> MessageComponent {
+        MessageComponent {
+            model: Some("claude-opus-4-6".into()),
+            ..MessageComponent::test(session_id, MessageRole::User, "hello", ts_secs)
+        }
+    }
+
+    fn audit_line(session: &str, ts_ms: i64) -> String {
+        serde_json::to_string(&audit::AuditEntry {
+            id: Uuid::new_v4().to_string(),
+            session_id: Some(session.into()),
+            method: "POST".into(),
+            url: "u".into(),
+            model: "m".into(),
+            provider: "anthropic".into(),
+            input_tokens: 1,
+            output_tokens: 1,
+            cache_creation_tokens: 0,
+            cache_read_tokens: 0,
+            cost_usd: 0.0,
+            timestamp: ts_ms,
+            latency_ms: 1,
+        })
+        .unwrap()
+            + "\n"
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_then_compaction_archives_trims_and_restores() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = StoragePaths {
+            policy: dir.path().join("retention.json"),
+            audit_log: dir.path().join("audit-log.jsonl"),
+            proxy_configs: dir.path().to_path_buf(),
+            archive: dir.path().join("archive"),
+            recordings: dir.path().join("recordings"),
+        };
+        let db = Arc::new(Db::open(":memory:").await.unwrap());
+        let now_ms = now_ms();
+        let day = DAY_MS;
+
+        // Idle for 100 days: archived.
+        let idle = Uuid::new_v4();
+        db.insert_message(&message(idle, (now_ms - 100 * day) / 1000))
+            .await
+            .unwrap();
+        db.insert_api_request(&ApiRequestComponent::test(idle, now_ms - 100 * day))
+            .await
+            .unwrap();
+        // Active: one request too old, one over the per-session count.
+        let active = Uuid::new_v4();
+        db.insert_message(&message(active, now_ms / 1000))
+            .await
+            .unwrap();
+        for age in [40 * day, 3000, 2000, 1000] {
+            db.insert_api_request(&ApiRequestComponent::test(active, now_ms - age))
+                .await
+                .unwrap();
+        }
+
+        let audit = audit_line("s", now_ms - 400 * day) + &audit_line("s", now_ms);
+        std::fs::write(&paths.audit_log, &audit).unwrap();
+        let stale = dir.path().join("proxy-config-old.json");
+        std::fs::write(&stale, "{}").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(40 * 86_400))
+            .unwrap();
+        std::fs::write(dir.path().join("proxy-config-new.json"), "{}").unwrap();
+        let old_cast = paths.recordings.join(idle.to_string()).join("1.cast");
+        let new_cast = paths.recordings.join(active.to_string()).join("2.cast");
+        for cast in [&old_cast, &new_cast] {
+            std::fs::create_dir_all(cast.parent().unwrap()).unwrap();
+            std::fs::write(cast, "{}\n").unwrap();
+        }
+        std::fs::File::options()
+            .write(true)
+            .open(&old_cast)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(40 * 86_400))
+            .unwrap();
+
+        let compactor = Compactor::new(db.clone(), paths.clone());
+        let mut policy = RetentionPolicy::default();
+        policy.api_requests.max_per_session = Some(2);
+        compactor.set_policy(policy).await.unwrap();
+        assert!(paths.policy.exists());
+
+        let dry = compactor.compact(true).await;
+        assert!(dry.errors.is_empty(), "{:?}", dry.errors);
+        assert_eq!(dry.archive.archived.len(), 1);
+        assert_eq!(dry.archive.archived[0].session_id, idle);
+        assert_eq!(dry.archive.archived[0].rows, 2);
+        assert_eq!((dry.api_requests.by_age, dry.api_requests.by_count), (1, 1));
+        assert_eq!(dry.audit_log.removed, 1);
+        assert_eq!(dry.proxy_configs.removed, 1);
+        assert_eq!((dry.recordings.by_age, dry.recordings.kept), (1, 1));
+        // Nothing changed.
+        assert_eq!(db.api_request_stats().await.unwrap().len(), 5);
+        assert_eq!(std::fs::read_to_string(&paths.audit_log).unwrap(), audit);
+        assert!(stale.exists());
+        assert!(old_cast.exists());
+        assert!(compactor.archives().is_empty());
+        assert!(compactor.last_report().await.is_none());
+
+        let report = compactor.compact(false).await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.archive, dry.archive);
+        assert_eq!(report.api_requests, dry.api_requests);
+        assert_eq!(report.audit_log, dry.audit_log);
+        assert_eq!(compactor.last_report().await, Some(report));
+
+        assert!(db.get_messages_by_session(&idle).await.unwrap().is_empty());
+        assert!(
+            db.get_api_requests_by_session(&idle)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            db.get_api_requests_by_session(&active).await.unwrap().len(),
+            2
+        );
+        assert_eq!(
+            std::fs::read_to_string(&paths.audit_log)
+                .unwrap()
+                .lines()
+                .count(),
+            1
+        );
+        assert!(!stale.exists());
+        assert!(dir.path().join("proxy-config-new.json").exists());
+        assert!(
+            !old_cast.parent().unwrap().exists(),
+            "empty session dir removed"
+        );
+        assert!(new_cast.exists());
+        let archives = compactor.archives();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(archives[0].header.rows["messages"], 1);
+        assert_eq!(archives[0].header.rows["api_requests"], 1);
+
+        // Restore brings the rows back and exempts the session from the
+        // next run.
+        assert_eq!(compactor.restore(idle).await.unwrap(), 2);
+        assert!(!compactor.has_archive(&idle));
+        let restored = db.get_messages_by_session(&idle).await.unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].model.as_deref(), Some("claude-opus-4-6"));
+        let again = compactor.compact(false).await;
+        assert!(again.archive.archived.is_empty());
+        assert_eq!(
+            db.get_api_requests_by_session(&idle).await.unwrap().len(),
+            0,
+            "old request trimmed by age"
+        );
+    }
+}
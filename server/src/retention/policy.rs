@@ -0,0 +1,146 @@
+//! How long each class of stored data is kept.
+//!
+//! The policy lives in `retention.json` in the data directory and can be
+//! replaced at runtime through `PUT /api/retention`.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Limits for one class of data; `None` is unlimited. Items are kept
+/// newest first until a limit is hit, so the oldest go first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Limits {
+    /// Drop items older than this.
+    pub max_age_days: Option<u64>,
+    /// Total size kept across all sessions.
+    pub max_bytes: Option<u64>,
+    /// Items kept per session.
+    pub max_per_session: Option<u64>,
+}
+
+/// When whole sessions move to cold storage, and when they are dropped
+/// from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ArchivePolicy {
+    /// Sessions without new messages or requests for this long are
+    /// archived and removed from the database.
+    pub after_days: Option<u64>,
+    /// Archives are deleted this long after they were written.
+    pub delete_after_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetentionPolicy {
+    /// Run compaction every `interval_hours`. Off until explicitly enabled,
+    /// since it deletes data; manual runs work either way.
+    pub enabled: bool,
+    pub interval_hours: u64,
+    /// Captured proxy traffic (`api_requests`).
+    pub api_requests: Limits,
+    /// The cost audit log (`audit-log.jsonl`).
+    pub audit_log: Limits,
+    /// Per-session proxy configs (`proxy-config-*.json`); age only.
+    pub proxy_configs: Limits,
+    /// Terminal recordings of managed sessions (`recordings/<id>/*.cast`).
+    pub recordings: Limits,
+    pub archive: ArchivePolicy,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: 6,
+            api_requests: Limits {
+                max_age_days: Some(30),
+                max_bytes: Some(1024 * MIB),
+                max_per_session: Some(2000),
+            },
+            audit_log: Limits {
+                max_age_days: Some(365),
+                max_bytes: Some(256 * MIB),
+                max_per_session: None,
+            },
+            proxy_configs: Limits {
+                max_age_days: Some(30),
+                ..Limits::default()
+            },
+            recordings: Limits {
+                max_age_days: Some(30),
+                max_bytes: Some(2048 * MIB),
+                max_per_session: None,
+            },
+            archive: ArchivePolicy {
+                after_days: Some(90),
+                delete_after_days: None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PolicyError {
+    #[error("intervalHours must be at least 1")]
+    Interval,
+    #[error("{0} limits must be greater than zero")]
+    Zero(&'static str),
+    #[error("proxyConfigs only supports maxAgeDays")]
+    ConfigLimits,
+}
+
+impl RetentionPolicy {
+    /// Read the policy at `path`; a missing or unreadable file gives the
+    /// defaults.
+    pub fn load(path: &Path) -> Self {
+        let Ok(json) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Self>(&json) {
+            Ok(policy) if policy.validate().is_ok() => policy,
+            Ok(_) | Err(_) => {
+                warn!(path = %path.display(), "invalid retention policy, using defaults");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, json)
+    }
+
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.interval_hours == 0 {
+            return Err(PolicyError::Interval);
+        }
+        let classes = [
+            ("apiRequests", self.api_requests),
+            ("auditLog", self.audit_log),
+            ("proxyConfigs", self.proxy_configs),
+            ("recordings", self.recordings),
+        ];
+        for (name, l) in classes {
+            if [l.max_age_days, l.max_bytes, l.max_per_session].contains(&Some(0)) {
+                return Err(PolicyError::Zero(name));
+            }
+        }
+        if [self.archive.after_days, self.archive.delete_after_days].contains(&Some(0)) {
+            return Err(PolicyError::Zero("archive"));
+        }
+        if self.proxy_configs.max_bytes.is_some() || self.proxy_configs.max_per_session.is_some() {
+            return Err(PolicyError::ConfigLimits);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//! Background search indexer.
+//!
+//! Every transcript's parser cursor is kept in `search_sources`, so a
+//! restart only reads what was appended in the meantime and a live update
+//! costs one incremental parse. `SESSION_MESSAGES` events serve as triggers
+//! only: the indexer re-reads the transcript from its own cursor instead of
+//! trusting event payloads, so ids and ordering match a full parse.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{RwLock, broadcast};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::analytics;
+use crate::bus::EventEnvelope;
+use crate::db::{Db, SearchMeta, SearchSource};
+use crate::discovery::scanner::CliType;
+use crate::ecs::SharedEcsWorld;
+use crate::ecs::components::MessageRole;
+use crate::parser::{self, ClaudeMessage, ContentBlock, CursorKind, MessageContent};
+
+/// Triggers for the same session within this window are indexed once.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Keeps the search index in step with the discovered transcripts.
+#[derive(Clone)]
+pub struct SearchIndexer {
+    db: Arc<Db>,
+    ecs: SharedEcsWorld,
+    session_paths: Arc<RwLock<HashMap<Uuid, PathBuf>>>,
+    session_cli_types: Arc<RwLock<HashMap<Uuid, CliType>>>,
+}
+
+impl SearchIndexer {
+    pub fn new(
+        db: Arc<Db>,
+        ecs: SharedEcsWorld,
+        session_paths: Arc<RwLock<HashMap<Uuid, PathBuf>>>,
+        session_cli_types: Arc<RwLock<HashMap<Uuid, CliType>>>,
+    ) -> Self {
+        Self {
+            db,
+            ecs,
+            session_paths,
+            session_cli_types,
+        }
+    }
+
+    /// Index every known session, then follow `SESSION_MESSAGES` triggers.
+    pub async fn run(self, mut rx: broadcast::Receiver<EventEnvelope>) {
+        self.backfill().await;
+
+        let mut pending: HashSet<Uuid> = HashSet::new();
+        let mut tick = tokio::time::interval(DEBOUNCE);
+        loop {
+            tokio::select! {
+                event = rx.recv() => match event {
+                    Ok(envelope) => {
+                        if let Some(sid) = envelope.session_id {
+                            pending.insert(sid);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        // Triggers were lost; a sweep only re-reads what changed.
+                        warn!(missed = n, "search indexer lagged, re-checking all sessions");
+                        self.backfill().await;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = tick.tick() => {
+                    for sid in pending.drain() {
+                        if let Err(e) = self.index_session(sid).await {
+                            warn!(session = %sid, error = %e, "search indexing failed");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bring every registered session up to date.
+    pub async fn backfill(&self) {
+        let ids: Vec<Uuid> = self.session_paths.read().await.keys().copied().collect();
+        let started = std::time::Instant::now();
+        let mut indexed = 0usize;
+        for sid in &ids {
+            match self.index_session(*sid).await {
+                Ok(n) => indexed += n,
+                Err(e) => warn!(session = %sid, error = %e, "search indexing failed"),
+            }
+            tokio::task::yield_now().await;
+        }
+        info!(
+            sessions = ids.len(),
+            new_messages = indexed,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "search index up to date"
+        );
+    }
+
+    /// Index what is new in one session's transcript and fold it into the
+    /// analytics aggregates. Returns the number of messages added.
+    pub async fn index_session(&self, session_id: Uuid) -> anyhow::Result<usize> {
+        let Some(path) = self.session_paths.read().await.get(&session_id).cloned() else {
+            return Ok(0);
+        };
+        let cli = self
+            .session_cli_types
+            .read()
+            .await
+            .get(&session_id)
+            .copied()
+            .unwrap_or_default();
+        let project = self
+            .ecs
+            .read()
+            .await
+            .query_session_by_id(session_id)
+            .map(|s| s.path)
+            .filter(|p| !p.is_empty());
+        let added = index_transcript(&self.db, session_id, &path, cli, project.clone()).await?;
+        if let Err(e) = analytics::update_session(&self.db, session_id, &path, cli, project).await {
+            warn!(session = %session_id, error = %e, "analytics update failed");
+        }
+        Ok(added)
+    }
+}
+
+/// Index what is new in a transcript since its stored cursor.
+pub async fn index_transcript(
+    db: &Db,
+    session_id: Uuid,
+    path: &Path,
+    cli: CliType,
+    project: Option<String>,
+) -> anyhow::Result<usize> {
+    let source = parser::registry::for_cli(cli);
+    let file_size = tokio::fs::metadata(path).await?.len();
+    let path_str = path.display().to_string();
+
+    let mut state = db
+        .get_search_source(&session_id)
+        .await?
+        .filter(|s| s.path == path_str);
+    if let Some(s) = &state
+        && file_size < s.file_size
+    {
+        debug!(session = %session_id, "transcript shrank, re-indexing");
+        state = None;
+    }
+    let reset = state.is_none();
+    let (cursor, mut seq) = state
+        .as_ref()
+        .map_or((0, 0), |s| (s.cursor, s.message_count));
+
+    // Message-count cursors re-parse the whole file; skip when unchanged.
+    if source.cursor_kind() == CursorKind::MessageCount
+        && state.as_ref().is_some_and(|s| s.file_size == file_size)
+    {
+        return Ok(0);
+    }
+
+    let (messages, new_cursor) = source.parse_incremental(path, cursor).await?;
+    if reset || new_cursor < cursor {
+        db.clear_search_session(&session_id).await?;
+        seq = 0;
+    }
+
+    let mut added = 0;
+    for msg in &messages {
+        let Some(mut component) = parser::message_to_component(msg, session_id) else {
+            continue;
+        };
+        let position = seq;
+        seq += 1;
+        if component.role == MessageRole::Meta {
+            continue;
+        }
+        // The transcript stays the source of truth for structured blocks;
+        // keeping them (and inline images) out of the index keeps it small.
+        component.content_blocks_json = None;
+        let tools = tool_names(msg);
+        append_tool_text(msg, &mut component.content);
+        if component.content.trim().is_empty() && tools.is_empty() {
+            continue;
+        }
+        let meta = SearchMeta {
+            seq: position,
+            cli: source.name().to_string(),
+            project: project.clone(),
+            model: component.model.clone(),
+            tools,
+        };
+        if db.index_message(&component, &meta).await? {
+            added += 1;
+        }
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    db.put_search_source(&SearchSource {
+        session_id,
+        path: path_str,
+        cursor: new_cursor,
+        file_size,
+        message_count: seq,
+        indexed_at: now,
+    })
+    .await?;
+    if added > 0 {
+        debug!(session = %session_id, added, "search index updated");
+    }
+    Ok(added)
+}
+
+/// Lowercased names of the tools a message calls.
+fn tool_names(msg: &ClaudeMessage) -> Vec<String> {
+    let MessageContent::Blocks(blocks) = &msg.content else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = Vec::new();
+    for block in blocks {
+        if let ContentBlock::ToolUse { name, .. } = block {
+            let name = name.to_lowercase();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Make tool inputs and structured tool results searchable: the flattened
+/// message text only carries `[tool_use: Name]` markers for them.
+fn append_tool_text(msg: &ClaudeMessage, text: &mut String) {
+    let MessageContent::Blocks(blocks) = &msg.content else {
+        return;
+    };
+    for block in blocks {
+        match block {
+            ContentBlock::ToolUse { input, .. } => push_strings(input, text),
+            ContentBlock::ToolResult { content, .. } if !content.is_string() => {
+                push_strings(content, text)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn push_strings(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::String(s) if !s.is_empty() => {
+            out.push('\n');
+            out.push_str(s);
+        }
+        serde_json::Value::Array(items) => items.iter().for_each(|v| push_strings(v, out)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| push_strings(v, out)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::search::{Query, search};
+
+    fn line(uuid: &str, kind: &str, content: serde_json::Value) -> String {
+        serde_json::json!({
+            "type": kind,
+            "uuid": uuid,
+            "sessionId": "s",
+            "timestamp": "2026-03-01T10:00:00.000Z",
+            "message": {"role": kind, "content": content, "model": "claude-opus-4-6"},
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn indexes_incrementally_and_resets_on_truncation() {
+        let db = Db::open(":memory:").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let sid = Uuid::new_v4();
+
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(
+            file,
+            "{}",
+            line(
+                "00000000-0000-4000-8000-000000000001",
+                "user",
+                "please run the migrations".into()
+            )
+        )
+        .unwrap();
+        drop(file);
+        let project = Some("/work/app".to_string());
+        let n = index_transcript(&db, sid, &path, CliType::Claude, project.clone())
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writeln!(
+            file,
+            "{}",
+            line(
+                "00000000-0000-4000-8000-000000000002",
+                "assistant",
+                serde_json::json!([{"type": "tool_use", "id": "t1", "name": "Bash",
+                    "input": {"command": "sqlx migrate run"}}])
+            )
+        )
+        .unwrap();
+        drop(file);
+        let n = index_transcript(&db, sid, &path, CliType::Claude, project.clone())
+            .await
+            .unwrap();
+        assert_eq!(n, 1, "only the appended line is read");
+        assert_eq!(
+            index_transcript(&db, sid, &path, CliType::Claude, project.clone())
+                .await
+                .unwrap(),
+            0
+        );
+
+        let query = Query::parse("\"migrate run\" tool:bash project:app").unwrap();
+        let hits = search(&db, &query, 0, 10).await.unwrap().hits;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].seq, 1);
+        assert_eq!(hits[0].model.as_deref(), Some("claude-opus-4-6"));
+
+        // Rewritten transcript: the old rows go, the new content is indexed.
+        std::fs::write(
+            &path,
+            line(
+                "00000000-0000-4000-8000-000000000003",
+                "user",
+                "fresh".into(),
+            ) + "\n",
+        )
+        .unwrap();
+        let n = index_transcript(&db, sid, &path, CliType::Claude, project)
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+        assert!(
+            search(&db, &Query::parse("migrations").unwrap(), 0, 10)
+                .await
+                .unwrap()
+                .hits
+                .is_empty()
+        );
+        assert_eq!(db.search_index_counts().await.unwrap(), (1, 1));
+    }
+}
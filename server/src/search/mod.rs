@@ -0,0 +1,360 @@
+//! Full-text search across all sessions.
+//!
+//! The [`indexer`] copies every discovered transcript into the database
+//! (`messages` plus `message_search` metadata) and keeps it current.
+//! [`search`] runs a parsed [`Query`] against that index: the database
+//! narrows candidates by text, role, source and time, then every candidate
+//! is checked against the full query here and turned into a [`SearchHit`]
+//! with a snippet and highlight ranges.
+
+pub mod indexer;
+mod query;
+
+pub use indexer::SearchIndexer;
+pub use query::{Field, Filter, Query, QueryError, Term};
+
+use serde::Serialize;
+
+use crate::db::{Db, DbResult, IndexScan, IndexedMessage};
+use crate::ecs::components::MessageRole;
+
+/// Characters of context kept before the first match in a snippet.
+const SNIPPET_LEAD: usize = 60;
+
+/// Maximum snippet length in characters (excluding ellipses).
+const SNIPPET_LEN: usize = 240;
+
+/// One matching message.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub session_id: String,
+    pub message_id: String,
+    /// Position of the message in its transcript. Codex message ids are
+    /// regenerated on every parse, so clients fall back to this.
+    pub seq: u64,
+    pub role: &'static str,
+    pub timestamp: i64,
+    pub cli: String,
+    pub project: Option<String>,
+    pub model: Option<String>,
+    pub tools: Vec<String>,
+    pub snippet: String,
+    /// `[start, end)` ranges of matched terms in `snippet`, as UTF-16 code
+    /// unit offsets (JavaScript string indices).
+    pub highlights: Vec<[usize; 2]>,
+}
+
+/// A page of hits, newest first.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    pub has_more: bool,
+}
+
+/// Run `query` against the index, skipping `offset` hits and returning at
+/// most `limit`.
+pub async fn search(
+    db: &Db,
+    query: &Query,
+    offset: usize,
+    limit: usize,
+) -> DbResult<SearchResults> {
+    let scan = IndexScan {
+        contains: query.required_terms().map(str::to_string).collect(),
+        role: query.required(Field::Role).map(role_from_str),
+        cli: query.required(Field::Cli).map(str::to_string),
+        after: query.after,
+        before: query.before,
+    };
+    let needles: Vec<String> = query.required_terms().map(fold_case).collect();
+
+    let mut results = SearchResults::default();
+    let mut skipped = 0;
+    db.scan_search_index(&scan, |indexed| {
+        if !matches(query, &indexed) {
+            return true;
+        }
+        if skipped < offset {
+            skipped += 1;
+            return true;
+        }
+        if results.hits.len() == limit {
+            results.has_more = true;
+            return false;
+        }
+        results.hits.push(to_hit(indexed, &needles));
+        true
+    })
+    .await?;
+    Ok(results)
+}
+
+fn role_from_str(s: &str) -> MessageRole {
+    match s {
+        "assistant" => MessageRole::Assistant,
+        "system" => MessageRole::System,
+        _ => MessageRole::User,
+    }
+}
+
+fn role_str(role: MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+        MessageRole::System => "system",
+        MessageRole::Meta => "meta",
+    }
+}
+
+/// Whether a message satisfies every term, filter and time bound.
+fn matches(query: &Query, indexed: &IndexedMessage) -> bool {
+    let m = &indexed.message;
+    let meta = &indexed.meta;
+    if query.after.is_some_and(|t| m.timestamp < t)
+        || query.before.is_some_and(|t| m.timestamp >= t)
+    {
+        return false;
+    }
+
+    let content = fold_case(&m.content);
+    let terms_ok = query
+        .terms
+        .iter()
+        .all(|t| content.contains(&fold_case(&t.text)) != t.negated);
+    if !terms_ok {
+        return false;
+    }
+
+    query.filters.iter().all(|f| {
+        let hit = match f.field {
+            Field::Role => role_str(m.role) == f.value,
+            Field::Tool => match f.value.strip_suffix('*') {
+                Some(prefix) => meta.tools.iter().any(|t| t.starts_with(prefix)),
+                None => meta.tools.contains(&f.value),
+            },
+            Field::Model => meta
+                .model
+                .as_deref()
+                .is_some_and(|model| model.to_lowercase().contains(&f.value)),
+            Field::Project => meta
+                .project
+                .as_deref()
+                .is_some_and(|p| p.to_lowercase().contains(&f.value)),
+            Field::Cli => meta.cli == f.value,
+            Field::Session => m.session_id.to_string().starts_with(&f.value),
+        };
+        hit != f.negated
+    })
+}
+
+fn to_hit(indexed: IndexedMessage, needles: &[String]) -> SearchHit {
+    let IndexedMessage { message: m, meta } = indexed;
+    let (snippet, highlights) = snippet(&m.content, needles);
+    SearchHit {
+        session_id: m.session_id.to_string(),
+        message_id: m.id.to_string(),
+        seq: meta.seq,
+        role: role_str(m.role),
+        timestamp: m.timestamp,
+        cli: meta.cli,
+        project: meta.project,
+        model: meta.model,
+        tools: meta.tools,
+        snippet,
+        highlights,
+    }
+}
+
+/// Cut a window around the first match of any needle (already case-folded)
+/// and locate every needle inside it.
+fn snippet(content: &str, needles: &[String]) -> (String, Vec<[usize; 2]>) {
+    let first = needles
+        .iter()
+        .filter_map(|n| find_folded(content, n).first().map(|r| r.0))
+        .min()
+        .unwrap_or(0);
+
+    let first_char = content[..first].chars().count();
+    let start_char = first_char.saturating_sub(SNIPPET_LEAD);
+    let mut chars = content.char_indices().skip(start_char);
+    let start = chars.next().map_or(content.len(), |(i, _)| i);
+    let end = content[start..]
+        .char_indices()
+        .nth(SNIPPET_LEN)
+        .map_or(content.len(), |(i, _)| start + i);
+
+    let mut text = String::new();
+    if start > 0 {
+        text.push('…');
+    }
+    // Same-length replacements keep byte offsets stable.
+    text.extend(content[start..end].chars().map(|c| match c {
+        '\n' | '\r' | '\t' => ' ',
+        c => c,
+    }));
+    if end < content.len() {
+        text.push('…');
+    }
+
+    let mut ranges: Vec<(usize, usize)> =
+        needles.iter().flat_map(|n| find_folded(&text, n)).collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in ranges {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    let highlights = merged
+        .into_iter()
+        .map(|(s, e)| [utf16_len(&text[..s]), utf16_len(&text[..e])])
+        .collect();
+    (text, highlights)
+}
+
+fn fold_case(s: &str) -> String {
+    s.to_lowercase()
+}
+
+/// Byte ranges in `haystack` where the case-folded `needle` occurs.
+///
+/// Lowercasing can change a character's byte length, so matches are found
+/// in the folded text and mapped back through per-byte origin offsets.
+fn find_folded(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let mut folded = String::with_capacity(haystack.len());
+    let mut origin: Vec<(usize, usize)> = Vec::with_capacity(haystack.len());
+    for (i, c) in haystack.char_indices() {
+        let end = i + c.len_utf8();
+        for lc in c.to_lowercase() {
+            folded.push(lc);
+            origin.extend(std::iter::repeat_n((i, end), lc.len_utf8()));
+        }
+    }
+    folded
+        .match_indices(needle)
+        .map(|(pos, m)| (origin[pos].0, origin[pos + m.len() - 1].1))
+        .collect()
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::db::SearchMeta;
+    use crate::ecs::components::MessageComponent;
+
+    fn meta(cli: &str, model: Option<&str>, tools: &[&str]) -> SearchMeta {
+        SearchMeta {
+            seq: 0,
+            cli: cli.into(),
+            project: Some("/work/noaide".into()),
+            model: model.map(str::to_string),
+            tools: tools.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    async fn run(db: &Db, q: &str) -> Vec<SearchHit> {
+        let query = Query::parse_at(q, 1_800_000_000).unwrap();
+        search(db, &query, 0, 50).await.unwrap().hits
+    }
+
+    #[tokio::test]
+    async fn query_language_against_the_index() {
+        let db = Db::open(":memory:").await.unwrap();
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let rows = [
+            (
+                MessageComponent::test(
+                    a,
+                    MessageRole::User,
+                    "Why is the deploy failing?",
+                    1_767_000_000,
+                ),
+                meta("claude", None, &[]),
+            ),
+            (
+                MessageComponent::test(
+                    a,
+                    MessageRole::Assistant,
+                    "[tool_use: Bash] kubectl logs: Connection refused on staging",
+                    1_767_000_100,
+                ),
+                meta("claude", Some("claude-opus-4-6"), &["bash"]),
+            ),
+            (
+                MessageComponent::test(
+                    b,
+                    MessageRole::Assistant,
+                    "The deploy to production hit connection refused too",
+                    1_768_000_000,
+                ),
+                meta("codex", Some("gpt-5-codex"), &[]),
+            ),
+        ];
+        for (m, meta) in &rows {
+            assert!(db.index_message(m, meta).await.unwrap());
+        }
+        // A message id that is already stored is skipped.
+        assert!(!db.index_message(&rows[0].0, &rows[0].1).await.unwrap());
+
+        let hits = run(&db, r#""connection refused""#).await;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].session_id, b.to_string(), "newest first");
+
+        let hits = run(&db, r#""connection refused" -staging"#).await;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].cli, "codex");
+
+        let hits = run(&db, "refused tool:bash model:opus").await;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_id, rows[1].0.id.to_string());
+        assert_eq!(hits[0].tools, vec!["bash"]);
+
+        assert_eq!(run(&db, "deploy role:user").await.len(), 1);
+        assert_eq!(run(&db, "deploy -role:user").await.len(), 1);
+        assert_eq!(run(&db, "deploy after:2026-01-05").await.len(), 1);
+        assert_eq!(run(&db, "project:noaide cli:claude").await.len(), 2);
+        assert!(run(&db, "deploy project:elsewhere").await.is_empty());
+
+        let query = Query::parse_at("deploy", 0).unwrap();
+        let page = search(&db, &query, 1, 1).await.unwrap();
+        assert_eq!(page.hits.len(), 1);
+        assert!(!page.has_more);
+
+        db.clear_search_session(&a).await.unwrap();
+        assert_eq!(run(&db, "deploy").await.len(), 1);
+    }
+
+    #[test]
+    fn snippets_are_windowed_and_highlighted() {
+        let content = format!(
+            "{}\nThe Deploy ran. deploy again, ünïcode DEPLOY",
+            "x".repeat(200)
+        );
+        let (text, highlights) = snippet(&content, &["deploy".to_string()]);
+        assert!(text.starts_with('…'));
+        assert!(!text.contains('\n'));
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        let marked: Vec<String> = highlights
+            .iter()
+            .map(|[s, e]| String::from_utf16(&utf16[*s..*e]).unwrap())
+            .collect();
+        assert_eq!(marked, vec!["Deploy", "deploy", "DEPLOY"]);
+
+        let (text, highlights) = snippet("short text", &[]);
+        assert_eq!(text, "short text");
+        assert!(highlights.is_empty());
+    }
+}
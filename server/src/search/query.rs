@@ -0,0 +1,364 @@
+//! The search query language.
+//!
+//! ```text
+//! deploy "connection refused" -staging role:assistant tool:bash
+//! model:opus project:noaide after:2026-01-01 -tool:edit before:7d
+//! ```
+//!
+//! Bare words and quoted phrases must all occur in the message text
+//! (case-insensitive); a leading `-` excludes messages containing them.
+//! `key:value` filters narrow by metadata, take quoted values and can be
+//! negated the same way. Keys that aren't filters are searched as text, so
+//! `http://host` or `Foo::bar` need no quoting.
+
+use crate::discovery::parse_iso_to_epoch_secs;
+
+/// A word or quoted phrase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub text: String,
+    pub negated: bool,
+}
+
+/// Metadata a [`Filter`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `user`, `assistant` or `system`.
+    Role,
+    /// A tool the message calls; `mcp__*` matches by prefix.
+    Tool,
+    /// Substring of the model name (`model:opus`).
+    Model,
+    /// Substring of the project directory.
+    Project,
+    /// Transcript source (`claude`, `codex`, `gemini`, …).
+    Cli,
+    /// Prefix of the session id.
+    Session,
+}
+
+impl Field {
+    fn parse(key: &str) -> Option<Self> {
+        match key {
+            "role" => Some(Self::Role),
+            "tool" => Some(Self::Tool),
+            "model" => Some(Self::Model),
+            "project" => Some(Self::Project),
+            "cli" => Some(Self::Cli),
+            "session" => Some(Self::Session),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub field: Field,
+    /// Lowercased value.
+    pub value: String,
+    pub negated: bool,
+}
+
+/// A parsed query. Every term and filter must hold for a message to match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+    pub filters: Vec<Filter>,
+    /// Inclusive lower bound, epoch seconds.
+    pub after: Option<i64>,
+    /// Exclusive upper bound, epoch seconds.
+    pub before: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryError {
+    #[error("empty query")]
+    Empty,
+    #[error("unknown role `{0}` (expected user, assistant or system)")]
+    UnknownRole(String),
+    #[error(
+        "invalid time `{value}` for {key} (expected YYYY-MM-DD, an ISO timestamp or 30m/12h/7d/2w)"
+    )]
+    InvalidTime { key: String, value: String },
+    #[error("`{0}:` needs a value")]
+    MissingValue(String),
+}
+
+impl Query {
+    /// Parse a query, resolving relative times (`after:7d`) against now.
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Self::parse_at(input, now)
+    }
+
+    /// Parse a query, resolving relative times against `now` (epoch seconds).
+    pub fn parse_at(input: &str, now: i64) -> Result<Self, QueryError> {
+        let mut query = Query::default();
+        for token in tokenize(input) {
+            let Some(key) = token.key else {
+                query.terms.push(Term {
+                    text: token.value,
+                    negated: token.negated,
+                });
+                continue;
+            };
+            if token.value.is_empty() {
+                return Err(QueryError::MissingValue(key));
+            }
+
+            if key == "after" || key == "before" {
+                let t = parse_time(&token.value, now).ok_or_else(|| QueryError::InvalidTime {
+                    key: key.clone(),
+                    value: token.value.clone(),
+                })?;
+                // `-after:x` is `before:x` and vice versa.
+                if (key == "after") != token.negated {
+                    query.after = Some(query.after.map_or(t, |a| a.max(t)));
+                } else {
+                    query.before = Some(query.before.map_or(t, |b| b.min(t)));
+                }
+                continue;
+            }
+
+            let Some(field) = Field::parse(&key) else {
+                continue;
+            };
+            let value = token.value.to_lowercase();
+            if field == Field::Role && !matches!(value.as_str(), "user" | "assistant" | "system") {
+                return Err(QueryError::UnknownRole(token.value));
+            }
+            query.filters.push(Filter {
+                field,
+                value,
+                negated: token.negated,
+            });
+        }
+
+        if query.terms.is_empty()
+            && query.filters.is_empty()
+            && query.after.is_none()
+            && query.before.is_none()
+        {
+            return Err(QueryError::Empty);
+        }
+        Ok(query)
+    }
+
+    /// Terms that must occur, in query order.
+    pub fn required_terms(&self) -> impl Iterator<Item = &str> {
+        self.terms
+            .iter()
+            .filter(|t| !t.negated)
+            .map(|t| t.text.as_str())
+    }
+
+    /// The value of the first non-negated filter on `field`.
+    pub fn required(&self, field: Field) -> Option<&str> {
+        self.filters
+            .iter()
+            .find(|f| f.field == field && !f.negated)
+            .map(|f| f.value.as_str())
+    }
+}
+
+struct Token {
+    negated: bool,
+    key: Option<String>,
+    value: String,
+}
+
+fn is_key(key: &str) -> bool {
+    key == "after" || key == "before" || Field::parse(key).is_some()
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let negated = chars[i] == '-';
+        if negated {
+            i += 1;
+            if i == chars.len() || chars[i].is_whitespace() {
+                continue;
+            }
+        }
+
+        if chars[i] == '"' {
+            let (value, next) = read_quoted(&chars, i + 1);
+            i = next;
+            if !value.is_empty() {
+                tokens.push(Token {
+                    negated,
+                    key: None,
+                    value,
+                });
+            }
+            continue;
+        }
+
+        let start = i;
+        let mut quoted = None;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            // `key:"quoted value"`
+            if chars[i] == ':' && chars.get(i + 1) == Some(&'"') {
+                let key = chars[start..i].iter().collect::<String>().to_lowercase();
+                if is_key(&key) {
+                    let (value, next) = read_quoted(&chars, i + 2);
+                    quoted = Some((key, value));
+                    i = next;
+                    break;
+                }
+            }
+            i += 1;
+        }
+        if let Some((key, value)) = quoted {
+            tokens.push(Token {
+                negated,
+                key: Some(key),
+                value,
+            });
+            continue;
+        }
+        let word: String = chars[start..i].iter().collect();
+        if let Some((key, value)) = word.split_once(':')
+            && is_key(&key.to_lowercase())
+        {
+            tokens.push(Token {
+                negated,
+                key: Some(key.to_lowercase()),
+                value: value.to_string(),
+            });
+        } else {
+            tokens.push(Token {
+                negated,
+                key: None,
+                value: word,
+            });
+        }
+    }
+    tokens
+}
+
+/// Read up to the closing quote (or the end of input). Returns the content
+/// and the index after the closing quote.
+fn read_quoted(chars: &[char], start: usize) -> (String, usize) {
+    let end = chars[start..]
+        .iter()
+        .position(|&c| c == '"')
+        .map_or(chars.len(), |p| start + p);
+    let value: String = chars[start..end].iter().collect();
+    (value.trim().to_string(), (end + 1).min(chars.len()))
+}
+
+/// `YYYY-MM-DD`, an ISO timestamp, or a duration before `now`
+/// (`30m`, `12h`, `7d`, `2w`).
+fn parse_time(value: &str, now: i64) -> Option<i64> {
+    if let Some(unit) = value.chars().last()
+        && let Ok(n) = value[..value.len() - unit.len_utf8()].parse::<i64>()
+    {
+        let secs = match unit {
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 7 * 86_400,
+            _ => return None,
+        };
+        return Some(now - n * secs);
+    }
+    if value.len() == 10 {
+        return parse_iso_to_epoch_secs(&format!("{value}T00:00:00Z"));
+    }
+    parse_iso_to_epoch_secs(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_780_000_000;
+
+    fn term(text: &str, negated: bool) -> Term {
+        Term {
+            text: text.into(),
+            negated,
+        }
+    }
+
+    #[test]
+    fn words_phrases_and_negation() {
+        let q = Query::parse_at(r#"deploy "connection refused" -staging -"dry run""#, NOW).unwrap();
+        assert_eq!(
+            q.terms,
+            vec![
+                term("deploy", false),
+                term("connection refused", false),
+                term("staging", true),
+                term("dry run", true),
+            ]
+        );
+        assert!(q.filters.is_empty());
+        assert_eq!(
+            q.required_terms().collect::<Vec<_>>(),
+            vec!["deploy", "connection refused"]
+        );
+    }
+
+    #[test]
+    fn filters_and_times() {
+        let q = Query::parse_at(
+            r#"role:Assistant tool:Bash -tool:edit project:"my app" model:opus after:2026-01-01 before:7d"#,
+            NOW,
+        )
+        .unwrap();
+        assert!(q.terms.is_empty());
+        assert_eq!(q.required(Field::Role), Some("assistant"));
+        assert_eq!(q.required(Field::Tool), Some("bash"));
+        assert_eq!(q.required(Field::Project), Some("my app"));
+        assert!(
+            q.filters
+                .iter()
+                .any(|f| f.field == Field::Tool && f.value == "edit" && f.negated)
+        );
+        assert_eq!(q.after, Some(1_767_225_600));
+        assert_eq!(q.before, Some(NOW - 7 * 86_400));
+
+        let q = Query::parse_at("-after:2026-01-01T12:00:00Z x", NOW).unwrap();
+        assert_eq!(q.before, Some(1_767_268_800));
+        assert_eq!(q.after, None);
+    }
+
+    #[test]
+    fn unknown_keys_are_text_and_bad_input_is_rejected() {
+        let q = Query::parse_at("http://localhost:8080 Foo::bar", NOW).unwrap();
+        assert_eq!(
+            q.terms,
+            vec![
+                term("http://localhost:8080", false),
+                term("Foo::bar", false)
+            ]
+        );
+
+        assert_eq!(Query::parse_at("  - ", NOW), Err(QueryError::Empty));
+        assert_eq!(
+            Query::parse_at("role:robot", NOW),
+            Err(QueryError::UnknownRole("robot".into()))
+        );
+        assert!(matches!(
+            Query::parse_at("after:yesterday", NOW),
+            Err(QueryError::InvalidTime { .. })
+        ));
+        assert_eq!(
+            Query::parse_at("tool:", NOW),
+            Err(QueryError::MissingValue("tool".into()))
+        );
+    }
+}
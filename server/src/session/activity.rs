@@ -0,0 +1,504 @@
+//! Agent activity: what a session is doing right now.
+//!
+//! [`SessionStatus`](crate::ecs::SessionStatus) only knows active and idle.
+//! The activity model is finer — thinking, running a tool, waiting on a
+//! permission prompt or on the user, rate-limited, compacting, crashed — and
+//! fuses three sources into one state machine per session:
+//!
+//! - PTY output of managed sessions ([`Observation::from_pty`]): spinners,
+//!   tool markers, permission dialogs, retry notices, process exit.
+//! - Transcript entries ([`Observation::from_message`]): tool calls and
+//!   results, end of turn, compaction boundaries.
+//! - Proxy traffic ([`Observation::from_api_status`]): 429/529 responses
+//!   and the successful request that ends a retry loop.
+//!
+//! Each transition carries the time spent in the previous state, and every
+//! tracker keeps running totals per state.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use super::managed::patterns;
+use super::types::ExitStatus;
+use crate::bus::{EventEnvelope, EventSource};
+use crate::ecs::components::{MessageComponent, MessageRole, MessageType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Activity {
+    Starting,
+    Thinking,
+    RunningTool {
+        tool: String,
+    },
+    AwaitingPermission {
+        tool: Option<String>,
+    },
+    AwaitingInput,
+    /// Waiting out a 429/529 or an overloaded API before retrying.
+    RateLimited,
+    Compacting,
+    /// The process ended with a failure exit code or a signal.
+    Crashed {
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+    /// The process ended normally or was closed.
+    Exited,
+}
+
+impl Activity {
+    /// State name, as used for time accounting and in the `state` tag.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::Thinking => "thinking",
+            Self::RunningTool { .. } => "running_tool",
+            Self::AwaitingPermission { .. } => "awaiting_permission",
+            Self::AwaitingInput => "awaiting_input",
+            Self::RateLimited => "rate_limited",
+            Self::Compacting => "compacting",
+            Self::Crashed { .. } => "crashed",
+            Self::Exited => "exited",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Crashed { .. } | Self::Exited)
+    }
+}
+
+/// One piece of evidence about a session, from any source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observation {
+    /// The model is producing output (spinner, streaming text, thinking).
+    Working,
+    ToolStarted(String),
+    ToolFinished,
+    PermissionPrompt,
+    /// The assistant finished its turn.
+    TurnEnded,
+    /// No PTY output for a while.
+    Quiet,
+    /// The user sent a prompt or answered a dialog.
+    Input,
+    RateLimited,
+    /// An API request succeeded.
+    ApiOk,
+    CompactionStarted,
+    CompactionFinished,
+    ProcessExited(ExitStatus),
+    /// The session was closed without an exit status.
+    Closed,
+}
+
+impl Observation {
+    /// Read a chunk of PTY output.
+    pub fn from_pty(output: &str) -> Vec<Self> {
+        let text = patterns::strip_ansi(output);
+        let mut found = Vec::new();
+        if patterns::is_compacting(&text) {
+            found.push(Self::CompactionStarted);
+        }
+        if patterns::is_rate_limited(&text) {
+            found.push(Self::RateLimited);
+        }
+        if let Some(tool) = patterns::tool_name(&text) {
+            found.push(Self::ToolStarted(tool.to_string()));
+        }
+        if patterns::is_permission_prompt(&text) {
+            found.push(Self::PermissionPrompt);
+        } else if found.is_empty() && patterns::is_thinking(&text) {
+            found.push(Self::Working);
+        }
+        found
+    }
+
+    /// Read a parsed transcript entry.
+    pub fn from_message(msg: &MessageComponent) -> Option<Self> {
+        match (msg.role, msg.message_type) {
+            (_, MessageType::CompactBoundary | MessageType::Summary) => {
+                Some(Self::CompactionFinished)
+            }
+            (MessageRole::Assistant, MessageType::ToolUse) => {
+                let tool = msg
+                    .content_blocks_json
+                    .as_deref()
+                    .and_then(|json| {
+                        serde_json::from_str::<Vec<crate::parser::ContentBlock>>(json).ok()
+                    })
+                    .and_then(|blocks| {
+                        blocks.into_iter().rev().find_map(|b| match b {
+                            crate::parser::ContentBlock::ToolUse { name, .. } => Some(name),
+                            _ => None,
+                        })
+                    })?;
+                Some(Self::ToolStarted(tool))
+            }
+            (MessageRole::Assistant, _) if msg.stop_reason.as_deref() == Some("end_turn") => {
+                Some(Self::TurnEnded)
+            }
+            (MessageRole::Assistant, MessageType::Text | MessageType::Thinking) => {
+                Some(Self::Working)
+            }
+            (MessageRole::User, MessageType::ToolResult) => Some(Self::ToolFinished),
+            (MessageRole::User, MessageType::Text) if !msg.hidden => Some(Self::Input),
+            (_, MessageType::Error) if patterns::is_rate_limited(&msg.content) => {
+                Some(Self::RateLimited)
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the status of a proxied API response.
+    pub fn from_api_status(status: u16) -> Option<Self> {
+        match status {
+            429 | 529 => Some(Self::RateLimited),
+            200..=299 => Some(Self::ApiOk),
+            _ => None,
+        }
+    }
+}
+
+/// The next state after `obs`, or `None` if it does not change anything.
+fn next(current: &Activity, obs: &Observation) -> Option<Activity> {
+    use Activity as A;
+    use Observation as O;
+
+    if current.is_terminal() {
+        return None;
+    }
+    let next = match (obs, current) {
+        (O::ProcessExited(status), _) if status.success() => A::Exited,
+        (O::ProcessExited(status), _) => A::Crashed {
+            exit_code: match status {
+                ExitStatus::Code(code) => Some(*code),
+                ExitStatus::Signal(_) => None,
+            },
+            signal: match status {
+                ExitStatus::Signal(signal) => Some(*signal),
+                ExitStatus::Code(_) => None,
+            },
+        },
+        (O::Closed, _) => A::Exited,
+        (O::RateLimited, _) => A::RateLimited,
+        (O::CompactionStarted, _) => A::Compacting,
+        (O::PermissionPrompt, A::RunningTool { tool }) => A::AwaitingPermission {
+            tool: Some(tool.clone()),
+        },
+        (O::PermissionPrompt, A::AwaitingPermission { .. }) => return None,
+        (O::PermissionPrompt, _) => A::AwaitingPermission { tool: None },
+        // The call is echoed before its permission dialog; keep waiting.
+        (O::ToolStarted(_), A::AwaitingPermission { .. }) => return None,
+        (O::ToolStarted(tool), _) => A::RunningTool { tool: tool.clone() },
+        (O::ToolFinished, A::RunningTool { .. } | A::AwaitingPermission { .. }) => A::Thinking,
+        (O::Input, A::AwaitingPermission { tool: Some(tool) }) => {
+            A::RunningTool { tool: tool.clone() }
+        }
+        (O::Input, _) => A::Thinking,
+        (O::TurnEnded, _) => A::AwaitingInput,
+        (O::ApiOk, A::RateLimited) => A::Thinking,
+        (O::CompactionFinished, A::Compacting) => A::Thinking,
+        // Spinners keep turning while tools run, retries wait and history
+        // is compacted; they only mean something after a pause.
+        (O::Working, A::Starting | A::AwaitingInput) => A::Thinking,
+        (O::Quiet, A::Starting | A::Thinking) => A::AwaitingInput,
+        _ => return None,
+    };
+    (next != *current).then_some(next)
+}
+
+/// One state change of one session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub session_id: Uuid,
+    pub from: Activity,
+    pub to: Activity,
+    /// Epoch milliseconds.
+    pub at_ms: i64,
+    /// Time spent in `from`.
+    pub from_duration_ms: u64,
+}
+
+impl Transition {
+    /// The `session/status` event for this transition.
+    pub fn envelope(&self, source: EventSource) -> EventEnvelope {
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "type": "session_activity",
+            "session_id": self.session_id.to_string(),
+            "activity": self.to,
+            "previous": self.from,
+            "at_ms": self.at_ms,
+            "previous_duration_ms": self.from_duration_ms,
+        }))
+        .unwrap_or_default();
+        EventEnvelope::new(source, 0, 0, Some(self.session_id), payload)
+    }
+}
+
+/// Current activity of a session and the time spent per state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityReport {
+    pub activity: Activity,
+    /// Epoch milliseconds the current state began.
+    pub since_ms: i64,
+    /// Milliseconds per state key, including the current state up to now.
+    pub totals_ms: BTreeMap<String, u64>,
+    pub transitions: u64,
+}
+
+#[derive(Debug)]
+struct Tracker {
+    activity: Activity,
+    since_ms: i64,
+    totals_ms: BTreeMap<&'static str, u64>,
+    transitions: u64,
+}
+
+impl Tracker {
+    fn new(at_ms: i64) -> Self {
+        Self {
+            activity: Activity::Starting,
+            since_ms: at_ms,
+            totals_ms: BTreeMap::new(),
+            transitions: 0,
+        }
+    }
+
+    fn observe(&mut self, obs: &Observation, at_ms: i64) -> Option<(Activity, Activity, u64)> {
+        let to = next(&self.activity, obs)?;
+        let spent = at_ms.saturating_sub(self.since_ms).max(0) as u64;
+        *self.totals_ms.entry(self.activity.key()).or_default() += spent;
+        let from = std::mem::replace(&mut self.activity, to.clone());
+        self.since_ms = at_ms;
+        self.transitions += 1;
+        Some((from, to, spent))
+    }
+
+    fn report(&self, now_ms: i64) -> ActivityReport {
+        let mut totals_ms: BTreeMap<String, u64> = self
+            .totals_ms
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        if !self.activity.is_terminal() {
+            *totals_ms
+                .entry(self.activity.key().to_string())
+                .or_default() += now_ms.saturating_sub(self.since_ms).max(0) as u64;
+        }
+        ActivityReport {
+            activity: self.activity.clone(),
+            since_ms: self.since_ms,
+            totals_ms,
+            transitions: self.transitions,
+        }
+    }
+}
+
+/// Activity trackers of all sessions, keyed by the (managed) session id.
+#[derive(Clone, Default)]
+pub struct ActivityBoard {
+    trackers: Arc<RwLock<HashMap<Uuid, Tracker>>>,
+}
+
+impl ActivityBoard {
+    /// Start tracking a freshly spawned session.
+    pub async fn start(&self, session_id: Uuid, at_ms: i64) {
+        self.trackers
+            .write()
+            .await
+            .insert(session_id, Tracker::new(at_ms));
+    }
+
+    /// Apply an observation. Sessions seen for the first time start out as
+    /// [`Activity::Starting`] at `at_ms`.
+    pub async fn observe(
+        &self,
+        session_id: Uuid,
+        obs: &Observation,
+        at_ms: i64,
+    ) -> Option<Transition> {
+        let mut trackers = self.trackers.write().await;
+        let tracker = trackers
+            .entry(session_id)
+            .or_insert_with(|| Tracker::new(at_ms));
+        let (from, to, from_duration_ms) = tracker.observe(obs, at_ms)?;
+        Some(Transition {
+            session_id,
+            from,
+            to,
+            at_ms,
+            from_duration_ms,
+        })
+    }
+
+    pub async fn report(&self, session_id: Uuid, now_ms: i64) -> Option<ActivityReport> {
+        let trackers = self.trackers.read().await;
+        trackers.get(&session_id).map(|t| t.report(now_ms))
+    }
+
+    /// Stop tracking a session that was closed or deleted.
+    pub async fn remove(&self, session_id: Uuid) -> bool {
+        self.trackers.write().await.remove(&session_id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: MessageRole, message_type: MessageType) -> MessageComponent {
+        MessageComponent {
+            message_type,
+            ..MessageComponent::test(Uuid::nil(), role, "", 0)
+        }
+    }
+
+    #[test]
+    fn pty_output_is_classified() {
+        assert_eq!(
+            Observation::from_pty("\u{1b}[2K\u{1b}[1m\u{280B}\u{1b}[0m Pondering…"),
+            [Observation::Working]
+        );
+        assert_eq!(
+            Observation::from_pty("⏺ Bash(cargo test)\r\n Do you want to proceed?\r\n ❯ 1. Yes"),
+            [
+                Observation::ToolStarted("Bash".into()),
+                Observation::PermissionPrompt
+            ]
+        );
+        assert_eq!(
+            Observation::from_pty("API Error (429 rate_limit_error) · Retrying in 5 seconds…"),
+            [Observation::RateLimited]
+        );
+        assert!(
+            Observation::from_pty("Compacting conversation…")
+                .contains(&Observation::CompactionStarted)
+        );
+        assert!(Observation::from_pty("ReadBuffer(x)").is_empty());
+    }
+
+    #[test]
+    fn transcript_entries_are_classified() {
+        let mut call = message(MessageRole::Assistant, MessageType::ToolUse);
+        call.content_blocks_json = Some(
+            serde_json::json!([{"type": "tool_use", "id": "t1", "name": "Edit", "input": {}}])
+                .to_string(),
+        );
+        assert_eq!(
+            Observation::from_message(&call),
+            Some(Observation::ToolStarted("Edit".into()))
+        );
+        let mut done = message(MessageRole::Assistant, MessageType::Text);
+        done.stop_reason = Some("end_turn".into());
+        assert_eq!(
+            Observation::from_message(&done),
+            Some(Observation::TurnEnded)
+        );
+        assert_eq!(
+            Observation::from_message(&message(MessageRole::User, MessageType::ToolResult)),
+            Some(Observation::ToolFinished)
+        );
+        assert_eq!(
+            Observation::from_message(&message(MessageRole::Meta, MessageType::CompactBoundary)),
+            Some(Observation::CompactionFinished)
+        );
+    }
+
+    #[tokio::test]
+    async fn transitions_account_time_per_state() {
+        let board = ActivityBoard::default();
+        let sid = Uuid::new_v4();
+        board.start(sid, 0).await;
+
+        let steps = [
+            (Observation::Working, 1_000, Some("thinking")),
+            (
+                Observation::ToolStarted("Bash".into()),
+                3_000,
+                Some("running_tool"),
+            ),
+            (
+                Observation::PermissionPrompt,
+                3_100,
+                Some("awaiting_permission"),
+            ),
+            // The echo of the same call does not cancel the prompt.
+            (Observation::ToolStarted("Bash".into()), 3_200, None),
+            (Observation::Input, 8_000, Some("running_tool")),
+            (Observation::Working, 8_500, None),
+            (Observation::ToolFinished, 9_000, Some("thinking")),
+            (Observation::RateLimited, 9_500, Some("rate_limited")),
+            (Observation::Working, 10_000, None),
+            (Observation::ApiOk, 12_000, Some("thinking")),
+            (Observation::TurnEnded, 13_000, Some("awaiting_input")),
+            (Observation::Quiet, 20_000, None),
+        ];
+        for (obs, at, expected) in steps {
+            let transition = board.observe(sid, &obs, at).await;
+            assert_eq!(transition.as_ref().map(|t| t.to.key()), expected, "{obs:?}");
+        }
+
+        let report = board.report(sid, 15_000).await.unwrap();
+        assert_eq!(report.activity, Activity::AwaitingInput);
+        assert_eq!(report.since_ms, 13_000);
+        assert_eq!(report.totals_ms["starting"], 1_000);
+        assert_eq!(report.totals_ms["thinking"], 2_000 + 500 + 1_000);
+        assert_eq!(report.totals_ms["running_tool"], 100 + 1_000);
+        assert_eq!(report.totals_ms["awaiting_permission"], 4_900);
+        assert_eq!(report.totals_ms["rate_limited"], 2_500);
+        assert_eq!(report.totals_ms["awaiting_input"], 2_000);
+        assert_eq!(report.transitions, 8);
+    }
+
+    #[tokio::test]
+    async fn exit_status_decides_between_crashed_and_exited() {
+        let board = ActivityBoard::default();
+        let (crashed, exited) = (Uuid::new_v4(), Uuid::new_v4());
+        let t = board
+            .observe(crashed, &Observation::ProcessExited(ExitStatus::Code(1)), 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            t.to,
+            Activity::Crashed {
+                exit_code: Some(1),
+                signal: None
+            }
+        );
+        // Terminal: the close that follows changes nothing.
+        assert!(
+            board
+                .observe(crashed, &Observation::Closed, 6)
+                .await
+                .is_none()
+        );
+
+        board
+            .observe(exited, &Observation::ProcessExited(ExitStatus::Code(0)), 5)
+            .await;
+        assert_eq!(
+            board.report(exited, 10).await.unwrap().activity,
+            Activity::Exited
+        );
+    }
+
+    #[tokio::test]
+    async fn removed_sessions_are_forgotten() {
+        let board = ActivityBoard::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        board.start(a, 0).await;
+        board.start(b, 0).await;
+        board.observe(a, &Observation::Closed, 10).await;
+
+        assert!(board.remove(a).await);
+        assert!(!board.remove(a).await);
+        assert!(board.report(a, 20).await.is_none());
+        assert!(board.report(b, 20).await.is_some());
+    }
+}
@@ -1,20 +1,26 @@
 use std::ffi::CString;
 use std::io::Write;
 use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Duration;
 
 use nix::libc;
 use nix::pty::{ForkptyResult, Winsize, forkpty};
+use nix::sys::signal::{Signal, killpg};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::{Pid, execvp};
 use tokio::sync::{Mutex, broadcast};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use super::recording::{Header, Recorder};
+use super::sandbox::{self, Plan, Sandbox, SandboxConfig};
+use super::terminal::{DEFAULT_SIZE, SizePolicy, Terminal};
 use super::types::{
-    SESSION_EVENT_CAPACITY, Session, SessionError, SessionEvent, SessionId, SessionMode,
-    SessionState,
+    ExitStatus, SESSION_EVENT_CAPACITY, Session, SessionError, SessionEvent, SessionId,
+    SessionMode, SessionState,
 };
 
 /// State encoding for AtomicU8.
@@ -24,6 +30,11 @@ const STATE_IDLE: u8 = 2;
 const STATE_ERROR: u8 = 3;
 const STATE_CLOSED: u8 = 4;
 
+/// How long `close()` lets the CLI react to ^C/^D before signalling it.
+const CLOSE_GRACE: Duration = Duration::from_secs(2);
+/// How long `close()` waits after each signal to the process group.
+const SIGNAL_GRACE: Duration = Duration::from_secs(3);
+
 fn state_from_u8(v: u8) -> SessionState {
     match v {
         STATE_STARTING => SessionState::Starting,
@@ -35,6 +46,29 @@ fn state_from_u8(v: u8) -> SessionState {
     }
 }
 
+/// Optional settings for [`ManagedSession::spawn_with`].
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    /// Reuse this session id instead of generating one. A resumed session
+    /// keeps its proxy prefix (`/s/{id}`) and ECS entry.
+    pub session_id: Option<Uuid>,
+    /// Appended after the built-in CLI arguments (e.g. `--resume <id>`).
+    pub extra_args: Vec<String>,
+    /// Extra environment, applied after (and overriding) the generated one.
+    pub env: Vec<(String, String)>,
+    /// Record the terminal to this asciicast file (see [`super::recording`]).
+    pub record_to: Option<PathBuf>,
+    /// Initial terminal size as `(cols, rows)`; 80x24 if unset.
+    pub size: Option<(u16, u16)>,
+    /// How attached viewers size the terminal.
+    pub size_policy: SizePolicy,
+    /// Run the CLI in a sandbox (see [`super::sandbox`]).
+    pub sandbox: Option<SandboxConfig>,
+}
+
+/// How long a sandboxed child may take to set up its namespaces.
+const SANDBOX_SETUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 fn build_exec_argv(
     binary: &str,
     auto_approve: bool,
@@ -43,8 +77,11 @@ fn build_exec_argv(
     let c_binary = CString::new(binary).map_err(|e| SessionError::PtySpawn(e.to_string()))?;
     let mut c_args = vec![c_binary.clone()];
 
-    if auto_approve && binary == "claude" {
-        c_args.push(CString::new("--dangerously-skip-permissions").unwrap());
+    if auto_approve
+        && let Some(flag) =
+            crate::parser::registry::by_name(binary).and_then(|source| source.auto_approve_flag())
+    {
+        c_args.push(CString::new(flag).unwrap());
     }
 
     if binary == "codex"
@@ -60,25 +97,116 @@ fn build_exec_argv(
     Ok((c_binary, c_args))
 }
 
-/// PTY output patterns used to detect Breathing Orb state.
-/// Currently used in tests; will drive fine-grained state detection in WP-9.
-#[allow(dead_code)]
-mod patterns {
+/// PTY output patterns that feed the activity model (see
+/// [`super::activity`]). Callers strip ANSI escapes first.
+pub(crate) mod patterns {
     /// Braille spinner characters → THINKING state.
-    pub const BRAILLE_CHARS: &[char] = &['\u{280B}', '\u{2819}', '\u{2839}', '\u{2838}'];
+    pub const BRAILLE_CHARS: &[char] = &[
+        '\u{280B}', '\u{2819}', '\u{2839}', '\u{2838}', '\u{283C}', '\u{2834}', '\u{2826}',
+        '\u{2827}', '\u{2807}', '\u{280F}',
+    ];
+
+    /// Tool names as they appear in PTY output ("⏺ Read(...)").
+    const TOOL_MARKERS: &[&str] = &[
+        "Read",
+        "Edit",
+        "MultiEdit",
+        "Bash",
+        "Write",
+        "Glob",
+        "Grep",
+        "Task",
+        "LSP",
+        "WebFetch",
+        "WebSearch",
+        "NotebookEdit",
+        "TodoWrite",
+    ];
+
+    /// Permission dialogs of Claude, Codex and Gemini.
+    const PERMISSION_PROMPTS: &[&str] = &[
+        "Do you want to proceed?",
+        "Do you want to make this edit",
+        "Do you want to create",
+        "Allow command?",
+        "Allow execution",
+        "Apply this change?",
+    ];
+
+    const RATE_LIMIT_MARKERS: &[&str] = &[
+        "rate limit",
+        "rate_limit_error",
+        "overloaded",
+        "Retrying in",
+    ];
 
     /// Check if output contains a braille spinner pattern.
     pub fn is_thinking(output: &str) -> bool {
         output.chars().any(|c| BRAILLE_CHARS.contains(&c))
+            || output.to_ascii_lowercase().contains("esc to interrupt")
+    }
+
+    /// Name of the tool whose call marker appears in `output` (Read, Edit,
+    /// Bash, etc.).
+    pub fn tool_name(output: &str) -> Option<&'static str> {
+        TOOL_MARKERS.iter().copied().find(|name| {
+            output.match_indices(name).any(|(at, _)| {
+                let before = output[..at].chars().next_back();
+                output[at + name.len()..].starts_with('(')
+                    && !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            })
+        })
+    }
+
+    pub fn is_permission_prompt(output: &str) -> bool {
+        PERMISSION_PROMPTS.iter().any(|p| output.contains(p))
     }
 
-    /// Check if output looks like tool use (Read, Edit, Bash, etc.).
-    pub fn is_tool_use(output: &str) -> bool {
-        // Tool names appear in PTY output as "⏺ Read(...)" etc.
-        let tool_markers = [
-            "Read(", "Edit(", "Bash(", "Write(", "Glob(", "Grep(", "Task(", "LSP(",
-        ];
-        tool_markers.iter().any(|m| output.contains(m))
+    pub fn is_rate_limited(output: &str) -> bool {
+        let lower = output.to_ascii_lowercase();
+        RATE_LIMIT_MARKERS
+            .iter()
+            .any(|m| lower.contains(&m.to_ascii_lowercase()))
+    }
+
+    pub fn is_compacting(output: &str) -> bool {
+        output.contains("Compacting conversation")
+    }
+
+    /// Drop ANSI escape sequences (CSI, OSC and two-byte escapes).
+    pub fn strip_ansi(output: &str) -> String {
+        let mut out = String::with_capacity(output.len());
+        let mut chars = output.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\u{1b}' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                // CSI: parameters up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: up to BEL or ST (ESC \)
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' {
+                            break;
+                        }
+                        if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        out
     }
 }
 
@@ -94,6 +222,12 @@ pub struct ManagedSession {
     event_tx: broadcast::Sender<SessionEvent>,
     /// PID of the child process (kept for cleanup).
     child_pid: Pid,
+    /// Exit status, set once the child was reaped; its PID may be reused after that.
+    exit: std::sync::Mutex<Option<ExitStatus>>,
+    /// Set once `Exited`/`Closed` were announced.
+    finished: AtomicBool,
+    terminal: Arc<Terminal>,
+    sandbox: Option<Arc<Sandbox>>,
 }
 
 impl ManagedSession {
@@ -111,15 +245,54 @@ impl ManagedSession {
         cli_type: &str,
         auto_approve: bool,
     ) -> Result<Arc<Self>, SessionError> {
-        // Generate session ID FIRST — needed for per-session proxy URL prefix
-        let session_id = SessionId(Uuid::new_v4());
-
-        // Select binary name based on CLI type
-        let binary = match cli_type {
-            "codex" => "codex",
-            "gemini" => "gemini",
-            _ => "claude", // default
-        };
+        Self::spawn_with(
+            working_dir,
+            anthropic_base_url,
+            cli_type,
+            auto_approve,
+            LaunchOptions::default(),
+        )
+    }
+
+    /// [`Self::spawn_with`] on the blocking pool. A sandboxed spawn waits
+    /// up to `SANDBOX_SETUP_TIMEOUT` for the child's namespace handoff, which
+    /// must not stall an async worker.
+    pub async fn spawn_blocking(
+        working_dir: PathBuf,
+        anthropic_base_url: Option<String>,
+        cli_type: String,
+        auto_approve: bool,
+        options: LaunchOptions,
+    ) -> Result<Arc<Self>, SessionError> {
+        tokio::task::spawn_blocking(move || {
+            Self::spawn_with(
+                &working_dir,
+                anthropic_base_url.as_deref(),
+                &cli_type,
+                auto_approve,
+                options,
+            )
+        })
+        .await
+        .map_err(|e| SessionError::PtySpawn(format!("spawn task failed: {e}")))?
+    }
+
+    /// [`Self::spawn`] with a fixed session id, extra arguments or extra
+    /// environment.
+    pub fn spawn_with(
+        working_dir: &Path,
+        anthropic_base_url: Option<&str>,
+        cli_type: &str,
+        auto_approve: bool,
+        options: LaunchOptions,
+    ) -> Result<Arc<Self>, SessionError> {
+        // Session ID FIRST — needed for per-session proxy URL prefix
+        let session_id = SessionId(options.session_id.unwrap_or_else(Uuid::new_v4));
+
+        // Select binary name based on CLI type (unknown names fall back to claude)
+        let source = crate::parser::registry::by_name(cli_type)
+            .unwrap_or_else(|| crate::parser::registry::for_cli(Default::default()));
+        let binary = source.binary();
 
         // Build environment variables for the child process.
         // Collect all CLAUDE_CODE_* vars from the parent to clear them.
@@ -129,6 +302,8 @@ impl ManagedSession {
         // headless instead of showing an interactive TUI prompt.
         let mut env_vars: Vec<(String, String)> = Vec::new();
         let mut codex_chatgpt_base_url: Option<String> = None;
+        // `host:port` of the proxy, forwarded into a sandbox's network
+        let mut proxy_addr: Option<(String, u16)> = None;
         env_vars.push(("TERM".to_string(), "xterm-256color".to_string()));
         // Clear ALL Claude-related env vars to ensure fresh interactive session
         env_vars.push(("CLAUDECODE".to_string(), String::new()));
@@ -191,6 +366,12 @@ impl ManagedSession {
                 .next()
                 .and_then(|p| p.parse::<u16>().ok())
                 .unwrap_or(4434);
+            let proxy_host = base
+                .split_once("://")
+                .map_or(base, |(_, rest)| rest)
+                .rsplit_once(':')
+                .map_or("localhost", |(host, _)| host);
+            proxy_addr = Some((proxy_host.to_string(), proxy_port));
 
             env_vars.push((
                 "HTTPS_PROXY".to_string(),
@@ -219,18 +400,44 @@ impl ManagedSession {
                 // Codex CLI (native Rust binary, respects this env var)
                 env_vars.push(("CODEX_CA_CERTIFICATE".to_string(), ca_path));
             }
+
+            // Source-specific overrides (Aider, OpenCode) replace the generic
+            // provider URLs above.
+            for (key, value) in source.proxy_env(&format!("{base}/s/{sid}")) {
+                env_vars.retain(|(k, _)| *k != key);
+                env_vars.push((key, value));
+            }
+        }
+
+        for (key, value) in options.env {
+            env_vars.retain(|(k, _)| *k != key);
+            env_vars.push((key, value));
         }
 
         // Prepare argv before fork (no heap allocation after fork).
-        let (c_binary, c_args) =
+        let (c_binary, mut c_args) =
             build_exec_argv(binary, auto_approve, codex_chatgpt_base_url.as_deref())?;
+        for arg in options.extra_args {
+            c_args.push(CString::new(arg).map_err(|e| SessionError::PtySpawn(e.to_string()))?);
+        }
 
         let working_dir_owned = working_dir.to_path_buf();
 
-        // Set terminal size to 80x24
+        let sandbox_plan = options
+            .sandbox
+            .as_ref()
+            .map(|config| Plan::new(config, working_dir, proxy_addr.as_ref().map(|(_, p)| *p)))
+            .transpose()
+            .map_err(SessionError::PtySpawn)?;
+        let sandbox_channel = sandbox_plan
+            .as_ref()
+            .map(|_| sandbox::channel())
+            .transpose()?;
+
+        let (cols, rows) = options.size.unwrap_or(DEFAULT_SIZE);
         let winsize = Winsize {
-            ws_row: 24,
-            ws_col: 80,
+            ws_row: rows,
+            ws_col: cols,
             ws_xpixel: 0,
             ws_ypixel: 0,
         };
@@ -247,6 +454,12 @@ impl ManagedSession {
                 // forkpty already did: setsid, TIOCSCTTY, dup2(slave → 0/1/2), close slave.
                 // We just need to set env vars, chdir, and exec.
 
+                // Namespaces before chdir, so the working directory is the
+                // sandbox's bind mount.
+                if let (Some(plan), Some((_, channel))) = (&sandbox_plan, &sandbox_channel) {
+                    plan.enter(channel.as_raw_fd());
+                }
+
                 // SAFETY: Post-fork, single-threaded in child.
                 unsafe {
                     for (key, val) in &env_vars {
@@ -271,12 +484,31 @@ impl ManagedSession {
             ForkptyResult::Parent { child, master } => (master, child),
         };
 
+        let sandbox_handoff = match sandbox_channel {
+            Some((channel, child_end)) => {
+                drop(child_end);
+                match sandbox::receive(channel, SANDBOX_SETUP_TIMEOUT) {
+                    Ok(handoff) => Some(handoff),
+                    Err(e) => {
+                        let _ =
+                            nix::sys::signal::kill(child_pid, nix::sys::signal::Signal::SIGKILL);
+                        let _ = waitpid(child_pid, None);
+                        return Err(SessionError::PtySpawn(e));
+                    }
+                }
+            }
+            None => None,
+        };
+
         // Master fd: use try_clone() for separate reader (idiomatic dup)
         let master_raw = master_fd.as_raw_fd();
         let master_file = unsafe { std::fs::File::from_raw_fd(master_fd.into_raw_fd()) };
         let reader_file = master_file
             .try_clone()
             .map_err(|e| SessionError::PtySpawn(format!("dup master failed: {e}")))?;
+        let resize_file = master_file
+            .try_clone()
+            .map_err(|e| SessionError::PtySpawn(format!("dup master failed: {e}")))?;
 
         info!(
             session = %session_id,
@@ -288,12 +520,42 @@ impl ManagedSession {
 
         let (event_tx, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
 
+        let recorder = options.record_to.and_then(|path| {
+            let title = format!("{binary} {}", working_dir.display());
+            let header = Header::new(winsize.ws_col, winsize.ws_row, Some(title));
+            Recorder::create(path, &header)
+                .inspect_err(|e| warn!(session = %session_id, error = %e, "PTY recording disabled"))
+                .ok()
+        });
+        let terminal = Terminal::new(resize_file, (cols, rows), options.size_policy, recorder);
+
+        let sandbox = options.sandbox.zip(sandbox_handoff).map(|(config, handoff)| {
+            let tx = event_tx.clone();
+            let project = sandbox_plan
+                .as_ref()
+                .map_or_else(|| working_dir.to_path_buf(), |plan| plan.project().to_path_buf());
+            info!(session = %session_id, network = ?config.network, "managed session sandboxed");
+            Sandbox::start(
+                config,
+                project,
+                handoff,
+                proxy_addr.map(|(host, port)| format!("{host}:{port}")),
+                move |violation| {
+                    let _ = tx.send(SessionEvent::SandboxViolation(violation));
+                },
+            )
+        });
+
         let session = Arc::new(Self {
             id: session_id.clone(),
             state: AtomicU8::new(STATE_STARTING),
             writer: Mutex::new(master_file),
             event_tx: event_tx.clone(),
             child_pid,
+            exit: std::sync::Mutex::new(None),
+            finished: AtomicBool::new(false),
+            terminal: Arc::new(terminal),
+            sandbox,
         });
 
         // Background task: read PTY master and emit events.
@@ -347,8 +609,7 @@ impl ManagedSession {
                             continue; // Interrupted by signal, retry
                         }
                         warn!(session = %sid, error = %err, "PTY poll error");
-                        state_ref.set_state(SessionState::Closed);
-                        let _ = tx.send(SessionEvent::Closed);
+                        state_ref.finish(&tx);
                         break;
                     }
 
@@ -367,8 +628,7 @@ impl ManagedSession {
 
                         if n == 0 {
                             info!(session = %sid, "PTY EOF (read returned 0)");
-                            state_ref.set_state(SessionState::Closed);
-                            let _ = tx.send(SessionEvent::Closed);
+                            state_ref.finish(&tx);
                             break;
                         }
                         if n < 0 {
@@ -380,12 +640,12 @@ impl ManagedSession {
                             } else {
                                 warn!(session = %sid, error = %err, "PTY read error");
                             }
-                            state_ref.set_state(SessionState::Closed);
-                            let _ = tx.send(SessionEvent::Closed);
+                            state_ref.finish(&tx);
                             break;
                         }
 
                         let n = n as usize;
+                        state_ref.terminal.output(&buf[..n]);
                         let output = String::from_utf8_lossy(&buf[..n]).to_string();
 
                         if !output.trim().is_empty() {
@@ -404,8 +664,7 @@ impl ManagedSession {
                             pollerr = has_pollerr,
                             "PTY hangup/error (slave closed)"
                         );
-                        state_ref.set_state(SessionState::Closed);
-                        let _ = tx.send(SessionEvent::Closed);
+                        state_ref.finish(&tx);
                         break;
                     }
                 }
@@ -443,6 +702,118 @@ impl ManagedSession {
         Ok(session)
     }
 
+    /// The PTY is gone: collect the exit status and announce the close.
+    ///
+    /// Called by the reader thread and by `close()`; only the first call announces.
+    fn finish(&self, tx: &broadcast::Sender<SessionEvent>) {
+        let status = self.reap();
+        if self.finished.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.terminal.close(
+            status
+                .map(|status| match status {
+                    ExitStatus::Code(code) => format!("exit {code}"),
+                    ExitStatus::Signal(signal) => format!("signal {signal}"),
+                })
+                .as_deref(),
+        );
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.stop();
+        }
+        if let Some(status) = status {
+            info!(session = %self.id, ?status, "managed session process exited");
+            let _ = tx.send(SessionEvent::Exited(status));
+        }
+        self.set_state(SessionState::Closed);
+        let _ = tx.send(SessionEvent::Closed);
+    }
+
+    /// The asciicast file this session is recorded to, if any.
+    pub fn recording_path(&self) -> Option<&Path> {
+        self.terminal.recording_path()
+    }
+
+    /// Wait briefly for the child to exit after its PTY closed.
+    fn reap(&self) -> Option<ExitStatus> {
+        for _ in 0..20 {
+            // Held across waitpid so the reader thread and close() never both reap
+            let mut exit = self.exit.lock().unwrap_or_else(|e| e.into_inner());
+            if exit.is_some() {
+                return *exit;
+            }
+            match waitpid(self.child_pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => {
+                    *exit = Some(ExitStatus::Code(code));
+                    return *exit;
+                }
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    *exit = Some(ExitStatus::Signal(signal as i32));
+                    return *exit;
+                }
+                Ok(_) => {
+                    drop(exit);
+                    std::thread::sleep(std::time::Duration::from_millis(25));
+                }
+                Err(_) => return None,
+            }
+        }
+        None
+    }
+
+    fn reaped(&self) -> bool {
+        self.exit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Whether the child has exited, without reaping it.
+    ///
+    /// An unreaped zombie keeps its PID and process group ID reserved, so the
+    /// group can still be signalled safely.
+    fn exited(&self) -> bool {
+        if self.reaped() {
+            return true;
+        }
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let rc = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                self.child_pid.as_raw() as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+            )
+        };
+        rc == 0 && unsafe { info.si_pid() } != 0
+    }
+
+    /// Poll until the child exits or `grace` elapses.
+    async fn wait_exit(&self, grace: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + grace;
+        loop {
+            if self.exited() {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+    }
+
+    /// Signal the child's process group (forkpty made it a session leader).
+    fn signal_group(&self, signal: Signal) {
+        if self.reaped() {
+            return;
+        }
+        if let Err(e) = killpg(self.child_pid, signal)
+            && e != nix::errno::Errno::ESRCH
+        {
+            warn!(session = %self.id, ?signal, error = %e, "failed to signal process group");
+        }
+    }
+
     fn set_state(&self, state: SessionState) {
         let val = match state {
             SessionState::Starting => STATE_STARTING,
@@ -458,7 +829,10 @@ impl ManagedSession {
 impl Drop for ManagedSession {
     fn drop(&mut self) {
         // Best-effort cleanup: send SIGTERM to the child process
-        let _ = nix::sys::signal::kill(self.child_pid, nix::sys::signal::Signal::SIGTERM);
+        if self.reaped() {
+            return;
+        }
+        let _ = nix::sys::signal::kill(self.child_pid, Signal::SIGTERM);
     }
 }
 
@@ -480,6 +854,7 @@ impl Session for ManagedSession {
         let mut writer = self.writer.lock().await;
         writer.write_all(text.as_bytes())?;
         writer.flush()?;
+        self.terminal.input(text);
         debug!(session = %self.id, bytes = text.len(), "sent input to PTY");
         Ok(())
     }
@@ -488,22 +863,49 @@ impl Session for ManagedSession {
         self.event_tx.subscribe()
     }
 
+    fn terminal(&self) -> Option<Arc<Terminal>> {
+        Some(Arc::clone(&self.terminal))
+    }
+
+    fn sandbox(&self) -> Option<Arc<Sandbox>> {
+        self.sandbox.clone()
+    }
+
     async fn close(&self) -> anyhow::Result<()> {
         info!(session = %self.id, "closing managed session");
-        // Send Ctrl-C then Ctrl-D to gracefully terminate
-        {
-            let mut writer = self.writer.lock().await;
-            let _ = writer.write_all(b"\x03"); // Ctrl-C
-            let _ = writer.flush();
+        if !self.exited() {
+            // Send Ctrl-C then Ctrl-D to gracefully terminate
+            {
+                let mut writer = self.writer.lock().await;
+                let _ = writer.write_all(b"\x03"); // Ctrl-C
+                let _ = writer.flush();
+            }
+            self.terminal.input("\x03");
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            {
+                let mut writer = self.writer.lock().await;
+                let _ = writer.write_all(b"\x04"); // Ctrl-D (EOF)
+                let _ = writer.flush();
+            }
+            self.terminal.input("\x04");
         }
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        {
-            let mut writer = self.writer.lock().await;
-            let _ = writer.write_all(b"\x04"); // Ctrl-D (EOF)
-            let _ = writer.flush();
+        let mut exited = self.wait_exit(CLOSE_GRACE).await;
+        for signal in [Signal::SIGTERM, Signal::SIGKILL] {
+            if exited {
+                break;
+            }
+            debug!(session = %self.id, ?signal, "CLI still running, signalling its process group");
+            self.signal_group(signal);
+            exited = self.wait_exit(SIGNAL_GRACE).await;
+        }
+        if exited {
+            // Tools the CLI left behind in its group must not outlive the session
+            self.signal_group(Signal::SIGKILL);
+        }
+        self.finish(&self.event_tx);
+        if !self.reaped() {
+            anyhow::bail!("process {} did not exit after SIGKILL", self.child_pid);
         }
-        self.set_state(SessionState::Closed);
-        let _ = self.event_tx.send(SessionEvent::Closed);
         Ok(())
     }
 }
@@ -539,9 +941,9 @@ mod tests {
 
     #[test]
     fn pattern_tool_detection() {
-        assert!(patterns::is_tool_use("Read(file.rs)"));
-        assert!(patterns::is_tool_use("running Bash(ls -la)"));
-        assert!(!patterns::is_tool_use("just some text output"));
+        assert_eq!(patterns::tool_name("Read(file.rs)"), Some("Read"));
+        assert_eq!(patterns::tool_name("running Bash(ls -la)"), Some("Bash"));
+        assert_eq!(patterns::tool_name("just some text output"), None);
     }
 
     /// Helper: spawn a test session with an arbitrary command via raw PTY.
@@ -586,6 +988,12 @@ mod tests {
 
         let master_file = unsafe { std::fs::File::from_raw_fd(master_fd.into_raw_fd()) };
         let reader_file = master_file.try_clone().unwrap();
+        let terminal = Terminal::new(
+            master_file.try_clone().unwrap(),
+            DEFAULT_SIZE,
+            SizePolicy::default(),
+            None,
+        );
 
         let (event_tx, event_rx) = broadcast::channel(64);
 
@@ -595,6 +1003,10 @@ mod tests {
             writer: Mutex::new(master_file),
             event_tx: event_tx.clone(),
             child_pid,
+            exit: std::sync::Mutex::new(None),
+            finished: AtomicBool::new(false),
+            terminal: Arc::new(terminal),
+            sandbox: None,
         });
 
         // Background reader thread
@@ -769,6 +1181,41 @@ mod tests {
         session.close().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn close_kills_a_cli_that_ignores_interrupts() {
+        // Ignores ^C and SIGTERM (inherited by `sleep`), so only SIGKILL ends the group
+        let (session, mut event_rx) = spawn_test_session(
+            "sh",
+            &[
+                "-c",
+                "trap '' INT TERM HUP; echo ready; while :; do sleep 1; done",
+            ],
+            &[],
+        );
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        while tokio::time::Instant::now() < deadline {
+            match tokio::time::timeout(std::time::Duration::from_secs(2), event_rx.recv()).await {
+                Ok(Ok(SessionEvent::Output(text))) if text.contains("ready") => break,
+                Ok(Ok(_)) => {}
+                _ => break,
+            }
+        }
+
+        session.close().await.unwrap();
+
+        assert_eq!(
+            session.exit.lock().unwrap().as_ref(),
+            Some(&ExitStatus::Signal(Signal::SIGKILL as i32))
+        );
+        assert_eq!(session.state(), SessionState::Closed);
+        assert_eq!(
+            nix::sys::signal::kill(session.child_pid, None),
+            Err(nix::errno::Errno::ESRCH)
+        );
+        // Closing again is a no-op
+        session.close().await.unwrap();
+    }
+
     /// AC-5-3: Proxy env vars set in spawned process environment
     ///
     /// Per-session URLs include /s/{uuid}/ prefix. OPENAI_BASE_URL also includes
@@ -1,10 +1,19 @@
+pub mod activity;
 pub mod managed;
 pub mod observed;
+pub mod recording;
+pub mod resume;
+pub mod sandbox;
+pub mod terminal;
 pub mod types;
 
-pub use managed::ManagedSession;
+pub use managed::{LaunchOptions, ManagedSession};
 pub use observed::ObservedSession;
-pub use types::{Session, SessionError, SessionEvent, SessionId, SessionMode, SessionState};
+pub use sandbox::{Sandbox, SandboxConfig, Violation};
+pub use terminal::{SizePolicy, Terminal, TerminalEvent, Viewer, check_size};
+pub use types::{
+    ExitStatus, Session, SessionError, SessionEvent, SessionId, SessionMode, SessionState,
+};
 
 use std::collections::HashMap;
 use std::path::Path;
@@ -34,12 +43,42 @@ impl SessionManager {
         cli_type: &str,
         auto_approve: bool,
     ) -> Result<SessionId, SessionError> {
-        let session =
-            ManagedSession::spawn(working_dir, anthropic_base_url, cli_type, auto_approve)?;
+        self.spawn_managed_with(
+            working_dir,
+            anthropic_base_url,
+            cli_type,
+            auto_approve,
+            LaunchOptions::default(),
+        )
+    }
+
+    /// [`Self::spawn_managed`] with [`LaunchOptions`]. Spawning with the id
+    /// of a closed session replaces it.
+    pub fn spawn_managed_with(
+        &mut self,
+        working_dir: &Path,
+        anthropic_base_url: Option<&str>,
+        cli_type: &str,
+        auto_approve: bool,
+        options: LaunchOptions,
+    ) -> Result<SessionId, SessionError> {
+        let session = ManagedSession::spawn_with(
+            working_dir,
+            anthropic_base_url,
+            cli_type,
+            auto_approve,
+            options,
+        )?;
+        Ok(self.register_managed(session))
+    }
+
+    /// Register a session spawned with [`ManagedSession::spawn_blocking`].
+    /// Registering the id of a closed session replaces it.
+    pub fn register_managed(&mut self, session: Arc<ManagedSession>) -> SessionId {
         let id = session.id().clone();
         self.sessions.insert(id.clone(), session);
         info!(session = %id, mode = "managed", "session registered");
-        Ok(id)
+        id
     }
 
     /// Attach to an existing observed Claude Code session.
@@ -0,0 +1,588 @@
+//! Asciicast v2 recordings of managed-session terminals.
+//!
+//! Each launch of a managed session writes its PTY to
+//! `{dir}/{session_id}/{started_ms}.cast`: a header line, then one JSON array
+//! per event — `[seconds, "o", data]` for output, `"i"` for input written to
+//! the PTY (supervisor keystrokes, sent messages), `"r"` for resizes
+//! (`"COLSxROWS"`) and `"m"` for markers such as the process exit.
+//!
+//! Playback goes through [`Clipper`]: output before the seek point is run
+//! through a terminal emulator and its screen emitted as a single event at
+//! 0 s, so a player rebuilds the screen at once, and later events are
+//! re-based to the seek point.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+pub const EXTENSION: &str = "cast";
+pub const CONTENT_TYPE: &str = "application/x-asciicast";
+
+/// First line of an asciicast v2 file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// Epoch seconds.
+    #[serde(default)]
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Header {
+    pub fn new(width: u16, height: u16, title: Option<String>) -> Self {
+        Self {
+            version: 2,
+            width,
+            height,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+            title,
+            env: BTreeMap::from([("TERM".to_string(), "xterm-256color".to_string())]),
+        }
+    }
+}
+
+/// Appends events to one recording. Write errors stop the recording rather
+/// than the session.
+pub struct Recorder {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    out: Option<BufWriter<File>>,
+    start: Instant,
+    /// Trailing bytes of a UTF-8 sequence split across reads.
+    partial: Vec<u8>,
+}
+
+impl Recorder {
+    /// Create the file (and its directory) and write the header.
+    pub fn create(path: PathBuf, header: &Header) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer(&mut out, header)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(Self {
+            path,
+            inner: Mutex::new(Inner {
+                out: Some(out),
+                start: Instant::now(),
+                partial: Vec::new(),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Bytes read from the PTY.
+    pub fn output(&self, bytes: &[u8]) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.partial.extend_from_slice(bytes);
+        let text = take_utf8(&mut inner.partial);
+        if !text.is_empty() {
+            self.write(&mut inner, "o", &text);
+        }
+    }
+
+    /// Text written to the PTY.
+    pub fn input(&self, text: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.write(&mut inner, "i", text);
+    }
+
+    pub fn resize(&self, cols: u16, rows: u16) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.write(&mut inner, "r", &format!("{cols}x{rows}"));
+    }
+
+    pub fn marker(&self, label: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.write(&mut inner, "m", label);
+    }
+
+    fn write(&self, inner: &mut Inner, code: &str, data: &str) {
+        let t = (inner.start.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        let Some(out) = inner.out.as_mut() else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut *out, &(t, code, data))
+            .map_err(std::io::Error::from)
+            .and_then(|()| out.write_all(b"\n"))
+            .and_then(|()| out.flush());
+        if let Err(e) = result {
+            warn!(path = %self.path.display(), error = %e, "PTY recording stopped");
+            inner.out = None;
+        }
+    }
+}
+
+/// Decode as much of `buf` as possible, keeping an incomplete trailing
+/// UTF-8 sequence for the next read.
+fn take_utf8(buf: &mut Vec<u8>) -> String {
+    match std::str::from_utf8(buf) {
+        Ok(s) => {
+            let s = s.to_string();
+            buf.clear();
+            s
+        }
+        Err(e) if e.error_len().is_none() => {
+            let tail = buf.split_off(e.valid_up_to());
+            let s = String::from_utf8_lossy(buf).into_owned();
+            *buf = tail;
+            s
+        }
+        Err(_) => {
+            let s = String::from_utf8_lossy(buf).into_owned();
+            buf.clear();
+            s
+        }
+    }
+}
+
+/// Where recordings live: `NOAIDE_RECORDINGS_DIR`, else
+/// `/data/noaide/recordings`.
+pub fn recordings_dir() -> PathBuf {
+    std::env::var("NOAIDE_RECORDINGS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("recordings"))
+}
+
+/// Whether managed sessions are recorded (`NOAIDE_RECORD_PTY`, default on).
+pub fn enabled() -> bool {
+    std::env::var("NOAIDE_RECORD_PTY").map_or(true, |v| v != "false" && v != "0")
+}
+
+/// File for a launch starting now.
+pub fn new_recording_path(dir: &Path, session_id: Uuid) -> PathBuf {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    dir.join(session_id.to_string())
+        .join(format!("{now_ms}.{EXTENSION}"))
+}
+
+/// The file for a recording name as listed by [`list`]. `None` for names
+/// that are not recording file names.
+pub fn recording_file(dir: &Path, session_id: Uuid, name: &str) -> Option<PathBuf> {
+    let stem = name.strip_suffix(EXTENSION)?.strip_suffix('.')?;
+    if stem.is_empty() || !stem.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(dir.join(session_id.to_string()).join(name))
+}
+
+/// Delete every recording of a session.
+pub fn remove_session(dir: &Path, session_id: Uuid) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(dir.join(session_id.to_string())) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// One recording file on disk, as seen by retention.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredRecording {
+    pub session_id: Uuid,
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Last write, epoch milliseconds.
+    pub modified_ms: i64,
+}
+
+/// Every recording under `dir`, in no particular order.
+pub fn stored(dir: &Path) -> Vec<StoredRecording> {
+    let Ok(sessions) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for session in sessions.flatten() {
+        let Some(session_id) = session
+            .file_name()
+            .to_str()
+            .and_then(|n| Uuid::parse_str(n).ok())
+        else {
+            continue;
+        };
+        let Ok(files) = std::fs::read_dir(session.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let Some(name) = file.file_name().into_string().ok() else {
+                continue;
+            };
+            let Some(path) = recording_file(dir, session_id, &name) else {
+                continue;
+            };
+            let Ok(metadata) = file.metadata() else {
+                continue;
+            };
+            let modified_ms = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis() as i64);
+            out.push(StoredRecording {
+                session_id,
+                path,
+                bytes: metadata.len(),
+                modified_ms,
+            });
+        }
+    }
+    out
+}
+
+/// Summary of one recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingInfo {
+    pub name: String,
+    /// Epoch seconds.
+    pub started_at: i64,
+    pub width: u16,
+    pub height: u16,
+    /// Time of the last event.
+    pub duration_secs: f64,
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Recordings of a session, oldest first.
+pub fn list(dir: &Path, session_id: Uuid) -> Vec<RecordingInfo> {
+    let Ok(entries) = std::fs::read_dir(dir.join(session_id.to_string())) else {
+        return Vec::new();
+    };
+    let mut infos: Vec<RecordingInfo> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let path = recording_file(dir, session_id, &name)?;
+            info(&path, name)
+        })
+        .collect();
+    infos.sort_by(|a, b| (a.started_at, &a.name).cmp(&(b.started_at, &b.name)));
+    infos
+}
+
+fn info(path: &Path, name: String) -> Option<RecordingInfo> {
+    let mut file = File::open(path).ok()?;
+    let bytes = file.metadata().ok()?.len();
+    let mut first = String::new();
+    BufReader::new(&mut file).read_line(&mut first).ok()?;
+    let header: Header = serde_json::from_str(&first).ok()?;
+
+    // The last complete event sits in the tail of the file.
+    let tail_len = bytes.min(64 * 1024);
+    file.seek(SeekFrom::End(-(tail_len as i64))).ok()?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    file.read_to_end(&mut tail).ok()?;
+    let duration_secs = String::from_utf8_lossy(&tail)
+        .lines()
+        .rev()
+        .find_map(|line| {
+            serde_json::from_str::<(f64, String, String)>(line)
+                .ok()
+                .map(|e| e.0)
+        })
+        .unwrap_or(0.0);
+
+    Some(RecordingInfo {
+        name,
+        started_at: header.timestamp,
+        width: header.width,
+        height: header.height,
+        duration_secs,
+        bytes,
+        title: header.title,
+    })
+}
+
+/// Part of a recording to play back, in seconds from its start.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Clip {
+    pub from: f64,
+    pub to: Option<f64>,
+}
+
+/// Rewrites a recording line by line for playback of a [`Clip`].
+pub struct Clipper {
+    clip: Clip,
+    redact: bool,
+    header_seen: bool,
+    /// The terminal as output before `clip.from` left it, replayed at 0 s.
+    screen: Option<vt100::Parser>,
+    size: Option<String>,
+    flushed: bool,
+    done: bool,
+}
+
+impl Clipper {
+    /// `redact` applies the proxy's secret redaction to output and input.
+    pub fn new(clip: Clip, redact: bool) -> Self {
+        Self {
+            clip,
+            redact,
+            header_seen: false,
+            screen: None,
+            size: None,
+            flushed: false,
+            done: false,
+        }
+    }
+
+    /// Lines to emit (newline-terminated) for one recorded line.
+    pub fn push(&mut self, line: &str) -> String {
+        if self.done || line.trim().is_empty() {
+            return String::new();
+        }
+        if !self.header_seen {
+            self.header_seen = true;
+            if self.clip.from > 0.0 {
+                let (width, height) =
+                    serde_json::from_str::<Header>(line).map_or((80, 24), |h| (h.width, h.height));
+                self.screen = Some(vt100::Parser::new(height, width, 0));
+            }
+            return format!("{}\n", line.trim_end());
+        }
+        let Ok((t, code, data)) = serde_json::from_str::<(f64, String, String)>(line) else {
+            return String::new();
+        };
+        if t < self.clip.from {
+            if let Some(screen) = self.screen.as_mut() {
+                match code.as_str() {
+                    "o" => screen.process(data.as_bytes()),
+                    "r" => {
+                        if let Some((cols, rows)) = parse_size(&data) {
+                            screen.screen_mut().set_size(rows, cols);
+                        }
+                        self.size = Some(data);
+                    }
+                    _ => {}
+                }
+            }
+            return String::new();
+        }
+        let mut out = self.flush();
+        if self.clip.to.is_some_and(|to| t > to) {
+            self.done = true;
+            return out;
+        }
+        let data = if self.redact && (code == "o" || code == "i") {
+            crate::proxy::mitm::redact(&data)
+        } else {
+            data
+        };
+        let t = ((t - self.clip.from) * 1e6).round() / 1e6;
+        out.push_str(&event_line(t, &code, &data));
+        out
+    }
+
+    /// Lines still owed at the end of the recording.
+    pub fn finish(&mut self) -> String {
+        self.flush()
+    }
+
+    /// Whether the clip's end was reached; later lines are ignored.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn flush(&mut self) -> String {
+        if self.flushed {
+            return String::new();
+        }
+        self.flushed = true;
+        let mut out = String::new();
+        if let Some(size) = self.size.take() {
+            out.push_str(&event_line(0.0, "r", &size));
+        }
+        if let Some(screen) = self.screen.take() {
+            let state = String::from_utf8_lossy(&screen.screen().state_formatted()).into_owned();
+            let state = if self.redact {
+                crate::proxy::mitm::redact(&state)
+            } else {
+                state
+            };
+            out.push_str(&event_line(0.0, "o", &state));
+        }
+        out
+    }
+}
+
+/// `"COLSxROWS"` as recorded for a resize.
+fn parse_size(data: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = data.split_once('x')?;
+    Some((cols.parse().ok()?, rows.parse().ok()?))
+}
+
+fn event_line(t: f64, code: &str, data: &str) -> String {
+    let mut line = serde_json::to_string(&(t, code, data)).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+/// Apply `f` to the data of every output and input event.
+pub fn map_data(cast: &str, f: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(cast.len());
+    for (i, line) in cast.lines().enumerate() {
+        match serde_json::from_str::<(f64, String, String)>(line) {
+            Ok((t, code, data)) if i > 0 && (code == "o" || code == "i") => {
+                out.push_str(&event_line(t, &code, &f(&data)));
+            }
+            _ => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// A whole recording clipped and redacted, e.g. for an export.
+pub fn read_clipped(path: &Path, clip: Clip, redact: bool) -> std::io::Result<String> {
+    let mut clipper = Clipper::new(clip, redact);
+    let mut out = String::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        out.push_str(&clipper.push(&line?));
+        if clipper.is_done() {
+            break;
+        }
+    }
+    out.push_str(&clipper.finish());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(cast: &str) -> Vec<(f64, String, String)> {
+        cast.lines()
+            .skip(1)
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn records_output_input_resize_and_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let sid = Uuid::new_v4();
+        let path = new_recording_path(dir.path(), sid);
+        let rec =
+            Recorder::create(path.clone(), &Header::new(80, 24, Some("claude".into()))).unwrap();
+        // "é" split across two reads is written once, whole.
+        rec.output(b"caf\xc3");
+        rec.output(b"\xa9 ready\r\n");
+        rec.input("hello\r");
+        rec.resize(120, 40);
+        rec.marker("exit 0");
+
+        let cast = std::fs::read_to_string(&path).unwrap();
+        let header: Header = serde_json::from_str(cast.lines().next().unwrap()).unwrap();
+        assert_eq!((header.version, header.width, header.height), (2, 80, 24));
+        let codes: Vec<(String, String)> =
+            events(&cast).into_iter().map(|(_, c, d)| (c, d)).collect();
+        assert_eq!(
+            codes,
+            [
+                ("o".into(), "caf".into()),
+                ("o".into(), "é ready\r\n".into()),
+                ("i".into(), "hello\r".into()),
+                ("r".into(), "120x40".into()),
+                ("m".into(), "exit 0".into()),
+            ]
+        );
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let listed = list(dir.path(), sid);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, name);
+        assert_eq!(listed[0].title.as_deref(), Some("claude"));
+        assert_eq!(recording_file(dir.path(), sid, name), Some(path.clone()));
+        assert_eq!(recording_file(dir.path(), sid, "../x.cast"), None);
+        assert_eq!(recording_file(dir.path(), sid, "1.txt"), None);
+
+        let stored = stored(dir.path());
+        assert_eq!(stored.len(), 1);
+        assert_eq!((stored[0].session_id, &stored[0].path), (sid, &path));
+        remove_session(dir.path(), sid).unwrap();
+        assert!(list(dir.path(), sid).is_empty());
+        remove_session(dir.path(), sid).unwrap();
+    }
+
+    #[test]
+    fn seeking_rebuilds_the_screen_and_rebases_the_rest() {
+        let cast = [
+            r#"{"version":2,"width":80,"height":24,"timestamp":1}"#,
+            r#"[0.2,"o","old output\u001b[2J\u001b[H"]"#,
+            r#"[0.5,"o","$ "]"#,
+            r#"[1.0,"r","100x30"]"#,
+            r#"[1.5,"i","ls\r"]"#,
+            r#"[2.0,"o","ls\r\n"]"#,
+            r#"[3.0,"o","sk-ant-REDACTED\r\n"]"#,
+            r#"[5.0,"o","done"]"#,
+        ];
+        let mut clipper = Clipper::new(
+            Clip {
+                from: 1.8,
+                to: Some(4.0),
+            },
+            true,
+        );
+        let mut out = String::new();
+        for line in cast {
+            out.push_str(&clipper.push(line));
+        }
+        out.push_str(&clipper.finish());
+        assert!(clipper.is_done());
+
+        let got = events(&out);
+        assert_eq!(got[0], (0.0, "r".into(), "100x30".into()));
+        // Input before the seek point is dropped, output replaced by the
+        // screen it produced.
+        assert_eq!(got[1].0, 0.0);
+        let mut replay = vt100::Parser::new(30, 100, 0);
+        replay.process(got[1].2.as_bytes());
+        assert!(!got[1].2.contains("old output"), "cleared output is gone");
+        assert_eq!(replay.screen().contents(), "$ ");
+        assert_eq!(replay.screen().cursor_position(), (0, 2));
+        assert_eq!(got[2], (0.2, "o".into(), "ls\r\n".into()));
+        assert_eq!(got[3].0, 1.2);
+        assert!(!got[3].2.contains("abcdefghij"), "{}", got[3].2);
+        assert_eq!(got.len(), 4);
+    }
+
+    #[test]
+    fn whole_recording_passes_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.cast");
+        let rec = Recorder::create(path.clone(), &Header::new(80, 24, None)).unwrap();
+        rec.output(b"hi");
+        let original = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            read_clipped(&path, Clip::default(), false).unwrap(),
+            original
+        );
+    }
+}
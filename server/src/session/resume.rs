@@ -0,0 +1,280 @@
+//! Launch records for resuming managed sessions after a server restart.
+//!
+//! A managed CLI dies with the server — its PTY master belongs to the server
+//! process — but the conversation survives in the CLI's own transcript. Every
+//! managed launch is recorded with what it takes to start it again (CLI,
+//! working directory, proxy profile, extra environment). Once the watcher
+//! links the CLI's transcript to the session, the record names it too, so a
+//! respawn can continue the conversation (`claude --resume <id>`,
+//! `codex resume <id>`, `gemini --resume <id>`) under the same session id.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::managed::LaunchOptions;
+use super::terminal::SizePolicy;
+use crate::git::Worktree;
+
+/// Everything needed to start a managed session again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedLaunch {
+    pub session_id: Uuid,
+    /// Source name as accepted by the managed-session API ("claude", ...).
+    pub cli_type: String,
+    pub working_dir: PathBuf,
+    #[serde(default)]
+    pub auto_approve: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Extra environment passed to the CLI on top of the generated one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub size_policy: SizePolicy,
+    /// The session's own git worktree; `working_dir` lies inside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<Worktree>,
+    /// Id of the CLI's own session (its transcript), once linked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cli_session_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<PathBuf>,
+    /// Epoch seconds of the first launch.
+    pub started_at: i64,
+    /// Epoch seconds of the last respawn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resumed_at: Option<i64>,
+    /// Epoch seconds at which the CLI exited on its own; unset while it
+    /// runs, so only sessions the server stopped are resumed automatically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exited_at: Option<i64>,
+}
+
+impl ManagedLaunch {
+    /// Arguments that continue the recorded conversation. `None` before the
+    /// transcript is linked or if the CLI cannot resume it. May read the
+    /// transcript.
+    pub fn resume_args(&self) -> Option<Vec<String>> {
+        let transcript = self.transcript.as_deref()?;
+        crate::parser::registry::by_name(&self.cli_type)?.resume_args(transcript)
+    }
+
+    /// Spawn options for a respawn under the same session id.
+    pub fn launch_options(&self, resume_args: Option<Vec<String>>) -> LaunchOptions {
+        LaunchOptions {
+            session_id: Some(self.session_id),
+            extra_args: resume_args.unwrap_or_default(),
+            env: self
+                .env
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            size_policy: self.size_policy,
+            ..Default::default()
+        }
+    }
+}
+
+/// Launch records, written through to one JSON file.
+#[derive(Clone)]
+pub struct LaunchStore {
+    path: Arc<PathBuf>,
+    launches: Arc<RwLock<HashMap<Uuid, ManagedLaunch>>>,
+}
+
+impl LaunchStore {
+    /// Read the records at `path`. A missing or unreadable file starts empty.
+    pub fn load(path: PathBuf) -> Self {
+        let launches = match std::fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str::<Vec<ManagedLaunch>>(&data) {
+                Ok(list) => list.into_iter().map(|l| (l.session_id, l)).collect(),
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "ignoring unreadable launch records");
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path: Arc::new(path),
+            launches: Arc::new(RwLock::new(launches)),
+        }
+    }
+
+    /// Add or replace a record.
+    pub async fn record(&self, launch: ManagedLaunch) {
+        let mut launches = self.launches.write().await;
+        launches.insert(launch.session_id, launch);
+        self.save(&launches).await;
+    }
+
+    /// Name the transcript a managed session writes to. Returns `false` if
+    /// there is no record or it already names this transcript.
+    pub async fn link(&self, session_id: Uuid, cli_session_id: Uuid, transcript: &Path) -> bool {
+        let mut launches = self.launches.write().await;
+        let Some(launch) = launches.get_mut(&session_id) else {
+            return false;
+        };
+        if launch.cli_session_id == Some(cli_session_id)
+            && launch.transcript.as_deref() == Some(transcript)
+        {
+            return false;
+        }
+        launch.cli_session_id = Some(cli_session_id);
+        launch.transcript = Some(transcript.to_path_buf());
+        self.save(&launches).await;
+        true
+    }
+
+    /// Note a respawn at `at` (epoch seconds).
+    pub async fn mark_resumed(&self, session_id: Uuid, at: i64) {
+        let mut launches = self.launches.write().await;
+        if let Some(launch) = launches.get_mut(&session_id) {
+            launch.resumed_at = Some(at);
+            launch.exited_at = None;
+            self.save(&launches).await;
+        }
+    }
+
+    /// Note that the CLI exited at `at` (epoch seconds) while the server
+    /// kept running. The record stays for a manual resume.
+    pub async fn mark_exited(&self, session_id: Uuid, at: i64) {
+        let mut launches = self.launches.write().await;
+        if let Some(launch) = launches.get_mut(&session_id)
+            && launch.exited_at.is_none()
+        {
+            launch.exited_at = Some(at);
+            self.save(&launches).await;
+        }
+    }
+
+    /// Forget a session, e.g. one closed on purpose.
+    pub async fn remove(&self, session_id: Uuid) -> Option<ManagedLaunch> {
+        let mut launches = self.launches.write().await;
+        let removed = launches.remove(&session_id);
+        if removed.is_some() {
+            self.save(&launches).await;
+        }
+        removed
+    }
+
+    pub async fn get(&self, session_id: Uuid) -> Option<ManagedLaunch> {
+        self.launches.read().await.get(&session_id).cloned()
+    }
+
+    /// All records, oldest launch first.
+    pub async fn list(&self) -> Vec<ManagedLaunch> {
+        let mut list: Vec<ManagedLaunch> = self.launches.read().await.values().cloned().collect();
+        list.sort_by_key(|l| (l.started_at, l.session_id));
+        list
+    }
+
+    async fn save(&self, launches: &HashMap<Uuid, ManagedLaunch>) {
+        let mut list: Vec<&ManagedLaunch> = launches.values().collect();
+        list.sort_by_key(|l| (l.started_at, l.session_id));
+        let result = async {
+            if let Some(dir) = self.path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let json = serde_json::to_string_pretty(&list).map_err(std::io::Error::other)?;
+            let tmp = self.path.with_extension("tmp");
+            tokio::fs::write(&tmp, json + "\n").await?;
+            tokio::fs::rename(&tmp, &*self.path).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!(path = %self.path.display(), error = %e, "failed to persist launch records");
+        }
+    }
+}
+
+/// Where launch records live: `NOAIDE_LAUNCHES_PATH`, else
+/// `/data/noaide/managed-launches.json`.
+pub fn launches_path() -> PathBuf {
+    std::env::var("NOAIDE_LAUNCHES_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::proxy::persist::config_dir().join("managed-launches.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launch(session_id: Uuid, started_at: i64) -> ManagedLaunch {
+        ManagedLaunch {
+            session_id,
+            cli_type: "claude".into(),
+            working_dir: "/work/app".into(),
+            auto_approve: false,
+            profile: Some("review".into()),
+            env: BTreeMap::from([("FOO".into(), "bar".into())]),
+            size_policy: SizePolicy::Owner,
+            worktree: None,
+            cli_session_id: None,
+            transcript: None,
+            started_at,
+            resumed_at: None,
+            exited_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn records_survive_a_reload_and_links_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("launches.json");
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let cli = Uuid::parse_str("a1b2c3d4-e5f6-7890-abcd-ef1234567890").unwrap();
+        let transcript = PathBuf::from(format!("/home/u/.claude/projects/-work-app/{cli}.jsonl"));
+
+        let store = LaunchStore::load(path.clone());
+        store.record(launch(second, 20)).await;
+        store.record(launch(first, 10)).await;
+        assert!(store.link(first, cli, &transcript).await);
+        assert!(!store.link(first, cli, &transcript).await);
+        assert!(!store.link(Uuid::new_v4(), cli, &transcript).await);
+        store.mark_exited(first, 25).await;
+        assert_eq!(store.get(first).await.unwrap().exited_at, Some(25));
+        store.mark_resumed(first, 30).await;
+        assert!(store.remove(second).await.is_some());
+
+        let reloaded = LaunchStore::load(path);
+        let list = reloaded.list().await;
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].cli_session_id, Some(cli));
+        assert_eq!(list[0].resumed_at, Some(30));
+        assert_eq!(list[0].exited_at, None, "a respawn runs again");
+        assert_eq!(list[0].env.get("FOO").map(String::as_str), Some("bar"));
+        assert_eq!(
+            list[0].resume_args(),
+            Some(vec!["--resume".to_string(), cli.to_string()])
+        );
+    }
+
+    #[test]
+    fn unlinked_launch_respawns_fresh_under_the_same_id() {
+        let id = Uuid::new_v4();
+        let record = launch(id, 1);
+        assert_eq!(record.resume_args(), None);
+        let options = record.launch_options(record.resume_args());
+        assert_eq!(options.session_id, Some(id));
+        assert!(options.extra_args.is_empty());
+        assert_eq!(options.env, [("FOO".to_string(), "bar".to_string())]);
+        assert_eq!(options.size_policy, SizePolicy::Owner);
+    }
+
+    #[test]
+    fn unreadable_file_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("launches.json");
+        std::fs::write(&path, "not json").unwrap();
+        let store = LaunchStore::load(path);
+        assert!(store.launches.try_read().unwrap().is_empty());
+    }
+}
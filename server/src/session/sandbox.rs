@@ -0,0 +1,1674 @@
+//! Optional sandbox for managed sessions.
+//!
+//! A sandboxed CLI runs in its own user, mount and network namespaces. The
+//! project directory stays writable, configured paths are mounted read-only
+//! or hidden (by default also noaide's own data directory and `/run`), `/tmp`
+//! is a private tmpfs, and the network namespace has nothing but loopback,
+//! where a listener on the proxy port forwards to the noaide proxy. Whatever
+//! the CLI does with `HTTPS_PROXY`, the proxy is its only way out. The child
+//! also runs with `no_new_privs` and a seccomp filter: denied syscalls,
+//! connects to anything but loopback and to Unix sockets outside the project
+//! are answered by the server, which reports them as [`Violation`]s.
+//!
+//! Setup happens in the forked child before `exec` ([`Plan::enter`]); it
+//! hands the proxy listeners and the seccomp notification fd back over a
+//! socketpair, and [`Sandbox::start`] serves them. The configuration comes
+//! from the session's proxy profile.
+
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nix::libc;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::bus::{EventEnvelope, EventSource};
+
+/// How much network a sandboxed session gets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxNetwork {
+    /// Loopback only, with the proxy port forwarded to the noaide proxy.
+    #[default]
+    Proxy,
+    /// Loopback only.
+    None,
+    /// The host's network (no network namespace).
+    Host,
+}
+
+/// Sandbox settings of a proxy profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Mounted read-only. `~` is the home directory; missing paths are
+    /// skipped.
+    pub read_only: Vec<String>,
+    /// Kept writable inside read-only or hidden paths, besides the project
+    /// directory.
+    pub writable: Vec<String>,
+    /// Covered by an empty tmpfs (directories) or `/dev/null` (files).
+    pub hidden: Vec<String>,
+    /// Give the session an empty `/tmp` of its own.
+    pub private_tmp: bool,
+    pub network: SandboxNetwork,
+    /// Syscalls refused with `EPERM` and reported.
+    pub deny_syscalls: Vec<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        let strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        Self {
+            read_only: strings(&[
+                "/usr", "/etc", "/opt", "/bin", "/sbin", "/lib", "/lib64", "/var", "~",
+            ]),
+            writable: strings(&[
+                "~/.claude",
+                "~/.claude.json",
+                "~/.codex",
+                "~/.gemini",
+                "~/.aider",
+                "~/.config/opencode",
+                "~/.local/share/opencode",
+                "~/.cache",
+                "~/.npm",
+            ]),
+            hidden: strings(&[
+                "~/.ssh",
+                "~/.gnupg",
+                "~/.aws",
+                "~/.kube",
+                "~/.docker",
+                "~/.config/gcloud",
+                "~/.netrc",
+                // Daemon sockets (docker, ssh-agent, dbus)
+                "/run",
+                "/var/run",
+            ])
+            .into_iter()
+            // Profiles, launch records and keys: the sandbox's own policy
+            .chain([crate::proxy::persist::config_dir().display().to_string()])
+            .collect(),
+            private_tmp: true,
+            network: SandboxNetwork::Proxy,
+            deny_syscalls: DEFAULT_DENIED.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// Check paths and syscall names.
+    pub fn validate(&self) -> Result<(), String> {
+        for path in self
+            .read_only
+            .iter()
+            .chain(&self.writable)
+            .chain(&self.hidden)
+        {
+            if !(path.starts_with('/') || path == "~" || path.starts_with("~/")) {
+                return Err(format!(
+                    "sandbox path must be absolute or start with ~/: {path}"
+                ));
+            }
+        }
+        if self.writable.len() >= MAX_WRITABLE {
+            return Err(format!(
+                "sandbox allows at most {} writable paths",
+                MAX_WRITABLE - 1
+            ));
+        }
+        if let Some(root) = self
+            .read_only
+            .iter()
+            .chain(&self.hidden)
+            .find(|p| *p == "/")
+        {
+            return Err(format!(
+                "sandbox cannot cover {root}; list its directories instead"
+            ));
+        }
+        for name in &self.deny_syscalls {
+            if syscall_number(name).is_none() {
+                return Err(format!("unknown syscall: {name}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Something a sandboxed session tried and was refused.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// Syscall name, or the destination of a refused connect.
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    Syscall,
+    Network,
+}
+
+impl Violation {
+    /// The `session/status` event for this violation.
+    pub fn envelope(&self, session_id: Uuid, at_ms: i64) -> EventEnvelope {
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "type": "sandbox_violation",
+            "session_id": session_id.to_string(),
+            "kind": self.kind,
+            "detail": self.detail,
+            "at_ms": at_ms,
+        }))
+        .unwrap_or_default();
+        EventEnvelope::new(EventSource::Pty, 0, 0, Some(session_id), payload)
+    }
+}
+
+// ── Seccomp ─────────────────────────────────────────────────────────────────
+
+/// Denied unless a profile says otherwise: tracing other processes, kernel
+/// modules and keys, and other ways around the namespaces.
+const DEFAULT_DENIED: &[&str] = &[
+    "ptrace",
+    "process_vm_readv",
+    "process_vm_writev",
+    "kexec_load",
+    "init_module",
+    "finit_module",
+    "delete_module",
+    "bpf",
+    "perf_event_open",
+    "keyctl",
+    "add_key",
+    "request_key",
+    "userfaultfd",
+    "open_by_handle_at",
+    "setns",
+    "reboot",
+    "swapon",
+    "swapoff",
+    "acct",
+];
+
+/// Syscalls a profile may deny.
+const SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("ptrace", libc::SYS_ptrace),
+    ("process_vm_readv", libc::SYS_process_vm_readv),
+    ("process_vm_writev", libc::SYS_process_vm_writev),
+    ("kexec_load", libc::SYS_kexec_load),
+    ("init_module", libc::SYS_init_module),
+    ("finit_module", libc::SYS_finit_module),
+    ("delete_module", libc::SYS_delete_module),
+    ("bpf", libc::SYS_bpf),
+    ("perf_event_open", libc::SYS_perf_event_open),
+    ("keyctl", libc::SYS_keyctl),
+    ("add_key", libc::SYS_add_key),
+    ("request_key", libc::SYS_request_key),
+    ("userfaultfd", libc::SYS_userfaultfd),
+    ("open_by_handle_at", libc::SYS_open_by_handle_at),
+    ("setns", libc::SYS_setns),
+    ("unshare", libc::SYS_unshare),
+    ("mount", libc::SYS_mount),
+    ("umount2", libc::SYS_umount2),
+    ("pivot_root", libc::SYS_pivot_root),
+    ("chroot", libc::SYS_chroot),
+    ("reboot", libc::SYS_reboot),
+    ("swapon", libc::SYS_swapon),
+    ("swapoff", libc::SYS_swapoff),
+    ("acct", libc::SYS_acct),
+    ("settimeofday", libc::SYS_settimeofday),
+    ("clock_settime", libc::SYS_clock_settime),
+];
+
+fn syscall_number(name: &str) -> Option<libc::c_long> {
+    SYSCALLS.iter().find(|(n, _)| *n == name).map(|(_, nr)| *nr)
+}
+
+fn syscall_name(nr: libc::c_long) -> Option<&'static str> {
+    SYSCALLS
+        .iter()
+        .find(|(_, n)| *n == nr)
+        .map(|(name, _)| *name)
+}
+
+/// `AUDIT_ARCH_*` of the syscall ABI the filter is written for.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Syscall numbers from here on belong to the x32 ABI.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+#[cfg(not(target_arch = "x86_64"))]
+const X32_SYSCALL_BIT: Option<u32> = None;
+
+// From linux/seccomp.h (`_IOWR('!', 0..2, ...)`); not in libc.
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+const SECCOMP_IOCTL_NOTIF_ID_VALID: libc::c_ulong = 0x4008_2102;
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// A filter that hands `notify` to the supervisor and allows everything
+/// else. Other syscall ABIs are refused outright, so they cannot be used
+/// to get around the list.
+fn seccomp_filter(arch: u32, notify: &[libc::c_long]) -> Vec<libc::sock_filter> {
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    let refuse = bpf_stmt(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+    );
+    let mut prog = vec![
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH),
+        bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+        refuse,
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR),
+    ];
+    if let Some(bit) = X32_SYSCALL_BIT {
+        prog.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            bit,
+            0,
+            1,
+        ));
+        prog.push(refuse);
+    }
+    for &nr in notify {
+        prog.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            nr as u32,
+            0,
+            1,
+        ));
+        prog.push(bpf_stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_USER_NOTIF,
+        ));
+    }
+    prog.push(bpf_stmt(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ALLOW,
+    ));
+    prog
+}
+
+fn address_family(addr: &[u8]) -> Option<i32> {
+    Some(i32::from(u16::from_ne_bytes([
+        *addr.first()?,
+        *addr.get(1)?,
+    ])))
+}
+
+/// Where a connect goes, or `None` if the sandbox allows it: loopback,
+/// netlink, `AF_UNSPEC` to disconnect, anything with the host's network, and
+/// the Unix socket paths `unix_allowed` accepts. Abstract Unix sockets are
+/// private to the network namespace, so only reachable with the host's.
+fn connect_destination(
+    addr: &[u8],
+    host_network: bool,
+    unix_allowed: impl Fn(&Path) -> bool,
+) -> Option<String> {
+    let family = address_family(addr)?;
+    let port = || addr.get(2..4).map(|p| u16::from_be_bytes([p[0], p[1]]));
+    match family {
+        libc::AF_UNSPEC | libc::AF_NETLINK => None,
+        libc::AF_UNIX => {
+            let sun_path = addr.get(2..).unwrap_or_default();
+            match sun_path.first() {
+                // Unnamed, or autobind
+                None => None,
+                Some(0) => host_network.then(|| {
+                    let name = &sun_path[1..];
+                    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                    format!("unix:@{}", String::from_utf8_lossy(&name[..end]))
+                }),
+                Some(_) => {
+                    let end = sun_path
+                        .iter()
+                        .position(|&b| b == 0)
+                        .unwrap_or(sun_path.len());
+                    let path = Path::new(std::ffi::OsStr::from_bytes(&sun_path[..end]));
+                    (!unix_allowed(path)).then(|| format!("unix:{}", path.display()))
+                }
+            }
+        }
+        _ if host_network => None,
+        libc::AF_INET => {
+            let ip: [u8; 4] = addr.get(4..8)?.try_into().ok()?;
+            let ip = Ipv4Addr::from(ip);
+            (!ip.is_loopback()).then(|| format!("{ip}:{}", port().unwrap_or(0)))
+        }
+        libc::AF_INET6 => {
+            let ip: [u8; 16] = addr.get(8..24)?.try_into().ok()?;
+            let ip = Ipv6Addr::from(ip);
+            let loopback =
+                ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|v4| v4.is_loopback());
+            (!loopback).then(|| format!("[{ip}]:{}", port().unwrap_or(0)))
+        }
+        other => Some(format!("address family {other}")),
+    }
+}
+
+// ── Child side ──────────────────────────────────────────────────────────────
+
+enum MountOp {
+    ReadOnly(CString),
+    HideDir(CString),
+    HideFile(CString),
+    PrivateTmp,
+}
+
+/// Writable paths, the project directory included. Bounds the descriptors
+/// the child keeps open while it mounts.
+const MAX_WRITABLE: usize = 64;
+
+/// Everything the child needs, prepared before `fork` so that setup after
+/// it does not allocate.
+pub(crate) struct Plan {
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    project: PathBuf,
+    mounts: Vec<MountOp>,
+    /// Put back on top of `mounts` from descriptors opened before them, so
+    /// they survive a hidden or private parent: `(path, is_dir)`, the
+    /// project directory first.
+    writable: Vec<(CString, bool)>,
+    /// Hidden paths inside writable ones, covered again afterwards.
+    rehide: Vec<MountOp>,
+    network: SandboxNetwork,
+    proxy_port: Option<u16>,
+    filter: Vec<libc::sock_filter>,
+}
+
+/// Why setup failed: the step and its errno.
+struct Failure(&'static str, i32);
+
+fn check(ret: libc::c_long, step: &'static str) -> Result<libc::c_long, Failure> {
+    if ret < 0 {
+        Err(Failure(
+            step,
+            io::Error::last_os_error().raw_os_error().unwrap_or(0),
+        ))
+    } else {
+        Ok(ret)
+    }
+}
+
+/// What the child hands to the parent.
+const HAS_V4: u8 = 1;
+const HAS_V6: u8 = 2;
+const HAS_NOTIFY: u8 = 4;
+
+impl Plan {
+    /// Resolve `config` for a session in `working_dir`. `proxy_port` is
+    /// forwarded into the network namespace.
+    pub(crate) fn new(
+        config: &SandboxConfig,
+        working_dir: &Path,
+        proxy_port: Option<u16>,
+    ) -> Result<Self, String> {
+        config.validate()?;
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let resolve = |path: &str| -> Option<PathBuf> {
+            let path = match path.strip_prefix('~') {
+                Some(rest) => home.as_ref()?.join(rest.trim_start_matches('/')),
+                None => PathBuf::from(path),
+            };
+            path.canonicalize().ok()
+        };
+        let c_path = |path: &Path| {
+            CString::new(path.as_os_str().as_encoded_bytes())
+                .map_err(|_| format!("invalid sandbox path: {}", path.display()))
+        };
+
+        let mut mounts = Vec::new();
+        for path in config.read_only.iter().filter_map(|p| resolve(p)) {
+            mounts.push(MountOp::ReadOnly(c_path(&path)?));
+        }
+        if config.private_tmp {
+            mounts.push(MountOp::PrivateTmp);
+        }
+        let project = working_dir
+            .canonicalize()
+            .map_err(|e| format!("working_dir: {e}"))?;
+        let writable: Vec<PathBuf> = std::iter::once(project.clone())
+            .chain(config.writable.iter().filter_map(|p| resolve(p)))
+            .collect();
+        let hide = |path: &Path| {
+            Ok::<_, String>(if path.is_dir() {
+                MountOp::HideDir(c_path(path)?)
+            } else {
+                MountOp::HideFile(c_path(path)?)
+            })
+        };
+        let mut rehide = Vec::new();
+        for path in config.hidden.iter().filter_map(|p| resolve(p)) {
+            mounts.push(hide(&path)?);
+            if writable.iter().any(|w| path != *w && path.starts_with(w)) {
+                rehide.push(hide(&path)?);
+            }
+        }
+        let writable = writable
+            .iter()
+            .map(|path| Ok((c_path(path)?, path.is_dir())))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Connects are always inspected: Unix sockets reach past every
+        // namespace but the mount namespace.
+        let mut notify: Vec<libc::c_long> = config
+            .deny_syscalls
+            .iter()
+            .filter_map(|name| syscall_number(name))
+            .collect();
+        notify.push(libc::SYS_connect);
+        let filter = match (notify.is_empty(), AUDIT_ARCH) {
+            (true, _) => Vec::new(),
+            (false, Some(arch)) => seccomp_filter(arch, &notify),
+            (false, None) => {
+                return Err("the sandbox's syscall filter is not supported on this CPU".to_string());
+            }
+        };
+
+        // SAFETY: getuid and getgid cannot fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(Self {
+            uid_map: format!("{uid} {uid} 1").into_bytes(),
+            gid_map: format!("{gid} {gid} 1").into_bytes(),
+            project,
+            mounts,
+            writable,
+            rehide,
+            network: config.network,
+            proxy_port: proxy_port.filter(|_| config.network == SandboxNetwork::Proxy),
+            filter,
+        })
+    }
+
+    /// The project directory, as the sandbox mounts it.
+    pub(crate) fn project(&self) -> &Path {
+        &self.project
+    }
+
+    /// In the forked child: enter the sandbox and report to the parent on
+    /// `channel`. Exits the child if setup fails.
+    pub(crate) fn enter(&self, channel: RawFd) {
+        // SAFETY: single-threaded child between fork and exec; only raw
+        // syscalls on memory prepared before the fork.
+        unsafe {
+            match self.setup() {
+                Ok((fds, count, mask)) => {
+                    send_fds(channel, &[b'o', mask], &fds[..count]);
+                    for fd in &fds[..count] {
+                        libc::close(*fd);
+                    }
+                    libc::close(channel);
+                }
+                Err(Failure(step, errno)) => {
+                    let errno = errno.to_ne_bytes();
+                    let parts = [&[b'e'][..], &errno, step.as_bytes()];
+                    let iov = parts.map(|p| libc::iovec {
+                        iov_base: p.as_ptr() as *mut libc::c_void,
+                        iov_len: p.len(),
+                    });
+                    let mut msg: libc::msghdr = std::mem::zeroed();
+                    msg.msg_iov = iov.as_ptr() as *mut libc::iovec;
+                    msg.msg_iovlen = iov.len() as _;
+                    libc::sendmsg(channel, &msg, 0);
+                    libc::_exit(126);
+                }
+            }
+        }
+    }
+
+    unsafe fn setup(&self) -> Result<([RawFd; 3], usize, u8), Failure> {
+        unsafe {
+            let mut flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS;
+            if self.network != SandboxNetwork::Host {
+                flags |= libc::CLONE_NEWNET;
+            }
+            check(libc::unshare(flags) as _, "unshare")?;
+            // Older kernels have no setgroups file
+            let _ = write_file(c"/proc/self/setgroups", b"deny");
+            write_file(c"/proc/self/uid_map", &self.uid_map)?;
+            write_file(c"/proc/self/gid_map", &self.gid_map)?;
+
+            check(
+                libc::mount(
+                    std::ptr::null(),
+                    c"/".as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                ) as _,
+                "make mounts private",
+            )?;
+            // Descriptors first: mounts below may cover the paths
+            let mut keep = [-1; MAX_WRITABLE];
+            for ((path, _), fd) in self.writable.iter().zip(keep.iter_mut()) {
+                *fd = libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC);
+            }
+            check(keep[0] as _, "open project")?;
+            mount_all(&self.mounts)?;
+            for ((path, is_dir), fd) in self.writable.iter().zip(keep) {
+                if fd < 0 {
+                    continue;
+                }
+                if libc::access(path.as_ptr(), libc::F_OK) != 0 {
+                    make_path(path, *is_dir);
+                }
+                let source = fd_path(fd);
+                let bound = check(
+                    libc::mount(
+                        source.as_ptr().cast(),
+                        path.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        std::ptr::null(),
+                    ) as _,
+                    "bind writable path",
+                );
+                libc::close(fd);
+                bound?;
+                // A path that is read-only on the host stays so
+                let _ = remount(path, locked_flags(path), "writable remount");
+            }
+            mount_all(&self.rehide)?;
+
+            let mut fds = [-1; 3];
+            let mut count = 0;
+            let mut mask = 0;
+            if self.network != SandboxNetwork::Host {
+                loopback_up()?;
+            }
+            if let Some(port) = self.proxy_port {
+                fds[count] = listen(libc::AF_INET, port)?;
+                count += 1;
+                mask |= HAS_V4;
+                // IPv6 may be disabled
+                if let Ok(fd) = listen(libc::AF_INET6, port) {
+                    fds[count] = fd;
+                    count += 1;
+                    mask |= HAS_V6;
+                }
+            }
+
+            check(
+                libc::prctl(
+                    libc::PR_SET_NO_NEW_PRIVS,
+                    1 as libc::c_ulong,
+                    0 as libc::c_ulong,
+                    0 as libc::c_ulong,
+                    0 as libc::c_ulong,
+                ) as _,
+                "no_new_privs",
+            )?;
+            if !self.filter.is_empty() {
+                let prog = libc::sock_fprog {
+                    len: self.filter.len() as u16,
+                    filter: self.filter.as_ptr() as *mut libc::sock_filter,
+                };
+                let fd = check(
+                    libc::syscall(
+                        libc::SYS_seccomp,
+                        libc::SECCOMP_SET_MODE_FILTER,
+                        libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                        &prog as *const libc::sock_fprog,
+                    ),
+                    "seccomp",
+                )?;
+                fds[count] = fd as RawFd;
+                count += 1;
+                mask |= HAS_NOTIFY;
+            }
+            Ok((fds, count, mask))
+        }
+    }
+}
+
+unsafe fn mount_all(ops: &[MountOp]) -> Result<(), Failure> {
+    unsafe {
+        for op in ops {
+            match op {
+                MountOp::ReadOnly(path) => {
+                    bind(path)?;
+                    let flags = locked_flags(path) | libc::MS_RDONLY;
+                    remount(path, flags, "read-only remount")?;
+                }
+                MountOp::HideDir(path) => {
+                    tmpfs(path, c"size=64k,mode=0700", "hide directory")?;
+                }
+                MountOp::HideFile(path) => {
+                    check(
+                        libc::mount(
+                            c"/dev/null".as_ptr(),
+                            path.as_ptr(),
+                            std::ptr::null(),
+                            libc::MS_BIND,
+                            std::ptr::null(),
+                        ) as _,
+                        "hide file",
+                    )?;
+                }
+                MountOp::PrivateTmp => {
+                    tmpfs(c"/tmp", c"mode=1777", "private /tmp")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe fn tmpfs(
+    path: &std::ffi::CStr,
+    options: &std::ffi::CStr,
+    step: &'static str,
+) -> Result<(), Failure> {
+    unsafe {
+        check(
+            libc::mount(
+                c"tmpfs".as_ptr(),
+                path.as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                options.as_ptr().cast(),
+            ) as _,
+            step,
+        )
+        .map(|_| ())
+    }
+}
+
+/// `/proc/self/fd/<fd>`, NUL-terminated, without allocating.
+fn fd_path(fd: RawFd) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let prefix = b"/proc/self/fd/";
+    buf[..prefix.len()].copy_from_slice(prefix);
+    let mut digits = [0u8; 10];
+    let (mut n, mut len) = (fd.unsigned_abs(), 0);
+    loop {
+        digits[len] = b'0' + (n % 10) as u8;
+        len += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for (i, digit) in digits[..len].iter().rev().enumerate() {
+        buf[prefix.len() + i] = *digit;
+    }
+    buf
+}
+
+/// Create `path` and its missing parents (in a tmpfs that covered them),
+/// as a directory or an empty file. Errors are left to the bind mount.
+unsafe fn make_path(path: &CString, is_dir: bool) {
+    let bytes = path.as_bytes();
+    let mut buf = [0u8; libc::PATH_MAX as usize];
+    if bytes.len() >= buf.len() {
+        return;
+    }
+    buf[..bytes.len()].copy_from_slice(bytes);
+    unsafe {
+        for i in 1..bytes.len() {
+            if buf[i] == b'/' {
+                buf[i] = 0;
+                libc::mkdir(buf.as_ptr().cast(), 0o755);
+                buf[i] = b'/';
+            }
+        }
+        if is_dir {
+            libc::mkdir(path.as_ptr(), 0o755);
+        } else {
+            let fd = libc::open(
+                path.as_ptr(),
+                libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                0o600,
+            );
+            if fd >= 0 {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+unsafe fn write_file(path: &std::ffi::CStr, data: &[u8]) -> Result<(), Failure> {
+    unsafe {
+        let fd = check(
+            libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) as _,
+            "open id map",
+        )? as RawFd;
+        let written = libc::write(fd, data.as_ptr().cast(), data.len());
+        libc::close(fd);
+        check(written as _, "write id map").map(|_| ())
+    }
+}
+
+unsafe fn bind(path: &CString) -> Result<(), Failure> {
+    unsafe {
+        check(
+            libc::mount(
+                path.as_ptr(),
+                path.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                std::ptr::null(),
+            ) as _,
+            "bind mount",
+        )
+        .map(|_| ())
+    }
+}
+
+unsafe fn remount(path: &CString, flags: libc::c_ulong, step: &'static str) -> Result<(), Failure> {
+    unsafe {
+        check(
+            libc::mount(
+                std::ptr::null(),
+                path.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REMOUNT | flags,
+                std::ptr::null(),
+            ) as _,
+            step,
+        )
+        .map(|_| ())
+    }
+}
+
+/// Flags of the mount at `path` that a remount from inside a user
+/// namespace has to keep.
+unsafe fn locked_flags(path: &CString) -> libc::c_ulong {
+    unsafe {
+        let mut st: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut st) != 0 {
+            return 0;
+        }
+        [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ]
+        .iter()
+        .filter(|(st_flag, _)| st.f_flag & st_flag != 0)
+        .fold(0, |flags, (_, ms_flag)| flags | ms_flag)
+    }
+}
+
+unsafe fn loopback_up() -> Result<(), Failure> {
+    unsafe {
+        let sock = check(
+            libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) as _,
+            "loopback socket",
+        )? as RawFd;
+        let mut req: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+            *dst = *src as libc::c_char;
+        }
+        let result = check(
+            libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut req) as _,
+            "loopback flags",
+        )
+        .and_then(|_| {
+            req.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            check(
+                libc::ioctl(sock, libc::SIOCSIFFLAGS, &req) as _,
+                "loopback up",
+            )
+        });
+        libc::close(sock);
+        result.map(|_| ())
+    }
+}
+
+unsafe fn listen(family: libc::c_int, port: u16) -> Result<RawFd, Failure> {
+    unsafe {
+        let fd = check(
+            libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) as _,
+            "proxy socket",
+        )? as RawFd;
+        let bound = if family == libc::AF_INET {
+            let mut addr: libc::sockaddr_in = std::mem::zeroed();
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_port = port.to_be();
+            addr.sin_addr.s_addr = u32::from(Ipv4Addr::LOCALHOST).to_be();
+            libc::bind(
+                fd,
+                (&addr as *const libc::sockaddr_in).cast(),
+                std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        } else {
+            let mut addr: libc::sockaddr_in6 = std::mem::zeroed();
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_port = port.to_be();
+            addr.sin6_addr.s6_addr = Ipv6Addr::LOCALHOST.octets();
+            libc::bind(
+                fd,
+                (&addr as *const libc::sockaddr_in6).cast(),
+                std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+            )
+        };
+        let result = check(bound as _, "proxy bind")
+            .and_then(|_| check(libc::listen(fd, 128) as _, "proxy listen"));
+        if let Err(failure) = result {
+            libc::close(fd);
+            return Err(failure);
+        }
+        Ok(fd)
+    }
+}
+
+/// Room for the three descriptors a child can hand over.
+#[repr(C, align(8))]
+struct CmsgBuf([u8; 64]);
+
+unsafe fn send_fds(channel: RawFd, data: &[u8], fds: &[RawFd]) {
+    unsafe {
+        let iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut buf = CmsgBuf([0; 64]);
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &iov as *const libc::iovec as *mut libc::iovec;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            let size = std::mem::size_of_val(fds) as libc::c_uint;
+            msg.msg_control = buf.0.as_mut_ptr().cast();
+            msg.msg_controllen = libc::CMSG_SPACE(size) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr().cast::<u8>(),
+                libc::CMSG_DATA(cmsg),
+                size as usize,
+            );
+        }
+        libc::sendmsg(channel, &msg, 0);
+    }
+}
+
+// ── Parent side ─────────────────────────────────────────────────────────────
+
+/// The socketpair a child reports its setup on: `(parent, child)`.
+pub(crate) fn channel() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: socketpair fills both descriptors on success.
+    let ret = unsafe {
+        libc::socketpair(
+            libc::AF_UNIX,
+            libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+            0,
+            fds.as_mut_ptr(),
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: freshly created and owned by nobody else.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// What a child set up for the server to serve.
+pub(crate) struct Handoff {
+    listeners: Vec<OwnedFd>,
+    notify: Option<OwnedFd>,
+}
+
+/// Wait for the child's report on `channel`.
+pub(crate) fn receive(channel: OwnedFd, timeout: Duration) -> Result<Handoff, String> {
+    let fd = channel.as_raw_fd();
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: one valid pollfd.
+    let ready = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+    if ready == 0 {
+        return Err("sandbox setup timed out".to_string());
+    }
+    if ready < 0 {
+        return Err(format!("sandbox setup: {}", io::Error::last_os_error()));
+    }
+
+    let mut data = [0u8; 256];
+    let mut buf = CmsgBuf([0; 64]);
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    // SAFETY: msghdr points at live buffers of the given sizes; received
+    // descriptors are taken over exactly once.
+    let (len, fds) = unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = buf.0.as_mut_ptr().cast();
+        msg.msg_controllen = buf.0.len() as _;
+        let len = libc::recvmsg(fd, &mut msg, libc::MSG_CMSG_CLOEXEC);
+        if len < 0 {
+            return Err(format!("sandbox setup: {}", io::Error::last_os_error()));
+        }
+        let mut fds = Vec::new();
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let bytes = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                for i in 0..bytes / std::mem::size_of::<RawFd>() {
+                    fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        (len as usize, fds)
+    };
+
+    match data[..len] {
+        [b'o', mask] => {
+            let mut fds = fds.into_iter();
+            let mut listeners = Vec::new();
+            for bit in [HAS_V4, HAS_V6] {
+                if mask & bit != 0 {
+                    listeners.extend(fds.next());
+                }
+            }
+            let notify = if mask & HAS_NOTIFY != 0 {
+                fds.next()
+            } else {
+                None
+            };
+            Ok(Handoff { listeners, notify })
+        }
+        [b'e', a, b, c, d, ref step @ ..] => Err(format!(
+            "sandbox setup failed ({}): {}",
+            String::from_utf8_lossy(step),
+            io::Error::from_raw_os_error(i32::from_ne_bytes([a, b, c, d]))
+        )),
+        _ => Err("sandbox setup failed: the child exited without a report".to_string()),
+    }
+}
+
+/// A running session's sandbox: its settings, the proxy forwarder and the
+/// syscall supervisor.
+pub struct Sandbox {
+    config: SandboxConfig,
+    project: PathBuf,
+    violations: AtomicUsize,
+    seen: Mutex<HashSet<Violation>>,
+    listeners: Vec<OwnedFd>,
+    stopped: AtomicBool,
+}
+
+impl Sandbox {
+    /// Serve what the child handed over: forward connections on its proxy
+    /// listeners to `upstream` (`host:port`) and answer its filtered
+    /// syscalls. Each distinct violation is passed to `report` once.
+    pub(crate) fn start(
+        config: SandboxConfig,
+        project: PathBuf,
+        handoff: Handoff,
+        upstream: Option<String>,
+        report: impl Fn(Violation) + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let sandbox = Arc::new(Self {
+            config,
+            project,
+            violations: AtomicUsize::new(0),
+            seen: Mutex::new(HashSet::new()),
+            listeners: handoff.listeners,
+            stopped: AtomicBool::new(false),
+        });
+        if let Some(upstream) = upstream {
+            for fd in &sandbox.listeners {
+                let listener = match fd.try_clone() {
+                    Ok(fd) => TcpListener::from(fd),
+                    Err(e) => {
+                        warn!(error = %e, "sandbox proxy listener unusable");
+                        continue;
+                    }
+                };
+                let (sandbox, upstream) = (Arc::clone(&sandbox), upstream.clone());
+                let _ = std::thread::Builder::new()
+                    .name("sandbox-proxy".to_string())
+                    .spawn(move || sandbox.forward(listener, &upstream));
+            }
+        }
+        if let Some(notify) = handoff.notify {
+            let sandbox_ref = Arc::clone(&sandbox);
+            let report: Arc<dyn Fn(Violation) + Send + Sync> = Arc::new(report);
+            let _ = std::thread::Builder::new()
+                .name("sandbox-seccomp".to_string())
+                .spawn(move || sandbox_ref.supervise(notify, report));
+        }
+        sandbox
+    }
+
+    pub fn config(&self) -> &SandboxConfig {
+        &self.config
+    }
+
+    /// Refused syscalls and connects so far.
+    pub fn violation_count(&self) -> usize {
+        self.violations.load(Ordering::Relaxed)
+    }
+
+    /// Stop forwarding; called when the session's PTY closes.
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        for fd in &self.listeners {
+            // SAFETY: shutting down a socket we own wakes its accept loop.
+            unsafe { libc::shutdown(fd.as_raw_fd(), libc::SHUT_RDWR) };
+        }
+    }
+
+    fn forward(&self, listener: TcpListener, upstream: &str) {
+        for conn in listener.incoming() {
+            if self.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+            let Ok(inside) = conn else { continue };
+            let upstream = upstream.to_string();
+            let _ = std::thread::Builder::new()
+                .name("sandbox-proxy-conn".to_string())
+                .spawn(move || match TcpStream::connect(&upstream) {
+                    Ok(outside) => splice(inside, outside),
+                    Err(e) => debug!(error = %e, %upstream, "sandbox proxy connect failed"),
+                });
+        }
+    }
+
+    fn supervise(&self, notify: OwnedFd, report: Arc<dyn Fn(Violation) + Send + Sync>) {
+        let fd = notify.as_raw_fd();
+        loop {
+            let mut pfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd.
+            if unsafe { libc::poll(&mut pfd, 1, -1) } < 0 {
+                if io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                break;
+            }
+            // Every process under the filter is gone
+            if pfd.revents & libc::POLLIN == 0 {
+                break;
+            }
+            // SAFETY: the kernel requires a zeroed request and fills it.
+            let mut req: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+            if unsafe { libc::ioctl(fd, SECCOMP_IOCTL_NOTIF_RECV, &mut req) } < 0 {
+                continue;
+            }
+
+            let nr = libc::c_long::from(req.data.nr);
+            let (violation, errno) = if nr == libc::SYS_connect {
+                let addr = read_sockaddr(&req).unwrap_or_default();
+                let host_network = self.config.network == SandboxNetwork::Host;
+                let dest = connect_destination(&addr, host_network, |path| {
+                    self.unix_allowed(req.pid, path)
+                })
+                .filter(|_| notification_valid(fd, req.id));
+                let violation = dest.map(|dest| Violation {
+                    kind: ViolationKind::Network,
+                    detail: format!("connect to {dest}"),
+                });
+                let errno = if address_family(&addr) == Some(libc::AF_UNIX) {
+                    libc::EACCES
+                } else {
+                    libc::ENETUNREACH
+                };
+                (violation, errno)
+            } else {
+                let name = syscall_name(nr).map_or_else(|| format!("syscall {nr}"), str::to_string);
+                let violation = Violation {
+                    kind: ViolationKind::Syscall,
+                    detail: name,
+                };
+                (Some(violation), libc::EPERM)
+            };
+
+            let mut resp: libc::seccomp_notif_resp = unsafe { std::mem::zeroed() };
+            resp.id = req.id;
+            match violation {
+                Some(violation) => {
+                    resp.error = -errno;
+                    self.record(violation, &*report);
+                }
+                None => resp.flags = libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+            }
+            // SAFETY: a filled response for the request just received; a
+            // target that died meanwhile only makes this fail.
+            unsafe { libc::ioctl(fd, SECCOMP_IOCTL_NOTIF_SEND, &resp) };
+        }
+    }
+}
+
+impl Sandbox {
+    /// Whether a sandboxed process may connect to the Unix socket at `path`:
+    /// only sockets in the project or its private `/tmp`. Daemon sockets
+    /// elsewhere (docker, ssh-agent) would reach past the sandbox. Like any
+    /// check of a user notification this is advisory against a caller that
+    /// rewrites the address meanwhile; hiding `/run` is what keeps the
+    /// well-known sockets out of reach.
+    fn unix_allowed(&self, pid: u32, path: &Path) -> bool {
+        let path = if path.is_relative() {
+            match std::fs::read_link(format!("/proc/{pid}/cwd")) {
+                Ok(cwd) => cwd.join(path),
+                Err(_) => return false,
+            }
+        } else {
+            path.to_path_buf()
+        };
+        let path = normalize(&path);
+        if self.config.private_tmp && path.starts_with("/tmp") {
+            return true;
+        }
+        path.canonicalize()
+            .unwrap_or(path)
+            .starts_with(&self.project)
+    }
+
+    fn record(&self, violation: Violation, report: &(dyn Fn(Violation) + Send + Sync)) {
+        self.violations.fetch_add(1, Ordering::Relaxed);
+        let first = self
+            .seen
+            .lock()
+            .map(|mut seen| seen.insert(violation.clone()))
+            .unwrap_or(false);
+        if first {
+            info!(kind = ?violation.kind, detail = %violation.detail, "sandbox violation");
+            report(violation);
+        }
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// `path` with `.` and `..` resolved lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// The address argument of a trapped `connect`, read from the caller.
+fn read_sockaddr(req: &libc::seccomp_notif) -> Option<Vec<u8>> {
+    let (ptr, len) = (req.data.args[1], req.data.args[2].min(128) as usize);
+    let mem = std::fs::File::open(format!("/proc/{}/mem", req.pid)).ok()?;
+    let mut addr = vec![0u8; len];
+    let n = mem.read_at(&mut addr, ptr).ok()?;
+    addr.truncate(n);
+    Some(addr)
+}
+
+/// Whether the request is still pending, i.e. what was read from the
+/// caller's memory belonged to it.
+fn notification_valid(fd: RawFd, id: u64) -> bool {
+    // SAFETY: ID_VALID only reads the id.
+    unsafe { libc::ioctl(fd, SECCOMP_IOCTL_NOTIF_ID_VALID, &id) == 0 }
+}
+
+/// Copy both directions until either side closes.
+fn splice(inside: TcpStream, outside: TcpStream) {
+    let (Ok(mut inside_rx), Ok(mut outside_tx)) = (inside.try_clone(), outside.try_clone()) else {
+        return;
+    };
+    let upload = std::thread::spawn(move || {
+        let _ = io::copy(&mut inside_rx, &mut outside_tx);
+        let _ = outside_tx.shutdown(Shutdown::Write);
+    });
+    let (mut outside_rx, mut inside_tx) = (outside, inside);
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        match outside_rx.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if inside_tx.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = inside_tx.shutdown(Shutdown::Write);
+    let _ = upload.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether this kernel lets an unprivileged process create the
+    /// namespaces the sandbox needs.
+    fn namespaces_available() -> bool {
+        match unsafe { nix::unistd::fork() } {
+            Ok(nix::unistd::ForkResult::Child) => unsafe {
+                let flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET;
+                libc::_exit(if libc::unshare(flags) == 0 { 0 } else { 1 });
+            },
+            Ok(nix::unistd::ForkResult::Parent { child }) => matches!(
+                nix::sys::wait::waitpid(child, None),
+                Ok(nix::sys::wait::WaitStatus::Exited(_, 0))
+            ),
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn config_validation() {
+        let config = SandboxConfig::default();
+        assert_eq!(config.validate(), Ok(()));
+        let json = serde_json::json!({"network": "none", "deny_syscalls": ["ptrace"]});
+        let parsed: SandboxConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.network, SandboxNetwork::None);
+        assert_eq!(parsed.hidden, config.hidden);
+        assert!(parsed.private_tmp);
+        for path in ["/data/noaide", "/run", "/var/run"] {
+            assert!(config.hidden.iter().any(|p| p == path), "{path} not hidden");
+        }
+
+        let bad = |config: SandboxConfig| config.validate().unwrap_err();
+        assert!(
+            bad(SandboxConfig {
+                deny_syscalls: vec!["open".to_string()],
+                ..Default::default()
+            })
+            .contains("unknown syscall")
+        );
+        assert!(
+            bad(SandboxConfig {
+                hidden: vec!["relative/path".to_string()],
+                ..Default::default()
+            })
+            .contains("absolute")
+        );
+        assert!(
+            bad(SandboxConfig {
+                read_only: vec!["/".to_string()],
+                ..Default::default()
+            })
+            .contains("cannot cover")
+        );
+        assert!(
+            bad(SandboxConfig {
+                writable: vec!["/work".to_string(); MAX_WRITABLE],
+                ..Default::default()
+            })
+            .contains("at most")
+        );
+    }
+
+    #[test]
+    fn connects_off_the_machine_are_flagged() {
+        let nowhere = |_: &Path| false;
+        let v4 = |ip: [u8; 4], port: u16| {
+            let mut addr = (libc::AF_INET as u16).to_ne_bytes().to_vec();
+            addr.extend(port.to_be_bytes());
+            addr.extend(ip);
+            addr.extend([0; 8]);
+            addr
+        };
+        assert_eq!(
+            connect_destination(&v4([127, 0, 0, 1], 4434), false, nowhere),
+            None
+        );
+        assert_eq!(
+            connect_destination(&v4([1, 1, 1, 1], 443), false, nowhere),
+            Some("1.1.1.1:443".to_string())
+        );
+        assert_eq!(
+            connect_destination(&v4([1, 1, 1, 1], 443), true, nowhere),
+            None
+        );
+
+        let v6 = |ip: Ipv6Addr| {
+            let mut addr = (libc::AF_INET6 as u16).to_ne_bytes().to_vec();
+            addr.extend(53u16.to_be_bytes());
+            addr.extend([0; 4]);
+            addr.extend(ip.octets());
+            addr
+        };
+        assert_eq!(
+            connect_destination(&v6(Ipv6Addr::LOCALHOST), false, nowhere),
+            None
+        );
+        assert_eq!(
+            connect_destination(&v6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()), false, nowhere),
+            None
+        );
+        assert_eq!(
+            connect_destination(&v6("2001:db8::1".parse().unwrap()), false, nowhere),
+            Some("[2001:db8::1]:53".to_string())
+        );
+    }
+
+    #[test]
+    fn unix_sockets_outside_the_project_are_flagged() {
+        let unix = |path: &[u8]| {
+            let mut addr = (libc::AF_UNIX as u16).to_ne_bytes().to_vec();
+            addr.extend(path);
+            addr.push(0);
+            addr
+        };
+        let in_project = |path: &Path| path.starts_with("/work/app");
+        assert_eq!(
+            connect_destination(&unix(b"/var/run/docker.sock"), false, in_project),
+            Some("unix:/var/run/docker.sock".to_string())
+        );
+        // Host network: still checked
+        assert_eq!(
+            connect_destination(&unix(b"/run/user/1000/ssh-agent"), true, in_project),
+            Some("unix:/run/user/1000/ssh-agent".to_string())
+        );
+        assert_eq!(
+            connect_destination(&unix(b"/work/app/.lsp.sock"), false, in_project),
+            None
+        );
+        // Abstract sockets are private to a network namespace
+        assert_eq!(
+            connect_destination(&unix(b"\0dbus-1"), false, in_project),
+            None
+        );
+        assert_eq!(
+            connect_destination(&unix(b"\0dbus-1"), true, in_project),
+            Some("unix:@dbus-1".to_string())
+        );
+        let unnamed = (libc::AF_UNIX as u16).to_ne_bytes();
+        assert_eq!(connect_destination(&unnamed, false, in_project), None);
+
+        assert_eq!(
+            normalize(Path::new("/work/app/../../run/./docker.sock")),
+            PathBuf::from("/run/docker.sock")
+        );
+        assert_eq!(fd_path(1234)[..19], *b"/proc/self/fd/1234\0");
+    }
+
+    #[test]
+    fn filter_hands_listed_syscalls_to_the_supervisor() {
+        let prog = seccomp_filter(0xc000_003e, &[libc::SYS_ptrace, libc::SYS_connect]);
+        let notified: Vec<u32> = prog
+            .windows(2)
+            .filter(|w| w[1].k == libc::SECCOMP_RET_USER_NOTIF)
+            .map(|w| w[0].k)
+            .collect();
+        assert_eq!(
+            notified,
+            vec![libc::SYS_ptrace as u32, libc::SYS_connect as u32]
+        );
+        assert_eq!(prog.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+        assert_eq!(prog[1].k, 0xc000_003e);
+    }
+
+    /// Fork a child into `config`'s sandbox for `project` and exit it with
+    /// what `checks` returns there. Returns that exit code, the sandbox and
+    /// the reported violations.
+    fn run_sandboxed(
+        config: &SandboxConfig,
+        project: &Path,
+        proxy_port: Option<u16>,
+        checks: impl FnOnce() -> i32,
+    ) -> (i32, Arc<Sandbox>, Vec<Violation>) {
+        let plan = Plan::new(config, project, proxy_port).unwrap();
+        let (parent, child) = channel().unwrap();
+        match unsafe { nix::unistd::fork() }.unwrap() {
+            nix::unistd::ForkResult::Child => unsafe {
+                drop(parent);
+                plan.enter(child.as_raw_fd());
+                libc::_exit(checks());
+            },
+            nix::unistd::ForkResult::Parent { child: pid } => {
+                drop(child);
+                let handoff = receive(parent, Duration::from_secs(5)).unwrap();
+                let reported = Arc::new(Mutex::new(Vec::new()));
+                let sink = Arc::clone(&reported);
+                let sandbox = Sandbox::start(
+                    config.clone(),
+                    plan.project().to_path_buf(),
+                    handoff,
+                    proxy_port.map(|port| format!("127.0.0.1:{port}")),
+                    move |v| sink.lock().unwrap().push(v),
+                );
+                let code = match nix::sys::wait::waitpid(pid, None).unwrap() {
+                    nix::sys::wait::WaitStatus::Exited(_, code) => code,
+                    other => panic!("sandboxed child did not exit: {other:?}"),
+                };
+                let reported = reported.lock().unwrap().clone();
+                (code, sandbox, reported)
+            }
+        }
+    }
+
+    fn c_path(path: PathBuf) -> CString {
+        CString::new(path.into_os_string().into_encoded_bytes()).unwrap()
+    }
+
+    /// Whether `path` can be created (as a file) in the calling process.
+    unsafe fn create(path: &CString) -> bool {
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_CREAT | libc::O_WRONLY, 0o600);
+            if fd >= 0 {
+                libc::close(fd);
+            }
+            fd >= 0
+        }
+    }
+
+    /// Whether a Unix stream connect to `path` succeeds.
+    unsafe fn connect_unix(path: &CString) -> bool {
+        unsafe {
+            let fd = libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0);
+            let mut addr: libc::sockaddr_un = std::mem::zeroed();
+            addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+            for (dst, src) in addr.sun_path.iter_mut().zip(path.as_bytes()) {
+                *dst = *src as libc::c_char;
+            }
+            let ok = libc::connect(
+                fd,
+                (&addr as *const libc::sockaddr_un).cast(),
+                std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+            ) == 0;
+            libc::close(fd);
+            ok
+        }
+    }
+
+    /// Runs a child in the sandbox and checks what it may do. Skipped where
+    /// unprivileged user namespaces are off.
+    #[test]
+    fn sandboxed_child_is_confined() {
+        if AUDIT_ARCH.is_none() || !namespaces_available() {
+            eprintln!("SKIP: user namespaces unavailable");
+            return;
+        }
+        let project = tempfile::tempdir().unwrap();
+        let locked = tempfile::tempdir().unwrap();
+        let secret = locked.path().join("secret");
+        std::fs::create_dir(&secret).unwrap();
+        std::fs::write(secret.join("key"), "hunter2").unwrap();
+        // A daemon socket outside the project, and a tool's inside it
+        let _agent = std::os::unix::net::UnixListener::bind(locked.path().join("agent.sock"));
+        let _tool = std::os::unix::net::UnixListener::bind(project.path().join("tool.sock"));
+
+        // Stand-in for the noaide proxy
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = proxy.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            if let Ok((mut conn, _)) = proxy.accept() {
+                let _ = conn.write_all(b"proxied");
+            }
+        });
+
+        let config = SandboxConfig {
+            read_only: vec![locked.path().display().to_string()],
+            writable: vec![],
+            hidden: vec![secret.display().to_string()],
+            private_tmp: false,
+            network: SandboxNetwork::Proxy,
+            deny_syscalls: vec!["keyctl".to_string()],
+        };
+        let (in_project, in_locked, hidden_key, agent, tool) = (
+            c_path(project.path().join("ok")),
+            c_path(locked.path().join("nope")),
+            c_path(secret.join("key")),
+            c_path(locked.path().join("agent.sock")),
+            c_path(project.path().join("tool.sock")),
+        );
+        let (code, sandbox, reported) =
+            run_sandboxed(&config, project.path(), Some(port), || unsafe {
+                let connect = |ip: Ipv4Addr, port: u16| {
+                    let fd = libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0);
+                    let mut addr: libc::sockaddr_in = std::mem::zeroed();
+                    addr.sin_family = libc::AF_INET as libc::sa_family_t;
+                    addr.sin_port = port.to_be();
+                    addr.sin_addr.s_addr = u32::from(ip).to_be();
+                    let ok = libc::connect(
+                        fd,
+                        (&addr as *const libc::sockaddr_in).cast(),
+                        std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    ) == 0;
+                    (fd, ok)
+                };
+                let mut failures = 0;
+                if !create(&in_project) {
+                    failures |= 1;
+                }
+                if create(&in_locked) {
+                    failures |= 2;
+                }
+                if libc::access(hidden_key.as_ptr(), libc::F_OK) == 0 {
+                    failures |= 4;
+                }
+                let (fd, ok) = connect(Ipv4Addr::LOCALHOST, port);
+                let mut buf = [0u8; 7];
+                if !ok || libc::read(fd, buf.as_mut_ptr().cast(), 7) != 7 || &buf != b"proxied" {
+                    failures |= 8;
+                }
+                if connect(Ipv4Addr::new(192, 0, 2, 1), 443).1 {
+                    failures |= 16;
+                }
+                if libc::syscall(libc::SYS_keyctl, 0, 0, 0, 0, 0) != -1 {
+                    failures |= 32;
+                }
+                if connect_unix(&agent) {
+                    failures |= 64;
+                }
+                if !connect_unix(&tool) {
+                    failures |= 128;
+                }
+                failures
+            });
+        assert_eq!(code, 0);
+        assert_eq!(sandbox.violation_count(), 3);
+        assert!(reported.contains(&Violation {
+            kind: ViolationKind::Network,
+            detail: "connect to 192.0.2.1:443".to_string(),
+        }));
+        assert!(reported.contains(&Violation {
+            kind: ViolationKind::Syscall,
+            detail: "keyctl".to_string(),
+        }));
+        assert!(reported.contains(&Violation {
+            kind: ViolationKind::Network,
+            detail: format!(
+                "connect to unix:{}",
+                locked.path().join("agent.sock").display()
+            ),
+        }));
+        assert!(project.path().join("ok").exists());
+        assert!(!locked.path().join("nope").exists());
+    }
+
+    /// A private `/tmp` hides the host's but keeps a project inside it.
+    #[test]
+    fn private_tmp_keeps_the_project() {
+        if AUDIT_ARCH.is_none() || !namespaces_available() {
+            eprintln!("SKIP: user namespaces unavailable");
+            return;
+        }
+        let project = tempfile::tempdir().unwrap();
+        let outside = tempfile::NamedTempFile::new().unwrap();
+        let scratch = PathBuf::from(format!("/tmp/noaide-sandbox-{}", Uuid::new_v4()));
+        let config = SandboxConfig {
+            read_only: vec![],
+            writable: vec![],
+            hidden: vec![],
+            private_tmp: true,
+            network: SandboxNetwork::None,
+            deny_syscalls: vec![],
+        };
+        let (in_project, host_file, in_tmp) = (
+            c_path(project.path().join("ok")),
+            c_path(outside.path().to_path_buf()),
+            c_path(scratch.clone()),
+        );
+        let (code, _sandbox, reported) = run_sandboxed(&config, project.path(), None, || unsafe {
+            let mut failures = 0;
+            if !create(&in_project) {
+                failures |= 1;
+            }
+            if libc::access(host_file.as_ptr(), libc::F_OK) == 0 {
+                failures |= 2;
+            }
+            if !create(&in_tmp) {
+                failures |= 4;
+            }
+            failures
+        });
+        assert_eq!(code, 0);
+        assert!(reported.is_empty());
+        assert!(project.path().join("ok").exists());
+        assert!(!scratch.exists());
+    }
+}
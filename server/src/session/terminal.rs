@@ -0,0 +1,417 @@
+//! The terminal side of a managed session: PTY size, viewers and screen.
+//!
+//! Browser viewers attach through [`Terminal::attach`] and report their
+//! size; the session's [`SizePolicy`] decides which size the PTY gets. A
+//! resize sets the window size on the PTY master (`TIOCSWINSZ`), and the
+//! kernel delivers `SIGWINCH` to the CLI so it redraws.
+//!
+//! A VT emulator (`vt100`) follows the output, so a viewer joining mid-session
+//! starts from the current screen instead of a blank terminal and then
+//! continues with the live output.
+
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use nix::libc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use super::recording::Recorder;
+use super::types::SESSION_EVENT_CAPACITY;
+
+/// Size a PTY gets when nothing else was asked for.
+pub const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// Largest size a client may ask for, as `(cols, rows)`.
+pub const MAX_SIZE: (u16, u16) = (1000, 500);
+
+/// Check a requested PTY size: at least 1x1 and at most [`MAX_SIZE`].
+pub fn check_size(cols: u16, rows: u16) -> std::io::Result<()> {
+    if cols == 0 || rows == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "terminal size must be at least 1x1",
+        ));
+    }
+    if cols > MAX_SIZE.0 || rows > MAX_SIZE.1 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "terminal size must be at most {}x{}",
+                MAX_SIZE.0, MAX_SIZE.1
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Which viewer decides the PTY size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizePolicy {
+    /// The smallest width and height among the viewers, so the screen fits
+    /// every pane.
+    #[default]
+    Smallest,
+    /// The viewer that attached first; when it leaves, the next oldest one.
+    Owner,
+}
+
+/// What attached viewers receive.
+#[derive(Debug, Clone)]
+pub enum TerminalEvent {
+    /// Raw PTY output.
+    Output(Bytes),
+    /// The PTY size changed.
+    Resize { cols: u16, rows: u16 },
+    /// The session closed; no more output follows.
+    Closed,
+}
+
+/// Attached viewers in join order.
+#[derive(Debug, Default)]
+struct Viewers {
+    next_id: u64,
+    sizes: Vec<(u64, u16, u16)>,
+}
+
+impl Viewers {
+    fn join(&mut self, cols: u16, rows: u16) -> u64 {
+        self.next_id += 1;
+        self.sizes.push((self.next_id, cols, rows));
+        self.next_id
+    }
+
+    fn resize(&mut self, id: u64, cols: u16, rows: u16) {
+        if let Some(entry) = self.sizes.iter_mut().find(|(v, _, _)| *v == id) {
+            *entry = (id, cols, rows);
+        }
+    }
+
+    fn leave(&mut self, id: u64) {
+        self.sizes.retain(|(v, _, _)| *v != id);
+    }
+
+    fn owner(&self) -> Option<u64> {
+        self.sizes.first().map(|(id, _, _)| *id)
+    }
+
+    /// The size the policy asks for; `None` without viewers.
+    fn size(&self, policy: SizePolicy) -> Option<(u16, u16)> {
+        match policy {
+            SizePolicy::Owner => self.sizes.first().map(|&(_, cols, rows)| (cols, rows)),
+            SizePolicy::Smallest => {
+                let cols = self.sizes.iter().map(|&(_, cols, _)| cols).min()?;
+                let rows = self.sizes.iter().map(|&(_, _, rows)| rows).min()?;
+                Some((cols, rows))
+            }
+        }
+    }
+}
+
+/// PTY size, screen state, viewers and recording of one managed session.
+pub struct Terminal {
+    /// A duplicate of the PTY master, used for `TIOCSWINSZ` only.
+    master: std::fs::File,
+    policy: SizePolicy,
+    /// Also serializes output with attaching viewers: a viewer's screen
+    /// snapshot and its event stream meet without gap or overlap.
+    screen: Mutex<vt100::Parser>,
+    viewers: Mutex<Viewers>,
+    tx: broadcast::Sender<TerminalEvent>,
+    recorder: Option<Recorder>,
+}
+
+impl Terminal {
+    pub(crate) fn new(
+        master: std::fs::File,
+        (cols, rows): (u16, u16),
+        policy: SizePolicy,
+        recorder: Option<Recorder>,
+    ) -> Self {
+        let (tx, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        Self {
+            master,
+            policy,
+            screen: Mutex::new(vt100::Parser::new(rows, cols, 0)),
+            viewers: Mutex::new(Viewers::default()),
+            tx,
+            recorder,
+        }
+    }
+
+    /// Current PTY size as `(cols, rows)`.
+    pub fn size(&self) -> (u16, u16) {
+        let (rows, cols) = self.screen.lock().unwrap().screen().size();
+        (cols, rows)
+    }
+
+    pub fn policy(&self) -> SizePolicy {
+        self.policy
+    }
+
+    pub fn viewer_count(&self) -> usize {
+        self.viewers.lock().unwrap().sizes.len()
+    }
+
+    /// The asciicast file this terminal is recorded to, if any.
+    pub fn recording_path(&self) -> Option<&Path> {
+        self.recorder.as_ref().map(Recorder::path)
+    }
+
+    /// Feed PTY output to the recording, the screen and the viewers.
+    pub(crate) fn output(&self, bytes: &[u8]) {
+        if let Some(recorder) = &self.recorder {
+            recorder.output(bytes);
+        }
+        let mut screen = self.screen.lock().unwrap();
+        screen.process(bytes);
+        let _ = self
+            .tx
+            .send(TerminalEvent::Output(Bytes::copy_from_slice(bytes)));
+    }
+
+    /// Note input written to the PTY.
+    pub(crate) fn input(&self, text: &str) {
+        if let Some(recorder) = &self.recorder {
+            recorder.input(text);
+        }
+    }
+
+    /// The session ended; `marker` (e.g. the exit status) goes into the
+    /// recording.
+    pub(crate) fn close(&self, marker: Option<&str>) {
+        if let (Some(recorder), Some(marker)) = (&self.recorder, marker) {
+            recorder.marker(marker);
+        }
+        let _ = self.tx.send(TerminalEvent::Closed);
+    }
+
+    /// Set the PTY size. Returns `false` if it already had this size.
+    ///
+    /// The size holds until the next viewer joins, leaves or resizes.
+    pub fn resize(&self, cols: u16, rows: u16) -> std::io::Result<bool> {
+        check_size(cols, rows)?;
+        let mut screen = self.screen.lock().unwrap();
+        if screen.screen().size() == (rows, cols) {
+            return Ok(false);
+        }
+        let winsize = libc::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // The kernel sends SIGWINCH to the foreground process group.
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        screen.screen_mut().set_size(rows, cols);
+        if let Some(recorder) = &self.recorder {
+            recorder.resize(cols, rows);
+        }
+        let _ = self.tx.send(TerminalEvent::Resize { cols, rows });
+        Ok(true)
+    }
+
+    /// Attach a viewer of `cols`×`rows`. The PTY is resized per the policy
+    /// before the viewer's screen snapshot is taken.
+    pub fn attach(self: &Arc<Self>, cols: u16, rows: u16) -> Viewer {
+        let id = {
+            let mut viewers = self.viewers.lock().unwrap();
+            let id = viewers.join(cols, rows);
+            self.apply_policy(&viewers);
+            id
+        };
+        let (screen, events) = self.subscribe();
+        Viewer {
+            id,
+            terminal: Arc::clone(self),
+            screen,
+            events,
+        }
+    }
+
+    /// A snapshot of the screen and a receiver for everything after it.
+    fn subscribe(&self) -> (Vec<u8>, broadcast::Receiver<TerminalEvent>) {
+        let parser = self.screen.lock().unwrap();
+        let screen = parser.screen();
+        let mut snapshot = Vec::new();
+        if screen.alternate_screen() {
+            snapshot.extend_from_slice(b"\x1b[?1049h");
+        }
+        snapshot.extend(screen.state_formatted());
+        (snapshot, self.tx.subscribe())
+    }
+
+    fn apply_policy(&self, viewers: &Viewers) {
+        if let Some((cols, rows)) = viewers.size(self.policy)
+            && let Err(e) = self.resize(cols, rows)
+        {
+            warn!(cols, rows, error = %e, "PTY resize failed");
+        }
+    }
+}
+
+/// One attached viewer. Detaches (and lets the PTY size follow the remaining
+/// viewers) when dropped.
+pub struct Viewer {
+    id: u64,
+    terminal: Arc<Terminal>,
+    screen: Vec<u8>,
+    events: broadcast::Receiver<TerminalEvent>,
+}
+
+impl Viewer {
+    /// Escape sequences that draw the screen as it was when the viewer
+    /// attached. Write these first, then the events.
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    /// Whether this viewer is the oldest one attached.
+    pub fn is_owner(&self) -> bool {
+        self.terminal.viewers.lock().unwrap().owner() == Some(self.id)
+    }
+
+    /// Report a new viewer size.
+    pub fn resize(&self, cols: u16, rows: u16) {
+        let mut viewers = self.terminal.viewers.lock().unwrap();
+        viewers.resize(self.id, cols, rows);
+        self.terminal.apply_policy(&viewers);
+    }
+
+    /// The next event; `None` once the session is gone. A viewer that fell
+    /// behind gets the current screen as one output event and continues
+    /// from there.
+    pub async fn recv(&mut self) -> Option<TerminalEvent> {
+        match self.events.recv().await {
+            Ok(event) => Some(event),
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                let (screen, events) = self.terminal.subscribe();
+                self.events = events;
+                Some(TerminalEvent::Output(screen.into()))
+            }
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        let mut viewers = self.terminal.viewers.lock().unwrap();
+        viewers.leave(self.id);
+        self.terminal.apply_policy(&viewers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_pick_the_smallest_or_the_oldest_viewer() {
+        let mut viewers = Viewers::default();
+        assert_eq!(viewers.size(SizePolicy::Smallest), None);
+        let first = viewers.join(120, 40);
+        let second = viewers.join(100, 50);
+        assert_eq!(viewers.size(SizePolicy::Smallest), Some((100, 40)));
+        assert_eq!(viewers.size(SizePolicy::Owner), Some((120, 40)));
+
+        viewers.resize(second, 90, 30);
+        assert_eq!(viewers.size(SizePolicy::Smallest), Some((90, 30)));
+
+        viewers.leave(first);
+        assert_eq!(viewers.owner(), Some(second));
+        assert_eq!(viewers.size(SizePolicy::Owner), Some((90, 30)));
+    }
+
+    fn pty_terminal(policy: SizePolicy) -> (Arc<Terminal>, std::fs::File) {
+        let pty = nix::pty::openpty(None, None).unwrap();
+        let master = std::fs::File::from(pty.master);
+        let slave = std::fs::File::from(pty.slave);
+        (
+            Arc::new(Terminal::new(master, DEFAULT_SIZE, policy, None)),
+            slave,
+        )
+    }
+
+    fn slave_size(slave: &std::fs::File) -> (u16, u16) {
+        let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) },
+            0
+        );
+        (winsize.ws_col, winsize.ws_row)
+    }
+
+    #[test]
+    fn sizes_are_bounded() {
+        assert!(check_size(1, 1).is_ok());
+        assert!(check_size(MAX_SIZE.0, MAX_SIZE.1).is_ok());
+        assert!(check_size(0, 24).is_err());
+        assert!(check_size(80, 0).is_err());
+        assert!(check_size(MAX_SIZE.0 + 1, 24).is_err());
+        assert!(check_size(80, MAX_SIZE.1 + 1).is_err());
+        assert!(check_size(u16::MAX, u16::MAX).is_err());
+    }
+
+    #[test]
+    fn viewers_resize_the_pty_and_late_joiners_see_the_screen() {
+        let (terminal, slave) = pty_terminal(SizePolicy::Smallest);
+        terminal.output(b"\x1b[2J\x1b[1;1Hhello\r\nworld");
+
+        let first = terminal.attach(120, 40);
+        assert_eq!(terminal.size(), (120, 40));
+        assert_eq!(slave_size(&slave), (120, 40));
+        assert!(first.is_owner());
+
+        let late = terminal.attach(100, 50);
+        assert_eq!(terminal.size(), (100, 40));
+        assert!(!late.is_owner());
+        let mut replay = vt100::Parser::new(40, 100, 0);
+        replay.process(late.screen());
+        assert_eq!(replay.screen().contents(), "hello\nworld");
+
+        drop(first);
+        assert_eq!(slave_size(&slave), (100, 50));
+        assert!(late.is_owner());
+        drop(late);
+        assert_eq!(terminal.viewer_count(), 0);
+        assert_eq!(terminal.size(), (100, 50));
+    }
+
+    #[tokio::test]
+    async fn viewers_follow_output_after_the_snapshot() {
+        let (terminal, _slave) = pty_terminal(SizePolicy::Owner);
+        let mut viewer = terminal.attach(80, 24);
+        let _other = terminal.attach(40, 10);
+        assert_eq!(terminal.size(), (80, 24));
+
+        terminal.output(b"ls\r\n");
+        assert!(matches!(
+            viewer.recv().await,
+            Some(TerminalEvent::Output(bytes)) if &bytes[..] == b"ls\r\n"
+        ));
+        assert!(terminal.resize(0, 10).is_err());
+        assert!(terminal.resize(1001, 30).is_err());
+        assert!(terminal.resize(100, u16::MAX).is_err());
+        assert!(terminal.resize(100, 30).unwrap());
+        assert!(matches!(
+            viewer.recv().await,
+            Some(TerminalEvent::Resize {
+                cols: 100,
+                rows: 30
+            })
+        ));
+        terminal.close(None);
+        assert!(matches!(viewer.recv().await, Some(TerminalEvent::Closed)));
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
@@ -29,10 +31,28 @@ pub enum SessionEvent {
     StateChange(SessionState),
     /// An error occurred in the session.
     Error(String),
+    /// The child process exited. Sent before `Closed` when the exit status
+    /// could be collected.
+    Exited(ExitStatus),
+    /// The sandbox refused something (first occurrence of each).
+    SandboxViolation(super::sandbox::Violation),
     /// Session has been closed.
     Closed,
 }
 
+/// How a session's child process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Code(i32),
+    Signal(i32),
+}
+
+impl ExitStatus {
+    pub fn success(self) -> bool {
+        self == Self::Code(0)
+    }
+}
+
 /// Lifecycle state of a session.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionState {
@@ -97,6 +117,16 @@ pub trait Session: Send + Sync {
     /// Each call returns a new receiver; events are broadcast to all.
     fn events(&self) -> broadcast::Receiver<SessionEvent>;
 
+    /// The session's terminal, for sessions that own a PTY (managed only).
+    fn terminal(&self) -> Option<Arc<super::terminal::Terminal>> {
+        None
+    }
+
+    /// The sandbox the session runs in, if any (managed only).
+    fn sandbox(&self) -> Option<Arc<super::sandbox::Sandbox>> {
+        None
+    }
+
     /// Gracefully close the session.
     async fn close(&self) -> anyhow::Result<()>;
 }
@@ -10,8 +10,8 @@ use tracing::{debug, info, warn};
 
 use crate::bus::EventBus;
 use crate::bus::topics::{
-    AGENT_METRICS, API_REQUESTS, FILE_CHANGES, PLAN_UPDATES, SESSION_MESSAGES, SESSION_STATUS,
-    SYSTEM_EVENTS, TASK_UPDATES,
+    AGENT_METRICS, API_REQUESTS, FILE_CHANGES, JOB_STATUS, PLAN_UPDATES, SESSION_MESSAGES,
+    SESSION_STATUS, SYSTEM_EVENTS, TASK_UPDATES,
 };
 
 use super::adaptive::AdaptiveQuality;
@@ -27,6 +27,7 @@ const ALL_TOPICS: &[&str] = &[
     AGENT_METRICS,
     API_REQUESTS,
     PLAN_UPDATES,
+    JOB_STATUS,
 ];
 
 /// Default capacity for the replay buffer (number of events).